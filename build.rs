@@ -0,0 +1,13 @@
+fn main() {
+    println!("cargo::rerun-if-changed=proto");
+    #[cfg(feature = "grpc")]
+    {
+        let mut config = tonic_prost_build::Config::new();
+        config.protoc_executable(
+            protoc_bin_vendored::protoc_bin_path().expect("protoc is vendored for this platform"),
+        );
+        tonic_prost_build::configure()
+            .compile_with_config(config, &["proto/cedar_tpe.proto"], &["proto"])
+            .expect("Failed to compile the gRPC protocol");
+    }
+}
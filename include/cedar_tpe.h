@@ -0,0 +1,80 @@
+#ifndef CEDAR_TPE_H
+#define CEDAR_TPE_H
+
+/* Generated by cbindgen from src/ffi.rs. Do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// An engine created by `cedar_tpe_engine_new`. It can be used from several threads at once.
+typedef struct CedarTpeEngine CedarTpeEngine;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Create an engine from a schema and policies in Cedar's JSON formats and entities in Cedar's
+// entities JSON format. Free it with `cedar_tpe_engine_free`.
+//
+// # Safety
+//
+// The strings must be NUL-terminated, and `error` must be `NULL` or valid for writes.
+struct CedarTpeEngine *cedar_tpe_engine_new(const char *schema,
+                                            const char *policies,
+                                            const char *entities,
+                                            char **error);
+
+// Replace the schema and policies with those of a bundle written by `bundle::write`. The
+// entities are kept, and must be valid under the new schema.
+//
+// # Safety
+//
+// `engine` must be returned by `cedar_tpe_engine_new`, `path` must be NUL-terminated, and
+// `error` must be `NULL` or valid for writes.
+bool cedar_tpe_engine_load_bundle(const struct CedarTpeEngine *engine,
+                                  const char *path,
+                                  char **error);
+
+// Replace the entities with entities in Cedar's entities JSON format.
+//
+// # Safety
+//
+// `engine` must be returned by `cedar_tpe_engine_new`, `entities` must be NUL-terminated, and
+// `error` must be `NULL` or valid for writes.
+bool cedar_tpe_engine_load_entities(const struct CedarTpeEngine *engine,
+                                    const char *entities,
+                                    char **error);
+
+// The decision on a call, as `{"decision": "Allow", "diagnostics": {...}}`.
+//
+// # Safety
+//
+// `engine` must be returned by `cedar_tpe_engine_new`, `call` must be NUL-terminated, and
+// `error` must be `NULL` or valid for writes.
+char *cedar_tpe_is_authorized(const struct CedarTpeEngine *engine, const char *call, char **error);
+
+// The residuals of a call with unknown principal or resource IDs in Cedar's JSON policy
+// format, as input for compiling a filter.
+//
+// # Safety
+//
+// As for `cedar_tpe_is_authorized`.
+char *cedar_tpe_compile_filter(const struct CedarTpeEngine *engine, const char *call, char **error);
+
+// # Safety
+//
+// `engine` must be `NULL` or returned by `cedar_tpe_engine_new` and not yet freed.
+void cedar_tpe_engine_free(struct CedarTpeEngine *engine);
+
+// # Safety
+//
+// `s` must be `NULL` or a string returned by this library and not yet freed.
+void cedar_tpe_string_free(char *s);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* CEDAR_TPE_H */
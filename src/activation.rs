@@ -0,0 +1,176 @@
+//! Time-bound policies, for temporary access that expires without anybody deleting a policy.
+//!
+//! A policy annotated with `@not_before` or `@not_after` RFC 3339 timestamps, e.g.
+//! `@not_after("2026-01-01T00:00:00Z")`, is only evaluated and partially evaluated within its
+//! window, both ends included, as told by the engine's [`Clock`](crate::clock::Clock). Policies
+//! with malformed timestamps are rejected when they are installed.
+//!
+//! When a policy expires, the first evaluation after its `@not_after` records a
+//! [`PolicyExpiry`](crate::audit::PolicyExpiry) with the audit sink. Policies that had already
+//! expired when they were installed are excluded without one.
+
+use std::sync::Arc;
+
+use cedar_policy::{PolicyId, PolicySet};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{annotations::annotations, engine::EngineState, error::Result};
+
+pub const NOT_BEFORE: &str = "not_before";
+pub const NOT_AFTER: &str = "not_after";
+
+/// The period in which a policy is active. An open end is unbounded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Window {
+    pub not_before: Option<DateTime<Utc>>,
+    pub not_after: Option<DateTime<Utc>>,
+}
+
+impl Window {
+    pub fn contains(&self, now: DateTime<Utc>) -> bool {
+        self.not_before.is_none_or(|start| start <= now)
+            && self.not_after.is_none_or(|end| now <= end)
+    }
+
+    /// Whether the window ended before `now`.
+    pub fn has_ended(&self, now: DateTime<Utc>) -> bool {
+        self.not_after.is_some_and(|end| end < now)
+    }
+}
+
+/// The windows of the time-bound policies in `policies`.
+pub(crate) fn windows(policies: &PolicySet) -> Result<Vec<(PolicyId, Window)>> {
+    policies
+        .policies()
+        .filter(|p| p.annotation(NOT_BEFORE).is_some() || p.annotation(NOT_AFTER).is_some())
+        .map(|p| Ok((p.id().clone(), annotations::<Window>(p)?)))
+        .collect()
+}
+
+/// The state with the policies active at some time, derived from an installed state.
+#[derive(Debug)]
+pub(crate) struct Activation {
+    pub(crate) base: Arc<EngineState>,
+    /// Per window of `base`, whether its policy is active.
+    pub(crate) active: Vec<bool>,
+    pub(crate) state: Arc<EngineState>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use cedar_policy::{Context, Decision, Entities, EntityUid, Request, SchemaFragment};
+    use chrono::TimeDelta;
+
+    use super::*;
+    use crate::{
+        CEDAR_SCHEMA, CEDAR_SCHEMA_SRC, Engine,
+        audit::{AuditRecord, AuditSink, PolicyExpiry},
+        clock::TestClock,
+    };
+
+    #[derive(Debug, Clone, Default)]
+    struct Expiries(Arc<std::sync::Mutex<Vec<PolicyExpiry>>>);
+
+    impl AuditSink for Expiries {
+        fn record(&self, _: &AuditRecord) -> std::io::Result<()> {
+            Ok(())
+        }
+
+        fn record_expiry(&self, expiry: &PolicyExpiry) -> std::io::Result<()> {
+            self.0.lock().unwrap().push(expiry.clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_time_bound_policies() {
+        let engine = Engine::new(
+            SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(),
+            PolicySet::from_str(
+                r#"
+                @not_after("2026-01-01T00:00:00Z")
+                permit (principal == MyApp::User::"0", action, resource);
+                @not_before("2026-01-01T00:00:00Z")
+                permit (principal == MyApp::User::"1", action, resource);
+                "#,
+            )
+            .unwrap(),
+            Entities::empty(),
+        )
+        .unwrap();
+        let clock = Arc::new(TestClock::new("2025-12-31T23:59:59Z".parse().unwrap()));
+        engine.set_clock(clock.clone());
+        let expiries = Expiries::default();
+        engine.set_audit_sink(expiries.clone());
+        let decision = |user: &str| {
+            let request = Request::new(
+                EntityUid::from_str(&format!(r#"MyApp::User::"{user}""#)).unwrap(),
+                EntityUid::from_str(r#"MyApp::Action::"GetProjectMetadata""#).unwrap(),
+                EntityUid::from_str(r#"MyApp::Project::"0""#).unwrap(),
+                Context::empty(),
+                Some(&CEDAR_SCHEMA),
+            )
+            .unwrap();
+            engine.is_authorized(&request).decision()
+        };
+        assert_eq!(decision("0"), Decision::Allow);
+        assert_eq!(decision("1"), Decision::Deny);
+
+        clock.advance(TimeDelta::seconds(2));
+        assert_eq!(decision("0"), Decision::Deny);
+        assert_eq!(decision("1"), Decision::Allow);
+        let expiries = expiries.0.lock().unwrap().clone();
+        assert_eq!(expiries.len(), 1);
+        assert_eq!(expiries[0].expired_policy, "policy0");
+        // The installed policies are kept.
+        assert_eq!(engine.state().policies().policies().count(), 2);
+
+        let err = Engine::new(
+            SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(),
+            PolicySet::from_str(r#"@not_after("tomorrow") permit (principal, action, resource);"#)
+                .unwrap(),
+            Entities::empty(),
+        );
+        assert!(matches!(err, Err(crate::Error::Annotations { .. })));
+    }
+
+    #[test]
+    fn test_expiry_across_state_swaps() {
+        let engine = Engine::new(
+            SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(),
+            PolicySet::from_str(
+                r#"@not_after("2026-01-01T00:00:00Z")
+                permit (principal == MyApp::User::"0", action, resource);"#,
+            )
+            .unwrap(),
+            Entities::empty(),
+        )
+        .unwrap();
+        let clock = Arc::new(TestClock::new("2025-12-31T23:59:59Z".parse().unwrap()));
+        engine.set_clock(clock.clone());
+        let expiries = Expiries::default();
+        engine.set_audit_sink(expiries.clone());
+        let request = Request::new(
+            EntityUid::from_str(r#"MyApp::User::"0""#).unwrap(),
+            EntityUid::from_str(r#"MyApp::Action::"GetProjectMetadata""#).unwrap(),
+            EntityUid::from_str(r#"MyApp::Project::"0""#).unwrap(),
+            Context::empty(),
+            Some(&CEDAR_SCHEMA),
+        )
+        .unwrap();
+        assert_eq!(engine.is_authorized(&request).decision(), Decision::Allow);
+
+        // The policy expires while other entities are installed.
+        engine.replace_entities(Entities::empty()).unwrap();
+        clock.advance(TimeDelta::seconds(2));
+        assert_eq!(engine.is_authorized(&request).decision(), Decision::Deny);
+        engine.replace_entities(Entities::empty()).unwrap();
+        assert_eq!(engine.is_authorized(&request).decision(), Decision::Deny);
+        let expiries = expiries.0.lock().unwrap().clone();
+        assert_eq!(expiries.len(), 1);
+        assert_eq!(expiries[0].expired_policy, "policy0");
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::BTreeMap;
+
+use cedar_policy::{AuthorizationError, Authorizer, Effect, Entities, PolicySet, Request};
+use serde::Serialize;
+
+use crate::{engine::static_policies, error::Result, namespace::id_str};
+
+/// How often a policy was exercised by the replayed requests.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct PolicyCoverage {
+    /// The policy was among the reasons for the decision.
+    pub determining: u64,
+    /// The policy's scope and conditions matched, whether it determined the decision or not.
+    pub satisfied: u64,
+    /// Evaluating the policy's conditions failed.
+    pub errored: u64,
+}
+
+/// Per-policy coverage of a corpus of requests.
+///
+/// A satisfied `permit` does not show up in the reasons of a response if a `forbid` applies as
+/// well, so permits and forbids are evaluated separately.
+#[derive(Debug)]
+pub struct Coverage {
+    permits: PolicySet,
+    forbids: PolicySet,
+    requests: u64,
+    policies: BTreeMap<String, PolicyCoverage>,
+    authorizer: Authorizer,
+}
+
+impl Coverage {
+    pub fn new(policies: &PolicySet) -> Result<Self> {
+        let policies = static_policies(policies)?;
+        let (permits, forbids): (Vec<_>, Vec<_>) = policies
+            .policies()
+            .cloned()
+            .partition(|p| p.effect() == Effect::Permit);
+        Ok(Self {
+            policies: policies
+                .policies()
+                .map(|p| (id_str(p.id()).to_string(), PolicyCoverage::default()))
+                .collect(),
+            permits: PolicySet::from_policies(permits)?,
+            forbids: PolicySet::from_policies(forbids)?,
+            requests: 0,
+            authorizer: Authorizer::new(),
+        })
+    }
+
+    pub fn record(&mut self, request: &Request, entities: &Entities) {
+        self.requests += 1;
+        let (forbids, forbid_errors) = self.evaluate(request, &self.forbids, entities);
+        let (permits, permit_errors) = self.evaluate(request, &self.permits, entities);
+        let determining = if forbids.is_empty() {
+            &permits
+        } else {
+            &forbids
+        };
+        for id in determining {
+            self.entry(id).determining += 1;
+        }
+        for id in forbids.iter().chain(&permits) {
+            self.entry(id).satisfied += 1;
+        }
+        for id in forbid_errors.iter().chain(&permit_errors) {
+            self.entry(id).errored += 1;
+        }
+    }
+
+    /// The number of recorded requests.
+    pub fn requests(&self) -> u64 {
+        self.requests
+    }
+
+    pub fn policies(&self) -> &BTreeMap<String, PolicyCoverage> {
+        &self.policies
+    }
+
+    /// Policies no recorded request satisfied, candidates for pruning.
+    pub fn never_matched(&self) -> impl Iterator<Item = &str> {
+        self.policies
+            .iter()
+            .filter(|(_, coverage)| coverage.satisfied == 0)
+            .map(|(id, _)| id.as_str())
+    }
+
+    /// The satisfied and the erroring policies.
+    fn evaluate(
+        &self,
+        request: &Request,
+        policies: &PolicySet,
+        entities: &Entities,
+    ) -> (Vec<String>, Vec<String>) {
+        let response = self.authorizer.is_authorized(request, policies, entities);
+        let satisfied = response
+            .diagnostics()
+            .reason()
+            .map(|id| id_str(id).to_string())
+            .collect();
+        let errored = response
+            .diagnostics()
+            .errors()
+            .map(|AuthorizationError::PolicyEvaluationError(e)| id_str(e.policy_id()).to_string())
+            .collect();
+        (satisfied, errored)
+    }
+
+    fn entry(&mut self, id: &str) -> &mut PolicyCoverage {
+        self.policies.entry(id.to_string()).or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use cedar_policy::{Context, EntityUid};
+
+    use super::*;
+    use crate::CEDAR_SCHEMA;
+
+    fn request(principal: &str, action: &str) -> Request {
+        Request::new(
+            EntityUid::from_str(&format!(r#"MyApp::User::"{principal}""#)).unwrap(),
+            EntityUid::from_str(&format!(r#"MyApp::Action::"{action}""#)).unwrap(),
+            EntityUid::from_str(r#"MyApp::Project::"0""#).unwrap(),
+            Context::empty(),
+            Some(&CEDAR_SCHEMA),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_coverage() {
+        let policies = PolicySet::from_str(
+            r#"
+            permit (principal, action == MyApp::Action::"GetProjectMetadata", resource);
+            forbid (principal == MyApp::User::"1", action, resource);
+            permit (principal, action == MyApp::Action::"DeleteProject", resource);
+            "#,
+        )
+        .unwrap();
+        let mut coverage = Coverage::new(&policies).unwrap();
+        coverage.record(&request("0", "GetProjectMetadata"), &Entities::empty());
+        coverage.record(&request("1", "GetProjectMetadata"), &Entities::empty());
+        assert_eq!(coverage.requests(), 2);
+
+        let policy0 = coverage.policies()["policy0"];
+        assert_eq!((policy0.determining, policy0.satisfied), (1, 2));
+        let policy1 = coverage.policies()["policy1"];
+        assert_eq!((policy1.determining, policy1.satisfied), (1, 1));
+        assert_eq!(
+            coverage.never_matched().collect::<Vec<_>>(),
+            vec!["policy2"]
+        );
+    }
+}
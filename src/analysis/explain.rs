@@ -0,0 +1,228 @@
+//! What would have to change for a denied request to be allowed.
+
+use std::{fmt, str::FromStr};
+
+use cedar_policy::{
+    ActionConstraint, Authorizer, Decision, Effect, Policy, PolicySet, PolicySetError,
+    PrincipalConstraint, Request, ResourceConstraint,
+};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::principals::{cedar_text, conjuncts};
+use crate::{
+    engine::{EngineState, static_policies},
+    error::Result,
+    namespace::id_str,
+};
+
+/// A part of a policy the request does not satisfy, in Cedar syntax.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "requirement", rename_all = "snake_case")]
+pub enum Requirement {
+    Principal(String),
+    Action(String),
+    Resource(String),
+    Condition(String),
+}
+
+impl fmt::Display for Requirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Principal(r) => write!(f, "principal would need to satisfy `{r}`"),
+            Self::Action(r) => write!(f, "action would need to satisfy `{r}`"),
+            Self::Resource(r) => write!(f, "resource would need to satisfy `{r}`"),
+            Self::Condition(r) => write!(f, "`{r}` would need to hold"),
+        }
+    }
+}
+
+/// A `permit` that would allow the request if its unmet requirements held.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Alternative {
+    pub policy: String,
+    pub unmet: Vec<Requirement>,
+}
+
+/// Why a request was denied.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Explanation {
+    /// `forbid` policies that apply and would have to stop applying.
+    pub forbids: Vec<String>,
+    /// Every `permit`, by increasing number of unmet requirements.
+    pub alternatives: Vec<Alternative>,
+}
+
+impl Explanation {
+    /// The permits requiring the fewest changes.
+    pub fn minimal(&self) -> impl Iterator<Item = &Alternative> {
+        let fewest = self.alternatives.first().map_or(0, |a| a.unmet.len());
+        self.alternatives
+            .iter()
+            .take_while(move |a| a.unmet.len() == fewest)
+    }
+}
+
+/// Explain the denial of `request`, or `None` if it is allowed. Each `permit` is split into its
+/// scope constraints and the conjuncts of its conditions, which are evaluated separately against
+/// the request; those that are not satisfied, or fail to evaluate, are unmet.
+pub fn explain(state: &EngineState, request: &Request) -> Result<Option<Explanation>> {
+    let authorizer = Authorizer::new();
+    let response = authorizer.is_authorized(request, state.policies(), state.entities());
+    if response.decision() == Decision::Allow {
+        return Ok(None);
+    }
+    let holds = |source: &str| -> Result<bool> {
+        let policies = PolicySet::from_str(source)?;
+        let response = authorizer.is_authorized(request, &policies, state.entities());
+        Ok(response.decision() == Decision::Allow)
+    };
+
+    let mut forbids = Vec::new();
+    let mut alternatives = Vec::new();
+    for policy in static_policies(state.policies())?.policies() {
+        let id = id_str(policy.id()).to_string();
+        if policy.effect() == Effect::Forbid {
+            if response.diagnostics().reason().any(|r| r == policy.id()) {
+                forbids.push(id);
+            }
+            continue;
+        }
+        let mut unmet = Vec::new();
+        for requirement in requirements(policy)? {
+            let source = match &requirement {
+                Requirement::Principal(r) => format!("permit ({r}, action, resource);"),
+                Requirement::Action(r) => format!("permit (principal, {r}, resource);"),
+                Requirement::Resource(r) => format!("permit (principal, action, {r});"),
+                Requirement::Condition(r) => {
+                    format!("permit (principal, action, resource) when {{ {r} }};")
+                }
+            };
+            if !holds(&source)? {
+                unmet.push(requirement);
+            }
+        }
+        alternatives.push(Alternative { policy: id, unmet });
+    }
+    forbids.sort();
+    alternatives.sort_by(|a, b| (a.unmet.len(), &a.policy).cmp(&(b.unmet.len(), &b.policy)));
+    Ok(Some(Explanation {
+        forbids,
+        alternatives,
+    }))
+}
+
+fn requirements(policy: &Policy) -> Result<Vec<Requirement>> {
+    let mut requirements = Vec::new();
+    let principal = match policy.principal_constraint() {
+        PrincipalConstraint::Any => None,
+        PrincipalConstraint::Eq(uid) => Some(format!("principal == {uid}")),
+        PrincipalConstraint::In(uid) => Some(format!("principal in {uid}")),
+        PrincipalConstraint::Is(ty) => Some(format!("principal is {ty}")),
+        PrincipalConstraint::IsIn(ty, uid) => Some(format!("principal is {ty} in {uid}")),
+    };
+    requirements.extend(principal.map(Requirement::Principal));
+    let action = match policy.action_constraint() {
+        ActionConstraint::Any => None,
+        ActionConstraint::Eq(uid) => Some(format!("action == {uid}")),
+        ActionConstraint::In(uids) => Some(format!("action in [{}]", uids.iter().join(", "))),
+    };
+    requirements.extend(action.map(Requirement::Action));
+    let resource = match policy.resource_constraint() {
+        ResourceConstraint::Any => None,
+        ResourceConstraint::Eq(uid) => Some(format!("resource == {uid}")),
+        ResourceConstraint::In(uid) => Some(format!("resource in {uid}")),
+        ResourceConstraint::Is(ty) => Some(format!("resource is {ty}")),
+        ResourceConstraint::IsIn(ty, uid) => Some(format!("resource is {ty} in {uid}")),
+    };
+    requirements.extend(resource.map(Requirement::Resource));
+
+    let json = policy.to_json().map_err(PolicySetError::from)?;
+    for condition in json["conditions"].as_array().into_iter().flatten() {
+        let body = &condition["body"];
+        if condition["kind"] == "unless" {
+            let negated = json!({ "!": { "arg": body } });
+            requirements.push(Requirement::Condition(cedar_text(&negated)?));
+        } else {
+            for conjunct in conjuncts(body) {
+                requirements.push(Requirement::Condition(cedar_text(conjunct)?));
+            }
+        }
+    }
+    Ok(requirements)
+}
+
+#[cfg(test)]
+mod tests {
+    use cedar_policy::{Context, Entities, EntityUid, SchemaFragment};
+
+    use super::*;
+    use crate::{CEDAR_SCHEMA, CEDAR_SCHEMA_SRC};
+
+    #[test]
+    fn test_explain() {
+        let entities = Entities::from_json_str(
+            r#"[
+                { "uid": { "type": "MyApp::Server", "id": "0" }, "attrs": {}, "parents": [] },
+                {
+                    "uid": { "type": "MyApp::Project", "id": "0" },
+                    "attrs": {},
+                    "parents": [{ "type": "MyApp::Server", "id": "0" }]
+                },
+                { "uid": { "type": "MyApp::User", "id": "0" }, "attrs": {}, "parents": [] }
+            ]"#,
+            Some(&CEDAR_SCHEMA),
+        )
+        .unwrap();
+        let state = EngineState::new(
+            SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(),
+            PolicySet::from_str(
+                r#"
+                permit (principal in MyApp::Role::"project_admin", action, resource in MyApp::Server::"0");
+                permit (principal, action, resource is MyApp::Project)
+                    when { principal in MyApp::Role::"reader" && resource == MyApp::Project::"1" };
+                forbid (principal, action == MyApp::Action::"DeleteProject", resource);
+                "#,
+            )
+            .unwrap(),
+            entities,
+        )
+        .unwrap();
+        let request = |action: &str| {
+            Request::new(
+                EntityUid::from_str(r#"MyApp::User::"0""#).unwrap(),
+                EntityUid::from_str(&format!(r#"MyApp::Action::"{action}""#)).unwrap(),
+                EntityUid::from_str(r#"MyApp::Project::"0""#).unwrap(),
+                Context::empty(),
+                Some(&CEDAR_SCHEMA),
+            )
+            .unwrap()
+        };
+
+        let explanation = explain(&state, &request("GetProjectMetadata"))
+            .unwrap()
+            .unwrap();
+        assert!(explanation.forbids.is_empty());
+        let minimal = explanation.minimal().collect::<Vec<_>>();
+        assert_eq!(
+            minimal,
+            [&Alternative {
+                policy: "policy0".to_string(),
+                unmet: vec![Requirement::Principal(
+                    r#"principal in MyApp::Role::"project_admin""#.to_string()
+                )],
+            }]
+        );
+        assert_eq!(
+            explanation.alternatives[1].unmet,
+            [
+                Requirement::Condition(r#"principal in MyApp::Role::"reader""#.to_string()),
+                Requirement::Condition(r#"resource == MyApp::Project::"1""#.to_string()),
+            ]
+        );
+
+        let explanation = explain(&state, &request("DeleteProject")).unwrap().unwrap();
+        assert_eq!(explanation.forbids, ["policy2"]);
+    }
+}
@@ -0,0 +1,434 @@
+//! Suggestions for merging policies that differ only in their action or resource scope.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use cedar_policy::{
+    ActionConstraint, Entities, EntityUid, Policy, PolicyId, PolicySet, PolicySetError,
+    ResourceConstraint, Schema,
+};
+use serde_json::{Value, json};
+
+use super::Scope;
+use crate::{
+    engine::static_policies,
+    error::{Error, Result},
+    fingerprint::canonical,
+    namespace::id_str,
+};
+
+/// Policies that can be replaced by the single policy `merged`.
+#[derive(Debug, Clone)]
+pub struct MergeSuggestion {
+    pub policies: Vec<String>,
+    /// Takes the ID of the first of `policies`.
+    pub merged: Policy,
+}
+
+impl MergeSuggestion {
+    /// Replace the suggested policies in `policies` by the merged one, after checking that they
+    /// apply to the same requests. Actions are compared across the schema and resources across
+    /// `entities`, so a resource merge may no longer hold once the hierarchy changes.
+    pub fn apply(
+        &self,
+        schema: &Schema,
+        entities: &Entities,
+        policies: &PolicySet,
+    ) -> Result<PolicySet> {
+        let replaced = self
+            .policies
+            .iter()
+            .map(|id| {
+                policies
+                    .policy(&PolicyId::new(id))
+                    .filter(|policy| policy.is_static())
+                    .ok_or_else(|| Error::NotFound(format!("Static policy `{id}`")))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if !equivalent(schema, entities, &replaced, &self.merged)? {
+            return Err(Error::Conflict(format!(
+                "Merging {} does not preserve their decisions",
+                self.policies.join(", ")
+            )));
+        }
+        let mut merged = policies.clone();
+        for policy in replaced {
+            merged.remove_static(policy.id().clone())?;
+        }
+        merged.add(self.merged.clone())?;
+        Ok(merged)
+    }
+}
+
+/// Groups of static policies with the same effect, principal scope, conditions and
+/// annotations that can be merged into one:
+/// - policies on different actions, into one `action in [...]` policy, and
+/// - policies on resources in the hierarchy below an entity of `entities` that together cover
+///   all of it, into one `resource in` policy.
+pub fn merge_suggestions(
+    schema: &Schema,
+    entities: &Entities,
+    policies: &PolicySet,
+) -> Result<Vec<MergeSuggestion>> {
+    let actions = schema.action_entities()?;
+    let policies = static_policies(policies)?;
+    let policies = policies
+        .policies()
+        .filter(|policy| policy.is_static())
+        .collect::<Vec<_>>();
+
+    let mut suggestions = Vec::new();
+    for group in groups(&policies, "action")? {
+        // `action in` also matches the members of an action group, unlike `action ==`.
+        let (members, uids): (Vec<_>, Vec<_>) = group
+            .into_iter()
+            .filter_map(|policy| match policy.action_constraint() {
+                ActionConstraint::Eq(uid) if !has_members(&actions, &uid) => {
+                    Some((policy, vec![uid]))
+                }
+                ActionConstraint::In(uids) => Some((policy, uids)),
+                _ => None,
+            })
+            .unzip();
+        if members.len() > 1 {
+            let uids = uids.into_iter().flatten().collect::<BTreeSet<_>>();
+            let constraint =
+                json!({ "op": "in", "entities": uids.iter().map(uid_json).collect::<Vec<_>>() });
+            suggestions.push(suggestion(&members, "action", constraint)?);
+        }
+    }
+    for group in groups(&policies, "resource")? {
+        let mut members = group
+            .into_iter()
+            .filter_map(|policy| match policy.resource_constraint() {
+                ResourceConstraint::Eq(uid) | ResourceConstraint::In(uid) => Some((policy, uid)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        while let Some((root, covered)) = largest_cover(entities, &members) {
+            let (merged, rest): (Vec<_>, Vec<_>) = members
+                .into_iter()
+                .partition(|(policy, _)| covered.contains(policy.id()));
+            let merged = merged
+                .into_iter()
+                .map(|(policy, _)| policy)
+                .collect::<Vec<_>>();
+            let constraint = json!({ "op": "in", "entity": uid_json(&root) });
+            suggestions.push(suggestion(&merged, "resource", constraint)?);
+            members = rest;
+        }
+    }
+    suggestions.sort_by(|a, b| a.policies.cmp(&b.policies));
+    Ok(suggestions)
+}
+
+/// Apply [`merge_suggestions`] to `policies` until none applies. A policy can be in several
+/// suggestions, so they are recomputed after each merge.
+pub fn minimize(schema: &Schema, entities: &Entities, policies: &PolicySet) -> Result<PolicySet> {
+    let mut minimized = policies.clone();
+    'merge: loop {
+        for suggestion in merge_suggestions(schema, entities, &minimized)? {
+            match suggestion.apply(schema, entities, &minimized) {
+                Ok(merged) => {
+                    minimized = merged;
+                    continue 'merge;
+                }
+                Err(Error::Conflict(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        return Ok(minimized);
+    }
+}
+
+/// Policies whose JSON is equal apart from `field`, in groups of more than one.
+fn groups<'a>(policies: &[&'a Policy], field: &str) -> Result<Vec<Vec<&'a Policy>>> {
+    let mut groups = BTreeMap::<String, Vec<&Policy>>::new();
+    for policy in policies {
+        groups.entry(key(policy, field)?).or_default().push(policy);
+    }
+    Ok(groups
+        .into_values()
+        .filter(|group| group.len() > 1)
+        .collect())
+}
+
+fn key(policy: &Policy, field: &str) -> Result<String> {
+    let mut json = policy.to_json().map_err(PolicySetError::from)?;
+    if let Some(json) = json.as_object_mut() {
+        json.remove(field);
+    }
+    Ok(canonical(json, false).to_string())
+}
+
+fn has_members(actions: &Entities, action: &EntityUid) -> bool {
+    actions.iter().any(|entity| {
+        actions
+            .ancestors(&entity.uid())
+            .into_iter()
+            .flatten()
+            .any(|ancestor| ancestor == action)
+    })
+}
+
+/// The entity whose hierarchy is covered exactly by the most `members`, with the IDs of those
+/// members, if there are at least two.
+fn largest_cover(
+    entities: &Entities,
+    members: &[(&Policy, EntityUid)],
+) -> Option<(EntityUid, BTreeSet<PolicyId>)> {
+    let roots = members
+        .iter()
+        .flat_map(|(_, uid)| {
+            std::iter::once(uid.clone())
+                .chain(entities.ancestors(uid).into_iter().flatten().cloned())
+        })
+        .collect::<BTreeSet<_>>();
+    roots
+        .into_iter()
+        .filter_map(|root| {
+            let below = descendants(entities, &root);
+            let inside = members
+                .iter()
+                .filter(|(_, uid)| below.contains(uid))
+                .collect::<Vec<_>>();
+            let covered = inside
+                .iter()
+                .flat_map(|(policy, uid)| match policy.resource_constraint() {
+                    ResourceConstraint::In(_) => descendants(entities, uid),
+                    _ => BTreeSet::from([uid.clone()]),
+                })
+                .collect::<BTreeSet<_>>();
+            (inside.len() > 1 && covered == below).then(|| {
+                let ids = inside.iter().map(|(policy, _)| policy.id().clone());
+                (root, ids.collect::<BTreeSet<_>>())
+            })
+        })
+        .max_by(|(a_root, a), (b_root, b)| a.len().cmp(&b.len()).then(b_root.cmp(a_root)))
+}
+
+/// `uid` and all entities below it.
+fn descendants(entities: &Entities, uid: &EntityUid) -> BTreeSet<EntityUid> {
+    entities
+        .iter()
+        .map(|entity| entity.uid())
+        .filter(|entity| {
+            entities
+                .ancestors(entity)
+                .into_iter()
+                .flatten()
+                .any(|a| a == uid)
+        })
+        .chain(std::iter::once(uid.clone()))
+        .collect()
+}
+
+fn suggestion(members: &[&Policy], field: &str, constraint: Value) -> Result<MergeSuggestion> {
+    let mut policies = members
+        .iter()
+        .map(|policy| id_str(policy.id()).to_string())
+        .collect::<Vec<_>>();
+    policies.sort();
+    let first = members
+        .iter()
+        .find(|policy| id_str(policy.id()) == policies[0])
+        .expect("members are not empty");
+    let mut json = first.to_json().map_err(PolicySetError::from)?;
+    json[field] = constraint;
+    let merged = Policy::from_json(Some(first.id().clone()), json).map_err(PolicySetError::from)?;
+    Ok(MergeSuggestion { policies, merged })
+}
+
+fn uid_json(uid: &EntityUid) -> Value {
+    json!({ "type": uid.type_name().to_string(), "id": uid.id().unescaped() })
+}
+
+/// Whether `merged` applies to exactly the requests any of `replaced` applies to, for the
+/// actions of the schema and the resources in `entities` or named by the policies.
+fn equivalent(
+    schema: &Schema,
+    entities: &Entities,
+    replaced: &[&Policy],
+    merged: &Policy,
+) -> Result<bool> {
+    let keys = replaced
+        .iter()
+        .chain(std::iter::once(&merged))
+        .map(|policy| Ok((key(policy, "action")?, key(policy, "resource")?)))
+        .collect::<Result<Vec<_>>>()?;
+    // Each merge changes only one of the two scopes.
+    let same_except_scope = keys.iter().all(|(action, _)| *action == keys[0].0)
+        || keys.iter().all(|(_, resource)| *resource == keys[0].1);
+    if !same_except_scope {
+        return Ok(false);
+    }
+
+    let actions = schema.action_entities()?;
+    let resources = entities
+        .iter()
+        .map(|entity| entity.uid())
+        .chain(
+            replaced
+                .iter()
+                .chain(std::iter::once(&merged))
+                .filter_map(|policy| match policy.resource_constraint() {
+                    ResourceConstraint::Eq(uid)
+                    | ResourceConstraint::In(uid)
+                    | ResourceConstraint::IsIn(_, uid) => Some(uid),
+                    _ => None,
+                }),
+        )
+        .collect::<BTreeSet<_>>();
+    let applies = |policy: &Policy, action: &EntityUid, resource: &EntityUid| {
+        let action_matches = match policy.action_constraint() {
+            ActionConstraint::Any => true,
+            ActionConstraint::Eq(uid) => uid == *action,
+            ActionConstraint::In(uids) => {
+                uids.contains(action)
+                    || actions
+                        .ancestors(action)
+                        .into_iter()
+                        .flatten()
+                        .any(|ancestor| uids.contains(ancestor))
+            }
+        };
+        action_matches && Scope::from(policy.resource_constraint()).matches(entities, resource)
+    };
+    Ok(schema.actions().all(|action| {
+        resources.iter().all(|resource| {
+            replaced
+                .iter()
+                .any(|policy| applies(policy, action, resource))
+                == applies(merged, action, resource)
+        })
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::CEDAR_SCHEMA;
+
+    #[test]
+    fn test_merge_suggestions() {
+        let policies = PolicySet::from_str(
+            r#"
+            permit (principal == MyApp::User::"0", action == MyApp::Action::"GetProjectMetadata", resource);
+            permit (principal == MyApp::User::"0", action == MyApp::Action::"DeleteProject", resource);
+            permit (principal == MyApp::User::"1", action, resource == MyApp::Server::"0");
+            permit (principal == MyApp::User::"1", action, resource in MyApp::Project::"a");
+            permit (principal == MyApp::User::"1", action, resource == MyApp::Project::"b");
+            permit (principal == MyApp::User::"2", action, resource == MyApp::Project::"a");
+            "#,
+        )
+        .unwrap();
+        let entities = Entities::from_json_value(
+            serde_json::json!([
+                { "uid": { "type": "MyApp::Server", "id": "0" }, "attrs": {}, "parents": [] },
+                {
+                    "uid": { "type": "MyApp::Project", "id": "a" },
+                    "attrs": {},
+                    "parents": [{ "type": "MyApp::Server", "id": "0" }]
+                },
+                {
+                    "uid": { "type": "MyApp::Project", "id": "b" },
+                    "attrs": {},
+                    "parents": [{ "type": "MyApp::Server", "id": "0" }]
+                }
+            ]),
+            None,
+        )
+        .unwrap();
+
+        let suggestions = merge_suggestions(&CEDAR_SCHEMA, &entities, &policies).unwrap();
+        let groups = suggestions
+            .iter()
+            .map(|s| s.policies.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            groups,
+            vec![
+                vec!["policy0".to_string(), "policy1".to_string()],
+                vec![
+                    "policy2".to_string(),
+                    "policy3".to_string(),
+                    "policy4".to_string()
+                ],
+            ]
+        );
+        assert_eq!(
+            suggestions[1].merged.resource_constraint(),
+            ResourceConstraint::In(EntityUid::from_str(r#"MyApp::Server::"0""#).unwrap())
+        );
+
+        let minimized = minimize(&CEDAR_SCHEMA, &entities, &policies).unwrap();
+        assert_eq!(minimized.policies().count(), 3);
+
+        // Without `MyApp::Project::"b"`, the server's hierarchy is no longer covered.
+        let partial = PolicySet::from_policies(
+            policies
+                .policies()
+                .filter(|p| p.id() != &PolicyId::new("policy4"))
+                .cloned(),
+        )
+        .unwrap();
+        assert!(matches!(
+            suggestions[1].apply(&CEDAR_SCHEMA, &entities, &partial),
+            Err(Error::NotFound(_))
+        ));
+        let wrong = MergeSuggestion {
+            policies: vec!["policy2".to_string(), "policy3".to_string()],
+            merged: suggestions[1].merged.clone(),
+        };
+        assert!(matches!(
+            wrong.apply(&CEDAR_SCHEMA, &entities, &policies),
+            Err(Error::Conflict(_))
+        ));
+    }
+
+    #[test]
+    fn test_minimize_overlapping_groups() {
+        // `policy0` can be merged with `policy1` by action and with `policy2` and `policy3` by
+        // resource.
+        let policies = PolicySet::from_str(
+            r#"
+            permit (principal == MyApp::User::"0", action == MyApp::Action::"GetProjectMetadata", resource == MyApp::Project::"a");
+            permit (principal == MyApp::User::"0", action == MyApp::Action::"DeleteProject", resource == MyApp::Project::"a");
+            permit (principal == MyApp::User::"0", action == MyApp::Action::"GetProjectMetadata", resource == MyApp::Project::"b");
+            permit (principal == MyApp::User::"0", action == MyApp::Action::"GetProjectMetadata", resource == MyApp::Server::"0");
+            "#,
+        )
+        .unwrap();
+        let entities = Entities::from_json_value(
+            serde_json::json!([
+                { "uid": { "type": "MyApp::Server", "id": "0" }, "attrs": {}, "parents": [] },
+                {
+                    "uid": { "type": "MyApp::Project", "id": "a" },
+                    "attrs": {},
+                    "parents": [{ "type": "MyApp::Server", "id": "0" }]
+                },
+                {
+                    "uid": { "type": "MyApp::Project", "id": "b" },
+                    "attrs": {},
+                    "parents": [{ "type": "MyApp::Server", "id": "0" }]
+                }
+            ]),
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            merge_suggestions(&CEDAR_SCHEMA, &entities, &policies)
+                .unwrap()
+                .len(),
+            2
+        );
+        let minimized = minimize(&CEDAR_SCHEMA, &entities, &policies).unwrap();
+        assert_eq!(minimized.policies().count(), 3);
+        assert!(
+            merge_suggestions(&CEDAR_SCHEMA, &entities, &minimized)
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,387 @@
+//! Static analyses of a policy set against its schema.
+//!
+//! The analyses work on request shapes: the combinations of principal type, action and resource
+//! type the schema allows and a policy typechecks for. They are conservative: a reported finding
+//! is possible for some request of that shape, but may be ruled out by policy conditions or
+//! entity data.
+
+use std::collections::BTreeSet;
+
+use cedar_policy::{
+    ActionConstraint, Effect, Entities, EntityTypeName, EntityUid, Policy, PolicySet,
+    PolicySetError, PrincipalConstraint, RequestEnv, ResourceConstraint, Schema,
+};
+use serde::Serialize;
+
+use crate::{engine::static_policies, error::Result, namespace::id_str};
+
+mod coverage;
+mod explain;
+mod merge;
+mod principals;
+#[cfg(feature = "symcc")]
+pub mod symbolic;
+mod trace;
+mod what_if;
+
+pub use coverage::{Coverage, PolicyCoverage};
+pub use explain::{Alternative, Explanation, Requirement, explain};
+pub use merge::{MergeSuggestion, merge_suggestions, minimize};
+pub(crate) use principals::cedar_text;
+pub use principals::{PrincipalDescription, PrincipalGrant};
+#[cfg(feature = "symcc")]
+pub use symbolic::{entails, semantic_diff};
+pub use trace::{
+    AttributeValue, ConditionTrace, DecisionTrace, Outcome, PolicyTrace, explain_decision,
+};
+pub use what_if::{Flip, PolicyChange, synthetic_requests, what_if};
+
+/// The types of a request.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub struct RequestShape {
+    pub principal_type: String,
+    pub action: String,
+    pub resource_type: String,
+}
+
+impl From<&RequestEnv> for RequestShape {
+    fn from(env: &RequestEnv) -> Self {
+        Self {
+            principal_type: env.principal().to_string(),
+            action: env.action().to_string(),
+            resource_type: env.resource().to_string(),
+        }
+    }
+}
+
+/// A `permit` and a `forbid` that can both apply to requests of the given shapes, so the
+/// forbid may override the permit.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PolicyConflict {
+    pub permit: String,
+    pub forbid: String,
+    pub shapes: Vec<RequestShape>,
+}
+
+/// All pairs of a `permit` and a `forbid` in `policies` that can apply to the same request.
+/// Template-linked policies are analysed with their slot values; templates themselves are not.
+pub fn conflicts(schema: &Schema, policies: &PolicySet) -> Result<Vec<PolicyConflict>> {
+    let policies = static_policies(policies)?;
+    let analysed = policies
+        .policies()
+        .map(|policy| (policy, request_envs(schema, policy)))
+        .collect::<Vec<_>>();
+    let (permits, forbids): (Vec<_>, Vec<_>) = analysed
+        .iter()
+        .partition(|(policy, _)| policy.effect() == Effect::Permit);
+
+    let mut conflicts = Vec::new();
+    for (permit, permit_envs) in &permits {
+        for (forbid, forbid_envs) in &forbids {
+            if !scopes_overlap(permit, forbid) {
+                continue;
+            }
+            let shapes = permit_envs
+                .intersection(forbid_envs)
+                .map(RequestShape::from)
+                .collect::<Vec<_>>();
+            if !shapes.is_empty() {
+                conflicts.push(PolicyConflict {
+                    permit: id_str(permit.id()).to_string(),
+                    forbid: id_str(forbid.id()).to_string(),
+                    shapes,
+                });
+            }
+        }
+    }
+    conflicts.sort_by(|a, b| (&a.permit, &a.forbid).cmp(&(&b.permit, &b.forbid)));
+    Ok(conflicts)
+}
+
+/// A policy that can never determine a decision.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Finding {
+    /// The policy does not typecheck for any request shape of the schema, so it never applies.
+    Unsatisfiable { policy: String },
+    /// An unconditional policy with the same effect applies whenever this policy applies.
+    Subsumed { policy: String, by: String },
+    /// An unconditional `forbid` applies whenever this `permit` applies.
+    Overridden { policy: String, by: String },
+}
+
+/// Policies that never determine a decision. Only policies without `when` or `unless`
+/// conditions are considered to cover others, and only by comparing scopes, so some shadowed
+/// policies are not found; every reported finding holds.
+pub fn findings(schema: &Schema, policies: &PolicySet) -> Result<Vec<Finding>> {
+    let policies = static_policies(policies)?;
+    let actions = schema.action_entities()?;
+    let mut findings = Vec::new();
+    let mut satisfiable = Vec::new();
+    for policy in policies.policies() {
+        if request_envs(schema, policy).is_empty() {
+            findings.push(Finding::Unsatisfiable {
+                policy: id_str(policy.id()).to_string(),
+            });
+        } else {
+            satisfiable.push((policy, is_unconditional(policy)?));
+        }
+    }
+
+    for (policy, policy_unconditional) in &satisfiable {
+        let covering = satisfiable.iter().filter(|(other, unconditional)| {
+            *unconditional && other.id() != policy.id() && scope_contains(&actions, other, policy)
+        });
+        for (other, _) in covering {
+            let (id, by) = (id_str(policy.id()), id_str(other.id()));
+            let finding = match (policy.effect(), other.effect()) {
+                (Effect::Permit, Effect::Forbid) => Finding::Overridden {
+                    policy: id.to_string(),
+                    by: by.to_string(),
+                },
+                (Effect::Forbid, Effect::Permit) => continue,
+                // Of two equal unconditional policies, only the second is reported.
+                _ if *policy_unconditional
+                    && scope_contains(&actions, policy, other)
+                    && id < by =>
+                {
+                    continue;
+                }
+                _ => Finding::Subsumed {
+                    policy: id.to_string(),
+                    by: by.to_string(),
+                },
+            };
+            findings.push(finding);
+        }
+    }
+    findings.sort();
+    Ok(findings)
+}
+
+fn is_unconditional(policy: &Policy) -> Result<bool> {
+    let json = policy.to_json().map_err(PolicySetError::from)?;
+    Ok(json["conditions"].as_array().is_none_or(Vec::is_empty))
+}
+
+/// Whether every request matching the scope of `inner` also matches the scope of `outer`.
+fn scope_contains(actions: &Entities, outer: &Policy, inner: &Policy) -> bool {
+    Scope::from(outer.principal_constraint()).contains(&Scope::from(inner.principal_constraint()))
+        && Scope::from(outer.resource_constraint())
+            .contains(&Scope::from(inner.resource_constraint()))
+        && action_contains(
+            actions,
+            &outer.action_constraint(),
+            &inner.action_constraint(),
+        )
+}
+
+fn action_contains(actions: &Entities, outer: &ActionConstraint, inner: &ActionConstraint) -> bool {
+    // An action is in a group if the group is the action itself or one of its ancestors.
+    let in_any = |action: &EntityUid, groups: &[EntityUid]| {
+        groups.contains(action)
+            || actions
+                .ancestors(action)
+                .into_iter()
+                .flatten()
+                .any(|ancestor| groups.contains(ancestor))
+    };
+    match (outer, inner) {
+        (ActionConstraint::Any, _) => true,
+        (_, ActionConstraint::Any) => false,
+        (ActionConstraint::Eq(outer), ActionConstraint::Eq(inner)) => outer == inner,
+        (ActionConstraint::Eq(_), ActionConstraint::In(_)) => false,
+        (ActionConstraint::In(groups), ActionConstraint::Eq(action)) => in_any(action, groups),
+        (ActionConstraint::In(groups), ActionConstraint::In(inner)) => {
+            inner.iter().all(|group| in_any(group, groups))
+        }
+    }
+}
+
+/// A principal or resource scope constraint.
+enum Scope {
+    Any,
+    Eq(EntityUid),
+    In(EntityUid),
+    Is(EntityTypeName),
+    IsIn(EntityTypeName, EntityUid),
+}
+
+impl Scope {
+    // `in` is reflexive, and the hierarchy is not known, so `in` only covers the same entity.
+    fn contains(&self, inner: &Self) -> bool {
+        match (self, inner) {
+            (Self::Any, _) => true,
+            (Self::Eq(outer), Self::Eq(inner)) => outer == inner,
+            (Self::In(outer), Self::Eq(inner) | Self::In(inner) | Self::IsIn(_, inner)) => {
+                outer == inner
+            }
+            (Self::Is(outer), Self::Eq(inner)) => inner.type_name() == outer,
+            (Self::Is(outer), Self::Is(inner) | Self::IsIn(inner, _)) => outer == inner,
+            (Self::IsIn(ty, outer), Self::Eq(inner)) => inner.type_name() == ty && outer == inner,
+            (Self::IsIn(ty, outer), Self::IsIn(inner_ty, inner)) => {
+                ty == inner_ty && outer == inner
+            }
+            _ => false,
+        }
+    }
+
+    fn matches(&self, entities: &Entities, uid: &EntityUid) -> bool {
+        let is_in = |group: &EntityUid| {
+            uid == group
+                || entities
+                    .ancestors(uid)
+                    .into_iter()
+                    .flatten()
+                    .any(|ancestor| ancestor == group)
+        };
+        match self {
+            Self::Any => true,
+            Self::Eq(other) => uid == other,
+            Self::In(group) => is_in(group),
+            Self::Is(ty) => uid.type_name() == ty,
+            Self::IsIn(ty, group) => uid.type_name() == ty && is_in(group),
+        }
+    }
+}
+
+impl From<PrincipalConstraint> for Scope {
+    fn from(constraint: PrincipalConstraint) -> Self {
+        match constraint {
+            PrincipalConstraint::Any => Self::Any,
+            PrincipalConstraint::Eq(uid) => Self::Eq(uid),
+            PrincipalConstraint::In(uid) => Self::In(uid),
+            PrincipalConstraint::Is(ty) => Self::Is(ty),
+            PrincipalConstraint::IsIn(ty, uid) => Self::IsIn(ty, uid),
+        }
+    }
+}
+
+impl From<ResourceConstraint> for Scope {
+    fn from(constraint: ResourceConstraint) -> Self {
+        match constraint {
+            ResourceConstraint::Any => Self::Any,
+            ResourceConstraint::Eq(uid) => Self::Eq(uid),
+            ResourceConstraint::In(uid) => Self::In(uid),
+            ResourceConstraint::Is(ty) => Self::Is(ty),
+            ResourceConstraint::IsIn(ty, uid) => Self::IsIn(ty, uid),
+        }
+    }
+}
+
+/// The request environments `policy` typechecks for. Policies that are always false for an
+/// environment, e.g. because their scope names another type, do not typecheck for it.
+pub(crate) fn request_envs(schema: &Schema, policy: &Policy) -> BTreeSet<RequestEnv> {
+    policy.get_valid_request_envs(schema).collect()
+}
+
+/// Whether two policy scopes can match the same request. Only `==` constraints on different
+/// entities are known to be disjoint; `in` constraints depend on the entity hierarchy.
+pub(crate) fn scopes_overlap(a: &Policy, b: &Policy) -> bool {
+    let principals = match (a.principal_constraint(), b.principal_constraint()) {
+        (PrincipalConstraint::Eq(a), PrincipalConstraint::Eq(b)) => a == b,
+        (PrincipalConstraint::Eq(uid), other) | (other, PrincipalConstraint::Eq(uid)) => {
+            type_allowed(&uid, principal_type(&other))
+        }
+        _ => true,
+    };
+    let resources = match (a.resource_constraint(), b.resource_constraint()) {
+        (ResourceConstraint::Eq(a), ResourceConstraint::Eq(b)) => a == b,
+        (ResourceConstraint::Eq(uid), other) | (other, ResourceConstraint::Eq(uid)) => {
+            type_allowed(&uid, resource_type(&other))
+        }
+        _ => true,
+    };
+    principals && resources
+}
+
+fn type_allowed(uid: &EntityUid, required: Option<&EntityTypeName>) -> bool {
+    required.is_none_or(|t| uid.type_name() == t)
+}
+
+fn principal_type(constraint: &PrincipalConstraint) -> Option<&EntityTypeName> {
+    match constraint {
+        PrincipalConstraint::Is(t) | PrincipalConstraint::IsIn(t, _) => Some(t),
+        _ => None,
+    }
+}
+
+fn resource_type(constraint: &ResourceConstraint) -> Option<&EntityTypeName> {
+    match constraint {
+        ResourceConstraint::Is(t) | ResourceConstraint::IsIn(t, _) => Some(t),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::CEDAR_SCHEMA;
+
+    #[test]
+    fn test_conflicts() {
+        let policies = PolicySet::from_str(
+            r#"
+            permit (principal, action == MyApp::Action::"GetProjectMetadata", resource);
+            permit (principal == MyApp::User::"0", action, resource);
+            forbid (principal == MyApp::User::"1", action, resource is MyApp::Project);
+            forbid (principal, action, resource is MyApp::Server);
+            "#,
+        )
+        .unwrap();
+        let conflicts = conflicts(&CEDAR_SCHEMA, &policies).unwrap();
+        let pairs = conflicts
+            .iter()
+            .map(|c| (c.permit.as_str(), c.forbid.as_str()))
+            .collect::<Vec<_>>();
+        // `policy1` is only for `MyApp::User::"0"`, so it cannot meet `policy2`.
+        assert_eq!(pairs, vec![("policy0", "policy2"), ("policy1", "policy3")]);
+        assert!(conflicts[0].shapes.iter().all(|s| {
+            s.action == r#"MyApp::Action::"GetProjectMetadata""#
+                && s.resource_type == "MyApp::Project"
+        }));
+    }
+
+    #[test]
+    fn test_findings() {
+        let policies = PolicySet::from_str(
+            r#"
+            permit (principal, action in MyApp::Action::"ProjectActions", resource);
+            permit (principal == MyApp::User::"0", action == MyApp::Action::"DeleteProject", resource);
+            forbid (principal == MyApp::User::"1", action, resource);
+            permit (principal == MyApp::User::"1", action, resource is MyApp::Server);
+            permit (principal, action == MyApp::Action::"CreateProject", resource is MyApp::Project);
+            permit (principal, action in MyApp::Action::"ProjectActions", resource)
+                when { resource in MyApp::Server::"0" };
+            "#,
+        )
+        .unwrap();
+        let findings = findings(&CEDAR_SCHEMA, &policies).unwrap();
+        assert_eq!(
+            findings,
+            vec![
+                Finding::Unsatisfiable {
+                    policy: "policy4".to_string()
+                },
+                Finding::Subsumed {
+                    policy: "policy1".to_string(),
+                    by: "policy0".to_string()
+                },
+                Finding::Subsumed {
+                    policy: "policy5".to_string(),
+                    by: "policy0".to_string()
+                },
+                Finding::Overridden {
+                    policy: "policy3".to_string(),
+                    by: "policy2".to_string()
+                },
+            ]
+        );
+        assert_eq!(
+            serde_json::to_value(&findings[0]).unwrap(),
+            serde_json::json!({ "kind": "unsatisfiable", "policy": "policy4" })
+        );
+    }
+}
@@ -0,0 +1,137 @@
+//! Symbolic answers to "who can perform this action on this resource".
+
+use cedar_policy::{Effect, EntityTypeName, EntityUid, PolicySetError};
+use serde::Serialize;
+use serde_json::{Value, json};
+
+use crate::{error::Result, namespace::id_str, residuals::Residuals};
+
+/// The principals a policy applies to for a fixed action and resource. A principal matches if
+/// it has the type, is `principal` if given, is in every group of `memberships`, and satisfies
+/// every condition.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct PrincipalGrant {
+    pub policy: String,
+    pub principal_type: String,
+    pub principal: Option<String>,
+    pub memberships: Vec<String>,
+    /// The remaining conditions in Cedar syntax, e.g. `principal.project == MyApp::Project::"0"`.
+    pub conditions: Vec<String>,
+}
+
+/// All principals allowed by a `permit` and not excluded by a `forbid`. Entity data that is
+/// known is already applied, so the description only depends on the principal.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct PrincipalDescription {
+    pub permits: Vec<PrincipalGrant>,
+    pub forbids: Vec<PrincipalGrant>,
+}
+
+impl PrincipalDescription {
+    /// Add the policies of `residuals`, computed for an unknown principal of `principal_type`.
+    pub(crate) fn add(
+        &mut self,
+        principal_type: &EntityTypeName,
+        residuals: &Residuals,
+    ) -> Result<()> {
+        for policy in residuals.policies() {
+            let json = policy.to_json().map_err(PolicySetError::from)?;
+            // Residual policies have a single `when` clause.
+            let Some(grant) = grant(principal_type, &json["conditions"][0]["body"])? else {
+                continue;
+            };
+            let grant = PrincipalGrant {
+                policy: id_str(policy.id()).to_string(),
+                ..grant
+            };
+            match policy.effect() {
+                Effect::Permit => self.permits.push(grant),
+                Effect::Forbid => self.forbids.push(grant),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The grant for a residual condition, or `None` if no principal of the type satisfies it.
+fn grant(principal_type: &EntityTypeName, residual: &Value) -> Result<Option<PrincipalGrant>> {
+    let mut grant = PrincipalGrant {
+        policy: String::new(),
+        principal_type: principal_type.to_string(),
+        principal: None,
+        memberships: Vec::new(),
+        conditions: Vec::new(),
+    };
+    let is_principal = |expr: &Value| expr == &json!({ "Var": "principal" });
+    let entity = |expr: &Value| EntityUid::from_json(expr["Value"].clone()).ok();
+    for conjunct in conjuncts(residual) {
+        if let Some(value) = conjunct["Value"].as_bool() {
+            if value {
+                continue;
+            }
+            return Ok(None);
+        }
+        if let Some(is) = conjunct.get("is").filter(|is| is_principal(&is["left"])) {
+            if is["entity_type"].as_str() != Some(grant.principal_type.as_str()) {
+                return Ok(None);
+            }
+            if let Some(group) = is.get("in") {
+                match entity(group) {
+                    Some(uid) => grant.memberships.push(uid.to_string()),
+                    None => grant.conditions.push(cedar_text(
+                        &json!({ "in": { "left": is["left"], "right": group } }),
+                    )?),
+                }
+            }
+            continue;
+        }
+        let (op, operands) = match conjunct.as_object().and_then(|op| op.iter().next()) {
+            Some((op, operands)) if op == "==" || op == "in" => (op, operands),
+            _ => {
+                grant.conditions.push(cedar_text(conjunct)?);
+                continue;
+            }
+        };
+        match (is_principal(&operands["left"]), entity(&operands["right"])) {
+            (true, Some(uid)) if op == "==" => {
+                if uid.type_name() != principal_type {
+                    return Ok(None);
+                }
+                grant.principal = Some(uid.to_string());
+            }
+            (true, Some(uid)) => grant.memberships.push(uid.to_string()),
+            _ => grant.conditions.push(cedar_text(conjunct)?),
+        }
+    }
+    Ok(Some(grant))
+}
+
+pub(super) fn conjuncts(expr: &Value) -> Vec<&Value> {
+    match expr.get("&&") {
+        Some(and) => [conjuncts(&and["left"]), conjuncts(&and["right"])].concat(),
+        None => vec![expr],
+    }
+}
+
+// Cedar only prints whole policies, so the expression is printed as the condition of one.
+pub(crate) fn cedar_text(expr: &Value) -> Result<String> {
+    let policy = cedar_policy::Policy::from_json(
+        None,
+        json!({
+            "effect": "permit",
+            "principal": { "op": "All" },
+            "action": { "op": "All" },
+            "resource": { "op": "All" },
+            "conditions": [{ "kind": "when", "body": expr }]
+        }),
+    )
+    .map_err(PolicySetError::from)?
+    .to_string();
+    let body = policy
+        .split_once("when {")
+        .and_then(|(_, rest)| rest.rsplit_once('}'))
+        .map_or(policy.as_str(), |(body, _)| body);
+    Ok(body.trim().to_string())
+}
@@ -0,0 +1,314 @@
+//! Symbolic analyses with [cedar-policy-symcc](https://crates.io/crates/cedar-policy-symcc).
+//!
+//! Policies are compiled to SMT formulas per request shape and checked by an SMT solver, so the
+//! results hold for every well-typed request and entity store, not just for known entities.
+//! [`LocalSolver::cvc5`] runs the `cvc5` executable named by the `CVC5` environment variable.
+
+use cedar_policy::{Entities, PolicySet, Request, Schema};
+use cedar_policy_symcc::{CedarSymCompiler, CompiledPolicies, Env, solver::Solver};
+use serde::Serialize;
+
+pub use cedar_policy_symcc::solver::LocalSolver;
+
+use super::RequestShape;
+use crate::{
+    engine::static_policies,
+    error::{Error, Result},
+};
+
+/// A request, with the entity store it is evaluated against, on which two policy sets decide
+/// differently.
+#[derive(Debug, Clone)]
+pub struct Difference {
+    pub shape: RequestShape,
+    pub request: Request,
+    pub entities: Entities,
+}
+
+/// Compare `a` and `b` for every request shape of `schema`. Returns a counterexample for each
+/// shape on which they are not equivalent, so an empty result means they are equivalent for all
+/// well-typed requests.
+pub async fn differences<S: Solver>(
+    solver: S,
+    schema: &Schema,
+    a: &PolicySet,
+    b: &PolicySet,
+) -> Result<Vec<Difference>> {
+    let (a, b) = (static_policies(a)?, static_policies(b)?);
+    let mut compiler = CedarSymCompiler::new(solver).map_err(symbolic)?;
+    let mut differences = Vec::new();
+    for env in schema.request_envs() {
+        let compiled_a = CompiledPolicies::compile(&a, &env, schema).map_err(symbolic)?;
+        let compiled_b = CompiledPolicies::compile(&b, &env, schema).map_err(symbolic)?;
+        if let Some(counterexample) = compiler
+            .check_equivalent_with_counterexample_opt(&compiled_a, &compiled_b)
+            .await
+            .map_err(symbolic)?
+        {
+            differences.push(Difference {
+                shape: RequestShape::from(&env),
+                request: counterexample.request,
+                entities: counterexample.entities,
+            });
+        }
+    }
+    Ok(differences)
+}
+
+/// Whether `a` and `b` decide every well-typed request the same way.
+pub async fn equivalent<S: Solver>(
+    solver: S,
+    schema: &Schema,
+    a: &PolicySet,
+    b: &PolicySet,
+) -> Result<bool> {
+    let (a, b) = (static_policies(a)?, static_policies(b)?);
+    let mut compiler = CedarSymCompiler::new(solver).map_err(symbolic)?;
+    for env in schema.request_envs() {
+        let compiled_a = CompiledPolicies::compile(&a, &env, schema).map_err(symbolic)?;
+        let compiled_b = CompiledPolicies::compile(&b, &env, schema).map_err(symbolic)?;
+        if !compiler
+            .check_equivalent_opt(&compiled_a, &compiled_b)
+            .await
+            .map_err(symbolic)?
+        {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Whether `a` allows every well-typed request `b` allows, e.g. whether tenant policies `b` stay
+/// within the guardrails `a`.
+pub async fn entails<S: Solver>(
+    solver: S,
+    schema: &Schema,
+    a: &PolicySet,
+    b: &PolicySet,
+) -> Result<bool> {
+    Ok(excess_up_to(solver, schema, a, b, 1).await?.is_empty())
+}
+
+/// Requests `b` allows and `a` denies, one per request shape. Empty if and only if `a` entails
+/// `b`.
+pub async fn excess<S: Solver>(
+    solver: S,
+    schema: &Schema,
+    a: &PolicySet,
+    b: &PolicySet,
+) -> Result<Vec<Difference>> {
+    excess_up_to(solver, schema, a, b, usize::MAX).await
+}
+
+/// The first `limit` requests of [`excess`], without checking the remaining request shapes.
+async fn excess_up_to<S: Solver>(
+    solver: S,
+    schema: &Schema,
+    a: &PolicySet,
+    b: &PolicySet,
+    limit: usize,
+) -> Result<Vec<Difference>> {
+    let (a, b) = (static_policies(a)?, static_policies(b)?);
+    let mut compiler = CedarSymCompiler::new(solver).map_err(symbolic)?;
+    let mut excess = Vec::new();
+    for env in schema.request_envs() {
+        if excess.len() >= limit {
+            break;
+        }
+        let compiled_a = CompiledPolicies::compile(&a, &env, schema).map_err(symbolic)?;
+        let compiled_b = CompiledPolicies::compile(&b, &env, schema).map_err(symbolic)?;
+        if let Some(counterexample) = compiler
+            .check_implies_with_counterexample_opt(&compiled_b, &compiled_a)
+            .await
+            .map_err(symbolic)?
+        {
+            excess.push(Difference {
+                shape: RequestShape::from(&env),
+                request: counterexample.request,
+                entities: counterexample.entities,
+            });
+        }
+    }
+    Ok(excess)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Equivalent,
+    /// The new policies allow everything the old ones allow, and more.
+    MorePermissive,
+    /// The old policies allow everything the new ones allow, and more.
+    LessPermissive,
+    /// Each policy set allows requests the other denies.
+    Incomparable,
+}
+
+#[derive(Debug, Clone)]
+pub struct SemanticDiff {
+    pub kind: ChangeKind,
+    /// Requests allowed by the new policies but not by the old ones, one per request shape.
+    pub broadened: Vec<Difference>,
+    /// Requests allowed by the old policies but not by the new ones, one per request shape.
+    pub narrowed: Vec<Difference>,
+}
+
+/// Classify the change from `old` to `new` by the requests it allows.
+pub async fn semantic_diff<S: Solver>(
+    solver: S,
+    schema: &Schema,
+    old: &PolicySet,
+    new: &PolicySet,
+) -> Result<SemanticDiff> {
+    let (old, new) = (static_policies(old)?, static_policies(new)?);
+    let mut compiler = CedarSymCompiler::new(solver).map_err(symbolic)?;
+    let (mut broadened, mut narrowed) = (Vec::new(), Vec::new());
+    for env in schema.request_envs() {
+        let compiled_old = CompiledPolicies::compile(&old, &env, schema).map_err(symbolic)?;
+        let compiled_new = CompiledPolicies::compile(&new, &env, schema).map_err(symbolic)?;
+        let difference = |counterexample: Env| Difference {
+            shape: RequestShape::from(&env),
+            request: counterexample.request,
+            entities: counterexample.entities,
+        };
+        if let Some(counterexample) = compiler
+            .check_implies_with_counterexample_opt(&compiled_new, &compiled_old)
+            .await
+            .map_err(symbolic)?
+        {
+            broadened.push(difference(counterexample));
+        }
+        if let Some(counterexample) = compiler
+            .check_implies_with_counterexample_opt(&compiled_old, &compiled_new)
+            .await
+            .map_err(symbolic)?
+        {
+            narrowed.push(difference(counterexample));
+        }
+    }
+    let kind = match (broadened.is_empty(), narrowed.is_empty()) {
+        (true, true) => ChangeKind::Equivalent,
+        (false, true) => ChangeKind::MorePermissive,
+        (true, false) => ChangeKind::LessPermissive,
+        (false, false) => ChangeKind::Incomparable,
+    };
+    Ok(SemanticDiff {
+        kind,
+        broadened,
+        narrowed,
+    })
+}
+
+fn symbolic(e: cedar_policy_symcc::err::Error) -> Error {
+    Error::Symbolic(Box::new(e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::CEDAR_SCHEMA;
+
+    fn solver() -> LocalSolver {
+        LocalSolver::cvc5().unwrap()
+    }
+
+    // Runs with the solver in `CVC5`, and is skipped if it is not set.
+    #[tokio::test]
+    async fn test_equivalence() {
+        if std::env::var_os("CVC5").is_none() {
+            return;
+        }
+        let original = PolicySet::from_str(
+            r#"
+            permit (principal, action == MyApp::Action::"GetProjectMetadata", resource);
+            permit (principal, action == MyApp::Action::"DeleteProject", resource);
+            "#,
+        )
+        .unwrap();
+        let refactored = PolicySet::from_str(
+            r#"permit (principal, action in MyApp::Action::"ProjectActions", resource);"#,
+        )
+        .unwrap();
+        assert!(
+            equivalent(solver(), &CEDAR_SCHEMA, &original, &refactored)
+                .await
+                .unwrap()
+        );
+
+        let narrowed = PolicySet::from_str(
+            r#"permit (principal is MyApp::User, action in MyApp::Action::"ProjectActions", resource);"#,
+        )
+        .unwrap();
+        let differences = differences(solver(), &CEDAR_SCHEMA, &original, &narrowed)
+            .await
+            .unwrap();
+        assert!(!differences.is_empty());
+        assert!(
+            differences
+                .iter()
+                .all(|d| d.shape.principal_type == "MyApp::Role")
+        );
+    }
+
+    // Runs with the solver in `CVC5`, and is skipped if it is not set.
+    #[tokio::test]
+    async fn test_semantic_diff() {
+        if std::env::var_os("CVC5").is_none() {
+            return;
+        }
+        let old = PolicySet::from_str(
+            r#"permit (principal is MyApp::User, action == MyApp::Action::"GetProjectMetadata", resource);"#,
+        )
+        .unwrap();
+        let new = PolicySet::from_str(
+            r#"permit (principal, action == MyApp::Action::"GetProjectMetadata", resource);"#,
+        )
+        .unwrap();
+        let diff = semantic_diff(solver(), &CEDAR_SCHEMA, &old, &new)
+            .await
+            .unwrap();
+        assert_eq!(diff.kind, ChangeKind::MorePermissive);
+        assert!(
+            diff.broadened
+                .iter()
+                .all(|d| d.shape.principal_type == "MyApp::Role")
+        );
+
+        let diff = semantic_diff(solver(), &CEDAR_SCHEMA, &new, &old)
+            .await
+            .unwrap();
+        assert_eq!(diff.kind, ChangeKind::LessPermissive);
+    }
+
+    // Runs with the solver in `CVC5`, and is skipped if it is not set.
+    #[tokio::test]
+    async fn test_entails() {
+        if std::env::var_os("CVC5").is_none() {
+            return;
+        }
+        let guardrails = PolicySet::from_str(
+            r#"permit (principal, action in MyApp::Action::"ProjectActions", resource);"#,
+        )
+        .unwrap();
+        let tenant = PolicySet::from_str(
+            r#"permit (principal is MyApp::User, action == MyApp::Action::"GetProjectMetadata", resource);"#,
+        )
+        .unwrap();
+        assert!(
+            entails(solver(), &CEDAR_SCHEMA, &guardrails, &tenant)
+                .await
+                .unwrap()
+        );
+        assert!(
+            !entails(solver(), &CEDAR_SCHEMA, &tenant, &guardrails)
+                .await
+                .unwrap()
+        );
+        let excess = excess(solver(), &CEDAR_SCHEMA, &tenant, &guardrails)
+            .await
+            .unwrap();
+        assert!(!excess.is_empty());
+    }
+}
@@ -0,0 +1,243 @@
+//! How every policy contributed to a decision, for debugging policies and answering "why".
+
+use std::str::FromStr;
+
+use cedar_policy::{
+    AuthorizationError, Authorizer, Decision, Effect, Expression, Policy, PolicySet,
+    PolicySetError, Request, eval_expression,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::principals::cedar_text;
+use crate::{
+    engine::{EngineState, static_policies},
+    error::{Error, Result},
+    namespace::id_str,
+};
+
+/// The result of evaluating an expression, as a Cedar value or an evaluation error.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Value(String),
+    Error(String),
+}
+
+/// An attribute access in a condition and the value it had.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AttributeValue {
+    pub expression: String,
+    pub outcome: Outcome,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConditionTrace {
+    /// `when` or `unless`.
+    pub kind: String,
+    pub condition: String,
+    pub outcome: Outcome,
+    /// The attribute accesses in the condition, in order of appearance.
+    pub attributes: Vec<AttributeValue>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicyTrace {
+    pub policy: String,
+    pub effect: Effect,
+    pub scope_matched: bool,
+    /// Empty if the scope did not match, as the conditions are not evaluated then.
+    pub conditions: Vec<ConditionTrace>,
+    /// The scope matched and all conditions held.
+    pub satisfied: bool,
+    /// The policy is one of the reasons for the decision.
+    pub determining: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DecisionTrace {
+    pub decision: Decision,
+    /// Every policy, ordered by ID.
+    pub policies: Vec<PolicyTrace>,
+}
+
+/// Evaluate `request` against `state` and trace every policy: whether its scope matched, the
+/// value of each condition and of the attributes it reads, and whether it determined the
+/// decision. Template-linked policies are traced with their slots filled in.
+pub fn explain_decision(state: &EngineState, request: &Request) -> Result<DecisionTrace> {
+    let authorizer = Authorizer::new();
+    let response = authorizer.is_authorized(request, state.policies(), state.entities());
+    let evaluate = |expr: &Value| -> Result<Outcome> {
+        let text = cedar_text(expr)?;
+        let expression = Expression::from_str(&text)
+            .map_err(|e| Error::Mapping(format!("Invalid expression `{text}`: {e}")))?;
+        let outcome = match eval_expression(request, state.entities(), &expression) {
+            Ok(value) => Outcome::Value(value.to_string()),
+            Err(e) => Outcome::Error(e.to_string()),
+        };
+        Ok(outcome)
+    };
+
+    let mut policies = Vec::new();
+    for policy in static_policies(state.policies())?.policies() {
+        let json = policy.to_json().map_err(PolicySetError::from)?;
+        let scope_matched = scope_matches(&authorizer, state, request, &json)?;
+        let mut conditions = Vec::new();
+        if scope_matched {
+            for condition in json["conditions"].as_array().into_iter().flatten() {
+                let body = &condition["body"];
+                let mut accesses = Vec::new();
+                attribute_accesses(body, &mut accesses);
+                let mut attributes = Vec::<AttributeValue>::new();
+                for access in accesses {
+                    let expression = cedar_text(access)?;
+                    if attributes.iter().all(|a| a.expression != expression) {
+                        let outcome = evaluate(access)?;
+                        attributes.push(AttributeValue {
+                            expression,
+                            outcome,
+                        });
+                    }
+                }
+                conditions.push(ConditionTrace {
+                    kind: condition["kind"].as_str().unwrap_or("when").to_string(),
+                    condition: cedar_text(body)?,
+                    outcome: evaluate(body)?,
+                    attributes,
+                });
+            }
+        }
+        let error = response.diagnostics().errors().find_map(|e| match e {
+            AuthorizationError::PolicyEvaluationError(e) => {
+                (e.policy_id() == policy.id()).then(|| e.inner().to_string())
+            }
+        });
+        policies.push(PolicyTrace {
+            policy: id_str(policy.id()).to_string(),
+            effect: policy.effect(),
+            scope_matched,
+            satisfied: scope_matched && conditions.iter().all(ConditionTrace::holds),
+            determining: response.diagnostics().reason().any(|r| r == policy.id()),
+            conditions,
+            error,
+        });
+    }
+    policies.sort_by(|a, b| a.policy.cmp(&b.policy));
+    Ok(DecisionTrace {
+        decision: response.decision(),
+        policies,
+    })
+}
+
+impl ConditionTrace {
+    fn holds(&self) -> bool {
+        let expected = if self.kind == "unless" {
+            "false"
+        } else {
+            "true"
+        };
+        self.outcome == Outcome::Value(expected.to_string())
+    }
+}
+
+/// Whether the scope of the policy in `json` matches, by evaluating it without its conditions.
+fn scope_matches(
+    authorizer: &Authorizer,
+    state: &EngineState,
+    request: &Request,
+    json: &Value,
+) -> Result<bool> {
+    let mut scope = json.clone();
+    scope["effect"] = "permit".into();
+    scope["conditions"] = Value::Array(Vec::new());
+    let policy = Policy::from_json(None, scope).map_err(PolicySetError::from)?;
+    let policies = PolicySet::from_policies([policy])?;
+    let response = authorizer.is_authorized(request, &policies, state.entities());
+    Ok(response.decision() == Decision::Allow)
+}
+
+/// The `.` expressions in `expr`, outermost first.
+fn attribute_accesses<'a>(expr: &'a Value, accesses: &mut Vec<&'a Value>) {
+    match expr {
+        Value::Object(map) => {
+            if map.contains_key(".") {
+                accesses.push(expr);
+            }
+            for value in map.values() {
+                attribute_accesses(value, accesses);
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                attribute_accesses(value, accesses);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cedar_policy::{Context, Entities, EntityUid, SchemaFragment};
+
+    use super::*;
+    use crate::{CEDAR_SCHEMA, CEDAR_SCHEMA_SRC};
+
+    #[test]
+    fn test_explain_decision() {
+        let entities = Entities::from_json_str(
+            r#"[
+                { "uid": { "type": "MyApp::Project", "id": "0" }, "attrs": {}, "parents": [] },
+                {
+                    "uid": { "type": "MyApp::Role", "id": "admin" },
+                    "attrs": { "project": { "type": "MyApp::Project", "id": "0" } },
+                    "parents": []
+                }
+            ]"#,
+            Some(&CEDAR_SCHEMA),
+        )
+        .unwrap();
+        let state = EngineState::new(
+            SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(),
+            PolicySet::from_str(
+                r#"
+                permit (principal is MyApp::Role, action, resource is MyApp::Project)
+                    when { principal.project == resource };
+                forbid (principal, action == MyApp::Action::"DeleteProject", resource);
+                "#,
+            )
+            .unwrap(),
+            entities,
+        )
+        .unwrap();
+        let request = Request::new(
+            EntityUid::from_str(r#"MyApp::Role::"admin""#).unwrap(),
+            EntityUid::from_str(r#"MyApp::Action::"GetProjectMetadata""#).unwrap(),
+            EntityUid::from_str(r#"MyApp::Project::"0""#).unwrap(),
+            Context::empty(),
+            Some(&CEDAR_SCHEMA),
+        )
+        .unwrap();
+
+        let trace = explain_decision(&state, &request).unwrap();
+        assert_eq!(trace.decision, Decision::Allow);
+        let [permit, forbid] = trace.policies.as_slice() else {
+            panic!("expected two policies");
+        };
+        assert!(permit.scope_matched && permit.satisfied && permit.determining);
+        assert_eq!(
+            permit.conditions[0].outcome,
+            Outcome::Value("true".to_string())
+        );
+        assert_eq!(
+            permit.conditions[0].attributes,
+            [AttributeValue {
+                expression: "principal.project".to_string(),
+                outcome: Outcome::Value(r#"MyApp::Project::"0""#.to_string()),
+            }]
+        );
+        assert!(!forbid.scope_matched && !forbid.satisfied && !forbid.determining);
+        assert!(forbid.conditions.is_empty());
+    }
+}
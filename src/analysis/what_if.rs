@@ -0,0 +1,185 @@
+//! The impact of a pending policy change on concrete requests.
+
+use cedar_policy::{
+    Authorizer, Context, Decision, EntityTypeName, Policy, PolicyId, PolicySet, Request,
+};
+
+use crate::{
+    engine::{EngineState, validate_policies},
+    error::Result,
+    namespace::id_str,
+};
+
+/// A candidate change to the static policies of a state.
+#[derive(Debug, Clone)]
+pub enum PolicyChange {
+    Add(Policy),
+    /// Replace the static policy with the same ID.
+    Replace(Policy),
+    Remove(PolicyId),
+}
+
+impl PolicyChange {
+    fn apply(&self, policies: &mut PolicySet) -> Result<()> {
+        match self {
+            Self::Add(policy) => policies.add(policy.clone())?,
+            Self::Replace(policy) => {
+                policies.remove_static(policy.id().clone())?;
+                policies.add(policy.clone())?;
+            }
+            Self::Remove(id) => {
+                policies.remove_static(id.clone())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A request whose decision changes.
+#[derive(Debug, Clone)]
+pub struct Flip {
+    pub request: Request,
+    pub before: Decision,
+    pub after: Decision,
+    /// The policies that determine the new decision.
+    pub reasons: Vec<String>,
+}
+
+/// The requests whose decision changes if `changes` are applied to the policies of `state`. The
+/// changed policies must validate against the schema. `requests` can be recorded traffic or
+/// [`synthetic_requests`].
+pub fn what_if<'a>(
+    state: &EngineState,
+    changes: &[PolicyChange],
+    requests: impl IntoIterator<Item = &'a Request>,
+) -> Result<Vec<Flip>> {
+    let mut changed = state.policies().clone();
+    for change in changes {
+        change.apply(&mut changed)?;
+    }
+    validate_policies(state.schema(), &changed)?;
+
+    let authorizer = Authorizer::new();
+    let flips = requests
+        .into_iter()
+        .filter_map(|request| {
+            let before = authorizer.is_authorized(request, state.policies(), state.entities());
+            let after = authorizer.is_authorized(request, &changed, state.entities());
+            (before.decision() != after.decision()).then(|| {
+                let mut reasons = after
+                    .diagnostics()
+                    .reason()
+                    .map(|id| id_str(id).to_string())
+                    .collect::<Vec<_>>();
+                reasons.sort();
+                Flip {
+                    request: request.clone(),
+                    before: before.decision(),
+                    after: after.decision(),
+                    reasons,
+                }
+            })
+        })
+        .collect();
+    Ok(flips)
+}
+
+/// A request with an empty context for every combination of known principal, action and known
+/// resource the schema allows. Actions whose context cannot be empty are left out.
+pub fn synthetic_requests(state: &EngineState) -> Vec<Request> {
+    let schema = state.schema();
+    let of_type = |type_name: &EntityTypeName| {
+        state
+            .entities()
+            .iter()
+            .map(|entity| entity.uid())
+            .filter(|uid| uid.type_name() == type_name)
+            .collect::<Vec<_>>()
+    };
+    let mut requests = Vec::new();
+    for env in schema.request_envs() {
+        let resources = of_type(env.resource());
+        for principal in of_type(env.principal()) {
+            requests.extend(resources.iter().filter_map(|resource| {
+                Request::new(
+                    principal.clone(),
+                    env.action().clone(),
+                    resource.clone(),
+                    Context::empty(),
+                    Some(schema),
+                )
+                .ok()
+            }));
+        }
+    }
+    requests
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use cedar_policy::{Entities, SchemaFragment};
+
+    use super::*;
+    use crate::{CEDAR_SCHEMA, CEDAR_SCHEMA_SRC, error::Error};
+
+    #[test]
+    fn test_what_if() {
+        let entities = Entities::from_json_str(
+            r#"[
+                { "uid": { "type": "MyApp::Server", "id": "0" }, "attrs": {}, "parents": [] },
+                {
+                    "uid": { "type": "MyApp::Project", "id": "0" },
+                    "attrs": {},
+                    "parents": [{ "type": "MyApp::Server", "id": "0" }]
+                },
+                { "uid": { "type": "MyApp::User", "id": "0" }, "attrs": {}, "parents": [] },
+                { "uid": { "type": "MyApp::User", "id": "1" }, "attrs": {}, "parents": [] }
+            ]"#,
+            Some(&CEDAR_SCHEMA),
+        )
+        .unwrap();
+        let state = EngineState::new(
+            SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(),
+            PolicySet::from_str("permit (principal, action, resource);").unwrap(),
+            entities,
+        )
+        .unwrap();
+        let requests = synthetic_requests(&state);
+        // 2 users on 1 server and 1 project, for 2 actions each.
+        assert_eq!(requests.len(), 8);
+
+        let forbid = Policy::parse(
+            Some(PolicyId::new("no-user-1")),
+            r#"forbid (principal == MyApp::User::"1", action, resource is MyApp::Project);"#,
+        )
+        .unwrap();
+        let flips = what_if(&state, &[PolicyChange::Add(forbid)], &requests).unwrap();
+        assert_eq!(flips.len(), 2);
+        assert!(flips.iter().all(|flip| {
+            flip.before == Decision::Allow
+                && flip.after == Decision::Deny
+                && flip.reasons == ["no-user-1"]
+                && flip.request.principal().unwrap().to_string() == r#"MyApp::User::"1""#
+        }));
+
+        let flips = what_if(
+            &state,
+            &[PolicyChange::Remove(PolicyId::new("policy0"))],
+            &requests,
+        )
+        .unwrap();
+        assert_eq!(flips.len(), requests.len());
+
+        let invalid = Policy::parse(
+            Some(PolicyId::new("invalid")),
+            "permit (principal, action, resource) when { principal.missing };",
+        )
+        .unwrap();
+        assert!(matches!(
+            what_if(&state, &[PolicyChange::Add(invalid)], &requests),
+            Err(Error::Validation(_))
+        ));
+    }
+}
@@ -0,0 +1,190 @@
+//! Typed access to policy annotations, so operational metadata such as owners or expiry dates
+//! can ride along with policies.
+//!
+//! Annotations are deserialized into any `serde` type, with annotation keys as field names and
+//! the annotation values as strings. An annotation without a value, such as `@experimental`,
+//! has the empty string as value.
+//!
+//! Policies are tagged with a comma-separated `@tags("experimental, beta")` annotation. A
+//! [`TagFilter`] selects the policies an evaluation considers, so staged policies can live next
+//! to production policies without affecting their decisions.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use cedar_policy::{Policy, PolicySet};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::{
+    error::{Error, Result},
+    namespace::id_str,
+    store::PolicyStore,
+};
+
+/// Common operational metadata. Other annotations are kept in `other`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicyMetadata {
+    pub owner: Option<String>,
+    pub ticket: Option<String>,
+    /// An RFC 3339 timestamp, e.g. `@expiry("2025-01-01T00:00:00Z")`.
+    pub expiry: Option<DateTime<Utc>>,
+    pub severity: Option<Severity>,
+    #[serde(flatten)]
+    pub other: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+/// Deserialize the annotations of `policy` into `T`.
+pub fn annotations<T: DeserializeOwned>(policy: &Policy) -> Result<T> {
+    let map = policy
+        .annotations()
+        .map(|(key, value)| (key.to_string(), serde_json::Value::from(value)))
+        .collect::<serde_json::Map<_, _>>();
+    serde_json::from_value(serde_json::Value::Object(map)).map_err(|source| Error::Annotations {
+        id: id_str(policy.id()).to_string(),
+        source,
+    })
+}
+
+pub const TAGS: &str = "tags";
+
+/// The tags of `policy` from its `@tags` annotation.
+pub fn tags(policy: &Policy) -> BTreeSet<&str> {
+    policy
+        .annotation(TAGS)
+        .into_iter()
+        .flat_map(|tags| tags.split(','))
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .collect()
+}
+
+/// Selects policies by tag. A policy matches if it has any of the included tags, or if no tags
+/// are included, and none of the excluded tags. The default filter matches every policy.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TagFilter {
+    #[serde(default)]
+    pub include: BTreeSet<String>,
+    #[serde(default)]
+    pub exclude: BTreeSet<String>,
+}
+
+impl TagFilter {
+    pub fn include(mut self, tag: impl Into<String>) -> Self {
+        self.include.insert(tag.into());
+        self
+    }
+
+    pub fn exclude(mut self, tag: impl Into<String>) -> Self {
+        self.exclude.insert(tag.into());
+        self
+    }
+
+    pub fn matches(&self, policy: &Policy) -> bool {
+        let tags = tags(policy);
+        (self.include.is_empty() || self.include.iter().any(|t| tags.contains(t.as_str())))
+            && !self.exclude.iter().any(|t| tags.contains(t.as_str()))
+    }
+
+    /// The matching policies of `policies`, which must all be static.
+    pub(crate) fn apply(&self, policies: &PolicySet) -> Result<PolicySet> {
+        Ok(PolicySet::from_policies(
+            policies.policies().filter(|p| self.matches(p)).cloned(),
+        )?)
+    }
+}
+
+/// The policies in `store` whose annotations, deserialized into `T`, satisfy `filter`.
+pub async fn query<T: DeserializeOwned>(
+    store: &impl PolicyStore,
+    filter: impl Fn(&T) -> bool,
+) -> Result<Vec<(Policy, T)>> {
+    let mut matches = Vec::new();
+    for policy in store.list().await? {
+        let metadata = annotations::<T>(&policy)?;
+        if filter(&metadata) {
+            matches.push((policy, metadata));
+        }
+    }
+    Ok(matches)
+}
+
+#[cfg(test)]
+mod tests {
+    use cedar_policy::PolicyId;
+
+    use super::*;
+    use crate::{CEDAR_SCHEMA, store::MemoryPolicyStore};
+
+    fn policy(id: &str, annotations: &str) -> Policy {
+        Policy::parse(
+            Some(PolicyId::new(id)),
+            format!("{annotations} permit (principal, action, resource);"),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_policy_metadata() {
+        let metadata = annotations::<PolicyMetadata>(&policy(
+            "p",
+            r#"@owner("team-a") @expiry("2025-01-01T00:00:00Z") @severity("high") @experimental"#,
+        ))
+        .unwrap();
+        assert_eq!(metadata.owner.as_deref(), Some("team-a"));
+        assert_eq!(
+            metadata.expiry.unwrap().to_rfc3339(),
+            "2025-01-01T00:00:00+00:00"
+        );
+        assert_eq!(metadata.severity, Some(Severity::High));
+        assert_eq!(
+            metadata.other.get("experimental").map(String::as_str),
+            Some("")
+        );
+
+        let err = annotations::<PolicyMetadata>(&policy("bad", r#"@severity("urgent")"#));
+        assert!(matches!(err, Err(Error::Annotations { id, .. }) if id == "bad"));
+    }
+
+    #[test]
+    fn test_tag_filter() {
+        let stable = policy("stable", "");
+        let staged = policy("staged", r#"@tags("experimental, beta")"#);
+        assert_eq!(tags(&staged), BTreeSet::from(["beta", "experimental"]));
+
+        let production = TagFilter::default().exclude("experimental");
+        assert!(production.matches(&stable));
+        assert!(!production.matches(&staged));
+        let beta = TagFilter::default().include("beta");
+        assert!(!beta.matches(&stable));
+        assert!(beta.matches(&staged));
+    }
+
+    #[tokio::test]
+    async fn test_query() {
+        #[derive(Deserialize)]
+        struct Owned {
+            owner: String,
+        }
+
+        let store = MemoryPolicyStore::new(CEDAR_SCHEMA.clone());
+        store.put(policy("a", r#"@owner("team-a")"#)).await.unwrap();
+        store.put(policy("b", r#"@owner("team-b")"#)).await.unwrap();
+        let owned = query::<Owned>(&store, |m| m.owner == "team-b")
+            .await
+            .unwrap();
+        assert_eq!(owned.len(), 1);
+        assert_eq!(owned[0].0.id(), &PolicyId::new("b"));
+
+        store.put(policy("c", "")).await.unwrap();
+        assert!(query::<Owned>(&store, |_| true).await.is_err());
+    }
+}
@@ -0,0 +1,626 @@
+//! An audit log of the decisions made by an [`Engine`](crate::Engine).
+//!
+//! While a sink is installed with [`crate::Engine::set_audit_sink`], every decision is
+//! recorded with the request, the determining and erroring policies, and the fingerprints of
+//! the policies and entities it was made with. Records have the fields of a
+//! [`RecordedRequest`](crate::replay::RecordedRequest), so a JSON-lines audit log can be
+//! replayed with [`crate::replay::replay`]. With the `otel` feature, [`otel::OtelSink`] adds
+//! decisions to the active OpenTelemetry span; [`otel::OtelSink::wrap`] does so in addition to
+//! another sink, with a [`FanOutSink`]. Partially evaluated requests are recorded as a
+//! [`PartialDecision`] with their residuals.
+//!
+//! An [`AuditConfig`] samples allowed decisions and redacts context attributes before records
+//! reach the sink. Records name entities by UID only, so entity attributes never reach it.
+
+use std::{
+    collections::HashSet,
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    sync::{
+        Mutex, PoisonError,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use cedar_policy::{
+    AuthorizationError, Context, Decision, Entity, EntityId, EntityUid, Request, Response,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    error::{Error, ErrorHandling, Result},
+    namespace::id_str,
+    pdp::PartialUid,
+};
+
+#[cfg(feature = "otel")]
+pub mod otel;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub timestamp: DateTime<Utc>,
+    pub principal: String,
+    pub action: String,
+    pub resource: String,
+    pub context: Option<Value>,
+    pub decision: Decision,
+    /// The policies that determined the decision, sorted.
+    pub reasons: Vec<String>,
+    pub errors: Vec<AuditError>,
+    /// [`crate::fingerprint::StateFingerprint::policies`] of the evaluated policies: the
+    /// installed ones, a rollout candidate or the policies matching a tag filter.
+    pub policies_fingerprint: String,
+    /// [`crate::fingerprint::StateFingerprint::entities`] of the evaluated entities.
+    pub entities_fingerprint: String,
+    /// How erroring policies affected the decision.
+    #[serde(default)]
+    pub on_error: ErrorHandling,
+}
+
+/// A partially evaluated request, recorded by [`crate::Engine::tpe`] and
+/// [`crate::Engine::tpe_filtered`]. Written to the same log as decisions, so
+/// [`crate::replay::replay`] skips it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PartialDecision {
+    pub timestamp: DateTime<Utc>,
+    pub principal: PartialUid,
+    pub action: String,
+    pub resource: PartialUid,
+    pub context: Option<Value>,
+    /// `None` if the residuals depend on the unknowns.
+    pub decision: Option<Decision>,
+    /// The residual policies that are neither `true` nor `false`, sorted.
+    pub residuals: Vec<String>,
+    /// The policies that may fail to evaluate, sorted.
+    pub errors: Vec<String>,
+    /// Like [`AuditRecord::policies_fingerprint`].
+    pub policies_fingerprint: String,
+    pub entities_fingerprint: String,
+    #[serde(default)]
+    pub on_error: ErrorHandling,
+}
+
+/// A time-bound policy that passed its `@not_after`, see [`crate::activation`]. Written to the
+/// same log as decisions, so [`crate::replay::replay`] skips it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicyExpiry {
+    pub timestamp: DateTime<Utc>,
+    pub expired_policy: String,
+    pub not_after: DateTime<Utc>,
+}
+
+/// A policy that failed to evaluate and was skipped.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditError {
+    pub policy: String,
+    pub message: String,
+}
+
+impl AuditRecord {
+    pub(crate) fn new(
+        request: &Request,
+        response: &Response,
+        policies_fingerprint: &str,
+        entities_fingerprint: &str,
+        on_error: ErrorHandling,
+    ) -> Self {
+        let uid = |uid: Option<&EntityUid>| uid.map(ToString::to_string).unwrap_or_default();
+        let mut reasons = response
+            .diagnostics()
+            .reason()
+            .map(|id| id_str(id).to_string())
+            .collect::<Vec<_>>();
+        reasons.sort();
+        Self {
+            timestamp: Utc::now(),
+            principal: uid(request.principal()),
+            action: uid(request.action()),
+            resource: uid(request.resource()),
+            context: request.context().and_then(|c| context_json(c).ok()),
+            decision: response.decision(),
+            reasons,
+            errors: response
+                .diagnostics()
+                .errors()
+                .map(|e| match e {
+                    AuthorizationError::PolicyEvaluationError(e) => AuditError {
+                        policy: id_str(e.policy_id()).to_string(),
+                        message: e.inner().to_string(),
+                    },
+                })
+                .collect(),
+            policies_fingerprint: policies_fingerprint.to_string(),
+            entities_fingerprint: entities_fingerprint.to_string(),
+            on_error,
+        }
+    }
+}
+
+/// Cedar has no JSON format for a context on its own, but one for entity attributes.
+pub(crate) fn context_json(context: &Context) -> Result<Value> {
+    let uid = EntityUid::from_type_name_and_id(
+        "Context".parse().expect("`Context` is a valid type name"),
+        EntityId::new(""),
+    );
+    let entity = Entity::new(uid, context.clone().into_iter().collect(), HashSet::new())
+        .map_err(|e| Error::Mapping(e.to_string()))?;
+    Ok(entity.to_json_value()?["attrs"].take())
+}
+
+/// Receives a record of every decision. Sinks are called on the request path, so they should
+/// be fast.
+pub trait AuditSink: Send + Sync + 'static {
+    fn record(&self, record: &AuditRecord) -> io::Result<()>;
+
+    /// Called when a time-bound policy expired. Ignored by default.
+    fn record_expiry(&self, _expiry: &PolicyExpiry) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called for every partially evaluated request. Ignored by default.
+    fn record_partial(&self, _decision: &PartialDecision) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes one JSON object per line. Every record is written with a single `write_all`, so
+/// records of concurrent requests do not interleave.
+#[derive(Debug)]
+pub struct JsonLinesSink<W> {
+    writer: Mutex<W>,
+}
+
+impl<W: Write + Send + 'static> JsonLinesSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: Mutex::new(writer),
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub(crate) fn write_json(&self, value: &impl Serialize) -> io::Result<()> {
+        let mut line = serde_json::to_vec(value)?;
+        line.push(b'\n');
+        let mut writer = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        writer.write_all(&line)?;
+        writer.flush()
+    }
+}
+
+impl JsonLinesSink<File> {
+    /// Append to the file at `path`, creating it if needed.
+    pub fn append(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self::new(file))
+    }
+}
+
+impl<W: Write + Send + 'static> AuditSink for JsonLinesSink<W> {
+    fn record(&self, record: &AuditRecord) -> io::Result<()> {
+        self.write_json(record)
+    }
+
+    fn record_expiry(&self, expiry: &PolicyExpiry) -> io::Result<()> {
+        self.write_json(expiry)
+    }
+
+    fn record_partial(&self, decision: &PartialDecision) -> io::Result<()> {
+        self.write_json(decision)
+    }
+}
+
+/// Writes JSON lines to standard output, e.g. for collection by a log shipper.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StdoutSink;
+
+impl AuditSink for StdoutSink {
+    fn record(&self, record: &AuditRecord) -> io::Result<()> {
+        write_stdout(record)
+    }
+
+    fn record_expiry(&self, expiry: &PolicyExpiry) -> io::Result<()> {
+        write_stdout(expiry)
+    }
+
+    fn record_partial(&self, decision: &PartialDecision) -> io::Result<()> {
+        write_stdout(decision)
+    }
+}
+
+fn write_stdout(value: &impl Serialize) -> io::Result<()> {
+    let mut line = serde_json::to_vec(value)?;
+    line.push(b'\n');
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(&line)?;
+    stdout.flush()
+}
+
+/// Records to several sinks, in the order they were added. A failing sink does not keep the
+/// others from recording; the first failure is returned.
+#[derive(Default)]
+pub struct FanOutSink {
+    sinks: Vec<Box<dyn AuditSink>>,
+}
+
+impl std::fmt::Debug for FanOutSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FanOutSink")
+            .field("sinks", &self.sinks.len())
+            .finish()
+    }
+}
+
+impl FanOutSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, sink: impl AuditSink) -> Self {
+        self.sinks.push(Box::new(sink));
+        self
+    }
+
+    fn each(&self, f: impl Fn(&dyn AuditSink) -> io::Result<()>) -> io::Result<()> {
+        self.sinks
+            .iter()
+            .map(|sink| f(sink.as_ref()))
+            .fold(Ok(()), Result::and)
+    }
+}
+
+impl AuditSink for FanOutSink {
+    fn record(&self, record: &AuditRecord) -> io::Result<()> {
+        self.each(|sink| sink.record(record))
+    }
+
+    fn record_expiry(&self, expiry: &PolicyExpiry) -> io::Result<()> {
+        self.each(|sink| sink.record_expiry(expiry))
+    }
+
+    fn record_partial(&self, decision: &PartialDecision) -> io::Result<()> {
+        self.each(|sink| sink.record_partial(decision))
+    }
+}
+
+/// Which decisions are recorded, and what is redacted from them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditConfig {
+    /// Share of allowed decisions recorded, from 0 to 100. Denied decisions are always recorded.
+    pub allow_percentage: u8,
+    /// Dot-separated paths of context attributes whose values are replaced by [`REDACTED`],
+    /// e.g. `user.email` for `context.user.email`.
+    pub redact: Vec<String>,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            allow_percentage: 100,
+            redact: Vec::new(),
+        }
+    }
+}
+
+pub const REDACTED: &str = "[redacted]";
+
+fn redact(context: &mut Value, path: &str) {
+    let mut value = context;
+    let mut keys = path.split('.').peekable();
+    while let Some(key) = keys.next() {
+        let Some(next) = value.get_mut(key) else {
+            return;
+        };
+        if keys.peek().is_none() {
+            *next = REDACTED.into();
+            return;
+        }
+        value = next;
+    }
+}
+
+/// An installed sink with the number of records it failed to write.
+pub(crate) struct Audit {
+    sink: Box<dyn AuditSink>,
+    config: AuditConfig,
+    allowed: AtomicU64,
+    failures: AtomicU64,
+}
+
+impl std::fmt::Debug for Audit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Audit")
+            .field("failures", &self.failures)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Audit {
+    pub(crate) fn new(sink: impl AuditSink, config: AuditConfig) -> Self {
+        Self {
+            sink: Box::new(sink),
+            config,
+            allowed: AtomicU64::new(0),
+            failures: AtomicU64::new(0),
+        }
+    }
+
+    // Allowed decisions are sampled by count, so exactly the configured share is recorded.
+    fn is_sampled(&self, decision: Option<Decision>) -> bool {
+        let percentage = u64::from(self.config.allow_percentage.min(100));
+        if decision != Some(Decision::Allow) || percentage == 100 {
+            return true;
+        }
+        let n = self.allowed.fetch_add(1, Ordering::Relaxed);
+        (n * percentage / 100) != ((n + 1) * percentage / 100)
+    }
+
+    /// A failing sink does not fail the request; failures are counted instead.
+    pub(crate) fn record(
+        &self,
+        request: &Request,
+        response: &Response,
+        policies_fingerprint: &str,
+        entities_fingerprint: &str,
+        on_error: ErrorHandling,
+    ) {
+        if !self.is_sampled(Some(response.decision())) {
+            return;
+        }
+        let mut record = AuditRecord::new(
+            request,
+            response,
+            policies_fingerprint,
+            entities_fingerprint,
+            on_error,
+        );
+        self.redact(&mut record.context);
+        if self.sink.record(&record).is_err() {
+            self.failures.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Like [`Audit::record`], for a partially evaluated request.
+    pub(crate) fn record_partial(&self, mut decision: PartialDecision) {
+        if !self.is_sampled(decision.decision) {
+            return;
+        }
+        self.redact(&mut decision.context);
+        if self.sink.record_partial(&decision).is_err() {
+            self.failures.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn redact(&self, context: &mut Option<Value>) {
+        if let Some(context) = context {
+            for path in &self.config.redact {
+                redact(context, path);
+            }
+        }
+    }
+
+    pub(crate) fn expired(&self, expiry: &PolicyExpiry) {
+        if self.sink.record_expiry(expiry).is_err() {
+            self.failures.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn failures(&self) -> u64 {
+        self.failures.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{str::FromStr, sync::Arc};
+
+    use cedar_policy::{Entities, PolicySet, SchemaFragment};
+
+    use super::*;
+    use crate::{CEDAR_SCHEMA, CEDAR_SCHEMA_SRC, Engine, annotations::TagFilter, replay::replay};
+
+    #[derive(Debug, Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_audit_log() {
+        let engine = Engine::new(
+            SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(),
+            PolicySet::from_str(r#"permit (principal == MyApp::User::"0", action, resource);"#)
+                .unwrap(),
+            Entities::empty(),
+        )
+        .unwrap();
+        let log = Shared::default();
+        engine.set_audit_sink(JsonLinesSink::new(log.clone()));
+        for user in ["0", "1"] {
+            let request = Request::new(
+                EntityUid::from_str(&format!(r#"MyApp::User::"{user}""#)).unwrap(),
+                EntityUid::from_str(r#"MyApp::Action::"GetProjectMetadata""#).unwrap(),
+                EntityUid::from_str(r#"MyApp::Project::"0""#).unwrap(),
+                Context::empty(),
+                Some(&CEDAR_SCHEMA),
+            )
+            .unwrap();
+            engine.is_authorized(&request);
+        }
+        assert_eq!(engine.clear_audit_sink(), Some(0));
+
+        let log = log.0.lock().unwrap().clone();
+        let records = log
+            .split(|b| *b == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice::<AuditRecord>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].decision, Decision::Allow);
+        assert_eq!(records[0].reasons, ["policy0"]);
+        assert_eq!(records[1].decision, Decision::Deny);
+        assert_eq!(records[1].context, Some(serde_json::json!({})));
+        let fingerprint = engine.state().fingerprint().clone();
+        assert_eq!(records[1].policies_fingerprint, fingerprint.policies);
+
+        // The log replays without changes against the same state.
+        let report = replay(&engine, log.as_slice()).unwrap();
+        assert_eq!((report.replayed, report.changes.len()), (2, 0));
+    }
+
+    #[test]
+    fn test_partial_decisions() {
+        let engine = Engine::new(
+            SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(),
+            PolicySet::from_str(
+                r#"
+                @tags("experimental")
+                permit (principal == MyApp::User::"0", action, resource);
+                forbid (principal, action, resource == MyApp::Project::"1");
+                "#,
+            )
+            .unwrap(),
+            Entities::empty(),
+        )
+        .unwrap();
+        let log = Shared::default();
+        engine.set_audit_sink(JsonLinesSink::new(log.clone()));
+        let action = EntityUid::from_str(r#"MyApp::Action::"GetProjectMetadata""#).unwrap();
+        let user = PartialUid::unknown("MyApp::User".parse().unwrap());
+        let project = EntityUid::from_str(r#"MyApp::Project::"0""#).unwrap();
+        let call = || (user.clone(), action.clone(), project.clone().into());
+        let (principal, action, resource) = call();
+        engine
+            .tpe(principal, action, resource, Some(Context::empty()))
+            .unwrap();
+        let (principal, action, resource) = call();
+        let filter = TagFilter::default().exclude("experimental");
+        engine
+            .tpe_filtered(principal, action, resource, None, &filter)
+            .unwrap();
+
+        let log = log.0.lock().unwrap().clone();
+        let decisions = log
+            .split(|b| *b == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice::<PartialDecision>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(decisions.len(), 2);
+        assert_eq!(decisions[0].principal, user);
+        assert_eq!(decisions[0].decision, None);
+        assert_eq!(decisions[0].residuals, ["policy0"]);
+        let fingerprint = engine.state().fingerprint().clone();
+        assert_eq!(decisions[0].policies_fingerprint, fingerprint.policies);
+        // Without the experimental permit, nothing is allowed.
+        assert_eq!(decisions[1].decision, Some(Decision::Deny));
+        assert_ne!(decisions[1].policies_fingerprint, fingerprint.policies);
+
+        // Partial decisions are not replayed.
+        let report = replay(&engine, log.as_slice()).unwrap();
+        assert_eq!((report.replayed, report.errors.len()), (0, 0));
+    }
+
+    #[test]
+    fn test_sampling_and_redaction() {
+        let engine = Engine::new(
+            SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(),
+            PolicySet::from_str(r#"permit (principal == MyApp::User::"0", action, resource);"#)
+                .unwrap(),
+            Entities::empty(),
+        )
+        .unwrap();
+        let log = Shared::default();
+        engine.set_audit_sink_with(
+            JsonLinesSink::new(log.clone()),
+            AuditConfig {
+                allow_percentage: 50,
+                redact: vec!["user.email".to_string(), "missing.path".to_string()],
+            },
+        );
+        let context = serde_json::json!({ "user": { "email": "a@example.com", "name": "A" } });
+        for user in ["0", "0", "1", "0", "0", "1"] {
+            let request = Request::new(
+                EntityUid::from_str(&format!(r#"MyApp::User::"{user}""#)).unwrap(),
+                EntityUid::from_str(r#"MyApp::Action::"GetProjectMetadata""#).unwrap(),
+                EntityUid::from_str(r#"MyApp::Project::"0""#).unwrap(),
+                Context::from_json_value(context.clone(), None).unwrap(),
+                None,
+            )
+            .unwrap();
+            engine.is_authorized(&request);
+        }
+
+        let log = log.0.lock().unwrap().clone();
+        let records = log
+            .split(|b| *b == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice::<AuditRecord>(line).unwrap())
+            .collect::<Vec<_>>();
+        let decisions = records.iter().map(|r| r.decision).collect::<Vec<_>>();
+        assert_eq!(
+            decisions,
+            [
+                Decision::Allow,
+                Decision::Deny,
+                Decision::Allow,
+                Decision::Deny
+            ]
+        );
+        assert_eq!(
+            records[0].context,
+            Some(serde_json::json!({ "user": { "email": REDACTED, "name": "A" } }))
+        );
+    }
+
+    #[test]
+    fn test_fan_out() {
+        struct Failing;
+
+        impl AuditSink for Failing {
+            fn record(&self, _: &AuditRecord) -> io::Result<()> {
+                Err(io::Error::other("unavailable"))
+            }
+        }
+
+        let engine = Engine::new(
+            SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(),
+            PolicySet::new(),
+            Entities::empty(),
+        )
+        .unwrap();
+        let (first, second) = (Shared::default(), Shared::default());
+        engine.set_audit_sink(
+            FanOutSink::new()
+                .with(JsonLinesSink::new(first.clone()))
+                .with(Failing)
+                .with(JsonLinesSink::new(second.clone())),
+        );
+        let request = Request::new(
+            EntityUid::from_str(r#"MyApp::User::"0""#).unwrap(),
+            EntityUid::from_str(r#"MyApp::Action::"GetProjectMetadata""#).unwrap(),
+            EntityUid::from_str(r#"MyApp::Project::"0""#).unwrap(),
+            Context::empty(),
+            None,
+        )
+        .unwrap();
+        engine.is_authorized(&request);
+        assert_eq!(engine.clear_audit_sink(), Some(1));
+        let first = first.0.lock().unwrap().clone();
+        assert!(!first.is_empty());
+        assert_eq!(first, *second.0.lock().unwrap());
+    }
+}
@@ -0,0 +1,152 @@
+//! Decisions as OpenTelemetry span events, so authorization outcomes show up next to the
+//! request traces they belong to.
+
+use std::io;
+
+use opentelemetry::{
+    Array, Context, KeyValue, StringValue, Value,
+    trace::{Status, TraceContextExt},
+};
+
+use super::{AuditRecord, AuditSink, FanOutSink};
+
+/// The name of the span event recorded per decision.
+pub const EVENT: &str = "cedar.decision";
+
+pub const PRINCIPAL: &str = "cedar.principal";
+pub const ACTION: &str = "cedar.action";
+pub const RESOURCE: &str = "cedar.resource";
+/// `allow` or `deny`.
+pub const DECISION: &str = "cedar.decision";
+/// The determining policies.
+pub const REASONS: &str = "cedar.reasons";
+/// The policies that failed to evaluate.
+pub const ERRORS: &str = "cedar.errors";
+pub const POLICIES_FINGERPRINT: &str = "cedar.policies_fingerprint";
+pub const ENTITIES_FINGERPRINT: &str = "cedar.entities_fingerprint";
+
+/// Adds a [`EVENT`] event to the span active when the decision is made. Decisions made outside
+/// of a sampled span are not recorded, as they belong to no trace.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct OtelSink;
+
+impl OtelSink {
+    /// Record to `sink` and to the active span, so enabling tracing keeps the audit log.
+    pub fn wrap(sink: impl AuditSink) -> FanOutSink {
+        FanOutSink::new().with(sink).with(Self)
+    }
+}
+
+impl AuditSink for OtelSink {
+    fn record(&self, record: &AuditRecord) -> io::Result<()> {
+        let context = Context::current();
+        let span = context.span();
+        if !span.span_context().is_sampled() {
+            return Ok(());
+        }
+        let strings = |values: Vec<String>| {
+            Value::Array(Array::String(
+                values.into_iter().map(StringValue::from).collect(),
+            ))
+        };
+        let decision = serde_json::to_value(record.decision)?;
+        let attributes = vec![
+            KeyValue::new(PRINCIPAL, record.principal.clone()),
+            KeyValue::new(ACTION, record.action.clone()),
+            KeyValue::new(RESOURCE, record.resource.clone()),
+            KeyValue::new(DECISION, decision.as_str().unwrap_or_default().to_string()),
+            KeyValue::new(REASONS, strings(record.reasons.clone())),
+            KeyValue::new(
+                ERRORS,
+                strings(record.errors.iter().map(|e| e.policy.clone()).collect()),
+            ),
+            KeyValue::new(POLICIES_FINGERPRINT, record.policies_fingerprint.clone()),
+            KeyValue::new(ENTITIES_FINGERPRINT, record.entities_fingerprint.clone()),
+        ];
+        span.add_event_with_timestamp(EVENT, record.timestamp.into(), attributes);
+        if !record.errors.is_empty() {
+            span.set_status(Status::error("Policies failed to evaluate"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Write,
+        str::FromStr,
+        sync::{Arc, Mutex},
+    };
+
+    use cedar_policy::{Context as CedarContext, Entities, EntityUid, PolicySet, Request};
+    use opentelemetry::trace::{Tracer, TracerProvider};
+    use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider};
+
+    use super::*;
+    use crate::{CEDAR_SCHEMA_SRC, Engine, audit::JsonLinesSink};
+
+    #[test]
+    fn test_span_events() {
+        let exporter = InMemorySpanExporter::default();
+        let provider = SdkTracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        let engine = Engine::new(
+            CEDAR_SCHEMA_SRC.parse().unwrap(),
+            PolicySet::from_str("permit (principal, action, resource);").unwrap(),
+            Entities::empty(),
+        )
+        .unwrap();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        engine.set_audit_sink(OtelSink::wrap(JsonLinesSink::new(SharedLog(log.clone()))));
+        let request = Request::new(
+            EntityUid::from_str(r#"MyApp::User::"0""#).unwrap(),
+            EntityUid::from_str(r#"MyApp::Action::"GetProjectMetadata""#).unwrap(),
+            EntityUid::from_str(r#"MyApp::Project::"0""#).unwrap(),
+            CedarContext::empty(),
+            None,
+        )
+        .unwrap();
+        // Outside of a span, there is no trace to link the decision to.
+        engine.is_authorized(&request);
+        provider.tracer("test").in_span("request", |_| {
+            engine.is_authorized(&request);
+        });
+
+        let spans = exporter.get_finished_spans().unwrap();
+        let [span] = spans.as_slice() else {
+            panic!("expected one span");
+        };
+        let [event] = span.events.events.as_slice() else {
+            panic!("expected one event");
+        };
+        assert_eq!(event.name, EVENT);
+        let attribute = |key: &str| {
+            event
+                .attributes
+                .iter()
+                .find(|kv| kv.key.as_str() == key)
+                .map(|kv| kv.value.to_string())
+        };
+        assert_eq!(attribute(DECISION).as_deref(), Some("allow"));
+        assert_eq!(attribute(PRINCIPAL).as_deref(), Some(r#"MyApp::User::"0""#));
+        assert_eq!(attribute(REASONS).as_deref(), Some("[\"policy0\"]"));
+        assert_eq!(engine.clear_audit_sink(), Some(0));
+        // The wrapped sink records decisions with and without a span.
+        let lines = log.lock().unwrap().iter().filter(|b| **b == b'\n').count();
+        assert_eq!(lines, 2);
+    }
+
+    struct SharedLog(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedLog {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+}
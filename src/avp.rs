@@ -0,0 +1,436 @@
+//! Periodically pulls policies and the schema from an Amazon Verified Permissions (AVP) policy
+//! store and installs them into an [`Engine`], keeping AVP as the source of truth while
+//! evaluating locally.
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+
+use cedar_policy::{EntityUid, Policy, PolicyId, PolicySet, SchemaFragment, SlotId, Template};
+use chrono::{DateTime, Utc};
+use tokio::task::JoinHandle;
+
+use crate::{engine::Engine, error::Result};
+
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AvpPolicy {
+    Static {
+        id: String,
+        statement: String,
+    },
+    TemplateLinked {
+        id: String,
+        template_id: String,
+        /// The entities of the `?principal` and `?resource` slots the template has.
+        slots: HashMap<SlotId, EntityUid>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AvpTemplate {
+    pub id: String,
+    pub statement: String,
+}
+
+/// Contents of an AVP policy store. The schema is in Cedar's JSON schema format, as returned
+/// by `GetSchema`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AvpSnapshot {
+    pub schema: String,
+    pub templates: Vec<AvpTemplate>,
+    pub policies: Vec<AvpPolicy>,
+}
+
+impl AvpSnapshot {
+    pub fn schema(&self) -> Result<SchemaFragment> {
+        Ok(SchemaFragment::from_json_str(&self.schema)?)
+    }
+
+    pub fn policy_set(&self) -> Result<PolicySet> {
+        let mut policies = PolicySet::new();
+        for template in &self.templates {
+            let id = PolicyId::new(&template.id);
+            policies.add_template(Template::parse(Some(id), &template.statement)?)?;
+        }
+        for policy in &self.policies {
+            match policy {
+                AvpPolicy::Static { id, statement } => {
+                    policies.add(Policy::parse(Some(PolicyId::new(id)), statement)?)?;
+                }
+                AvpPolicy::TemplateLinked {
+                    id,
+                    template_id,
+                    slots,
+                } => {
+                    policies.link(PolicyId::new(template_id), PolicyId::new(id), slots.clone())?;
+                }
+            }
+        }
+        Ok(policies)
+    }
+}
+
+/// Fetches the full contents of a policy store.
+pub trait AvpPolicySource: Send + Sync + 'static {
+    fn fetch(&self) -> impl Future<Output = Result<AvpSnapshot>> + Send;
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AvpSyncStatus {
+    pub last_success: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+pub struct AvpSync<S> {
+    source: S,
+    engine: Arc<Engine>,
+    interval: Duration,
+    applied: Mutex<Option<AvpSnapshot>>,
+}
+
+impl<S: AvpPolicySource> AvpSync<S> {
+    pub fn new(source: S, engine: Arc<Engine>) -> Self {
+        Self {
+            source,
+            engine,
+            interval: DEFAULT_INTERVAL,
+            applied: Mutex::new(None),
+        }
+    }
+
+    #[must_use]
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Pull the policy store once and install it if it changed since the last successful pull.
+    /// Returns whether the engine state was replaced. Invalid snapshots are rejected and the
+    /// engine keeps its current policies.
+    pub async fn sync_once(&self) -> Result<bool> {
+        let snapshot = self.source.fetch().await?;
+        if self
+            .applied
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_ref()
+            .is_some_and(|applied| *applied == snapshot)
+        {
+            return Ok(false);
+        }
+
+        self.engine
+            .replace_policies(snapshot.schema()?, snapshot.policy_set()?)?;
+        *self.applied.lock().unwrap_or_else(PoisonError::into_inner) = Some(snapshot);
+        Ok(true)
+    }
+
+    /// Run [`AvpSync::sync_once`] every interval on the tokio runtime until the returned handle
+    /// is dropped.
+    pub fn spawn(self) -> AvpSyncHandle {
+        let status = Arc::new(Mutex::new(AvpSyncStatus::default()));
+        let task_status = status.clone();
+        let task = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.interval);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                let result = self.sync_once().await;
+                let mut status = task_status.lock().unwrap_or_else(PoisonError::into_inner);
+                match result {
+                    Ok(_) => {
+                        status.last_success = Some(Utc::now());
+                        status.last_error = None;
+                    }
+                    Err(e) => status.last_error = Some(e.to_string()),
+                }
+            }
+        });
+        AvpSyncHandle { status, task }
+    }
+}
+
+pub struct AvpSyncHandle {
+    status: Arc<Mutex<AvpSyncStatus>>,
+    task: JoinHandle<()>,
+}
+
+impl AvpSyncHandle {
+    pub fn status(&self) -> AvpSyncStatus {
+        self.status
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
+
+impl Drop for AvpSyncHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[cfg(feature = "avp")]
+pub use sdk::SdkPolicySource;
+
+#[cfg(feature = "avp")]
+mod sdk {
+    use std::{collections::HashMap, str::FromStr};
+
+    use aws_sdk_verifiedpermissions::{
+        Client,
+        types::{EntityIdentifier, PolicyDefinitionDetail},
+    };
+    use cedar_policy::{EntityId, EntityTypeName, EntityUid, SlotId};
+
+    use super::{AvpPolicy, AvpPolicySource, AvpSnapshot, AvpTemplate};
+    use crate::error::{Error, Result};
+
+    /// [`AvpPolicySource`] backed by the AWS SDK.
+    #[derive(Debug, Clone)]
+    pub struct SdkPolicySource {
+        client: Client,
+        policy_store_id: String,
+    }
+
+    impl SdkPolicySource {
+        pub fn new(client: Client, policy_store_id: impl Into<String>) -> Self {
+            Self {
+                client,
+                policy_store_id: policy_store_id.into(),
+            }
+        }
+
+        /// Create a source using credentials and region from the environment.
+        pub async fn from_env(policy_store_id: impl Into<String>) -> Self {
+            let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+            Self::new(Client::new(&config), policy_store_id)
+        }
+
+        async fn templates(&self) -> Result<Vec<AvpTemplate>> {
+            let mut items = self
+                .client
+                .list_policy_templates()
+                .policy_store_id(&self.policy_store_id)
+                .into_paginator()
+                .items()
+                .send();
+            let mut templates = Vec::new();
+            while let Some(item) = items.next().await {
+                let item = item.map_err(avp_error)?;
+                let template = self
+                    .client
+                    .get_policy_template()
+                    .policy_store_id(&self.policy_store_id)
+                    .policy_template_id(item.policy_template_id())
+                    .send()
+                    .await
+                    .map_err(avp_error)?;
+                templates.push(AvpTemplate {
+                    id: template.policy_template_id().to_string(),
+                    statement: template.statement().to_string(),
+                });
+            }
+            Ok(templates)
+        }
+
+        async fn policies(&self) -> Result<Vec<AvpPolicy>> {
+            let mut items = self
+                .client
+                .list_policies()
+                .policy_store_id(&self.policy_store_id)
+                .into_paginator()
+                .items()
+                .send();
+            let mut policies = Vec::new();
+            while let Some(item) = items.next().await {
+                let item = item.map_err(avp_error)?;
+                let policy = self
+                    .client
+                    .get_policy()
+                    .policy_store_id(&self.policy_store_id)
+                    .policy_id(item.policy_id())
+                    .send()
+                    .await
+                    .map_err(avp_error)?;
+                let id = policy.policy_id().to_string();
+                match policy.definition() {
+                    Some(PolicyDefinitionDetail::Static(definition)) => {
+                        policies.push(AvpPolicy::Static {
+                            id,
+                            statement: definition.statement().to_string(),
+                        });
+                    }
+                    Some(PolicyDefinitionDetail::TemplateLinked(definition)) => {
+                        let mut slots = HashMap::new();
+                        if let Some(principal) = definition.principal() {
+                            slots.insert(SlotId::principal(), entity_uid(principal)?);
+                        }
+                        if let Some(resource) = definition.resource() {
+                            slots.insert(SlotId::resource(), entity_uid(resource)?);
+                        }
+                        policies.push(AvpPolicy::TemplateLinked {
+                            id,
+                            template_id: definition.policy_template_id().to_string(),
+                            slots,
+                        });
+                    }
+                    _ => {
+                        return Err(avp_error(format!(
+                            "Policy {id} has an unsupported definition type"
+                        )));
+                    }
+                }
+            }
+            Ok(policies)
+        }
+    }
+
+    impl AvpPolicySource for SdkPolicySource {
+        async fn fetch(&self) -> Result<AvpSnapshot> {
+            let schema = self
+                .client
+                .get_schema()
+                .policy_store_id(&self.policy_store_id)
+                .send()
+                .await
+                .map_err(avp_error)?;
+            Ok(AvpSnapshot {
+                schema: schema.schema().to_string(),
+                templates: self.templates().await?,
+                policies: self.policies().await?,
+            })
+        }
+    }
+
+    fn entity_uid(identifier: &EntityIdentifier) -> Result<EntityUid> {
+        let type_name = EntityTypeName::from_str(identifier.entity_type()).map_err(avp_error)?;
+        Ok(EntityUid::from_type_name_and_id(
+            type_name,
+            EntityId::new(identifier.entity_id()),
+        ))
+    }
+
+    fn avp_error(e: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Error {
+        Error::Avp(e.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use cedar_policy::{Decision, Entities, Request};
+
+    use super::*;
+    use crate::CEDAR_SCHEMA_SRC;
+
+    struct StaticSource(Mutex<AvpSnapshot>);
+
+    impl AvpPolicySource for StaticSource {
+        async fn fetch(&self) -> Result<AvpSnapshot> {
+            Ok(self.0.lock().unwrap().clone())
+        }
+    }
+
+    fn snapshot(policies: Vec<AvpPolicy>) -> AvpSnapshot {
+        AvpSnapshot {
+            schema: SchemaFragment::from_str(CEDAR_SCHEMA_SRC)
+                .unwrap()
+                .to_json_string()
+                .unwrap(),
+            templates: vec![AvpTemplate {
+                id: "project-reader".to_string(),
+                statement: r#"permit (principal == ?principal, action == MyApp::Action::"GetProjectMetadata", resource == ?resource);"#
+                    .to_string(),
+            }],
+            policies,
+        }
+    }
+
+    fn request(principal: &str) -> Request {
+        Request::builder()
+            .principal(EntityUid::from_str(principal).unwrap())
+            .action(EntityUid::from_str("MyApp::Action::\"GetProjectMetadata\"").unwrap())
+            .resource(EntityUid::from_str("MyApp::Project::\"0\"").unwrap())
+            .build()
+    }
+
+    #[tokio::test]
+    async fn test_sync_once() {
+        let engine = Arc::new(
+            Engine::new(
+                SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(),
+                PolicySet::new(),
+                Entities::empty(),
+            )
+            .unwrap(),
+        );
+        let source = StaticSource(Mutex::new(snapshot(vec![
+            AvpPolicy::Static {
+                id: "user-0".to_string(),
+                statement: r#"permit (principal == MyApp::User::"0", action, resource);"#
+                    .to_string(),
+            },
+            AvpPolicy::TemplateLinked {
+                id: "user-1-project-0".to_string(),
+                template_id: "project-reader".to_string(),
+                slots: HashMap::from([
+                    (
+                        SlotId::principal(),
+                        EntityUid::from_str("MyApp::User::\"1\"").unwrap(),
+                    ),
+                    (
+                        SlotId::resource(),
+                        EntityUid::from_str("MyApp::Project::\"0\"").unwrap(),
+                    ),
+                ]),
+            },
+        ])));
+        let sync = AvpSync::new(source, engine.clone());
+
+        assert!(sync.sync_once().await.unwrap());
+        assert!(!sync.sync_once().await.unwrap());
+        for principal in ["MyApp::User::\"0\"", "MyApp::User::\"1\""] {
+            let response = engine.is_authorized(&request(principal));
+            assert_eq!(response.decision(), Decision::Allow);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sync_rejects_invalid_snapshot() {
+        let engine = Arc::new(
+            Engine::new(
+                SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(),
+                PolicySet::new(),
+                Entities::empty(),
+            )
+            .unwrap(),
+        );
+        let valid = AvpPolicy::Static {
+            id: "user-0".to_string(),
+            statement: r#"permit (principal == MyApp::User::"0", action, resource);"#.to_string(),
+        };
+        let sync = AvpSync::new(
+            StaticSource(Mutex::new(snapshot(vec![valid.clone()]))),
+            engine.clone(),
+        );
+        sync.sync_once().await.unwrap();
+
+        *sync.source.0.lock().unwrap() = snapshot(vec![
+            valid,
+            AvpPolicy::Static {
+                id: "unknown-type".to_string(),
+                statement: r#"permit (principal == MyApp::Group::"0", action, resource);"#
+                    .to_string(),
+            },
+        ]);
+        assert!(sync.sync_once().await.is_err());
+        assert_eq!(engine.state().policies().num_of_policies(), 1);
+    }
+}
@@ -0,0 +1,144 @@
+//! `cedar-tpe-server`: the HTTP API of [`cedar_test::server`] as a standalone policy decision
+//! point.
+//!
+//! Policies are read from and written to the configured store: a directory of `.cedar` files,
+//! which is read-only, a Postgres database, or memory if neither is given. Entities are loaded
+//! from a file at startup and managed through `/v1/data`. The engine is warmed up before the
+//! listener is bound, so `/readyz` succeeds from the start.
+
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::Context as _;
+use axum::Router;
+use cedar_policy::{Entities, PolicySet, Schema, SchemaFragment};
+use cedar_test::{
+    Engine,
+    server::store_router,
+    store::{DirectoryPolicyStore, MemoryPolicyStore, PolicyStore, load_policies},
+};
+use clap::Parser;
+
+#[derive(Debug, Parser)]
+#[command(name = "cedar-tpe-server", about, version)]
+struct Args {
+    #[arg(long, default_value = "127.0.0.1:8180")]
+    listen: SocketAddr,
+    /// Schema in Cedar syntax, or in JSON if the file ends in `.json`.
+    #[arg(long)]
+    schema: PathBuf,
+    /// Entities in Cedar's JSON format.
+    #[arg(long)]
+    entities: Option<PathBuf>,
+    /// A directory with policies in `.cedar` files.
+    #[arg(long)]
+    policies: Option<PathBuf>,
+    /// A Postgres connection URL, e.g. `postgres://localhost/policies`.
+    #[cfg(feature = "postgres")]
+    #[arg(long, conflicts_with = "policies")]
+    postgres: Option<String>,
+    /// The size of the Postgres connection pool.
+    #[cfg(feature = "postgres")]
+    #[arg(long, default_value_t = 10, requires = "postgres")]
+    max_connections: u32,
+}
+
+impl Args {
+    async fn router(&self) -> anyhow::Result<Router> {
+        let src = read(&self.schema)?;
+        let fragment = if self.schema.extension().is_some_and(|ext| ext == "json") {
+            SchemaFragment::from_json_str(&src)?
+        } else {
+            SchemaFragment::from_cedarschema_str(&src)?.0
+        };
+        let schema: Schema = fragment.clone().try_into()?;
+        let entities = match &self.entities {
+            Some(path) => Entities::from_json_str(&read(path)?, Some(&schema))?,
+            None => Entities::empty(),
+        };
+        let engine = Arc::new(Engine::new(fragment, PolicySet::new(), entities)?);
+
+        #[cfg(feature = "postgres")]
+        if let Some(url) = &self.postgres {
+            let config = cedar_test::store::StoreConfig {
+                pool: cedar_test::store::PoolConfig {
+                    max_connections: self.max_connections,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let store =
+                cedar_test::store::PostgresPolicyStore::connect_url(url, schema, &config.pool)
+                    .await?;
+            return serve_store(engine, config.wrap(store)).await;
+        }
+        match &self.policies {
+            Some(path) => serve_store(engine, DirectoryPolicyStore::open(path, schema)?).await,
+            None => serve_store(engine, MemoryPolicyStore::new(schema)).await,
+        }
+    }
+}
+
+/// Load and warm up the policies of `store` and keep the engine in sync with changes made elsewhere.
+async fn serve_store<S: PolicyStore>(engine: Arc<Engine>, store: S) -> anyhow::Result<Router> {
+    let store = Arc::new(store);
+    load_policies(&store, &engine).await?;
+    engine.warm_up();
+    let (task_store, task_engine) = (store.clone(), engine.clone());
+    tokio::spawn(async move {
+        let mut revision = task_store.watch();
+        while revision.changed().await.is_ok() {
+            if let Err(e) = load_policies(&task_store, &task_engine).await {
+                eprintln!("failed to reload policies: {e}");
+            }
+        }
+    });
+    Ok(store_router(engine, store))
+}
+
+fn read(path: &Path) -> anyhow::Result<String> {
+    std::fs::read_to_string(path).with_context(|| format!("reading `{}`", path.display()))
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let app = args.router().await?;
+    let listener = tokio::net::TcpListener::bind(args.listen).await?;
+    println!("listening on http://{}", listener.local_addr()?);
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_router() {
+        let schema = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/src/resources/example.cedarschema"
+        );
+        let args = Args::try_parse_from(["cedar-tpe-server", "--schema", schema]).unwrap();
+        let app = args.router().await.unwrap();
+        let response = app
+            .oneshot(
+                Request::post("/v1/policies")
+                    .header("content-type", "application/json")
+                    .body(Body::from(
+                        r#"{"id": "all", "content": "permit (principal, action, resource);"}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+    }
+}
@@ -0,0 +1,36 @@
+//! Latency percentiles of the engine's APIs on a workload generated from a schema.
+
+use std::path::PathBuf;
+
+use cedar_test::testing::{BenchConfig, benchmark};
+use clap::Args;
+
+use crate::{load_schema, read_file};
+
+#[derive(Debug, Args)]
+pub(crate) struct BenchArgs {
+    /// Schema in Cedar syntax, or in JSON if the file ends in `.json`.
+    #[arg(long)]
+    schema: PathBuf,
+    /// The workload as JSON, e.g. `{ "entities_per_type": 100, "unknown_ratio": 0.9 }`.
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// Print the report as JSON.
+    #[arg(long)]
+    json: bool,
+}
+
+impl BenchArgs {
+    pub(crate) fn run(&self) -> anyhow::Result<String> {
+        let schema = load_schema(&self.schema)?;
+        let config = match &self.config {
+            Some(path) => serde_json::from_str(&read_file(path)?)?,
+            None => BenchConfig::default(),
+        };
+        let report = benchmark(&schema, &config)?;
+        if self.json {
+            return Ok(serde_json::to_string_pretty(&report)? + "\n");
+        }
+        Ok(report.to_string())
+    }
+}
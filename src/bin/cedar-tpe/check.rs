@@ -0,0 +1,242 @@
+//! Validation of a schema, policies and entities together, for pre-commit hooks and CI.
+
+use std::{fmt::Write as _, path::PathBuf};
+
+use cedar_policy::{Entities, Schema};
+use cedar_test::{
+    EngineState, Error,
+    analysis::{Finding, PolicyConflict, conflicts, findings},
+};
+use clap::{Args, ValueEnum};
+use serde::Serialize;
+
+use crate::{load_policies, load_schema, read_file};
+
+#[derive(Debug, Args)]
+pub(crate) struct CheckArgs {
+    /// Schema in Cedar syntax, or in JSON if the file ends in `.json`.
+    #[arg(long)]
+    schema: PathBuf,
+    /// Policies in Cedar syntax, in a file or in the `.cedar` files below a directory.
+    #[arg(long)]
+    policies: PathBuf,
+    /// Entities in Cedar's JSON format.
+    #[arg(long)]
+    entities: Option<PathBuf>,
+    /// Fail on lint findings and conflicts, too.
+    #[arg(long)]
+    deny_warnings: bool,
+    /// Check again whenever one of the files changes.
+    #[arg(long)]
+    pub(crate) watch: bool,
+    #[arg(long, value_enum, default_value_t = Format::Human)]
+    format: Format,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    Human,
+    Json,
+}
+
+/// A schema, policy or entity error.
+#[derive(Debug, Serialize)]
+struct Problem {
+    input: &'static str,
+    file: Option<PathBuf>,
+    policy: Option<String>,
+    message: String,
+    help: Option<String>,
+    span: Option<(usize, usize)>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct CheckReport {
+    errors: Vec<Problem>,
+    findings: Vec<Finding>,
+    conflicts: Vec<PolicyConflict>,
+}
+
+impl CheckArgs {
+    /// The rendered report, and whether the check passed.
+    pub(crate) fn run(&self) -> anyhow::Result<(String, bool)> {
+        let report = self.check();
+        let warnings = !report.findings.is_empty() || !report.conflicts.is_empty();
+        let passed = report.errors.is_empty() && !(self.deny_warnings && warnings);
+        let text = match self.format {
+            Format::Human => report.human(),
+            Format::Json => serde_json::to_string_pretty(&report)? + "\n",
+        };
+        Ok((text, passed))
+    }
+
+    /// The files and directories the check reads.
+    pub(crate) fn paths(&self) -> Vec<PathBuf> {
+        [&self.schema, &self.policies]
+            .into_iter()
+            .chain(&self.entities)
+            .cloned()
+            .collect()
+    }
+
+    fn check(&self) -> CheckReport {
+        let mut report = CheckReport::default();
+        let (fragment, schema) = match load_schema(&self.schema).and_then(|fragment| {
+            let schema: Schema = fragment.clone().try_into()?;
+            Ok((fragment, schema))
+        }) {
+            Ok(schema) => schema,
+            Err(e) => {
+                report.problems("schema", e);
+                return report;
+            }
+        };
+        if let Some(path) = &self.entities {
+            let entities = read_file(path).and_then(|src| {
+                Entities::from_json_str(&src, Some(&schema)).map_err(|e| Error::File {
+                    path: path.clone(),
+                    source: Box::new(e.into()),
+                })
+            });
+            if let Err(e) = entities {
+                report.problems("entities", e);
+            }
+        }
+        let lints = load_policies(&self.policies, &schema).and_then(|policies| {
+            // Validates the policies in strict mode.
+            EngineState::new(fragment, policies.clone(), Entities::empty())?;
+            Ok((
+                findings(&schema, &policies)?,
+                conflicts(&schema, &policies)?,
+            ))
+        });
+        match lints {
+            Ok((findings, conflicts)) => {
+                report.findings = findings;
+                report.conflicts = conflicts;
+            }
+            Err(e) => report.problems("policies", e),
+        }
+        report
+    }
+}
+
+impl CheckReport {
+    fn problems(&mut self, input: &'static str, error: Error) {
+        self.problems_in(input, None, error);
+    }
+
+    fn problems_in(&mut self, input: &'static str, file: Option<PathBuf>, error: Error) {
+        match error {
+            Error::File { path, source } => self.problems_in(input, Some(path), *source),
+            Error::Validation(diagnostics) => {
+                self.errors
+                    .extend(diagnostics.into_iter().map(|diagnostic| Problem {
+                        input,
+                        file: file.clone(),
+                        policy: Some(diagnostic.policy_id),
+                        message: diagnostic.message,
+                        help: diagnostic.help,
+                        span: diagnostic.span,
+                    }));
+            }
+            error => self.errors.push(Problem {
+                input,
+                file,
+                policy: None,
+                message: error.to_string(),
+                help: None,
+                span: None,
+            }),
+        }
+    }
+
+    fn human(&self) -> String {
+        let mut text = String::new();
+        for problem in &self.errors {
+            let mut location = problem.input.to_string();
+            if let Some(file) = &problem.file {
+                let _ = write!(location, " in `{}`", file.display());
+            }
+            if let Some(policy) = &problem.policy {
+                let _ = write!(location, ", policy `{policy}`");
+            }
+            let _ = writeln!(text, "error: {location}: {}", problem.message);
+            if let Some(help) = &problem.help {
+                let _ = writeln!(text, "  help: {help}");
+            }
+        }
+        for finding in &self.findings {
+            let message = match finding {
+                Finding::Unsatisfiable { policy } => format!("`{policy}` never applies"),
+                Finding::Subsumed { policy, by } => format!("`{policy}` is subsumed by `{by}`"),
+                Finding::Overridden { policy, by } => {
+                    format!("`{policy}` is always overridden by `{by}`")
+                }
+            };
+            let _ = writeln!(text, "warning: {message}");
+        }
+        for conflict in &self.conflicts {
+            let _ = writeln!(
+                text,
+                "warning: `{}` may override `{}`",
+                conflict.forbid, conflict.permit
+            );
+        }
+        let _ = writeln!(
+            text,
+            "{} errors, {} warnings",
+            self.errors.len(),
+            self.findings.len() + self.conflicts.len()
+        );
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let dir = std::env::temp_dir().join(format!("cedar-tpe-check-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("policies/team")).unwrap();
+        std::fs::write(
+            dir.join("policies/team/admins.cedar"),
+            r#"
+permit (principal in MyApp::Role::"admin", action, resource);
+forbid (principal in MyApp::Role::"admin", action, resource);
+"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("entities.json"),
+            r#"[{"uid": {"type": "MyApp::Role", "id": "admin"}, "attrs": {}, "parents": []}]"#,
+        )
+        .unwrap();
+        let mut args = CheckArgs {
+            schema: "src/resources/example.cedarschema".into(),
+            policies: dir.join("policies"),
+            entities: Some(dir.join("entities.json")),
+            deny_warnings: false,
+            watch: false,
+            format: Format::Json,
+        };
+        let (text, passed) = args.run().unwrap();
+        let report = serde_json::from_str::<serde_json::Value>(&text).unwrap();
+        assert!(!passed);
+        assert_eq!(report["errors"].as_array().unwrap().len(), 1);
+        assert_eq!(report["errors"][0]["input"], "entities");
+        assert_eq!(report["findings"][0]["kind"], "overridden");
+        assert_eq!(report["conflicts"][0]["permit"], "team/admins#0");
+
+        args.entities = None;
+        assert!(args.run().unwrap().1);
+        args.deny_warnings = true;
+        args.format = Format::Human;
+        let (text, passed) = args.run().unwrap();
+        assert!(!passed);
+        assert!(text.ends_with("0 errors, 2 warnings\n"), "{text}");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
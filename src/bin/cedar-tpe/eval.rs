@@ -0,0 +1,200 @@
+//! Evaluation of a single concrete request.
+
+use std::{fmt::Write as _, path::PathBuf, str::FromStr};
+
+use anyhow::Context as _;
+use cedar_policy::{Authorizer, Context, Decision, EntityUid, Request};
+use cedar_test::EngineState;
+use clap::{Args, ValueEnum};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{StateArgs, read_file, resolve_action};
+
+#[derive(Debug, Args)]
+pub(crate) struct EvalArgs {
+    #[command(flatten)]
+    pub(crate) state: StateArgs,
+    #[arg(long, required_unless_present = "request")]
+    principal: Option<String>,
+    /// An action UID, or the ID of an action.
+    #[arg(long, required_unless_present = "request")]
+    action: Option<String>,
+    #[arg(long, required_unless_present = "request")]
+    resource: Option<String>,
+    /// The context as JSON.
+    #[arg(long)]
+    context: Option<String>,
+    /// A JSON file with `principal`, `action`, `resource` and optionally `context`, instead of
+    /// the flags.
+    #[arg(long, conflicts_with_all = ["principal", "action", "resource", "context"])]
+    request: Option<PathBuf>,
+    #[arg(long, value_enum, default_value_t = Format::Human)]
+    format: Format,
+    /// Evaluate again whenever one of the files changes.
+    #[arg(long)]
+    pub(crate) watch: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    Human,
+    Json,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RequestFile {
+    pub(crate) principal: String,
+    pub(crate) action: String,
+    pub(crate) resource: String,
+    #[serde(default)]
+    pub(crate) context: Option<Value>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct Evaluation {
+    decision: Decision,
+    determining_policies: Vec<String>,
+    errors: Vec<String>,
+}
+
+impl EvalArgs {
+    fn request_file(&self) -> anyhow::Result<RequestFile> {
+        if let Some(path) = &self.request {
+            return serde_json::from_str(&read_file(path)?)
+                .with_context(|| format!("Invalid request in `{}`", path.display()));
+        }
+        let flag = |value: &Option<String>| value.clone().unwrap_or_default();
+        Ok(RequestFile {
+            principal: flag(&self.principal),
+            action: flag(&self.action),
+            resource: flag(&self.resource),
+            context: self
+                .context
+                .as_deref()
+                .map(serde_json::from_str)
+                .transpose()
+                .context("Invalid context")?,
+        })
+    }
+
+    /// The files and directories the evaluation reads.
+    pub(crate) fn paths(&self) -> Vec<PathBuf> {
+        let mut paths = self.state.paths();
+        paths.extend(self.request.clone());
+        paths
+    }
+
+    /// The rendered decision, determining policies and errors.
+    pub(crate) fn run(&self, state: &EngineState) -> anyhow::Result<String> {
+        let evaluation = evaluate(state, self.request_file()?)?;
+        Ok(match self.format {
+            Format::Human => evaluation.human(),
+            Format::Json => serde_json::to_string_pretty(&evaluation)? + "\n",
+        })
+    }
+}
+
+pub(crate) fn evaluate(state: &EngineState, request: RequestFile) -> anyhow::Result<Evaluation> {
+    let uid =
+        |uid: &str| EntityUid::from_str(uid).with_context(|| format!("Invalid entity UID `{uid}`"));
+    let action = resolve_action(state.schema(), &request.action).map_err(anyhow::Error::msg)?;
+    let context = match request.context {
+        Some(context) => Context::from_json_value(context, Some((state.schema(), &action)))?,
+        None => Context::empty(),
+    };
+    let request = Request::new(
+        uid(&request.principal)?,
+        action,
+        uid(&request.resource)?,
+        context,
+        Some(state.schema()),
+    )?;
+    let response = Authorizer::new().is_authorized(&request, state.policies(), state.entities());
+    let mut determining_policies = response
+        .diagnostics()
+        .reason()
+        .map(|id| AsRef::<str>::as_ref(id).to_string())
+        .collect::<Vec<_>>();
+    determining_policies.sort();
+    Ok(Evaluation {
+        decision: response.decision(),
+        determining_policies,
+        errors: response
+            .diagnostics()
+            .errors()
+            .map(ToString::to_string)
+            .collect(),
+    })
+}
+
+impl Evaluation {
+    fn human(&self) -> String {
+        let decision = match self.decision {
+            Decision::Allow => "allow",
+            Decision::Deny => "deny",
+        };
+        let determining = if self.determining_policies.is_empty() {
+            "none".to_string()
+        } else {
+            self.determining_policies.join(", ")
+        };
+        let mut text = format!("decision: {decision}\ndetermining policies: {determining}\n");
+        for error in &self.errors {
+            let _ = writeln!(text, "error: {error}");
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cedar_policy::{Entities, PolicySet, SchemaFragment};
+
+    use super::*;
+
+    #[test]
+    fn test_evaluate() {
+        let state = EngineState::new(
+            SchemaFragment::from_cedarschema_str(include_str!(
+                "../../resources/example.cedarschema"
+            ))
+            .unwrap()
+            .0,
+            PolicySet::from_str(
+                r#"
+permit (principal == MyApp::User::"0", action, resource);
+forbid (principal, action, resource) when { 9223372036854775807 + 1 > 0 };
+"#,
+            )
+            .unwrap(),
+            Entities::empty(),
+        )
+        .unwrap();
+        let evaluation = evaluate(
+            &state,
+            RequestFile {
+                principal: r#"MyApp::User::"0""#.to_string(),
+                action: "GetProjectMetadata".to_string(),
+                resource: r#"MyApp::Project::"0""#.to_string(),
+                context: None,
+            },
+        )
+        .unwrap();
+        let error = "error while evaluating policy `policy1`: integer overflow while attempting \
+                     to add the values `9223372036854775807` and `1`";
+        assert_eq!(
+            evaluation.human(),
+            format!("decision: allow\ndetermining policies: policy0\nerror: {error}\n")
+        );
+        assert_eq!(
+            serde_json::to_value(&evaluation).unwrap(),
+            serde_json::json!({
+                "decision": "allow",
+                "determining_policies": ["policy0"],
+                "errors": [error],
+            })
+        );
+    }
+}
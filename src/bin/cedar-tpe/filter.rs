@@ -0,0 +1,157 @@
+//! Residuals of a partial request, compiled for a filter backend.
+
+use anyhow::bail;
+use cedar_policy::Context;
+use cedar_test::{
+    Engine,
+    pdp::PartialUid,
+    residuals::filter::{FilterTable, Variable},
+};
+use clap::{Args, ValueEnum};
+
+use crate::{StateArgs, partial_uid, resolve_action};
+
+#[derive(Debug, Args)]
+pub(crate) struct FilterArgs {
+    #[command(flatten)]
+    pub(crate) state: StateArgs,
+    /// A UID, or a type such as `MyApp::User` for an unknown ID.
+    #[arg(long)]
+    principal: String,
+    /// An action UID, or the ID of an action.
+    #[arg(long)]
+    action: String,
+    /// A UID, or a type such as `MyApp::Project` for an unknown ID.
+    #[arg(long)]
+    resource: String,
+    /// The context as JSON. Unknown if omitted.
+    #[arg(long)]
+    context: Option<String>,
+    #[arg(long, value_enum, default_value_t = Target::Est)]
+    target: Target,
+    /// The field of the unknown's entity ID in Postgres or MongoDB.
+    #[arg(long, default_value = "id")]
+    id_field: String,
+    /// The array field of the unknown's ancestor UIDs in Postgres or MongoDB.
+    #[arg(long, default_value = "ancestors")]
+    ancestors_field: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Target {
+    /// A condition for a `WHERE` clause.
+    Postgres,
+    /// A MongoDB query document.
+    Mongo,
+    /// Cedar's JSON policy format.
+    Est,
+}
+
+impl FilterArgs {
+    pub(crate) fn run(&self, engine: &Engine) -> anyhow::Result<String> {
+        let state = engine.state();
+        let action = resolve_action(state.schema(), &self.action).map_err(anyhow::Error::msg)?;
+        let context = match &self.context {
+            Some(context) => Some(Context::from_json_value(
+                serde_json::from_str(context)?,
+                Some((state.schema(), &action)),
+            )?),
+            None => None,
+        };
+        let principal = partial_uid(&self.principal).map_err(anyhow::Error::msg)?;
+        let resource = partial_uid(&self.resource).map_err(anyhow::Error::msg)?;
+        let table = self.table(&principal, &resource);
+        let residuals = engine.tpe(principal, action, resource, context)?;
+        match self.target {
+            Target::Postgres => Ok(table?.postgres(&residuals)? + "\n"),
+            Target::Mongo => Ok(serde_json::to_string_pretty(&table?.mongo(&residuals)?)? + "\n"),
+            Target::Est => Ok(serde_json::to_string_pretty(&residuals.est())? + "\n"),
+        }
+    }
+
+    /// The table of the unknown, which is the principal or the resource without an ID.
+    fn table(&self, principal: &PartialUid, resource: &PartialUid) -> anyhow::Result<FilterTable> {
+        let (variable, unknown) = match (principal.id.is_none(), resource.id.is_none()) {
+            (true, false) => (Variable::Principal, principal),
+            (false, true) => (Variable::Resource, resource),
+            _ => bail!("Either the principal or the resource must have an unknown ID"),
+        };
+        Ok(FilterTable {
+            id: self.id_field.clone(),
+            ancestors: self.ancestors_field.clone(),
+            ..FilterTable::new(variable, unknown.entity_type.clone())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, str::FromStr};
+
+    use cedar_policy::{Entities, PolicySet, SchemaFragment};
+    use serde_json::{Value, json};
+
+    use super::*;
+
+    #[test]
+    fn test_filter() {
+        let engine = Engine::new(
+            SchemaFragment::from_cedarschema_str(include_str!(
+                "../../resources/example.cedarschema"
+            ))
+            .unwrap()
+            .0,
+            PolicySet::from_str(
+                r#"
+permit (principal, action, resource in MyApp::Server::"0");
+permit (principal, action, resource is MyApp::Server);
+"#,
+            )
+            .unwrap(),
+            Entities::empty(),
+        )
+        .unwrap();
+        let mut args = FilterArgs {
+            state: StateArgs {
+                schema: PathBuf::new(),
+                policies: PathBuf::new(),
+                entities: None,
+            },
+            principal: r#"MyApp::User::"0""#.to_string(),
+            action: "GetProjectMetadata".to_string(),
+            resource: "MyApp::Project".to_string(),
+            context: Some("{}".to_string()),
+            target: Target::Est,
+            id_field: "id".to_string(),
+            ancestors_field: "ancestors".to_string(),
+        };
+        let filter = serde_json::from_str::<Value>(&args.run(&engine).unwrap()).unwrap();
+        assert_eq!(filter["decision"], Value::Null);
+        assert_eq!(
+            filter["policies"]
+                .as_object()
+                .unwrap()
+                .keys()
+                .collect::<Vec<_>>(),
+            ["policy0"]
+        );
+        assert_eq!(
+            filter["policies"]["policy0"]["conditions"][0]["body"]["in"]["left"],
+            json!({ "Var": "resource" })
+        );
+
+        args.target = Target::Postgres;
+        assert_eq!(
+            args.run(&engine).unwrap(),
+            "\"ancestors\" && ARRAY['MyApp::Server::\"0\"']::text[]\n"
+        );
+        args.target = Target::Mongo;
+        args.ancestors_field = "parents".to_string();
+        assert_eq!(
+            serde_json::from_str::<Value>(&args.run(&engine).unwrap()).unwrap(),
+            json!({ "parents": { "$in": [r#"MyApp::Server::"0""#] } })
+        );
+        args.principal = "MyApp::User".to_string();
+        assert!(args.run(&engine).is_err());
+    }
+}
@@ -0,0 +1,178 @@
+//! `cedar-tpe`: command line tools for exploring a schema, policies and entities.
+
+#[cfg(feature = "bench")]
+mod bench;
+mod check;
+mod eval;
+mod filter;
+#[cfg(feature = "playground")]
+mod playground;
+mod repl;
+mod watch;
+
+use std::{
+    io::{stdin, stdout},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    str::FromStr,
+};
+
+use cedar_policy::{Entities, EntityTypeName, EntityUid, PolicySet, Schema, SchemaFragment};
+use cedar_test::{
+    Engine, EngineState, Error,
+    pdp::PartialUid,
+    store::{DirectoryPolicyStore, PolicyStore},
+};
+use clap::{Args, Parser, Subcommand};
+
+#[derive(Debug, Parser)]
+#[command(name = "cedar-tpe", about, version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Measure the latency of the engine's APIs on a workload generated from a schema.
+    #[cfg(feature = "bench")]
+    Bench(bench::BenchArgs),
+    /// Validate a schema, policies and entities, and lint the policies.
+    Check(check::CheckArgs),
+    /// Evaluate a concrete request and print the decision.
+    Eval(eval::EvalArgs),
+    /// Partially evaluate a request and print the residuals as a filter for a backend.
+    Filter(filter::FilterArgs),
+    /// Serve a web UI that shows decisions and residuals while editing policies.
+    #[cfg(feature = "playground")]
+    Playground(playground::PlaygroundArgs),
+    /// Type partial requests and inspect their residuals.
+    Repl(StateArgs),
+}
+
+#[derive(Debug, Args)]
+struct StateArgs {
+    /// Schema in Cedar syntax, or in JSON if the file ends in `.json`.
+    #[arg(long)]
+    schema: PathBuf,
+    /// Policies in Cedar syntax, in a file or in the `.cedar` files below a directory.
+    #[arg(long)]
+    policies: PathBuf,
+    /// Entities in Cedar's JSON format.
+    #[arg(long)]
+    entities: Option<PathBuf>,
+}
+
+impl StateArgs {
+    fn paths(&self) -> Vec<PathBuf> {
+        [&self.schema, &self.policies]
+            .into_iter()
+            .chain(&self.entities)
+            .cloned()
+            .collect()
+    }
+
+    fn load(&self) -> anyhow::Result<EngineState> {
+        let fragment = load_schema(&self.schema)?;
+        let schema = fragment.clone().try_into()?;
+        let policies = load_policies(&self.policies, &schema)?;
+        let entities = match &self.entities {
+            Some(path) => Entities::from_json_str(&read_file(path)?, None)?,
+            None => Entities::empty(),
+        };
+        Ok(EngineState::new(fragment, policies, entities)?)
+    }
+}
+
+/// A schema in Cedar syntax, or in JSON if the file ends in `.json`.
+fn load_schema(path: &Path) -> cedar_test::Result<SchemaFragment> {
+    let src = read_file(path)?;
+    let fragment = if path.extension().is_some_and(|ext| ext == "json") {
+        SchemaFragment::from_json_str(&src)?
+    } else {
+        SchemaFragment::from_cedarschema_str(&src)?.0
+    };
+    Ok(fragment)
+}
+
+/// The policies of a `.cedar` file, or of all `.cedar` files below a directory with IDs as
+/// assigned by [`DirectoryPolicyStore`].
+fn load_policies(path: &Path, schema: &Schema) -> cedar_test::Result<PolicySet> {
+    if !path.is_dir() {
+        return read_file(path)?
+            .parse::<PolicySet>()
+            .map_err(|e| Error::File {
+                path: path.to_path_buf(),
+                source: Box::new(e.into()),
+            });
+    }
+    let store = DirectoryPolicyStore::open(path, schema.clone())?;
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .map_err(|source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+    Ok(PolicySet::from_policies(runtime.block_on(store.list())?)?)
+}
+
+fn read_file(path: &Path) -> cedar_test::Result<String> {
+    std::fs::read_to_string(path).map_err(|source| Error::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// An action UID, or the action with ID `action`.
+fn resolve_action(schema: &Schema, action: &str) -> Result<EntityUid, String> {
+    EntityUid::from_str(action).or_else(|_| {
+        schema
+            .actions()
+            .find(|uid| AsRef::<str>::as_ref(uid.id()) == action)
+            .cloned()
+            .ok_or_else(|| format!("unknown action `{action}`"))
+    })
+}
+
+/// A UID, or a type for a UID with unknown ID.
+fn partial_uid(word: &str) -> Result<PartialUid, String> {
+    if word.ends_with('"') {
+        let uid = EntityUid::from_str(word).map_err(|e| e.to_string())?;
+        Ok(uid.into())
+    } else {
+        let entity_type = EntityTypeName::from_str(word).map_err(|e| e.to_string())?;
+        Ok(PartialUid::unknown(entity_type))
+    }
+}
+
+fn main() -> anyhow::Result<ExitCode> {
+    match Cli::parse().command {
+        #[cfg(feature = "bench")]
+        Command::Bench(args) => print!("{}", args.run()?),
+        Command::Check(args) if args.watch => {
+            watch::watch(args.paths(), stdout(), || Ok(args.run()?.0))?;
+        }
+        Command::Check(args) => {
+            let (report, passed) = args.run()?;
+            print!("{report}");
+            if !passed {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        Command::Eval(args) if args.watch => {
+            watch::watch(args.paths(), stdout(), || args.run(&args.state.load()?))?;
+        }
+        Command::Eval(args) => print!("{}", args.run(&args.state.load()?)?),
+        Command::Filter(args) => {
+            let engine = Engine::from_state(args.state.load()?);
+            print!("{}", args.run(&engine)?);
+        }
+        #[cfg(feature = "playground")]
+        Command::Playground(args) => args.serve()?,
+        Command::Repl(args) => {
+            let engine = Engine::from_state(args.load()?);
+            repl::run(&engine, stdin().lock(), stdout().lock())?;
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
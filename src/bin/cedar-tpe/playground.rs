@@ -0,0 +1,221 @@
+//! A local web UI to edit a schema, policies and entities and see decisions and residuals
+//! while typing.
+
+use std::{net::SocketAddr, path::PathBuf, str::FromStr};
+
+use anyhow::Context as _;
+use axum::{
+    Json, Router,
+    extract::State,
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+    routing::{get, post},
+};
+use cedar_policy::{Context, Decision, Entities, PolicySet, SchemaFragment};
+use cedar_test::Engine;
+use clap::Args;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    eval::{Evaluation, RequestFile, evaluate},
+    partial_uid, read_file, resolve_action,
+};
+
+const INDEX: &str = include_str!("playground.html");
+
+#[derive(Debug, Args)]
+pub(crate) struct PlaygroundArgs {
+    /// Schema to start with, in Cedar syntax. Defaults to an example schema.
+    #[arg(long)]
+    schema: Option<PathBuf>,
+    /// Policies to start with, in Cedar syntax.
+    #[arg(long)]
+    policies: Option<PathBuf>,
+    /// Entities to start with, in Cedar's JSON format.
+    #[arg(long)]
+    entities: Option<PathBuf>,
+    #[arg(long, default_value = "127.0.0.1:8180")]
+    listen: SocketAddr,
+}
+
+/// The editor contents. All inputs are sent on every change, the server keeps no state.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Inputs {
+    schema: String,
+    policies: String,
+    entities: String,
+    /// A UID, or a type such as `MyApp::User` for an unknown ID.
+    principal: String,
+    /// An action UID, or the ID of an action.
+    action: String,
+    /// A UID, or a type such as `MyApp::Project` for an unknown ID.
+    resource: String,
+    /// The context as JSON. Unknown to TPE if empty.
+    context: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Answer {
+    /// The concrete decision, if the principal and resource are UIDs.
+    authorization: Option<Evaluation>,
+    tpe: Tpe,
+}
+
+#[derive(Debug, Serialize)]
+struct Tpe {
+    decision: Option<Decision>,
+    residuals: Vec<Residual>,
+    /// The residuals as printed by `cedar-tpe filter --target est`.
+    filter: Value,
+}
+
+#[derive(Debug, Serialize)]
+struct Residual {
+    id: String,
+    effect: String,
+    condition: String,
+}
+
+impl PlaygroundArgs {
+    pub(crate) fn serve(&self) -> anyhow::Result<()> {
+        let initial = self.initial()?;
+        let app = Router::new()
+            .route("/", get(|| async { Html(INDEX) }))
+            .route(
+                "/api/initial",
+                get(|State(initial): State<Inputs>| async { Json(initial) }),
+            )
+            .route("/api/evaluate", post(answer))
+            .with_state(initial);
+        let runtime = tokio::runtime::Runtime::new()?;
+        runtime.block_on(async {
+            let listener = tokio::net::TcpListener::bind(self.listen).await?;
+            println!("playground at http://{}", listener.local_addr()?);
+            axum::serve(listener, app).await?;
+            Ok(())
+        })
+    }
+
+    fn initial(&self) -> anyhow::Result<Inputs> {
+        let file = |path: &Option<PathBuf>| path.as_deref().map(read_file).transpose();
+        let schema = file(&self.schema)?
+            .unwrap_or_else(|| include_str!("../../resources/example.cedarschema").to_string());
+        Ok(Inputs {
+            schema,
+            policies: file(&self.policies)?.unwrap_or_default(),
+            entities: file(&self.entities)?.unwrap_or_else(|| "[]".to_string()),
+            principal: r#"MyApp::User::"0""#.to_string(),
+            action: "GetProjectMetadata".to_string(),
+            resource: "MyApp::Project".to_string(),
+            context: String::new(),
+        })
+    }
+}
+
+async fn answer(Json(inputs): Json<Inputs>) -> Response {
+    match inputs.answer() {
+        Ok(answer) => Json(answer).into_response(),
+        Err(e) => {
+            let body = serde_json::json!({ "reason": format!("{e:#}") });
+            (StatusCode::BAD_REQUEST, Json(body)).into_response()
+        }
+    }
+}
+
+impl Inputs {
+    fn answer(&self) -> anyhow::Result<Answer> {
+        let fragment = if self.schema.trim_start().starts_with('{') {
+            SchemaFragment::from_json_str(&self.schema)?
+        } else {
+            SchemaFragment::from_cedarschema_str(&self.schema)?.0
+        };
+        let policies = PolicySet::from_str(&self.policies).context("Invalid policies")?;
+        let entities = match self.entities.trim() {
+            "" => Entities::empty(),
+            entities => Entities::from_json_str(entities, None).context("Invalid entities")?,
+        };
+        let engine = Engine::new(fragment, policies, entities)?;
+        let state = engine.state();
+
+        let context = match self.context.trim() {
+            "" => None,
+            context => Some(serde_json::from_str::<Value>(context).context("Invalid context")?),
+        };
+        let principal = partial_uid(&self.principal).map_err(anyhow::Error::msg)?;
+        let resource = partial_uid(&self.resource).map_err(anyhow::Error::msg)?;
+        let authorization = if self.principal.ends_with('"') && self.resource.ends_with('"') {
+            Some(evaluate(
+                &state,
+                RequestFile {
+                    principal: self.principal.clone(),
+                    action: self.action.clone(),
+                    resource: self.resource.clone(),
+                    context: context.clone(),
+                },
+            )?)
+        } else {
+            None
+        };
+
+        let action = resolve_action(state.schema(), &self.action).map_err(anyhow::Error::msg)?;
+        let context = match context {
+            Some(context) => Some(Context::from_json_value(
+                context,
+                Some((state.schema(), &action)),
+            )?),
+            None => None,
+        };
+        let residuals = engine.tpe(principal, action, resource, context)?;
+        Ok(Answer {
+            authorization,
+            tpe: Tpe {
+                decision: residuals.decision(),
+                residuals: residuals
+                    .conditions()
+                    .filter(|(policy, _)| residuals.is_nontrivial(policy.id()))
+                    .map(|(policy, condition)| Residual {
+                        id: AsRef::<str>::as_ref(policy.id()).to_string(),
+                        effect: format!("{:?}", policy.effect()).to_lowercase(),
+                        condition,
+                    })
+                    .collect(),
+                filter: residuals.est(),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_answer() {
+        let args = PlaygroundArgs {
+            schema: None,
+            policies: None,
+            entities: None,
+            listen: "127.0.0.1:0".parse().unwrap(),
+        };
+        let mut inputs = Inputs {
+            policies: r#"permit (principal, action, resource in MyApp::Server::"0");"#.to_string(),
+            ..args.initial().unwrap()
+        };
+        let answer = serde_json::to_value(inputs.answer().unwrap()).unwrap();
+        assert_eq!(answer["authorization"], Value::Null);
+        assert_eq!(answer["tpe"]["decision"], Value::Null);
+        assert_eq!(
+            answer["tpe"]["residuals"][0]["condition"],
+            r#"resource in MyApp::Server::"0""#
+        );
+        assert!(answer["tpe"]["filter"]["policies"]["policy0"].is_object());
+
+        inputs.resource = r#"MyApp::Project::"0""#.to_string();
+        let answer = serde_json::to_value(inputs.answer().unwrap()).unwrap();
+        assert_eq!(answer["authorization"]["decision"], "deny");
+
+        inputs.policies = "permit (".to_string();
+        assert!(inputs.answer().is_err());
+    }
+}
@@ -0,0 +1,167 @@
+//! An interactive loop for partial requests against a loaded engine.
+
+use std::{
+    fmt::Write as _,
+    io::{self, BufRead, Write},
+};
+
+use cedar_policy::{Context, Decision, PolicyId};
+use cedar_test::Engine;
+
+use crate::{partial_uid, resolve_action};
+
+const HELP: &str = r#"Commands:
+  tpe <principal> <action> <resource> [<context>]
+      Partially evaluate a request. The principal and resource are a UID such as
+      `MyApp::User::"0"`, or a type such as `MyApp::User` for an unknown ID. The action may
+      be given by ID alone. The context is JSON and unknown if omitted.
+  policies
+      List the installed policies.
+  help
+  quit
+"#;
+
+pub(crate) fn run(engine: &Engine, input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+    write!(output, "> ")?;
+    output.flush()?;
+    for line in input.lines() {
+        match execute(engine, &line?) {
+            Some(Ok(text)) => write!(output, "{text}")?,
+            Some(Err(e)) => writeln!(output, "error: {e}")?,
+            None => return Ok(()),
+        }
+        write!(output, "> ")?;
+        output.flush()?;
+    }
+    writeln!(output)
+}
+
+/// The output of one command line, or `None` to quit.
+fn execute(engine: &Engine, line: &str) -> Option<Result<String, String>> {
+    let (command, args) = next_word(line);
+    Some(match command {
+        "" => Ok(String::new()),
+        "quit" | "exit" => return None,
+        "help" => Ok(HELP.to_string()),
+        "policies" => Ok(engine
+            .state()
+            .policies()
+            .policies()
+            .map(|policy| format!("{:?} {}\n", policy.effect(), id(policy.id())).to_lowercase())
+            .collect()),
+        "tpe" => tpe(engine, args),
+        other => Err(format!("unknown command `{other}`, try `help`")),
+    })
+}
+
+fn tpe(engine: &Engine, args: &str) -> Result<String, String> {
+    let (principal, args) = next_word(args);
+    let (action, args) = next_word(args);
+    let (resource, context) = next_word(args);
+    if resource.is_empty() {
+        return Err("usage: tpe <principal> <action> <resource> [<context>]".to_string());
+    }
+    let state = engine.state();
+    let action = resolve_action(state.schema(), action)?;
+    let context = match context.trim() {
+        "" => None,
+        context => {
+            let json = serde_json::from_str(context).map_err(|e| e.to_string())?;
+            Some(
+                Context::from_json_value(json, Some((state.schema(), &action)))
+                    .map_err(|e| e.to_string())?,
+            )
+        }
+    };
+    let residuals = engine
+        .tpe(
+            partial_uid(principal)?,
+            action,
+            partial_uid(resource)?,
+            context,
+        )
+        .map_err(|e| e.to_string())?;
+
+    let decision = match residuals.decision() {
+        Some(Decision::Allow) => "allow",
+        Some(Decision::Deny) => "deny",
+        None => "unknown",
+    };
+    let mut text = format!("decision: {decision}\n");
+    let (mut satisfied, mut pruned) = (Vec::new(), Vec::new());
+    let mut determining = String::new();
+    for (policy, condition) in residuals.conditions() {
+        if residuals.is_nontrivial(policy.id()) {
+            let effect = format!("{:?}", policy.effect()).to_lowercase();
+            let _ = writeln!(determining, "  {effect} {}: {condition}", id(policy.id()));
+        } else if condition == "true" {
+            satisfied.push(id(policy.id()));
+        } else {
+            pruned.push(id(policy.id()));
+        }
+    }
+    if !determining.is_empty() {
+        text.push_str("may be determining:\n");
+        text.push_str(&determining);
+    }
+    if !satisfied.is_empty() {
+        let _ = writeln!(text, "satisfied: {}", satisfied.join(", "));
+    }
+    if !pruned.is_empty() {
+        let _ = writeln!(text, "pruned: {}", pruned.join(", "));
+    }
+    Ok(text)
+}
+
+fn id(id: &PolicyId) -> &str {
+    id.as_ref()
+}
+
+/// The first whitespace-separated word of `line` and the rest.
+fn next_word(line: &str) -> (&str, &str) {
+    let line = line.trim_start();
+    line.split_once(char::is_whitespace)
+        .map_or((line, ""), |(word, rest)| (word, rest.trim_start()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use cedar_policy::{Entities, PolicySet, SchemaFragment};
+
+    use super::*;
+
+    #[test]
+    fn test_repl() {
+        let engine = Engine::new(
+            SchemaFragment::from_cedarschema_str(include_str!(
+                "../../resources/example.cedarschema"
+            ))
+            .unwrap()
+            .0,
+            PolicySet::from_str(
+                r#"
+permit (principal in MyApp::Role::"admin", action, resource);
+permit (principal, action, resource is MyApp::Server);
+permit (principal, action == MyApp::Action::"GetProjectMetadata", resource);
+"#,
+            )
+            .unwrap(),
+            Entities::empty(),
+        )
+        .unwrap();
+        let input = "tpe MyApp::User GetProjectMetadata MyApp::Project::\"0\" {}\nbogus\nquit\ntpe";
+        let mut output = Vec::new();
+        run(&engine, input.as_bytes(), &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "> decision: allow\n\
+             may be determining:\n  permit policy0: principal in MyApp::Role::\"admin\"\n\
+             satisfied: policy2\n\
+             pruned: policy1\n\
+             > error: unknown command `bogus`, try `help`\n\
+             > "
+        );
+    }
+}
@@ -0,0 +1,133 @@
+//! Re-running a command whenever one of its input files changes.
+
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+const INTERVAL: Duration = Duration::from_millis(500);
+
+/// Modification times of files, and of the files below directories.
+#[derive(Debug)]
+struct Watcher {
+    roots: Vec<PathBuf>,
+    files: BTreeMap<PathBuf, Option<SystemTime>>,
+}
+
+impl Watcher {
+    fn new(roots: Vec<PathBuf>) -> Self {
+        let mut watcher = Self {
+            roots,
+            files: BTreeMap::new(),
+        };
+        watcher.files = watcher.scan();
+        watcher
+    }
+
+    /// The files that were created, modified or removed since the last call.
+    fn changed(&mut self) -> Vec<PathBuf> {
+        let files = self.scan();
+        let mut changed = files
+            .iter()
+            .filter(|(path, modified)| self.files.get(*path) != Some(modified))
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>();
+        changed.extend(
+            self.files
+                .keys()
+                .filter(|path| !files.contains_key(*path))
+                .cloned(),
+        );
+        changed.sort();
+        self.files = files;
+        changed
+    }
+
+    fn scan(&self) -> BTreeMap<PathBuf, Option<SystemTime>> {
+        let mut files = BTreeMap::new();
+        for root in &self.roots {
+            scan(root, &mut files);
+        }
+        files
+    }
+}
+
+fn scan(path: &Path, files: &mut BTreeMap<PathBuf, Option<SystemTime>>) {
+    if let Ok(entries) = std::fs::read_dir(path) {
+        for entry in entries.flatten() {
+            // Links to directories are not followed below the roots, so link cycles end.
+            let is_link = entry.file_type().is_ok_and(|t| t.is_symlink());
+            if !(is_link && entry.path().is_dir()) {
+                scan(&entry.path(), files);
+            }
+        }
+        return;
+    }
+    // Files that cannot be read right now, e.g. while an editor replaces them, are kept with
+    // an unknown time so that they count as changed once they are back.
+    let modified = std::fs::metadata(path).and_then(|metadata| metadata.modified());
+    files.insert(path.to_path_buf(), modified.ok());
+}
+
+/// Print the output of `command`, and again with the changed files whenever one of `paths`
+/// changes. Runs until writing to `output` fails.
+pub(crate) fn watch(
+    paths: Vec<PathBuf>,
+    mut output: impl Write,
+    mut command: impl FnMut() -> anyhow::Result<String>,
+) -> io::Result<()> {
+    let mut watcher = Watcher::new(paths);
+    let mut render = |output: &mut dyn Write| match command() {
+        Ok(text) => write!(output, "{text}"),
+        Err(e) => writeln!(output, "error: {e:#}"),
+    };
+    render(&mut output)?;
+    output.flush()?;
+    loop {
+        std::thread::sleep(INTERVAL);
+        let changed = watcher.changed();
+        if changed.is_empty() {
+            continue;
+        }
+        for path in changed {
+            writeln!(output, "changed: {}", path.display())?;
+        }
+        render(&mut output)?;
+        output.flush()?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use super::*;
+
+    #[test]
+    fn test_changed() {
+        let dir = std::env::temp_dir().join(format!("cedar-tpe-watch-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("policies")).unwrap();
+        std::fs::write(dir.join("schema.cedarschema"), "").unwrap();
+        std::fs::write(dir.join("policies/a.cedar"), "").unwrap();
+        let mut watcher = Watcher::new(vec![dir.join("schema.cedarschema"), dir.join("policies")]);
+        assert!(watcher.changed().is_empty());
+
+        File::options()
+            .write(true)
+            .open(dir.join("schema.cedarschema"))
+            .unwrap()
+            .set_modified(SystemTime::UNIX_EPOCH)
+            .unwrap();
+        std::fs::write(dir.join("policies/b.cedar"), "").unwrap();
+        assert_eq!(
+            watcher.changed(),
+            [dir.join("policies/b.cedar"), dir.join("schema.cedarschema")]
+        );
+        std::fs::remove_file(dir.join("policies/a.cedar")).unwrap();
+        assert_eq!(watcher.changed(), [dir.join("policies/a.cedar")]);
+        assert!(watcher.changed().is_empty());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,7 @@
+//! `uniffi-bindgen`: generates the Swift and Kotlin bindings of [`cedar_test::mobile`] from the
+//! built library, for example
+//! `uniffi-bindgen generate --library target/release/libcedar_test.so --language swift --out-dir out`.
+
+fn main() {
+    uniffi::uniffi_bindgen_main()
+}
@@ -0,0 +1,181 @@
+//! Calls shared by the bindings for other languages. Schema, policies and entities are in
+//! Cedar's JSON formats, and calls have the shapes of the HTTP API's requests.
+
+use std::str::FromStr;
+
+use cedar_policy::{
+    Context, Entities, EntityId, EntityTypeName, EntityUid, PolicySet, Request, Schema,
+    SchemaFragment,
+};
+use serde::{Deserialize, de::DeserializeOwned};
+use serde_json::Value;
+
+use crate::{Engine, Error, Residuals, Result};
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct AuthorizationCall {
+    pub(crate) principal: String,
+    pub(crate) action: String,
+    pub(crate) resource: String,
+    pub(crate) context: Option<Value>,
+}
+
+/// An entity whose ID may be unknown: `{"type": "MyApp::User", "id": null}`.
+#[derive(Debug, Deserialize)]
+struct PartialUid {
+    #[serde(rename = "type")]
+    type_name: String,
+    id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TpeCall {
+    principal: PartialUid,
+    action: String,
+    resource: PartialUid,
+    context: Option<Value>,
+}
+
+fn parse<T: DeserializeOwned>(call: Value) -> Result<T> {
+    serde_json::from_value(call).map_err(|e| Error::Mapping(e.to_string()))
+}
+
+pub(crate) fn parse_uid(uid: &str) -> Result<EntityUid> {
+    EntityUid::from_str(uid).map_err(|e| Error::Mapping(format!("Invalid entity UID `{uid}`: {e}")))
+}
+
+impl PartialUid {
+    fn parse(self) -> Result<crate::pdp::PartialUid> {
+        let entity_type = EntityTypeName::from_str(&self.type_name).map_err(|e| {
+            Error::Mapping(format!("Invalid entity type `{}`: {e}", self.type_name))
+        })?;
+        Ok(crate::pdp::PartialUid {
+            entity_type,
+            id: self.id.as_deref().map(EntityId::new),
+        })
+    }
+}
+
+pub(crate) fn context(
+    engine: &Engine,
+    context: Option<Value>,
+    action: &EntityUid,
+) -> Result<Context> {
+    match context {
+        Some(context) => Ok(Context::from_json_value(
+            context,
+            Some((engine.state().schema(), action)),
+        )?),
+        None => Ok(Context::empty()),
+    }
+}
+
+pub(crate) fn load(schema: &str, policies: &str, entities: &str) -> Result<Engine> {
+    let fragment = SchemaFragment::from_json_str(schema)?;
+    let schema: Schema = fragment.clone().try_into()?;
+    let entities = Entities::from_json_str(entities, Some(&schema))?;
+    Engine::new(fragment, PolicySet::from_json_str(policies)?, entities)
+}
+
+pub(crate) fn request(engine: &Engine, call: AuthorizationCall) -> Result<Request> {
+    let action = parse_uid(&call.action)?;
+    let context = context(engine, call.context, &action)?;
+    Ok(Request::new(
+        parse_uid(&call.principal)?,
+        action,
+        parse_uid(&call.resource)?,
+        context,
+        Some(engine.state().schema()),
+    )?)
+}
+
+/// The decision on a call, as `{"decision": "Allow", "diagnostics": {...}}`.
+#[cfg(any(feature = "wasm", feature = "node", feature = "ffi"))]
+pub(crate) fn authorize(engine: &Engine, call: Value) -> Result<Value> {
+    use cedar_policy::Decision;
+
+    let request = request(engine, parse(call)?)?;
+    let response = engine.is_authorized(&request);
+    let mut reason = response
+        .diagnostics()
+        .reason()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    reason.sort();
+    let errors = response
+        .diagnostics()
+        .errors()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    Ok(serde_json::json!({
+        "decision": match response.decision() {
+            Decision::Allow => "Allow",
+            Decision::Deny => "Deny",
+        },
+        "diagnostics": { "reason": reason, "errors": errors },
+    }))
+}
+
+pub(crate) fn residuals(engine: &Engine, call: Value) -> Result<Residuals> {
+    let call: TpeCall = parse(call)?;
+    let action = parse_uid(&call.action)?;
+    // TPE only accepts a fully known context, so an absent context means an empty one.
+    let context = context(engine, call.context, &action)?;
+    engine.tpe(
+        call.principal.parse()?,
+        action,
+        call.resource.parse()?,
+        Some(context),
+    )
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::CEDAR_SCHEMA_SRC;
+
+    /// Schema, policies and entities in the formats [`load`] expects.
+    pub(crate) fn inputs() -> (String, String, &'static str) {
+        let schema = SchemaFragment::from_str(CEDAR_SCHEMA_SRC)
+            .unwrap()
+            .to_json_string()
+            .unwrap();
+        let policies = PolicySet::from_str(
+            r#"permit (principal == MyApp::User::"0", action, resource in MyApp::Server::"0");"#,
+        )
+        .unwrap()
+        .to_json()
+        .unwrap();
+        let entities = r#"[
+            {
+                "uid": { "type": "MyApp::Project", "id": "0" },
+                "attrs": {},
+                "parents": [{ "type": "MyApp::Server", "id": "0" }]
+            }
+        ]"#;
+        (schema, policies.to_string(), entities)
+    }
+
+    pub(crate) fn engine() -> Engine {
+        let (schema, policies, entities) = inputs();
+        load(&schema, &policies, entities).unwrap()
+    }
+
+    #[test]
+    #[cfg(any(feature = "wasm", feature = "node", feature = "ffi"))]
+    fn test_calls() {
+        use serde_json::json;
+
+        use crate::testing::fixtures::{authorization_call, tpe_call};
+
+        let engine = engine();
+        let answer = authorize(&engine, authorization_call()).unwrap();
+        assert_eq!(answer["decision"], "Allow");
+        assert_eq!(answer["diagnostics"]["reason"], json!(["policy0"]));
+        assert!(authorize(&engine, json!({})).is_err());
+
+        let residuals = residuals(&engine, tpe_call()).unwrap();
+        assert_eq!(residuals.decision(), None);
+        assert_eq!(residuals.nontrivial_policies().count(), 1);
+    }
+}
@@ -0,0 +1,402 @@
+//! Typed construction of policies, for services that generate policies instead of formatting
+//! Cedar source.
+//!
+//! [`PolicyBuilder`] assembles the scope and conditions of a policy from [`EntityUid`]s and
+//! [`Expr`]s, which quote and escape their parts, and validates the result against a schema:
+//!
+//! ```
+//! # use std::str::FromStr;
+//! # use cedar_policy::{EntityTypeName, EntityUid, Schema};
+//! # use cedar_test::builder::{Expr, PolicyBuilder};
+//! # let schema = Schema::from_str(include_str!("resources/example.cedarschema")).unwrap();
+//! let policy = PolicyBuilder::permit()
+//!     .id("tenant-a/project-roles")
+//!     .principal_is(EntityTypeName::from_str("MyApp::Role").unwrap())
+//!     .action_in(EntityUid::from_str(r#"MyApp::Action::"ProjectActions""#).unwrap())
+//!     .when(Expr::principal().attr("project").eq(Expr::resource()))
+//!     .build(&schema)
+//!     .unwrap();
+//! ```
+
+use std::fmt::{self, Display};
+
+use cedar_policy::{Effect, EntityTypeName, EntityUid, Policy, PolicyId, PolicySet, Schema};
+
+use crate::{Result, engine::validate_policies};
+
+/// A Cedar expression. Values are quoted and escaped, and every compound expression is
+/// parenthesized, so expressions compose without regard to precedence.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expr(String);
+
+impl Expr {
+    pub fn principal() -> Self {
+        Self("principal".to_string())
+    }
+
+    pub fn action() -> Self {
+        Self("action".to_string())
+    }
+
+    pub fn resource() -> Self {
+        Self("resource".to_string())
+    }
+
+    pub fn context() -> Self {
+        Self("context".to_string())
+    }
+
+    pub fn string(value: impl AsRef<str>) -> Self {
+        // Cedar string literals accept the escapes of Rust's debug formatting.
+        Self(format!("{:?}", value.as_ref()))
+    }
+
+    pub fn long(value: i64) -> Self {
+        Self(value.to_string())
+    }
+
+    pub fn bool(value: bool) -> Self {
+        Self(value.to_string())
+    }
+
+    pub fn uid(uid: EntityUid) -> Self {
+        Self(uid.to_string())
+    }
+
+    pub fn set(elements: impl IntoIterator<Item = Expr>) -> Self {
+        let elements = elements.into_iter().map(|e| e.0).collect::<Vec<_>>();
+        Self(format!("[{}]", elements.join(", ")))
+    }
+
+    /// An expression in Cedar syntax, checked when the policy is built.
+    pub fn raw(src: impl Into<String>) -> Self {
+        Self(format!("({})", src.into()))
+    }
+
+    /// `self["name"]`, which also works for names that are not identifiers.
+    pub fn attr(self, name: &str) -> Self {
+        Self(format!("{}[{}]", self.0, Self::string(name).0))
+    }
+
+    pub fn has(self, name: &str) -> Self {
+        Self(format!("({} has {})", self.0, Self::string(name).0))
+    }
+
+    pub fn eq(self, other: Expr) -> Self {
+        self.binary("==", other)
+    }
+
+    pub fn ne(self, other: Expr) -> Self {
+        self.binary("!=", other)
+    }
+
+    pub fn lt(self, other: Expr) -> Self {
+        self.binary("<", other)
+    }
+
+    pub fn le(self, other: Expr) -> Self {
+        self.binary("<=", other)
+    }
+
+    /// `self in other`, named so as not to clash with the keyword.
+    pub fn is_in(self, other: Expr) -> Self {
+        self.binary("in", other)
+    }
+
+    pub fn is(self, entity_type: &EntityTypeName) -> Self {
+        Self(format!("({} is {entity_type})", self.0))
+    }
+
+    pub fn contains(self, element: Expr) -> Self {
+        Self(format!("{}.contains({})", self.0, element.0))
+    }
+
+    pub fn and(self, other: Expr) -> Self {
+        self.binary("&&", other)
+    }
+
+    pub fn or(self, other: Expr) -> Self {
+        self.binary("||", other)
+    }
+
+    /// An expression as printed by Cedar, which parenthesizes all compound subexpressions.
+    pub(crate) fn cedar(text: String) -> Self {
+        Self(text)
+    }
+
+    fn binary(self, op: &str, other: Expr) -> Self {
+        Self(format!("({} {op} {})", self.0, other.0))
+    }
+}
+
+impl Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::ops::Not for Expr {
+    type Output = Self;
+
+    fn not(self) -> Self {
+        Self(format!("(!{})", self.0))
+    }
+}
+
+impl From<EntityUid> for Expr {
+    fn from(uid: EntityUid) -> Self {
+        Self::uid(uid)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Scope {
+    Any,
+    Eq(EntityUid),
+    In(EntityUid),
+    Is(EntityTypeName),
+    IsIn(EntityTypeName, EntityUid),
+}
+
+#[derive(Debug, Clone)]
+enum ActionScope {
+    Any,
+    Eq(EntityUid),
+    In(Vec<EntityUid>),
+}
+
+/// A policy under construction. Scope methods replace any earlier constraint on the same
+/// variable, conditions accumulate.
+#[derive(Debug, Clone)]
+pub struct PolicyBuilder {
+    effect: Effect,
+    id: Option<PolicyId>,
+    annotations: Vec<(String, Option<String>)>,
+    principal: Scope,
+    action: ActionScope,
+    resource: Scope,
+    conditions: Vec<(&'static str, Expr)>,
+}
+
+impl PolicyBuilder {
+    pub fn permit() -> Self {
+        Self::new(Effect::Permit)
+    }
+
+    pub fn forbid() -> Self {
+        Self::new(Effect::Forbid)
+    }
+
+    fn new(effect: Effect) -> Self {
+        Self {
+            effect,
+            id: None,
+            annotations: Vec::new(),
+            principal: Scope::Any,
+            action: ActionScope::Any,
+            resource: Scope::Any,
+            conditions: Vec::new(),
+        }
+    }
+
+    /// The ID of the built policy. Defaults to `policy0`, as for a parsed policy.
+    pub fn id(mut self, id: impl AsRef<str>) -> Self {
+        self.id = Some(PolicyId::new(id));
+        self
+    }
+
+    pub fn annotation(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.annotations.push((key.into(), Some(value.into())));
+        self
+    }
+
+    /// An annotation without a value, such as `@experimental`.
+    pub fn flag(mut self, key: impl Into<String>) -> Self {
+        self.annotations.push((key.into(), None));
+        self
+    }
+
+    pub fn principal_eq(mut self, uid: EntityUid) -> Self {
+        self.principal = Scope::Eq(uid);
+        self
+    }
+
+    pub fn principal_in(mut self, uid: EntityUid) -> Self {
+        self.principal = Scope::In(uid);
+        self
+    }
+
+    pub fn principal_is(mut self, entity_type: EntityTypeName) -> Self {
+        self.principal = Scope::Is(entity_type);
+        self
+    }
+
+    pub fn principal_is_in(mut self, entity_type: EntityTypeName, uid: EntityUid) -> Self {
+        self.principal = Scope::IsIn(entity_type, uid);
+        self
+    }
+
+    pub fn action_eq(mut self, uid: EntityUid) -> Self {
+        self.action = ActionScope::Eq(uid);
+        self
+    }
+
+    /// The action is `group` or a member of it.
+    pub fn action_in(self, group: EntityUid) -> Self {
+        self.action_in_any([group])
+    }
+
+    pub fn action_in_any(mut self, groups: impl IntoIterator<Item = EntityUid>) -> Self {
+        self.action = ActionScope::In(groups.into_iter().collect());
+        self
+    }
+
+    pub fn resource_eq(mut self, uid: EntityUid) -> Self {
+        self.resource = Scope::Eq(uid);
+        self
+    }
+
+    pub fn resource_in(mut self, uid: EntityUid) -> Self {
+        self.resource = Scope::In(uid);
+        self
+    }
+
+    pub fn resource_is(mut self, entity_type: EntityTypeName) -> Self {
+        self.resource = Scope::Is(entity_type);
+        self
+    }
+
+    pub fn resource_is_in(mut self, entity_type: EntityTypeName, uid: EntityUid) -> Self {
+        self.resource = Scope::IsIn(entity_type, uid);
+        self
+    }
+
+    pub fn when(mut self, condition: Expr) -> Self {
+        self.conditions.push(("when", condition));
+        self
+    }
+
+    pub fn unless(mut self, condition: Expr) -> Self {
+        self.conditions.push(("unless", condition));
+        self
+    }
+
+    /// The policy, validated in strict mode against `schema`.
+    pub fn build(&self, schema: &Schema) -> Result<Policy> {
+        let policy = self.build_unvalidated()?;
+        validate_policies(schema, &PolicySet::from_policies([policy.clone()])?)?;
+        Ok(policy)
+    }
+
+    /// The policy, checked for syntax only.
+    pub fn build_unvalidated(&self) -> Result<Policy> {
+        Ok(Policy::parse(self.id.clone(), self.to_string())?)
+    }
+}
+
+/// The policy in Cedar syntax.
+impl Display for PolicyBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (key, value) in &self.annotations {
+            match value {
+                Some(value) => writeln!(f, "@{key}({})", Expr::string(value))?,
+                None => writeln!(f, "@{key}")?,
+            }
+        }
+        let effect = match self.effect {
+            Effect::Permit => "permit",
+            Effect::Forbid => "forbid",
+        };
+        write!(f, "{effect} (\n  ")?;
+        scope(f, "principal", &self.principal)?;
+        f.write_str(",\n  action")?;
+        match &self.action {
+            ActionScope::Any => {}
+            ActionScope::Eq(uid) => write!(f, " == {uid}")?,
+            ActionScope::In(groups) if groups.len() == 1 => write!(f, " in {}", groups[0])?,
+            ActionScope::In(groups) => write!(
+                f,
+                " in {}",
+                Expr::set(groups.iter().cloned().map(Expr::uid))
+            )?,
+        }
+        f.write_str(",\n  ")?;
+        scope(f, "resource", &self.resource)?;
+        f.write_str("\n)")?;
+        for (kind, condition) in &self.conditions {
+            write!(f, "\n{kind} {{ {condition} }}")?;
+        }
+        f.write_str(";")
+    }
+}
+
+fn scope(f: &mut fmt::Formatter<'_>, var: &str, scope: &Scope) -> fmt::Result {
+    match scope {
+        Scope::Any => write!(f, "{var}"),
+        Scope::Eq(uid) => write!(f, "{var} == {uid}"),
+        Scope::In(uid) => write!(f, "{var} in {uid}"),
+        Scope::Is(entity_type) => write!(f, "{var} is {entity_type}"),
+        Scope::IsIn(entity_type, uid) => write!(f, "{var} is {entity_type} in {uid}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::CEDAR_SCHEMA;
+
+    fn uid(uid: &str) -> EntityUid {
+        EntityUid::from_str(uid).unwrap()
+    }
+
+    #[test]
+    fn test_build() {
+        let policy = PolicyBuilder::permit()
+            .id("tenant-a/readers")
+            .annotation("owner", "team \"a\"")
+            .principal_is_in(
+                EntityTypeName::from_str("MyApp::User").unwrap(),
+                uid(r#"MyApp::Role::"reader""#),
+            )
+            .action_in(uid(r#"MyApp::Action::"ProjectActions""#))
+            .resource_in(uid(r#"MyApp::Server::"a""#))
+            .unless(
+                !Expr::principal()
+                    .is_in(Expr::resource())
+                    .and(Expr::bool(false)),
+            )
+            .build(&CEDAR_SCHEMA)
+            .unwrap();
+        assert_eq!(AsRef::<str>::as_ref(policy.id()), "tenant-a/readers");
+        assert_eq!(policy.annotation("owner"), Some("team \"a\""));
+        assert_eq!(
+            policy.to_string(),
+            "@owner(\"team \\\"a\\\"\")\n\
+             permit (\n  principal is MyApp::User in MyApp::Role::\"reader\",\n  \
+             action in MyApp::Action::\"ProjectActions\",\n  \
+             resource in MyApp::Server::\"a\"\n)\n\
+             unless { (!((principal in resource) && false)) };"
+        );
+    }
+
+    #[test]
+    fn test_build_invalid() {
+        // Roles have no `name` attribute.
+        let builder = PolicyBuilder::forbid()
+            .principal_is(EntityTypeName::from_str("MyApp::Role").unwrap())
+            .when(Expr::principal().attr("name").eq(Expr::string("x")));
+        assert!(builder.build_unvalidated().is_ok());
+        assert!(matches!(
+            builder.build(&CEDAR_SCHEMA),
+            Err(crate::Error::Validation(_))
+        ));
+        assert!(
+            PolicyBuilder::permit()
+                .when(Expr::raw("principal =="))
+                .build_unvalidated()
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,169 @@
+//! Decisions for many candidate resources of one partial request, for filtering rows the
+//! policies cannot be compiled to a query for.
+//!
+//! [`Engine::resource_filter`](crate::Engine::resource_filter) runs type-aware partial
+//! evaluation once with the resource ID unknown, against the policies sliced for the principal
+//! and resource types. The residuals have every resource-independent part evaluated, so a
+//! candidate is decided by partially evaluating the few residual policies that are not `false`
+//! with the candidate as the resource. If the residuals already decide the request, no
+//! candidate is evaluated at all.
+
+use std::sync::Arc;
+
+use cedar_policy::{
+    Context, Decision, EntityTypeName, EntityUid, PartialEntityUid, PartialRequest, PolicySet,
+};
+
+use crate::{
+    engine::EngineState,
+    error::{Error, Result},
+    pdp::PartialUid,
+    residuals::{Determination, Residuals},
+};
+
+/// A request with the resource ID unknown, partially evaluated for deciding it per candidate.
+#[derive(Debug, Clone)]
+pub struct ResourceFilter {
+    state: Arc<EngineState>,
+    principal: PartialUid,
+    action: EntityUid,
+    resource_type: EntityTypeName,
+    context: Option<Context>,
+    residuals: Residuals,
+    /// The residual policies that are satisfied or conditional.
+    policies: PolicySet,
+}
+
+impl ResourceFilter {
+    pub(crate) fn new(
+        state: Arc<EngineState>,
+        principal: PartialUid,
+        action: EntityUid,
+        resource_type: EntityTypeName,
+        context: Option<Context>,
+        residuals: Residuals,
+    ) -> Self {
+        let relevant = residuals
+            .policies()
+            .filter(|p| residuals.determination(p.id()) != Some(Determination::Unsatisfied))
+            .cloned();
+        let policies =
+            PolicySet::from_policies(relevant).expect("a subset of a policy set has unique IDs");
+        Self {
+            state,
+            principal,
+            action,
+            resource_type,
+            context,
+            residuals,
+            policies,
+        }
+    }
+
+    /// The residuals with the resource ID unknown.
+    pub fn residuals(&self) -> &Residuals {
+        &self.residuals
+    }
+
+    /// The decision for `resource` as its partial evaluation would make it, or `None` if it
+    /// depends on the unknown principal ID or context.
+    pub fn decision(&self, resource: &EntityUid) -> Result<Option<Decision>> {
+        if *resource.type_name() != self.resource_type {
+            return Err(Error::Mapping(format!(
+                "Resource `{resource}` is not of type `{}`",
+                self.resource_type
+            )));
+        }
+        if let Some(decision) = self.residuals.decision() {
+            return Ok(Some(decision));
+        }
+        let request = PartialRequest::new(
+            self.principal.clone().into(),
+            self.action.clone(),
+            PartialEntityUid::from_concrete(resource.clone()),
+            self.context.clone(),
+            self.state.schema(),
+        )?;
+        let response =
+            self.policies
+                .tpe(&request, self.state.partial_entities(), self.state.schema())?;
+        Ok(response.decision())
+    }
+
+    /// The candidates that are allowed, in the order given. Candidates whose decision is
+    /// unknown are left out.
+    pub fn allowed<'a>(
+        &self,
+        candidates: impl IntoIterator<Item = &'a EntityUid>,
+    ) -> Result<Vec<&'a EntityUid>> {
+        let mut allowed = Vec::new();
+        for candidate in candidates {
+            if self.decision(candidate)? == Some(Decision::Allow) {
+                allowed.push(candidate);
+            }
+        }
+        Ok(allowed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use cedar_policy::{Entities, SchemaFragment};
+
+    use super::*;
+    use crate::{CEDAR_SCHEMA_SRC, Engine, uid::entity_uid};
+
+    #[test]
+    fn test_resource_filter() {
+        let policies = PolicySet::from_str(
+            r#"
+            permit (principal, action, resource in MyApp::Server::"0");
+            forbid (principal, action, resource == MyApp::Project::"1");
+            "#,
+        )
+        .unwrap();
+        let entities = Entities::from_json_str(
+            r#"[
+                { "uid": { "type": "MyApp::Server", "id": "0" }, "attrs": {}, "parents": [] },
+                { "uid": { "type": "MyApp::Project", "id": "0" }, "attrs": {},
+                  "parents": [{ "type": "MyApp::Server", "id": "0" }] },
+                { "uid": { "type": "MyApp::Project", "id": "1" }, "attrs": {},
+                  "parents": [{ "type": "MyApp::Server", "id": "0" }] },
+                { "uid": { "type": "MyApp::Project", "id": "2" }, "attrs": {}, "parents": [] }
+            ]"#,
+            None,
+        )
+        .unwrap();
+        let engine = Engine::new(
+            SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(),
+            policies,
+            entities,
+        )
+        .unwrap();
+        let principal = PartialUid::from(entity_uid("MyApp::User", "0").unwrap());
+        let action = entity_uid("MyApp::Action", "GetProjectMetadata").unwrap();
+        let project = EntityTypeName::from_str("MyApp::Project").unwrap();
+        let filter = engine
+            .resource_filter(principal.clone(), action.clone(), project, None)
+            .unwrap();
+        assert_eq!(filter.residuals().decision(), None);
+
+        let candidates = ["0", "1", "2", "3"].map(|id| entity_uid("MyApp::Project", id).unwrap());
+        for candidate in &candidates {
+            let full = engine
+                .tpe(
+                    principal.clone(),
+                    action.clone(),
+                    PartialUid::from(candidate.clone()),
+                    None,
+                )
+                .unwrap();
+            assert_eq!(filter.decision(candidate).unwrap(), full.decision());
+        }
+        assert_eq!(filter.allowed(&candidates).unwrap(), [&candidates[0]]);
+        let server = entity_uid("MyApp::Server", "0").unwrap();
+        assert!(filter.decision(&server).is_err());
+    }
+}
@@ -0,0 +1,315 @@
+//! Single-file policy bundles, for shipping schema and policies between environments.
+//!
+//! A bundle is a `tar.gz` archive with the schema (`schema.json`), the static and
+//! template-linked policies (`policies.json`), the templates (`templates.json`) and a
+//! `manifest.json` listing the SHA-256 fingerprint of every file. The archive is written
+//! deterministically, so the same schema and policies yield the same [`Manifest::fingerprint`].
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{Read, Write},
+    path::Path,
+};
+
+use cedar_policy::{Entities, PolicySet, SchemaFragment};
+use chrono::{DateTime, Utc};
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    engine::{EngineState, validate_policies},
+    error::{Error, Result},
+    fingerprint::{canonical, sha256},
+};
+
+pub const FORMAT_VERSION: u32 = 1;
+
+const MANIFEST: &str = "manifest.json";
+const SCHEMA: &str = "schema.json";
+const POLICIES: &str = "policies.json";
+const TEMPLATES: &str = "templates.json";
+
+#[derive(Debug, Clone)]
+pub struct Bundle {
+    pub schema: SchemaFragment,
+    pub policies: PolicySet,
+}
+
+impl Bundle {
+    pub fn from_state(state: &EngineState) -> Self {
+        Self {
+            schema: state.schema_fragment().clone(),
+            policies: state.policies().clone(),
+        }
+    }
+
+    /// Validate the bundle and build an engine state from it.
+    pub fn into_state(self, entities: Entities) -> Result<EngineState> {
+        EngineState::new(self.schema, self.policies, entities)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub format_version: u32,
+    pub created_at: DateTime<Utc>,
+    pub policies: usize,
+    pub templates: usize,
+    /// Hex-encoded SHA-256 of every other file in the bundle.
+    pub files: BTreeMap<String, String>,
+    /// Hex-encoded SHA-256 over `files`. Bundles with the same content have the same
+    /// fingerprint, regardless of when they were written.
+    pub fingerprint: String,
+}
+
+/// Validate `bundle` and write it to `path`.
+pub fn write(path: impl AsRef<Path>, bundle: &Bundle) -> Result<Manifest> {
+    let path = path.as_ref();
+    let schema = bundle.schema.clone().try_into()?;
+    validate_policies(&schema, &bundle.policies)?;
+
+    let mut policies = serde_json::from_value::<BTreeMap<String, Value>>(canonical(
+        bundle.policies.clone().to_json()?,
+        false,
+    ))
+    .map_err(|e| Error::Bundle(format!("Unexpected policy set JSON: {e}")))?;
+    if let Some(Value::Array(links)) = policies.get_mut("templateLinks") {
+        links.sort_by(|a, b| a["newId"].as_str().cmp(&b["newId"].as_str()));
+    }
+    let templates = policies.remove("templates").unwrap_or_default();
+    let files = BTreeMap::from([
+        (
+            SCHEMA,
+            to_vec(&canonical(bundle.schema.clone().to_json_value()?, false))?,
+        ),
+        (POLICIES, to_vec(&policies)?),
+        (TEMPLATES, to_vec(&templates)?),
+    ]);
+
+    let hashes = files
+        .iter()
+        .map(|(name, content)| (name.to_string(), sha256(content)))
+        .collect::<BTreeMap<_, _>>();
+    let manifest = Manifest {
+        format_version: FORMAT_VERSION,
+        created_at: Utc::now(),
+        policies: bundle.policies.policies().count(),
+        templates: bundle.policies.templates().count(),
+        fingerprint: fingerprint(&hashes),
+        files: hashes,
+    };
+
+    let io = |source| Error::Io {
+        path: path.to_path_buf(),
+        source,
+    };
+    let file = File::create(path).map_err(io)?;
+    let mut archive = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+    for (name, content) in std::iter::once((MANIFEST, to_vec(&manifest)?)).chain(files) {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(0);
+        header.set_cksum();
+        archive
+            .append_data(&mut header, name, content.as_slice())
+            .map_err(io)?;
+    }
+    archive
+        .into_inner()
+        .and_then(GzEncoder::finish)
+        .and_then(|mut file| file.flush())
+        .map_err(io)?;
+    Ok(manifest)
+}
+
+/// Read the bundle at `path` and check its fingerprints. The policies are validated against the
+/// schema when the bundle is turned into an engine state.
+pub fn read(path: impl AsRef<Path>) -> Result<(Manifest, Bundle)> {
+    let path = path.as_ref();
+    let in_file = |source| Error::File {
+        path: path.to_path_buf(),
+        source: Box::new(source),
+    };
+    let mut files = read_archive(path)?;
+    let manifest = files
+        .remove(MANIFEST)
+        .ok_or_else(|| Error::Bundle(format!("Missing `{MANIFEST}`")))
+        .and_then(|content| parse::<Manifest>(MANIFEST, &content))
+        .map_err(in_file)?;
+    if manifest.format_version != FORMAT_VERSION {
+        return Err(in_file(Error::Bundle(format!(
+            "Unsupported format version {}",
+            manifest.format_version
+        ))));
+    }
+    if fingerprint(&manifest.files) != manifest.fingerprint {
+        return Err(in_file(Error::Bundle(
+            "Fingerprint does not match the manifest".to_string(),
+        )));
+    }
+    for (name, hash) in &manifest.files {
+        match files.get(name.as_str()) {
+            Some(content) if sha256(content) == *hash => {}
+            Some(_) => {
+                return Err(in_file(Error::Bundle(format!(
+                    "`{name}` does not match its fingerprint"
+                ))));
+            }
+            None => return Err(in_file(Error::Bundle(format!("Missing `{name}`")))),
+        }
+    }
+
+    let file = |name: &str| {
+        files
+            .get(name)
+            .ok_or_else(|| Error::Bundle(format!("Missing `{name}`")))
+            .and_then(|content| parse::<Value>(name, content))
+    };
+    let bundle = (|| {
+        let schema = SchemaFragment::from_json_value(file(SCHEMA)?)?;
+        let mut policies = file(POLICIES)?;
+        policies["templates"] = file(TEMPLATES)?;
+        let policies = PolicySet::from_json_value(policies)?;
+        Ok(Bundle { schema, policies })
+    })()
+    .map_err(in_file)?;
+    Ok((manifest, bundle))
+}
+
+fn read_archive(path: &Path) -> Result<BTreeMap<String, Vec<u8>>> {
+    let io = |source| Error::Io {
+        path: path.to_path_buf(),
+        source,
+    };
+    let file = File::open(path).map_err(io)?;
+    let mut archive = tar::Archive::new(GzDecoder::new(file));
+    let mut files = BTreeMap::new();
+    for entry in archive.entries().map_err(io)? {
+        let mut entry = entry.map_err(io)?;
+        let name = entry.path().map_err(io)?.to_string_lossy().into_owned();
+        let mut content = Vec::new();
+        entry.read_to_end(&mut content).map_err(io)?;
+        files.insert(name, content);
+    }
+    Ok(files)
+}
+
+fn to_vec(value: &impl Serialize) -> Result<Vec<u8>> {
+    serde_json::to_vec_pretty(value).map_err(|e| Error::Bundle(e.to_string()))
+}
+
+fn parse<T: serde::de::DeserializeOwned>(name: &str, content: &[u8]) -> Result<T> {
+    serde_json::from_slice(content).map_err(|e| Error::Bundle(format!("Invalid `{name}`: {e}")))
+}
+
+fn fingerprint(files: &BTreeMap<String, String>) -> String {
+    sha256(
+        files
+            .iter()
+            .map(|(name, hash)| format!("{hash}  {name}\n"))
+            .collect::<String>()
+            .as_bytes(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, str::FromStr};
+
+    use cedar_policy::{EntityUid, PolicyId, SlotId};
+
+    use super::*;
+    use crate::CEDAR_SCHEMA_SRC;
+
+    fn bundle() -> Bundle {
+        let mut policies = PolicySet::from_str(
+            r#"
+            @id("allow")
+            permit (principal == MyApp::User::"0", action, resource);
+            permit (principal == ?principal, action, resource);
+            "#,
+        )
+        .unwrap();
+        policies
+            .link(
+                PolicyId::new("policy1"),
+                PolicyId::new("owner-1"),
+                HashMap::from([(
+                    SlotId::principal(),
+                    EntityUid::from_str(r#"MyApp::User::"1""#).unwrap(),
+                )]),
+            )
+            .unwrap();
+        Bundle {
+            schema: SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(),
+            policies,
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("cedar-bundle-{}", uuid::Uuid::now_v7()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("bundle.tar.gz");
+
+        let written = write(&path, &bundle()).unwrap();
+        assert_eq!((written.policies, written.templates), (2, 1));
+        assert_eq!(
+            write(dir.join("again.tar.gz"), &bundle())
+                .unwrap()
+                .fingerprint,
+            written.fingerprint
+        );
+
+        let (manifest, read_bundle) = read(&path).unwrap();
+        assert_eq!(manifest, written);
+        let state = read_bundle.into_state(Entities::empty()).unwrap();
+        let link = state.policies().policy(&PolicyId::new("owner-1")).unwrap();
+        assert_eq!(link.template_id(), Some(&PolicyId::new("policy1")));
+        assert_eq!(
+            state
+                .policies()
+                .policy(&PolicyId::new("policy0"))
+                .unwrap()
+                .annotation("id"),
+            Some("allow")
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_rejects_tampered_bundle() {
+        let dir = std::env::temp_dir().join(format!("cedar-bundle-{}", uuid::Uuid::now_v7()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("bundle.tar.gz");
+        let mut manifest = write(&path, &bundle()).unwrap();
+
+        // Rewrite the archive with a policy file that no longer matches the manifest.
+        manifest.files.insert(POLICIES.to_string(), sha256(b"{}"));
+        let mut files = read_archive(&path).unwrap();
+        files.insert(MANIFEST.to_string(), to_vec(&manifest).unwrap());
+        let mut archive = tar::Builder::new(GzEncoder::new(
+            File::create(&path).unwrap(),
+            Compression::default(),
+        ));
+        for (name, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_cksum();
+            archive
+                .append_data(&mut header, name, content.as_slice())
+                .unwrap();
+        }
+        archive.into_inner().unwrap().finish().unwrap();
+
+        let err = read(&path).unwrap_err();
+        assert!(
+            matches!(&err, Error::File { source, .. } if matches!(**source, Error::Bundle(_))),
+            "{err}"
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
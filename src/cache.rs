@@ -0,0 +1,232 @@
+//! A cache of decisions for hot, repeated checks.
+//!
+//! Entries are keyed by the request and the fingerprints of the policies and entities it was
+//! evaluated against, so a cached decision is never served for a state it was not made with.
+//! The [`Engine`](crate::Engine) also clears its cache whenever a new state is installed.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        Mutex, MutexGuard, PoisonError,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use cedar_policy::{Request, Response};
+use serde::Serialize;
+
+use crate::{audit::context_json, fingerprint::sha256};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheConfig {
+    /// The maximum number of cached decisions. The oldest decisions are evicted first.
+    pub capacity: usize,
+    /// How long a decision is served from the cache.
+    pub ttl: Duration,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 10_000,
+            ttl: Duration::from_secs(60),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Decisions dropped because the cache was full or they expired.
+    pub evictions: u64,
+    pub entries: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct CacheKey {
+    principal: String,
+    action: String,
+    resource: String,
+    context: String,
+    policies: String,
+    entities: String,
+}
+
+impl CacheKey {
+    /// `None` if the request has unknowns or its context cannot be hashed.
+    pub(crate) fn new(request: &Request, policies: &str, entities: &str) -> Option<Self> {
+        let context = context_json(request.context()?).ok()?;
+        Some(Self {
+            principal: request.principal()?.to_string(),
+            action: request.action()?.to_string(),
+            resource: request.resource()?.to_string(),
+            context: sha256(context.to_string().as_bytes()),
+            policies: policies.to_string(),
+            entities: entities.to_string(),
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    responses: HashMap<CacheKey, (Response, Instant)>,
+    /// Keys in insertion order. A key re-inserted after it expired appears twice; only the
+    /// occurrence matching the entry's insertion time removes it.
+    order: VecDeque<(CacheKey, Instant)>,
+}
+
+#[derive(Debug)]
+pub(crate) struct DecisionCache {
+    config: CacheConfig,
+    entries: Mutex<Entries>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl DecisionCache {
+    pub(crate) fn new(config: CacheConfig) -> Self {
+        Self {
+            config,
+            entries: Mutex::default(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    /// The cached response for `key`, or the response of `evaluate`, which is then cached.
+    /// `evaluate` runs without holding the lock.
+    pub(crate) fn get_or_evaluate(
+        &self,
+        key: CacheKey,
+        evaluate: impl FnOnce() -> Response,
+    ) -> Response {
+        let now = Instant::now();
+        if let Some((response, inserted)) = self.lock().responses.get(&key)
+            && now.duration_since(*inserted) < self.config.ttl
+        {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            #[cfg(feature = "metrics")]
+            crate::telemetry::cache_lookup(true);
+            return response.clone();
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        crate::telemetry::cache_lookup(false);
+        let response = evaluate();
+        if self.config.capacity > 0 {
+            self.insert(key, response.clone(), now);
+        }
+        response
+    }
+
+    fn insert(&self, key: CacheKey, response: Response, now: Instant) {
+        let mut entries = self.lock();
+        let mut evicted = 0;
+        while let Some((_, inserted)) = entries.order.front() {
+            let expired = now.duration_since(*inserted) >= self.config.ttl;
+            if !expired && entries.responses.len() < self.config.capacity {
+                break;
+            }
+            let (oldest, inserted) = entries.order.pop_front().expect("the front exists");
+            if entries
+                .responses
+                .get(&oldest)
+                .is_some_and(|(_, i)| *i == inserted)
+            {
+                entries.responses.remove(&oldest);
+                evicted += 1;
+            }
+        }
+        self.evictions.fetch_add(evicted, Ordering::Relaxed);
+        entries.order.push_back((key.clone(), now));
+        entries.responses.insert(key, (response, now));
+    }
+
+    pub(crate) fn clear(&self) {
+        let mut entries = self.lock();
+        entries.responses.clear();
+        entries.order.clear();
+    }
+
+    pub(crate) fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            entries: self.lock().responses.len(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Entries> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use cedar_policy::{
+        Context, Decision, Entities, EntityUid, Policy, PolicyId, PolicySet, SchemaFragment,
+    };
+
+    use super::*;
+    use crate::{CEDAR_SCHEMA_SRC, Engine};
+
+    fn request(user: &str) -> Request {
+        Request::new(
+            EntityUid::from_str(&format!(r#"MyApp::User::"{user}""#)).unwrap(),
+            EntityUid::from_str(r#"MyApp::Action::"GetProjectMetadata""#).unwrap(),
+            EntityUid::from_str(r#"MyApp::Project::"0""#).unwrap(),
+            Context::empty(),
+            None,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_decision_cache() {
+        let engine = Engine::new(
+            SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(),
+            PolicySet::from_str(r#"permit (principal == MyApp::User::"0", action, resource);"#)
+                .unwrap(),
+            Entities::empty(),
+        )
+        .unwrap();
+        engine.enable_decision_cache(CacheConfig {
+            capacity: 2,
+            ttl: Duration::from_secs(60),
+        });
+        for user in ["0", "0", "1", "2"] {
+            engine.is_authorized(&request(user));
+        }
+        let stats = engine.decision_cache_stats().unwrap();
+        assert_eq!((stats.hits, stats.misses), (1, 3));
+        assert_eq!((stats.evictions, stats.entries), (1, 2));
+        // Decisions for caller-provided entities bypass the cache.
+        engine.is_authorized_with_entities(&request("0"), &Entities::empty());
+        assert_eq!(engine.decision_cache_stats().unwrap(), stats);
+
+        // Installing new policies clears the cache.
+        engine
+            .add_policy(
+                Policy::parse(
+                    Some(PolicyId::new("deny")),
+                    "forbid (principal, action, resource);",
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        assert_eq!(engine.decision_cache_stats().unwrap().entries, 0);
+        let response = engine.is_authorized(&request("2"));
+        assert_eq!(response.decision(), Decision::Deny);
+
+        let disabled = engine.disable_decision_cache().unwrap();
+        assert_eq!(disabled.misses, 4);
+        assert_eq!(engine.decision_cache_stats(), None);
+    }
+}
@@ -0,0 +1,479 @@
+//! Maps the claims of a validated JWT to a Cedar principal and request context, so a web
+//! service can go from an `Authorization` header to a [`Request`] in one call.
+//!
+//! Claims are selected with JSON pointers (RFC 6901), as in [`crate::opa`].
+
+use std::{collections::BTreeMap, str::FromStr};
+
+use cedar_policy::{
+    Context, Entities, Entity, EntityId, EntityTypeName, EntityUid, Request, Schema,
+};
+use jsonwebtoken::{
+    Algorithm, DecodingKey, Validation,
+    jwk::{AlgorithmParameters, EllipticCurve, JwkSet},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{Error, Result},
+    opa::{lookup_id, select},
+};
+
+/// Keys used to verify token signatures.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClaimsKeys {
+    /// A shared HS256 secret.
+    Hs256 { secret: String },
+    /// A JSON Web Key Set. Tokens must name their key with a `kid` header.
+    Jwks(JwkSet),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClaimsConfig {
+    pub keys: ClaimsKeys,
+    /// Required `iss` claim, if set.
+    #[serde(default)]
+    pub issuer: Option<String>,
+    /// Required `aud` claim, if set.
+    #[serde(default)]
+    pub audience: Option<String>,
+    pub principal_type: String,
+    /// JSON pointer to the principal ID. Defaults to `/sub`.
+    #[serde(default = "default_principal_id")]
+    pub principal_id: String,
+    /// Principal attribute name to the JSON pointer of its claim.
+    #[serde(default)]
+    pub attributes: BTreeMap<String, String>,
+    /// Context attribute name to the JSON pointer of its claim.
+    #[serde(default)]
+    pub context: BTreeMap<String, String>,
+    /// Claims such as `groups` or `roles` whose values become parents of the principal.
+    #[serde(default)]
+    pub parents: Vec<ParentMapping>,
+}
+
+/// Turns the values of a claim into parent entities of one type. The claim may hold a single
+/// string or an array of strings; a missing claim yields no parents.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParentMapping {
+    #[serde(rename = "type")]
+    pub entity_type: String,
+    /// JSON pointer to the claim, e.g. `/groups`.
+    pub claim: String,
+    /// Optional translation of claim values to entity IDs, e.g. group paths to role names.
+    /// Values without a translation are used as they are.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub values: BTreeMap<String, String>,
+}
+
+fn default_principal_id() -> String {
+    "/sub".to_string()
+}
+
+/// A request built from a token, together with the principal entity carrying the mapped
+/// attributes. Add the principal to the entities the request is authorized against.
+#[derive(Debug, Clone)]
+pub struct ClaimsRequest {
+    pub request: Request,
+    pub principal: Entity,
+}
+
+impl ClaimsRequest {
+    /// `entities` with the principal added or replaced, so that its parents take effect even
+    /// if the parent entities are not in the store.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "cedar.entities", level = "debug", skip_all)
+    )]
+    pub fn entities(&self, entities: &Entities, schema: &Schema) -> Result<Entities> {
+        Ok(entities
+            .clone()
+            .upsert_entities([self.principal.clone()], Some(schema))?)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ClaimsMapper {
+    config: ClaimsConfig,
+    principal_type: EntityTypeName,
+    parent_types: Vec<EntityTypeName>,
+}
+
+impl ClaimsMapper {
+    pub fn new(config: ClaimsConfig) -> Result<Self> {
+        let principal_type = parse_type_name(&config.principal_type)?;
+        let parent_types = config
+            .parents
+            .iter()
+            .map(|p| parse_type_name(&p.entity_type))
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            config,
+            principal_type,
+            parent_types,
+        })
+    }
+
+    pub fn config(&self) -> &ClaimsConfig {
+        &self.config
+    }
+
+    /// Verify the signature and registered claims of `token` and return its claims.
+    pub fn verify(&self, token: &str) -> Result<serde_json::Value> {
+        let (key, algorithm) = self.decoding_key(token)?;
+        let mut validation = Validation::new(algorithm);
+        if let Some(issuer) = &self.config.issuer {
+            validation.set_issuer(&[issuer]);
+        }
+        match &self.config.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+        let data = jsonwebtoken::decode::<serde_json::Value>(token, &key, &validation)
+            .map_err(|e| Error::Token(e.to_string()))?;
+        Ok(data.claims)
+    }
+
+    fn decoding_key(&self, token: &str) -> Result<(DecodingKey, Algorithm)> {
+        match &self.config.keys {
+            ClaimsKeys::Hs256 { secret } => Ok((
+                DecodingKey::from_secret(secret.as_bytes()),
+                Algorithm::HS256,
+            )),
+            ClaimsKeys::Jwks(jwks) => {
+                let header =
+                    jsonwebtoken::decode_header(token).map_err(|e| Error::Token(e.to_string()))?;
+                let kid = header
+                    .kid
+                    .ok_or_else(|| Error::Token("Token header has no `kid`".to_string()))?;
+                let jwk = jwks
+                    .find(&kid)
+                    .ok_or_else(|| Error::Token(format!("No key with ID `{kid}`")))?;
+                // The key's own algorithm takes precedence over the one claimed by the token.
+                // Without one, the token may only claim an algorithm for the key's type.
+                let algorithm = match jwk.common.key_algorithm {
+                    Some(alg) => {
+                        Algorithm::try_from(alg).map_err(|e| Error::Token(e.to_string()))?
+                    }
+                    None if key_algorithms(&jwk.algorithm).contains(&header.alg) => header.alg,
+                    None => {
+                        return Err(Error::Token(format!(
+                            "Key `{kid}` cannot verify {:?} signatures",
+                            header.alg
+                        )));
+                    }
+                };
+                let key = DecodingKey::from_jwk(jwk).map_err(|e| Error::Token(e.to_string()))?;
+                Ok((key, algorithm))
+            }
+        }
+    }
+
+    /// The principal entity described by already verified `claims`, validated against `schema`.
+    pub fn principal(&self, claims: &serde_json::Value, schema: &Schema) -> Result<Entity> {
+        let uid = EntityUid::from_type_name_and_id(
+            self.principal_type.clone(),
+            EntityId::new(lookup_id(claims, &self.config.principal_id)?),
+        );
+        let entity = serde_json::json!({
+            "uid": uid_json(&uid),
+            "attrs": select(claims, &self.config.attributes),
+            "parents": self.parents(claims)?.iter().map(uid_json).collect::<Vec<_>>(),
+        });
+        Ok(Entity::from_json_value(entity, Some(schema))?)
+    }
+
+    /// The parents of the principal described by already verified `claims`.
+    pub fn parents(&self, claims: &serde_json::Value) -> Result<Vec<EntityUid>> {
+        let mut parents = Vec::new();
+        for (mapping, entity_type) in self.config.parents.iter().zip(&self.parent_types) {
+            let values = match claims.pointer(&mapping.claim) {
+                None | Some(serde_json::Value::Null) => continue,
+                Some(serde_json::Value::String(s)) => vec![s.as_str()],
+                Some(serde_json::Value::Array(values)) => values
+                    .iter()
+                    .map(|v| {
+                        v.as_str().ok_or_else(|| {
+                            Error::Mapping(format!(
+                                "Expected strings in `{}`, found `{v}`",
+                                mapping.claim
+                            ))
+                        })
+                    })
+                    .collect::<Result<_>>()?,
+                Some(other) => {
+                    return Err(Error::Mapping(format!(
+                        "Expected a string or array at `{}`, found `{other}`",
+                        mapping.claim
+                    )));
+                }
+            };
+            parents.extend(values.into_iter().map(|value| {
+                let id = mapping.values.get(value).map_or(value, String::as_str);
+                EntityUid::from_type_name_and_id(entity_type.clone(), EntityId::new(id))
+            }));
+        }
+        Ok(parents)
+    }
+
+    /// The request context described by already verified `claims`.
+    pub fn context(
+        &self,
+        claims: &serde_json::Value,
+        schema: &Schema,
+        action: &EntityUid,
+    ) -> Result<Context> {
+        let context = select(claims, &self.config.context);
+        Ok(Context::from_json_value(
+            serde_json::Value::Object(context),
+            Some((schema, action)),
+        )?)
+    }
+
+    /// Verify the bearer token in an `Authorization` header value and build the request for
+    /// `action` on `resource`.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "cedar.request", level = "debug", skip_all)
+    )]
+    pub fn to_request(
+        &self,
+        authorization: &str,
+        action: EntityUid,
+        resource: EntityUid,
+        schema: &Schema,
+    ) -> Result<ClaimsRequest> {
+        let claims = self.verify(bearer_token(authorization)?)?;
+        let principal = self.principal(&claims, schema)?;
+        let context = self.context(&claims, schema, &action)?;
+        let request = Request::new(principal.uid(), action, resource, context, Some(schema))?;
+        Ok(ClaimsRequest { request, principal })
+    }
+}
+
+fn parse_type_name(name: &str) -> Result<EntityTypeName> {
+    EntityTypeName::from_str(name)
+        .map_err(|e| Error::Mapping(format!("Invalid entity type `{name}`: {e}")))
+}
+
+fn uid_json(uid: &EntityUid) -> serde_json::Value {
+    serde_json::json!({ "type": uid.type_name().to_string(), "id": uid.id().unescaped() })
+}
+
+/// Extract the token from a `Bearer <token>` header value.
+pub fn bearer_token(authorization: &str) -> Result<&str> {
+    match authorization.trim().split_once(' ') {
+        Some((scheme, token)) if scheme.eq_ignore_ascii_case("bearer") => Ok(token.trim()),
+        _ => Err(Error::Token(
+            "Expected an `Authorization: Bearer <token>` header".to_string(),
+        )),
+    }
+}
+
+/// The algorithms that can verify signatures with a key of the type of `params`.
+fn key_algorithms(params: &AlgorithmParameters) -> &'static [Algorithm] {
+    use Algorithm::*;
+    match params {
+        AlgorithmParameters::RSA(_) => &[RS256, RS384, RS512, PS256, PS384, PS512],
+        AlgorithmParameters::EllipticCurve(ec) => match ec.curve {
+            EllipticCurve::P256 => &[ES256],
+            EllipticCurve::P384 => &[ES384],
+            _ => &[],
+        },
+        AlgorithmParameters::OctetKeyPair(okp) if okp.curve == EllipticCurve::Ed25519 => &[EdDSA],
+        AlgorithmParameters::OctetKey(_) => &[HS256, HS384, HS512],
+        _ => &[],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cedar_policy::{Authorizer, Decision, PolicySet};
+    use jsonwebtoken::{EncodingKey, Header};
+    use serde_json::json;
+
+    use super::*;
+
+    const SECRET: &str = "secret";
+
+    fn schema() -> Schema {
+        Schema::from_str(
+            r#"
+            entity Group;
+            entity User in [Group] = { "email": String, "roles": Set<String> };
+            entity Document;
+            action Read appliesTo {
+                principal: [User],
+                resource: [Document],
+                context: { "mfa": Bool }
+            };
+            "#,
+        )
+        .unwrap()
+    }
+
+    fn mapper(keys: ClaimsKeys) -> ClaimsMapper {
+        ClaimsMapper::new(
+            serde_json::from_value(json!({
+                "keys": keys,
+                "issuer": "https://idp.example.com",
+                "principal_type": "User",
+                "attributes": { "email": "/email", "roles": "/realm_access/roles" },
+                "context": { "mfa": "/mfa" },
+                "parents": [{
+                    "type": "Group",
+                    "claim": "/groups",
+                    "values": { "/org/admins": "admins" }
+                }]
+            }))
+            .unwrap(),
+        )
+        .unwrap()
+    }
+
+    fn token(header: &Header, key: &EncodingKey, issuer: &str) -> String {
+        let claims = json!({
+            "sub": "alice",
+            "iss": issuer,
+            "exp": chrono::Utc::now().timestamp() + 60,
+            "email": "alice@example.com",
+            "realm_access": { "roles": ["admin"] },
+            "mfa": true,
+            "groups": ["/org/admins", "eng"]
+        });
+        jsonwebtoken::encode(header, &claims, key).unwrap()
+    }
+
+    #[test]
+    fn test_to_request() {
+        let mapper = mapper(ClaimsKeys::Hs256 {
+            secret: SECRET.to_string(),
+        });
+        let key = EncodingKey::from_secret(SECRET.as_bytes());
+        let token = token(&Header::default(), &key, "https://idp.example.com");
+
+        let ClaimsRequest { request, principal } = mapper
+            .to_request(
+                &format!("Bearer {token}"),
+                EntityUid::from_str(r#"Action::"Read""#).unwrap(),
+                EntityUid::from_str(r#"Document::"0""#).unwrap(),
+                &schema(),
+            )
+            .unwrap();
+        assert_eq!(request.principal().unwrap().to_string(), r#"User::"alice""#);
+        assert_eq!(
+            request.context().unwrap().get("mfa").unwrap().to_string(),
+            "true"
+        );
+        assert_eq!(
+            principal.attr("email").unwrap().unwrap().to_string(),
+            r#""alice@example.com""#
+        );
+    }
+
+    #[test]
+    fn test_group_parents() {
+        let mapper = mapper(ClaimsKeys::Hs256 {
+            secret: SECRET.to_string(),
+        });
+        let key = EncodingKey::from_secret(SECRET.as_bytes());
+        let token = token(&Header::default(), &key, "https://idp.example.com");
+        let schema = schema();
+        let claims_request = mapper
+            .to_request(
+                &format!("Bearer {token}"),
+                EntityUid::from_str(r#"Action::"Read""#).unwrap(),
+                EntityUid::from_str(r#"Document::"0""#).unwrap(),
+                &schema,
+            )
+            .unwrap();
+        assert_eq!(
+            mapper.parents(&mapper.verify(&token).unwrap()).unwrap(),
+            vec![
+                EntityUid::from_str(r#"Group::"admins""#).unwrap(),
+                EntityUid::from_str(r#"Group::"eng""#).unwrap(),
+            ]
+        );
+
+        // The groups themselves are not in the entity store.
+        let entities = claims_request
+            .entities(&Entities::empty(), &schema)
+            .unwrap();
+        let policies =
+            PolicySet::from_str(r#"permit (principal in Group::"admins", action, resource);"#)
+                .unwrap();
+        let response =
+            Authorizer::new().is_authorized(&claims_request.request, &policies, &entities);
+        assert_eq!(response.decision(), Decision::Allow);
+    }
+
+    #[test]
+    fn test_rejects_invalid_tokens() {
+        let mapper = mapper(ClaimsKeys::Hs256 {
+            secret: SECRET.to_string(),
+        });
+        let wrong_issuer = token(
+            &Header::default(),
+            &EncodingKey::from_secret(SECRET.as_bytes()),
+            "https://other.example.com",
+        );
+        assert!(matches!(mapper.verify(&wrong_issuer), Err(Error::Token(_))));
+        let wrong_key = token(
+            &Header::default(),
+            &EncodingKey::from_secret(b"other"),
+            "https://idp.example.com",
+        );
+        assert!(matches!(mapper.verify(&wrong_key), Err(Error::Token(_))));
+        assert!(bearer_token("Basic abc").is_err());
+    }
+
+    #[test]
+    fn test_jwks() {
+        let key = EncodingKey::from_secret(SECRET.as_bytes());
+        let mut jwk = jsonwebtoken::jwk::Jwk::from_encoding_key(&key, Algorithm::HS256).unwrap();
+        jwk.common.key_id = Some("k1".to_string());
+        let mapper = mapper(ClaimsKeys::Jwks(JwkSet { keys: vec![jwk] }));
+
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some("k1".to_string());
+        let claims = mapper
+            .verify(&token(&header, &key, "https://idp.example.com"))
+            .unwrap();
+        assert_eq!(claims["sub"], "alice");
+
+        header.kid = Some("k2".to_string());
+        assert!(
+            mapper
+                .verify(&token(&header, &key, "https://idp.example.com"))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_jwks_algorithm_mismatch() {
+        let key = EncodingKey::from_secret(SECRET.as_bytes());
+        let mut jwk = jsonwebtoken::jwk::Jwk::from_encoding_key(&key, Algorithm::HS256).unwrap();
+        jwk.common.key_id = Some("k1".to_string());
+        let mut header = Header::new(Algorithm::HS384);
+        header.kid = Some("k1".to_string());
+        let hs384 = token(&header, &key, "https://idp.example.com");
+
+        // A key with an algorithm only verifies that algorithm.
+        let with_alg = mapper(ClaimsKeys::Jwks(JwkSet {
+            keys: vec![jwk.clone()],
+        }));
+        assert!(matches!(with_alg.verify(&hs384), Err(Error::Token(_))));
+
+        // A key without one verifies the algorithms of its type.
+        jwk.common.key_algorithm = None;
+        let without_alg = mapper(ClaimsKeys::Jwks(JwkSet { keys: vec![jwk] }));
+        assert_eq!(without_alg.verify(&hs384).unwrap()["sub"], "alice");
+        // An RS256 header on an HMAC key, i.e. `{"alg":"RS256","kid":"k1"}`.
+        let (_, rest) = hs384.split_once('.').unwrap();
+        let rs256 = format!("eyJhbGciOiJSUzI1NiIsImtpZCI6ImsxIn0.{rest}");
+        assert!(
+            matches!(without_alg.verify(&rs256), Err(Error::Token(e)) if e.contains("cannot verify"))
+        );
+    }
+}
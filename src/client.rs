@@ -0,0 +1,445 @@
+//! Clients for a remote policy decision point that implement [`Pdp`] like the in-process
+//! [`Engine`](crate::Engine): [`HttpClient`] for the routes of `server`, and `GrpcClient` for
+//! the gRPC service if the `grpc` feature is enabled.
+//!
+//! Residuals are fetched in Cedar's JSON policy format with the `false` policies included, so
+//! they are the residuals the remote engine computed.
+
+use std::sync::{
+    Arc,
+    atomic::{AtomicU64, Ordering},
+};
+
+use cedar_policy::{Context, Decision, Entities, EntityUid, PolicyId, Request};
+use reqwest::Method;
+use serde::{Deserialize, de::DeserializeOwned};
+use serde_json::{Value, json};
+
+use crate::{
+    Error, Residuals, Result,
+    audit::context_json,
+    pdp::{Authorization, PartialUid, Pdp},
+};
+
+// The headers of `crate::server`, which clients can be built without.
+const REVISION: &str = "cedar-revision";
+const MIN_REVISION: &str = "cedar-min-revision";
+
+fn remote(e: impl std::error::Error + Send + Sync + 'static) -> Error {
+    Error::Remote(Box::new(e))
+}
+
+fn required<'a>(uid: Option<&'a EntityUid>, field: &str) -> Result<&'a EntityUid> {
+    uid.ok_or_else(|| Error::Mapping(format!("The `{field}` of a request must be known")))
+}
+
+fn reasons(reasons: Vec<String>) -> Vec<PolicyId> {
+    reasons.into_iter().map(PolicyId::new).collect()
+}
+
+/// A client for the HTTP API served by [`crate::server::router`].
+#[derive(Debug, Clone)]
+pub struct HttpClient {
+    http: reqwest::Client,
+    base: String,
+    /// The highest revision seen, shared by clones, in read-your-writes mode.
+    revision: Option<Arc<AtomicU64>>,
+}
+
+#[derive(Debug, Deserialize)]
+enum AnswerDecision {
+    Allow,
+    Deny,
+}
+
+#[derive(Debug, Deserialize)]
+struct Answer {
+    decision: AnswerDecision,
+    diagnostics: AnswerDiagnostics,
+}
+
+#[derive(Debug, Deserialize)]
+struct AnswerDiagnostics {
+    reason: Vec<String>,
+    errors: Vec<String>,
+}
+
+impl From<Answer> for Authorization {
+    fn from(answer: Answer) -> Self {
+        Self {
+            decision: match answer.decision {
+                AnswerDecision::Allow => Decision::Allow,
+                AnswerDecision::Deny => Decision::Deny,
+            },
+            reasons: reasons(answer.diagnostics.reason),
+            errors: answer.diagnostics.errors,
+        }
+    }
+}
+
+impl HttpClient {
+    /// `base` is the URL the `/v1` routes are served under, e.g. `http://localhost:8180`.
+    pub fn new(base: impl Into<String>) -> Self {
+        Self::with_client(reqwest::Client::new(), base)
+    }
+
+    /// Like [`HttpClient::new`], with a preconfigured client, e.g. for timeouts or TLS.
+    pub fn with_client(http: reqwest::Client, base: impl Into<String>) -> Self {
+        let base = base.into().trim_end_matches('/').to_string();
+        Self {
+            http,
+            base,
+            revision: None,
+        }
+    }
+
+    /// Guarantee that requests see the writes made through this client and its clones before:
+    /// each request waits until the server installed the revision of the latest response.
+    #[must_use]
+    pub fn read_your_writes(mut self) -> Self {
+        self.revision = Some(Arc::default());
+        self
+    }
+
+    /// Replace the entities of the server, through `PUT /v1/data`.
+    pub async fn put_entities(&self, entities: &Entities) -> Result<()> {
+        let mut json = Vec::new();
+        entities.write_to_json(&mut json)?;
+        let json = serde_json::from_slice(&json).map_err(remote)?;
+        let _: Value = self.send(Method::PUT, "/v1/data", json).await?;
+        Ok(())
+    }
+
+    async fn post<T: DeserializeOwned>(&self, path: &str, body: Value) -> Result<T> {
+        self.send(Method::POST, path, body).await
+    }
+
+    async fn send<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Value,
+    ) -> Result<T> {
+        let mut request = self
+            .http
+            .request(method, format!("{}{path}", self.base))
+            .json(&body);
+        if let Some(revision) = &self.revision {
+            request = request.header(MIN_REVISION, revision.load(Ordering::Acquire));
+        }
+        let response = request.send().await.map_err(remote)?;
+        if let Some(revision) = &self.revision
+            && let Some(seen) = response
+                .headers()
+                .get(REVISION)
+                .and_then(|value| value.to_str().ok()?.parse().ok())
+        {
+            revision.fetch_max(seen, Ordering::AcqRel);
+        }
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.json::<Value>().await.unwrap_or_default();
+            let reason = body["reason"].as_str().unwrap_or_default();
+            return Err(Error::Remote(format!("{status}: {reason}").into()));
+        }
+        response.json().await.map_err(remote)
+    }
+
+    fn call(request: &Request) -> Result<Value> {
+        let mut call = json!({
+            "principal": required(request.principal(), "principal")?.to_string(),
+            "action": required(request.action(), "action")?.to_string(),
+            "resource": required(request.resource(), "resource")?.to_string(),
+        });
+        if let Some(context) = request.context() {
+            call["context"] = context_json(context)?;
+        }
+        Ok(call)
+    }
+}
+
+impl Pdp for HttpClient {
+    async fn is_authorized(&self, request: &Request) -> Result<Authorization> {
+        let call = Self::call(request)?;
+        let answer: Answer = self.post("/v1/is_authorized", call).await?;
+        Ok(answer.into())
+    }
+
+    async fn is_authorized_batch(&self, requests: &[Request]) -> Result<Vec<Authorization>> {
+        let calls = requests
+            .iter()
+            .map(Self::call)
+            .collect::<Result<Vec<_>>>()?;
+        let answers: Vec<Answer> = self.post("/v1/is_authorized/batch", calls.into()).await?;
+        Ok(answers.into_iter().map(Into::into).collect())
+    }
+
+    async fn tpe(
+        &self,
+        principal: PartialUid,
+        action: EntityUid,
+        resource: PartialUid,
+        context: Context,
+    ) -> Result<Residuals> {
+        let call = json!({
+            "principal": principal,
+            "action": action.to_string(),
+            "resource": resource,
+            "context": context_json(&context)?,
+        });
+        let est: Value = self.post("/v1/residuals", call).await?;
+        Residuals::from_est(&est)
+    }
+}
+
+#[cfg(feature = "grpc")]
+pub use grpc::GrpcClient;
+
+#[cfg(feature = "grpc")]
+mod grpc {
+    use tonic::transport::Channel;
+
+    use super::*;
+    use crate::grpc::proto::{self, cedar_tpe_client::CedarTpeClient};
+
+    /// A client for [`crate::grpc::GrpcService`].
+    #[derive(Debug, Clone)]
+    pub struct GrpcClient {
+        client: CedarTpeClient<Channel>,
+    }
+
+    impl GrpcClient {
+        pub fn new(channel: Channel) -> Self {
+            Self {
+                client: CedarTpeClient::new(channel),
+            }
+        }
+
+        /// Connect to `url`, e.g. `http://localhost:50051`.
+        pub async fn connect(url: impl Into<String>) -> Result<Self> {
+            let client = CedarTpeClient::connect(url.into()).await.map_err(remote)?;
+            Ok(Self { client })
+        }
+
+        fn call(request: &Request) -> Result<proto::IsAuthorizedRequest> {
+            let context = match request.context() {
+                Some(context) => context_json(context)?.to_string(),
+                None => String::new(),
+            };
+            Ok(proto::IsAuthorizedRequest {
+                principal: required(request.principal(), "principal")?.to_string(),
+                action: required(request.action(), "action")?.to_string(),
+                resource: required(request.resource(), "resource")?.to_string(),
+                context,
+                additional_entities: String::new(),
+            })
+        }
+    }
+
+    impl TryFrom<proto::IsAuthorizedResponse> for Authorization {
+        type Error = Error;
+
+        fn try_from(response: proto::IsAuthorizedResponse) -> Result<Self> {
+            let decision = match response.decision() {
+                proto::Decision::Allow => Decision::Allow,
+                proto::Decision::Deny => Decision::Deny,
+                proto::Decision::Unspecified => {
+                    return Err(Error::Remote("Response without a decision".into()));
+                }
+            };
+            Ok(Self {
+                decision,
+                reasons: reasons(response.reasons),
+                errors: response.errors,
+            })
+        }
+    }
+
+    fn partial_uid(uid: PartialUid) -> proto::PartialUid {
+        proto::PartialUid {
+            r#type: uid.entity_type.to_string(),
+            id: uid.id.map(|id| id.unescaped().to_string()),
+        }
+    }
+
+    impl Pdp for GrpcClient {
+        async fn is_authorized(&self, request: &Request) -> Result<Authorization> {
+            let call = Self::call(request)?;
+            let mut client = self.client.clone();
+            let response = client.is_authorized(call).await.map_err(remote)?;
+            response.into_inner().try_into()
+        }
+
+        async fn is_authorized_batch(&self, requests: &[Request]) -> Result<Vec<Authorization>> {
+            let requests = requests
+                .iter()
+                .map(Self::call)
+                .collect::<Result<Vec<_>>>()?;
+            let mut client = self.client.clone();
+            let response = client
+                .is_authorized_batch(proto::IsAuthorizedBatchRequest { requests })
+                .await
+                .map_err(remote)?;
+            response
+                .into_inner()
+                .responses
+                .into_iter()
+                .map(TryInto::try_into)
+                .collect()
+        }
+
+        async fn tpe(
+            &self,
+            principal: PartialUid,
+            action: EntityUid,
+            resource: PartialUid,
+            context: Context,
+        ) -> Result<Residuals> {
+            let request = proto::CompileFilterRequest {
+                request: Some(proto::TpeRequest {
+                    principal: Some(partial_uid(principal)),
+                    action: action.to_string(),
+                    resource: Some(partial_uid(resource)),
+                    context: context_json(&context)?.to_string(),
+                }),
+                target: proto::FilterTarget::Residuals.into(),
+                ..Default::default()
+            };
+            let mut client = self.client.clone();
+            let response = client.compile_filter(request).await.map_err(remote)?;
+            let est = serde_json::from_str(&response.into_inner().filter).map_err(remote)?;
+            Residuals::from_est(&est)
+        }
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use std::{str::FromStr, sync::Arc};
+
+    use cedar_policy::{Entities, PolicySet, SchemaFragment};
+
+    use super::*;
+    use crate::{CEDAR_SCHEMA, CEDAR_SCHEMA_SRC, Engine};
+
+    fn engine() -> Arc<Engine> {
+        let entities = Entities::from_json_str(
+            r#"[
+                { "uid": { "type": "MyApp::Server", "id": "0" }, "attrs": {}, "parents": [] },
+                {
+                    "uid": { "type": "MyApp::Project", "id": "0" },
+                    "attrs": {},
+                    "parents": [{ "type": "MyApp::Server", "id": "0" }]
+                }
+            ]"#,
+            Some(&CEDAR_SCHEMA),
+        )
+        .unwrap();
+        let engine = Engine::new(
+            SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(),
+            PolicySet::from_str(
+                r#"
+                permit (principal == MyApp::User::"0", action, resource in MyApp::Server::"0");
+                forbid (principal, action, resource == MyApp::Project::"1");
+                "#,
+            )
+            .unwrap(),
+            entities,
+        )
+        .unwrap();
+        Arc::new(engine)
+    }
+
+    /// The same calls against any PDP, with the residuals as a snapshot.
+    async fn answers(pdp: &impl Pdp) -> (Vec<Authorization>, String) {
+        let uid = |uid: &str| EntityUid::from_str(uid).unwrap();
+        let action = uid(r#"MyApp::Action::"GetProjectMetadata""#);
+        let request = |principal: &str| {
+            Request::new(
+                uid(principal),
+                action.clone(),
+                uid(r#"MyApp::Project::"0""#),
+                Context::empty(),
+                Some(&CEDAR_SCHEMA),
+            )
+            .unwrap()
+        };
+        let requests = [
+            request(r#"MyApp::User::"0""#),
+            request(r#"MyApp::User::"1""#),
+        ];
+        let mut authorizations = vec![pdp.is_authorized(&requests[0]).await.unwrap()];
+        authorizations.extend(pdp.is_authorized_batch(&requests).await.unwrap());
+        let residuals = pdp
+            .tpe(
+                PartialUid::unknown("MyApp::User".parse().unwrap()),
+                action.clone(),
+                uid(r#"MyApp::Project::"0""#).into(),
+                Context::empty(),
+            )
+            .await
+            .unwrap();
+        (authorizations, residuals.snapshot())
+    }
+
+    #[tokio::test]
+    async fn test_http_client() {
+        let engine = engine();
+        let expected = answers(&*engine).await;
+        assert_eq!(expected.0[0].decision, Decision::Allow);
+        assert_eq!(expected.0[0].reasons, [PolicyId::new("policy0")]);
+        assert_eq!(expected.0[2].decision, Decision::Deny);
+        // The `false` forbid is a residual remotely as well.
+        assert!(expected.1.contains("forbid policy1: false"));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(axum::serve(listener, crate::server::router(engine)).into_future());
+        let client = HttpClient::new(format!("http://{addr}/"));
+        assert_eq!(answers(&client).await, expected);
+    }
+
+    #[tokio::test]
+    async fn test_read_your_writes() {
+        let engine = engine();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(axum::serve(listener, crate::server::router(engine.clone())).into_future());
+        let client = HttpClient::new(format!("http://{addr}")).read_your_writes();
+        client.put_entities(&Entities::empty()).await.unwrap();
+        assert_eq!(engine.revision(), 1);
+        let seen = client.revision.as_ref().unwrap().load(Ordering::Acquire);
+        assert_eq!(seen, 1);
+
+        // The project is no longer in the server.
+        let request = Request::new(
+            EntityUid::from_str(r#"MyApp::User::"0""#).unwrap(),
+            EntityUid::from_str(r#"MyApp::Action::"GetProjectMetadata""#).unwrap(),
+            EntityUid::from_str(r#"MyApp::Project::"0""#).unwrap(),
+            Context::empty(),
+            None,
+        )
+        .unwrap();
+        let authorization = client.clone().is_authorized(&request).await.unwrap();
+        assert_eq!(authorization.decision, Decision::Deny);
+    }
+
+    #[cfg(feature = "grpc")]
+    #[tokio::test]
+    async fn test_grpc_client() {
+        use tonic::transport::{Server, server::TcpIncoming};
+
+        use crate::grpc::GrpcService;
+
+        let engine = engine();
+        let expected = answers(&*engine).await;
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = Server::builder()
+            .add_service(GrpcService::new(engine).into_server())
+            .serve_with_incoming(TcpIncoming::from(listener));
+        tokio::spawn(server);
+        let client = GrpcClient::connect(format!("http://{addr}")).await.unwrap();
+        assert_eq!(answers(&client).await, expected);
+    }
+}
@@ -0,0 +1,182 @@
+//! The source of the current time, so time-window policies can be tested deterministically.
+//!
+//! An engine reads the clock installed with [`crate::Engine::set_clock`] to decide which
+//! [time-bound policies](crate::activation) are active. With [`crate::Engine::inject_time`],
+//! requests are also evaluated with the current time in their context, as a `datetime`
+//! attribute policies compare against, e.g. `context.now < datetime("2026-01-01")`.
+
+use std::{
+    fmt,
+    sync::{Mutex, PoisonError},
+};
+
+use cedar_policy::{Context, Request};
+use chrono::{DateTime, TimeDelta, Utc};
+use serde_json::Value;
+
+use crate::{audit::context_json, extension::ExtensionValue};
+
+pub trait Clock: fmt::Debug + Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The system time.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock that only moves when told to.
+#[derive(Debug)]
+pub struct TestClock {
+    now: Mutex<DateTime<Utc>>,
+}
+
+impl TestClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            now: Mutex::new(now),
+        }
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.lock().unwrap_or_else(PoisonError::into_inner) = now;
+    }
+
+    pub fn advance(&self, by: TimeDelta) {
+        *self.now.lock().unwrap_or_else(PoisonError::into_inner) += by;
+    }
+}
+
+impl Clock for TestClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+// Clocks are shared with tests that move them while the engine reads them.
+impl<C: Clock + ?Sized> Clock for std::sync::Arc<C> {
+    fn now(&self) -> DateTime<Utc> {
+        C::now(self)
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct TimeInjection {
+    pub(crate) attribute: String,
+}
+
+impl TimeInjection {
+    /// `request` with `now` added to its context. `None` if the context already has the
+    /// attribute, the request has unknowns or the time cannot be represented.
+    pub(crate) fn inject(&self, request: &Request, now: DateTime<Utc>) -> Option<Request> {
+        let mut context = context_json(request.context()?).ok()?;
+        let Value::Object(attributes) = &mut context else {
+            return None;
+        };
+        if attributes.contains_key(&self.attribute) {
+            return None;
+        }
+        let now = ExtensionValue::datetime(&now).ok()?;
+        attributes.insert(self.attribute.clone(), now.to_json());
+        Request::new(
+            request.principal()?.clone(),
+            request.action()?.clone(),
+            request.resource()?.clone(),
+            Context::from_json_value(context, None).ok()?,
+            None,
+        )
+        .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{str::FromStr, sync::Arc};
+
+    use cedar_policy::{Decision, Entities, EntityUid, PolicySet, SchemaFragment};
+
+    use super::*;
+    use crate::{Engine, annotations::TagFilter};
+
+    /// An engine with a time-bound policy, injecting the time of `clock` as `now`.
+    fn time_bound(clock: impl Clock + 'static) -> (Engine, Request) {
+        let (schema, _) = SchemaFragment::from_cedarschema_str(
+            r#"
+            entity User;
+            entity Document;
+            action Read appliesTo {
+                principal: [User],
+                resource: [Document],
+                context: { "now": datetime }
+            };
+            "#,
+        )
+        .unwrap();
+        let engine = Engine::new(
+            schema,
+            PolicySet::from_str(
+                r#"@tags("time")
+                permit (principal, action, resource)
+                    when { context.now < datetime("2026-01-01") };"#,
+            )
+            .unwrap(),
+            Entities::empty(),
+        )
+        .unwrap();
+        engine.set_clock(clock);
+        engine.inject_time("now");
+        let request = Request::new(
+            EntityUid::from_str(r#"User::"0""#).unwrap(),
+            EntityUid::from_str(r#"Action::"Read""#).unwrap(),
+            EntityUid::from_str(r#"Document::"0""#).unwrap(),
+            Context::empty(),
+            None,
+        )
+        .unwrap();
+        (engine, request)
+    }
+
+    fn at(now: &str) -> TestClock {
+        TestClock::new(now.parse().unwrap())
+    }
+
+    #[test]
+    fn test_injected_time() {
+        let clock = Arc::new(at("2025-12-31T23:59:00Z"));
+        let (engine, request) = time_bound(clock.clone());
+        assert_eq!(engine.is_authorized(&request).decision(), Decision::Allow);
+        clock.advance(TimeDelta::minutes(1));
+        assert_eq!(engine.is_authorized(&request).decision(), Decision::Deny);
+
+        engine.stop_injecting_time();
+        let response = engine.is_authorized(&request);
+        assert!(response.diagnostics().errors().next().is_some());
+    }
+
+    #[test]
+    fn test_injected_time_when_filtered() {
+        let filter = TagFilter::default().include("time");
+        let (engine, request) = time_bound(at("2025-12-31T23:59:00Z"));
+        let response = engine.is_authorized_filtered(&request, &filter).unwrap();
+        assert_eq!(response.decision(), Decision::Allow);
+        let (engine, request) = time_bound(at("2026-01-01T00:00:00Z"));
+        let response = engine.is_authorized_filtered(&request, &filter).unwrap();
+        assert_eq!(response.decision(), Decision::Deny);
+        assert!(response.diagnostics().errors().next().is_none());
+    }
+
+    #[test]
+    fn test_injected_time_when_explained() {
+        let (engine, request) = time_bound(at("2025-12-31T23:59:00Z"));
+        assert_eq!(engine.explain(&request).unwrap().decision, Decision::Allow);
+        let (engine, request) = time_bound(at("2026-01-01T00:00:00Z"));
+        let trace = engine.explain(&request).unwrap();
+        assert_eq!(trace.decision, Decision::Deny);
+        assert_eq!(trace.policies[0].error, None);
+    }
+}
@@ -0,0 +1,475 @@
+//! Typed request builders generated from a schema, so that a request for an action whose
+//! `appliesTo` does not include the principal or resource type fails to compile.
+//!
+//! [`generate`] emits Rust source with a module per namespace, a struct per entity type and,
+//! in its `actions` module, a struct per action that applies to any principal and resource.
+//! Generated entity types implement [`PrincipalOf`] and [`ResourceOf`] for exactly the actions
+//! that accept them. A build script typically writes the source to `OUT_DIR`:
+//!
+//! ```no_run
+//! # let schema = cedar_test::CEDAR_SCHEMA.clone();
+//! let out = std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap());
+//! std::fs::write(out.join("cedar.rs"), cedar_test::codegen::generate(&schema).unwrap()).unwrap();
+//! ```
+//!
+//! and the crate includes it with `include!(concat!(env!("OUT_DIR"), "/cedar.rs"))`:
+//!
+//! ```
+//! # mod cedar {
+//! #     include!(concat!(env!("CARGO_MANIFEST_DIR"), "/src/resources/example_requests.rs"));
+//! # }
+//! use cedar::my_app::{Project, User, actions::GetProjectMetadata};
+//!
+//! let request = GetProjectMetadata::request()
+//!     .principal(User::new("0"))
+//!     .resource(Project::new("0"))
+//!     .build(Some(&cedar_test::CEDAR_SCHEMA))
+//!     .unwrap();
+//! ```
+//!
+//! ```compile_fail
+//! # mod cedar {
+//! #     include!(concat!(env!("CARGO_MANIFEST_DIR"), "/src/resources/example_requests.rs"));
+//! # }
+//! use cedar::my_app::{Server, User, actions::GetProjectMetadata};
+//!
+//! // `GetProjectMetadata` applies to projects only.
+//! let request = GetProjectMetadata::request()
+//!     .principal(User::new("0"))
+//!     .resource(Server::new("0"));
+//! ```
+//!
+//! Contexts are not typed; [`TypedRequest::build`] validates them against the schema.
+//!
+//! [`embed_schema`] moves parsing and validating the schema itself to the build as well. Its
+//! source defines an [`EmbeddedSchema`], so an invalid schema fails the build instead of the
+//! first use at runtime:
+//!
+//! ```no_run
+//! # let out = std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap());
+//! println!("cargo::rerun-if-changed=schema.cedarschema");
+//! let src = std::fs::read_to_string("schema.cedarschema").unwrap();
+//! std::fs::write(out.join("schema.rs"), cedar_test::codegen::embed_schema(&src).unwrap()).unwrap();
+//! ```
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+    marker::PhantomData,
+    sync::{Arc, OnceLock},
+};
+
+use cedar_policy::{
+    Context, Entities, EntityId, EntityTypeName, EntityUid, PolicySet, Request, Schema,
+    SchemaFragment,
+};
+
+use crate::{
+    engine::EngineState,
+    error::{Error, Result},
+};
+
+/// A generated entity type.
+pub trait TypedEntity {
+    fn uid(&self) -> &EntityUid;
+}
+
+/// A generated action.
+pub trait TypedAction {
+    const TYPE: &'static str;
+    const ID: &'static str;
+
+    fn uid() -> EntityUid {
+        uid(Self::TYPE, Self::ID)
+    }
+}
+
+/// Implemented by the principal types `A` applies to.
+pub trait PrincipalOf<A: TypedAction>: TypedEntity {}
+
+/// Implemented by the resource types `A` applies to.
+pub trait ResourceOf<A: TypedAction>: TypedEntity {}
+
+#[doc(hidden)]
+pub fn uid(type_name: &str, id: &str) -> EntityUid {
+    let type_name = type_name
+        .parse::<EntityTypeName>()
+        .expect("generated type names are valid");
+    EntityUid::from_type_name_and_id(type_name, EntityId::new(id))
+}
+
+/// A principal or resource that was not set yet.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Unset;
+
+/// A request for the action `A`. [`TypedRequest::build`] is only available once a principal
+/// and a resource the action applies to are set.
+#[derive(Debug, Clone)]
+pub struct TypedRequest<A, P = Unset, R = Unset> {
+    principal: P,
+    resource: R,
+    context: Context,
+    action: PhantomData<A>,
+}
+
+impl<A: TypedAction> TypedRequest<A> {
+    pub fn new() -> Self {
+        Self {
+            principal: Unset,
+            resource: Unset,
+            context: Context::empty(),
+            action: PhantomData,
+        }
+    }
+}
+
+impl<A: TypedAction> Default for TypedRequest<A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<A: TypedAction, R> TypedRequest<A, Unset, R> {
+    pub fn principal<P: PrincipalOf<A>>(self, principal: P) -> TypedRequest<A, P, R> {
+        TypedRequest {
+            principal,
+            resource: self.resource,
+            context: self.context,
+            action: PhantomData,
+        }
+    }
+}
+
+impl<A: TypedAction, P> TypedRequest<A, P, Unset> {
+    pub fn resource<R: ResourceOf<A>>(self, resource: R) -> TypedRequest<A, P, R> {
+        TypedRequest {
+            principal: self.principal,
+            resource,
+            context: self.context,
+            action: PhantomData,
+        }
+    }
+}
+
+impl<A, P, R> TypedRequest<A, P, R> {
+    #[must_use]
+    pub fn context(mut self, context: Context) -> Self {
+        self.context = context;
+        self
+    }
+}
+
+impl<A: TypedAction, P: PrincipalOf<A>, R: ResourceOf<A>> TypedRequest<A, P, R> {
+    /// The request, with its context validated against `schema` if given.
+    pub fn build(self, schema: Option<&Schema>) -> Result<Request> {
+        Ok(Request::new(
+            self.principal.uid().clone(),
+            A::uid(),
+            self.resource.uid().clone(),
+            self.context,
+            schema,
+        )?)
+    }
+}
+
+const HEADER: &str = "// Generated by cedar_test::codegen from a Cedar schema. Do not edit.\n";
+
+/// A schema validated by [`embed_schema`] when the crate was built. It is embedded in the JSON
+/// format and built on first use, without parsing the Cedar syntax again.
+#[derive(Debug)]
+pub struct EmbeddedSchema {
+    json: &'static str,
+    parsed: OnceLock<(Arc<SchemaFragment>, Arc<Schema>)>,
+}
+
+impl EmbeddedSchema {
+    #[doc(hidden)]
+    pub const fn new(json: &'static str) -> Self {
+        Self {
+            json,
+            parsed: OnceLock::new(),
+        }
+    }
+
+    fn parsed(&self) -> &(Arc<SchemaFragment>, Arc<Schema>) {
+        self.parsed.get_or_init(|| {
+            let fragment = SchemaFragment::from_json_str(self.json)
+                .expect("embedded schemas were validated when they were embedded");
+            let schema = fragment
+                .clone()
+                .try_into()
+                .expect("embedded schemas were validated when they were embedded");
+            (Arc::new(fragment), Arc::new(schema))
+        })
+    }
+
+    pub fn schema(&self) -> &Schema {
+        &self.parsed().1
+    }
+
+    pub fn fragment(&self) -> &SchemaFragment {
+        &self.parsed().0
+    }
+
+    /// A state with `policies` and `entities` validated against the schema, sharing the
+    /// schema instead of building it again.
+    pub fn state(&self, policies: PolicySet, entities: Entities) -> Result<EngineState> {
+        let (fragment, schema) = self.parsed();
+        EngineState::with_schema(fragment.clone(), schema.clone(), policies, entities)
+    }
+}
+
+/// The Rust source of a `SCHEMA` static holding the schema `src` in Cedar syntax as an
+/// [`EmbeddedSchema`]. Fails if the schema does not parse or validate.
+pub fn embed_schema(src: &str) -> Result<String> {
+    let (fragment, _) = SchemaFragment::from_cedarschema_str(src)?;
+    Schema::from_schema_fragments([fragment.clone()])?;
+    let json = fragment.to_json_value()?.to_string();
+    Ok(format!(
+        "{HEADER}
+/// The schema, validated when the crate was built.
+pub static SCHEMA: ::cedar_test::codegen::EmbeddedSchema =
+    ::cedar_test::codegen::EmbeddedSchema::new({json:?});
+"
+    ))
+}
+
+#[derive(Default)]
+struct Module {
+    items: Vec<String>,
+    names: BTreeSet<String>,
+    children: BTreeMap<String, Module>,
+}
+
+impl Module {
+    fn get(&mut self, path: &[String]) -> &mut Module {
+        path.iter().fold(self, |module, name| {
+            module.children.entry(name.clone()).or_default()
+        })
+    }
+
+    fn add(&mut self, name: &str, item: String, of: &str) -> Result<()> {
+        if !self.names.insert(name.to_string()) {
+            return Err(Error::Mapping(format!(
+                "`{of}` and another name both generate `{name}`"
+            )));
+        }
+        self.items.push(item);
+        Ok(())
+    }
+
+    fn write(&self, out: &mut String, depth: usize) {
+        let indent = "    ".repeat(depth);
+        let mut first = true;
+        for item in &self.items {
+            if !std::mem::take(&mut first) {
+                out.push('\n');
+            }
+            for line in item.lines() {
+                match line {
+                    "" => out.push('\n'),
+                    line => {
+                        let _ = writeln!(out, "{indent}{line}");
+                    }
+                }
+            }
+        }
+        for (name, child) in &self.children {
+            if !std::mem::take(&mut first) {
+                out.push('\n');
+            }
+            let _ = writeln!(out, "{indent}pub mod {} {{", ident(name));
+            child.write(out, depth + 1);
+            let _ = writeln!(out, "{indent}}}");
+        }
+    }
+}
+
+/// The Rust source of typed request builders for `schema`. Fails if a name is not a valid
+/// Rust identifier once converted, or two names convert to the same identifier.
+pub fn generate(schema: &Schema) -> Result<String> {
+    let mut root = Module::default();
+    let mut entity_paths = BTreeMap::new();
+    let mut entity_types = schema.entity_types().collect::<Vec<_>>();
+    entity_types.sort_by_key(|name| name.to_string());
+    for type_name in entity_types {
+        let path = namespace(type_name);
+        let name = camel_case(type_name.basename(), &type_name.to_string())?;
+        let item = format!(
+            "/// `{type_name}`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct {name}(::cedar_policy::EntityUid);
+
+impl {name} {{
+    pub fn new(id: &str) -> Self {{
+        Self(::cedar_test::codegen::uid({type_str:?}, id))
+    }}
+}}
+
+impl ::cedar_test::codegen::TypedEntity for {name} {{
+    fn uid(&self) -> &::cedar_policy::EntityUid {{
+        &self.0
+    }}
+}}",
+            type_str = type_name.to_string(),
+        );
+        root.get(&path).add(&name, item, &type_name.to_string())?;
+        entity_paths.insert(type_name.to_string(), (path, name));
+    }
+
+    let mut actions = schema.actions().collect::<Vec<_>>();
+    actions.sort();
+    for action in actions {
+        let sorted = |types: &mut dyn Iterator<Item = &EntityTypeName>| {
+            types.map(ToString::to_string).collect::<BTreeSet<_>>()
+        };
+        let principals = sorted(&mut schema.principals_for_action(action).into_iter().flatten());
+        let resources = sorted(&mut schema.resources_for_action(action).into_iter().flatten());
+        if principals.is_empty() || resources.is_empty() {
+            continue;
+        }
+        let mut path = namespace(action.type_name());
+        path.push("actions".to_string());
+        let id = action.id().unescaped();
+        let name = camel_case(id, &action.to_string())?;
+        let mut item = format!(
+            "/// `{action}`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct {name};
+
+impl {name} {{
+    pub fn request() -> ::cedar_test::codegen::TypedRequest<Self> {{
+        ::cedar_test::codegen::TypedRequest::new()
+    }}
+}}
+
+impl ::cedar_test::codegen::TypedAction for {name} {{
+    const TYPE: &'static str = {type_str:?};
+    const ID: &'static str = {id:?};
+}}
+",
+            type_str = action.type_name().to_string(),
+        );
+        let relative = |type_name: &str| {
+            let (entity_path, entity) = &entity_paths[type_name];
+            let mut segments = vec!["super".to_string(); path.len()];
+            segments.extend(entity_path.iter().map(|s| ident(s)));
+            segments.push(entity.clone());
+            segments.join("::")
+        };
+        for (role, types) in [("PrincipalOf", &principals), ("ResourceOf", &resources)] {
+            for type_name in types {
+                let _ = write!(
+                    item,
+                    "\nimpl ::cedar_test::codegen::{role}<{name}> for {} {{}}",
+                    relative(type_name)
+                );
+            }
+        }
+        root.get(&path).add(&name, item, &action.to_string())?;
+    }
+
+    let mut out = HEADER.to_string();
+    out.push('\n');
+    root.write(&mut out, 0);
+    Ok(out)
+}
+
+/// The module path of the namespace of `type_name`, in snake case.
+fn namespace(type_name: &EntityTypeName) -> Vec<String> {
+    type_name.namespace_components().map(snake_case).collect()
+}
+
+fn snake_case(name: &str) -> String {
+    let mut out = String::new();
+    let mut previous_lower = false;
+    for c in name.chars() {
+        if c.is_ascii_uppercase() && previous_lower {
+            out.push('_');
+        }
+        previous_lower = c.is_ascii_lowercase() || c.is_ascii_digit();
+        out.push(if c.is_ascii_alphanumeric() {
+            c.to_ascii_lowercase()
+        } else {
+            '_'
+        });
+    }
+    out
+}
+
+fn camel_case(name: &str, of: &str) -> Result<String> {
+    let out = name
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .map(|part| part[..1].to_ascii_uppercase() + &part[1..])
+        .collect::<String>();
+    if !out.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return Err(Error::Mapping(format!(
+            "`{of}` does not start with a letter, so it has no Rust name"
+        )));
+    }
+    if out == "Self" {
+        return Err(Error::Mapping(format!("`{of}` has no Rust name")));
+    }
+    Ok(out)
+}
+
+/// `name` as an identifier, escaping keywords.
+fn ident(name: &str) -> String {
+    const KEYWORDS: &[&str] = &[
+        "as", "async", "await", "break", "const", "continue", "dyn", "else", "enum", "extern",
+        "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut",
+        "pub", "ref", "return", "static", "struct", "trait", "true", "type", "unsafe", "use",
+        "where", "while", "gen", "abstract", "become", "box", "do", "final", "macro", "override",
+        "priv", "typeof", "unsized", "virtual", "yield", "try",
+    ];
+    if KEYWORDS.contains(&name) {
+        format!("r#{name}")
+    } else {
+        name.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CEDAR_SCHEMA;
+
+    #[test]
+    fn test_generate() {
+        let source = generate(&CEDAR_SCHEMA).unwrap();
+        // The checked-in example is compiled by the module documentation.
+        assert_eq!(source, include_str!("resources/example_requests.rs"));
+        assert!(
+            source.contains("ResourceOf<GetProjectMetadata> for super::super::my_app::Project {}")
+        );
+        assert!(!source.contains("ServerActions"));
+        assert_eq!(snake_case("MyApp"), "my_app");
+        assert_eq!(camel_case("read file", "").unwrap(), "ReadFile");
+        assert!(camel_case("2fa", "").is_err());
+    }
+
+    #[test]
+    fn test_embed_schema() {
+        let source = embed_schema(crate::CEDAR_SCHEMA_SRC).unwrap();
+        assert!(source.contains("pub static SCHEMA: ::cedar_test::codegen::EmbeddedSchema"));
+        // The string literal in the source is the embedded JSON.
+        let literal = source
+            .split_once("new(")
+            .unwrap()
+            .1
+            .rsplit_once(");")
+            .unwrap()
+            .0;
+        let json = serde_json::from_str::<String>(literal).unwrap();
+        let embedded = EmbeddedSchema::new(json.leak());
+        assert_eq!(
+            embedded.schema().actions().count(),
+            CEDAR_SCHEMA.actions().count()
+        );
+        let state = embedded.state(PolicySet::new(), Entities::empty()).unwrap();
+        assert!(std::ptr::eq(state.schema(), embedded.schema()));
+
+        assert!(embed_schema("entity User in [Group];").is_err());
+    }
+}
@@ -0,0 +1,336 @@
+//! Ahead-of-time compiled policies, for fast cold starts.
+//!
+//! [`compile`] encodes the already validated schema and policies of a state as CBOR. [`load`]
+//! restores the state without validating the policies again, which is most of the startup cost.
+//! Policies are stored as a single Cedar text, which Cedar parses faster than the JSON format or
+//! many separate policies. A checksum guards against loading a corrupted blob unvalidated.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    str::FromStr,
+    sync::Arc,
+};
+
+use cedar_policy::{
+    ActionConstraint, Entities, EntityUid, PolicyId, PolicySet, Schema, SchemaFragment, SlotId,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    engine::EngineState,
+    error::{Error, Result},
+    namespace::id_str,
+};
+
+pub const FORMAT_VERSION: u32 = 1;
+
+const MAGIC: &[u8; 8] = b"CEDARBIN";
+const CHECKSUM_LEN: usize = 32;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CompileOptions {
+    /// Precompute which policies apply to each action of the schema.
+    pub action_index: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Blob {
+    format_version: u32,
+    schema: Value,
+    /// Templates followed by static policies. Cedar names them `policy0`, `policy1`, ... when
+    /// parsing, in the order of `ids`.
+    source: String,
+    ids: Vec<String>,
+    links: Vec<Link>,
+    action_index: Option<BTreeMap<String, Vec<String>>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Link {
+    template_id: String,
+    id: String,
+    principal: Option<String>,
+    resource: Option<String>,
+}
+
+/// A state restored by [`load`].
+#[derive(Debug, Clone)]
+pub struct Compiled {
+    pub state: EngineState,
+    pub action_index: Option<ActionIndex>,
+}
+
+/// The policies whose action scope can match each action of the schema. Policy conditions are
+/// not considered, so an indexed policy may still not apply to a request.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ActionIndex(BTreeMap<EntityUid, BTreeSet<PolicyId>>);
+
+impl ActionIndex {
+    pub fn build(schema: &Schema, policies: &PolicySet) -> Result<Self> {
+        let actions = schema.action_entities()?;
+        let index = schema
+            .actions()
+            .map(|action| {
+                let ancestors = actions
+                    .ancestors(action)
+                    .into_iter()
+                    .flatten()
+                    .collect::<BTreeSet<_>>();
+                let applicable = policies
+                    .policies()
+                    .filter(|policy| match policy.action_constraint() {
+                        ActionConstraint::Any => true,
+                        ActionConstraint::Eq(uid) => uid == *action,
+                        ActionConstraint::In(uids) => uids
+                            .iter()
+                            .any(|uid| uid == action || ancestors.contains(uid)),
+                    })
+                    .map(|policy| policy.id().clone())
+                    .collect();
+                (action.clone(), applicable)
+            })
+            .collect();
+        Ok(Self(index))
+    }
+
+    /// The policies that may apply to `action`, or `None` if the action is not in the schema.
+    pub fn policies(&self, action: &EntityUid) -> Option<&BTreeSet<PolicyId>> {
+        self.0.get(action)
+    }
+}
+
+/// Encode the validated policies and schema of `state`.
+pub fn compile(state: &EngineState, options: CompileOptions) -> Result<Vec<u8>> {
+    let action_index = options
+        .action_index
+        .then(|| ActionIndex::build(state.schema(), state.policies()))
+        .transpose()?
+        .map(|index| {
+            index
+                .0
+                .into_iter()
+                .map(|(action, ids)| {
+                    let ids = ids.iter().map(|id| id_str(id).to_string()).collect();
+                    (action.to_string(), ids)
+                })
+                .collect()
+        });
+    let policies = state.policies();
+    let statics = policies.policies().filter(|p| p.is_static());
+    let mut source = String::new();
+    let mut ids = Vec::new();
+    for (id, text) in policies
+        .templates()
+        .map(|t| (t.id(), t.to_string()))
+        .chain(statics.map(|p| (p.id(), p.to_string())))
+    {
+        source.push_str(&text);
+        source.push('\n');
+        ids.push(id_str(id).to_string());
+    }
+    let links = policies
+        .policies()
+        .filter_map(|policy| {
+            let template_id = policy.template_id()?;
+            let slots = policy.template_links()?;
+            let slot = |slot: SlotId| slots.get(&slot).map(ToString::to_string);
+            Some(Link {
+                template_id: id_str(template_id).to_string(),
+                id: id_str(policy.id()).to_string(),
+                principal: slot(SlotId::principal()),
+                resource: slot(SlotId::resource()),
+            })
+        })
+        .collect();
+    let blob = Blob {
+        format_version: FORMAT_VERSION,
+        schema: state.schema_fragment().clone().to_json_value()?,
+        source,
+        ids,
+        links,
+        action_index,
+    };
+    let mut body = Vec::new();
+    ciborium::into_writer(&blob, &mut body).map_err(|e| Error::Compiled(e.to_string()))?;
+    Ok([MAGIC.as_slice(), Sha256::digest(&body).as_slice(), &body].concat())
+}
+
+/// Restore a state from a blob produced by [`compile`]. Only `entities` are validated.
+pub fn load(blob: &[u8], entities: Entities) -> Result<Compiled> {
+    let body = blob
+        .strip_prefix(MAGIC.as_slice())
+        .ok_or_else(|| Error::Compiled("Not a compiled policy blob".to_string()))?;
+    if body.len() < CHECKSUM_LEN {
+        return Err(Error::Compiled("Truncated blob".to_string()));
+    }
+    let (checksum, body) = body.split_at(CHECKSUM_LEN);
+    if Sha256::digest(body).as_slice() != checksum {
+        return Err(Error::Compiled("Checksum mismatch".to_string()));
+    }
+    let blob =
+        ciborium::from_reader::<Blob, _>(body).map_err(|e| Error::Compiled(e.to_string()))?;
+    if blob.format_version != FORMAT_VERSION {
+        return Err(Error::Compiled(format!(
+            "Unsupported format version {}",
+            blob.format_version
+        )));
+    }
+
+    let fragment = SchemaFragment::from_json_value(blob.schema)?;
+    let schema = fragment.clone().try_into()?;
+    let policies = policy_set(&blob.source, blob.ids, blob.links)?;
+    let action_index = blob
+        .action_index
+        .map(|index| {
+            index
+                .into_iter()
+                .map(|(action, ids)| {
+                    let action = EntityUid::from_str(&action)
+                        .map_err(|e| Error::Compiled(format!("Invalid action `{action}`: {e}")))?;
+                    Ok((action, ids.into_iter().map(PolicyId::new).collect()))
+                })
+                .collect::<Result<_>>()
+                .map(ActionIndex)
+        })
+        .transpose()?;
+    let state =
+        EngineState::prevalidated(Arc::new(fragment), Arc::new(schema), policies, entities)?;
+    Ok(Compiled {
+        state,
+        action_index,
+    })
+}
+
+fn policy_set(source: &str, ids: Vec<String>, links: Vec<Link>) -> Result<PolicySet> {
+    let parsed = PolicySet::from_str(source)?;
+    let mut policies = PolicySet::new();
+    for (i, id) in ids.into_iter().enumerate() {
+        let parsed_id = PolicyId::new(format!("policy{i}"));
+        let id = PolicyId::new(id);
+        if let Some(template) = parsed.template(&parsed_id) {
+            policies.add_template(template.new_id(id))?;
+        } else if let Some(policy) = parsed.policy(&parsed_id) {
+            policies.add(policy.new_id(id))?;
+        } else {
+            return Err(Error::Compiled(format!("Missing policy `{id}`")));
+        }
+    }
+    for link in links {
+        let uid = |uid: Option<String>| {
+            uid.map(|uid| {
+                EntityUid::from_str(&uid)
+                    .map_err(|e| Error::Compiled(format!("Invalid entity UID `{uid}`: {e}")))
+            })
+            .transpose()
+        };
+        let slots = [
+            (SlotId::principal(), uid(link.principal)?),
+            (SlotId::resource(), uid(link.resource)?),
+        ]
+        .into_iter()
+        .filter_map(|(slot, uid)| Some((slot, uid?)))
+        .collect::<HashMap<_, _>>();
+        policies.link(
+            PolicyId::new(link.template_id),
+            PolicyId::new(link.id),
+            slots,
+        )?;
+    }
+    Ok(policies)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CEDAR_SCHEMA_SRC;
+
+    fn state() -> EngineState {
+        let mut policies = PolicySet::from_str(
+            r#"
+            permit (principal, action == MyApp::Action::"GetProjectMetadata", resource);
+            @id("any")
+            permit (principal, action, resource);
+            permit (principal == ?principal, action, resource in ?resource);
+            "#,
+        )
+        .unwrap();
+        policies
+            .link(
+                PolicyId::new("policy2"),
+                PolicyId::new("owner-0"),
+                HashMap::from([
+                    (
+                        SlotId::principal(),
+                        EntityUid::from_str(r#"MyApp::User::"0""#).unwrap(),
+                    ),
+                    (
+                        SlotId::resource(),
+                        EntityUid::from_str(r#"MyApp::Server::"0""#).unwrap(),
+                    ),
+                ]),
+            )
+            .unwrap();
+        EngineState::new(
+            SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(),
+            policies,
+            Entities::empty(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_compile_and_load() {
+        let blob = compile(&state(), CompileOptions { action_index: true }).unwrap();
+        let compiled = load(&blob, Entities::empty()).unwrap();
+        for policy in state().policies().policies() {
+            let loaded = compiled.state.policies().policy(policy.id()).unwrap();
+            assert_eq!(loaded.to_json().unwrap(), policy.to_json().unwrap());
+        }
+        assert!(
+            compiled
+                .state
+                .policies()
+                .template(&PolicyId::new("policy2"))
+                .is_some()
+        );
+
+        let index = compiled.action_index.unwrap();
+        let get = EntityUid::from_str(r#"MyApp::Action::"GetProjectMetadata""#).unwrap();
+        assert_eq!(
+            index.policies(&get).unwrap(),
+            &BTreeSet::from([
+                PolicyId::new("owner-0"),
+                PolicyId::new("policy0"),
+                PolicyId::new("policy1")
+            ])
+        );
+        let other = state()
+            .schema()
+            .actions()
+            .find(|action| **action != get)
+            .unwrap()
+            .clone();
+        assert_eq!(
+            index.policies(&other).unwrap(),
+            &BTreeSet::from([PolicyId::new("owner-0"), PolicyId::new("policy1")])
+        );
+    }
+
+    #[test]
+    fn test_rejects_corrupted_blob() {
+        let mut blob = compile(&state(), CompileOptions::default()).unwrap();
+        assert!(
+            load(&blob, Entities::empty())
+                .unwrap()
+                .action_index
+                .is_none()
+        );
+        *blob.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            load(&blob, Entities::empty()),
+            Err(Error::Compiled(_))
+        ));
+    }
+}
@@ -0,0 +1,327 @@
+//! Checks a context against the context type of an action in the schema and reports every
+//! mismatch with the path of the offending value, e.g. "`mfa` expected Bool, got String at
+//! $.mfa". Cedar fails a request on the first mismatch and without a path.
+//!
+//! Contexts are given in Cedar's JSON format, as for [`cedar_policy::Context::from_json_value`].
+//! Extension values may be given as strings, which Cedar parses with the schema; their
+//! arguments are checked by Cedar only.
+
+use std::fmt;
+
+use cedar_policy::{EntityUid, SchemaFragment};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::error::{Error, Result};
+
+const EXTENSIONS: &[&str] = &["ipaddr", "decimal", "datetime", "duration"];
+
+/// A value that does not match the context type.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldError {
+    /// A JSON path such as `$.user.roles[0]`.
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {}", self.message, self.path)
+    }
+}
+
+/// The mismatches of `context` against the context type of `action` in `schema`, empty if
+/// the context matches.
+pub fn validate_context(
+    schema: &SchemaFragment,
+    action: &EntityUid,
+    context: &Value,
+) -> Result<Vec<FieldError>> {
+    let schema = schema.clone().to_json_value()?;
+    let namespace = action.type_name().namespace();
+    let applies_to = schema
+        .get(&namespace)
+        .and_then(|ns| ns.get("actions"))
+        .and_then(|actions| actions.get(action.id().unescaped()))
+        .ok_or_else(|| Error::NotFound(format!("Action `{action}`")))?
+        .get("appliesTo");
+    let empty = Value::Object(Map::from_iter([(
+        "type".to_string(),
+        Value::from("Record"),
+    )]));
+    let context_type = applies_to
+        .and_then(|applies_to| applies_to.get("context"))
+        .unwrap_or(&empty);
+    let mut checker = Checker {
+        schema: &schema,
+        errors: Vec::new(),
+    };
+    checker.check(context, context_type, &namespace, "$", "context");
+    Ok(checker.errors)
+}
+
+/// A type of the JSON schema format, with common types resolved.
+enum Type<'a> {
+    Bool,
+    Long,
+    String,
+    Set(&'a Value),
+    Record(&'a Value),
+    Entity(String),
+    Extension(String),
+}
+
+impl fmt::Display for Type<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Bool => f.write_str("Bool"),
+            Type::Long => f.write_str("Long"),
+            Type::String => f.write_str("String"),
+            Type::Set(_) => f.write_str("Set"),
+            Type::Record(_) => f.write_str("Record"),
+            Type::Entity(name) | Type::Extension(name) => f.write_str(name),
+        }
+    }
+}
+
+struct Checker<'a> {
+    schema: &'a Value,
+    errors: Vec<FieldError>,
+}
+
+impl<'a> Checker<'a> {
+    fn check(&mut self, value: &Value, ty: &'a Value, namespace: &str, path: &str, name: &str) {
+        let Some((ty, namespace)) = self.resolve(ty, namespace) else {
+            return;
+        };
+        let mismatch = |errors: &mut Vec<FieldError>| {
+            errors.push(FieldError {
+                path: path.to_string(),
+                message: format!("`{name}` expected {ty}, got {}", kind(value)),
+            });
+        };
+        match (&ty, value) {
+            (Type::Bool, Value::Bool(_)) | (Type::String, Value::String(_)) => {}
+            (Type::Long, Value::Number(n)) if n.is_i64() => {}
+            (Type::Extension(_), Value::String(_)) => {}
+            (Type::Extension(_), Value::Object(o)) if o.contains_key("__extn") => {}
+            (Type::Entity(expected), value) => match entity_type(value, true) {
+                Some(actual) if actual == expected => {}
+                Some(actual) => self.errors.push(FieldError {
+                    path: path.to_string(),
+                    message: format!("`{name}` expected {expected}, got {actual}"),
+                }),
+                None => mismatch(&mut self.errors),
+            },
+            (Type::Set(element), Value::Array(values)) => {
+                for (i, value) in values.iter().enumerate() {
+                    let path = format!("{path}[{i}]");
+                    self.check(value, element, &namespace, &path, name);
+                }
+            }
+            (Type::Record(ty), Value::Object(values)) if entity_type(value, false).is_none() => {
+                self.check_record(values, ty, &namespace, path);
+            }
+            _ => mismatch(&mut self.errors),
+        }
+    }
+
+    fn check_record(
+        &mut self,
+        values: &Map<String, Value>,
+        ty: &'a Value,
+        namespace: &str,
+        path: &str,
+    ) {
+        let attributes = ty.get("attributes").and_then(Value::as_object);
+        for (name, attribute) in attributes.into_iter().flatten() {
+            let path = format!("{path}.{name}");
+            match values.get(name) {
+                Some(value) => self.check(value, attribute, namespace, &path, name),
+                None if attribute.get("required") != Some(&Value::Bool(false)) => {
+                    self.errors.push(FieldError {
+                        path,
+                        message: format!("`{name}` is required"),
+                    });
+                }
+                None => {}
+            }
+        }
+        if ty.get("additionalAttributes") != Some(&Value::Bool(true)) {
+            let declared = |name: &String| attributes.is_some_and(|a| a.contains_key(name));
+            for name in values.keys().filter(|name| !declared(name)) {
+                self.errors.push(FieldError {
+                    path: format!("{path}.{name}"),
+                    message: format!("`{name}` is not declared"),
+                });
+            }
+        }
+    }
+
+    /// The type `ty` refers to in `namespace`, with the namespace its own references are in.
+    /// `None` for references to undeclared types, which the schema would have rejected.
+    fn resolve(&self, ty: &'a Value, namespace: &str) -> Option<(Type<'a>, String)> {
+        let tag = ty.get("type")?.as_str()?;
+        let primitive = match tag {
+            "Boolean" => Some(Type::Bool),
+            "Long" => Some(Type::Long),
+            "String" => Some(Type::String),
+            "Set" => Some(Type::Set(ty.get("element")?)),
+            "Record" => Some(Type::Record(ty)),
+            "Entity" => Some(Type::Entity(qualify(ty.get("name")?.as_str()?, namespace))),
+            "Extension" => Some(Type::Extension(ty.get("name")?.as_str()?.to_string())),
+            _ => None,
+        };
+        if let Some(primitive) = primitive {
+            return Some((primitive, namespace.to_string()));
+        }
+        let name = match tag {
+            "EntityOrCommon" => ty.get("name")?.as_str()?,
+            name => name,
+        };
+        self.resolve_name(name, namespace)
+    }
+
+    // Names resolve to common types before entity types before built-in types, and
+    // unqualified names to the action's namespace before the empty namespace.
+    fn resolve_name(&self, name: &str, namespace: &str) -> Option<(Type<'a>, String)> {
+        let candidates = match name.rsplit_once("::") {
+            Some((ns, base)) => vec![(ns, base)],
+            None => vec![(namespace, name), ("", name)],
+        };
+        for (ns, base) in candidates {
+            let declared = self.schema.get(ns);
+            if let Some(common) = declared.and_then(|d| d.get("commonTypes")?.get(base)) {
+                return self.resolve(common, ns);
+            }
+            if declared
+                .and_then(|d| d.get("entityTypes")?.get(base))
+                .is_some()
+            {
+                return Some((Type::Entity(qualify(base, ns)), namespace.to_string()));
+            }
+        }
+        let builtin = match name.strip_prefix("__cedar::").unwrap_or(name) {
+            "Bool" => Type::Bool,
+            "Long" => Type::Long,
+            "String" => Type::String,
+            extension if EXTENSIONS.contains(&extension) => Type::Extension(extension.to_string()),
+            _ => return None,
+        };
+        Some((builtin, namespace.to_string()))
+    }
+}
+
+fn qualify(name: &str, namespace: &str) -> String {
+    if namespace.is_empty() || name.contains("::") {
+        name.to_string()
+    } else {
+        format!("{namespace}::{name}")
+    }
+}
+
+/// The type of an entity reference in the explicit `__entity` form, or in the implicit
+/// `{ "type", "id" }` form if `implicit`. Cedar only takes the implicit form where the schema
+/// expects an entity, as it is a valid record as well.
+fn entity_type(value: &Value, implicit: bool) -> Option<&str> {
+    let object = value.as_object()?;
+    let reference = match object.get("__entity") {
+        Some(reference) => reference.as_object()?,
+        None if implicit && object.len() == 2 && object.contains_key("id") => object,
+        None => return None,
+    };
+    reference.get("type")?.as_str()
+}
+
+fn kind(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        Value::Bool(_) => "Bool".to_string(),
+        Value::Number(n) if n.is_i64() => "Long".to_string(),
+        Value::Number(_) => "a decimal number".to_string(),
+        Value::String(_) => "String".to_string(),
+        Value::Array(_) => "Set".to_string(),
+        Value::Object(o) if o.contains_key("__extn") => "an extension value".to_string(),
+        Value::Object(_) => match entity_type(value, false) {
+            Some(ty) => ty.to_string(),
+            None => "Record".to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_validate_context() {
+        let (schema, _) = SchemaFragment::from_cedarschema_str(
+            r#"
+            type Network = { "ip": ipaddr, "trusted"?: Bool };
+            type Tag = { "type": String, "id": String };
+            namespace App {
+                entity User;
+                entity Device;
+                action Read appliesTo {
+                    principal: [User],
+                    resource: [User],
+                    context: {
+                        "mfa": Bool,
+                        "level": Long,
+                        "roles": Set<String>,
+                        "device": Device,
+                        "network": Network,
+                        "tag"?: Tag,
+                    }
+                };
+            }
+            "#,
+        )
+        .unwrap();
+        let action = EntityUid::from_str(r#"App::Action::"Read""#).unwrap();
+        let valid = json!({
+            "mfa": true,
+            "level": 2,
+            "roles": ["admin"],
+            "device": { "type": "App::Device", "id": "0" },
+            "network": { "ip": "10.0.0.1" },
+            // A record that looks like an entity reference.
+            "tag": { "type": "label", "id": "0" },
+        });
+        assert_eq!(validate_context(&schema, &action, &valid).unwrap(), []);
+
+        let invalid = json!({
+            "mfa": "yes",
+            "roles": ["admin", 1],
+            "device": { "__entity": { "type": "App::User", "id": "0" } },
+            "network": { "ip": "10.0.0.1", "vpn": true },
+            "tag": { "type": "label", "id": 0 },
+        });
+        let errors = validate_context(&schema, &action, &invalid)
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            [
+                "`device` expected App::Device, got App::User at $.device",
+                "`level` is required at $.level",
+                "`mfa` expected Bool, got String at $.mfa",
+                "`vpn` is not declared at $.network.vpn",
+                "`roles` expected String, got Long at $.roles[1]",
+                "`id` expected String, got Long at $.tag.id",
+            ]
+        );
+
+        let unknown = EntityUid::from_str(r#"App::Action::"Write""#).unwrap();
+        assert!(matches!(
+            validate_context(&schema, &unknown, &valid),
+            Err(Error::NotFound(_))
+        ));
+    }
+}
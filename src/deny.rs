@@ -0,0 +1,143 @@
+//! Why a request was denied, so APIs can return meaningful `403` bodies.
+//!
+//! Forbid policies can carry a human-readable `@message("...")` annotation, which is surfaced
+//! with the policy ID when the policy determined a denial.
+
+use std::collections::BTreeMap;
+
+use cedar_policy::{AuthorizationError, Decision, PolicySet, Response};
+use serde::{Deserialize, Serialize};
+
+use crate::{audit::AuditError, namespace::id_str};
+
+pub const MESSAGE: &str = "message";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum DenyReason {
+    /// Forbid policies were satisfied, sorted by ID.
+    ExplicitForbid { policies: Vec<ForbidPolicy> },
+    /// No permit was satisfied and no policy failed to evaluate.
+    NoApplicablePermit,
+    /// No permit was satisfied, but some policies failed to evaluate and may have permitted the
+    /// request otherwise.
+    EvaluationError { errors: Vec<AuditError> },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ForbidPolicy {
+    pub id: String,
+    /// The `@message` annotation.
+    pub message: Option<String>,
+    pub annotations: BTreeMap<String, String>,
+}
+
+impl DenyReason {
+    /// The `@message` annotations of the forbid policies, if any.
+    pub fn messages(&self) -> Vec<&str> {
+        match self {
+            Self::ExplicitForbid { policies } => policies
+                .iter()
+                .filter_map(|policy| policy.message.as_deref())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Classify the denial in `response`, looking up annotations of the forbid policies in
+/// `policies`, the policies the request was evaluated against. `None` if the request was
+/// allowed.
+pub fn deny_reason(response: &Response, policies: &PolicySet) -> Option<DenyReason> {
+    if response.decision() == Decision::Allow {
+        return None;
+    }
+    let diagnostics = response.diagnostics();
+    // A denial is determined by forbid policies only, so any reason is a forbid.
+    let mut forbids = diagnostics
+        .reason()
+        .map(|id| {
+            let annotations = policies
+                .policy(id)
+                .into_iter()
+                .flat_map(|policy| policy.annotations())
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect::<BTreeMap<_, _>>();
+            ForbidPolicy {
+                id: id_str(id).to_string(),
+                message: annotations.get(MESSAGE).cloned(),
+                annotations,
+            }
+        })
+        .collect::<Vec<_>>();
+    if !forbids.is_empty() {
+        forbids.sort_by(|a, b| a.id.cmp(&b.id));
+        return Some(DenyReason::ExplicitForbid { policies: forbids });
+    }
+    let errors = diagnostics
+        .errors()
+        .map(|e| match e {
+            AuthorizationError::PolicyEvaluationError(e) => AuditError {
+                policy: id_str(e.policy_id()).to_string(),
+                message: e.inner().to_string(),
+            },
+        })
+        .collect::<Vec<_>>();
+    if errors.is_empty() {
+        Some(DenyReason::NoApplicablePermit)
+    } else {
+        Some(DenyReason::EvaluationError { errors })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use cedar_policy::{Authorizer, Context, Entities, EntityUid, Request};
+
+    use super::*;
+
+    #[test]
+    fn test_deny_reason() {
+        let policies = PolicySet::from_str(
+            r#"
+            permit (principal == MyApp::User::"admin", action, resource);
+            @message("User 1 is banned")
+            forbid (principal == MyApp::User::"1", action, resource);
+            permit (principal == MyApp::User::"2", action, resource)
+                when { principal.missing };
+            "#,
+        )
+        .unwrap();
+        let reason = |user: &str| {
+            let request = Request::new(
+                EntityUid::from_str(&format!(r#"MyApp::User::"{user}""#)).unwrap(),
+                EntityUid::from_str(r#"MyApp::Action::"GetProjectMetadata""#).unwrap(),
+                EntityUid::from_str(r#"MyApp::Project::"0""#).unwrap(),
+                Context::empty(),
+                None,
+            )
+            .unwrap();
+            let response = Authorizer::new().is_authorized(&request, &policies, &Entities::empty());
+            deny_reason(&response, &policies)
+        };
+
+        assert_eq!(reason("admin"), None);
+        let banned = reason("1").unwrap();
+        assert_eq!(banned.messages(), ["User 1 is banned"]);
+        let DenyReason::ExplicitForbid { policies: forbids } = &banned else {
+            panic!("expected an explicit forbid");
+        };
+        assert_eq!(forbids[0].id, "policy1");
+        assert_eq!(
+            serde_json::to_value(&banned).unwrap()["reason"],
+            "explicit_forbid"
+        );
+        assert_eq!(reason("3"), Some(DenyReason::NoApplicablePermit));
+        let Some(DenyReason::EvaluationError { errors }) = reason("2") else {
+            panic!("expected an evaluation error");
+        };
+        assert_eq!(errors[0].policy, "policy2");
+    }
+}
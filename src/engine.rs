@@ -0,0 +1,2240 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    sync::{
+        Arc, Mutex, MutexGuard, OnceLock, PoisonError,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use arc_swap::{ArcSwap, ArcSwapOption};
+use cedar_policy::{
+    Authorizer, Context, Entities, Entity, EntityId, EntityTypeName, EntityUid, PartialEntities,
+    PartialEntityUid, PartialRequest, Policy, PolicyId, PolicySet, PolicySetError,
+    PrincipalQueryRequest, Request, ResourceQueryRequest, Response, Schema, SchemaFragment, SlotId,
+    Template, ValidationMode, Validator,
+};
+
+use tokio::sync::{broadcast, watch};
+
+use crate::{
+    activation::{Activation, Window, windows},
+    analysis::{DecisionTrace, PrincipalDescription, explain_decision},
+    annotations::TagFilter,
+    audit::{Audit, AuditConfig, AuditSink, PartialDecision, PolicyExpiry, context_json},
+    bulk::ResourceFilter,
+    cache::{CacheConfig, CacheKey, CacheStats, DecisionCache},
+    clock::{Clock, SystemClock, TimeInjection},
+    context::{FieldError, validate_context},
+    error::{Diagnostic, Error, ErrorHandling, Result},
+    fingerprint::{StateFingerprint, entities_fingerprint, policies_fingerprint},
+    limits::{EvaluationLimits, timed_out},
+    namespace::id_str,
+    pdp::PartialUid,
+    profile::{ProfileReport, Profiler, ProfilerConfig},
+    residuals::Residuals,
+    rollout::{Rollout, RolloutConfig, RolloutStatus},
+    shadow::{Shadow, ShadowReport},
+    slice::ActionIndex,
+};
+#[cfg(feature = "metrics")]
+use crate::{fingerprint::schema_fingerprint, telemetry};
+
+/// Changes buffered per subscriber before it lags behind.
+const CHANGE_CAPACITY: usize = 64;
+
+/// Tag filters whose policies fingerprint a state remembers.
+const FILTERED_FINGERPRINTS: usize = 64;
+
+/// The schema, policies and entities an [`Engine`] evaluates requests against.
+///
+/// A state is immutable; updates to the engine install a new state instead. The schema
+/// fragment it was built from is kept so the schema can be served and exported again.
+#[derive(Debug, Clone)]
+pub struct EngineState {
+    schema_fragment: Arc<SchemaFragment>,
+    schema: Arc<Schema>,
+    policies: PolicySet,
+    tpe_policies: PolicySet,
+    entities: Entities,
+    partial_entities: PartialEntities,
+    /// The windows of the time-bound policies, see [`crate::activation`].
+    windows: Arc<[(PolicyId, Window)]>,
+    index: Arc<ActionIndex>,
+    validation: ValidationConfig,
+    warnings: Arc<[Diagnostic]>,
+    fingerprint: OnceLock<StateFingerprint>,
+    /// Shared with the states that only differ by their entities.
+    filtered_fingerprints: Arc<Mutex<HashMap<TagFilter, String>>>,
+}
+
+impl EngineState {
+    /// Validate `policies` and `entities` against the schema described by `schema_fragment`.
+    pub fn new(
+        schema_fragment: SchemaFragment,
+        policies: PolicySet,
+        entities: Entities,
+    ) -> Result<Self> {
+        let schema = schema_fragment.clone().try_into()?;
+        Self::with_schema(
+            Arc::new(schema_fragment),
+            Arc::new(schema),
+            policies,
+            entities,
+        )
+    }
+
+    /// Like [`EngineState::new`], but validates `policies` as configured by `validation`.
+    /// States derived from this one by engine updates are validated the same way.
+    pub fn with_validation(
+        schema_fragment: SchemaFragment,
+        policies: PolicySet,
+        entities: Entities,
+        validation: ValidationConfig,
+    ) -> Result<Self> {
+        let schema = schema_fragment.clone().try_into()?;
+        Self::validated(
+            Arc::new(schema_fragment),
+            Arc::new(schema),
+            policies,
+            entities,
+            validation,
+        )
+    }
+
+    /// Like [`EngineState::new`], but shares an already parsed schema.
+    pub(crate) fn with_schema(
+        schema_fragment: Arc<SchemaFragment>,
+        schema: Arc<Schema>,
+        policies: PolicySet,
+        entities: Entities,
+    ) -> Result<Self> {
+        Self::validated(
+            schema_fragment,
+            schema,
+            policies,
+            entities,
+            ValidationConfig::default(),
+        )
+    }
+
+    fn validated(
+        schema_fragment: Arc<SchemaFragment>,
+        schema: Arc<Schema>,
+        policies: PolicySet,
+        entities: Entities,
+        validation: ValidationConfig,
+    ) -> Result<Self> {
+        let warnings = validation.validate(&schema, &policies)?;
+        Ok(Self {
+            validation,
+            warnings: warnings.into(),
+            ..Self::prevalidated(schema_fragment, schema, policies, entities)?
+        })
+    }
+
+    /// A state like this one with other policies and entities, validated the same way.
+    fn derive(
+        &self,
+        schema_fragment: Arc<SchemaFragment>,
+        schema: Arc<Schema>,
+        policies: PolicySet,
+        entities: Entities,
+    ) -> Result<Self> {
+        Self::validated(schema_fragment, schema, policies, entities, self.validation)
+    }
+
+    /// A state like this one with other entities. The policies were validated against the same
+    /// schema, so they and their index are shared instead of validated and built again.
+    fn with_entities(&self, entities: Entities) -> Result<Self> {
+        let entities = validate_entities(&self.schema, entities)?;
+        let partial_entities = PartialEntities::from_concrete(entities.clone(), &self.schema)?;
+        Ok(Self {
+            entities,
+            partial_entities,
+            fingerprint: OnceLock::new(),
+            ..self.clone()
+        })
+    }
+
+    /// Like [`EngineState::with_schema`], for policies already validated against `schema`.
+    pub(crate) fn prevalidated(
+        schema_fragment: Arc<SchemaFragment>,
+        schema: Arc<Schema>,
+        policies: PolicySet,
+        entities: Entities,
+    ) -> Result<Self> {
+        let entities = validate_entities(&schema, entities)?;
+        let partial_entities = PartialEntities::from_concrete(entities.clone(), &schema)?;
+        let tpe_policies = static_policies(&policies)?;
+        Ok(Self {
+            index: Arc::new(ActionIndex::new(&schema, &tpe_policies)),
+            schema_fragment,
+            schema,
+            windows: windows(&tpe_policies)?.into(),
+            tpe_policies,
+            policies,
+            entities,
+            partial_entities,
+            validation: ValidationConfig::default(),
+            warnings: Arc::new([]),
+            fingerprint: OnceLock::new(),
+            filtered_fingerprints: Arc::default(),
+        })
+    }
+
+    /// The state with only the policies whose windows are `active`, as static policies.
+    fn activated(&self, active: &[bool]) -> Self {
+        let inactive = self
+            .windows
+            .iter()
+            .zip(active)
+            .filter(|(_, active)| !**active)
+            .map(|((id, _), _)| id)
+            .collect::<HashSet<_>>();
+        let policies = PolicySet::from_policies(
+            self.tpe_policies
+                .policies()
+                .filter(|p| !inactive.contains(p.id()))
+                .cloned(),
+        )
+        .expect("a subset of a policy set has unique IDs");
+        // Leaving out policies leaves the rest where the index put them.
+        let index = if inactive.is_empty() {
+            self.index.clone()
+        } else {
+            Arc::new(self.index.without(&inactive))
+        };
+        Self {
+            index,
+            schema_fragment: self.schema_fragment.clone(),
+            schema: self.schema.clone(),
+            policies: policies.clone(),
+            tpe_policies: policies,
+            entities: self.entities.clone(),
+            partial_entities: self.partial_entities.clone(),
+            windows: Arc::new([]),
+            validation: self.validation,
+            warnings: self.warnings.clone(),
+            fingerprint: OnceLock::new(),
+            filtered_fingerprints: Arc::default(),
+        }
+    }
+
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    pub fn schema_fragment(&self) -> &SchemaFragment {
+        &self.schema_fragment
+    }
+
+    pub fn policies(&self) -> &PolicySet {
+        &self.policies
+    }
+
+    pub fn entities(&self) -> &Entities {
+        &self.entities
+    }
+
+    pub(crate) fn partial_entities(&self) -> &PartialEntities {
+        &self.partial_entities
+    }
+
+    /// The policies with template links replaced by static policies, as used by TPE.
+    pub(crate) fn tpe_policies(&self) -> &PolicySet {
+        &self.tpe_policies
+    }
+
+    /// The policies that may apply to `request`, as static policies. `None` for requests with
+    /// an unknown action or one that is not in the schema.
+    fn slice(&self, request: &Request) -> Option<&PolicySet> {
+        match (request.principal(), request.action(), request.resource()) {
+            (Some(principal), Some(action), Some(resource)) => {
+                self.index
+                    .bucket(principal.type_name(), action, resource.type_name())
+            }
+            (_, Some(action), _) => self.index.get(action),
+            _ => None,
+        }
+    }
+
+    /// The policies that may apply to requests for `action`, for TPE.
+    fn tpe_policies_for(&self, action: &EntityUid) -> &PolicySet {
+        self.index.get(action).unwrap_or(&self.tpe_policies)
+    }
+
+    /// Like [`EngineState::tpe_policies_for`], for a principal and resource of the given types.
+    fn tpe_bucket(
+        &self,
+        principal: &EntityTypeName,
+        action: &EntityUid,
+        resource: &EntityTypeName,
+    ) -> &PolicySet {
+        self.index
+            .bucket(principal, action, resource)
+            .unwrap_or(&self.tpe_policies)
+    }
+
+    pub fn validation(&self) -> ValidationConfig {
+        self.validation
+    }
+
+    /// The validation failures of the policies, if they were installed with
+    /// [`ValidationConfig::warn_only`].
+    pub fn validation_warnings(&self) -> &[Diagnostic] {
+        &self.warnings
+    }
+
+    /// Computed on first use, as hashing large entity sets is not free.
+    pub fn fingerprint(&self) -> &StateFingerprint {
+        self.fingerprint.get_or_init(|| {
+            StateFingerprint::new(&self.schema_fragment, &self.policies, &self.entities)
+        })
+    }
+
+    /// The fingerprint of the policies matching `filter`, computed once per filter. Only so
+    /// many filters are remembered, so callers choosing arbitrary filters cannot grow the state.
+    fn filtered_fingerprint(&self, filter: &TagFilter) -> Result<String> {
+        let lock = || {
+            self.filtered_fingerprints
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+        };
+        if let Some(fingerprint) = lock().get(filter) {
+            return Ok(fingerprint.clone());
+        }
+        let fingerprint = policies_fingerprint(&filter.apply(&self.tpe_policies)?);
+        let mut fingerprints = lock();
+        if fingerprints.len() < FILTERED_FINGERPRINTS {
+            fingerprints.insert(filter.clone(), fingerprint.clone());
+        }
+        Ok(fingerprint)
+    }
+}
+
+/// Sent to the receivers of [`Engine::subscribe`] when a state with different content is
+/// installed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateChange {
+    pub schema_changed: bool,
+    pub policies_changed: bool,
+    pub entities_changed: bool,
+    pub fingerprint: StateFingerprint,
+}
+
+/// Evaluates requests against an atomically swappable [`EngineState`].
+///
+/// Reads never block: a request loads the current state once and keeps using it, even if a
+/// new state is installed concurrently. Updates are validated before they are installed and
+/// are serialized, so no update is lost and a failed update leaves the current state in place.
+#[derive(Debug)]
+pub struct Engine {
+    state: ArcSwap<EngineState>,
+    update_lock: Mutex<()>,
+    changes: broadcast::Sender<StateChange>,
+    revision: watch::Sender<u64>,
+    shadow: ArcSwapOption<Shadow>,
+    rollout: ArcSwapOption<Rollout>,
+    authorizer: Authorizer,
+    audit: ArcSwapOption<Audit>,
+    warmed_up: AtomicBool,
+    cache: ArcSwapOption<DecisionCache>,
+    profiler: ArcSwapOption<Profiler>,
+    limits: ArcSwap<EvaluationLimits>,
+    time: ArcSwapOption<TimeInjection>,
+    clock: ArcSwap<Box<dyn Clock>>,
+    activation: ArcSwapOption<Activation>,
+    on_error: ArcSwap<ErrorHandling>,
+}
+
+impl Engine {
+    /// Create an engine after validating `policies` and `entities` against `schema_fragment`.
+    pub fn new(
+        schema_fragment: SchemaFragment,
+        policies: PolicySet,
+        entities: Entities,
+    ) -> Result<Self> {
+        Ok(Self::from_state(EngineState::new(
+            schema_fragment,
+            policies,
+            entities,
+        )?))
+    }
+
+    /// Like [`Engine::new`], but validates policies as configured by `validation`, now and on
+    /// every update.
+    pub fn with_validation(
+        schema_fragment: SchemaFragment,
+        policies: PolicySet,
+        entities: Entities,
+        validation: ValidationConfig,
+    ) -> Result<Self> {
+        Ok(Self::from_state(EngineState::with_validation(
+            schema_fragment,
+            policies,
+            entities,
+            validation,
+        )?))
+    }
+
+    pub fn from_state(state: EngineState) -> Self {
+        Self {
+            state: ArcSwap::from_pointee(state),
+            update_lock: Mutex::new(()),
+            changes: broadcast::Sender::new(CHANGE_CAPACITY),
+            revision: watch::Sender::new(0),
+            shadow: ArcSwapOption::empty(),
+            rollout: ArcSwapOption::empty(),
+            authorizer: Authorizer::new(),
+            audit: ArcSwapOption::empty(),
+            warmed_up: AtomicBool::new(false),
+            cache: ArcSwapOption::empty(),
+            profiler: ArcSwapOption::empty(),
+            limits: ArcSwap::default(),
+            time: ArcSwapOption::empty(),
+            clock: ArcSwap::from_pointee(Box::new(SystemClock) as Box<dyn Clock>),
+            activation: ArcSwapOption::empty(),
+            on_error: ArcSwap::default(),
+        }
+    }
+
+    /// The currently installed state. Requests in flight keep using the state they started with.
+    pub fn state(&self) -> Arc<EngineState> {
+        self.state.load_full()
+    }
+
+    /// The installed state without the time-bound policies that are not active now. Records
+    /// the policies that expired since the last evaluation with the audit sink.
+    fn active_state(&self) -> Arc<EngineState> {
+        let state = self.state();
+        if state.windows.is_empty() {
+            return state;
+        }
+        let now = self.clock.load().now();
+        let active = state
+            .windows
+            .iter()
+            .map(|(_, window)| window.contains(now))
+            .collect::<Vec<_>>();
+        let previous = self.activation.load_full();
+        if let Some(previous) = &previous
+            && Arc::ptr_eq(&previous.base, &state)
+            && previous.active == active
+        {
+            return previous.state.clone();
+        }
+        let activation = Arc::new(Activation {
+            state: Arc::new(state.activated(&active)),
+            base: state,
+            active,
+        });
+        let swapped = self
+            .activation
+            .compare_and_swap(&previous, Some(activation.clone()));
+        // Of concurrent evaluations noticing an expiry, only the one that installed the new
+        // activation records it. Policies are matched by ID, as the previous activation may be
+        // of a state installed before this one.
+        if let Some(previous) = &previous
+            && swapped.as_ref().is_some_and(|s| Arc::ptr_eq(s, previous))
+            && let Some(audit) = &*self.audit.load()
+        {
+            let was_active = previous
+                .base
+                .windows
+                .iter()
+                .zip(&previous.active)
+                .filter_map(|((id, _), active)| active.then_some(id))
+                .collect::<HashSet<_>>();
+            let windows = activation.base.windows.iter().zip(&activation.active);
+            for ((id, window), is_active) in windows {
+                if was_active.contains(id) && !is_active && window.has_ended(now) {
+                    audit.expired(&PolicyExpiry {
+                        timestamp: now,
+                        expired_policy: id_str(id).to_string(),
+                        not_after: window.not_after.unwrap_or(now),
+                    });
+                }
+            }
+        }
+        activation.state.clone()
+    }
+
+    /// Prepare the current state for traffic: compute its fingerprint and evaluate one request
+    /// for every action and principal and resource type it applies to, so the first requests do
+    /// not pay for it. Returns the number of requests evaluated.
+    pub fn warm_up(&self) -> usize {
+        let state = self.state();
+        state.fingerprint();
+        let id = EntityId::new("warm-up");
+        let mut evaluated = 0;
+        for action in state.schema.actions() {
+            let principals = state.schema.principals_for_action(action).into_iter();
+            for principal in principals.flatten() {
+                let resources = state.schema.resources_for_action(action).into_iter();
+                for resource in resources.flatten() {
+                    let request = Request::new(
+                        EntityUid::from_type_name_and_id(principal.clone(), id.clone()),
+                        action.clone(),
+                        EntityUid::from_type_name_and_id(resource.clone(), id.clone()),
+                        Context::empty(),
+                        None,
+                    );
+                    // Without a schema, building the request cannot fail. Shadow and rollout
+                    // policies and the audit log are left out, so warming up does not show in
+                    // their reports.
+                    if let Ok(request) = request {
+                        self.authorizer.is_authorized(
+                            &request,
+                            state.slice(&request).unwrap_or(&state.policies),
+                            &state.entities,
+                        );
+                        evaluated += 1;
+                    }
+                }
+            }
+        }
+        self.warmed_up.store(true, Ordering::Release);
+        evaluated
+    }
+
+    /// Whether [`Engine::warm_up`] was called.
+    pub fn is_warmed_up(&self) -> bool {
+        self.warmed_up.load(Ordering::Acquire)
+    }
+
+    /// Install an already validated state and return the previous one, e.g. to roll back to it
+    /// later.
+    pub fn swap_state(&self, state: EngineState) -> Arc<EngineState> {
+        let guard = self.lock_updates();
+        self.install(&guard, state)
+    }
+
+    /// Hold off other updates, so the state can be read and replaced with [`Engine::install`]
+    /// with no update in between.
+    pub(crate) fn lock_updates(&self) -> MutexGuard<'_, ()> {
+        self.update_lock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Install `state` while holding the guard of [`Engine::lock_updates`].
+    pub(crate) fn install(
+        &self,
+        _guard: &MutexGuard<'_, ()>,
+        state: EngineState,
+    ) -> Arc<EngineState> {
+        let state = Arc::new(state);
+        let previous = self.state.swap(state.clone());
+        self.clear_decision_cache();
+        self.notify(&previous, &state);
+        self.revision.send_modify(|revision| *revision += 1);
+        previous
+    }
+
+    /// Increases with every installed state, and is advanced to the
+    /// [sequence](crate::store::PolicyStore::sequence) of the store policies are loaded from,
+    /// so engines loading from one store report comparable revisions. A caller that changed the
+    /// engine, e.g. through a store that is reloaded asynchronously, can wait for the revision
+    /// after its write with [`Engine::wait_for_revision`] to read its own writes.
+    pub fn revision(&self) -> u64 {
+        *self.revision.borrow()
+    }
+
+    /// Advance the revision to `sequence`, if it is ahead. See [`Engine::revision`].
+    pub fn advance_revision(&self, sequence: Option<u64>) {
+        if let Some(sequence) = sequence {
+            self.revision.send_if_modified(|revision| {
+                let ahead = sequence > *revision;
+                *revision = (*revision).max(sequence);
+                ahead
+            });
+        }
+    }
+
+    /// Wait until the revision is at least `revision`. Fails with
+    /// [`Error::LimitExceeded`] after `timeout`.
+    pub async fn wait_for_revision(&self, revision: u64, timeout: Duration) -> Result<()> {
+        let mut receiver = self.revision.subscribe();
+        let wait = receiver.wait_for(|installed| *installed >= revision);
+        match tokio::time::timeout(timeout, wait).await {
+            Ok(_) => Ok(()),
+            Err(_) => Err(Error::LimitExceeded(format!(
+                "Revision {revision} was not installed within {timeout:?}"
+            ))),
+        }
+    }
+
+    /// Receive a [`StateChange`] for every installed state whose schema, policies or entities
+    /// differ from the previous one. A receiver that lags behind gets
+    /// [`broadcast::error::RecvError::Lagged`] and should assume that everything changed.
+    pub fn subscribe(&self) -> broadcast::Receiver<StateChange> {
+        self.changes.subscribe()
+    }
+
+    // Fingerprints are only computed while someone is subscribed. Metrics compare the parts
+    // instead, so installing a state does not hash all its entities.
+    fn notify(&self, previous: &EngineState, current: &EngineState) {
+        #[cfg(feature = "metrics")]
+        telemetry::reload(
+            !Arc::ptr_eq(&previous.schema_fragment, &current.schema_fragment)
+                && schema_fingerprint(&previous.schema_fragment)
+                    != schema_fingerprint(&current.schema_fragment),
+            previous.policies != current.policies,
+            previous.entities != current.entities,
+        );
+        if self.changes.receiver_count() == 0 {
+            return;
+        }
+        let (before, after) = (previous.fingerprint(), current.fingerprint());
+        if before == after {
+            return;
+        }
+        // Sending only fails if all receivers were dropped in the meantime.
+        let _ = self.changes.send(StateChange {
+            schema_changed: before.schema != after.schema,
+            policies_changed: before.policies != after.policies,
+            entities_changed: before.entities != after.entities,
+            fingerprint: after.clone(),
+        });
+    }
+
+    /// Replace schema and policies. The new artifacts are validated first; on failure the
+    /// current state stays installed.
+    pub fn replace_policies(
+        &self,
+        schema_fragment: SchemaFragment,
+        policies: PolicySet,
+    ) -> Result<()> {
+        self.update(|state| {
+            let schema = schema_fragment.clone().try_into()?;
+            state.derive(
+                Arc::new(schema_fragment),
+                Arc::new(schema),
+                policies,
+                state.entities.clone(),
+            )
+        })
+    }
+
+    /// Replace the schema, keeping the installed policies and entities. Policies written
+    /// concurrently are kept, as the policies are read under the update lock.
+    pub fn replace_schema(&self, schema_fragment: SchemaFragment) -> Result<()> {
+        self.update(|state| {
+            let schema = schema_fragment.clone().try_into()?;
+            state.derive(
+                Arc::new(schema_fragment),
+                Arc::new(schema),
+                state.policies.clone(),
+                state.entities.clone(),
+            )
+        })
+    }
+
+    /// Atomically derive a new policy set from the current one. Concurrent updates are
+    /// serialized, so no update is lost.
+    pub fn update_policies(&self, f: impl FnOnce(&PolicySet) -> Result<PolicySet>) -> Result<()> {
+        self.update(|state| {
+            state.derive(
+                state.schema_fragment.clone(),
+                state.schema.clone(),
+                f(&state.policies)?,
+                state.entities.clone(),
+            )
+        })
+    }
+
+    /// Add a static policy. Fails with [`Error::Validation`] if the resulting policy set does not
+    /// validate as configured for the installed state.
+    pub fn add_policy(&self, policy: Policy) -> Result<()> {
+        self.update_policies(|policies| {
+            let mut policies = policies.clone();
+            policies.add(policy)?;
+            Ok(policies)
+        })
+    }
+
+    /// Replace the static policy with the ID of `policy`, validated like [`Engine::add_policy`].
+    pub fn update_policy(&self, policy: Policy) -> Result<()> {
+        self.update_policies(|policies| {
+            let mut policies = policies.clone();
+            policies.remove_static(policy.id().clone())?;
+            policies.add(policy)?;
+            Ok(policies)
+        })
+    }
+
+    /// Remove a static policy. Template-linked policies are removed with [`Engine::unlink`].
+    pub fn remove_policy(&self, policy_id: PolicyId) -> Result<()> {
+        self.update_policies(|policies| {
+            let mut policies = policies.clone();
+            policies.remove_static(policy_id)?;
+            Ok(policies)
+        })
+    }
+
+    /// Add a policy template. Templates are validated like policies.
+    pub fn add_template(&self, template: Template) -> Result<()> {
+        self.update_policies(|policies| {
+            let mut policies = policies.clone();
+            policies.add_template(template)?;
+            Ok(policies)
+        })
+    }
+
+    /// Remove a template. Fails while policies are linked to it.
+    pub fn remove_template(&self, template_id: PolicyId) -> Result<()> {
+        self.update_policies(|policies| {
+            let mut policies = policies.clone();
+            policies.remove_template(template_id)?;
+            Ok(policies)
+        })
+    }
+
+    /// Link the template `template_id` as policy `link_id`. The linked policy is validated
+    /// against the schema, including the types of the slot values.
+    pub fn link(
+        &self,
+        template_id: PolicyId,
+        link_id: PolicyId,
+        slots: HashMap<SlotId, EntityUid>,
+    ) -> Result<()> {
+        self.update_policies(|policies| {
+            let mut policies = policies.clone();
+            policies.link(template_id, link_id, slots)?;
+            Ok(policies)
+        })
+    }
+
+    pub fn unlink(&self, link_id: PolicyId) -> Result<()> {
+        self.update_policies(|policies| {
+            let mut policies = policies.clone();
+            policies.unlink(link_id)?;
+            Ok(policies)
+        })
+    }
+
+    pub fn replace_entities(&self, entities: Entities) -> Result<()> {
+        self.update(|state| state.with_entities(entities))
+    }
+
+    pub(crate) fn update(&self, f: impl FnOnce(&EngineState) -> Result<EngineState>) -> Result<()> {
+        let guard = self.lock_updates();
+        let state = f(&self.state.load())?;
+        self.install(&guard, state);
+        Ok(())
+    }
+
+    pub fn is_authorized(&self, request: &Request) -> Response {
+        self.evaluate(request, &self.active_state(), None)
+    }
+
+    /// Evaluate `request` against the installed policies, but with caller-provided entities.
+    pub fn is_authorized_with_entities(&self, request: &Request, entities: &Entities) -> Response {
+        self.evaluate(request, &self.active_state(), Some(entities))
+    }
+
+    /// Like [`Engine::is_authorized`], but fails if the installed [`EvaluationLimits`] are
+    /// exceeded, or policies errored under [`ErrorHandling::Fail`].
+    pub fn try_is_authorized(&self, request: &Request) -> Result<Response> {
+        self.try_evaluate(request, None)
+    }
+
+    /// Like [`Engine::is_authorized_with_entities`], but fails if the installed
+    /// [`EvaluationLimits`] are exceeded.
+    pub fn try_is_authorized_with_entities(
+        &self,
+        request: &Request,
+        entities: &Entities,
+    ) -> Result<Response> {
+        self.try_evaluate(request, Some(entities))
+    }
+
+    /// Like [`Engine::try_is_authorized`], but evaluates on a blocking thread and returns as
+    /// soon as the timeout elapsed, so a slow evaluation does not hold up the caller.
+    ///
+    /// A timed-out evaluation cannot be cancelled: it keeps its blocking thread until it
+    /// finishes, so sustained slow requests can exhaust tokio's blocking pool. Bound the
+    /// request rate, or the pool with `max_blocking_threads`, accordingly.
+    pub async fn is_authorized_within(self: &Arc<Self>, request: Request) -> Result<Response> {
+        let Some(timeout) = self.limits().timeout else {
+            return self.try_is_authorized(&request);
+        };
+        let engine = self.clone();
+        let evaluation = tokio::task::spawn_blocking(move || engine.try_is_authorized(&request));
+        match tokio::time::timeout(timeout, evaluation).await {
+            Ok(Ok(response)) => response,
+            Ok(Err(e)) => std::panic::resume_unwind(e.into_panic()),
+            Err(_) => Err(timed_out(timeout)),
+        }
+    }
+
+    fn try_evaluate(&self, request: &Request, provided: Option<&Entities>) -> Result<Response> {
+        let limits = self.limits();
+        let state = self.active_state();
+        limits.check_entities(provided.unwrap_or(&state.entities))?;
+        let start = limits.start();
+        let response = self.evaluate(request, &state, provided);
+        limits.check_elapsed(start)?;
+        self.error_handling().check(&response)?;
+        Ok(response)
+    }
+
+    pub fn set_limits(&self, limits: EvaluationLimits) {
+        self.limits.store(Arc::new(limits));
+    }
+
+    pub fn limits(&self) -> EvaluationLimits {
+        **self.limits.load()
+    }
+
+    /// How policies that fail to evaluate affect decisions from now on. Audit records note the
+    /// handling their decision was made with.
+    pub fn set_error_handling(&self, on_error: ErrorHandling) {
+        self.on_error.store(Arc::new(on_error));
+    }
+
+    pub fn error_handling(&self) -> ErrorHandling {
+        **self.on_error.load()
+    }
+
+    /// Run a partial evaluation within the installed [`EvaluationLimits`].
+    fn limited(&self, tpe: impl FnOnce() -> Result<Residuals>) -> Result<Residuals> {
+        let limits = self.limits();
+        let start = limits.start();
+        let residuals = self.error_handling().apply_residuals(tpe()?)?;
+        limits.check_elapsed(start)?;
+        limits.check_residuals(&residuals)?;
+        Ok(residuals)
+    }
+
+    // Fingerprints are computed once per state, by the first traced request.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "cedar.authorize",
+            skip_all,
+            fields(
+                policies = %state.fingerprint().policies,
+                schema = %state.fingerprint().schema,
+                decision,
+            ),
+        )
+    )]
+    fn evaluate(
+        &self,
+        request: &Request,
+        state: &EngineState,
+        provided: Option<&Entities>,
+    ) -> Response {
+        #[cfg(feature = "metrics")]
+        let start = std::time::Instant::now();
+        let injected = self.inject_time_into(request);
+        let request = injected.as_ref().unwrap_or(request);
+        let entities = provided.unwrap_or(&state.entities);
+        let rollout = self.rollout.load();
+        let policies = state.slice(request).unwrap_or(&state.policies);
+        let evaluate = || self.authorizer.is_authorized(request, policies, entities);
+        // During a rollout, decisions depend on the principal's cohort as well. Caller-provided
+        // entities would have to be hashed per request to key their decisions, so those are
+        // not cached.
+        let cache = self.cache.load();
+        let key = match (&*cache, &*rollout, provided) {
+            (Some(_), None, None) => {
+                let fingerprint = state.fingerprint();
+                CacheKey::new(request, &fingerprint.policies, &fingerprint.entities)
+            }
+            _ => None,
+        };
+        let mut response = match (&*cache, key) {
+            (Some(cache), Some(key)) => cache.get_or_evaluate(key, evaluate),
+            _ => evaluate(),
+        };
+        let mut candidate = None;
+        if let Some(rollout) = &*rollout
+            && let Some(canary) = rollout.evaluate(request, entities, &response)
+        {
+            response = canary;
+            candidate = Some(rollout.policies_fingerprint());
+        }
+        let on_error = self.error_handling();
+        let response = on_error.apply(response);
+        if let Some(shadow) = &*self.shadow.load() {
+            shadow.record(request, entities, response.decision());
+        }
+        if let Some(profiler) = &*self.profiler.load() {
+            profiler.record(request, state, entities);
+        }
+        if let Some(audit) = &*self.audit.load() {
+            let fingerprint = state.fingerprint();
+            // Caller-provided entities are hashed per request, and only while auditing.
+            let entities = provided.map(entities_fingerprint);
+            audit.record(
+                request,
+                &response,
+                candidate.unwrap_or(&fingerprint.policies),
+                entities.as_deref().unwrap_or(&fingerprint.entities),
+                on_error,
+            );
+        }
+        #[cfg(feature = "metrics")]
+        telemetry::decision(response.decision(), start.elapsed());
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("decision", tracing::field::debug(response.decision()));
+        response
+    }
+
+    /// `request` with the current time added to its context, if [`Engine::inject_time`] is on
+    /// and the request does not have the attribute yet.
+    fn inject_time_into(&self, request: &Request) -> Option<Request> {
+        self.time
+            .load()
+            .as_ref()
+            .and_then(|time| time.inject(request, self.clock.load().now()))
+    }
+
+    /// The clock for time injection and the windows of time-bound policies. Defaults to
+    /// [`SystemClock`].
+    pub fn set_clock(&self, clock: impl Clock + 'static) {
+        self.clock
+            .store(Arc::new(Box::new(clock) as Box<dyn Clock>));
+    }
+
+    /// Evaluate requests with the current time of the clock in their context, as the
+    /// `datetime` attribute `attribute`. Requests whose context already has the attribute keep
+    /// their value. The time is added before the decision cache is consulted, so cached
+    /// decisions are only reused within the same millisecond.
+    pub fn inject_time(&self, attribute: impl Into<String>) {
+        self.time.store(Some(Arc::new(TimeInjection {
+            attribute: attribute.into(),
+        })));
+    }
+
+    pub fn stop_injecting_time(&self) {
+        self.time.store(None);
+    }
+
+    /// Cache decisions from now on, replacing any previous cache. Cached decisions are not
+    /// used while a rollout runs, nor for requests with caller-provided entities.
+    ///
+    /// The cache and [`Engine::inject_time`] do not work together: the injected time is part of
+    /// the key, so a decision is only reused within the same millisecond.
+    pub fn enable_decision_cache(&self, config: CacheConfig) {
+        self.cache.store(Some(Arc::new(DecisionCache::new(config))));
+    }
+
+    /// Stop caching decisions. Returns the statistics of the cache.
+    pub fn disable_decision_cache(&self) -> Option<CacheStats> {
+        self.cache.swap(None).map(|cache| cache.stats())
+    }
+
+    pub fn decision_cache_stats(&self) -> Option<CacheStats> {
+        self.cache.load().as_ref().map(|cache| cache.stats())
+    }
+
+    fn clear_decision_cache(&self) {
+        if let Some(cache) = &*self.cache.load() {
+            cache.clear();
+        }
+    }
+
+    /// Record every decision from now on in `sink`, replacing any previous sink.
+    pub fn set_audit_sink(&self, sink: impl AuditSink) {
+        self.set_audit_sink_with(sink, AuditConfig::default());
+    }
+
+    /// Like [`Engine::set_audit_sink`], but sampling and redacting records as configured.
+    pub fn set_audit_sink_with(&self, sink: impl AuditSink, config: AuditConfig) {
+        self.audit.store(Some(Arc::new(Audit::new(sink, config))));
+    }
+
+    /// Stop recording decisions. Returns the number of records the sink failed to write.
+    pub fn clear_audit_sink(&self) -> Option<u64> {
+        self.audit.swap(None).map(|audit| audit.failures())
+    }
+
+    /// The number of records the installed sink failed to write.
+    pub fn audit_failures(&self) -> Option<u64> {
+        self.audit.load().as_ref().map(|audit| audit.failures())
+    }
+
+    /// Profile the installed policies from now on, restarting any running profile.
+    pub fn start_profiling(&self, config: ProfilerConfig) {
+        self.profiler.store(Some(Arc::new(Profiler::new(config))));
+    }
+
+    /// Stop profiling and return the final report.
+    pub fn stop_profiling(&self) -> Option<ProfileReport> {
+        self.profiler.swap(None).map(|profiler| profiler.report())
+    }
+
+    pub fn profile_report(&self) -> Option<ProfileReport> {
+        self.profiler
+            .load()
+            .as_ref()
+            .map(|profiler| profiler.report())
+    }
+
+    /// Evaluate every request from now on against `policies` as well, without affecting the
+    /// returned responses. `policies` are validated against the current schema and replace any
+    /// previous shadow, whose report is returned.
+    pub fn set_shadow(&self, policies: PolicySet) -> Result<Option<ShadowReport>> {
+        let state = self.state();
+        state.validation.validate(&state.schema, &policies)?;
+        let previous = self.shadow.swap(Some(Arc::new(Shadow::new(policies))));
+        Ok(previous.map(|shadow| shadow.report()))
+    }
+
+    /// Stop shadow evaluation and return the final report.
+    pub fn clear_shadow(&self) -> Option<ShadowReport> {
+        self.shadow.swap(None).map(|shadow| shadow.report())
+    }
+
+    pub fn shadow_report(&self) -> Option<ShadowReport> {
+        self.shadow.load().as_ref().map(|shadow| shadow.report())
+    }
+
+    /// Decide requests of a share of principals by `policies` from now on, until the rollout is
+    /// stopped, promoted or falls back. `policies` are validated against the current schema and
+    /// replace any previous rollout.
+    pub fn start_rollout(&self, policies: PolicySet, config: RolloutConfig) -> Result<()> {
+        let state = self.state();
+        state.validation.validate(&state.schema, &policies)?;
+        self.rollout
+            .store(Some(Arc::new(Rollout::new(policies, config))));
+        Ok(())
+    }
+
+    pub fn rollout_status(&self) -> Option<RolloutStatus> {
+        self.rollout.load().as_ref().map(|rollout| rollout.status())
+    }
+
+    /// Stop the rollout and return its final status. All requests are decided by the installed
+    /// policies again.
+    pub fn stop_rollout(&self) -> Option<RolloutStatus> {
+        self.rollout.swap(None).map(|rollout| rollout.status())
+    }
+
+    /// Install the candidate policies for all requests and end the rollout. Fails if no rollout
+    /// is running or it fell back.
+    pub fn promote_rollout(&self) -> Result<RolloutStatus> {
+        let rollout = self
+            .rollout
+            .load_full()
+            .ok_or_else(|| Error::NotFound("Running rollout".to_string()))?;
+        let status = rollout.status();
+        if status.fallen_back {
+            return Err(Error::Conflict(
+                "The rollout fell back and cannot be promoted".to_string(),
+            ));
+        }
+        self.update_policies(|_| Ok(rollout.policies.clone()))?;
+        // A rollout started meanwhile is kept.
+        self.rollout.compare_and_swap(&Some(rollout), None);
+        Ok(status)
+    }
+
+    /// Trace how every installed policy contributed to the decision on `request`, with the
+    /// current time injected as by [`Engine::is_authorized`]. The request is not recorded by a
+    /// shadow, rollout or audit sink.
+    pub fn explain(&self, request: &Request) -> Result<DecisionTrace> {
+        let injected = self.inject_time_into(request);
+        explain_decision(&self.active_state(), injected.as_ref().unwrap_or(request))
+    }
+
+    /// Check `context` against the context type of `action` in the installed schema, with the
+    /// path of every mismatch.
+    pub fn validate_context(
+        &self,
+        action: &EntityUid,
+        context: &serde_json::Value,
+    ) -> Result<Vec<FieldError>> {
+        validate_context(&self.state().schema_fragment, action, context)
+    }
+
+    /// Evaluate `request` against the installed policies matching `filter` only.
+    pub fn is_authorized_filtered(
+        &self,
+        request: &Request,
+        filter: &TagFilter,
+    ) -> Result<Response> {
+        let injected = self.inject_time_into(request);
+        let request = injected.as_ref().unwrap_or(request);
+        let state = self.active_state();
+        let policies = filter.apply(state.slice(request).unwrap_or(&state.tpe_policies))?;
+        let on_error = self.error_handling();
+        let response = on_error.apply(self.authorizer.is_authorized(
+            request,
+            &policies,
+            &state.entities,
+        ));
+        if let Some(audit) = &*self.audit.load() {
+            let fingerprint = state.fingerprint();
+            audit.record(
+                request,
+                &response,
+                &state.filtered_fingerprint(filter)?,
+                &fingerprint.entities,
+                on_error,
+            );
+        }
+        on_error.check(&response)?;
+        Ok(response)
+    }
+
+    /// Run type-aware partial evaluation for a request whose principal or resource ID may be
+    /// unknown. The residuals are recorded with the audit sink as a
+    /// [`PartialDecision`](crate::audit::PartialDecision).
+    pub fn tpe(
+        &self,
+        principal: PartialUid,
+        action: EntityUid,
+        resource: PartialUid,
+        context: Option<Context>,
+    ) -> Result<Residuals> {
+        self.audited_tpe(principal, action, resource, context, None)
+    }
+
+    /// Like [`Engine::tpe`], but against the installed policies matching `filter` only.
+    pub fn tpe_filtered(
+        &self,
+        principal: PartialUid,
+        action: EntityUid,
+        resource: PartialUid,
+        context: Option<Context>,
+        filter: &TagFilter,
+    ) -> Result<Residuals> {
+        self.audited_tpe(principal, action, resource, context, Some(filter))
+    }
+
+    fn audited_tpe(
+        &self,
+        principal: PartialUid,
+        action: EntityUid,
+        resource: PartialUid,
+        context: Option<Context>,
+        filter: Option<&TagFilter>,
+    ) -> Result<Residuals> {
+        let state = self.active_state();
+        let policies = state.tpe_policies_for(&action);
+        let filtered = filter.map(|filter| filter.apply(policies)).transpose()?;
+        let audit = self.audit.load();
+        // The request is only copied while auditing.
+        let call = audit
+            .is_some()
+            .then(|| (principal.clone(), resource.clone(), context.clone()));
+        let residuals = self.limited(|| {
+            tpe(
+                &state,
+                filtered.as_ref().unwrap_or(policies),
+                principal.into(),
+                action.clone(),
+                resource.into(),
+                context,
+            )
+        })?;
+        if let (Some(audit), Some((principal, resource, context))) = (&*audit, call) {
+            let fingerprint = state.fingerprint();
+            let policies_fingerprint = match filter {
+                Some(filter) => state.filtered_fingerprint(filter)?,
+                None => fingerprint.policies.clone(),
+            };
+            let ids = |ids: &mut dyn Iterator<Item = &PolicyId>| {
+                let mut ids = ids.map(|id| id_str(id).to_string()).collect::<Vec<_>>();
+                ids.sort();
+                ids
+            };
+            audit.record_partial(PartialDecision {
+                timestamp: chrono::Utc::now(),
+                principal,
+                action: action.to_string(),
+                resource,
+                context: context.as_ref().and_then(|c| context_json(c).ok()),
+                decision: residuals.decision(),
+                residuals: ids(&mut residuals.nontrivial_policies().map(Policy::id)),
+                errors: ids(&mut residuals.errors().iter()),
+                policies_fingerprint,
+                entities_fingerprint: fingerprint.entities.clone(),
+                on_error: self.error_handling(),
+            });
+        }
+        Ok(residuals)
+    }
+
+    /// All known resources of `resource_type` that `principal` may perform `action` on.
+    pub fn query_resources(
+        &self,
+        principal: EntityUid,
+        action: EntityUid,
+        resource_type: EntityTypeName,
+        context: Context,
+    ) -> Result<Vec<EntityUid>> {
+        let state = self.active_state();
+        let policies = state.tpe_bucket(principal.type_name(), &action, &resource_type);
+        let request =
+            ResourceQueryRequest::new(principal, action, resource_type, context, &state.schema)?;
+        let mut resources = policies
+            .query_resource(&request, &state.entities, &state.schema)?
+            .collect::<Vec<_>>();
+        resources.sort();
+        Ok(resources)
+    }
+
+    /// Partially evaluate a request with the resource ID unknown once, for deciding it for many
+    /// candidate resources of `resource_type` with [`ResourceFilter::decision`].
+    pub fn resource_filter(
+        &self,
+        principal: PartialUid,
+        action: EntityUid,
+        resource_type: EntityTypeName,
+        context: Option<Context>,
+    ) -> Result<ResourceFilter> {
+        let state = self.active_state();
+        let residuals = self.limited(|| {
+            tpe(
+                &state,
+                state.tpe_bucket(&principal.entity_type, &action, &resource_type),
+                principal.clone().into(),
+                action.clone(),
+                PartialEntityUid::new(resource_type.clone(), None),
+                context.clone(),
+            )
+        })?;
+        Ok(ResourceFilter::new(
+            state,
+            principal,
+            action,
+            resource_type,
+            context,
+            residuals,
+        ))
+    }
+
+    /// Describe all principals, known or not, that may perform `action` on `resource`: the
+    /// principal types, groups and attribute conditions each policy requires.
+    pub fn who_can(
+        &self,
+        action: EntityUid,
+        resource: EntityUid,
+        context: Option<Context>,
+    ) -> Result<PrincipalDescription> {
+        let state = self.active_state();
+        let principal_types = state
+            .schema
+            .principals_for_action(&action)
+            .ok_or_else(|| Error::NotFound(format!("Action `{action}`")))?
+            .cloned()
+            .collect::<BTreeSet<_>>();
+        let mut description = PrincipalDescription::default();
+        for principal_type in principal_types {
+            let residuals = self.limited(|| {
+                tpe(
+                    &state,
+                    state.tpe_bucket(&principal_type, &action, resource.type_name()),
+                    PartialEntityUid::new(principal_type.clone(), None),
+                    action.clone(),
+                    PartialEntityUid::from_concrete(resource.clone()),
+                    context.clone(),
+                )
+            })?;
+            description.add(&principal_type, &residuals)?;
+        }
+        Ok(description)
+    }
+
+    /// All known principals of `principal_type` that may perform `action` on `resource`.
+    pub fn query_principals(
+        &self,
+        principal_type: EntityTypeName,
+        action: EntityUid,
+        resource: EntityUid,
+        context: Context,
+    ) -> Result<Vec<EntityUid>> {
+        let state = self.active_state();
+        let policies = state.tpe_bucket(&principal_type, &action, resource.type_name());
+        let request =
+            PrincipalQueryRequest::new(principal_type, action, resource, context, &state.schema)?;
+        let mut principals = policies
+            .query_principal(&request, &state.entities, &state.schema)?
+            .collect::<Vec<_>>();
+        principals.sort();
+        Ok(principals)
+    }
+    /// Evaluate a request of `principal` on `resource` for every action that applies to
+    /// their types, all against the same state. The context is validated per action, so an
+    /// action whose context type it does not match has an error.
+    pub fn is_authorized_for_actions(
+        &self,
+        principal: &EntityUid,
+        resource: &EntityUid,
+        context: &Context,
+    ) -> BTreeMap<EntityUid, Result<Response>> {
+        let state = self.active_state();
+        applicable_actions(&state.schema, principal.type_name(), resource.type_name())
+            .map(|action| {
+                let response = Request::new(
+                    principal.clone(),
+                    action.clone(),
+                    resource.clone(),
+                    context.clone(),
+                    Some(&state.schema),
+                )
+                .map(|request| self.evaluate(&request, &state, None))
+                .map_err(Error::from);
+                (action, response)
+            })
+            .collect()
+    }
+
+    /// Like [`Engine::is_authorized_for_actions`], but runs type-aware partial evaluation
+    /// for principals or resources whose ID may be unknown.
+    pub fn tpe_for_actions(
+        &self,
+        principal: &PartialUid,
+        resource: &PartialUid,
+        context: Option<&Context>,
+    ) -> BTreeMap<EntityUid, Result<Residuals>> {
+        let state = self.active_state();
+        applicable_actions(&state.schema, &principal.entity_type, &resource.entity_type)
+            .map(|action| {
+                let residuals = self.limited(|| {
+                    tpe(
+                        &state,
+                        state.tpe_bucket(&principal.entity_type, &action, &resource.entity_type),
+                        principal.clone().into(),
+                        action.clone(),
+                        resource.clone().into(),
+                        context.cloned(),
+                    )
+                });
+                (action, residuals)
+            })
+            .collect()
+    }
+}
+
+/// The actions of `schema` that apply to principals of `principal` and resources of `resource`.
+fn applicable_actions<'a>(
+    schema: &'a Schema,
+    principal: &'a EntityTypeName,
+    resource: &'a EntityTypeName,
+) -> impl Iterator<Item = EntityUid> + 'a {
+    schema
+        .actions()
+        .filter(move |action| {
+            schema
+                .principals_for_action(action)
+                .is_some_and(|mut types| types.any(|t| t == principal))
+                && schema
+                    .resources_for_action(action)
+                    .is_some_and(|mut types| types.any(|t| t == resource))
+        })
+        .cloned()
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        name = "cedar.tpe",
+        skip_all,
+        fields(
+            policies = %state.fingerprint().policies,
+            schema = %state.fingerprint().schema,
+            residuals,
+        ),
+    )
+)]
+pub(crate) fn tpe(
+    state: &EngineState,
+    policies: &PolicySet,
+    principal: PartialEntityUid,
+    action: EntityUid,
+    resource: PartialEntityUid,
+    context: Option<Context>,
+) -> Result<Residuals> {
+    #[cfg(feature = "metrics")]
+    let start = std::time::Instant::now();
+    let request = PartialRequest::new(principal, action, resource, context, &state.schema)?;
+    let response = policies.tpe(&request, &state.partial_entities, &state.schema)?;
+    let residuals = Residuals::from_response(&response);
+    #[cfg(feature = "metrics")]
+    telemetry::tpe(start.elapsed(), residuals.nontrivial_policies().count());
+    #[cfg(feature = "tracing")]
+    tracing::Span::current().record("residuals", residuals.nontrivial_policies().count());
+    Ok(residuals)
+}
+
+// TPE only accepts static policies, so template-linked policies are partially evaluated as the
+// equivalent static policies. Templates themselves are not evaluated.
+pub(crate) fn static_policies(policies: &PolicySet) -> Result<PolicySet> {
+    if policies.templates().next().is_none() {
+        return Ok(policies.clone());
+    }
+    let policies = policies
+        .policies()
+        .map(|policy| {
+            if policy.is_static() {
+                return Ok(policy.clone());
+            }
+            let json = policy.to_json().map_err(PolicySetError::from)?;
+            Policy::from_json(Some(policy.id().clone()), json)
+                .map_err(|e| Error::from(PolicySetError::from(e)))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(PolicySet::from_policies(policies)?)
+}
+
+/// How policies are validated when they are installed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ValidationConfig {
+    pub mode: ValidationMode,
+    /// Install policies that fail validation and keep the failures as
+    /// [warnings](EngineState::validation_warnings), e.g. for legacy policies during a
+    /// migration. Such policies may error when they are evaluated.
+    pub warn_only: bool,
+}
+
+impl ValidationConfig {
+    /// The validation failures of `policies` if they are only warnings.
+    fn validate(&self, schema: &Schema, policies: &PolicySet) -> Result<Vec<Diagnostic>> {
+        let validator = Validator::new(schema.clone());
+        let result = validator.validate(policies, self.mode);
+        let failures = result
+            .validation_errors()
+            .map(Diagnostic::from_validation_error)
+            .collect::<Vec<_>>();
+        if failures.is_empty() || self.warn_only {
+            Ok(failures)
+        } else {
+            Err(Error::Validation(failures))
+        }
+    }
+}
+
+pub(crate) fn validate_policies(schema: &Schema, policies: &PolicySet) -> Result<()> {
+    ValidationConfig::default()
+        .validate(schema, policies)
+        .map(drop)
+}
+
+// Action entities are provided by the schema, so they are dropped and re-added here. This
+// keeps entities valid when the schema they were loaded with is replaced. The actions of the
+// replaced schema are recognized by their type, as schemas cannot declare entity types named
+// `Action`.
+pub(crate) fn validate_entities(schema: &Schema, entities: Entities) -> Result<Entities> {
+    let entities = entities
+        .into_iter()
+        .filter(|e| e.uid().type_name().basename() != "Action");
+    Ok(Entities::from_entities(entities, Some(schema))?)
+}
+
+/// Whether `entity` is one of the actions `schema` adds to entities.
+pub(crate) fn is_action(schema: &Schema, entity: &Entity) -> bool {
+    let uid = entity.uid();
+    schema.actions().any(|action| *action == uid)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use cedar_policy::{Decision, EntityUid};
+
+    use super::*;
+    use crate::{CEDAR_SCHEMA, CEDAR_SCHEMA_SRC, audit::AuditRecord};
+
+    fn request(principal: &str) -> Request {
+        Request::builder()
+            .principal(EntityUid::from_str(principal).unwrap())
+            .action(EntityUid::from_str("MyApp::Action::\"GetProjectMetadata\"").unwrap())
+            .resource(EntityUid::from_str("MyApp::Project::\"0\"").unwrap())
+            .schema(&CEDAR_SCHEMA)
+            .build()
+            .unwrap()
+    }
+
+    fn engine(policies: &str) -> Engine {
+        Engine::new(
+            SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(),
+            PolicySet::from_str(policies).unwrap(),
+            Entities::empty(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_replace_policies() {
+        let engine = engine(r#"permit (principal == MyApp::User::"0", action, resource);"#);
+        let request = request("MyApp::User::\"1\"");
+        assert_eq!(engine.is_authorized(&request).decision(), Decision::Deny);
+
+        engine
+            .replace_policies(
+                SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(),
+                PolicySet::from_str(r#"permit (principal == MyApp::User::"1", action, resource);"#)
+                    .unwrap(),
+            )
+            .unwrap();
+        assert_eq!(engine.is_authorized(&request).decision(), Decision::Allow);
+    }
+
+    #[test]
+    fn test_replace_schema() {
+        let engine = engine("");
+        let (locked, lock) = std::sync::mpsc::channel();
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                engine
+                    .update_policies(|policies| {
+                        locked.send(()).unwrap();
+                        // Gives the schema update time to wait for the lock.
+                        std::thread::sleep(std::time::Duration::from_millis(50));
+                        let mut policies = policies.clone();
+                        policies.add(
+                            Policy::parse(
+                                Some(PolicyId::new("p")),
+                                "permit (principal, action, resource);",
+                            )
+                            .unwrap(),
+                        )?;
+                        Ok(policies)
+                    })
+                    .unwrap();
+            });
+            lock.recv().unwrap();
+            let schema = format!("{CEDAR_SCHEMA_SRC}\nentity Tenant;");
+            engine
+                .replace_schema(SchemaFragment::from_str(&schema).unwrap())
+                .unwrap();
+        });
+        let state = engine.state();
+        assert_eq!(state.policies().num_of_policies(), 1);
+        assert!(
+            state
+                .schema()
+                .entity_types()
+                .any(|t| t.to_string() == "Tenant")
+        );
+
+        let invalid = SchemaFragment::from_str("entity Tenant;").unwrap();
+        assert!(matches!(
+            engine.replace_schema(invalid),
+            Err(Error::Validation(_))
+        ));
+        assert_eq!(engine.state().policies().num_of_policies(), 1);
+    }
+
+    #[test]
+    fn test_is_action() {
+        let entity = |uid: &str| Entity::with_uid(EntityUid::from_str(uid).unwrap());
+        let read = entity(r#"MyApp::Action::"GetProjectMetadata""#);
+        assert!(is_action(&CEDAR_SCHEMA, &read));
+        assert!(!is_action(
+            &CEDAR_SCHEMA,
+            &entity(r#"MyApp::Action::"Removed""#)
+        ));
+        assert!(!is_action(&CEDAR_SCHEMA, &entity(r#"MyApp::Role::"0""#)));
+    }
+
+    #[test]
+    fn test_replace_policies_keeps_state_on_validation_error() {
+        let engine = engine(r#"permit (principal == MyApp::User::"0", action, resource);"#);
+        let invalid =
+            PolicySet::from_str(r#"permit (principal == MyApp::Missing::"0", action, resource);"#)
+                .unwrap();
+
+        let err = engine
+            .replace_policies(SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(), invalid)
+            .unwrap_err();
+        assert!(matches!(err, Error::Validation(_)));
+        assert_eq!(
+            engine
+                .is_authorized(&request("MyApp::User::\"0\""))
+                .decision(),
+            Decision::Allow
+        );
+    }
+
+    #[test]
+    fn test_policy_crud() {
+        let engine = engine("");
+        let request = request(r#"MyApp::User::"0""#);
+        let policy = |src: &str| Policy::parse(Some(PolicyId::new("p")), src).unwrap();
+        engine
+            .add_policy(policy("permit (principal, action, resource);"))
+            .unwrap();
+        assert!(matches!(
+            engine.add_policy(policy("permit (principal, action, resource);")),
+            Err(Error::PolicySet(_))
+        ));
+        assert_eq!(engine.is_authorized(&request).decision(), Decision::Allow);
+
+        let src = "forbid (principal, action, resource) when { principal.missing };";
+        let Err(Error::Validation(diagnostics)) = engine.update_policy(policy(src)) else {
+            panic!("expected a validation error");
+        };
+        assert_eq!(diagnostics[0].policy_id, "p");
+        let (offset, len) = diagnostics[0].span.unwrap();
+        assert_eq!(&src[offset..offset + len], "principal.missing");
+        assert_eq!(engine.is_authorized(&request).decision(), Decision::Allow);
+
+        engine
+            .update_policy(policy("forbid (principal, action, resource);"))
+            .unwrap();
+        assert_eq!(engine.is_authorized(&request).decision(), Decision::Deny);
+        engine.remove_policy(PolicyId::new("p")).unwrap();
+        assert!(engine.remove_policy(PolicyId::new("p")).is_err());
+        assert_eq!(engine.state().policies().num_of_policies(), 0);
+    }
+
+    #[test]
+    fn test_subscribe() {
+        let engine = engine("");
+        let mut changes = engine.subscribe();
+        let policy = Policy::parse(
+            Some(PolicyId::new("p")),
+            "permit (principal, action, resource);",
+        )
+        .unwrap();
+        engine.add_policy(policy).unwrap();
+        let change = changes.try_recv().unwrap();
+        assert!(change.policies_changed && !change.schema_changed && !change.entities_changed);
+        assert_eq!(&change.fingerprint, engine.state().fingerprint());
+
+        // Installing equal content again is not a change.
+        engine.replace_entities(Entities::empty()).unwrap();
+        assert!(changes.try_recv().is_err());
+
+        let entities = Entities::from_json_value(
+            serde_json::json!([
+                { "uid": { "type": "MyApp::Server", "id": "0" }, "attrs": {}, "parents": [] }
+            ]),
+            Some(&CEDAR_SCHEMA),
+        )
+        .unwrap();
+        let before = engine.state();
+        engine.replace_entities(entities.clone()).unwrap();
+        assert!(changes.try_recv().unwrap().entities_changed);
+        // The policies are unchanged, so is their index.
+        assert!(Arc::ptr_eq(&before.index, &engine.state().index));
+        let reloaded = EngineState::new(
+            SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(),
+            engine.state().policies().clone(),
+            entities,
+        )
+        .unwrap();
+        assert_eq!(reloaded.fingerprint(), engine.state().fingerprint());
+    }
+
+    #[test]
+    fn test_shadow() {
+        let engine = engine("permit (principal, action, resource);");
+        assert!(
+            engine
+                .set_shadow(
+                    PolicySet::from_str(
+                        r#"permit (principal == MyApp::User::"0", action, resource);"#
+                    )
+                    .unwrap()
+                )
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(
+            engine
+                .is_authorized(&request(r#"MyApp::User::"0""#))
+                .decision(),
+            Decision::Allow
+        );
+        assert_eq!(
+            engine
+                .is_authorized(&request(r#"MyApp::User::"1""#))
+                .decision(),
+            Decision::Allow
+        );
+
+        let report = engine.clear_shadow().unwrap();
+        assert_eq!((report.evaluated, report.diverged), (2, 1));
+        let divergence = &report.recent[0];
+        assert_eq!(
+            divergence.request.principal().unwrap().to_string(),
+            r#"MyApp::User::"1""#
+        );
+        assert_eq!(divergence.shadow_decision, Decision::Deny);
+        assert!(engine.shadow_report().is_none());
+
+        let invalid =
+            PolicySet::from_str("permit (principal, action, resource) when { principal.missing };")
+                .unwrap();
+        assert!(matches!(
+            engine.set_shadow(invalid),
+            Err(Error::Validation(_))
+        ));
+    }
+
+    #[test]
+    fn test_rollout() {
+        let engine = engine("permit (principal, action, resource);");
+        let candidate = PolicySet::from_str(r#"forbid (principal, action, resource);"#).unwrap();
+        let config = RolloutConfig {
+            percentage: 50,
+            max_divergence_rate: 1.0,
+            max_error_rate: 1.0,
+            min_requests: 1,
+        };
+        engine.start_rollout(candidate.clone(), config).unwrap();
+        let decisions = (0..20)
+            .map(|i| {
+                let principal = format!(r#"MyApp::User::"{i}""#);
+                let decision = engine.is_authorized(&request(&principal)).decision();
+                // The same principal is always decided the same way.
+                assert_eq!(
+                    engine.is_authorized(&request(&principal)).decision(),
+                    decision
+                );
+                decision
+            })
+            .collect::<Vec<_>>();
+        assert!(decisions.contains(&Decision::Allow) && decisions.contains(&Decision::Deny));
+        let status = engine.stop_rollout().unwrap();
+        assert_eq!(status.canary_requests, status.diverged);
+        assert!(!status.fallen_back);
+
+        // Every canary decision diverges, so the rollout falls back after the first.
+        engine
+            .start_rollout(
+                candidate,
+                RolloutConfig {
+                    percentage: 100,
+                    max_divergence_rate: 0.5,
+                    ..config
+                },
+            )
+            .unwrap();
+        for i in 0..3 {
+            engine.is_authorized(&request(&format!(r#"MyApp::User::"{i}""#)));
+        }
+        let status = engine.rollout_status().unwrap();
+        assert_eq!((status.canary_requests, status.fallen_back), (1, true));
+        assert_eq!(
+            engine
+                .is_authorized(&request(r#"MyApp::User::"0""#))
+                .decision(),
+            Decision::Allow
+        );
+        assert!(matches!(engine.promote_rollout(), Err(Error::Conflict(_))));
+
+        engine
+            .start_rollout(
+                PolicySet::from_str(r#"permit (principal == MyApp::User::"0", action, resource);"#)
+                    .unwrap(),
+                RolloutConfig::default(),
+            )
+            .unwrap();
+        engine.promote_rollout().unwrap();
+        assert!(engine.rollout_status().is_none());
+        assert_eq!(
+            engine
+                .is_authorized(&request(r#"MyApp::User::"1""#))
+                .decision(),
+            Decision::Deny
+        );
+    }
+
+    #[test]
+    fn test_concurrent_swaps() {
+        let engine = Arc::new(engine(
+            r#"permit (principal == MyApp::User::"0", action, resource);"#,
+        ));
+        let readers = (0..4)
+            .map(|_| {
+                let engine = engine.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..200 {
+                        let state = engine.state();
+                        // A loaded state is never changed by concurrent swaps.
+                        let before = state.policies().policies().count();
+                        let _ = engine.is_authorized(&request("MyApp::User::\"0\""));
+                        assert_eq!(state.policies().policies().count(), before);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for i in 0..20 {
+            engine
+                .update_policies(|policies| {
+                    let mut policies = policies.clone();
+                    policies.add(
+                        Policy::parse(
+                            Some(PolicyId::new(format!("p{i}"))),
+                            "permit (principal, action, resource);",
+                        )
+                        .unwrap(),
+                    )?;
+                    Ok(policies)
+                })
+                .unwrap();
+        }
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        assert_eq!(engine.state().policies().policies().count(), 21);
+
+        let previous = engine.swap_state(
+            EngineState::new(
+                SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(),
+                PolicySet::new(),
+                Entities::empty(),
+            )
+            .unwrap(),
+        );
+        assert_eq!(previous.policies().policies().count(), 21);
+        assert_eq!(engine.state().policies().policies().count(), 0);
+    }
+
+    #[test]
+    fn test_tpe_and_queries() {
+        let entities = Entities::from_json_str(
+            r#"[
+                { "uid": { "type": "MyApp::Server", "id": "0" }, "attrs": {}, "parents": [] },
+                {
+                    "uid": { "type": "MyApp::Project", "id": "0" },
+                    "attrs": {},
+                    "parents": [{ "type": "MyApp::Server", "id": "0" }]
+                },
+                { "uid": { "type": "MyApp::Project", "id": "1" }, "attrs": {}, "parents": [] },
+                { "uid": { "type": "MyApp::User", "id": "0" }, "attrs": {}, "parents": [] }
+            ]"#,
+            Some(&CEDAR_SCHEMA),
+        )
+        .unwrap();
+        let engine = Engine::new(
+            SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(),
+            PolicySet::from_str(
+                r#"permit (principal == MyApp::User::"0", action, resource in MyApp::Server::"0");"#,
+            )
+            .unwrap(),
+            entities,
+        )
+        .unwrap();
+        let action = EntityUid::from_str("MyApp::Action::\"GetProjectMetadata\"").unwrap();
+        let user = EntityUid::from_str("MyApp::User::\"0\"").unwrap();
+
+        let residuals = engine
+            .tpe(
+                PartialUid::from(user.clone()),
+                action.clone(),
+                PartialUid::unknown(EntityTypeName::from_str("MyApp::Project").unwrap()),
+                None,
+            )
+            .unwrap();
+        assert_eq!(residuals.decision(), None);
+        assert_eq!(residuals.nontrivial_policies().count(), 1);
+
+        let resources = engine
+            .query_resources(
+                user.clone(),
+                action.clone(),
+                EntityTypeName::from_str("MyApp::Project").unwrap(),
+                Context::empty(),
+            )
+            .unwrap();
+        assert_eq!(
+            resources,
+            vec![EntityUid::from_str("MyApp::Project::\"0\"").unwrap()]
+        );
+
+        let principals = engine
+            .query_principals(
+                EntityTypeName::from_str("MyApp::User").unwrap(),
+                action,
+                EntityUid::from_str("MyApp::Project::\"0\"").unwrap(),
+                Context::empty(),
+            )
+            .unwrap();
+        assert_eq!(principals, vec![user]);
+    }
+
+    #[test]
+    fn test_who_can() {
+        let engine = engine(
+            r#"
+            permit (principal in MyApp::Role::"admin", action, resource);
+            permit (principal is MyApp::Role, action, resource) when { principal.project == resource };
+            permit (principal == MyApp::User::"0", action, resource is MyApp::Server);
+            forbid (principal in MyApp::Role::"banned", action, resource);
+            "#,
+        );
+        let description = engine
+            .who_can(
+                EntityUid::from_str(r#"MyApp::Action::"GetProjectMetadata""#).unwrap(),
+                EntityUid::from_str(r#"MyApp::Project::"0""#).unwrap(),
+                Some(Context::empty()),
+            )
+            .unwrap();
+        let permits = description
+            .permits
+            .iter()
+            .map(|g| {
+                (
+                    g.policy.as_str(),
+                    g.principal_type.as_str(),
+                    g.memberships.clone(),
+                    g.conditions.clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            permits,
+            vec![
+                (
+                    "policy0",
+                    "MyApp::Role",
+                    vec![r#"MyApp::Role::"admin""#.to_string()],
+                    vec![]
+                ),
+                (
+                    "policy1",
+                    "MyApp::Role",
+                    vec![],
+                    vec![r#"(principal.project) == MyApp::Project::"0""#.to_string()]
+                ),
+                (
+                    "policy0",
+                    "MyApp::User",
+                    vec![r#"MyApp::Role::"admin""#.to_string()],
+                    vec![]
+                ),
+            ]
+        );
+        assert_eq!(description.forbids.len(), 2);
+    }
+
+    #[test]
+    fn test_evaluation_across_actions() {
+        let engine = engine(
+            r#"permit (principal == MyApp::User::"0", action == MyApp::Action::"DeleteProject", resource);"#,
+        );
+        let user = EntityUid::from_str(r#"MyApp::User::"0""#).unwrap();
+        let project = EntityUid::from_str(r#"MyApp::Project::"0""#).unwrap();
+        let decisions = engine
+            .is_authorized_for_actions(&user, &project, &Context::empty())
+            .into_iter()
+            .map(|(action, response)| {
+                (
+                    action.id().unescaped().to_string(),
+                    response.unwrap().decision(),
+                )
+            })
+            .collect::<Vec<_>>();
+        // Server actions do not apply to projects.
+        assert_eq!(
+            decisions,
+            [
+                ("DeleteProject".to_string(), Decision::Allow),
+                ("GetProjectMetadata".to_string(), Decision::Deny),
+            ]
+        );
+
+        let residuals = engine.tpe_for_actions(
+            &PartialUid::unknown("MyApp::User".parse().unwrap()),
+            &project.into(),
+            Some(&Context::empty()),
+        );
+        assert_eq!(residuals.len(), 2);
+        assert!(residuals.values().all(Result::is_ok));
+    }
+
+    #[test]
+    fn test_tpe_with_template_links() {
+        let engine = engine("");
+        engine
+            .add_template(
+                Template::parse(
+                    Some(PolicyId::new("owner")),
+                    "permit (principal == ?principal, action, resource in ?resource);",
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        let user = EntityUid::from_str(r#"MyApp::User::"0""#).unwrap();
+        let slots = |resource: &str| {
+            HashMap::from([
+                (SlotId::principal(), user.clone()),
+                (SlotId::resource(), EntityUid::from_str(resource).unwrap()),
+            ])
+        };
+        engine
+            .link(
+                PolicyId::new("owner"),
+                PolicyId::new("owner-0"),
+                slots(r#"MyApp::Server::"0""#),
+            )
+            .unwrap();
+        let err = engine
+            .link(
+                PolicyId::new("owner"),
+                PolicyId::new("owner-1"),
+                slots(r#"MyApp::Missing::"0""#),
+            )
+            .unwrap_err();
+        assert!(matches!(err, Error::Validation(_)));
+
+        let residuals = engine
+            .tpe(
+                PartialUid::from(user),
+                EntityUid::from_str("MyApp::Action::\"GetProjectMetadata\"").unwrap(),
+                PartialUid::unknown(EntityTypeName::from_str("MyApp::Project").unwrap()),
+                None,
+            )
+            .unwrap();
+        assert_eq!(residuals.decision(), None);
+        let ids = residuals
+            .nontrivial_policies()
+            .map(|p| p.id().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["owner-0"]);
+
+        engine.unlink(PolicyId::new("owner-0")).unwrap();
+        engine.remove_template(PolicyId::new("owner")).unwrap();
+    }
+
+    #[test]
+    fn test_filtered_evaluation() {
+        let engine = engine(
+            r#"
+            permit (principal, action, resource);
+            @tags("experimental")
+            forbid (principal, action, resource);
+            "#,
+        );
+        let request = request("MyApp::User::\"0\"");
+        let production = TagFilter::default().exclude("experimental");
+        assert_eq!(engine.is_authorized(&request).decision(), Decision::Deny);
+        assert_eq!(
+            engine
+                .is_authorized_filtered(&request, &production)
+                .unwrap()
+                .decision(),
+            Decision::Allow
+        );
+
+        let residuals = engine
+            .tpe_filtered(
+                PartialUid::unknown(EntityTypeName::from_str("MyApp::User").unwrap()),
+                EntityUid::from_str("MyApp::Action::\"GetProjectMetadata\"").unwrap(),
+                PartialUid::unknown(EntityTypeName::from_str("MyApp::Project").unwrap()),
+                None,
+                &production,
+            )
+            .unwrap();
+        assert_eq!(residuals.decision(), Some(Decision::Allow));
+    }
+
+    #[test]
+    fn test_error_handling() {
+        #[derive(Debug, Clone, Default)]
+        struct Records(Arc<std::sync::Mutex<Vec<AuditRecord>>>);
+
+        impl AuditSink for Records {
+            fn record(&self, record: &AuditRecord) -> std::io::Result<()> {
+                self.0.lock().unwrap().push(record.clone());
+                Ok(())
+            }
+        }
+
+        // The role is not in the entities, so reading its project errors.
+        let engine = engine(
+            r#"
+            permit (principal, action, resource);
+            permit (principal is MyApp::Role, action, resource)
+                when { principal.project == resource };
+            "#,
+        );
+        let records = Records::default();
+        engine.set_audit_sink(records.clone());
+        let role = request(r#"MyApp::Role::"0""#);
+        assert_eq!(engine.error_handling(), ErrorHandling::Ignore);
+        assert_eq!(engine.is_authorized(&role).decision(), Decision::Allow);
+
+        engine.set_error_handling(ErrorHandling::Deny);
+        let response = engine.is_authorized(&role);
+        assert_eq!(response.decision(), Decision::Deny);
+        assert_eq!(response.diagnostics().errors().count(), 1);
+        assert_eq!(
+            engine
+                .is_authorized(&request(r#"MyApp::User::"0""#))
+                .decision(),
+            Decision::Allow
+        );
+
+        engine.set_error_handling(ErrorHandling::Fail);
+        assert!(matches!(
+            engine.try_is_authorized(&role),
+            Err(Error::PolicyEvaluation(errors)) if errors.len() == 1
+        ));
+
+        engine.clear_audit_sink();
+        let modes = records
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|r| r.on_error)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            modes,
+            [
+                ErrorHandling::Ignore,
+                ErrorHandling::Deny,
+                ErrorHandling::Deny,
+                ErrorHandling::Fail
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tpe_error_handling() {
+        let engine = engine(
+            r#"
+            permit (principal, action, resource);
+            forbid (principal, action, resource)
+                when { principal in MyApp::Role::"admin" && 9223372036854775807 + 1 > 0 };
+            "#,
+        );
+        let tpe = || {
+            engine.tpe(
+                PartialUid::unknown(EntityTypeName::from_str("MyApp::User").unwrap()),
+                EntityUid::from_str(r#"MyApp::Action::"GetProjectMetadata""#).unwrap(),
+                PartialUid::from(EntityUid::from_str(r#"MyApp::Project::"0""#).unwrap()),
+                None,
+            )
+        };
+        // The forbid errors whenever it is satisfied, so Cedar skips it.
+        let residuals = tpe().unwrap();
+        assert_eq!(residuals.decision(), Some(Decision::Allow));
+        assert_eq!(residuals.errors(), [PolicyId::new("policy1")]);
+        assert_eq!(residuals.policies().count(), 1);
+        assert_eq!(residuals.est()["errors"], serde_json::json!(["policy1"]));
+        let roundtrip = serde_json::from_value::<Residuals>(residuals.est()).unwrap();
+        assert_eq!(roundtrip.errors(), residuals.errors());
+
+        engine.set_error_handling(ErrorHandling::Deny);
+        assert_eq!(tpe().unwrap().decision(), Some(Decision::Deny));
+        engine.set_error_handling(ErrorHandling::Fail);
+        assert!(matches!(
+            tpe(),
+            Err(Error::PolicyEvaluation(errors)) if errors.len() == 1
+        ));
+    }
+
+    #[test]
+    fn test_validation_config() {
+        // Sets of mixed entity types only validate in permissive mode.
+        let legacy = PolicySet::from_str(
+            r#"permit (principal, action, resource)
+                when { [MyApp::User::"0", MyApp::Project::"0"].contains(resource) };"#,
+        )
+        .unwrap();
+        let schema = || SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap();
+        let err = Engine::new(schema(), legacy.clone(), Entities::empty()).unwrap_err();
+        assert!(matches!(err, Error::Validation(_)));
+
+        let permissive = ValidationConfig {
+            mode: ValidationMode::Permissive,
+            ..Default::default()
+        };
+        let engine =
+            Engine::with_validation(schema(), legacy.clone(), Entities::empty(), permissive)
+                .unwrap();
+        assert_eq!(
+            engine
+                .is_authorized(&request(r#"MyApp::User::"0""#))
+                .decision(),
+            Decision::Allow
+        );
+        // Updates are validated the same way.
+        let invalid =
+            Policy::from_str("permit (principal, action, resource) when { principal.missing };")
+                .unwrap();
+        assert!(
+            engine
+                .add_policy(invalid.new_id(PolicyId::new("invalid")))
+                .is_err()
+        );
+
+        let warn_only = ValidationConfig {
+            warn_only: true,
+            ..Default::default()
+        };
+        let engine =
+            Engine::with_validation(schema(), legacy, Entities::empty(), warn_only).unwrap();
+        assert_eq!(engine.state().validation_warnings().len(), 1);
+        engine.remove_policy(PolicyId::new("policy0")).unwrap();
+        assert!(engine.state().validation_warnings().is_empty());
+        assert_eq!(engine.state().validation(), warn_only);
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn test_tracing_spans() {
+        use tracing::{
+            Subscriber,
+            field::{Field, Visit},
+            span::{Attributes, Id, Record},
+        };
+        use tracing_subscriber::{Layer, layer::Context as LayerContext, prelude::*};
+
+        type Spans = Arc<Mutex<HashMap<String, HashMap<String, String>>>>;
+
+        struct Fields<'a>(&'a mut HashMap<String, String>);
+
+        impl Visit for Fields<'_> {
+            fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+                self.0
+                    .insert(field.name().to_string(), format!("{value:?}"));
+            }
+        }
+
+        struct Collect(Spans, Mutex<HashMap<Id, String>>);
+
+        impl<S: Subscriber> Layer<S> for Collect {
+            fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, _: LayerContext<'_, S>) {
+                let name = attrs.metadata().name().to_string();
+                let mut spans = self.0.lock().unwrap();
+                attrs.record(&mut Fields(spans.entry(name.clone()).or_default()));
+                self.1.lock().unwrap().insert(id.clone(), name);
+            }
+
+            fn on_record(&self, id: &Id, values: &Record<'_>, _: LayerContext<'_, S>) {
+                let name = self.1.lock().unwrap()[id].clone();
+                values.record(&mut Fields(self.0.lock().unwrap().get_mut(&name).unwrap()));
+            }
+        }
+
+        let spans = Spans::default();
+        let subscriber =
+            tracing_subscriber::registry().with(Collect(spans.clone(), Mutex::default()));
+        let engine = engine("permit (principal, action, resource);");
+        tracing::subscriber::with_default(subscriber, || {
+            engine.is_authorized(&request("MyApp::User::\"0\""));
+            engine
+                .tpe(
+                    PartialUid::unknown(EntityTypeName::from_str("MyApp::User").unwrap()),
+                    EntityUid::from_str("MyApp::Action::\"GetProjectMetadata\"").unwrap(),
+                    PartialUid::unknown(EntityTypeName::from_str("MyApp::Project").unwrap()),
+                    None,
+                )
+                .unwrap();
+        });
+
+        let spans = spans.lock().unwrap();
+        let fingerprint = engine.state().fingerprint().clone();
+        let authorize = &spans["cedar.authorize"];
+        assert_eq!(authorize["policies"], fingerprint.policies);
+        assert_eq!(authorize["schema"], fingerprint.schema);
+        assert_eq!(authorize["decision"], "Allow");
+        assert_eq!(spans["cedar.tpe"]["residuals"], "0");
+        assert!(spans.contains_key("cedar.residuals"));
+    }
+}
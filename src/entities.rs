@@ -0,0 +1,149 @@
+//! Entities in Cedar's JSON format, indexed without copying the input.
+//!
+//! [`cedar_policy::Entities::from_json_str`] builds every entity of the input at once, with all
+//! its strings allocated. [`EntityJson`] only borrows each entity's UID and JSON from the input,
+//! so entities can be filtered before any is built, and only the ones retained are allocated
+//! when [`EntityJson::to_entities`] builds them.
+
+use std::borrow::Cow;
+
+use cedar_policy::{Entities, Entity, Schema};
+use serde::Deserialize;
+use serde_json::value::RawValue;
+
+use crate::error::{Error, Result};
+
+/// The entities of a JSON array, borrowed from it.
+#[derive(Debug, Clone)]
+pub struct EntityJson<'a> {
+    entities: Vec<(RawUid<'a>, &'a RawValue)>,
+}
+
+/// An entity UID in the `{ "type", "id" }` or the `{ "__entity": { .. } }` form. Strings are
+/// only copied if they contain escapes.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum Uid<'a> {
+    Explicit {
+        #[serde(borrow, rename = "__entity")]
+        entity: RawUid<'a>,
+    },
+    Implicit(#[serde(borrow)] RawUid<'a>),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct RawUid<'a> {
+    #[serde(borrow, rename = "type")]
+    type_name: Cow<'a, str>,
+    #[serde(borrow)]
+    id: Cow<'a, str>,
+}
+
+#[derive(Deserialize)]
+struct RawEntity<'a> {
+    #[serde(borrow)]
+    uid: Uid<'a>,
+}
+
+impl<'a> EntityJson<'a> {
+    /// Index the entities of `json`. Only their UIDs are parsed; the rest is checked to be
+    /// valid JSON, and parsed by Cedar when the entities are built.
+    pub fn parse(json: &'a str) -> Result<Self> {
+        let invalid = |e: serde_json::Error| Error::Mapping(format!("Invalid entity JSON: {e}"));
+        let raw = serde_json::from_str::<Vec<&'a RawValue>>(json).map_err(invalid)?;
+        let entities = raw
+            .into_iter()
+            .map(|entity| {
+                let uid = match serde_json::from_str::<RawEntity<'a>>(entity.get()) {
+                    Ok(RawEntity {
+                        uid: Uid::Explicit { entity: uid } | Uid::Implicit(uid),
+                    }) => uid,
+                    Err(e) => return Err(invalid(e)),
+                };
+                Ok((uid, entity))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { entities })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    /// The type name and ID of each entity, in input order.
+    pub fn uids(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entities
+            .iter()
+            .map(|(uid, _)| (uid.type_name.as_ref(), uid.id.as_ref()))
+    }
+
+    /// Keep only the entities for whose type name and ID `f` returns `true`.
+    pub fn retain(&mut self, mut f: impl FnMut(&str, &str) -> bool) {
+        self.entities.retain(|(uid, _)| f(&uid.type_name, &uid.id));
+    }
+
+    /// Build the retained entities, validated against `schema` if given as by
+    /// [`Entities::from_json_str`].
+    pub fn to_entities(&self, schema: Option<&Schema>) -> Result<Entities> {
+        let entities = self
+            .entities
+            .iter()
+            .map(|(_, json)| Ok(Entity::from_json_str(json.get(), schema)?))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Entities::from_entities(entities, schema)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CEDAR_SCHEMA;
+
+    const ENTITIES: &str = r#"[
+        { "uid": { "type": "MyApp::Server", "id": "0" }, "attrs": {}, "parents": [] },
+        {
+            "uid": { "type": "MyApp::Project", "id": "0" },
+            "attrs": {},
+            "parents": [{ "type": "MyApp::Server", "id": "0" }]
+        }
+    ]"#;
+
+    #[test]
+    fn test_entity_json() {
+        let mut json = EntityJson::parse(ENTITIES).unwrap();
+        let all = Entities::from_json_str(ENTITIES, Some(&CEDAR_SCHEMA)).unwrap();
+        assert_eq!(json.to_entities(Some(&CEDAR_SCHEMA)).unwrap(), all);
+        let uids = json.uids().collect::<Vec<_>>();
+        assert_eq!(uids, [("MyApp::Server", "0"), ("MyApp::Project", "0")]);
+        // Unescaped strings are borrowed from the input.
+        let (type_name, _) = uids[0];
+        assert!(
+            ENTITIES
+                .as_bytes()
+                .as_ptr_range()
+                .contains(&type_name.as_ptr())
+        );
+
+        json.retain(|type_name, _| type_name == "MyApp::Project");
+        let projects = json.to_entities(Some(&CEDAR_SCHEMA)).unwrap();
+        // The schema adds its actions.
+        let uids = projects
+            .iter()
+            .filter(|e| !crate::engine::is_action(&CEDAR_SCHEMA, e))
+            .map(|e| e.uid().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(uids, [r#"MyApp::Project::"0""#]);
+        assert_eq!(json.len(), 1);
+
+        let escaped = r#"[{ "uid": { "__entity": { "type": "User", "id": "a\"b" } },
+            "attrs": {}, "parents": [] }]"#;
+        let json = EntityJson::parse(escaped).unwrap();
+        assert_eq!(json.uids().collect::<Vec<_>>(), [("User", r#"a"b"#)]);
+        assert!(EntityJson::parse(r#"[{ "attrs": {} }]"#).is_err());
+        assert!(EntityJson::parse("[").is_err());
+    }
+}
@@ -0,0 +1,301 @@
+use std::collections::HashSet;
+
+use cedar_policy::{
+    CedarSchemaError, ContextJsonError, Decision, ParseErrors, PartialRequestCreationError,
+    PermissionQueryError, PolicySetError, RequestValidationError, Response, SchemaError,
+    ValidationError, entities_errors::EntitiesError, tpe_err,
+};
+use itertools::Itertools;
+
+use crate::residuals::Residuals;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+// Cedar's error types are large, so they are boxed to keep `Result<T>` small.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Failed to parse schema: {0}")]
+    Schema(#[source] Box<SchemaError>),
+    #[error("Failed to parse Cedar schema: {0}")]
+    CedarSchema(#[source] Box<CedarSchemaError>),
+    #[error("Failed to parse policies: {0}")]
+    Policies(#[source] Box<ParseErrors>),
+    #[error("Failed to build policy set: {0}")]
+    PolicySet(#[source] Box<PolicySetError>),
+    #[error("Failed to load entities: {0}")]
+    Entities(#[source] Box<EntitiesError>),
+    #[error("Failed to load partial entities: {0}")]
+    PartialEntities(#[source] Box<tpe_err::EntitiesError>),
+    #[error("Failed to parse context: {0}")]
+    Context(#[source] Box<ContextJsonError>),
+    #[error("Invalid request: {0}")]
+    Request(#[source] Box<RequestValidationError>),
+    #[error("Invalid partial request: {0}")]
+    PartialRequest(#[source] Box<PartialRequestCreationError>),
+    #[error("Type-aware partial evaluation failed: {0}")]
+    Tpe(#[source] Box<tpe_err::TpeError>),
+    #[error("Permission query failed: {0}")]
+    PermissionQuery(#[source] Box<PermissionQueryError>),
+    #[error("Failed to map input to a request: {0}")]
+    Mapping(String),
+    #[error("Invalid bearer token: {0}")]
+    Token(String),
+    #[error("Policies failed validation against the schema:\n{}", .0.iter().join("\n"))]
+    Validation(Vec<Diagnostic>),
+    #[error("Failed to access `{}`: {source}", path.display())]
+    Io {
+        path: std::path::PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("In `{}`: {source}", path.display())]
+    File {
+        path: std::path::PathBuf,
+        #[source]
+        source: Box<Error>,
+    },
+    #[error("For tenant `{tenant}`: {source}")]
+    Tenant {
+        tenant: String,
+        #[source]
+        source: Box<Error>,
+    },
+    #[error("Invalid annotations on policy `{id}`: {source}")]
+    Annotations {
+        id: String,
+        #[source]
+        source: serde_json::Error,
+    },
+    #[error("Invalid bundle: {0}")]
+    Bundle(String),
+    #[error("Invalid compiled policies: {0}")]
+    Compiled(String),
+    #[error("Invalid scenario file: {0}")]
+    Scenario(String),
+    #[error("Invalid policy source template: {0}")]
+    SourceTemplate(String),
+    #[error("Policy store is read-only")]
+    ReadOnly,
+    #[error("Not found: {0}")]
+    NotFound(String),
+    #[error("Conflicting update: {0}")]
+    Conflict(String),
+    #[error("Policy store request failed: {0}")]
+    Store(#[source] Box<dyn std::error::Error + Send + Sync>),
+    #[error("Not supported: {0}")]
+    Unsupported(&'static str),
+    #[error("Symbolic analysis failed: {0}")]
+    Symbolic(#[source] Box<dyn std::error::Error + Send + Sync>),
+    #[error("Request to the AVP policy store failed: {0}")]
+    Avp(#[source] Box<dyn std::error::Error + Send + Sync>),
+    #[error("Request to the policy decision point failed: {0}")]
+    Remote(#[source] Box<dyn std::error::Error + Send + Sync>),
+    #[error("Evaluation limit exceeded: {0}")]
+    LimitExceeded(String),
+    #[error("Policies failed to evaluate:\n{}", .0.iter().join("\n"))]
+    PolicyEvaluation(Vec<String>),
+}
+
+impl Error {
+    /// A stable, machine-readable code for the category of the error, e.g. for services to
+    /// branch on. Errors in a file or for a tenant have the code of their source.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Schema(_) | Self::CedarSchema(_) => "invalid_schema",
+            Self::Policies(_) => "invalid_policy_syntax",
+            Self::PolicySet(_) => "invalid_policy_set",
+            Self::Entities(_) | Self::PartialEntities(_) => "invalid_entities",
+            Self::Context(_) => "invalid_context",
+            Self::Request(_) | Self::PartialRequest(_) => "invalid_request",
+            Self::Tpe(_) => "partial_evaluation_failed",
+            Self::PermissionQuery(_) => "query_failed",
+            Self::Mapping(_) => "mapping_failed",
+            Self::Token(_) => "invalid_token",
+            Self::Validation(_) => "validation_failed",
+            Self::Io { .. } => "io_failed",
+            Self::File { source, .. } | Self::Tenant { source, .. } => source.code(),
+            Self::Annotations { .. } => "invalid_annotations",
+            Self::Bundle(_) => "invalid_bundle",
+            Self::Compiled(_) => "invalid_compiled_policies",
+            Self::Scenario(_) => "invalid_scenario",
+            Self::SourceTemplate(_) => "invalid_source_template",
+            Self::ReadOnly => "read_only",
+            Self::NotFound(_) => "not_found",
+            Self::Conflict(_) => "conflict",
+            Self::Store(_) => "store_failed",
+            Self::Unsupported(_) => "unsupported",
+            Self::Symbolic(_) => "symbolic_analysis_failed",
+            Self::Avp(_) => "avp_failed",
+            Self::Remote(_) => "remote_failed",
+            Self::LimitExceeded(_) => "limit_exceeded",
+            Self::PolicyEvaluation(_) => "policy_evaluation_failed",
+        }
+    }
+
+    /// Byte offset and length of the offending part of the policy or schema source, if known.
+    /// For validation errors, the span of the first failure that has one.
+    pub fn span(&self) -> Option<(usize, usize)> {
+        use miette::Diagnostic as _;
+        let labels = match self {
+            Self::Policies(e) => e.labels(),
+            Self::CedarSchema(e) => e.labels(),
+            Self::Validation(diagnostics) => return diagnostics.iter().find_map(|d| d.span),
+            Self::File { source, .. } | Self::Tenant { source, .. } => return source.span(),
+            _ => None,
+        };
+        labels
+            .and_then(|mut labels| labels.next())
+            .map(|label| (label.offset(), label.len()))
+    }
+}
+
+/// How policies that fail to evaluate affect a decision, set with
+/// [`crate::Engine::set_error_handling`]. For partial evaluation, a policy counts as failing if
+/// its residual may error, see [`crate::Residuals::errors`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorHandling {
+    /// Skip the erroring policies, as Cedar does: the other policies decide.
+    #[default]
+    Ignore,
+    /// Deny the request if any policy errored.
+    Deny,
+    /// Fail the fallible entry points, such as [`crate::Engine::try_is_authorized`], with
+    /// [`Error::PolicyEvaluation`]. The infallible ones deny.
+    Fail,
+}
+
+impl ErrorHandling {
+    /// `response` denied if policies errored and erroring policies are not ignored. The errors
+    /// are kept, but the reasons are dropped.
+    pub(crate) fn apply(self, response: Response) -> Response {
+        if self == Self::Ignore || response.diagnostics().errors().next().is_none() {
+            return response;
+        }
+        let errors = response.diagnostics().errors().cloned().collect();
+        Response::new(Decision::Deny, HashSet::new(), errors)
+    }
+
+    /// `residuals` denied if policies may error and erroring policies are not ignored, or an
+    /// error if errors are propagated. Partial evaluation has no infallible entry points.
+    pub(crate) fn apply_residuals(self, residuals: Residuals) -> Result<Residuals> {
+        if residuals.errors().is_empty() {
+            return Ok(residuals);
+        }
+        match self {
+            Self::Ignore => Ok(residuals),
+            Self::Deny => Ok(residuals.denied()),
+            Self::Fail => Err(Error::PolicyEvaluation(
+                residuals
+                    .errors()
+                    .iter()
+                    .map(|id| format!("Policy `{id}` may fail to evaluate"))
+                    .collect(),
+            )),
+        }
+    }
+
+    /// Fails if policies errored and errors are propagated.
+    pub(crate) fn check(self, response: &Response) -> Result<()> {
+        let errors = response
+            .diagnostics()
+            .errors()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        match self {
+            Self::Fail if !errors.is_empty() => Err(Error::PolicyEvaluation(errors)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A validation error of a single policy.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Diagnostic {
+    pub policy_id: String,
+    pub message: String,
+    pub help: Option<String>,
+    /// Byte offset and length of the offending part of the policy source, if known.
+    pub span: Option<(usize, usize)>,
+}
+
+impl Diagnostic {
+    pub(crate) fn from_validation_error(e: &ValidationError) -> Self {
+        use miette::Diagnostic as _;
+        Self {
+            policy_id: AsRef::<str>::as_ref(e.policy_id()).to_string(),
+            message: e.to_string(),
+            help: e.help().map(|help| help.to_string()),
+            span: e
+                .labels()
+                .and_then(|mut labels| labels.next())
+                .map(|label| (label.offset(), label.len())),
+        }
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+macro_rules! impl_from_boxed {
+    ($($source:ty => $variant:ident),* $(,)?) => {
+        $(
+            impl From<$source> for Error {
+                fn from(e: $source) -> Self {
+                    Self::$variant(Box::new(e))
+                }
+            }
+        )*
+    };
+}
+
+impl_from_boxed!(
+    SchemaError => Schema,
+    CedarSchemaError => CedarSchema,
+    ParseErrors => Policies,
+    PolicySetError => PolicySet,
+    EntitiesError => Entities,
+    tpe_err::EntitiesError => PartialEntities,
+    ContextJsonError => Context,
+    RequestValidationError => Request,
+    PartialRequestCreationError => PartialRequest,
+    tpe_err::TpeError => Tpe,
+    PermissionQueryError => PermissionQuery,
+);
+
+#[cfg(feature = "postgres")]
+impl From<sqlx::Error> for Error {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Store(Box::new(e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use cedar_policy::PolicySet;
+
+    use super::*;
+
+    #[test]
+    fn test_codes_and_spans() {
+        let src = "permit (principal, action, resource) when { principal. };";
+        let err = Error::from(PolicySet::from_str(src).unwrap_err());
+        assert_eq!(err.code(), "invalid_policy_syntax");
+        let (offset, len) = err.span().unwrap();
+        assert!(offset + len <= src.len());
+
+        let err = Error::File {
+            path: "policies.cedar".into(),
+            source: Box::new(err),
+        };
+        assert_eq!(err.code(), "invalid_policy_syntax");
+        assert_eq!(err.span(), Some((offset, len)));
+        assert_eq!(Error::ReadOnly.code(), "read_only");
+        assert_eq!(Error::ReadOnly.span(), None);
+    }
+}
@@ -0,0 +1,203 @@
+//! Builders for Cedar's `datetime`, `duration` and `decimal` extension values from Rust types,
+//! for contexts and entity attributes. Addresses and ranges are in [`crate::ip`].
+//!
+//! Cedar keeps milliseconds and four decimal places, so finer precision is truncated.
+
+use std::time::Duration;
+
+use cedar_policy::RestrictedExpression;
+use chrono::{DateTime, Datelike, TimeDelta, TimeZone, Utc};
+use serde_json::{Value, json};
+
+use crate::error::{Error, Result};
+
+/// An extension value: the extension function and its string argument.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ExtensionValue {
+    function: &'static str,
+    arg: String,
+}
+
+impl ExtensionValue {
+    /// A `datetime` in UTC. Fails for years outside `0..=9999`, which Cedar cannot represent.
+    pub fn datetime<Tz: TimeZone>(value: &DateTime<Tz>) -> Result<Self> {
+        let value = value.with_timezone(&Utc);
+        if !(0..=9999).contains(&value.year()) {
+            return Err(Error::Mapping(format!(
+                "`{value}` is outside the years Cedar represents"
+            )));
+        }
+        Ok(Self::new(
+            "datetime",
+            value.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string(),
+        ))
+    }
+
+    pub fn duration(value: TimeDelta) -> Self {
+        Self::new("duration", duration_arg(value.num_milliseconds()))
+    }
+
+    pub fn std_duration(value: Duration) -> Result<Self> {
+        let millis = i64::try_from(value.as_millis())
+            .map_err(|_| Error::Mapping(format!("{value:?} is too long for a duration")))?;
+        Ok(Self::new("duration", duration_arg(millis)))
+    }
+
+    /// A `decimal` of `units` ten-thousandths, e.g. `12_500` for `1.25`.
+    pub fn decimal(units: i64) -> Self {
+        let sign = if units < 0 { "-" } else { "" };
+        let (whole, fraction) = (units.unsigned_abs() / 10_000, units.unsigned_abs() % 10_000);
+        Self::new("decimal", format!("{sign}{whole}.{fraction:04}"))
+    }
+
+    /// A `decimal` from its string form, e.g. `-1.25`, with at most four decimal places.
+    pub fn parse_decimal(src: &str) -> Result<Self> {
+        let invalid = || Error::Mapping(format!("Invalid decimal `{src}`"));
+        let (whole, fraction) = src.trim().split_once('.').unwrap_or((src.trim(), "0"));
+        let digits = whole.strip_prefix('-').unwrap_or(whole);
+        if digits.is_empty()
+            || fraction.is_empty()
+            || fraction.len() > 4
+            || !digits
+                .bytes()
+                .chain(fraction.bytes())
+                .all(|b| b.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+        let units = format!("{whole}{fraction:0<4}")
+            .parse::<i64>()
+            .map_err(|_| invalid())?;
+        Ok(Self::decimal(units))
+    }
+
+    #[cfg(feature = "time")]
+    pub fn offset_datetime(value: &time::OffsetDateTime) -> Result<Self> {
+        let timestamp = i64::try_from(value.unix_timestamp_nanos() / 1_000_000)
+            .ok()
+            .and_then(DateTime::from_timestamp_millis)
+            .ok_or_else(|| Error::Mapping(format!("`{value}` is out of range")))?;
+        Self::datetime(&timestamp)
+    }
+
+    #[cfg(feature = "time")]
+    pub fn time_duration(value: time::Duration) -> Result<Self> {
+        let millis = i64::try_from(value.whole_milliseconds())
+            .map_err(|_| Error::Mapping(format!("{value} is too long for a duration")))?;
+        Ok(Self::new("duration", duration_arg(millis)))
+    }
+
+    fn new(function: &'static str, arg: String) -> Self {
+        Self { function, arg }
+    }
+
+    pub fn function(&self) -> &str {
+        self.function
+    }
+
+    pub fn arg(&self) -> &str {
+        &self.arg
+    }
+
+    /// The value for [`cedar_policy::Context::from_pairs`] or an entity attribute.
+    pub fn to_expression(&self) -> RestrictedExpression {
+        match self.function {
+            "datetime" => RestrictedExpression::new_datetime(&self.arg),
+            "duration" => RestrictedExpression::new_duration(&self.arg),
+            _ => RestrictedExpression::new_decimal(&self.arg),
+        }
+    }
+
+    /// The value in Cedar's JSON format.
+    pub fn to_json(&self) -> Value {
+        json!({ "__extn": { "fn": self.function, "arg": self.arg } })
+    }
+}
+
+impl From<ExtensionValue> for RestrictedExpression {
+    fn from(value: ExtensionValue) -> Self {
+        value.to_expression()
+    }
+}
+
+/// `millis` in Cedar's `1d2h3m4s5ms` form.
+fn duration_arg(millis: i64) -> String {
+    if millis == 0 {
+        return "0ms".to_string();
+    }
+    let sign = if millis < 0 { "-" } else { "" };
+    let mut rest = millis.unsigned_abs();
+    let mut arg = sign.to_string();
+    for (unit, size) in [
+        ("d", 86_400_000),
+        ("h", 3_600_000),
+        ("m", 60_000),
+        ("s", 1000),
+    ] {
+        if rest >= size {
+            arg.push_str(&format!("{}{unit}", rest / size));
+            rest %= size;
+        }
+    }
+    if rest > 0 {
+        arg.push_str(&format!("{rest}ms"));
+    }
+    arg
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use cedar_policy::{Authorizer, Context, Decision, Entities, EntityUid, PolicySet, Request};
+
+    use super::*;
+
+    #[test]
+    fn test_extension_values() {
+        let at = DateTime::parse_from_rfc3339("2026-03-01T12:30:00.25+02:00").unwrap();
+        let at = ExtensionValue::datetime(&at).unwrap();
+        assert_eq!(at.arg(), "2026-03-01T10:30:00.250Z");
+        let ttl = ExtensionValue::duration(TimeDelta::milliseconds(-90_061_001));
+        assert_eq!(ttl.arg(), "-1d1h1m1s1ms");
+        assert_eq!(
+            ExtensionValue::std_duration(Duration::from_secs(90))
+                .unwrap()
+                .arg(),
+            "1m30s"
+        );
+        assert_eq!(ExtensionValue::decimal(-12_500).arg(), "-1.2500");
+        assert_eq!(
+            ExtensionValue::parse_decimal("0.5").unwrap().arg(),
+            "0.5000"
+        );
+        assert!(ExtensionValue::parse_decimal("0.12345").is_err());
+        assert!(ExtensionValue::parse_decimal("1e3").is_err());
+
+        let request = Request::new(
+            EntityUid::from_str(r#"User::"0""#).unwrap(),
+            EntityUid::from_str(r#"Action::"Read""#).unwrap(),
+            EntityUid::from_str(r#"Document::"0""#).unwrap(),
+            Context::from_pairs([
+                ("at".to_string(), at.into()),
+                ("ttl".to_string(), ttl.into()),
+                (
+                    "score".to_string(),
+                    ExtensionValue::parse_decimal("0.75").unwrap().into(),
+                ),
+            ])
+            .unwrap(),
+            None,
+        )
+        .unwrap();
+        let policies = PolicySet::from_str(
+            r#"permit (principal, action, resource) when {
+                context.at > datetime("2026-01-01") && context.ttl < duration("0ms")
+                    && context.score.greaterThan(decimal("0.5"))
+            };"#,
+        )
+        .unwrap();
+        let response = Authorizer::new().is_authorized(&request, &policies, &Entities::empty());
+        assert_eq!(response.decision(), Decision::Allow);
+    }
+}
@@ -0,0 +1,328 @@
+//! A C API for embedding the engine, declared in `include/cedar_tpe.h`. Build the library with
+//! `cargo rustc --lib --release --crate-type cdylib --features ffi`, or `staticlib`. After
+//! changing this module, regenerate the header with
+//! `cbindgen --config cbindgen.toml --output include/cedar_tpe.h`.
+//!
+//! Strings are NUL-terminated UTF-8. Calls and answers are JSON in the shapes of the HTTP API's
+//! `/v1/is_authorized` and `/v1/filter`. Functions that can fail take a `char **error`: on
+//! failure they return `NULL` or `false` and, if `error` is not `NULL`, store a message in it.
+//! Strings returned by the library are freed with `cedar_tpe_string_free`.
+
+use std::{
+    ffi::{CStr, CString, c_char},
+    panic::{self, AssertUnwindSafe},
+    ptr,
+};
+
+use cedar_policy::Entities;
+use serde_json::Value;
+
+use crate::{Engine, Error, Result, bindings, bundle};
+
+/// An engine created by `cedar_tpe_engine_new`. It can be used from several threads at once.
+pub struct CedarTpeEngine {
+    engine: Engine,
+}
+
+/// # Safety
+///
+/// `s` must be `NULL` or a valid NUL-terminated string.
+unsafe fn read_str<'a>(s: *const c_char, name: &str) -> Result<&'a str> {
+    if s.is_null() {
+        return Err(Error::Mapping(format!("`{name}` is NULL")));
+    }
+    // SAFETY: `s` is not NULL, and the caller guarantees it is NUL-terminated.
+    unsafe { CStr::from_ptr(s) }
+        .to_str()
+        .map_err(|e| Error::Mapping(format!("`{name}` is not UTF-8: {e}")))
+}
+
+fn into_c_string(s: String) -> *mut c_char {
+    // Messages from Cedar and JSON output never contain NUL.
+    CString::new(s).map_or(ptr::null_mut(), CString::into_raw)
+}
+
+/// Run `f` and store its error, if any, in `error`. A panic is stored as an error too, as
+/// unwinding into C is undefined behavior.
+///
+/// # Safety
+///
+/// `error` must be `NULL` or valid for writes.
+unsafe fn report<T>(error: *mut *mut c_char, f: impl FnOnce() -> Result<T>) -> Option<T> {
+    let result = match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(result) => result.map_err(|e| e.to_string()),
+        Err(panic) => Err(format!("Panicked: {}", panic_message(&*panic))),
+    };
+    match result {
+        Ok(value) => Some(value),
+        Err(e) => {
+            if !error.is_null() {
+                // SAFETY: `error` is not NULL, and the caller guarantees it is valid for writes.
+                unsafe { *error = into_c_string(e) };
+            }
+            None
+        }
+    }
+}
+
+fn panic_message(panic: &(dyn std::any::Any + Send)) -> &str {
+    panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
+
+/// # Safety
+///
+/// `engine` must be `NULL` or returned by `cedar_tpe_engine_new` and not yet freed.
+unsafe fn engine<'a>(engine: *const CedarTpeEngine) -> Result<&'a Engine> {
+    // SAFETY: Guaranteed by the caller.
+    unsafe { engine.as_ref() }
+        .map(|engine| &engine.engine)
+        .ok_or_else(|| Error::Mapping("`engine` is NULL".to_string()))
+}
+
+/// Create an engine from a schema and policies in Cedar's JSON formats and entities in Cedar's
+/// entities JSON format. Free it with `cedar_tpe_engine_free`.
+///
+/// # Safety
+///
+/// The strings must be NUL-terminated, and `error` must be `NULL` or valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn cedar_tpe_engine_new(
+    schema: *const c_char,
+    policies: *const c_char,
+    entities: *const c_char,
+    error: *mut *mut c_char,
+) -> *mut CedarTpeEngine {
+    // SAFETY: Guaranteed by the caller.
+    let engine = unsafe { report(error, || load(schema, policies, entities)) };
+    engine.map_or(ptr::null_mut(), |engine| {
+        Box::into_raw(Box::new(CedarTpeEngine { engine }))
+    })
+}
+
+/// # Safety
+///
+/// As for `cedar_tpe_engine_new`.
+unsafe fn load(
+    schema: *const c_char,
+    policies: *const c_char,
+    entities: *const c_char,
+) -> Result<Engine> {
+    // SAFETY: Guaranteed by the caller.
+    let (schema, policies, entities) = unsafe {
+        (
+            read_str(schema, "schema")?,
+            read_str(policies, "policies")?,
+            read_str(entities, "entities")?,
+        )
+    };
+    bindings::load(schema, policies, entities)
+}
+
+/// Replace the schema and policies with those of a bundle written by `bundle::write`. The
+/// entities are kept, and must be valid under the new schema.
+///
+/// # Safety
+///
+/// `engine` must be returned by `cedar_tpe_engine_new`, `path` must be NUL-terminated, and
+/// `error` must be `NULL` or valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn cedar_tpe_engine_load_bundle(
+    engine: *const CedarTpeEngine,
+    path: *const c_char,
+    error: *mut *mut c_char,
+) -> bool {
+    // SAFETY: Guaranteed by the caller.
+    unsafe { report(error, || load_bundle(engine, path)) }.is_some()
+}
+
+/// # Safety
+///
+/// As for `cedar_tpe_engine_load_bundle`.
+unsafe fn load_bundle(engine: *const CedarTpeEngine, path: *const c_char) -> Result<()> {
+    // SAFETY: Guaranteed by the caller.
+    let (engine, path) = unsafe { (self::engine(engine)?, read_str(path, "path")?) };
+    let (_, bundle) = bundle::read(path)?;
+    // Entities replaced meanwhile are not lost.
+    engine.update(|state| bundle.into_state(state.entities().clone()))
+}
+
+/// Replace the entities with entities in Cedar's entities JSON format.
+///
+/// # Safety
+///
+/// `engine` must be returned by `cedar_tpe_engine_new`, `entities` must be NUL-terminated, and
+/// `error` must be `NULL` or valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn cedar_tpe_engine_load_entities(
+    engine: *const CedarTpeEngine,
+    entities: *const c_char,
+    error: *mut *mut c_char,
+) -> bool {
+    // SAFETY: Guaranteed by the caller.
+    unsafe { report(error, || load_entities(engine, entities)) }.is_some()
+}
+
+/// # Safety
+///
+/// As for `cedar_tpe_engine_load_entities`.
+unsafe fn load_entities(engine: *const CedarTpeEngine, entities: *const c_char) -> Result<()> {
+    // SAFETY: Guaranteed by the caller.
+    let (engine, entities) = unsafe { (self::engine(engine)?, read_str(entities, "entities")?) };
+    let entities = Entities::from_json_str(entities, Some(engine.state().schema()))?;
+    engine.replace_entities(entities)
+}
+
+/// # Safety
+///
+/// As for `cedar_tpe_is_authorized`.
+unsafe fn answer(
+    engine: *const CedarTpeEngine,
+    call: *const c_char,
+    error: *mut *mut c_char,
+    f: impl FnOnce(&Engine, Value) -> Result<Value>,
+) -> *mut c_char {
+    // SAFETY: Guaranteed by the caller.
+    let answer = unsafe {
+        report(error, || {
+            let (engine, call) = engine_and_call(engine, call)?;
+            f(engine, call)
+        })
+    };
+    answer.map_or(ptr::null_mut(), |answer| into_c_string(answer.to_string()))
+}
+
+/// # Safety
+///
+/// As for `cedar_tpe_is_authorized`.
+unsafe fn engine_and_call<'a>(
+    engine: *const CedarTpeEngine,
+    call: *const c_char,
+) -> Result<(&'a Engine, Value)> {
+    // SAFETY: Guaranteed by the caller.
+    let (engine, call) = unsafe { (self::engine(engine)?, read_str(call, "call")?) };
+    let call = serde_json::from_str(call).map_err(|e| Error::Mapping(e.to_string()))?;
+    Ok((engine, call))
+}
+
+/// The decision on a call, as `{"decision": "Allow", "diagnostics": {...}}`.
+///
+/// # Safety
+///
+/// `engine` must be returned by `cedar_tpe_engine_new`, `call` must be NUL-terminated, and
+/// `error` must be `NULL` or valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn cedar_tpe_is_authorized(
+    engine: *const CedarTpeEngine,
+    call: *const c_char,
+    error: *mut *mut c_char,
+) -> *mut c_char {
+    // SAFETY: Guaranteed by the caller.
+    unsafe { answer(engine, call, error, bindings::authorize) }
+}
+
+/// The residuals of a call with unknown principal or resource IDs in Cedar's JSON policy
+/// format, as input for compiling a filter.
+///
+/// # Safety
+///
+/// As for `cedar_tpe_is_authorized`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn cedar_tpe_compile_filter(
+    engine: *const CedarTpeEngine,
+    call: *const c_char,
+    error: *mut *mut c_char,
+) -> *mut c_char {
+    // SAFETY: Guaranteed by the caller.
+    unsafe {
+        answer(engine, call, error, |engine, call| {
+            Ok(bindings::residuals(engine, call)?.est())
+        })
+    }
+}
+
+/// # Safety
+///
+/// `engine` must be `NULL` or returned by `cedar_tpe_engine_new` and not yet freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn cedar_tpe_engine_free(engine: *mut CedarTpeEngine) {
+    if !engine.is_null() {
+        // SAFETY: Guaranteed by the caller.
+        let engine = unsafe { Box::from_raw(engine) };
+        // A panic while dropping cannot be reported, but must not unwind into C.
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(engine)));
+    }
+}
+
+/// # Safety
+///
+/// `s` must be `NULL` or a string returned by this library and not yet freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn cedar_tpe_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        // SAFETY: Guaranteed by the caller.
+        drop(unsafe { CString::from_raw(s) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bindings::tests::inputs,
+        testing::fixtures::{authorization_call, tpe_call},
+    };
+
+    fn c(s: impl Into<Vec<u8>>) -> CString {
+        CString::new(s).unwrap()
+    }
+
+    /// Take ownership of a string returned by the library.
+    fn take(s: *mut c_char) -> String {
+        assert!(!s.is_null());
+        let owned = unsafe { CStr::from_ptr(s) }.to_str().unwrap().to_string();
+        unsafe { cedar_tpe_string_free(s) };
+        owned
+    }
+
+    #[test]
+    fn test_api() {
+        let (schema, policies, entities) = inputs();
+        let (schema, policies, entities) = (c(schema), c(policies), c(entities));
+        let mut error = ptr::null_mut();
+        let engine = unsafe {
+            cedar_tpe_engine_new(
+                schema.as_ptr(),
+                policies.as_ptr(),
+                entities.as_ptr(),
+                &mut error,
+            )
+        };
+        assert!(!engine.is_null());
+
+        let call = c(authorization_call().to_string());
+        let answer = unsafe { cedar_tpe_is_authorized(engine, call.as_ptr(), &mut error) };
+        let answer: Value = serde_json::from_str(&take(answer)).unwrap();
+        assert_eq!(answer["decision"], "Allow");
+
+        let call = c(tpe_call().to_string());
+        let est = unsafe { cedar_tpe_compile_filter(engine, call.as_ptr(), &mut error) };
+        let est: Value = serde_json::from_str(&take(est)).unwrap();
+        assert!(est["policies"]["policy0"].is_object());
+
+        let invalid = c(r#"{"principal": "not a uid"}"#);
+        let answer = unsafe { cedar_tpe_is_authorized(engine, invalid.as_ptr(), &mut error) };
+        assert!(answer.is_null());
+        assert!(take(error).contains("missing field"));
+        let path = c("/nonexistent/bundle.tar.gz");
+        assert!(!unsafe { cedar_tpe_engine_load_bundle(engine, path.as_ptr(), ptr::null_mut()) });
+        let mut error = ptr::null_mut();
+        assert!(unsafe { report::<()>(&mut error, || panic!("boom")) }.is_none());
+        assert_eq!(take(error), "Panicked: boom");
+
+        unsafe { cedar_tpe_engine_free(engine) };
+    }
+}
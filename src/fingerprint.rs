@@ -0,0 +1,207 @@
+//! Content fingerprints of engine states.
+//!
+//! Fingerprints depend only on the content of the schema, policies and entities, not on the
+//! order they were added in, so replicas loading the same artifacts report the same fingerprint.
+//! [`Fingerprint`] gives the raw digests; cache keys, ETags and audit records use them
+//! hex-encoded.
+
+use cedar_policy::{Entities, Entity, Policy, PolicySet, SchemaFragment};
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::namespace::id_str;
+
+/// A SHA-256 hash of the content.
+pub trait Fingerprint {
+    fn fingerprint(&self) -> [u8; 32];
+}
+
+impl Fingerprint for SchemaFragment {
+    // The JSON forms are used where available, as they do not depend on formatting.
+    // Converting a valid schema or policy to JSON does not fail in practice; the text form is
+    // the fallback.
+    fn fingerprint(&self) -> [u8; 32] {
+        let content = self.clone().to_json_value().map_or_else(
+            |_| format!("{self:?}"),
+            |json| canonical(json, false).to_string(),
+        );
+        digest(content.as_bytes())
+    }
+}
+
+impl Fingerprint for PolicySet {
+    fn fingerprint(&self) -> [u8; 32] {
+        let mut parts = self
+            .templates()
+            .map(|t| {
+                let content = t
+                    .to_json()
+                    .map_or_else(|_| t.to_string(), |json| canonical(json, false).to_string());
+                format!("template {}\n{content}", id_str(t.id()))
+            })
+            .chain(self.policies().map(policy_content))
+            .collect::<Vec<_>>();
+        parts.sort();
+        digest(parts.join("\n").as_bytes())
+    }
+}
+
+/// Independent of the policy set the policy is in.
+impl Fingerprint for Policy {
+    fn fingerprint(&self) -> [u8; 32] {
+        digest(policy_content(self).as_bytes())
+    }
+}
+
+impl Fingerprint for Entities {
+    fn fingerprint(&self) -> [u8; 32] {
+        entities_digest(self.iter())
+    }
+}
+
+/// Equal to the fingerprint of [`Entities`] with the same entities.
+impl Fingerprint for [Entity] {
+    fn fingerprint(&self) -> [u8; 32] {
+        entities_digest(self.iter())
+    }
+}
+
+/// Hex-encoded SHA-256 of each part of an [`EngineState`](crate::EngineState).
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct StateFingerprint {
+    pub schema: String,
+    pub policies: String,
+    pub entities: String,
+}
+
+impl StateFingerprint {
+    pub(crate) fn new(schema: &SchemaFragment, policies: &PolicySet, entities: &Entities) -> Self {
+        Self {
+            schema: schema_fingerprint(schema),
+            policies: policies_fingerprint(policies),
+            entities: entities_fingerprint(entities),
+        }
+    }
+
+    /// A single fingerprint over all parts.
+    pub fn combined(&self) -> String {
+        sha256(format!("{}\n{}\n{}", self.schema, self.policies, self.entities).as_bytes())
+    }
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn digest(content: &[u8]) -> [u8; 32] {
+    Sha256::digest(content).into()
+}
+
+pub(crate) fn sha256(content: &[u8]) -> String {
+    hex(&digest(content))
+}
+
+/// `value` with all object keys sorted. With `sort_arrays`, arrays are sorted as well, which is
+/// only correct where they represent sets, as in entity JSON.
+pub(crate) fn canonical(value: Value, sort_arrays: bool) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut entries = map.into_iter().collect::<Vec<_>>();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(k, v)| (k, canonical(v, sort_arrays)))
+                    .collect(),
+            )
+        }
+        Value::Array(values) => {
+            let mut values = values
+                .into_iter()
+                .map(|v| canonical(v, sort_arrays))
+                .collect::<Vec<_>>();
+            if sort_arrays {
+                values.sort_by_cached_key(Value::to_string);
+            }
+            Value::Array(values)
+        }
+        value => value,
+    }
+}
+
+pub(crate) fn schema_fingerprint(schema: &SchemaFragment) -> String {
+    hex(&schema.fingerprint())
+}
+
+pub(crate) fn policies_fingerprint(policies: &PolicySet) -> String {
+    hex(&policies.fingerprint())
+}
+
+fn policy_content(policy: &Policy) -> String {
+    let content = policy.to_json().map_or_else(
+        |_| policy.to_string(),
+        |json| canonical(json, false).to_string(),
+    );
+    format!("policy {}\n{content}", id_str(policy.id()))
+}
+
+/// Hex-encoded SHA-256 of a single policy, independent of the policy set it is in.
+pub fn policy_fingerprint(policy: &Policy) -> String {
+    hex(&policy.fingerprint())
+}
+
+pub(crate) fn entities_fingerprint(entities: &Entities) -> String {
+    hex(&entities.fingerprint())
+}
+
+fn entities_digest<'a>(entities: impl Iterator<Item = &'a Entity>) -> [u8; 32] {
+    let mut parts = entities
+        .map(|e| {
+            e.to_json_value()
+                .map_or_else(|_| e.to_string(), |json| canonical(json, true).to_string())
+        })
+        .collect::<Vec<_>>();
+    parts.sort();
+    digest(parts.join("\n").as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::{CEDAR_SCHEMA_SRC, EngineState};
+
+    #[test]
+    fn test_fingerprints() {
+        let policies = PolicySet::from_str(
+            r#"
+            permit (principal, action, resource);
+            forbid (principal == MyApp::User::"0", action, resource);
+            "#,
+        )
+        .unwrap();
+        let entities = Entities::from_json_str(
+            r#"[
+                { "uid": { "type": "MyApp::Server", "id": "0" }, "attrs": {}, "parents": [] },
+                { "uid": { "type": "MyApp::User", "id": "0" }, "attrs": {}, "parents": [] }
+            ]"#,
+            None,
+        )
+        .unwrap();
+        let schema = SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap();
+        let state = EngineState::new(schema.clone(), policies.clone(), entities.clone()).unwrap();
+        let fingerprint = state.fingerprint();
+        assert_eq!(hex(&schema.fingerprint()), fingerprint.schema);
+        assert_eq!(hex(&policies.fingerprint()), fingerprint.policies);
+
+        // Slices are fingerprinted regardless of their order.
+        let mut slice = entities.iter().cloned().collect::<Vec<_>>();
+        slice.reverse();
+        assert_eq!(slice.fingerprint(), entities.fingerprint());
+        assert_ne!(slice[..1].fingerprint(), entities.fingerprint());
+        let policy = policies.policies().next().unwrap();
+        assert_eq!(hex(&policy.fingerprint()), policy_fingerprint(policy));
+    }
+}
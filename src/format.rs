@@ -0,0 +1,145 @@
+//! A canonical text form of policies, so stored policies diff cleanly.
+//!
+//! The canonical form does not depend on the formatting of the source: annotations are sorted
+//! by key, the scope has one constraint per line, and every condition is on its own line as
+//! printed by Cedar. Comments are dropped. Conditions keep their order, as it decides which
+//! error a policy reports. Two policies with the same meaning but different formatting get the
+//! same canonical text.
+
+use cedar_policy::{EntityTypeName, EntityUid, Policy, PolicySetError};
+use serde_json::Value;
+
+use crate::{
+    analysis::cedar_text,
+    builder::{Expr, PolicyBuilder},
+    error::{Error, Result},
+    namespace::id_str,
+};
+
+/// `policy` in canonical form, with the same ID. Template-linked policies are returned as is.
+pub fn canonicalize(policy: &Policy) -> Result<Policy> {
+    if !policy.is_static() {
+        return Ok(policy.clone());
+    }
+    let json = policy.to_json().map_err(PolicySetError::from)?;
+    let mut builder = match json["effect"].as_str() {
+        Some("forbid") => PolicyBuilder::forbid(),
+        _ => PolicyBuilder::permit(),
+    }
+    .id(id_str(policy.id()));
+    if let Some(annotations) = json["annotations"].as_object() {
+        let mut annotations = annotations.iter().collect::<Vec<_>>();
+        annotations.sort_by_key(|(key, _)| *key);
+        for (key, value) in annotations {
+            builder = match value.as_str() {
+                Some(value) => builder.annotation(key, value),
+                None => builder.flag(key),
+            };
+        }
+    }
+    builder = match scope(&json["principal"])? {
+        Scope::Any => builder,
+        Scope::Eq(uid) => builder.principal_eq(uid),
+        Scope::In(uid) => builder.principal_in(uid),
+        Scope::Is(entity_type, None) => builder.principal_is(entity_type),
+        Scope::Is(entity_type, Some(uid)) => builder.principal_is_in(entity_type, uid),
+    };
+    let action = &json["action"];
+    builder = match (
+        action["op"].as_str(),
+        &action["entity"],
+        &action["entities"],
+    ) {
+        (Some("=="), entity, _) => builder.action_eq(uid(entity)?),
+        (Some("in"), Value::Null, Value::Array(entities)) => {
+            builder.action_in_any(entities.iter().map(uid).collect::<Result<Vec<_>>>()?)
+        }
+        (Some("in"), entity, _) => builder.action_in(uid(entity)?),
+        _ => builder,
+    };
+    builder = match scope(&json["resource"])? {
+        Scope::Any => builder,
+        Scope::Eq(uid) => builder.resource_eq(uid),
+        Scope::In(uid) => builder.resource_in(uid),
+        Scope::Is(entity_type, None) => builder.resource_is(entity_type),
+        Scope::Is(entity_type, Some(uid)) => builder.resource_is_in(entity_type, uid),
+    };
+    for condition in json["conditions"].as_array().into_iter().flatten() {
+        let body = Expr::cedar(cedar_text(&condition["body"])?);
+        builder = match condition["kind"].as_str() {
+            Some("unless") => builder.unless(body),
+            _ => builder.when(body),
+        };
+    }
+    builder.build_unvalidated()
+}
+
+enum Scope {
+    Any,
+    Eq(EntityUid),
+    In(EntityUid),
+    Is(EntityTypeName, Option<EntityUid>),
+}
+
+fn scope(json: &Value) -> Result<Scope> {
+    Ok(match json["op"].as_str() {
+        Some("==") => Scope::Eq(uid(&json["entity"])?),
+        Some("in") => Scope::In(uid(&json["entity"])?),
+        Some("is") => Scope::Is(
+            json["entity_type"].as_str().unwrap_or_default().parse()?,
+            json["in"].get("entity").map(uid).transpose()?,
+        ),
+        _ => Scope::Any,
+    })
+}
+
+fn uid(json: &Value) -> Result<EntityUid> {
+    EntityUid::from_json(json.clone())
+        .map_err(|e| Error::Mapping(format!("Invalid entity in policy scope: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use cedar_policy::PolicyId;
+
+    use super::*;
+
+    #[test]
+    fn test_canonicalize() {
+        let a = Policy::parse(
+            Some(PolicyId::new("a")),
+            r#"@z("1") @a
+// Readers.
+permit(principal==MyApp::User::"a",action in [MyApp::Action::"x"],
+  resource is MyApp::Project in MyApp::Server::"0")
+when {principal.name  like "a*" && context has x} unless{false};"#,
+        )
+        .unwrap();
+        let b = Policy::parse(
+            Some(PolicyId::new("a")),
+            r#"@a @z("1") permit (
+    principal == MyApp::User::"a",
+    action in MyApp::Action::"x",
+    resource is MyApp::Project in MyApp::Server::"0"
+) when { principal.name like "a*" && context has x }
+unless { false };"#,
+        )
+        .unwrap();
+        let canonical = canonicalize(&a).unwrap();
+        assert_eq!(canonical.id(), a.id());
+        assert_eq!(canonical.to_string(), canonicalize(&b).unwrap().to_string());
+        assert_eq!(
+            canonical.to_string(),
+            "@a\n@z(\"1\")\n\
+             permit (\n  principal == MyApp::User::\"a\",\n  action in MyApp::Action::\"x\",\n  \
+             resource is MyApp::Project in MyApp::Server::\"0\"\n)\n\
+             when { ((principal.name) like \"a*\") && (context has x) }\n\
+             unless { false };"
+        );
+        assert_eq!(
+            canonicalize(&canonical).unwrap().to_string(),
+            canonical.to_string()
+        );
+        assert_eq!(canonical.to_json().unwrap(), a.to_json().unwrap());
+    }
+}
@@ -0,0 +1,376 @@
+//! The engine as a gRPC service, defined in `proto/cedar_tpe.proto`.
+//!
+//! Serve it with [`tonic::transport::Server`]:
+//! `Server::builder().add_service(GrpcService::new(engine).into_server())`.
+
+use std::{str::FromStr, sync::Arc};
+
+use cedar_policy::{Context, Decision, Effect, EntityId, EntityTypeName, EntityUid, Request};
+use tonic::{Response, Status};
+
+use crate::{
+    Engine, Error,
+    pdp::PartialUid,
+    residuals::filter::{FilterTable, Variable},
+};
+
+pub mod proto {
+    tonic::include_proto!("cedar_tpe.v1");
+}
+
+use proto::{cedar_tpe_server::CedarTpe, cedar_tpe_server::CedarTpeServer};
+
+type GrpcResult<T> = Result<Response<T>, Status>;
+
+#[derive(Debug, Clone)]
+pub struct GrpcService {
+    engine: Arc<Engine>,
+}
+
+impl GrpcService {
+    pub fn new(engine: Arc<Engine>) -> Self {
+        Self { engine }
+    }
+
+    pub fn into_server(self) -> CedarTpeServer<Self> {
+        CedarTpeServer::new(self)
+    }
+
+    fn authorize(
+        &self,
+        request: proto::IsAuthorizedRequest,
+    ) -> Result<proto::IsAuthorizedResponse, Status> {
+        let state = self.engine.state();
+        let schema = state.schema();
+        let action = parse_uid(&request.action)?;
+        let context = parse_context(&request.context, schema, &action)?;
+        let cedar = Request::new(
+            parse_uid(&request.principal)?,
+            action,
+            parse_uid(&request.resource)?,
+            context,
+            Some(schema),
+        )
+        .map_err(|e| status(e.into()))?;
+        let response = if request.additional_entities.is_empty() {
+            self.engine.is_authorized(&cedar)
+        } else {
+            let entities = state
+                .entities()
+                .clone()
+                .add_entities_from_json_str(&request.additional_entities, Some(schema))
+                .map_err(|e| status(e.into()))?;
+            self.engine.is_authorized_with_entities(&cedar, &entities)
+        };
+        let mut reasons = response
+            .diagnostics()
+            .reason()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        reasons.sort();
+        Ok(proto::IsAuthorizedResponse {
+            decision: decision(Some(response.decision())).into(),
+            reasons,
+            errors: response
+                .diagnostics()
+                .errors()
+                .map(ToString::to_string)
+                .collect(),
+        })
+    }
+
+    fn residuals(&self, request: Option<proto::TpeRequest>) -> Result<crate::Residuals, Status> {
+        let request = request.ok_or_else(|| Status::invalid_argument("Missing `request`"))?;
+        let action = parse_uid(&request.action)?;
+        // TPE only accepts a fully known context, so an empty context means an empty one.
+        let context = parse_context(&request.context, self.engine.state().schema(), &action)?;
+        self.engine
+            .tpe(
+                parse_partial_uid(request.principal, "principal")?,
+                action,
+                parse_partial_uid(request.resource, "resource")?,
+                Some(context),
+            )
+            .map_err(status)
+    }
+}
+
+#[tonic::async_trait]
+impl CedarTpe for GrpcService {
+    async fn is_authorized(
+        &self,
+        request: tonic::Request<proto::IsAuthorizedRequest>,
+    ) -> GrpcResult<proto::IsAuthorizedResponse> {
+        self.authorize(request.into_inner()).map(Response::new)
+    }
+
+    async fn is_authorized_batch(
+        &self,
+        request: tonic::Request<proto::IsAuthorizedBatchRequest>,
+    ) -> GrpcResult<proto::IsAuthorizedBatchResponse> {
+        let responses = request
+            .into_inner()
+            .requests
+            .into_iter()
+            .enumerate()
+            .map(|(i, request)| {
+                self.authorize(request)
+                    .map_err(|e| Status::new(e.code(), format!("In request {i}: {}", e.message())))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Response::new(proto::IsAuthorizedBatchResponse {
+            responses,
+        }))
+    }
+
+    async fn tpe(
+        &self,
+        request: tonic::Request<proto::TpeRequest>,
+    ) -> GrpcResult<proto::TpeResponse> {
+        let residuals = self.residuals(Some(request.into_inner()))?;
+        Ok(Response::new(proto::TpeResponse {
+            decision: decision(residuals.decision()).into(),
+            residuals: residuals
+                .nontrivial_policies()
+                .map(|p| proto::ResidualPolicy {
+                    id: p.id().to_string(),
+                    effect: match p.effect() {
+                        Effect::Permit => "permit",
+                        Effect::Forbid => "forbid",
+                    }
+                    .to_string(),
+                    policy: p.to_string(),
+                })
+                .collect(),
+        }))
+    }
+
+    async fn compile_filter(
+        &self,
+        request: tonic::Request<proto::CompileFilterRequest>,
+    ) -> GrpcResult<proto::CompileFilterResponse> {
+        let request = request.into_inner();
+        // `target()` would read unknown values as the default.
+        let Ok(target) = proto::FilterTarget::try_from(request.target) else {
+            return Err(Status::invalid_argument(format!(
+                "Unknown filter target {}",
+                request.target
+            )));
+        };
+        let table = filter_table(&request);
+        let residuals = self.residuals(request.request)?;
+        let filter = match target {
+            proto::FilterTarget::Est => residuals.est().to_string(),
+            proto::FilterTarget::Postgres => table?.postgres(&residuals).map_err(status)?,
+            proto::FilterTarget::Mongo => table?.mongo(&residuals).map_err(status)?.to_string(),
+            proto::FilterTarget::Residuals => serde_json::json!(residuals).to_string(),
+        };
+        Ok(Response::new(proto::CompileFilterResponse { filter }))
+    }
+}
+
+fn decision(decision: Option<Decision>) -> proto::Decision {
+    match decision {
+        Some(Decision::Allow) => proto::Decision::Allow,
+        Some(Decision::Deny) => proto::Decision::Deny,
+        None => proto::Decision::Unspecified,
+    }
+}
+
+fn status(e: Error) -> Status {
+    match e {
+        Error::Token(_) => Status::unauthenticated(e.to_string()),
+        Error::NotFound(_) => Status::not_found(e.to_string()),
+        Error::Unsupported(_) => Status::unimplemented(e.to_string()),
+        Error::Store(_) | Error::Avp(_) | Error::Remote(_) => Status::unavailable(e.to_string()),
+        _ => Status::invalid_argument(e.to_string()),
+    }
+}
+
+fn parse_uid(uid: &str) -> Result<EntityUid, Status> {
+    EntityUid::from_str(uid)
+        .map_err(|e| Status::invalid_argument(format!("Invalid entity UID `{uid}`: {e}")))
+}
+
+fn parse_partial_uid(uid: Option<proto::PartialUid>, field: &str) -> Result<PartialUid, Status> {
+    let uid = uid.ok_or_else(|| Status::invalid_argument(format!("Missing `{field}`")))?;
+    let type_name = EntityTypeName::from_str(&uid.r#type).map_err(|e| {
+        Status::invalid_argument(format!("Invalid entity type `{}`: {e}", uid.r#type))
+    })?;
+    Ok(PartialUid {
+        entity_type: type_name,
+        id: uid.id.as_deref().map(EntityId::new),
+    })
+}
+
+/// The table of the unknown, which is the principal or the resource without an ID.
+fn filter_table(request: &proto::CompileFilterRequest) -> Result<FilterTable, Status> {
+    let tpe =
+        (request.request.as_ref()).ok_or_else(|| Status::invalid_argument("Missing `request`"))?;
+    let principal = parse_partial_uid(tpe.principal.clone(), "principal")?;
+    let resource = parse_partial_uid(tpe.resource.clone(), "resource")?;
+    let (variable, entity_type) = match (principal.id, resource.id) {
+        (None, Some(_)) => (Variable::Principal, principal.entity_type),
+        (Some(_), None) => (Variable::Resource, resource.entity_type),
+        _ => {
+            return Err(Status::invalid_argument(
+                "Either the principal or the resource must have an unknown ID",
+            ));
+        }
+    };
+    let mut table = FilterTable::new(variable, entity_type);
+    if !request.id_field.is_empty() {
+        table.id.clone_from(&request.id_field);
+    }
+    if !request.ancestors_field.is_empty() {
+        table.ancestors.clone_from(&request.ancestors_field);
+    }
+    Ok(table)
+}
+
+fn parse_context(
+    context: &str,
+    schema: &cedar_policy::Schema,
+    action: &EntityUid,
+) -> Result<Context, Status> {
+    if context.is_empty() {
+        return Ok(Context::empty());
+    }
+    Context::from_json_str(context, Some((schema, action))).map_err(|e| status(e.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use cedar_policy::{Entities, PolicySet, SchemaFragment};
+
+    use super::*;
+    use crate::CEDAR_SCHEMA_SRC;
+
+    fn service() -> GrpcService {
+        let engine = Engine::new(
+            SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(),
+            PolicySet::from_str(
+                r#"permit (principal == MyApp::User::"0", action, resource in MyApp::Server::"0");"#,
+            )
+            .unwrap(),
+            Entities::empty(),
+        )
+        .unwrap();
+        GrpcService::new(Arc::new(engine))
+    }
+
+    fn authorization(principal: &str) -> proto::IsAuthorizedRequest {
+        proto::IsAuthorizedRequest {
+            principal: principal.to_string(),
+            action: r#"MyApp::Action::"GetProjectMetadata""#.to_string(),
+            resource: r#"MyApp::Project::"0""#.to_string(),
+            context: String::new(),
+            additional_entities: r#"[{
+                "uid": { "type": "MyApp::Project", "id": "0" },
+                "attrs": {},
+                "parents": [{ "type": "MyApp::Server", "id": "0" }]
+            }]"#
+            .to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_service() {
+        let service = service();
+        let response = CedarTpe::is_authorized(
+            &service,
+            tonic::Request::new(authorization("MyApp::User::\"0\"")),
+        )
+        .await
+        .unwrap()
+        .into_inner();
+        assert_eq!(response.decision(), proto::Decision::Allow);
+        assert_eq!(response.reasons, ["policy0"]);
+
+        let batch = proto::IsAuthorizedBatchRequest {
+            requests: vec![
+                authorization(r#"MyApp::User::"0""#),
+                authorization(r#"MyApp::User::"1""#),
+            ],
+        };
+        let responses = service
+            .is_authorized_batch(tonic::Request::new(batch))
+            .await
+            .unwrap()
+            .into_inner()
+            .responses;
+        assert_eq!(
+            responses.iter().map(|r| r.decision()).collect::<Vec<_>>(),
+            [proto::Decision::Allow, proto::Decision::Deny]
+        );
+        let invalid = proto::IsAuthorizedBatchRequest {
+            requests: vec![authorization("not a uid")],
+        };
+        let e = service
+            .is_authorized_batch(tonic::Request::new(invalid))
+            .await
+            .unwrap_err();
+        assert_eq!(e.code(), tonic::Code::InvalidArgument);
+
+        let tpe = proto::TpeRequest {
+            principal: Some(proto::PartialUid {
+                r#type: "MyApp::User".to_string(),
+                id: Some("0".to_string()),
+            }),
+            action: r#"MyApp::Action::"GetProjectMetadata""#.to_string(),
+            resource: Some(proto::PartialUid {
+                r#type: "MyApp::Project".to_string(),
+                id: None,
+            }),
+            context: String::new(),
+        };
+        let response = service
+            .tpe(tonic::Request::new(tpe.clone()))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.decision(), proto::Decision::Unspecified);
+        assert_eq!(response.residuals.len(), 1);
+
+        let mut filter = proto::CompileFilterRequest {
+            request: Some(tpe),
+            target: proto::FilterTarget::Est.into(),
+            ..Default::default()
+        };
+        let response = service
+            .compile_filter(tonic::Request::new(filter.clone()))
+            .await
+            .unwrap()
+            .into_inner();
+        let est = serde_json::from_str::<serde_json::Value>(&response.filter).unwrap();
+        assert!(est["policies"]["policy0"].is_object());
+
+        filter.target = proto::FilterTarget::Mongo.into();
+        filter.ancestors_field = "parents".to_string();
+        let response = service
+            .compile_filter(tonic::Request::new(filter.clone()))
+            .await
+            .unwrap()
+            .into_inner();
+        let mongo = serde_json::from_str::<serde_json::Value>(&response.filter).unwrap();
+        assert!(mongo["parents"]["$in"].is_array(), "{mongo}");
+        filter.target = proto::FilterTarget::Postgres.into();
+        let response = service
+            .compile_filter(tonic::Request::new(filter.clone()))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(
+            response.filter.starts_with("\"parents\" && ARRAY["),
+            "{}",
+            response.filter
+        );
+
+        filter.target = 7;
+        let e = service
+            .compile_filter(tonic::Request::new(filter))
+            .await
+            .unwrap_err();
+        assert_eq!(e.code(), tonic::Code::InvalidArgument);
+    }
+}
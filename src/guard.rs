@@ -0,0 +1,369 @@
+//! Authorization of web routes, so handlers only contain business logic.
+//!
+//! A [`Guard`] maps each route to a Cedar action and to the resource named by the route's path,
+//! and takes the principal and context from the bearer token via a [`ClaimsMapper`]. With axum,
+//! requests are authorized either by extracting [`Authorized`] in a handler, or for all routes of
+//! a router by [`require_authorization`]. The [`actix`] module does the same for actix-web.
+//! Routes without a mapping are denied.
+
+use std::{collections::HashMap, sync::Arc};
+
+#[cfg(feature = "axum")]
+use axum::{
+    extract::{FromRef, FromRequestParts, MatchedPath, RawPathParams, Request, State},
+    http::{StatusCode, header::AUTHORIZATION, request::Parts},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use cedar_policy::{Decision, Entity, EntityId, EntityTypeName, EntityUid};
+
+#[cfg(feature = "axum")]
+use crate::server::ApiError;
+use crate::{Engine, Error, claims::ClaimsMapper, deny::deny_reason};
+
+#[cfg(feature = "actix")]
+pub mod actix;
+
+/// The resource of a route.
+#[derive(Debug, Clone)]
+pub enum RouteResource {
+    /// The entity of `entity_type` whose ID is the path parameter `param`.
+    Param {
+        entity_type: EntityTypeName,
+        param: String,
+    },
+    /// Always the same entity, e.g. the application for routes that create something.
+    Fixed(EntityUid),
+}
+
+#[derive(Debug, Clone)]
+struct Route {
+    action: EntityUid,
+    resource: RouteResource,
+}
+
+/// The routes to authorize, shared by the framework integrations.
+#[derive(Debug, Clone)]
+pub struct Guard {
+    engine: Arc<Engine>,
+    claims: Arc<ClaimsMapper>,
+    routes: Arc<HashMap<(String, String), Route>>,
+}
+
+/// Why a request was not authorized, as an HTTP status and a reason.
+#[derive(Debug)]
+pub(crate) struct Rejection {
+    status: u16,
+    reason: String,
+}
+
+impl Rejection {
+    fn new(status: u16, reason: impl Into<String>) -> Self {
+        Self {
+            status,
+            reason: reason.into(),
+        }
+    }
+}
+
+impl From<Error> for Rejection {
+    fn from(e: Error) -> Self {
+        let status = match e {
+            Error::Token(_) => 401,
+            _ => 400,
+        };
+        Self::new(status, e.to_string())
+    }
+}
+
+impl Guard {
+    pub fn new(engine: Arc<Engine>, claims: ClaimsMapper) -> Self {
+        Self {
+            engine,
+            claims: Arc::new(claims),
+            routes: Arc::default(),
+        }
+    }
+
+    /// Authorize `action` on `resource` for requests with `method` to `path`, which is the path
+    /// pattern as given to the router, e.g. `/projects/{id}`.
+    pub fn route(
+        mut self,
+        method: impl AsRef<str>,
+        path: impl Into<String>,
+        action: EntityUid,
+        resource: RouteResource,
+    ) -> Self {
+        Arc::make_mut(&mut self.routes).insert(
+            (method.as_ref().to_string(), path.into()),
+            Route { action, resource },
+        );
+        self
+    }
+
+    /// Authorize a request to the route matched by `path`, reading path parameters with
+    /// `param` and the token from the `authorization` header.
+    fn authorize(
+        &self,
+        method: &str,
+        path: Option<&str>,
+        param: impl FnOnce(&str) -> Result<Option<String>, Rejection>,
+        authorization: Option<&str>,
+    ) -> Result<Authorized, Rejection> {
+        let forbidden = |reason: String| Rejection::new(403, reason);
+        let path = path.ok_or_else(|| {
+            forbidden("No route matched, add the guard to the routes it protects".into())
+        })?;
+        let route = self
+            .routes
+            .get(&(method.to_string(), path.to_string()))
+            .ok_or_else(|| forbidden(format!("No action for `{method} {path}`")))?;
+        let resource = match &route.resource {
+            RouteResource::Fixed(uid) => uid.clone(),
+            RouteResource::Param {
+                entity_type,
+                param: name,
+            } => {
+                let id = param(name)?.ok_or_else(|| {
+                    Rejection::new(500, format!("Route `{path}` has no parameter `{name}`"))
+                })?;
+                EntityUid::from_type_name_and_id(entity_type.clone(), EntityId::new(id))
+            }
+        };
+        let authorization = authorization
+            .ok_or_else(|| Error::Token("Missing `Authorization` header".to_string()))?;
+
+        let state = self.engine.state();
+        let claims = self.claims.to_request(
+            authorization,
+            route.action.clone(),
+            resource,
+            state.schema(),
+        )?;
+        let entities = claims.entities(state.entities(), state.schema())?;
+        let response = self
+            .engine
+            .is_authorized_with_entities(&claims.request, &entities);
+        match response.decision() {
+            Decision::Allow => Ok(Authorized {
+                request: claims.request,
+                principal: claims.principal,
+            }),
+            // The `@message` of the forbid policies is more helpful than the generic reason.
+            Decision::Deny => {
+                let messages = deny_reason(&response, state.policies())
+                    .map(|reason| reason.messages().join("; "))
+                    .unwrap_or_default();
+                if !messages.is_empty() {
+                    return Err(forbidden(messages));
+                }
+                Err(forbidden(format!(
+                    "`{}` may not `{}` `{}`",
+                    claims.principal.uid(),
+                    route.action.id().unescaped(),
+                    claims
+                        .request
+                        .resource()
+                        .map_or_else(String::new, ToString::to_string)
+                )))
+            }
+        }
+    }
+
+    #[cfg(feature = "axum")]
+    async fn authorize_parts(&self, parts: &mut Parts) -> Result<Authorized, ApiError> {
+        let path = MatchedPath::from_request_parts(parts, &()).await.ok();
+        let params = RawPathParams::from_request_parts(parts, &()).await;
+        let param = |name: &str| match params {
+            Ok(params) => Ok(params
+                .iter()
+                .find(|(param, _)| *param == name)
+                .map(|(_, id)| id.to_string())),
+            Err(e) => Err(Rejection::new(400, e.to_string())),
+        };
+        let authorization = parts
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok());
+        self.authorize(
+            parts.method.as_str(),
+            path.as_ref().map(MatchedPath::as_str),
+            param,
+            authorization,
+        )
+        .map_err(|rejection| {
+            ApiError::new(
+                StatusCode::from_u16(rejection.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+                rejection.reason,
+            )
+        })
+    }
+}
+
+/// The allowed Cedar request of a guarded route. Extracting it authorizes the request, unless
+/// a middleware already did. Rejects with 401 for a missing or invalid token and with 403 if the
+/// request is denied.
+#[derive(Debug, Clone)]
+pub struct Authorized {
+    pub request: cedar_policy::Request,
+    /// The principal as described by the token.
+    pub principal: Entity,
+}
+
+#[cfg(feature = "axum")]
+impl<S: Send + Sync> FromRequestParts<S> for Authorized
+where
+    Guard: FromRef<S>,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Response> {
+        if let Some(authorized) = parts.extensions.get::<Authorized>() {
+            return Ok(authorized.clone());
+        }
+        Guard::from_ref(state)
+            .authorize_parts(parts)
+            .await
+            .map_err(IntoResponse::into_response)
+    }
+}
+
+/// Middleware for [`axum::middleware::from_fn_with_state`] that rejects requests like
+/// [`Authorized`] does. Add it with [`axum::Router::route_layer`], so that the matched route is
+/// known.
+#[cfg(feature = "axum")]
+pub async fn require_authorization(
+    State(guard): State<Guard>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let (mut parts, body) = request.into_parts();
+    match guard.authorize_parts(&mut parts).await {
+        Ok(authorized) => {
+            parts.extensions.insert(authorized);
+            next.run(Request::from_parts(parts, body)).await
+        }
+        Err(e) => e.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    #[cfg(feature = "axum")]
+    use axum::{Router, body::Body, http::Method, middleware::from_fn_with_state, routing::get};
+    use cedar_policy::{Entities, PolicySet, SchemaFragment};
+    use jsonwebtoken::{EncodingKey, Header};
+    use serde_json::json;
+    #[cfg(feature = "axum")]
+    use tower::ServiceExt;
+
+    use super::*;
+
+    const SECRET: &str = "secret";
+
+    pub(super) fn guard() -> Guard {
+        let schema = SchemaFragment::from_cedarschema_str(
+            r#"
+            entity Group;
+            entity User in [Group];
+            entity Document;
+            action Read, Delete appliesTo { principal: [User], resource: [Document] };
+            "#,
+        )
+        .unwrap()
+        .0;
+        let policies = PolicySet::from_str(
+            r#"permit (principal in Group::"admins", action == Action::"Read", resource);"#,
+        )
+        .unwrap();
+        let engine = Engine::new(schema, policies, Entities::empty()).unwrap();
+        let claims = ClaimsMapper::new(
+            serde_json::from_value(json!({
+                "keys": { "type": "hs256", "secret": SECRET },
+                "principal_type": "User",
+                "parents": [{ "type": "Group", "claim": "/groups" }]
+            }))
+            .unwrap(),
+        )
+        .unwrap();
+        Guard::new(Arc::new(engine), claims)
+            .route(
+                "GET",
+                "/documents/{id}",
+                EntityUid::from_str(r#"Action::"Read""#).unwrap(),
+                RouteResource::Param {
+                    entity_type: EntityTypeName::from_str("Document").unwrap(),
+                    param: "id".to_string(),
+                },
+            )
+            .route(
+                "GET",
+                "/guarded/{id}",
+                EntityUid::from_str(r#"Action::"Read""#).unwrap(),
+                RouteResource::Fixed(EntityUid::from_str(r#"Document::"all""#).unwrap()),
+            )
+    }
+
+    pub(super) fn token(groups: &[&str]) -> String {
+        let claims = json!({
+            "sub": "alice",
+            "exp": chrono::Utc::now().timestamp() + 60,
+            "groups": groups,
+        });
+        let key = EncodingKey::from_secret(SECRET.as_bytes());
+        jsonwebtoken::encode(&Header::default(), &claims, &key).unwrap()
+    }
+
+    #[cfg(feature = "axum")]
+    async fn call(app: &Router, method: Method, uri: &str, token: Option<String>) -> StatusCode {
+        let mut request = Request::builder().method(method).uri(uri);
+        if let Some(token) = token {
+            request = request.header(AUTHORIZATION, format!("Bearer {token}"));
+        }
+        let response = app
+            .clone()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        response.status()
+    }
+
+    #[cfg(feature = "axum")]
+    #[tokio::test]
+    async fn test_extractor_and_middleware() {
+        let guard = guard();
+        let app = Router::new()
+            .route(
+                "/documents/{id}",
+                get(|authorized: Authorized| async move {
+                    authorized.request.resource().unwrap().to_string()
+                })
+                .delete(|_: Authorized| async {}),
+            )
+            .merge(
+                Router::new()
+                    .route("/guarded/{id}", get(|| async {}).delete(|| async {}))
+                    .route_layer(from_fn_with_state(guard.clone(), require_authorization)),
+            )
+            .with_state(guard);
+
+        let admin = || Some(token(&["admins"]));
+        for uri in ["/documents/1", "/guarded/1"] {
+            assert_eq!(call(&app, Method::GET, uri, admin()).await, StatusCode::OK);
+            assert_eq!(
+                call(&app, Method::GET, uri, Some(token(&["eng"]))).await,
+                StatusCode::FORBIDDEN
+            );
+            assert_eq!(
+                call(&app, Method::GET, uri, None).await,
+                StatusCode::UNAUTHORIZED
+            );
+            assert_eq!(
+                call(&app, Method::DELETE, uri, admin()).await,
+                StatusCode::FORBIDDEN
+            );
+        }
+    }
+}
@@ -0,0 +1,145 @@
+//! The [`Guard`] for actix-web. Add it to the app with [`App::app_data`] as [`web::Data`], then
+//! extract [`Authorized`] in handlers or wrap resources with [`require_authorization`].
+//!
+//! [`App::app_data`]: actix_web::App::app_data
+
+use std::{
+    fmt,
+    future::{Ready, ready},
+};
+
+use actix_web::{
+    FromRequest, HttpMessage, HttpRequest, HttpResponse, ResponseError,
+    body::{EitherBody, MessageBody},
+    dev::{Payload, ServiceRequest, ServiceResponse},
+    http::{StatusCode, header::AUTHORIZATION},
+    middleware::Next,
+    web,
+};
+
+use super::{Authorized, Guard, Rejection};
+
+/// A rejected request, answered with `{"reason": "..."}` like the axum integration.
+#[derive(Debug)]
+pub struct Rejected(Rejection);
+
+impl fmt::Display for Rejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0.reason)
+    }
+}
+
+impl ResponseError for Rejected {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::from_u16(self.0.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(serde_json::json!({ "reason": self.0.reason }))
+    }
+}
+
+fn authorize(request: &HttpRequest) -> Result<Authorized, Rejected> {
+    let guard = request
+        .app_data::<web::Data<Guard>>()
+        .ok_or_else(|| Rejected(Rejection::new(500, "No `Guard` in the app data")))?;
+    let path = request.match_pattern();
+    let authorization = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    guard
+        .authorize(
+            request.method().as_str(),
+            path.as_deref(),
+            |name| Ok(request.match_info().get(name).map(ToString::to_string)),
+            authorization,
+        )
+        .map_err(Rejected)
+}
+
+impl FromRequest for Authorized {
+    type Error = Rejected;
+    type Future = Ready<Result<Self, Rejected>>;
+
+    fn from_request(request: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let authorized = request.extensions().get::<Authorized>().cloned();
+        ready(authorized.map_or_else(|| authorize(request), Ok))
+    }
+}
+
+/// Middleware for [`actix_web::middleware::from_fn`] that rejects requests like [`Authorized`]
+/// does. Wrap resources with it rather than the app or a scope, so that path parameters are
+/// known.
+pub async fn require_authorization<B: MessageBody>(
+    request: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, actix_web::Error> {
+    match authorize(request.request()) {
+        Ok(authorized) => {
+            request.extensions_mut().insert(authorized);
+            Ok(next.call(request).await?.map_into_left_body())
+        }
+        Err(rejected) => Ok(request.error_response(rejected).map_into_right_body()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{App, middleware::from_fn, test};
+
+    use super::*;
+    use crate::guard::tests::{guard, token};
+
+    #[tokio::test]
+    async fn test_extractor_and_middleware() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(guard()))
+                .route(
+                    "/documents/{id}",
+                    web::get().to(|authorized: Authorized| async move {
+                        authorized.request.resource().unwrap().to_string()
+                    }),
+                )
+                .route(
+                    "/documents/{id}",
+                    web::delete().to(|_: Authorized| async { "" }),
+                )
+                .service(
+                    web::resource("/guarded/{id}")
+                        .wrap(from_fn(require_authorization))
+                        .get(|| async { "" })
+                        .delete(|| async { "" }),
+                ),
+        )
+        .await;
+
+        let admin = || Some(token(&["admins"]));
+        for uri in ["/documents/1", "/guarded/1"] {
+            let call = |request: test::TestRequest, token: Option<String>| {
+                let mut request = request.uri(uri);
+                if let Some(token) = token {
+                    request = request.insert_header((AUTHORIZATION, format!("Bearer {token}")));
+                }
+                test::call_service(&app, request.to_request())
+            };
+            let response = call(test::TestRequest::get(), admin()).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(
+                call(test::TestRequest::get(), Some(token(&["eng"])))
+                    .await
+                    .status(),
+                StatusCode::FORBIDDEN
+            );
+            assert_eq!(
+                call(test::TestRequest::get(), None).await.status(),
+                StatusCode::UNAUTHORIZED
+            );
+            assert_eq!(
+                call(test::TestRequest::delete(), admin()).await.status(),
+                StatusCode::FORBIDDEN
+            );
+        }
+    }
+}
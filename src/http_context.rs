@@ -0,0 +1,292 @@
+//! Builds a request context from HTTP request metadata with a declarative mapping, so services
+//! do not assemble context JSON by hand.
+//!
+//! Each context attribute names where its value comes from: the method, a path parameter, a
+//! header or the client IP, and the Cedar type to convert the value to. Attribute names with
+//! dots, e.g. `http.method`, build nested records. The context is validated against the
+//! action's context in the schema, so a missing required value is an error.
+
+use std::{collections::BTreeMap, net::IpAddr};
+
+use cedar_policy::{Context, EntityUid, Schema};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{
+    error::{Error, Result},
+    ip::ip_json,
+};
+
+/// The parts of an HTTP request a context can be built from.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HttpMetadata {
+    pub method: String,
+    /// Parameters of the matched route, e.g. `id` for `/projects/{id}`.
+    pub path_params: BTreeMap<String, String>,
+    /// Header names are lowercase. Repeated headers are joined with `, `.
+    pub headers: BTreeMap<String, String>,
+    pub client_ip: Option<IpAddr>,
+}
+
+impl HttpMetadata {
+    /// The method and headers of `parts`. Path parameters depend on the router and the client
+    /// IP on the connection, so both are left to the caller.
+    #[cfg(feature = "tower")]
+    pub fn from_parts(parts: &http::request::Parts) -> Self {
+        let mut headers = BTreeMap::<String, String>::new();
+        for (name, value) in &parts.headers {
+            let Ok(value) = value.to_str() else {
+                continue;
+            };
+            headers
+                .entry(name.as_str().to_string())
+                .and_modify(|joined| {
+                    joined.push_str(", ");
+                    joined.push_str(value);
+                })
+                .or_insert_with(|| value.to_string());
+        }
+        Self {
+            method: parts.method.to_string(),
+            headers,
+            ..Self::default()
+        }
+    }
+
+    #[must_use]
+    pub fn with_path_param(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.path_params.insert(name.into(), value.into());
+        self
+    }
+
+    #[must_use]
+    pub fn with_client_ip(mut self, ip: IpAddr) -> Self {
+        self.client_ip = Some(ip);
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "from", rename_all = "snake_case")]
+pub enum ContextSource {
+    Method,
+    Path {
+        name: String,
+    },
+    /// Matched case-insensitively.
+    Header {
+        name: String,
+    },
+    ClientIp,
+}
+
+/// The Cedar type a value is converted to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValueKind {
+    #[default]
+    String,
+    Long,
+    Bool,
+    /// An `ipaddr` extension value.
+    Ip,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContextAttribute {
+    #[serde(flatten)]
+    pub source: ContextSource,
+    #[serde(default, rename = "as")]
+    pub kind: ValueKind,
+}
+
+/// Context attribute name to the source of its value. Attributes whose source is absent from
+/// a request are left out of its context. Deserializing fails if an attribute name has an
+/// empty part or is a record of another attribute, e.g. `http` and `http.method`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(
+    into = "BTreeMap<String, ContextAttribute>",
+    try_from = "BTreeMap<String, ContextAttribute>"
+)]
+pub struct ContextMapping {
+    pub attributes: BTreeMap<String, ContextAttribute>,
+}
+
+impl From<ContextMapping> for BTreeMap<String, ContextAttribute> {
+    fn from(mapping: ContextMapping) -> Self {
+        mapping.attributes
+    }
+}
+
+impl TryFrom<BTreeMap<String, ContextAttribute>> for ContextMapping {
+    type Error = Error;
+
+    fn try_from(attributes: BTreeMap<String, ContextAttribute>) -> Result<Self> {
+        for name in attributes.keys() {
+            if name.split('.').any(str::is_empty) {
+                return Err(Error::Mapping(format!(
+                    "Context attribute `{name}` has an empty part"
+                )));
+            }
+            let mut records = name.match_indices('.').map(|(i, _)| &name[..i]);
+            if let Some(record) = records.find(|r| attributes.contains_key(*r)) {
+                return Err(Error::Mapping(format!(
+                    "Context attribute `{name}` conflicts with `{record}`"
+                )));
+            }
+        }
+        Ok(Self { attributes })
+    }
+}
+
+impl ContextMapping {
+    /// The context of `metadata`, validated against the context of `action` in `schema`.
+    pub fn context(
+        &self,
+        metadata: &HttpMetadata,
+        schema: &Schema,
+        action: &EntityUid,
+    ) -> Result<Context> {
+        Ok(Context::from_json_value(
+            Value::Object(self.json(metadata)?),
+            Some((schema, action)),
+        )?)
+    }
+
+    /// The context of `metadata` in Cedar's JSON format, without validation.
+    pub fn json(&self, metadata: &HttpMetadata) -> Result<Map<String, Value>> {
+        let mut context = Map::new();
+        for (name, attribute) in &self.attributes {
+            let raw = match &attribute.source {
+                ContextSource::Method => Some(metadata.method.clone()),
+                ContextSource::Path { name } => metadata.path_params.get(name).cloned(),
+                ContextSource::Header { name } => {
+                    metadata.headers.get(&name.to_ascii_lowercase()).cloned()
+                }
+                ContextSource::ClientIp => metadata.client_ip.map(|ip| ip.to_string()),
+            };
+            if let Some(raw) = raw {
+                insert(&mut context, name, convert(name, raw, attribute.kind)?);
+            }
+        }
+        Ok(context)
+    }
+}
+
+fn convert(name: &str, raw: String, kind: ValueKind) -> Result<Value> {
+    let invalid = |kind: &str| Error::Mapping(format!("`{name}` is not a valid {kind}: `{raw}`"));
+    Ok(match kind {
+        ValueKind::String => Value::String(raw),
+        ValueKind::Long => Value::from(raw.trim().parse::<i64>().map_err(|_| invalid("Long"))?),
+        ValueKind::Bool => Value::Bool(raw.trim().parse().map_err(|_| invalid("Bool"))?),
+        ValueKind::Ip => ip_json(raw.trim().parse().map_err(|_| invalid("IP address"))?),
+    })
+}
+
+/// Insert `value` at the dot-separated `path`, creating records on the way.
+fn insert(context: &mut Map<String, Value>, path: &str, value: Value) {
+    match path.split_once('.') {
+        Some((head, rest)) => {
+            let record = context
+                .entry(head)
+                .or_insert_with(|| Value::Object(Map::new()));
+            if let Value::Object(record) = record {
+                insert(record, rest, value);
+            }
+        }
+        None => {
+            context.insert(path.to_string(), value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use cedar_policy::{Authorizer, Decision, Entities, PolicySet, Request};
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_context_mapping() {
+        let schema = Schema::from_str(
+            r#"
+            entity User;
+            entity Project;
+            action Read appliesTo {
+                principal: [User],
+                resource: [Project],
+                context: {
+                    "http": { "method": String, "project": String },
+                    "client": ipaddr,
+                    "attempt": Long,
+                    "debug"?: Bool,
+                }
+            };
+            "#,
+        )
+        .unwrap();
+        let mapping: ContextMapping = serde_json::from_value(json!({
+            "http.method": { "from": "method" },
+            "http.project": { "from": "path", "name": "id" },
+            "client": { "from": "client_ip", "as": "ip" },
+            "attempt": { "from": "header", "name": "X-Attempt", "as": "long" },
+            "debug": { "from": "header", "name": "x-debug", "as": "bool" },
+        }))
+        .unwrap();
+        let metadata = HttpMetadata {
+            method: "GET".to_string(),
+            headers: BTreeMap::from([("x-attempt".to_string(), "2".to_string())]),
+            ..HttpMetadata::default()
+        }
+        .with_path_param("id", "0")
+        .with_client_ip("10.1.2.3".parse().unwrap());
+
+        let action = EntityUid::from_str(r#"Action::"Read""#).unwrap();
+        let context = mapping.context(&metadata, &schema, &action).unwrap();
+        let request = Request::new(
+            EntityUid::from_str(r#"User::"0""#).unwrap(),
+            action.clone(),
+            EntityUid::from_str(r#"Project::"0""#).unwrap(),
+            context,
+            Some(&schema),
+        )
+        .unwrap();
+        let policies = PolicySet::from_str(
+            r#"permit (principal, action, resource) when {
+                context.http.method == "GET" && context.client.isInRange(ip("10.0.0.0/8"))
+                    && context.attempt < 3 && !(context has debug)
+            };"#,
+        )
+        .unwrap();
+        let response = Authorizer::new().is_authorized(&request, &policies, &Entities::empty());
+        assert_eq!(response.decision(), Decision::Allow);
+
+        // A required attribute is missing without its header, and values must convert.
+        let mut without = metadata.clone();
+        without.headers.clear();
+        assert!(matches!(
+            mapping.context(&without, &schema, &action),
+            Err(Error::Context(_))
+        ));
+        without
+            .headers
+            .insert("x-attempt".to_string(), "two".to_string());
+        assert!(matches!(mapping.json(&without), Err(Error::Mapping(_))));
+
+        for conflicting in [
+            json!({ "http": { "from": "method" }, "http.method": { "from": "method" } }),
+            json!({ "http..method": { "from": "method" } }),
+        ] {
+            let error = serde_json::from_value::<ContextMapping>(conflicting).unwrap_err();
+            assert!(error.to_string().contains("http"), "{error}");
+        }
+        let json = serde_json::to_value(&mapping).unwrap();
+        assert_eq!(
+            json["http.method"],
+            json!({ "from": "method", "as": "string" })
+        );
+    }
+}
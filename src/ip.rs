@@ -0,0 +1,149 @@
+//! Constructors for Cedar's `ipaddr` extension values, for building contexts and entity
+//! attributes from [`IpAddr`]s and CIDR ranges without formatting extension calls by hand.
+
+use std::{fmt, net::IpAddr, str::FromStr};
+
+use cedar_policy::RestrictedExpression;
+use serde_json::{Value, json};
+
+use crate::error::{Error, Result};
+
+/// A single address, as a value for [`cedar_policy::Context::from_pairs`] or an attribute.
+pub fn ip(addr: IpAddr) -> RestrictedExpression {
+    RestrictedExpression::new_ip(addr.to_string())
+}
+
+/// A single address in Cedar's JSON format, e.g. for [`cedar_policy::Context::from_json_value`].
+pub fn ip_json(addr: IpAddr) -> Value {
+    extension_json(&addr.to_string())
+}
+
+fn extension_json(arg: &str) -> Value {
+    json!({ "__extn": { "fn": "ip", "arg": arg } })
+}
+
+/// An address range such as `10.0.0.0/8` or `2001:db8::/32`, the argument of `isInRange`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    /// Fails if `prefix` is longer than the address.
+    pub fn new(addr: IpAddr, prefix: u8) -> Result<Self> {
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        if prefix > max {
+            return Err(Error::Mapping(format!(
+                "Prefix /{prefix} is longer than the {max} bits of `{addr}`"
+            )));
+        }
+        Ok(Self { addr, prefix })
+    }
+
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    pub fn prefix(&self) -> u8 {
+        self.prefix
+    }
+
+    /// Whether `addr` is in the range, with the semantics of Cedar's `isInRange`: addresses of
+    /// the other family are never in range.
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match (self.addr, addr) {
+            (IpAddr::V4(net), IpAddr::V4(addr)) => {
+                masked(u128::from(net.to_bits()), self.prefix, 32)
+                    == masked(u128::from(addr.to_bits()), self.prefix, 32)
+            }
+            (IpAddr::V6(net), IpAddr::V6(addr)) => {
+                masked(net.to_bits(), self.prefix, 128) == masked(addr.to_bits(), self.prefix, 128)
+            }
+            _ => false,
+        }
+    }
+
+    pub fn to_expression(&self) -> RestrictedExpression {
+        RestrictedExpression::new_ip(self.to_string())
+    }
+
+    pub fn to_json(&self) -> Value {
+        extension_json(&self.to_string())
+    }
+}
+
+fn masked(bits: u128, prefix: u8, width: u8) -> u128 {
+    match u32::from(width - prefix) {
+        128 => 0,
+        host => bits >> host,
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = Error;
+
+    /// An address without a prefix is a range of one address.
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::Mapping(format!("Invalid CIDR range `{s}`"));
+        let (addr, prefix) = match s.trim().split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s.trim(), None),
+        };
+        let addr = IpAddr::from_str(addr).map_err(|_| invalid())?;
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse().map_err(|_| invalid())?,
+            None if addr.is_ipv4() => 32,
+            None => 128,
+        };
+        Self::new(addr, prefix)
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cedar_policy::{Authorizer, Context, Decision, Entities, EntityUid, PolicySet, Request};
+
+    use super::*;
+
+    #[test]
+    fn test_cidr() {
+        let range = Cidr::from_str("10.0.0.0/8").unwrap();
+        assert!(range.contains("10.1.2.3".parse().unwrap()));
+        assert!(!range.contains("11.0.0.1".parse().unwrap()));
+        assert!(!range.contains("::1".parse().unwrap()));
+        assert!(
+            Cidr::from_str("::/0")
+                .unwrap()
+                .contains("2001:db8::1".parse().unwrap())
+        );
+        assert_eq!(Cidr::from_str("::1").unwrap().to_string(), "::1/128");
+        assert!(Cidr::from_str("10.0.0.0/33").is_err());
+        assert!(Cidr::from_str("10.0.0/8").is_err());
+
+        let request = Request::new(
+            EntityUid::from_str(r#"User::"0""#).unwrap(),
+            EntityUid::from_str(r#"Action::"Read""#).unwrap(),
+            EntityUid::from_str(r#"Document::"0""#).unwrap(),
+            Context::from_pairs([
+                ("client".to_string(), ip("10.1.2.3".parse().unwrap())),
+                ("network".to_string(), range.to_expression()),
+            ])
+            .unwrap(),
+            None,
+        )
+        .unwrap();
+        let policies = PolicySet::from_str(
+            "permit (principal, action, resource) when { context.client.isInRange(context.network) };",
+        )
+        .unwrap();
+        let response = Authorizer::new().is_authorized(&request, &policies, &Entities::empty());
+        assert_eq!(response.decision(), Decision::Allow);
+    }
+}
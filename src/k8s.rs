@@ -0,0 +1,281 @@
+//! Loading the schema and policies from Kubernetes ConfigMap or Secret volumes.
+//!
+//! Kubelet updates a mounted volume atomically: it writes the new contents to a fresh
+//! `..<timestamp>` directory and swaps the `..data` symlink over to it, while the visible files
+//! link through `..data`. [`MountLoader`] polls the `..data` links of the schema and policy
+//! volumes and reloads both whenever one of them was swapped, so a pod never sees half an
+//! update. Directories that are not kubelet volumes are reloaded on every poll instead.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+
+use cedar_policy::{PolicySet, Schema, SchemaFragment};
+use chrono::{DateTime, Utc};
+use tokio::task::JoinHandle;
+
+use crate::{
+    engine::Engine,
+    error::{Error, Result},
+    fingerprint::{policies_fingerprint, schema_fingerprint},
+    store::{DirectoryPolicyStore, PolicyStore},
+};
+
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The symlink kubelet swaps to update a volume.
+const DATA: &str = "..data";
+
+#[derive(Debug, Clone, Default)]
+pub struct MountStatus {
+    pub last_success: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+pub struct MountLoader {
+    schema: PathBuf,
+    policies: PathBuf,
+    engine: Arc<Engine>,
+    interval: Duration,
+    /// The `..data` targets of the last successful load.
+    applied: Mutex<Option<Vec<Option<PathBuf>>>>,
+}
+
+impl MountLoader {
+    /// `schema` is a file in Cedar syntax, or in JSON if it ends in `.json`. Policies are read
+    /// from the `*.cedar` files in `policies` like a [`DirectoryPolicyStore`] does. Both may be
+    /// in the same volume.
+    pub fn new(
+        schema: impl Into<PathBuf>,
+        policies: impl Into<PathBuf>,
+        engine: Arc<Engine>,
+    ) -> Self {
+        Self {
+            schema: schema.into(),
+            policies: policies.into(),
+            engine,
+            interval: DEFAULT_INTERVAL,
+            applied: Mutex::new(None),
+        }
+    }
+
+    #[must_use]
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Load the schema and policies unless no volume was swapped since the last successful
+    /// load, and install them if they changed. Returns whether the engine state was replaced.
+    /// A load that fails, e.g. because the policies do not validate against the new schema,
+    /// keeps the engine state.
+    ///
+    /// Files are read through the `..data` targets resolved before reading, so both come from
+    /// the same version even if kubelet swaps a volume meanwhile. If it did, nothing is
+    /// installed and the next poll loads the new version.
+    pub async fn load(&self) -> Result<bool> {
+        let versions = self.versions();
+        if versions.iter().all(Option::is_some)
+            && self
+                .applied
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .as_ref()
+                .is_some_and(|applied| *applied == versions)
+        {
+            return Ok(false);
+        }
+
+        let [schema_version, policies_version] = versions.as_slice() else {
+            unreachable!("there is a version per volume");
+        };
+        let schema_path = match (schema_version, self.schema.file_name()) {
+            (Some(version), Some(name)) => self.schema_dir().join(version).join(name),
+            _ => self.schema.clone(),
+        };
+        let fragment = read_schema(&schema_path)?;
+        let schema: Schema = fragment.clone().try_into()?;
+        let policies_dir = match policies_version {
+            Some(version) => self.policies.join(version),
+            None => self.policies.clone(),
+        };
+        let store = DirectoryPolicyStore::open(&policies_dir, schema)?;
+        let policies = PolicySet::from_policies(store.list().await?)?;
+        if self.versions() != versions {
+            return Ok(false);
+        }
+        let fingerprint = self.engine.state().fingerprint().clone();
+        let changed = fingerprint.schema != schema_fingerprint(&fragment)
+            || fingerprint.policies != policies_fingerprint(&policies);
+        if changed {
+            self.engine.replace_policies(fragment, policies)?;
+        }
+        *self.applied.lock().unwrap_or_else(PoisonError::into_inner) = Some(versions);
+        Ok(changed)
+    }
+
+    fn schema_dir(&self) -> &Path {
+        self.schema.parent().unwrap_or(Path::new("."))
+    }
+
+    /// The `..data` targets of the schema and the policy volume, relative to the volumes.
+    fn versions(&self) -> Vec<Option<PathBuf>> {
+        [self.schema_dir(), &self.policies]
+            .into_iter()
+            .map(|dir| std::fs::read_link(dir.join(DATA)).ok())
+            .collect()
+    }
+
+    /// Run [`MountLoader::load`] every interval on the tokio runtime until the returned handle
+    /// is dropped.
+    pub fn spawn(self) -> MountHandle {
+        let status = Arc::new(Mutex::new(MountStatus::default()));
+        let task_status = status.clone();
+        let task = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.interval);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                let result = self.load().await;
+                let mut status = task_status.lock().unwrap_or_else(PoisonError::into_inner);
+                match result {
+                    Ok(_) => {
+                        status.last_success = Some(Utc::now());
+                        status.last_error = None;
+                    }
+                    Err(e) => status.last_error = Some(e.to_string()),
+                }
+            }
+        });
+        MountHandle { status, task }
+    }
+}
+
+pub struct MountHandle {
+    status: Arc<Mutex<MountStatus>>,
+    task: JoinHandle<()>,
+}
+
+impl MountHandle {
+    pub fn status(&self) -> MountStatus {
+        self.status
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
+
+impl Drop for MountHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+fn read_schema(path: &Path) -> Result<SchemaFragment> {
+    let src = std::fs::read_to_string(path).map_err(|source| Error::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    if path.extension().is_some_and(|ext| ext == "json") {
+        Ok(SchemaFragment::from_json_str(&src)?)
+    } else {
+        Ok(SchemaFragment::from_cedarschema_str(&src)?.0)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{os::unix::fs::symlink, str::FromStr};
+
+    use cedar_policy::Entities;
+
+    use super::*;
+    use crate::CEDAR_SCHEMA_SRC;
+
+    /// Write a new version of a kubelet volume and swap `..data` over to it.
+    fn publish(volume: &Path, version: &str, files: &[(&str, &str)]) {
+        let dir = volume.join(format!("..{version}"));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, content) in files {
+            std::fs::write(dir.join(name), content).unwrap();
+            let link = volume.join(name);
+            if !link.exists() {
+                symlink(Path::new(DATA).join(name), link).unwrap();
+            }
+        }
+        let tmp = volume.join("..data_tmp");
+        symlink(dir.file_name().unwrap(), &tmp).unwrap();
+        std::fs::rename(tmp, volume.join(DATA)).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_mount_loader() {
+        let volume = std::env::temp_dir().join(format!("cedar-k8s-{}", uuid::Uuid::now_v7()));
+        std::fs::create_dir_all(&volume).unwrap();
+        let permit = "permit (principal, action, resource);";
+        publish(
+            &volume,
+            "v1",
+            &[
+                ("schema.cedarschema", CEDAR_SCHEMA_SRC),
+                ("all.cedar", permit),
+            ],
+        );
+        let engine = Arc::new(
+            Engine::new(
+                SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(),
+                PolicySet::new(),
+                Entities::empty(),
+            )
+            .unwrap(),
+        );
+        let loader = MountLoader::new(volume.join("schema.cedarschema"), &volume, engine.clone());
+        assert!(loader.load().await.unwrap());
+        assert!(!loader.load().await.unwrap());
+        let ids = |engine: &Engine| {
+            let state = engine.state();
+            let mut ids = state
+                .policies()
+                .policies()
+                .map(|p| p.id().to_string())
+                .collect::<Vec<_>>();
+            ids.sort();
+            ids
+        };
+        assert_eq!(ids(&engine), ["all"]);
+
+        let forbid = "forbid (principal, action, resource);";
+        publish(
+            &volume,
+            "v2",
+            &[
+                ("schema.cedarschema", CEDAR_SCHEMA_SRC),
+                ("all.cedar", permit),
+                ("none.cedar", forbid),
+            ],
+        );
+        // Files are read from the version `..data` points to, not through the visible links.
+        std::fs::remove_file(volume.join("none.cedar")).unwrap();
+        assert!(loader.load().await.unwrap());
+        assert_eq!(ids(&engine), ["all", "none"]);
+
+        // A version that does not validate is not installed.
+        publish(
+            &volume,
+            "v3",
+            &[
+                ("schema.cedarschema", "namespace Empty {}"),
+                (
+                    "all.cedar",
+                    "permit (principal is MyApp::User, action, resource);",
+                ),
+                ("none.cedar", forbid),
+            ],
+        );
+        assert!(loader.load().await.is_err());
+        assert_eq!(ids(&engine), ["all", "none"]);
+        std::fs::remove_dir_all(&volume).unwrap();
+    }
+}
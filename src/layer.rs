@@ -0,0 +1,325 @@
+//! A [`tower::Layer`] that authorizes HTTP requests before they reach the wrapped service, for
+//! any stack built on `http` types, such as hyper, tonic and axum.
+//!
+//! How an HTTP request becomes a Cedar request is up to the caller: the layer takes a function
+//! from the HTTP request to a [`MappedRequest`]. Allowed requests are passed on with the Cedar
+//! request in their extensions. Others are answered with an empty body and status 401 for token
+//! errors, 400 for other mapping errors and 403 if the request is denied.
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex, PoisonError},
+    task::{Context, Poll},
+};
+
+use cedar_policy::{Decision, Entities, Entity, Request};
+use http::StatusCode;
+use tower::{Layer, Service};
+
+use crate::{Engine, EngineState, Error, Result};
+
+/// Sets of mapped entities whose store a layer remembers.
+const MERGED_STORES: usize = 64;
+
+/// A Cedar request, with entities such as a principal described by a token that are added to
+/// the engine's entities for this request only.
+#[derive(Debug, Clone)]
+pub struct MappedRequest {
+    pub request: Request,
+    pub entities: Vec<Entity>,
+}
+
+impl From<Request> for MappedRequest {
+    fn from(request: Request) -> Self {
+        Self {
+            request,
+            entities: Vec::new(),
+        }
+    }
+}
+
+#[cfg(feature = "claims")]
+impl From<crate::claims::ClaimsRequest> for MappedRequest {
+    fn from(request: crate::claims::ClaimsRequest) -> Self {
+        Self {
+            request: request.request,
+            entities: vec![request.principal],
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct AuthorizationLayer<F> {
+    engine: Arc<Engine>,
+    map: Arc<F>,
+    stores: Arc<Mutex<MergedStores>>,
+}
+
+/// The engine's entities with the mapped entities of a request added, per set of mapped
+/// entities, so requests with the same ones share a store instead of copying the engine's.
+#[derive(Debug, Default)]
+struct MergedStores {
+    /// The state the stores were built from.
+    state: Option<Arc<EngineState>>,
+    stores: HashMap<Vec<Entity>, Arc<Entities>>,
+}
+
+impl<F> AuthorizationLayer<F> {
+    /// Authorize requests as mapped by `map`, which receives the HTTP request and the engine
+    /// state the request will be evaluated against.
+    pub fn new(engine: Arc<Engine>, map: F) -> Self {
+        Self {
+            engine,
+            map: Arc::new(map),
+            stores: Arc::default(),
+        }
+    }
+}
+
+impl<F> Clone for AuthorizationLayer<F> {
+    fn clone(&self) -> Self {
+        Self {
+            engine: self.engine.clone(),
+            map: self.map.clone(),
+            stores: self.stores.clone(),
+        }
+    }
+}
+
+impl<S, F> Layer<S> for AuthorizationLayer<F> {
+    type Service = AuthorizationService<S, F>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AuthorizationService {
+            inner,
+            engine: self.engine.clone(),
+            map: self.map.clone(),
+            stores: self.stores.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct AuthorizationService<S, F> {
+    inner: S,
+    engine: Arc<Engine>,
+    map: Arc<F>,
+    stores: Arc<Mutex<MergedStores>>,
+}
+
+impl<S: Clone, F> Clone for AuthorizationService<S, F> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            engine: self.engine.clone(),
+            map: self.map.clone(),
+            stores: self.stores.clone(),
+        }
+    }
+}
+
+impl<S, F> AuthorizationService<S, F> {
+    fn authorize<B, M>(&self, request: &http::Request<B>) -> Result<Request, StatusCode>
+    where
+        F: Fn(&http::Request<B>, &EngineState) -> Result<M>,
+        M: Into<MappedRequest>,
+    {
+        let state = self.engine.state();
+        let mapped: MappedRequest = (self.map)(request, &state)
+            .map_err(|e| match e {
+                Error::Token(_) => StatusCode::UNAUTHORIZED,
+                _ => StatusCode::BAD_REQUEST,
+            })?
+            .into();
+        let response = if mapped.entities.is_empty() {
+            self.engine.is_authorized(&mapped.request)
+        } else {
+            let entities = self.merged_store(&state, mapped.entities)?;
+            self.engine
+                .is_authorized_with_entities(&mapped.request, &entities)
+        };
+        match response.decision() {
+            Decision::Allow => Ok(mapped.request),
+            Decision::Deny => Err(StatusCode::FORBIDDEN),
+        }
+    }
+
+    /// The entities of `state` with `entities` added, built once per state.
+    fn merged_store(
+        &self,
+        state: &Arc<EngineState>,
+        entities: Vec<Entity>,
+    ) -> Result<Arc<Entities>, StatusCode> {
+        let lock = || self.stores.lock().unwrap_or_else(PoisonError::into_inner);
+        {
+            let mut stores = lock();
+            if stores.state.as_ref().is_none_or(|s| !Arc::ptr_eq(s, state)) {
+                stores.state = Some(state.clone());
+                stores.stores.clear();
+            }
+            if let Some(store) = stores.stores.get(&entities) {
+                return Ok(store.clone());
+            }
+        }
+        let store = state
+            .entities()
+            .clone()
+            .upsert_entities(entities.iter().cloned(), Some(state.schema()))
+            .map_err(|_| StatusCode::BAD_REQUEST)?;
+        let store = Arc::new(store);
+        let mut stores = lock();
+        if stores.state.as_ref().is_some_and(|s| Arc::ptr_eq(s, state))
+            && stores.stores.len() < MERGED_STORES
+        {
+            stores.stores.insert(entities, store.clone());
+        }
+        Ok(store)
+    }
+}
+
+impl<S, F, M, B, ResBody> Service<http::Request<B>> for AuthorizationService<S, F>
+where
+    S: Service<http::Request<B>, Response = http::Response<ResBody>>,
+    S::Future: Send + 'static,
+    S::Error: Send + 'static,
+    F: Fn(&http::Request<B>, &EngineState) -> Result<M>,
+    M: Into<MappedRequest>,
+    ResBody: Default + Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: http::Request<B>) -> Self::Future {
+        match self.authorize(&request) {
+            Ok(cedar) => {
+                request.extensions_mut().insert(cedar);
+                Box::pin(self.inner.call(request))
+            }
+            Err(status) => {
+                let mut response = http::Response::new(ResBody::default());
+                *response.status_mut() = status;
+                Box::pin(std::future::ready(Ok(response)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, str::FromStr};
+
+    use cedar_policy::{Context, Entities, PolicySet};
+    use tower::{ServiceExt, service_fn};
+
+    use super::*;
+    use crate::{CEDAR_SCHEMA_SRC, uid::entity_uid};
+
+    #[tokio::test]
+    async fn test_layer() {
+        let engine = Engine::new(
+            CEDAR_SCHEMA_SRC.parse().unwrap(),
+            PolicySet::from_str(r#"permit (principal == MyApp::User::"0", action, resource);"#)
+                .unwrap(),
+            Entities::empty(),
+        )
+        .unwrap();
+        let layer = AuthorizationLayer::new(
+            Arc::new(engine),
+            |request: &http::Request<String>, state: &EngineState| {
+                let user = request
+                    .headers()
+                    .get("x-user")
+                    .and_then(|user| user.to_str().ok())
+                    .ok_or_else(|| Error::Token("Missing `x-user` header".to_string()))?;
+                let project = request.uri().path().trim_start_matches("/projects/");
+                Ok(Request::new(
+                    entity_uid("MyApp::User", user)?,
+                    entity_uid("MyApp::Action", "GetProjectMetadata")?,
+                    entity_uid("MyApp::Project", project)?,
+                    Context::empty(),
+                    Some(state.schema()),
+                )?)
+            },
+        );
+        let service = layer.layer(service_fn(|request: http::Request<String>| async move {
+            let cedar = request.extensions().get::<Request>().unwrap();
+            Ok::<_, Infallible>(http::Response::new(cedar.principal().unwrap().to_string()))
+        }));
+        let call = |user: Option<&str>| {
+            let mut request = http::Request::builder().uri("/projects/1");
+            if let Some(user) = user {
+                request = request.header("x-user", user);
+            }
+            service
+                .clone()
+                .oneshot(request.body(String::new()).unwrap())
+        };
+
+        let response = call(Some("0")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body(), r#"MyApp::User::"0""#);
+        assert_eq!(
+            call(Some("1")).await.unwrap().status(),
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(call(None).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_mapped_entities() {
+        let engine = Engine::new(
+            CEDAR_SCHEMA_SRC.parse().unwrap(),
+            PolicySet::from_str(r#"permit (principal in MyApp::Role::"admin", action, resource);"#)
+                .unwrap(),
+            Entities::empty(),
+        )
+        .unwrap();
+        let engine = Arc::new(engine);
+        let layer = AuthorizationLayer::new(
+            engine.clone(),
+            |request: &http::Request<String>, state: &EngineState| {
+                let user = request.uri().path().trim_start_matches("/users/");
+                let principal = Entity::new_no_attrs(
+                    entity_uid("MyApp::User", user)?,
+                    [entity_uid("MyApp::Role", "admin")?].into(),
+                );
+                Ok(MappedRequest {
+                    request: Request::new(
+                        principal.uid(),
+                        entity_uid("MyApp::Action", "GetProjectMetadata")?,
+                        entity_uid("MyApp::Project", "0")?,
+                        Context::empty(),
+                        Some(state.schema()),
+                    )?,
+                    entities: vec![principal],
+                })
+            },
+        );
+        let stores = layer.stores.clone();
+        let service = layer.layer(service_fn(|_: http::Request<String>| async {
+            Ok::<_, Infallible>(http::Response::new(String::new()))
+        }));
+        let call = |path: &str| {
+            service
+                .clone()
+                .oneshot(http::Request::get(path).body(String::new()).unwrap())
+        };
+
+        for path in ["/users/0", "/users/0", "/users/1"] {
+            assert_eq!(call(path).await.unwrap().status(), StatusCode::OK);
+        }
+        // Requests with the same mapped entities share a store.
+        assert_eq!(stores.lock().unwrap().stores.len(), 2);
+
+        engine.replace_entities(Entities::empty()).unwrap();
+        assert_eq!(call("/users/0").await.unwrap().status(), StatusCode::OK);
+        assert_eq!(stores.lock().unwrap().stores.len(), 1);
+    }
+}
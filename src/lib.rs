@@ -1,25 +1,108 @@
 use std::{str::FromStr, sync::LazyLock};
 
-pub static CEDAR_SCHEMA: LazyLock<cedar_policy::Schema> = LazyLock::new(|| {
-    cedar_policy::Schema::from_str(include_str!("./resources/example.cedarschema"))
-        .unwrap_or_else(|e| {
-            panic!(
-                "Failed to parse Cedar schema. This indicates a bug in the schema definition. Error: {e}"
-            )
-        })
-});
+pub mod activation;
+pub mod analysis;
+pub mod annotations;
+pub mod audit;
+pub mod avp;
+#[cfg(any(
+    feature = "wasm",
+    feature = "node",
+    feature = "ffi",
+    feature = "uniffi"
+))]
+mod bindings;
+pub mod builder;
+pub mod bulk;
+#[cfg(feature = "bundle")]
+pub mod bundle;
+pub mod cache;
+#[cfg(feature = "claims")]
+pub mod claims;
+#[cfg(feature = "client")]
+pub mod client;
+pub mod clock;
+#[cfg(feature = "codegen")]
+pub mod codegen;
+#[cfg(feature = "compiled")]
+pub mod compiled;
+pub mod context;
+pub mod deny;
+pub mod engine;
+pub mod entities;
+pub mod error;
+pub mod extension;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod fingerprint;
+pub mod format;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(any(feature = "axum", feature = "actix"))]
+pub mod guard;
+pub mod http_context;
+pub mod ip;
+pub mod k8s;
+#[cfg(feature = "tower")]
+pub mod layer;
+pub mod limits;
+#[cfg(feature = "uniffi")]
+pub mod mobile;
+pub mod namespace;
+#[cfg(feature = "node")]
+pub mod node;
+pub mod opa;
+pub mod pdp;
+pub mod profile;
+pub mod replay;
+pub mod residuals;
+pub mod rollout;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod shadow;
+mod slice;
+pub mod store;
+#[cfg(feature = "metrics")]
+pub mod telemetry;
+pub mod templating;
+pub mod tenant;
+pub mod testing;
+pub mod uid;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use engine::{Engine, EngineState, ValidationConfig};
+pub use error::{Error, Result};
+pub use pdp::Pdp;
+pub use residuals::Residuals;
+
+#[cfg(feature = "uniffi")]
+uniffi::setup_scaffolding!();
+
+pub(crate) const CEDAR_SCHEMA_SRC: &str = include_str!("./resources/example.cedarschema");
+
+/// The example schema, parsed. Prefer [`example_schema`] where a failure must not panic.
+pub static CEDAR_SCHEMA: LazyLock<cedar_policy::Schema> =
+    LazyLock::new(|| example_schema().expect("the bundled example schema is valid"));
+
+/// Parse the bundled example schema.
+pub fn example_schema() -> Result<cedar_policy::Schema> {
+    Ok(cedar_policy::Schema::from_str(CEDAR_SCHEMA_SRC)?)
+}
 
 #[cfg(test)]
 mod tests {
-    use std::collections::HashSet;
+    use std::collections::{HashMap, HashSet};
 
     use cedar_policy::{
         Authorizer, Decision, Entities, EntityTypeName, EntityUid, PartialEntities,
-        PartialEntityUid, PartialRequest, PolicySet, Request, ValidationMode, Validator,
+        PartialEntityUid, PartialRequest, PolicyId, PolicySet, Request, SchemaFragment, SlotId,
+        Template, ValidationMode, Validator,
     };
     use itertools::Itertools;
 
     use super::*;
+    use crate::pdp::PartialUid;
 
     // We will query these policies with TPE with the following request:
     // principal: MyApp::User (no ID specified)
@@ -119,6 +202,153 @@ permit (
         assert!(matches!(is_authorized.decision(), Decision::Allow));
     }
 
+    // `SAMPLE_POLICIES` as links of templates, with the same IDs: (template, link, principal,
+    // resource).
+    const SAMPLE_TEMPLATES: &str = r#"
+@id("exact")
+permit (
+    principal == ?principal,
+    action == MyApp::Action::"GetProjectMetadata",
+    resource == ?resource
+);
+@id("member")
+permit (
+    principal == ?principal,
+    action,
+    resource in ?resource
+);
+@id("delete")
+permit (
+    principal == ?principal,
+    action == MyApp::Action::"DeleteProject",
+    resource
+);
+@id("anyone")
+permit (
+    principal,
+    action == MyApp::Action::"GetProjectMetadata",
+    resource in ?resource
+);
+@id("get-member")
+permit (
+    principal == ?principal,
+    action == MyApp::Action::"GetProjectMetadata",
+    resource in ?resource
+);
+@id("server")
+permit (
+    principal == ?principal,
+    action in MyApp::Action::"ServerActions",
+    resource
+);
+"#;
+
+    const SAMPLE_LINKS: &[(&str, &str, Option<&str>, Option<&str>)] = &[
+        (
+            "exact",
+            "policy0",
+            Some("User::\"0\""),
+            Some("Project::\"0\""),
+        ),
+        (
+            "member",
+            "policy1",
+            Some("User::\"1\""),
+            Some("Server::\"0\""),
+        ),
+        ("delete", "policy2", Some("User::\"2\""), None),
+        ("anyone", "policy3", None, Some("Server::\"3\"")),
+        (
+            "get-member",
+            "policy4",
+            Some("User::\"4\""),
+            Some("Project::\"4\""),
+        ),
+        (
+            "exact",
+            "policy5",
+            Some("User::\"5\""),
+            Some("Project::\"5\""),
+        ),
+        ("server", "policy6", Some("User::\"6\""), None),
+    ];
+
+    fn linked_policies() -> PolicySet {
+        let mut policies = PolicySet::new();
+        for template in PolicySet::from_str(SAMPLE_TEMPLATES).unwrap().templates() {
+            let id = template.annotation("id").unwrap();
+            let template = Template::parse(Some(PolicyId::new(id)), template.to_string()).unwrap();
+            policies.add_template(template).unwrap();
+        }
+        for (template, link, principal, resource) in SAMPLE_LINKS {
+            let slot = |uid: &Option<&str>| {
+                uid.map(|uid| EntityUid::from_str(&format!("MyApp::{uid}")).unwrap())
+            };
+            let slots = [
+                (SlotId::principal(), slot(principal)),
+                (SlotId::resource(), slot(resource)),
+            ]
+            .into_iter()
+            .filter_map(|(slot, uid)| Some((slot, uid?)))
+            .collect::<HashMap<_, _>>();
+            policies
+                .link(PolicyId::new(*template), PolicyId::new(*link), slots)
+                .unwrap();
+        }
+        policies
+    }
+
+    // Links are partially evaluated with their slots filled in, so they leave exactly the
+    // residuals of the equivalent static policies.
+    #[test]
+    fn test_tpe_with_template_links() {
+        let entities = || Entities::from_json_str(SAMPLE_ENTITIES, Some(&CEDAR_SCHEMA)).unwrap();
+        let engine = |policies| {
+            Engine::new(
+                SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(),
+                policies,
+                entities(),
+            )
+            .unwrap()
+        };
+        let (static_engine, linked_engine) = (engine(policies()), engine(linked_policies()));
+        let action = EntityUid::from_str("MyApp::Action::\"GetProjectMetadata\"").unwrap();
+        let project = EntityUid::from_str("MyApp::Project::\"0\"").unwrap();
+        let tpe = |engine: &Engine| {
+            engine
+                .tpe(
+                    PartialUid::unknown("MyApp::User".parse().unwrap()),
+                    action.clone(),
+                    PartialUid::from(project.clone()),
+                    None,
+                )
+                .unwrap()
+        };
+        let linked = tpe(&linked_engine);
+        assert_eq!(linked.snapshot(), tpe(&static_engine).snapshot());
+        for id in ["policy0", "policy1"] {
+            assert!(linked.is_nontrivial(&PolicyId::new(id)));
+        }
+
+        for user in ["0", "1", "2", "4"] {
+            let request = Request::builder()
+                .principal(EntityUid::from_str(&format!("MyApp::User::\"{user}\"")).unwrap())
+                .action(action.clone())
+                .resource(project.clone())
+                .schema(&CEDAR_SCHEMA)
+                .build()
+                .unwrap();
+            let expected = static_engine.is_authorized(&request).decision();
+            assert_eq!(linked_engine.is_authorized(&request).decision(), expected);
+            assert_eq!(
+                Authorizer::new()
+                    .is_authorized(&request, &linked.policy_set(), &entities())
+                    .decision(),
+                expected
+            );
+        }
+    }
+
     #[test]
     fn test_tpe() {
         let policies = cedar_policy::PolicySet::from_str(SAMPLE_POLICIES).unwrap();
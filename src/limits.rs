@@ -0,0 +1,193 @@
+//! Limits on evaluation, so a pathological policy set or request cannot stall request handling.
+//!
+//! Limits are installed with [`crate::Engine::set_limits`] and checked by the engine's fallible
+//! entry points: partial evaluation and [`crate::Engine::try_is_authorized`]. Exceeding a limit
+//! fails with [`Error::LimitExceeded`].
+
+use std::time::{Duration, Instant};
+
+use cedar_policy::Entities;
+use serde_json::Value;
+
+use crate::{
+    error::{Error, Result},
+    residuals::Residuals,
+};
+
+/// Every limit is off by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EvaluationLimits {
+    /// Wall-clock time of a single evaluation. Evaluation is not interrupted; synchronous entry
+    /// points fail once it took longer, and [`crate::Engine::is_authorized_within`] returns as
+    /// soon as the timeout elapsed, leaving the evaluation to finish on its blocking thread.
+    /// The clock is only read if a timeout is set.
+    pub timeout: Option<Duration>,
+    /// Nodes in the expressions of all nontrivial residuals of a partial evaluation.
+    pub max_residual_nodes: Option<usize>,
+    /// Nesting depth of any residual expression.
+    pub max_expression_depth: Option<usize>,
+    /// Entities in the store a request is evaluated with: the installed entities, or the ones
+    /// the caller provides. Not the entities the evaluation actually reads.
+    pub max_store_entities: Option<usize>,
+}
+
+impl EvaluationLimits {
+    pub(crate) fn check_entities(&self, entities: &Entities) -> Result<()> {
+        match self.max_store_entities {
+            Some(max) if entities.len() > max => Err(Error::LimitExceeded(format!(
+                "{} entities in the store, at most {max} are allowed",
+                entities.len()
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// The start of an evaluation, if it is timed.
+    pub(crate) fn start(&self) -> Option<Instant> {
+        self.timeout.map(|_| Instant::now())
+    }
+
+    pub(crate) fn check_elapsed(&self, start: Option<Instant>) -> Result<()> {
+        match (self.timeout, start) {
+            (Some(timeout), Some(start)) if start.elapsed() > timeout => Err(timed_out(timeout)),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn check_residuals(&self, residuals: &Residuals) -> Result<()> {
+        if self.max_residual_nodes.is_none() && self.max_expression_depth.is_none() {
+            return Ok(());
+        }
+        let (mut nodes, mut depth) = (0, 0);
+        for policy in residuals.nontrivial_policies() {
+            // Residuals are built from valid policies, which convert to JSON.
+            if let Ok(json) = policy.to_json() {
+                let (n, d) = size(&json["conditions"]);
+                nodes += n;
+                depth = depth.max(d);
+            }
+        }
+        if let Some(max) = self.max_residual_nodes
+            && nodes > max
+        {
+            return Err(Error::LimitExceeded(format!(
+                "Residuals have {nodes} expression nodes, at most {max} are allowed"
+            )));
+        }
+        if let Some(max) = self.max_expression_depth
+            && depth > max
+        {
+            return Err(Error::LimitExceeded(format!(
+                "Residuals are nested {depth} levels deep, at most {max} are allowed"
+            )));
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn timed_out(timeout: Duration) -> Error {
+    Error::LimitExceeded(format!("Evaluation took longer than {timeout:?}"))
+}
+
+/// The expression nodes in `json`, i.e. its objects, and their nesting depth.
+fn size(json: &Value) -> (usize, usize) {
+    let children = match json {
+        Value::Object(map) => map.values().collect::<Vec<_>>(),
+        Value::Array(values) => values.iter().collect(),
+        _ => return (0, 0),
+    };
+    let (nodes, depth) = children
+        .into_iter()
+        .map(size)
+        .fold((0, 0), |(n, d), (cn, cd)| (n + cn, d.max(cd)));
+    match json {
+        Value::Object(_) => (nodes + 1, depth + 1),
+        _ => (nodes, depth),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{str::FromStr, sync::Arc};
+
+    use cedar_policy::{Context, EntityTypeName, EntityUid, PolicySet, Request};
+
+    use super::*;
+    use crate::{CEDAR_SCHEMA_SRC, Engine, pdp::PartialUid};
+
+    #[test]
+    fn test_residual_limits() {
+        let engine = Engine::new(
+            CEDAR_SCHEMA_SRC.parse().unwrap(),
+            PolicySet::from_str(
+                r#"permit (principal is MyApp::Role, action, resource is MyApp::Project)
+                    when { principal.project == resource };"#,
+            )
+            .unwrap(),
+            Entities::empty(),
+        )
+        .unwrap();
+        let tpe = || {
+            engine.tpe(
+                PartialUid::unknown(EntityTypeName::from_str("MyApp::Role").unwrap()),
+                EntityUid::from_str(r#"MyApp::Action::"GetProjectMetadata""#).unwrap(),
+                PartialUid::unknown(EntityTypeName::from_str("MyApp::Project").unwrap()),
+                None,
+            )
+        };
+        assert!(tpe().is_ok());
+        engine.set_limits(EvaluationLimits {
+            max_residual_nodes: Some(2),
+            ..EvaluationLimits::default()
+        });
+        assert!(matches!(tpe(), Err(Error::LimitExceeded(_))));
+        engine.set_limits(EvaluationLimits {
+            max_expression_depth: Some(100),
+            ..EvaluationLimits::default()
+        });
+        assert!(tpe().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_request_limits() {
+        let entities = Entities::from_json_str(
+            r#"[{ "uid": { "type": "MyApp::User", "id": "0" }, "attrs": {}, "parents": [] }]"#,
+            None,
+        )
+        .unwrap();
+        let engine = Arc::new(
+            Engine::new(
+                CEDAR_SCHEMA_SRC.parse().unwrap(),
+                PolicySet::from_str("permit (principal, action, resource);").unwrap(),
+                entities,
+            )
+            .unwrap(),
+        );
+        let request = Request::new(
+            EntityUid::from_str(r#"MyApp::User::"0""#).unwrap(),
+            EntityUid::from_str(r#"MyApp::Action::"GetProjectMetadata""#).unwrap(),
+            EntityUid::from_str(r#"MyApp::Project::"0""#).unwrap(),
+            Context::empty(),
+            None,
+        )
+        .unwrap();
+        assert!(engine.is_authorized_within(request.clone()).await.is_ok());
+
+        engine.set_limits(EvaluationLimits {
+            max_store_entities: Some(0),
+            ..EvaluationLimits::default()
+        });
+        assert!(matches!(
+            engine.try_is_authorized(&request),
+            Err(Error::LimitExceeded(_))
+        ));
+        engine.set_limits(EvaluationLimits {
+            timeout: Some(Duration::ZERO),
+            ..EvaluationLimits::default()
+        });
+        assert!(matches!(
+            engine.is_authorized_within(request).await,
+            Err(Error::LimitExceeded(_))
+        ));
+    }
+}
@@ -0,0 +1,207 @@
+//! Swift and Kotlin bindings built with UniFFI, for apps that evaluate a downloaded bundle
+//! offline. Build the library with
+//! `cargo rustc --lib --release --crate-type cdylib --features uniffi` (or `staticlib` for iOS)
+//! and generate the bindings with the `uniffi-bindgen` binary.
+//!
+//! Entity UIDs are in Cedar syntax, such as `MyApp::User::"0"`, and contexts and entities are in
+//! Cedar's JSON formats. Decisions are those of the server-side [`Engine`].
+
+use std::{str::FromStr, sync::Arc};
+
+use cedar_policy::{Decision, Entities, EntityTypeName};
+use serde_json::Value;
+
+use crate::{
+    Engine, Error, Result,
+    bindings::{self, AuthorizationCall},
+    bundle,
+    pdp::Authorization,
+};
+
+/// An [`Engine`] loaded from a bundle or JSON.
+#[derive(uniffi::Object)]
+pub struct MobileEngine {
+    engine: Engine,
+}
+
+#[derive(Debug, thiserror::Error, uniffi::Error)]
+#[uniffi(flat_error)]
+pub enum MobileError {
+    #[error("{0}")]
+    Engine(#[from] Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Enum)]
+pub enum MobileDecision {
+    Allow,
+    Deny,
+}
+
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct MobileRequest {
+    pub principal: String,
+    pub action: String,
+    pub resource: String,
+    /// The context as a JSON object. An absent context is empty.
+    pub context: Option<String>,
+}
+
+/// The decision on a request, with the IDs of the policies that determined it in ID order.
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct MobileAuthorization {
+    pub decision: MobileDecision,
+    pub reasons: Vec<String>,
+    pub errors: Vec<String>,
+}
+
+impl From<Authorization> for MobileAuthorization {
+    fn from(authorization: Authorization) -> Self {
+        Self {
+            decision: match authorization.decision {
+                Decision::Allow => MobileDecision::Allow,
+                Decision::Deny => MobileDecision::Deny,
+            },
+            reasons: authorization
+                .reasons
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+            errors: authorization.errors,
+        }
+    }
+}
+
+#[uniffi::export]
+impl MobileEngine {
+    /// Load an engine from the JSON the app already holds, such as from its own download,
+    /// instead of a bundle. UniFFI passes strings by value, so the schema and policies are
+    /// Cedar's JSON formats serialized to strings, and the entities an array of Cedar entities.
+    #[uniffi::constructor]
+    pub fn new(
+        schema: String,
+        policies: String,
+        entities: String,
+    ) -> Result<Arc<Self>, MobileError> {
+        let engine = bindings::load(&schema, &policies, &entities)?;
+        Ok(Arc::new(Self { engine }))
+    }
+
+    /// Load the schema and policies of a bundle written by [`bundle::write`], with entities in
+    /// Cedar's entities JSON format.
+    #[uniffi::constructor]
+    pub fn from_bundle(path: String, entities: String) -> Result<Arc<Self>, MobileError> {
+        let (_, bundle) = bundle::read(path)?;
+        let schema = bundle.schema.clone().try_into().map_err(Error::from)?;
+        let entities = Entities::from_json_str(&entities, Some(&schema)).map_err(Error::from)?;
+        let engine = Engine::from_state(bundle.into_state(entities)?);
+        Ok(Arc::new(Self { engine }))
+    }
+
+    /// Replace the entities, keeping the schema and policies.
+    pub fn load_entities(&self, entities: String) -> Result<(), MobileError> {
+        let entities = Entities::from_json_str(&entities, Some(self.engine.state().schema()))
+            .map_err(Error::from)?;
+        Ok(self.engine.replace_entities(entities)?)
+    }
+
+    /// Authorize `request` against the loaded entities. The answer is a record, a struct in
+    /// Swift and a data class in Kotlin, rather than JSON to parse.
+    pub fn is_authorized(
+        &self,
+        request: MobileRequest,
+    ) -> Result<MobileAuthorization, MobileError> {
+        let call = AuthorizationCall {
+            principal: request.principal,
+            action: request.action,
+            resource: request.resource,
+            context: parse(request.context)?,
+        };
+        let request = bindings::request(&self.engine, call)?;
+        let authorization = Authorization::from(&self.engine.is_authorized(&request));
+        Ok(authorization.into())
+    }
+
+    /// The UIDs of all known resources of `resource_type` that `principal` may perform `action`
+    /// on, for filtering a list in the app.
+    pub fn query_resources(
+        &self,
+        principal: String,
+        action: String,
+        resource_type: String,
+        context: Option<String>,
+    ) -> Result<Vec<String>, MobileError> {
+        let action = bindings::parse_uid(&action)?;
+        let context = bindings::context(&self.engine, parse(context)?, &action)?;
+        let resource_type = EntityTypeName::from_str(&resource_type)
+            .map_err(|e| Error::Mapping(format!("Invalid entity type `{resource_type}`: {e}")))?;
+        let resources = self.engine.query_resources(
+            bindings::parse_uid(&principal)?,
+            action,
+            resource_type,
+            context,
+        )?;
+        Ok(resources.iter().map(ToString::to_string).collect())
+    }
+
+    /// The residuals of a call in the shape of the HTTP API's `/v1/filter`, in Cedar's JSON
+    /// policy format, for filtering lists whose resources are not in the entities.
+    pub fn filter(&self, call: String) -> Result<String, MobileError> {
+        let call = parse(Some(call))?.unwrap_or_default();
+        Ok(bindings::residuals(&self.engine, call)?.est().to_string())
+    }
+}
+
+fn parse(json: Option<String>) -> Result<Option<Value>> {
+    json.map(|json| serde_json::from_str(&json))
+        .transpose()
+        .map_err(|e| Error::Mapping(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bindings::tests::{engine, inputs},
+        testing::fixtures::{ACTION, PRINCIPAL, RESOURCE, tpe_call},
+    };
+
+    #[test]
+    fn test_engine() {
+        let dir = std::env::temp_dir().join(format!("cedar-mobile-{}", uuid::Uuid::now_v7()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("bundle.tar.gz");
+        bundle::write(&path, &bundle::Bundle::from_state(&engine().state())).unwrap();
+
+        let (_, _, entities) = inputs();
+        let path = path.to_str().unwrap().to_string();
+        let engine = MobileEngine::from_bundle(path, entities.to_string()).unwrap();
+        let request = MobileRequest {
+            principal: PRINCIPAL.to_string(),
+            action: ACTION.to_string(),
+            resource: RESOURCE.to_string(),
+            context: None,
+        };
+        let authorization = engine.is_authorized(request.clone()).unwrap();
+        assert_eq!(authorization.decision, MobileDecision::Allow);
+        assert_eq!(authorization.reasons, ["policy0"]);
+
+        let resources = engine
+            .query_resources(
+                request.principal.clone(),
+                request.action.clone(),
+                "MyApp::Project".to_string(),
+                None,
+            )
+            .unwrap();
+        assert_eq!(resources, [RESOURCE]);
+
+        let est = engine.filter(tpe_call().to_string()).unwrap();
+        assert!(est.contains("policy0"));
+
+        engine.load_entities("[]".to_string()).unwrap();
+        let authorization = engine.is_authorized(request).unwrap();
+        assert_eq!(authorization.decision, MobileDecision::Deny);
+        assert!(MobileEngine::from_bundle("/nonexistent".to_string(), "[]".to_string()).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,149 @@
+//! Prefixed policy IDs such as `tenant-a/policy0`, for combining policies from several sources.
+//!
+//! Cedar names unnamed policies `policy0`, `policy1`, ... in every parsed file, so policy sets
+//! from different sources usually share IDs. Prefixing every ID with its source keeps them apart,
+//! and [`merge`] reports any remaining collision instead of dropping a policy.
+
+use std::collections::HashMap;
+
+use cedar_policy::{PolicyId, PolicySet};
+
+use crate::error::{Error, Result};
+
+pub const SEPARATOR: char = '/';
+
+/// The ID as written, without the escaping applied by its `Display` implementation.
+pub(crate) fn id_str(id: &PolicyId) -> &str {
+    id.as_ref()
+}
+
+/// `prefix/id`.
+pub fn prefixed(prefix: &str, id: &PolicyId) -> PolicyId {
+    PolicyId::new(format!("{prefix}{SEPARATOR}{}", id_str(id)))
+}
+
+/// The ID without `prefix/`, or `None` if it does not start with `prefix/`.
+pub fn strip_prefix(prefix: &str, id: &PolicyId) -> Option<PolicyId> {
+    id_str(id)
+        .strip_prefix(prefix)
+        .and_then(|rest| rest.strip_prefix(SEPARATOR))
+        .map(PolicyId::new)
+}
+
+/// Prefix the IDs of all policies, templates and template links in `policies`. Links are
+/// relinked to the prefixed templates.
+pub fn prefix_policy_set(prefix: &str, policies: &PolicySet) -> Result<PolicySet> {
+    let mut prefixed_set = PolicySet::new();
+    for template in policies.templates() {
+        prefixed_set.add_template(template.new_id(prefixed(prefix, template.id())))?;
+    }
+    for policy in policies.policies() {
+        match (policy.template_id(), policy.template_links()) {
+            (Some(template_id), Some(slots)) => prefixed_set.link(
+                prefixed(prefix, template_id),
+                prefixed(prefix, policy.id()),
+                slots,
+            )?,
+            _ => prefixed_set.add(policy.new_id(prefixed(prefix, policy.id())))?,
+        }
+    }
+    Ok(prefixed_set)
+}
+
+/// Prefix each policy set with its source and combine them. Fails if two sources yield the
+/// same ID, e.g. because a source is given twice.
+pub fn merge<'a>(sources: impl IntoIterator<Item = (&'a str, &'a PolicySet)>) -> Result<PolicySet> {
+    let mut merged = PolicySet::new();
+    let mut origins = HashMap::<PolicyId, &str>::new();
+    for (prefix, policies) in sources {
+        let prefixed_set = prefix_policy_set(prefix, policies)?;
+        let ids = prefixed_set
+            .templates()
+            .map(|t| t.id())
+            .chain(prefixed_set.policies().map(|p| p.id()));
+        for id in ids {
+            if let Some(other) = origins.insert(id.clone(), prefix) {
+                return Err(Error::Conflict(format!(
+                    "Policy ID `{id}` is defined by both `{other}` and `{prefix}`"
+                )));
+            }
+        }
+        for template in prefixed_set.templates() {
+            merged.add_template(template.clone())?;
+        }
+        for policy in prefixed_set.policies() {
+            match (policy.template_id(), policy.template_links()) {
+                (Some(template_id), Some(slots)) => {
+                    merged.link(template_id.clone(), policy.id().clone(), slots)?
+                }
+                _ => merged.add(policy.clone())?,
+            }
+        }
+    }
+    Ok(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn test_merge() {
+        let a = PolicySet::from_str(
+            "permit (principal, action, resource); forbid (principal, action, resource);",
+        )
+        .unwrap();
+        let b = PolicySet::from_str(
+            r#"
+            permit (principal, action, resource);
+            permit (principal == ?principal, action, resource);
+            "#,
+        )
+        .unwrap();
+        let mut b_linked = b.clone();
+        b_linked
+            .link(
+                PolicyId::new("policy1"),
+                PolicyId::new("owner-0"),
+                HashMap::from([(
+                    cedar_policy::SlotId::principal(),
+                    cedar_policy::EntityUid::from_str(r#"User::"0""#).unwrap(),
+                )]),
+            )
+            .unwrap();
+
+        let merged = merge([("tenant-a", &a), ("tenant-b", &b_linked)]).unwrap();
+        let mut ids = merged
+            .policies()
+            .map(|p| id_str(p.id()).to_string())
+            .collect::<Vec<_>>();
+        ids.sort();
+        assert_eq!(
+            ids,
+            vec![
+                "tenant-a/policy0",
+                "tenant-a/policy1",
+                "tenant-b/owner-0",
+                "tenant-b/policy0"
+            ]
+        );
+        let link = merged.policy(&PolicyId::new("tenant-b/owner-0")).unwrap();
+        assert_eq!(link.template_id(), Some(&PolicyId::new("tenant-b/policy1")));
+
+        let err = merge([("tenant-a", &a), ("tenant-a", &b)]).unwrap_err();
+        assert!(matches!(err, Error::Conflict(_)));
+    }
+
+    #[test]
+    fn test_strip_prefix() {
+        let id = prefixed("tenant-a", &PolicyId::new("policy0"));
+        assert_eq!(id, PolicyId::new("tenant-a/policy0"));
+        assert_eq!(
+            strip_prefix("tenant-a", &id),
+            Some(PolicyId::new("policy0"))
+        );
+        assert_eq!(strip_prefix("tenant", &id), None);
+    }
+}
@@ -0,0 +1,181 @@
+//! Node.js bindings built with napi-rs. Build the addon with
+//! `cargo rustc --lib --release --crate-type cdylib --features node` and load the library as a
+//! `.node` file.
+//!
+//! Calls and answers are objects in the shapes of the HTTP API's `/v1/is_authorized`,
+//! `/v1/tpe` and `/v1/filter`. Evaluation runs on the libuv thread pool, so every method
+//! returns a promise.
+
+use std::sync::Arc;
+
+use cedar_policy::{Decision, Effect};
+use napi::{
+    Env, Task, ValueType,
+    bindgen_prelude::{AsyncTask, ToNapiValue, TypeName},
+    sys,
+};
+use napi_derive::napi;
+use serde_json::{Value, json};
+
+use crate::{Engine, Error, Result, bindings};
+
+/// An [`Engine`] exported to JavaScript as the `Engine` class. Evaluations share it with the
+/// thread pool, so a call still running keeps it alive after the object is collected.
+#[napi(js_name = "Engine")]
+pub struct NodeEngine {
+    engine: Arc<Engine>,
+}
+
+#[napi]
+impl NodeEngine {
+    /// `new Engine(schema, policies, entities)` takes JSON strings: the schema and policies as
+    /// `JSON.stringify` of Cedar's JSON formats, the entities as an array of Cedar entities.
+    /// Invalid input throws an `Error` with the reason.
+    #[napi(constructor)]
+    pub fn new(schema: String, policies: String, entities: String) -> napi::Result<Self> {
+        let engine = bindings::load(&schema, &policies, &entities).map_err(napi_error)?;
+        Ok(Self {
+            engine: Arc::new(engine),
+        })
+    }
+
+    /// Resolves to `{ decision, diagnostics }` for a plain object call, or rejects with the
+    /// reason the call could not be evaluated.
+    #[napi]
+    pub fn is_authorized(&self, call: Value) -> AsyncTask<Evaluation> {
+        self.evaluation(Operation::Authorize, call)
+    }
+
+    /// The decision, if known, and the nontrivial residual policies in Cedar syntax.
+    #[napi]
+    pub fn tpe(&self, call: Value) -> AsyncTask<Evaluation> {
+        self.evaluation(Operation::Tpe, call)
+    }
+
+    /// The residuals in Cedar's JSON policy format, as input for compiling a filter.
+    #[napi]
+    pub fn filter(&self, call: Value) -> AsyncTask<Evaluation> {
+        self.evaluation(Operation::Filter, call)
+    }
+}
+
+impl NodeEngine {
+    fn evaluation(&self, operation: Operation, call: Value) -> AsyncTask<Evaluation> {
+        AsyncTask::new(Evaluation {
+            engine: self.engine.clone(),
+            operation,
+            call: Some(call),
+        })
+    }
+}
+
+enum Operation {
+    Authorize,
+    Tpe,
+    Filter,
+}
+
+/// A call evaluated off the JavaScript thread.
+pub struct Evaluation {
+    engine: Arc<Engine>,
+    operation: Operation,
+    call: Option<Value>,
+}
+
+impl Evaluation {
+    fn evaluate(&mut self) -> Result<Value> {
+        let call = self.call.take().unwrap_or_default();
+        match self.operation {
+            Operation::Authorize => bindings::authorize(&self.engine, call),
+            Operation::Tpe => {
+                let residuals = bindings::residuals(&self.engine, call)?;
+                let policies = residuals
+                    .nontrivial_policies()
+                    .map(|p| {
+                        json!({
+                            "id": p.id().to_string(),
+                            "effect": match p.effect() {
+                                Effect::Permit => "permit",
+                                Effect::Forbid => "forbid",
+                            },
+                            "policy": p.to_string(),
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                let decision = residuals.decision().map(|decision| match decision {
+                    Decision::Allow => "Allow",
+                    Decision::Deny => "Deny",
+                });
+                Ok(json!({ "decision": decision, "residuals": policies }))
+            }
+            Operation::Filter => Ok(bindings::residuals(&self.engine, call)?.est()),
+        }
+    }
+}
+
+/// An answer, converted to a plain JavaScript object.
+pub struct Answer(Value);
+
+impl TypeName for Answer {
+    fn type_name() -> &'static str {
+        "Object"
+    }
+
+    fn value_type() -> ValueType {
+        ValueType::Object
+    }
+}
+
+impl ToNapiValue for Answer {
+    unsafe fn to_napi_value(env: sys::napi_env, val: Self) -> napi::Result<sys::napi_value> {
+        // SAFETY: The caller guarantees that `env` is valid.
+        unsafe { Value::to_napi_value(env, val.0) }
+    }
+}
+
+impl Task for Evaluation {
+    type Output = Value;
+    type JsValue = Answer;
+
+    fn compute(&mut self) -> napi::Result<Value> {
+        self.evaluate().map_err(napi_error)
+    }
+
+    fn resolve(&mut self, _: Env, output: Value) -> napi::Result<Answer> {
+        Ok(Answer(output))
+    }
+}
+
+fn napi_error(e: Error) -> napi::Error {
+    napi::Error::from_reason(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bindings::tests::engine,
+        testing::fixtures::{authorization_call, tpe_call},
+    };
+
+    #[test]
+    fn test_evaluation() {
+        let engine = Arc::new(engine());
+        let evaluate = |operation, call| {
+            Evaluation {
+                engine: engine.clone(),
+                operation,
+                call: Some(call),
+            }
+            .compute()
+        };
+        let answer = evaluate(Operation::Authorize, authorization_call()).unwrap();
+        assert_eq!(answer["decision"], "Allow");
+        let answer = evaluate(Operation::Tpe, tpe_call()).unwrap();
+        assert!(answer["decision"].is_null());
+        assert_eq!(answer["residuals"][0]["id"], "policy0");
+        let est = evaluate(Operation::Filter, tpe_call()).unwrap();
+        assert!(est["policies"]["policy0"].is_object());
+        assert!(evaluate(Operation::Filter, json!({})).is_err());
+    }
+}
@@ -0,0 +1,166 @@
+//! Maps OPA-style `input` documents to Cedar requests, so services that currently query an OPA
+//! sidecar can switch to Cedar without changing what they send.
+//!
+//! Values are selected from the input document with JSON pointers (RFC 6901), e.g.
+//! `/user/id`. A document may be passed either bare or wrapped as `{"input": {...}}`.
+
+use std::{collections::BTreeMap, str::FromStr};
+
+use cedar_policy::{Context, EntityId, EntityTypeName, EntityUid, Request, Schema};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OpaMapping {
+    pub principal: UidMapping,
+    pub action: UidMapping,
+    pub resource: UidMapping,
+    /// Context attribute name to the JSON pointer of its value.
+    #[serde(default)]
+    pub context: BTreeMap<String, String>,
+}
+
+/// Builds an entity UID of a fixed type from a value in the input document.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UidMapping {
+    #[serde(rename = "type")]
+    pub entity_type: String,
+    /// JSON pointer to the entity ID. Strings and numbers are accepted.
+    pub id: String,
+    /// Optional translation of extracted values, e.g. HTTP methods to action names.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub values: BTreeMap<String, String>,
+}
+
+impl UidMapping {
+    fn to_uid(&self, input: &serde_json::Value) -> Result<EntityUid> {
+        let id = lookup_id(input, &self.id)?;
+        let id = self.values.get(&id).cloned().unwrap_or(id);
+        let entity_type = EntityTypeName::from_str(&self.entity_type).map_err(|e| {
+            Error::Mapping(format!("Invalid entity type `{}`: {e}", self.entity_type))
+        })?;
+        Ok(EntityUid::from_type_name_and_id(
+            entity_type,
+            EntityId::new(id),
+        ))
+    }
+}
+
+impl OpaMapping {
+    /// Build a Cedar request from an OPA input document. The request and its context are
+    /// validated against `schema`.
+    pub fn to_request(&self, input: &serde_json::Value, schema: &Schema) -> Result<Request> {
+        let input = input.get("input").unwrap_or(input);
+        let principal = self.principal.to_uid(input)?;
+        let action = self.action.to_uid(input)?;
+        let resource = self.resource.to_uid(input)?;
+
+        let context = select(input, &self.context);
+        let context =
+            Context::from_json_value(serde_json::Value::Object(context), Some((schema, &action)))?;
+
+        Ok(Request::new(
+            principal,
+            action,
+            resource,
+            context,
+            Some(schema),
+        )?)
+    }
+}
+
+pub(crate) fn lookup<'a>(
+    input: &'a serde_json::Value,
+    pointer: &str,
+) -> Result<&'a serde_json::Value> {
+    input
+        .pointer(pointer)
+        .ok_or_else(|| Error::Mapping(format!("Input document has no value at `{pointer}`")))
+}
+
+/// Collect the values at the given pointers under their keys, skipping missing values.
+pub(crate) fn select(
+    input: &serde_json::Value,
+    pointers: &BTreeMap<String, String>,
+) -> serde_json::Map<String, serde_json::Value> {
+    pointers
+        .iter()
+        .filter_map(|(key, pointer)| input.pointer(pointer).map(|v| (key.clone(), v.clone())))
+        .collect()
+}
+
+/// Look up an entity ID. Numbers are accepted as well, since IDs are often numeric in JSON.
+pub(crate) fn lookup_id(input: &serde_json::Value, pointer: &str) -> Result<String> {
+    match lookup(input, pointer)? {
+        serde_json::Value::String(s) => Ok(s.clone()),
+        serde_json::Value::Number(n) => Ok(n.to_string()),
+        other => Err(Error::Mapping(format!(
+            "Expected a string or number at `{pointer}`, found `{other}`"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cedar_policy::{Decision, Entities, PolicySet, SchemaFragment};
+    use serde_json::json;
+
+    use super::*;
+    use crate::{CEDAR_SCHEMA, CEDAR_SCHEMA_SRC, Engine};
+
+    fn mapping() -> OpaMapping {
+        serde_json::from_value(json!({
+            "principal": { "type": "MyApp::User", "id": "/user" },
+            "action": {
+                "type": "MyApp::Action",
+                "id": "/method",
+                "values": { "GET": "GetProjectMetadata", "DELETE": "DeleteProject" }
+            },
+            "resource": { "type": "MyApp::Project", "id": "/path/1" }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_to_request() {
+        let input = json!({ "input": { "user": "0", "method": "GET", "path": ["projects", 0] } });
+        let request = mapping().to_request(&input, &CEDAR_SCHEMA).unwrap();
+        assert_eq!(
+            request.principal().unwrap().to_string(),
+            r#"MyApp::User::"0""#
+        );
+        assert_eq!(
+            request.action().unwrap().to_string(),
+            r#"MyApp::Action::"GetProjectMetadata""#
+        );
+        assert_eq!(
+            request.resource().unwrap().to_string(),
+            r#"MyApp::Project::"0""#
+        );
+
+        let engine = Engine::new(
+            SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(),
+            PolicySet::from_str(r#"permit (principal == MyApp::User::"0", action, resource);"#)
+                .unwrap(),
+            Entities::empty(),
+        )
+        .unwrap();
+        assert_eq!(engine.is_authorized(&request).decision(), Decision::Allow);
+    }
+
+    #[test]
+    fn test_missing_and_unmapped_values() {
+        let err = mapping()
+            .to_request(
+                &json!({ "method": "GET", "path": ["projects", "0"] }),
+                &CEDAR_SCHEMA,
+            )
+            .unwrap_err();
+        assert!(matches!(err, Error::Mapping(_)));
+
+        // `PATCH` is not translated, so the action does not exist in the schema.
+        let input = json!({ "user": "0", "method": "PATCH", "path": ["projects", "0"] });
+        assert!(mapping().to_request(&input, &CEDAR_SCHEMA).is_err());
+    }
+}
@@ -0,0 +1,148 @@
+//! A policy decision point (PDP) interface shared by the in-process [`Engine`] and the remote
+//! clients in `client`, so calling code does not change when evaluation moves to a service.
+
+use std::future::Future;
+
+use cedar_policy::{
+    Context, Decision, EntityId, EntityTypeName, EntityUid, PartialEntityUid, PolicyId, Request,
+    Response,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Engine, Error, Residuals, Result};
+
+/// An entity whose ID may be unknown. Unlike [`PartialEntityUid`], its parts can be read, so it
+/// can be sent to a remote service. Serialized as `{"type": "MyApp::User", "id": null}`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "PartialUidJson", try_from = "PartialUidJson")]
+pub struct PartialUid {
+    pub entity_type: EntityTypeName,
+    pub id: Option<EntityId>,
+}
+
+impl PartialUid {
+    pub fn unknown(entity_type: EntityTypeName) -> Self {
+        Self {
+            entity_type,
+            id: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct PartialUidJson {
+    #[serde(rename = "type")]
+    entity_type: String,
+    id: Option<String>,
+}
+
+impl From<PartialUid> for PartialUidJson {
+    fn from(uid: PartialUid) -> Self {
+        Self {
+            entity_type: uid.entity_type.to_string(),
+            id: uid.id.as_ref().map(|id| id.unescaped().to_string()),
+        }
+    }
+}
+
+impl TryFrom<PartialUidJson> for PartialUid {
+    type Error = Error;
+
+    fn try_from(json: PartialUidJson) -> Result<Self> {
+        let entity_type = json
+            .entity_type
+            .parse()
+            .map_err(|_| Error::Mapping(format!("Invalid entity type `{}`", json.entity_type)))?;
+        Ok(Self {
+            entity_type,
+            id: json.id.as_deref().map(EntityId::new),
+        })
+    }
+}
+
+impl From<EntityUid> for PartialUid {
+    fn from(uid: EntityUid) -> Self {
+        Self {
+            entity_type: uid.type_name().clone(),
+            id: Some(uid.id().clone()),
+        }
+    }
+}
+
+impl From<PartialUid> for PartialEntityUid {
+    fn from(uid: PartialUid) -> Self {
+        PartialEntityUid::new(uid.entity_type, uid.id)
+    }
+}
+
+/// The decision on a request, with the IDs of the policies that determined it in ID order.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Authorization {
+    pub decision: Decision,
+    pub reasons: Vec<PolicyId>,
+    pub errors: Vec<String>,
+}
+
+impl From<&Response> for Authorization {
+    fn from(response: &Response) -> Self {
+        let mut reasons = response.diagnostics().reason().cloned().collect::<Vec<_>>();
+        reasons.sort();
+        Self {
+            decision: response.decision(),
+            reasons,
+            errors: response
+                .diagnostics()
+                .errors()
+                .map(ToString::to_string)
+                .collect(),
+        }
+    }
+}
+
+pub trait Pdp: Send + Sync {
+    fn is_authorized(
+        &self,
+        request: &Request,
+    ) -> impl Future<Output = Result<Authorization>> + Send;
+
+    /// Answers in the order of `requests`. Fails if any request fails.
+    fn is_authorized_batch(
+        &self,
+        requests: &[Request],
+    ) -> impl Future<Output = Result<Vec<Authorization>>> + Send {
+        async move {
+            let mut answers = Vec::with_capacity(requests.len());
+            for request in requests {
+                answers.push(self.is_authorized(request).await?);
+            }
+            Ok(answers)
+        }
+    }
+
+    /// Type-aware partial evaluation with a known context. Remote implementations leave out
+    /// residual policies that are `false`.
+    fn tpe(
+        &self,
+        principal: PartialUid,
+        action: EntityUid,
+        resource: PartialUid,
+        context: Context,
+    ) -> impl Future<Output = Result<Residuals>> + Send;
+}
+
+impl Pdp for Engine {
+    async fn is_authorized(&self, request: &Request) -> Result<Authorization> {
+        Ok((&Engine::is_authorized(self, request)).into())
+    }
+
+    async fn tpe(
+        &self,
+        principal: PartialUid,
+        action: EntityUid,
+        resource: PartialUid,
+        context: Context,
+    ) -> Result<Residuals> {
+        Engine::tpe(self, principal, action, resource, Some(context))
+    }
+}
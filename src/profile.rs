@@ -0,0 +1,236 @@
+//! Per-policy evaluation profiling, to find the policies worth refactoring.
+//!
+//! While profiling is started with [`crate::Engine::start_profiling`], every sampled request is
+//! evaluated against each installed policy on its own as well, and the time spent is recorded
+//! per policy. Policies with deep `in` hierarchies or large sets stand out in the report. The
+//! returned responses are not affected, but sampled requests take several times as long.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, Mutex, PoisonError,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use cedar_policy::{Authorizer, Entities, PolicySet, Request};
+
+use crate::{
+    engine::{EngineState, static_policies},
+    namespace::id_str,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProfilerConfig {
+    /// Profile every `sample_every`-th request. `1` profiles every request.
+    pub sample_every: u64,
+}
+
+impl Default for ProfilerConfig {
+    fn default() -> Self {
+        Self { sample_every: 100 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolicyProfile {
+    pub policy: String,
+    pub evaluations: u64,
+    /// Evaluations in which the policy was satisfied.
+    pub satisfied: u64,
+    pub errors: u64,
+    pub total: Duration,
+    pub max: Duration,
+}
+
+impl PolicyProfile {
+    pub fn mean(&self) -> Duration {
+        u32::try_from(self.evaluations)
+            .ok()
+            .filter(|n| *n > 0)
+            .map_or(Duration::ZERO, |n| self.total / n)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ProfileReport {
+    /// How long profiling ran.
+    pub window: Duration,
+    pub requests: u64,
+    pub sampled: u64,
+    /// Every evaluated policy, the one with the most time spent first.
+    pub policies: Vec<PolicyProfile>,
+}
+
+impl ProfileReport {
+    /// The `n` policies with the highest mean evaluation time.
+    pub fn slowest(&self, n: usize) -> Vec<&PolicyProfile> {
+        let mut policies = self.policies.iter().collect::<Vec<_>>();
+        policies.sort_by(|a, b| b.mean().cmp(&a.mean()).then(a.policy.cmp(&b.policy)));
+        policies.truncate(n);
+        policies
+    }
+}
+
+/// Single-policy sets of the policies with the fingerprint `policies`.
+#[derive(Debug)]
+struct Split {
+    policies: String,
+    sets: Vec<(String, PolicySet)>,
+}
+
+#[derive(Debug)]
+pub(crate) struct Profiler {
+    config: ProfilerConfig,
+    started: Instant,
+    authorizer: Authorizer,
+    requests: AtomicU64,
+    sampled: AtomicU64,
+    split: Mutex<Option<Arc<Split>>>,
+    profiles: Mutex<HashMap<String, PolicyProfile>>,
+}
+
+impl Profiler {
+    pub(crate) fn new(config: ProfilerConfig) -> Self {
+        Self {
+            config,
+            started: Instant::now(),
+            authorizer: Authorizer::new(),
+            requests: AtomicU64::new(0),
+            sampled: AtomicU64::new(0),
+            split: Mutex::default(),
+            profiles: Mutex::default(),
+        }
+    }
+
+    pub(crate) fn record(&self, request: &Request, state: &EngineState, entities: &Entities) {
+        let n = self.requests.fetch_add(1, Ordering::Relaxed);
+        if !n.is_multiple_of(self.config.sample_every.max(1)) {
+            return;
+        }
+        let Some(split) = self.split(state) else {
+            return;
+        };
+        self.sampled.fetch_add(1, Ordering::Relaxed);
+        let mut timings = Vec::with_capacity(split.sets.len());
+        for (id, policies) in &split.sets {
+            let start = Instant::now();
+            let response = self.authorizer.is_authorized(request, policies, entities);
+            let elapsed = start.elapsed();
+            let errored = response.diagnostics().errors().next().is_some();
+            let satisfied = !errored && response.diagnostics().reason().next().is_some();
+            timings.push((id, elapsed, satisfied, errored));
+        }
+        let mut profiles = self.profiles.lock().unwrap_or_else(PoisonError::into_inner);
+        for (id, elapsed, satisfied, errored) in timings {
+            let profile = profiles.entry(id.clone()).or_insert_with(|| PolicyProfile {
+                policy: id.clone(),
+                evaluations: 0,
+                satisfied: 0,
+                errors: 0,
+                total: Duration::ZERO,
+                max: Duration::ZERO,
+            });
+            profile.evaluations += 1;
+            profile.satisfied += u64::from(satisfied);
+            profile.errors += u64::from(errored);
+            profile.total += elapsed;
+            profile.max = profile.max.max(elapsed);
+        }
+    }
+
+    // The policies are split once per installed policy set. A policy on its own is satisfied if
+    // it is the reason for the decision, whether it is a permit or a forbid.
+    fn split(&self, state: &EngineState) -> Option<Arc<Split>> {
+        let fingerprint = &state.fingerprint().policies;
+        let mut split = self.split.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(split) = &*split
+            && split.policies == *fingerprint
+        {
+            return Some(split.clone());
+        }
+        let sets = static_policies(state.policies())
+            .ok()?
+            .policies()
+            .filter_map(|policy| {
+                let set = PolicySet::from_policies([policy.clone()]).ok()?;
+                Some((id_str(policy.id()).to_string(), set))
+            })
+            .collect();
+        let new = Arc::new(Split {
+            policies: fingerprint.clone(),
+            sets,
+        });
+        *split = Some(new.clone());
+        Some(new)
+    }
+
+    pub(crate) fn report(&self) -> ProfileReport {
+        let profiles = self.profiles.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut policies = profiles.values().cloned().collect::<Vec<_>>();
+        policies.sort_by(|a, b| b.total.cmp(&a.total).then(a.policy.cmp(&b.policy)));
+        ProfileReport {
+            window: self.started.elapsed(),
+            requests: self.requests.load(Ordering::Relaxed),
+            sampled: self.sampled.load(Ordering::Relaxed),
+            policies,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use cedar_policy::{Context, EntityUid};
+
+    use super::*;
+    use crate::{CEDAR_SCHEMA_SRC, Engine};
+
+    #[test]
+    fn test_profiling() {
+        let engine = Engine::new(
+            CEDAR_SCHEMA_SRC.parse().unwrap(),
+            PolicySet::from_str(
+                r#"
+                permit (principal == MyApp::User::"0", action, resource);
+                forbid (principal is MyApp::Role, action, resource)
+                    when { principal.project == resource };
+                "#,
+            )
+            .unwrap(),
+            Entities::empty(),
+        )
+        .unwrap();
+        engine.start_profiling(ProfilerConfig { sample_every: 2 });
+        // The role is not a known entity, so reading its project fails.
+        let principals = [
+            r#"User::"0""#,
+            r#"User::"1""#,
+            r#"Role::"0""#,
+            r#"User::"1""#,
+        ];
+        for principal in principals {
+            let request = Request::new(
+                EntityUid::from_str(&format!("MyApp::{principal}")).unwrap(),
+                EntityUid::from_str(r#"MyApp::Action::"GetProjectMetadata""#).unwrap(),
+                EntityUid::from_str(r#"MyApp::Project::"0""#).unwrap(),
+                Context::empty(),
+                None,
+            )
+            .unwrap();
+            engine.is_authorized(&request);
+        }
+        let report = engine.stop_profiling().unwrap();
+        assert_eq!((report.requests, report.sampled), (4, 2));
+        let profile = |id: &str| report.policies.iter().find(|p| p.policy == id).unwrap();
+        let permit = profile("policy0");
+        assert_eq!((permit.evaluations, permit.satisfied), (2, 1));
+        let forbid = profile("policy1");
+        assert_eq!((forbid.evaluations, forbid.errors), (2, 1));
+        assert!(forbid.max >= forbid.mean());
+        assert_eq!(report.slowest(1).len(), 1);
+        assert!(engine.profile_report().is_none());
+    }
+}
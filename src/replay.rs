@@ -0,0 +1,171 @@
+//! Replay of recorded authorization requests against the current engine state.
+//!
+//! Recordings are JSON lines, one [`RecordedRequest`] per line; policy expiries and partial
+//! decisions in an audit log are skipped. A changed decision means the policies now decide differently, unless the
+//! entities changed since the request was recorded, which [`DecisionChange::entities_changed`]
+//! tells from the recorded entities fingerprint.
+
+use std::{io::BufRead, str::FromStr};
+
+use cedar_policy::{Authorizer, Context, Decision, EntityUid, Request};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audit::{PartialDecision, PolicyExpiry},
+    engine::{Engine, EngineState},
+    error::{Error, Result},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    pub principal: String,
+    pub action: String,
+    pub resource: String,
+    #[serde(default)]
+    pub context: Option<serde_json::Value>,
+    /// [`crate::fingerprint::StateFingerprint::entities`] at the time of the request.
+    #[serde(default)]
+    pub entities_fingerprint: Option<String>,
+    pub decision: Decision,
+}
+
+impl RecordedRequest {
+    fn request(&self, state: &EngineState) -> Result<Request> {
+        let uid = |uid: &str| {
+            EntityUid::from_str(uid)
+                .map_err(|e| Error::Mapping(format!("Invalid entity UID `{uid}`: {e}")))
+        };
+        let action = uid(&self.action)?;
+        let context = match &self.context {
+            Some(context) => {
+                Context::from_json_value(context.clone(), Some((state.schema(), &action)))?
+            }
+            None => Context::empty(),
+        };
+        Ok(Request::new(
+            uid(&self.principal)?,
+            action,
+            uid(&self.resource)?,
+            context,
+            Some(state.schema()),
+        )?)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecisionChange {
+    /// 1-based line of the recording.
+    pub line: usize,
+    pub recorded: RecordedRequest,
+    pub decision: Decision,
+    /// The recorded entities fingerprint differs from the current one.
+    pub entities_changed: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct ReplayReport {
+    pub replayed: usize,
+    pub changes: Vec<DecisionChange>,
+    /// Lines that could not be parsed or no longer form a valid request, with the error.
+    pub errors: Vec<(usize, Error)>,
+}
+
+/// Re-evaluate every recorded request in `recording` against the current state of `engine`.
+/// The state is taken once, so all requests see the same policies. Fails only if reading the
+/// recording fails.
+pub fn replay(engine: &Engine, recording: impl BufRead) -> std::io::Result<ReplayReport> {
+    let state = engine.state();
+    let authorizer = Authorizer::new();
+    let mut report = ReplayReport::default();
+    for (i, line) in recording.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let number = i + 1;
+        if serde_json::from_str::<PolicyExpiry>(&line).is_ok()
+            || serde_json::from_str::<PartialDecision>(&line).is_ok()
+        {
+            continue;
+        }
+        let recorded = match serde_json::from_str::<RecordedRequest>(&line) {
+            Ok(recorded) => recorded,
+            Err(e) => {
+                let error = Error::Mapping(format!("Invalid recorded request: {e}"));
+                report.errors.push((number, error));
+                continue;
+            }
+        };
+        let request = match recorded.request(&state) {
+            Ok(request) => request,
+            Err(e) => {
+                report.errors.push((number, e));
+                continue;
+            }
+        };
+        report.replayed += 1;
+        let decision = authorizer
+            .is_authorized(&request, state.policies(), state.entities())
+            .decision();
+        if decision != recorded.decision {
+            report.changes.push(DecisionChange {
+                line: number,
+                entities_changed: recorded
+                    .entities_fingerprint
+                    .as_ref()
+                    .is_some_and(|fingerprint| *fingerprint != state.fingerprint().entities),
+                recorded,
+                decision,
+            });
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use cedar_policy::{Entities, PolicySet, SchemaFragment};
+
+    use super::*;
+    use crate::CEDAR_SCHEMA_SRC;
+
+    #[test]
+    fn test_replay() {
+        let engine = Engine::new(
+            SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(),
+            PolicySet::from_str(r#"permit (principal == MyApp::User::"0", action, resource);"#)
+                .unwrap(),
+            Entities::empty(),
+        )
+        .unwrap();
+        let fingerprint = engine.state().fingerprint().entities.clone();
+        let recording = format!(
+            r#"
+{{"principal": "MyApp::User::\"0\"", "action": "MyApp::Action::\"GetProjectMetadata\"", "resource": "MyApp::Project::\"0\"", "decision": "allow"}}
+{{"principal": "MyApp::User::\"1\"", "action": "MyApp::Action::\"GetProjectMetadata\"", "resource": "MyApp::Project::\"0\"", "entities_fingerprint": "{fingerprint}", "decision": "allow"}}
+{{"principal": "MyApp::User::\"0\"", "action": "MyApp::Action::\"DeleteProject\"", "resource": "MyApp::Project::\"0\"", "entities_fingerprint": "old", "decision": "deny"}}
+{{"principal": "MyApp::User::\"0\"", "action": "MyApp::Action::\"Missing\"", "resource": "MyApp::Project::\"0\"", "decision": "deny"}}
+not json
+"#
+        );
+        let report = replay(&engine, recording.as_bytes()).unwrap();
+        assert_eq!(report.replayed, 3);
+        let changes = report
+            .changes
+            .iter()
+            .map(|c| (c.line, c.decision, c.entities_changed))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            vec![(3, Decision::Deny, false), (4, Decision::Allow, true)]
+        );
+        assert_eq!(
+            report
+                .errors
+                .iter()
+                .map(|(line, _)| *line)
+                .collect::<Vec<_>>(),
+            vec![5, 6]
+        );
+    }
+}
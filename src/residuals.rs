@@ -0,0 +1,484 @@
+pub mod arena;
+pub mod filter;
+
+use std::{
+    collections::HashSet,
+    fmt::Write as _,
+    sync::{Arc, OnceLock},
+};
+
+use cedar_policy::{Decision, Effect, Policy, PolicyId, PolicySet, PolicySetError, TpeResponse};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de::Error as _};
+use serde_json::{Map, Value, json};
+
+use self::arena::{ExprArena, ExprId};
+use crate::{Error, Result, analysis::cedar_text, fingerprint::sha256, namespace::id_str};
+
+/// Result of type-aware partial evaluation (TPE), detached from the request and entities it
+/// was computed from.
+///
+/// Every residual policy inherits the ID and annotations of its input policy. Its scope is
+/// unconstrained and its condition is a single `when` clause holding the residual. Policies
+/// whose residual may fail to evaluate are left out and listed in [`Residuals::errors`]. The
+/// conditions are hash-consed into an [`ExprArena`] when first needed, so partial evaluation
+/// does not pay for it.
+#[derive(Debug, Clone)]
+pub struct Residuals {
+    decision: Option<Decision>,
+    policies: Vec<Policy>,
+    nontrivial: HashSet<PolicyId>,
+    errors: Vec<PolicyId>,
+    /// Shared by clones, so the conditions are interned at most once.
+    interned: Arc<OnceLock<Interned>>,
+}
+
+#[derive(Debug)]
+struct Interned {
+    arena: ExprArena,
+    /// The condition of each policy, in the order of the policies.
+    conditions: Vec<ExprId>,
+}
+
+/// Whether a residual policy is satisfied, whatever the values of the unknowns. A satisfied
+/// permit determines an `Allow` unless a forbid is satisfied as well.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Determination {
+    /// Satisfied for all values of the unknowns, so the policy is determining.
+    Satisfied,
+    /// Satisfied depending on the unknowns, as its residual condition tells.
+    Conditional,
+    /// Satisfied for no values of the unknowns, so the policy is never determining.
+    Unsatisfied,
+}
+
+/// Residual policy IDs by [`Determination`]. Residuals read from [`Residuals::est`] have no
+/// unsatisfied policies, as it leaves them out.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Classification {
+    pub satisfied: Vec<PolicyId>,
+    pub conditional: Vec<PolicyId>,
+    pub unsatisfied: Vec<PolicyId>,
+}
+
+impl Residuals {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "cedar.residuals", level = "debug", skip_all)
+    )]
+    pub(crate) fn from_response(response: &TpeResponse<'_>) -> Self {
+        // A residual that may error holds an `error()` call, which has no JSON form.
+        let (mut policies, errored) = response
+            .residual_policies()
+            .partition::<Vec<_>, _>(|p| p.to_json().is_ok());
+        policies.sort_by(|a, b| a.id().cmp(b.id()));
+        let mut errors = errored.iter().map(|p| p.id().clone()).collect::<Vec<_>>();
+        errors.sort();
+        let mut nontrivial = response
+            .nontrivial_residual_policies()
+            .map(|p| p.id().clone())
+            .collect::<HashSet<_>>();
+        let mut decision = response.decision();
+        if !errors.is_empty() {
+            // Cedar skips policies that error, so the decision is the one of the others.
+            nontrivial.retain(|id| !errors.contains(id));
+            decision = decide(&policies, &nontrivial);
+        }
+        Self {
+            errors,
+            ..Self::new(decision, policies, nontrivial)
+        }
+    }
+
+    /// Residuals of `policies` in ID order.
+    fn new(
+        decision: Option<Decision>,
+        policies: Vec<Policy>,
+        nontrivial: HashSet<PolicyId>,
+    ) -> Self {
+        Self {
+            decision,
+            policies,
+            nontrivial,
+            errors: Vec::new(),
+            interned: Arc::default(),
+        }
+    }
+
+    /// The residuals denied, as [`ErrorHandling::Deny`](crate::ErrorHandling::Deny) requires
+    /// if policies may error.
+    pub(crate) fn denied(self) -> Self {
+        Self {
+            decision: Some(Decision::Deny),
+            ..self
+        }
+    }
+
+    fn interned(&self) -> &Interned {
+        self.interned.get_or_init(|| {
+            let mut arena = ExprArena::new();
+            let conditions = self
+                .policies
+                .iter()
+                .map(|policy| arena.intern(&condition_json(policy)))
+                .collect();
+            Interned { arena, conditions }
+        })
+    }
+
+    /// The decision, if it does not depend on any unknowns.
+    pub fn decision(&self) -> Option<Decision> {
+        self.decision
+    }
+
+    /// All residual policies, ordered by policy ID.
+    pub fn policies(&self) -> impl Iterator<Item = &Policy> {
+        self.policies.iter()
+    }
+
+    /// Residual policies whose condition is neither `true` nor `false`.
+    pub fn nontrivial_policies(&self) -> impl Iterator<Item = &Policy> {
+        self.policies
+            .iter()
+            .filter(|p| self.nontrivial.contains(p.id()))
+    }
+
+    pub fn is_nontrivial(&self, id: &PolicyId) -> bool {
+        self.nontrivial.contains(id)
+    }
+
+    /// The policies whose residual condition may fail to evaluate, in ID order. They are not
+    /// residual policies: as Cedar skips policies that error, the decision is made without
+    /// them, unless [`crate::ErrorHandling`] says otherwise.
+    pub fn errors(&self) -> &[PolicyId] {
+        &self.errors
+    }
+
+    /// How the residual policy `id` relates to the decision, or `None` if it is not a residual
+    /// policy.
+    pub fn determination(&self, id: &PolicyId) -> Option<Determination> {
+        let i = self.position(id)?;
+        Some(self.determination_of(i))
+    }
+
+    fn determination_of(&self, i: usize) -> Determination {
+        if self.nontrivial.contains(self.policies[i].id()) {
+            return Determination::Conditional;
+        }
+        let Interned { arena, conditions } = self.interned();
+        if arena.find(&json!({ "Value": true })) == Some(conditions[i]) {
+            Determination::Satisfied
+        } else {
+            Determination::Unsatisfied
+        }
+    }
+
+    fn position(&self, id: &PolicyId) -> Option<usize> {
+        self.policies.binary_search_by(|p| p.id().cmp(id)).ok()
+    }
+
+    /// The hash-consed residual conditions.
+    pub fn arena(&self) -> &ExprArena {
+        &self.interned().arena
+    }
+
+    /// The residual condition of policy `id` in [`Residuals::arena`], or `None` if it is not a
+    /// residual policy. Policies with equal conditions have equal IDs.
+    pub fn condition(&self, id: &PolicyId) -> Option<ExprId> {
+        Some(self.interned().conditions[self.position(id)?])
+    }
+
+    /// Hex-encoded SHA-256 of the decision and the ID, effect and condition of every residual
+    /// policy. Annotations are left out, as in [`Residuals::snapshot`].
+    pub fn fingerprint(&self) -> String {
+        let Interned { arena, conditions } = self.interned();
+        let mut content = format!("{:?}\n", self.decision);
+        for (policy, condition) in self.policies.iter().zip(conditions) {
+            let _ = writeln!(
+                content,
+                "{:?} {:?} {}",
+                policy.effect(),
+                id_str(policy.id()),
+                arena.fingerprint(*condition)
+            );
+        }
+        sha256(content.as_bytes())
+    }
+
+    /// The residual policies by [`Determination`], each in ID order.
+    pub fn classify(&self) -> Classification {
+        let mut classification = Classification::default();
+        for (i, policy) in self.policies.iter().enumerate() {
+            let ids = match self.determination_of(i) {
+                Determination::Satisfied => &mut classification.satisfied,
+                Determination::Conditional => &mut classification.conditional,
+                Determination::Unsatisfied => &mut classification.unsatisfied,
+            };
+            ids.push(policy.id().clone());
+        }
+        classification
+    }
+
+    /// The residual policies as a policy set, e.g. for re-authorizing concrete requests.
+    pub fn policy_set(&self) -> PolicySet {
+        // Residual IDs are unique because they are taken from a policy set.
+        PolicySet::from_policies(self.policies.iter().cloned())
+            .expect("Residual policy IDs are unique")
+    }
+
+    /// A canonical text for snapshot tests: the decision, then one line per residual policy in
+    /// ID order with its effect, ID and condition in Cedar syntax. Annotations are left out.
+    pub fn snapshot(&self) -> String {
+        let decision = match self.decision {
+            Some(Decision::Allow) => "allow",
+            Some(Decision::Deny) => "deny",
+            None => "unknown",
+        };
+        let mut text = format!("decision: {decision}\n");
+        for (policy, condition) in self.conditions() {
+            let effect = match policy.effect() {
+                Effect::Permit => "permit",
+                Effect::Forbid => "forbid",
+            };
+            let _ = writeln!(text, "{effect} {}: {condition}", id_str(policy.id()));
+        }
+        text
+    }
+
+    /// Every residual policy with its condition in Cedar syntax, ordered by policy ID.
+    pub fn conditions(&self) -> impl Iterator<Item = (&Policy, String)> {
+        self.policies.iter().map(|policy| {
+            let condition = cedar_text(&condition_json(policy))
+                .expect("Residual conditions are valid expressions");
+            (policy, condition)
+        })
+    }
+
+    /// The decision, the residual policies that are not `false` in Cedar's JSON policy format
+    /// by policy ID, and the IDs of the policies that may error. This is the input for
+    /// compiling a filter.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "cedar.residuals.est", level = "debug", skip_all)
+    )]
+    pub fn est(&self) -> Value {
+        self.to_json(false)
+    }
+
+    fn to_json(&self, with_false: bool) -> Value {
+        let decision = self.decision.map(|decision| match decision {
+            Decision::Allow => "allow",
+            Decision::Deny => "deny",
+        });
+        let mut policies = Map::new();
+        for policy in &self.policies {
+            let json = policy
+                .to_json()
+                .expect("Residual policies are not templates");
+            if with_false || json["conditions"][0]["body"] != json!({ "Value": false }) {
+                policies.insert(id_str(policy.id()).to_string(), json);
+            }
+        }
+        let errors = self.errors.iter().map(id_str).collect::<Vec<_>>();
+        json!({ "decision": decision, "policies": policies, "errors": errors })
+    }
+
+    /// The inverse of [`Residuals::est`], e.g. for residuals computed by a remote service.
+    /// Policies that were `false` are not part of the EST, so they are missing from the result.
+    /// Fails if `est` is not in the format of [`Residuals::est`], with every policy in the form
+    /// of a residual.
+    pub fn from_est(est: &Value) -> Result<Self> {
+        let invalid = |message: String| Error::Mapping(format!("Invalid residuals: {message}"));
+        let decision = match &est["decision"] {
+            Value::Null => None,
+            Value::String(d) if d == "allow" => Some(Decision::Allow),
+            Value::String(d) if d == "deny" => Some(Decision::Deny),
+            d => return Err(invalid(format!("unknown decision {d}"))),
+        };
+        let json = est["policies"]
+            .as_object()
+            .ok_or_else(|| invalid("`policies` is not an object".to_string()))?;
+        let mut policies = Vec::new();
+        let mut nontrivial = HashSet::new();
+        for (id, json) in json {
+            let unconstrained = ["principal", "action", "resource"]
+                .iter()
+                .all(|var| json[var] == json!({ "op": "All" }));
+            let body = match json["conditions"].as_array().map(Vec::as_slice) {
+                Some([condition]) if unconstrained && condition["kind"] == "when" => {
+                    &condition["body"]
+                }
+                _ => {
+                    return Err(invalid(format!(
+                        "policy `{id}` is not a single `when` clause in an unconstrained scope"
+                    )));
+                }
+            };
+            let policy = Policy::from_json(Some(PolicyId::new(id)), json.clone())
+                .map_err(PolicySetError::from)?;
+            if !matches!(cedar_text(body)?.as_str(), "true" | "false") {
+                nontrivial.insert(policy.id().clone());
+            }
+            policies.push(policy);
+        }
+        policies.sort_by(|a, b| a.id().cmp(b.id()));
+        let mut errors = match &est["errors"] {
+            Value::Null => Vec::new(),
+            Value::Array(ids) => ids
+                .iter()
+                .map(|id| {
+                    id.as_str()
+                        .map(PolicyId::new)
+                        .ok_or_else(|| invalid(format!("policy ID {id} is not a string")))
+                })
+                .collect::<Result<_>>()?,
+            _ => return Err(invalid("`errors` is not an array".to_string())),
+        };
+        errors.sort();
+        Ok(Self {
+            errors,
+            ..Self::new(decision, policies, nontrivial)
+        })
+    }
+}
+
+/// The decision on residual `policies` as Cedar's TPE makes it: a satisfied forbid denies, a
+/// conditional one leaves it unknown, and otherwise a satisfied permit allows.
+fn decide(policies: &[Policy], nontrivial: &HashSet<PolicyId>) -> Option<Decision> {
+    let (mut permitted, mut maybe_permitted, mut maybe_forbidden) = (false, false, false);
+    for policy in policies {
+        let conditional = nontrivial.contains(policy.id());
+        let satisfied = !conditional && condition_json(policy) == json!({ "Value": true });
+        match policy.effect() {
+            Effect::Forbid if satisfied => return Some(Decision::Deny),
+            Effect::Forbid => maybe_forbidden |= conditional,
+            Effect::Permit => {
+                permitted |= satisfied;
+                maybe_permitted |= conditional;
+            }
+        }
+    }
+    match (permitted, maybe_permitted, maybe_forbidden) {
+        (false, false, _) => Some(Decision::Deny),
+        (_, _, true) | (false, true, false) => None,
+        (true, _, false) => Some(Decision::Allow),
+    }
+}
+
+/// The residual condition of `policy`, in Cedar's JSON format.
+fn condition_json(policy: &Policy) -> Value {
+    let mut json = policy
+        .to_json()
+        .expect("Residual policies are not templates");
+    json["conditions"][0]["body"].take()
+}
+
+// The EST with the `false` residual policies, so residuals round-trip.
+impl Serialize for Residuals {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        self.to_json(true).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Residuals {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        Self::from_est(&Value::deserialize(deserializer)?).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use cedar_policy::{Context, Entities, EntityTypeName, EntityUid, SchemaFragment};
+
+    use crate::{CEDAR_SCHEMA_SRC, Engine, pdp::PartialUid};
+
+    use super::*;
+
+    const POLICIES: &str = r#"
+@owner("team")
+permit (principal in MyApp::Role::"admin", action, resource);
+permit (principal, action, resource is MyApp::Server);
+forbid (principal, action, resource) when { principal has project && principal.project == resource };
+"#;
+
+    fn residuals(policies: &str) -> Residuals {
+        let engine = Engine::new(
+            SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(),
+            PolicySet::from_str(policies).unwrap(),
+            Entities::empty(),
+        )
+        .unwrap();
+        engine
+            .tpe(
+                PartialUid::unknown(EntityTypeName::from_str("MyApp::User").unwrap()),
+                EntityUid::from_str(r#"MyApp::Action::"GetProjectMetadata""#).unwrap(),
+                PartialUid::from(EntityUid::from_str(r#"MyApp::Project::"0""#).unwrap()),
+                Some(Context::empty()),
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn test_snapshot() {
+        assert_eq!(
+            residuals(POLICIES).snapshot(),
+            "decision: unknown\n\
+             permit policy0: principal in MyApp::Role::\"admin\"\n\
+             permit policy1: false\n\
+             forbid policy2: principal has project\n"
+        );
+    }
+
+    #[test]
+    fn test_serde() {
+        let residuals = residuals(POLICIES);
+        let json = serde_json::to_string(&residuals).unwrap();
+        let deserialized = serde_json::from_str::<Residuals>(&json).unwrap();
+        assert_eq!(deserialized.snapshot(), residuals.snapshot());
+        assert_eq!(deserialized.classify(), residuals.classify());
+        assert_eq!(deserialized.fingerprint(), residuals.fingerprint());
+        assert_eq!(
+            serde_json::to_value(residuals.classify()).unwrap()["unsatisfied"],
+            json!(["policy1"])
+        );
+
+        let est = |policy: Value| json!({ "decision": null, "policies": { "p": policy } });
+        let mut policy = serde_json::to_value(&residuals).unwrap()["policies"]["policy0"].take();
+        assert!(Residuals::from_est(&est(policy.clone())).is_ok());
+        for invalid in [
+            json!({ "decision": "maybe", "policies": {} }),
+            json!({ "decision": null }),
+            json!({ "decision": null, "policies": {}, "errors": [0] }),
+            est(json!({})),
+        ] {
+            assert!(Residuals::from_est(&invalid).is_err(), "{invalid}");
+        }
+        policy["conditions"] = json!([]);
+        assert!(Residuals::from_est(&est(policy.clone())).is_err());
+        policy["conditions"] = json!([{ "kind": "unless", "body": { "Value": true } }]);
+        assert!(Residuals::from_est(&est(policy)).is_err());
+    }
+
+    #[test]
+    fn test_classify() {
+        let residuals = residuals(&format!(
+            "{POLICIES}\npermit (principal, action, resource is MyApp::Project);"
+        ));
+        let ids = |ids: &[&str]| ids.iter().map(|id| PolicyId::new(*id)).collect::<Vec<_>>();
+        assert_eq!(
+            residuals.classify(),
+            Classification {
+                satisfied: ids(&["policy3"]),
+                conditional: ids(&["policy0", "policy2"]),
+                unsatisfied: ids(&["policy1"]),
+            }
+        );
+        assert_eq!(
+            residuals.determination(&PolicyId::new("policy1")),
+            Some(Determination::Unsatisfied)
+        );
+        assert_eq!(residuals.determination(&PolicyId::new("missing")), None);
+    }
+}
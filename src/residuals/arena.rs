@@ -0,0 +1,178 @@
+//! Residual conditions hash-consed into an arena: every distinct sub-expression is stored once
+//! and referred to by an [`ExprId`], so the sub-expressions residual policies have in common,
+//! such as the same hierarchy test, are shared.
+//!
+//! Equal expressions have equal IDs, so comparing them is a single integer comparison, and
+//! each distinct expression is fingerprinted once, from the fingerprints of its children, when
+//! a fingerprint is first asked for.
+
+use std::{collections::HashMap, sync::OnceLock};
+
+use serde_json::{Map, Value};
+
+use crate::fingerprint::sha256;
+
+/// An expression in an [`ExprArena`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ExprId(u32);
+
+/// A node of an expression in Cedar's JSON format, with its children interned.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Node {
+    /// A string, number, boolean or null, as JSON text.
+    Scalar(String),
+    Array(Vec<ExprId>),
+    /// An object, by key.
+    Object(Vec<(String, ExprId)>),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ExprArena {
+    nodes: Vec<Node>,
+    /// Per node, computed for all nodes at once.
+    fingerprints: OnceLock<Vec<String>>,
+    ids: HashMap<Node, ExprId>,
+}
+
+impl ExprArena {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Intern `expr`, reusing the nodes of equal sub-expressions interned before.
+    pub fn intern(&mut self, expr: &Value) -> ExprId {
+        let node = match expr {
+            Value::Array(values) => Node::Array(values.iter().map(|v| self.intern(v)).collect()),
+            Value::Object(map) => {
+                let mut entries = map
+                    .iter()
+                    .map(|(key, value)| (key.clone(), self.intern(value)))
+                    .collect::<Vec<_>>();
+                entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+                Node::Object(entries)
+            }
+            scalar => Node::Scalar(scalar.to_string()),
+        };
+        if let Some(id) = self.ids.get(&node) {
+            return *id;
+        }
+        let id = ExprId(u32::try_from(self.nodes.len()).expect("fewer than 2^32 expressions"));
+        self.fingerprints.take();
+        self.ids.insert(node.clone(), id);
+        self.nodes.push(node);
+        id
+    }
+
+    /// The ID of `expr` if it was interned.
+    pub fn find(&self, expr: &Value) -> Option<ExprId> {
+        let node = match expr {
+            Value::Array(values) => {
+                Node::Array(values.iter().map(|v| self.find(v)).collect::<Option<_>>()?)
+            }
+            Value::Object(map) => {
+                let mut entries = map
+                    .iter()
+                    .map(|(key, value)| Some((key.clone(), self.find(value)?)))
+                    .collect::<Option<Vec<_>>>()?;
+                entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+                Node::Object(entries)
+            }
+            scalar => Node::Scalar(scalar.to_string()),
+        };
+        self.ids.get(&node).copied()
+    }
+
+    pub fn get(&self, id: ExprId) -> &Node {
+        &self.nodes[id.0 as usize]
+    }
+
+    /// The number of distinct sub-expressions.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// The expression in Cedar's JSON format, with object keys sorted.
+    pub fn to_json(&self, id: ExprId) -> Value {
+        match self.get(id) {
+            Node::Scalar(text) => serde_json::from_str(text).expect("scalars are valid JSON"),
+            Node::Array(ids) => Value::Array(ids.iter().map(|id| self.to_json(*id)).collect()),
+            Node::Object(entries) => Value::Object(
+                entries
+                    .iter()
+                    .map(|(key, id)| (key.clone(), self.to_json(*id)))
+                    .collect::<Map<_, _>>(),
+            ),
+        }
+    }
+
+    /// Hex-encoded SHA-256 of the expression, equal for equal expressions in any arena.
+    pub fn fingerprint(&self, id: ExprId) -> &str {
+        &self.fingerprints.get_or_init(|| self.fingerprint_all())[id.0 as usize]
+    }
+
+    // Children are interned before their parents, so their fingerprints come first.
+    fn fingerprint_all(&self) -> Vec<String> {
+        let mut fingerprints = Vec::<String>::with_capacity(self.nodes.len());
+        for node in &self.nodes {
+            let child = |id: &ExprId| fingerprints[id.0 as usize].as_str();
+            let content = match node {
+                Node::Scalar(text) => format!("s{text}"),
+                Node::Array(ids) => ids
+                    .iter()
+                    .fold("a".to_string(), |content, id| content + " " + child(id)),
+                Node::Object(entries) => {
+                    entries.iter().fold("o".to_string(), |content, (key, id)| {
+                        // Keys are JSON-quoted, so they cannot run into the fingerprints.
+                        content + " " + &Value::from(key.as_str()).to_string() + child(id)
+                    })
+                }
+            };
+            fingerprints.push(sha256(content.as_bytes()));
+        }
+        fingerprints
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_sharing() {
+        let admin = json!({ "in": {
+            "left": { "Var": "principal" },
+            "right": { "Value": { "__entity": { "type": "MyApp::Role", "id": "admin" } } },
+        }});
+        let first = json!({ "&&": { "left": admin, "right": { "Value": true } } });
+        let second = json!({ "||": { "left": { "Value": false }, "right": admin } });
+
+        let mut arena = ExprArena::new();
+        let first_id = arena.intern(&first);
+        let nodes = arena.len();
+        let second_id = arena.intern(&second);
+        let admin_id = arena.find(&admin).unwrap();
+        let Node::Object(entries) = arena.get(second_id) else {
+            panic!("`||` is an object");
+        };
+        let Node::Object(operands) = arena.get(entries[0].1) else {
+            panic!("the operands are an object");
+        };
+        assert_eq!(operands[1], ("right".to_string(), admin_id));
+        // Only `||`, its operands and `false` are new.
+        assert_eq!(arena.len(), nodes + 4);
+
+        assert_eq!(arena.to_json(first_id), first);
+        let mut other = ExprArena::new();
+        other.intern(&second);
+        let same = other.find(&admin).unwrap();
+        assert_eq!(other.fingerprint(same), arena.fingerprint(admin_id));
+        assert_ne!(arena.fingerprint(first_id), arena.fingerprint(second_id));
+        assert_eq!(arena.find(&json!({ "Value": 1 })), None);
+    }
+}
@@ -0,0 +1,818 @@
+//! Residuals compiled to queries for a backend that stores the entities of the unknown, so a
+//! list can be filtered where it is stored instead of authorizing every row.
+//!
+//! The unknown is the principal or the resource of a request with its ID unknown; a
+//! [`FilterTable`] describes the rows it is looked up in, one row or document per entity:
+//!
+//! - the entity ID is in the [`id`](FilterTable::id) field;
+//! - each attribute is in the field of its name, records are nested, e.g. a JSONB column in
+//!   Postgres and a subdocument in MongoDB, and sets are arrays;
+//! - attributes referring to entities hold the ID of the entity;
+//! - the UIDs of the entity's ancestors, in Cedar syntax such as `MyApp::Server::"0"`, are in
+//!   the array field [`ancestors`](FilterTable::ancestors).
+//!
+//! A row is selected if a permit residual holds for it and no forbid residual does. Rows are
+//! assumed to have every attribute a residual reads without a `has` test. For a row that does
+//! not, Cedar fails to evaluate the policy, and the filter may decide the row differently.
+//!
+//! Residuals that read anything other than the unknown's ID, attributes and ancestors, e.g.
+//! the context when it is unknown, or that use operators without an equivalent in the
+//! backend, such as extension functions, fail to compile.
+
+use std::str::FromStr;
+
+use cedar_policy::{Decision, Effect, EntityId, EntityTypeName, EntityUid};
+use serde_json::{Map, Value, json};
+
+use super::{Residuals, condition_json};
+use crate::{Error, Result};
+
+/// The request variable whose ID is unknown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variable {
+    Principal,
+    Resource,
+}
+
+impl Variable {
+    fn name(self) -> &'static str {
+        match self {
+            Variable::Principal => "principal",
+            Variable::Resource => "resource",
+        }
+    }
+}
+
+/// Where the entities of the unknown are stored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterTable {
+    pub variable: Variable,
+    pub entity_type: EntityTypeName,
+    /// The field of the entity ID. Defaults to `id`.
+    pub id: String,
+    /// The array field of the ancestors' UIDs. Defaults to `ancestors`.
+    pub ancestors: String,
+}
+
+impl FilterTable {
+    pub fn new(variable: Variable, entity_type: EntityTypeName) -> Self {
+        Self {
+            variable,
+            entity_type,
+            id: "id".to_string(),
+            ancestors: "ancestors".to_string(),
+        }
+    }
+
+    /// A condition for the `WHERE` clause of a Postgres query over the table.
+    pub fn postgres(&self, residuals: &Residuals) -> Result<String> {
+        Ok(self.sql(&self.filter(residuals)?))
+    }
+
+    /// A MongoDB query document over the collection.
+    pub fn mongo(&self, residuals: &Residuals) -> Result<Value> {
+        Ok(self.document(&self.filter(residuals)?))
+    }
+
+    fn filter(&self, residuals: &Residuals) -> Result<Condition> {
+        if let Some(decision) = residuals.decision() {
+            return Ok(Condition::Bool(decision == Decision::Allow));
+        }
+        let (mut permits, mut forbids) = (Vec::new(), Vec::new());
+        for policy in residuals.policies() {
+            let condition = self.condition(&condition_json(policy))?;
+            match policy.effect() {
+                Effect::Permit => permits.push(condition),
+                Effect::Forbid => forbids.push(condition),
+            }
+        }
+        Ok(and(vec![or(permits), not(or(forbids))]))
+    }
+
+    /// The condition of a residual expression in Cedar's JSON format.
+    fn condition(&self, expr: &Value) -> Result<Condition> {
+        let (op, args) = operator(expr)?;
+        match op {
+            "Value" => match args {
+                Value::Bool(value) => Ok(Condition::Bool(*value)),
+                _ => Err(unsupported("a value that is not a boolean")),
+            },
+            "&&" | "||" => {
+                let operands = vec![
+                    self.condition(&args["left"])?,
+                    self.condition(&args["right"])?,
+                ];
+                Ok(if op == "&&" {
+                    and(operands)
+                } else {
+                    or(operands)
+                })
+            }
+            "!" => Ok(not(self.condition(&args["arg"])?)),
+            "if-then-else" => {
+                let test = self.condition(&args["if"])?;
+                let then = self.condition(&args["then"])?;
+                let otherwise = self.condition(&args["else"])?;
+                Ok(or(vec![
+                    and(vec![test.clone(), then]),
+                    and(vec![not(test), otherwise]),
+                ]))
+            }
+            "==" | "!=" | "<" | "<=" | ">" | ">=" => {
+                let op = Op::from_str(op);
+                match (self.path(&args["left"])?, self.path(&args["right"])?) {
+                    (Some(path), None) => self.compare(path, op, literal(&args["right"])?),
+                    (None, Some(path)) => self.compare(path, op.flip(), literal(&args["left"])?),
+                    _ => Err(unsupported(
+                        "comparisons that are not between a field and a value",
+                    )),
+                }
+            }
+            "has" => {
+                let mut path = self.field(&args["left"])?;
+                path.push(attribute(args)?);
+                Ok(Condition::Has(path))
+            }
+            "like" => {
+                let path = self.attribute_path(&args["left"])?;
+                let pattern = args["pattern"]
+                    .as_array()
+                    .ok_or_else(|| unsupported("`like` without a pattern"))?
+                    .iter()
+                    .map(|element| match element {
+                        Value::String(wildcard) if wildcard == "Wildcard" => Ok(Like::Wildcard),
+                        element => element["Literal"]
+                            .as_str()
+                            .map(|literal| Like::Literal(literal.to_string()))
+                            .ok_or_else(|| unsupported("`like` patterns of this form")),
+                    })
+                    .collect::<Result<_>>()?;
+                Ok(Condition::Like(path, pattern))
+            }
+            "contains" => {
+                if let Some(path) = self.path(&args["left"])? {
+                    let element = self.value(&path, literal(&args["right"])?)?;
+                    return Ok(Condition::Contains(path, element));
+                }
+                let path = self.attribute_path(&args["right"])?;
+                let Some(Value::Array(elements)) = args["left"].get("Set") else {
+                    return Err(unsupported("`contains` on a value that is not a set"));
+                };
+                let values = elements
+                    .iter()
+                    .map(|element| self.value(&path, literal(element)?))
+                    .collect::<Result<_>>()?;
+                Ok(Condition::OneOf(path, values))
+            }
+            "in" => {
+                if self.path(&args["left"])? != Some(Vec::new()) {
+                    return Err(unsupported("`in` on anything but the unknown"));
+                }
+                Ok(self.in_any(entities(&args["right"])?))
+            }
+            "is" => {
+                if self.path(&args["left"])? != Some(Vec::new()) {
+                    return Err(unsupported("`is` on anything but the unknown"));
+                }
+                let entity_type = args["entity_type"]
+                    .as_str()
+                    .ok_or_else(|| unsupported("`is` without an entity type"))?;
+                if entity_type != self.entity_type.to_string() {
+                    return Ok(Condition::Bool(false));
+                }
+                match args.get("in") {
+                    Some(ancestors) => Ok(self.in_any(entities(ancestors)?)),
+                    None => Ok(Condition::Bool(true)),
+                }
+            }
+            "." => {
+                let path = self.attribute_path(expr)?;
+                Ok(Condition::Compare(path, Op::Eq, Scalar::Bool(true)))
+            }
+            op => Err(Error::Mapping(format!(
+                "Cannot compile residuals with `{op}` to a filter"
+            ))),
+        }
+    }
+
+    fn compare(&self, path: Vec<String>, op: Op, literal: Literal) -> Result<Condition> {
+        if path.is_empty() {
+            let Literal::Entity(uid) = literal else {
+                return Err(unsupported(
+                    "comparing an entity with a value that is not one",
+                ));
+            };
+            let equal = match self.own_id(&uid) {
+                Some(id) => Condition::Compare(path, Op::Eq, Scalar::String(id)),
+                None => Condition::Bool(false),
+            };
+            return match op {
+                Op::Eq => Ok(equal),
+                Op::Ne => Ok(not(equal)),
+                _ => Err(unsupported("ordering entities")),
+            };
+        }
+        Ok(Condition::Compare(
+            path.clone(),
+            op,
+            self.value(&path, literal)?,
+        ))
+    }
+
+    /// The unknown is one of `ancestors` or a descendant of one.
+    fn in_any(&self, ancestors: Vec<EntityUid>) -> Condition {
+        let ids = ancestors
+            .iter()
+            .filter_map(|uid| self.own_id(uid))
+            .map(Scalar::String)
+            .collect::<Vec<_>>();
+        let ancestors = Condition::Ancestors(ancestors.iter().map(ToString::to_string).collect());
+        if ids.is_empty() {
+            ancestors
+        } else {
+            or(vec![Condition::OneOf(Vec::new(), ids), ancestors])
+        }
+    }
+
+    /// The ID of `uid` if it is of the unknown's type.
+    fn own_id(&self, uid: &EntityUid) -> Option<String> {
+        (*uid.type_name() == self.entity_type).then(|| AsRef::<str>::as_ref(uid.id()).to_string())
+    }
+
+    /// The scalar stored for `literal` in the field at `path`: entities are stored by ID.
+    fn value(&self, path: &[String], literal: Literal) -> Result<Scalar> {
+        match literal {
+            Literal::Scalar(scalar) => Ok(scalar),
+            Literal::Entity(uid) if !path.is_empty() => {
+                Ok(Scalar::String(AsRef::<str>::as_ref(uid.id()).to_string()))
+            }
+            Literal::Entity(_) => Err(unsupported("comparing an entity with a value")),
+        }
+    }
+
+    /// The attribute path of `expr` if it is the unknown or one of its attributes, with the
+    /// empty path for the unknown itself.
+    fn path(&self, expr: &Value) -> Result<Option<Vec<String>>> {
+        let (op, args) = operator(expr)?;
+        match op {
+            "Var" if args == self.variable.name() => Ok(Some(Vec::new())),
+            "Var" => Err(Error::Mapping(format!(
+                "Cannot compile residuals that depend on the unknown {} to a filter",
+                args.as_str().unwrap_or_default()
+            ))),
+            "." => {
+                let Some(mut path) = self.path(&args["left"])? else {
+                    return Ok(None);
+                };
+                path.push(attribute(args)?);
+                Ok(Some(path))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn field(&self, expr: &Value) -> Result<Vec<String>> {
+        self.path(expr)?
+            .ok_or_else(|| unsupported("attributes of anything but the unknown"))
+    }
+
+    fn attribute_path(&self, expr: &Value) -> Result<Vec<String>> {
+        Some(self.field(expr)?)
+            .filter(|path| !path.is_empty())
+            .ok_or_else(|| unsupported("this operator on an entity"))
+    }
+
+    fn sql(&self, condition: &Condition) -> String {
+        let column = |path: &[String], scalar: Option<&Scalar>| match path {
+            [] => quote_identifier(&self.id),
+            [column] => quote_identifier(column),
+            [column, nested @ ..] => {
+                let mut json = quote_identifier(column);
+                for (i, key) in nested.iter().enumerate() {
+                    let arrow = if i + 1 == nested.len() && scalar.is_some() {
+                        "->>"
+                    } else {
+                        "->"
+                    };
+                    json += &format!("{arrow}{}", quote_string(key));
+                }
+                match scalar {
+                    Some(Scalar::Bool(_)) => format!("({json})::boolean"),
+                    Some(Scalar::Long(_)) => format!("({json})::bigint"),
+                    _ => json,
+                }
+            }
+        };
+        match condition {
+            Condition::Bool(true) => "TRUE".to_string(),
+            Condition::Bool(false) => "FALSE".to_string(),
+            Condition::And(conditions) | Condition::Or(conditions) => {
+                let separator = match condition {
+                    Condition::And(_) => " AND ",
+                    _ => " OR ",
+                };
+                let conditions = conditions.iter().map(|c| self.sql(c)).collect::<Vec<_>>();
+                format!("({})", conditions.join(separator))
+            }
+            Condition::Not(condition) => match **condition {
+                Condition::And(_) | Condition::Or(_) => format!("NOT {}", self.sql(condition)),
+                _ => format!("NOT ({})", self.sql(condition)),
+            },
+            Condition::Compare(path, op, scalar) => format!(
+                "{} {} {}",
+                column(path, Some(scalar)),
+                op.sql(),
+                sql_scalar(scalar)
+            ),
+            Condition::Has(path) => format!("{} IS NOT NULL", column(path, None)),
+            Condition::Like(path, pattern) => {
+                let pattern = pattern
+                    .iter()
+                    .map(|element| match element {
+                        Like::Literal(literal) => literal
+                            .chars()
+                            .flat_map(|c| match c {
+                                '%' | '_' | '\\' => vec!['\\', c],
+                                c => vec![c],
+                            })
+                            .collect(),
+                        Like::Wildcard => "%".to_string(),
+                    })
+                    .collect::<String>();
+                let text = Scalar::String(String::new());
+                format!(
+                    "{} LIKE {}",
+                    column(path, Some(&text)),
+                    quote_string(&pattern)
+                )
+            }
+            Condition::Contains(path, scalar) => match path.as_slice() {
+                [column] => format!("{} = ANY({})", sql_scalar(scalar), quote_identifier(column)),
+                _ => format!(
+                    "{} @> {}::jsonb",
+                    column(path, None),
+                    quote_string(&json!([scalar.json()]).to_string())
+                ),
+            },
+            Condition::OneOf(path, scalars) => {
+                let values = scalars.iter().map(sql_scalar).collect::<Vec<_>>();
+                format!(
+                    "{} IN ({})",
+                    column(path, scalars.first()),
+                    values.join(", ")
+                )
+            }
+            Condition::Ancestors(uids) => {
+                let uids = uids.iter().map(|uid| quote_string(uid)).collect::<Vec<_>>();
+                format!(
+                    "{} && ARRAY[{}]::text[]",
+                    quote_identifier(&self.ancestors),
+                    uids.join(", ")
+                )
+            }
+        }
+    }
+
+    fn document(&self, condition: &Condition) -> Value {
+        let field = |path: &[String]| match path {
+            [] => self.id.clone(),
+            path => path.join("."),
+        };
+        let single = |field: String, value: Value| Value::Object(Map::from_iter([(field, value)]));
+        match condition {
+            Condition::Bool(true) => json!({}),
+            Condition::Bool(false) => json!({ "$expr": false }),
+            Condition::And(conditions) => {
+                json!({ "$and": conditions.iter().map(|c| self.document(c)).collect::<Vec<_>>() })
+            }
+            Condition::Or(conditions) => {
+                json!({ "$or": conditions.iter().map(|c| self.document(c)).collect::<Vec<_>>() })
+            }
+            Condition::Not(condition) => json!({ "$nor": [self.document(condition)] }),
+            Condition::Compare(path, Op::Eq, scalar) => single(field(path), scalar.json()),
+            Condition::Compare(path, op, scalar) => {
+                single(field(path), single(op.mongo().to_string(), scalar.json()))
+            }
+            Condition::Has(path) => single(field(path), json!({ "$exists": true })),
+            Condition::Like(path, pattern) => {
+                let mut regex = "^".to_string();
+                for element in pattern {
+                    match element {
+                        Like::Literal(literal) => {
+                            for c in literal.chars() {
+                                if "\\.^$|?*+()[]{}".contains(c) {
+                                    regex.push('\\');
+                                }
+                                regex.push(c);
+                            }
+                        }
+                        Like::Wildcard => regex += ".*",
+                    }
+                }
+                regex.push('$');
+                single(field(path), json!({ "$regex": regex, "$options": "s" }))
+            }
+            Condition::Contains(path, scalar) => single(field(path), scalar.json()),
+            Condition::OneOf(path, scalars) => single(
+                field(path),
+                json!({ "$in": scalars.iter().map(Scalar::json).collect::<Vec<_>>() }),
+            ),
+            Condition::Ancestors(uids) => single(self.ancestors.clone(), json!({ "$in": uids })),
+        }
+    }
+}
+
+/// A residual condition on one row.
+#[derive(Debug, Clone, PartialEq)]
+enum Condition {
+    Bool(bool),
+    And(Vec<Condition>),
+    Or(Vec<Condition>),
+    Not(Box<Condition>),
+    /// The field at the path compared with a value. The empty path is the ID.
+    Compare(Vec<String>, Op, Scalar),
+    Has(Vec<String>),
+    Like(Vec<String>, Vec<Like>),
+    /// The array field at the path contains the value.
+    Contains(Vec<String>, Scalar),
+    /// The field at the path is one of the values.
+    OneOf(Vec<String>, Vec<Scalar>),
+    /// One of the UIDs is an ancestor.
+    Ancestors(Vec<String>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    fn from_str(op: &str) -> Self {
+        match op {
+            "==" => Op::Eq,
+            "!=" => Op::Ne,
+            "<" => Op::Lt,
+            "<=" => Op::Le,
+            ">" => Op::Gt,
+            _ => Op::Ge,
+        }
+    }
+
+    /// The operator with its operands swapped.
+    fn flip(self) -> Self {
+        match self {
+            Op::Lt => Op::Gt,
+            Op::Le => Op::Ge,
+            Op::Gt => Op::Lt,
+            Op::Ge => Op::Le,
+            op => op,
+        }
+    }
+
+    fn sql(self) -> &'static str {
+        match self {
+            Op::Eq => "=",
+            Op::Ne => "<>",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+        }
+    }
+
+    fn mongo(self) -> &'static str {
+        match self {
+            Op::Eq => "$eq",
+            Op::Ne => "$ne",
+            Op::Lt => "$lt",
+            Op::Le => "$lte",
+            Op::Gt => "$gt",
+            Op::Ge => "$gte",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Scalar {
+    Bool(bool),
+    Long(i64),
+    String(String),
+}
+
+impl Scalar {
+    fn json(&self) -> Value {
+        match self {
+            Scalar::Bool(value) => json!(value),
+            Scalar::Long(value) => json!(value),
+            Scalar::String(value) => json!(value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Literal {
+    Scalar(Scalar),
+    Entity(EntityUid),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Like {
+    Literal(String),
+    Wildcard,
+}
+
+/// The conjunction with constants folded and nested conjunctions flattened.
+fn and(conditions: Vec<Condition>) -> Condition {
+    let mut operands = Vec::new();
+    for condition in conditions {
+        match condition {
+            Condition::Bool(true) => {}
+            Condition::Bool(false) => return Condition::Bool(false),
+            Condition::And(nested) => operands.extend(nested),
+            condition => operands.push(condition),
+        }
+    }
+    match operands.len() {
+        0 => Condition::Bool(true),
+        1 => operands.remove(0),
+        _ => Condition::And(operands),
+    }
+}
+
+/// The disjunction with constants folded and nested disjunctions flattened.
+fn or(conditions: Vec<Condition>) -> Condition {
+    let mut operands = Vec::new();
+    for condition in conditions {
+        match condition {
+            Condition::Bool(false) => {}
+            Condition::Bool(true) => return Condition::Bool(true),
+            Condition::Or(nested) => operands.extend(nested),
+            condition => operands.push(condition),
+        }
+    }
+    match operands.len() {
+        0 => Condition::Bool(false),
+        1 => operands.remove(0),
+        _ => Condition::Or(operands),
+    }
+}
+
+fn not(condition: Condition) -> Condition {
+    match condition {
+        Condition::Bool(value) => Condition::Bool(!value),
+        Condition::Not(condition) => *condition,
+        condition => Condition::Not(Box::new(condition)),
+    }
+}
+
+/// The operator of an expression in Cedar's JSON format and its arguments.
+fn operator(expr: &Value) -> Result<(&str, &Value)> {
+    match expr
+        .as_object()
+        .map(|object| object.iter().collect::<Vec<_>>())
+    {
+        Some(entries) if entries.len() == 1 => Ok((entries[0].0.as_str(), entries[0].1)),
+        _ => Err(Error::Mapping(format!(
+            "Invalid residual expression `{expr}`"
+        ))),
+    }
+}
+
+fn attribute(args: &Value) -> Result<String> {
+    args["attr"]
+        .as_str()
+        .map(ToString::to_string)
+        .ok_or_else(|| unsupported("attribute accesses without a name"))
+}
+
+fn literal(expr: &Value) -> Result<Literal> {
+    let (op, value) = operator(expr)?;
+    match (op, value) {
+        ("Value", Value::Bool(value)) => Ok(Literal::Scalar(Scalar::Bool(*value))),
+        ("Value", Value::String(value)) => Ok(Literal::Scalar(Scalar::String(value.clone()))),
+        ("Value", Value::Number(value)) => value
+            .as_i64()
+            .map(|value| Literal::Scalar(Scalar::Long(value)))
+            .ok_or_else(|| unsupported("numbers that are not longs")),
+        ("Value", _) => entity(expr).map(Literal::Entity),
+        _ => Err(unsupported("comparisons with computed values")),
+    }
+}
+
+/// The entity or set of entities on the right of `in`.
+fn entities(expr: &Value) -> Result<Vec<EntityUid>> {
+    match expr.get("Set") {
+        Some(Value::Array(elements)) => elements.iter().map(entity).collect(),
+        _ => entity(expr).map(|uid| vec![uid]),
+    }
+}
+
+fn entity(expr: &Value) -> Result<EntityUid> {
+    let uid = &expr["Value"]["__entity"];
+    let (Some(entity_type), Some(id)) = (uid["type"].as_str(), uid["id"].as_str()) else {
+        return Err(unsupported(
+            "values that are not entities where entities are expected",
+        ));
+    };
+    let entity_type = EntityTypeName::from_str(entity_type)
+        .map_err(|e| Error::Mapping(format!("Invalid entity type `{entity_type}`: {e}")))?;
+    Ok(EntityUid::from_type_name_and_id(
+        entity_type,
+        EntityId::new(id),
+    ))
+}
+
+fn unsupported(what: &str) -> Error {
+    Error::Mapping(format!("Cannot compile residuals with {what} to a filter"))
+}
+
+fn sql_scalar(scalar: &Scalar) -> String {
+    match scalar {
+        Scalar::Bool(value) => value.to_string().to_uppercase(),
+        Scalar::Long(value) => value.to_string(),
+        Scalar::String(value) => quote_string(value),
+    }
+}
+
+fn quote_identifier(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+fn quote_string(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use cedar_policy::{Context, Entities, PolicySet, Schema, SchemaFragment};
+
+    use crate::{Engine, pdp::PartialUid};
+
+    use super::*;
+
+    const SCHEMA: &str = r#"
+namespace Docs {
+    entity Folder;
+    entity User { level: Long };
+    entity Document in [Folder] {
+        owner: User,
+        title: String,
+        public: Bool,
+        tags: Set<String>,
+        meta: { region: String, size: Long },
+        draft?: Bool,
+    };
+    action Read appliesTo { principal: [User], resource: [Document] };
+}
+"#;
+
+    const POLICIES: &str = r#"
+permit (principal, action, resource in Docs::Folder::"shared") when { resource.public };
+permit (principal, action, resource) when { resource.owner == principal };
+permit (principal, action, resource)
+when { resource.title like "Q*_report" && resource.meta.size <= principal.level };
+forbid (principal, action, resource) when { resource has draft && resource.draft };
+forbid (principal, action, resource)
+when { resource.tags.contains("secret") || resource.meta.region != "eu" };
+"#;
+
+    fn residuals(policies: &str) -> Residuals {
+        let (fragment, _) = SchemaFragment::from_cedarschema_str(SCHEMA).unwrap();
+        let schema = Schema::from_schema_fragments([fragment.clone()]).unwrap();
+        let entities = Entities::from_json_value(
+            json!([{ "uid": { "type": "Docs::User", "id": "alice" }, "attrs": { "level": 3 }, "parents": [] }]),
+            Some(&schema),
+        )
+        .unwrap();
+        let engine =
+            Engine::new(fragment, PolicySet::from_str(policies).unwrap(), entities).unwrap();
+        engine
+            .tpe(
+                PartialUid::from(EntityUid::from_str(r#"Docs::User::"alice""#).unwrap()),
+                EntityUid::from_str(r#"Docs::Action::"Read""#).unwrap(),
+                PartialUid::unknown(document()),
+                Some(Context::empty()),
+            )
+            .unwrap()
+    }
+
+    fn document() -> EntityTypeName {
+        EntityTypeName::from_str("Docs::Document").unwrap()
+    }
+
+    #[test]
+    fn test_postgres() {
+        let table = FilterTable::new(Variable::Resource, document());
+        assert_eq!(
+            table.postgres(&residuals(POLICIES)).unwrap(),
+            "(((\"ancestors\" && ARRAY['Docs::Folder::\"shared\"']::text[] AND \"public\" = TRUE) \
+             OR \"owner\" = 'alice' \
+             OR (\"title\" LIKE 'Q%\\_report' AND (\"meta\"->>'size')::bigint <= 3)) \
+             AND NOT ((\"draft\" IS NOT NULL AND \"draft\" = TRUE) \
+             OR 'secret' = ANY(\"tags\") OR NOT (\"meta\"->>'region' = 'eu')))"
+        );
+        assert_eq!(
+            table
+                .postgres(&residuals(r#"permit (principal, action, resource);"#))
+                .unwrap(),
+            "TRUE"
+        );
+        assert_eq!(table.postgres(&residuals("")).unwrap(), "FALSE");
+        assert_eq!(
+            table
+                .postgres(&residuals(
+                    r#"permit (principal, action, resource) when { ["x", "y"].contains(resource.title) };"#
+                ))
+                .unwrap(),
+            "\"title\" IN ('x', 'y')"
+        );
+    }
+
+    #[test]
+    fn test_mongo() {
+        let table = FilterTable {
+            ancestors: "parents".to_string(),
+            ..FilterTable::new(Variable::Resource, document())
+        };
+        assert_eq!(
+            table.mongo(&residuals(POLICIES)).unwrap(),
+            json!({ "$and": [
+                { "$or": [
+                    { "$and": [
+                        { "parents": { "$in": [r#"Docs::Folder::"shared""#] } },
+                        { "public": true },
+                    ] },
+                    { "owner": "alice" },
+                    { "$and": [
+                        { "title": { "$regex": "^Q.*_report$", "$options": "s" } },
+                        { "meta.size": { "$lte": 3 } },
+                    ] },
+                ] },
+                { "$nor": [{ "$or": [
+                    { "$and": [{ "draft": { "$exists": true } }, { "draft": true }] },
+                    { "tags": "secret" },
+                    { "$nor": [{ "meta.region": "eu" }] },
+                ] }] },
+            ] })
+        );
+        assert_eq!(
+            table.mongo(&residuals("")).unwrap(),
+            json!({ "$expr": false })
+        );
+    }
+
+    #[test]
+    fn test_unknown_principal() {
+        let user = EntityTypeName::from_str("Docs::User").unwrap();
+        let residuals = Engine::new(
+            SchemaFragment::from_cedarschema_str(SCHEMA).unwrap().0,
+            PolicySet::from_str(
+                r#"
+permit (principal == Docs::User::"bob", action, resource);
+permit (principal, action, resource) when { principal.level > 5 };
+"#,
+            )
+            .unwrap(),
+            Entities::empty(),
+        )
+        .unwrap()
+        .tpe(
+            PartialUid::unknown(user.clone()),
+            EntityUid::from_str(r#"Docs::Action::"Read""#).unwrap(),
+            PartialUid::from(EntityUid::from_str(r#"Docs::Document::"0""#).unwrap()),
+            Some(Context::empty()),
+        )
+        .unwrap();
+        let table = FilterTable::new(Variable::Principal, user);
+        assert_eq!(
+            table.postgres(&residuals).unwrap(),
+            "(\"id\" = 'bob' OR NOT (\"level\" <= 5))"
+        );
+        assert_eq!(
+            table.mongo(&residuals).unwrap(),
+            json!({ "$or": [{ "id": "bob" }, { "$nor": [{ "level": { "$lte": 5 } }] }] })
+        );
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let table = FilterTable::new(Variable::Resource, document());
+        let extension = residuals(
+            r#"permit (principal, action, resource) when { resource.tags.containsAll(["a"]) };"#,
+        );
+        let error = table.mongo(&extension).unwrap_err().to_string();
+        assert!(error.contains("`containsAll`"), "{error}");
+
+        let table = FilterTable::new(
+            Variable::Principal,
+            EntityTypeName::from_str("Docs::User").unwrap(),
+        );
+        let error = table.postgres(&residuals(POLICIES)).unwrap_err();
+        assert!(error.to_string().contains("unknown resource"), "{error}");
+    }
+}
@@ -0,0 +1,145 @@
+// Generated by cedar_test::codegen from a Cedar schema. Do not edit.
+
+pub mod my_app {
+    /// `MyApp::Project`
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    pub struct Project(::cedar_policy::EntityUid);
+
+    impl Project {
+        pub fn new(id: &str) -> Self {
+            Self(::cedar_test::codegen::uid("MyApp::Project", id))
+        }
+    }
+
+    impl ::cedar_test::codegen::TypedEntity for Project {
+        fn uid(&self) -> &::cedar_policy::EntityUid {
+            &self.0
+        }
+    }
+
+    /// `MyApp::Role`
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    pub struct Role(::cedar_policy::EntityUid);
+
+    impl Role {
+        pub fn new(id: &str) -> Self {
+            Self(::cedar_test::codegen::uid("MyApp::Role", id))
+        }
+    }
+
+    impl ::cedar_test::codegen::TypedEntity for Role {
+        fn uid(&self) -> &::cedar_policy::EntityUid {
+            &self.0
+        }
+    }
+
+    /// `MyApp::Server`
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    pub struct Server(::cedar_policy::EntityUid);
+
+    impl Server {
+        pub fn new(id: &str) -> Self {
+            Self(::cedar_test::codegen::uid("MyApp::Server", id))
+        }
+    }
+
+    impl ::cedar_test::codegen::TypedEntity for Server {
+        fn uid(&self) -> &::cedar_policy::EntityUid {
+            &self.0
+        }
+    }
+
+    /// `MyApp::User`
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    pub struct User(::cedar_policy::EntityUid);
+
+    impl User {
+        pub fn new(id: &str) -> Self {
+            Self(::cedar_test::codegen::uid("MyApp::User", id))
+        }
+    }
+
+    impl ::cedar_test::codegen::TypedEntity for User {
+        fn uid(&self) -> &::cedar_policy::EntityUid {
+            &self.0
+        }
+    }
+
+    pub mod actions {
+        /// `MyApp::Action::"CreateProject"`
+        #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+        pub struct CreateProject;
+
+        impl CreateProject {
+            pub fn request() -> ::cedar_test::codegen::TypedRequest<Self> {
+                ::cedar_test::codegen::TypedRequest::new()
+            }
+        }
+
+        impl ::cedar_test::codegen::TypedAction for CreateProject {
+            const TYPE: &'static str = "MyApp::Action";
+            const ID: &'static str = "CreateProject";
+        }
+
+        impl ::cedar_test::codegen::PrincipalOf<CreateProject> for super::super::my_app::Role {}
+        impl ::cedar_test::codegen::PrincipalOf<CreateProject> for super::super::my_app::User {}
+        impl ::cedar_test::codegen::ResourceOf<CreateProject> for super::super::my_app::Server {}
+
+        /// `MyApp::Action::"DeleteProject"`
+        #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+        pub struct DeleteProject;
+
+        impl DeleteProject {
+            pub fn request() -> ::cedar_test::codegen::TypedRequest<Self> {
+                ::cedar_test::codegen::TypedRequest::new()
+            }
+        }
+
+        impl ::cedar_test::codegen::TypedAction for DeleteProject {
+            const TYPE: &'static str = "MyApp::Action";
+            const ID: &'static str = "DeleteProject";
+        }
+
+        impl ::cedar_test::codegen::PrincipalOf<DeleteProject> for super::super::my_app::Role {}
+        impl ::cedar_test::codegen::PrincipalOf<DeleteProject> for super::super::my_app::User {}
+        impl ::cedar_test::codegen::ResourceOf<DeleteProject> for super::super::my_app::Project {}
+
+        /// `MyApp::Action::"GetProjectMetadata"`
+        #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+        pub struct GetProjectMetadata;
+
+        impl GetProjectMetadata {
+            pub fn request() -> ::cedar_test::codegen::TypedRequest<Self> {
+                ::cedar_test::codegen::TypedRequest::new()
+            }
+        }
+
+        impl ::cedar_test::codegen::TypedAction for GetProjectMetadata {
+            const TYPE: &'static str = "MyApp::Action";
+            const ID: &'static str = "GetProjectMetadata";
+        }
+
+        impl ::cedar_test::codegen::PrincipalOf<GetProjectMetadata> for super::super::my_app::Role {}
+        impl ::cedar_test::codegen::PrincipalOf<GetProjectMetadata> for super::super::my_app::User {}
+        impl ::cedar_test::codegen::ResourceOf<GetProjectMetadata> for super::super::my_app::Project {}
+
+        /// `MyApp::Action::"GetServerMetadata"`
+        #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+        pub struct GetServerMetadata;
+
+        impl GetServerMetadata {
+            pub fn request() -> ::cedar_test::codegen::TypedRequest<Self> {
+                ::cedar_test::codegen::TypedRequest::new()
+            }
+        }
+
+        impl ::cedar_test::codegen::TypedAction for GetServerMetadata {
+            const TYPE: &'static str = "MyApp::Action";
+            const ID: &'static str = "GetServerMetadata";
+        }
+
+        impl ::cedar_test::codegen::PrincipalOf<GetServerMetadata> for super::super::my_app::Role {}
+        impl ::cedar_test::codegen::PrincipalOf<GetServerMetadata> for super::super::my_app::User {}
+        impl ::cedar_test::codegen::ResourceOf<GetServerMetadata> for super::super::my_app::Server {}
+    }
+}
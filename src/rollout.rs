@@ -0,0 +1,138 @@
+//! Percentage-based canary rollout of a candidate policy set.
+//!
+//! While a rollout is running, requests from a fixed share of principals are decided by the
+//! candidate policies instead of the installed ones. Principals are assigned by hashing their
+//! UID, so a principal sees consistent decisions. Canary requests are evaluated against both
+//! policy sets; once the share of diverging or erroring canary decisions exceeds its
+//! threshold, the rollout falls back and all requests are decided by the installed policies
+//! again.
+
+use std::sync::{
+    OnceLock,
+    atomic::{AtomicBool, AtomicU64, Ordering},
+};
+
+use cedar_policy::{Authorizer, Entities, PolicySet, Request, Response};
+use sha2::{Digest, Sha256};
+
+use crate::fingerprint::policies_fingerprint;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RolloutConfig {
+    /// Share of principals decided by the candidate, from 0 to 100.
+    pub percentage: u8,
+    /// Fall back once more than this share of canary decisions differs from the installed
+    /// policies' decisions.
+    pub max_divergence_rate: f64,
+    /// Fall back once more than this share of canary requests has evaluation errors.
+    pub max_error_rate: f64,
+    /// Canary requests before the rates are checked.
+    pub min_requests: u64,
+}
+
+impl Default for RolloutConfig {
+    fn default() -> Self {
+        Self {
+            percentage: 5,
+            max_divergence_rate: 0.05,
+            max_error_rate: 0.01,
+            min_requests: 100,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RolloutStatus {
+    pub config: RolloutConfig,
+    pub canary_requests: u64,
+    pub diverged: u64,
+    pub errored: u64,
+    /// A threshold was exceeded and the candidate no longer decides any request.
+    pub fallen_back: bool,
+}
+
+#[derive(Debug)]
+pub(crate) struct Rollout {
+    pub(crate) policies: PolicySet,
+    config: RolloutConfig,
+    authorizer: Authorizer,
+    canary_requests: AtomicU64,
+    diverged: AtomicU64,
+    errored: AtomicU64,
+    fallen_back: AtomicBool,
+    fingerprint: OnceLock<String>,
+}
+
+impl Rollout {
+    pub(crate) fn new(policies: PolicySet, config: RolloutConfig) -> Self {
+        Self {
+            policies,
+            config,
+            authorizer: Authorizer::new(),
+            canary_requests: AtomicU64::new(0),
+            diverged: AtomicU64::new(0),
+            errored: AtomicU64::new(0),
+            fallen_back: AtomicBool::new(false),
+            fingerprint: OnceLock::new(),
+        }
+    }
+
+    /// Computed on first use, for audit records of canary requests.
+    pub(crate) fn policies_fingerprint(&self) -> &str {
+        self.fingerprint
+            .get_or_init(|| policies_fingerprint(&self.policies))
+    }
+
+    /// The candidate's response if `request` is a canary request, recording how it compares to
+    /// `installed`.
+    pub(crate) fn evaluate(
+        &self,
+        request: &Request,
+        entities: &Entities,
+        installed: &Response,
+    ) -> Option<Response> {
+        if self.fallen_back.load(Ordering::Relaxed) || !self.is_canary(request) {
+            return None;
+        }
+        let response = self
+            .authorizer
+            .is_authorized(request, &self.policies, entities);
+        let requests = self.canary_requests.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut diverged = self.diverged.load(Ordering::Relaxed);
+        if response.decision() != installed.decision() {
+            diverged = self.diverged.fetch_add(1, Ordering::Relaxed) + 1;
+        }
+        let mut errored = self.errored.load(Ordering::Relaxed);
+        if response.diagnostics().errors().next().is_some() {
+            errored = self.errored.fetch_add(1, Ordering::Relaxed) + 1;
+        }
+        if requests >= self.config.min_requests {
+            let rate = |count: u64| count as f64 / requests as f64;
+            if rate(diverged) > self.config.max_divergence_rate
+                || rate(errored) > self.config.max_error_rate
+            {
+                self.fallen_back.store(true, Ordering::Relaxed);
+            }
+        }
+        Some(response)
+    }
+
+    fn is_canary(&self, request: &Request) -> bool {
+        let Some(principal) = request.principal() else {
+            return false;
+        };
+        let digest = Sha256::digest(principal.to_string().as_bytes());
+        let bucket = u64::from_be_bytes(digest[..8].try_into().expect("the digest has 32 bytes"));
+        bucket % 100 < u64::from(self.config.percentage)
+    }
+
+    pub(crate) fn status(&self) -> RolloutStatus {
+        RolloutStatus {
+            config: self.config,
+            canary_requests: self.canary_requests.load(Ordering::Relaxed),
+            diverged: self.diverged.load(Ordering::Relaxed),
+            errored: self.errored.load(Ordering::Relaxed),
+            fallen_back: self.fallen_back.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -0,0 +1,570 @@
+//! cedar-agent compatible routes: policy store CRUD, data store, schema and `is_authorized`,
+//! plus a batch variant of `is_authorized`.
+
+use std::{borrow::Cow, sync::Arc};
+
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::Response,
+    routing::{get, post},
+};
+use cedar_policy::{Entities, Policy, PolicyId, PolicySet, Request, Schema, SchemaFragment};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "openapi")]
+use super::ErrorBody;
+use super::{ApiDecision, ApiError, etag::conditional, parse_context, parse_uid};
+use crate::{Engine, engine::is_action, fingerprint::policy_fingerprint};
+
+type ApiResult<T> = Result<T, ApiError>;
+
+#[cfg(feature = "openapi")]
+#[derive(utoipa::OpenApi)]
+#[openapi(paths(
+    list_policies,
+    get_policy,
+    create_policy,
+    replace_policies,
+    update_policy,
+    delete_policy,
+    get_data,
+    put_data,
+    delete_data,
+    get_schema,
+    put_schema,
+    delete_schema,
+    is_authorized,
+    is_authorized_batch,
+))]
+pub(super) struct Api;
+
+/// Policy routes that change the engine's policies directly.
+pub(super) fn policy_routes() -> Router<Arc<Engine>> {
+    Router::new()
+        .route(
+            "/v1/policies",
+            get(list_policies).post(create_policy).put(replace_policies),
+        )
+        .route(
+            "/v1/policies/{id}",
+            get(get_policy).put(update_policy).delete(delete_policy),
+        )
+}
+
+/// Schema routes that change the engine's schema directly.
+pub(super) fn schema_routes() -> Router<Arc<Engine>> {
+    Router::new().route(
+        "/v1/schema",
+        get(get_schema).put(put_schema).delete(delete_schema),
+    )
+}
+
+/// Like [`schema_routes`], but the schema can only be read.
+pub(super) fn read_only_schema_routes() -> Router<Arc<Engine>> {
+    Router::new().route("/v1/schema", get(get_schema))
+}
+
+pub(super) fn routes() -> Router<Arc<Engine>> {
+    Router::new()
+        .route("/v1/data", get(get_data).put(put_data).delete(delete_data))
+        .route("/v1/is_authorized", post(is_authorized))
+        .route("/v1/is_authorized/batch", post(is_authorized_batch))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub(crate) struct AgentPolicy {
+    pub(crate) id: String,
+    pub(crate) content: String,
+}
+
+impl AgentPolicy {
+    pub(super) fn from_policy(policy: &Policy) -> Self {
+        Self {
+            id: policy.id().to_string(),
+            content: policy.to_cedar().unwrap_or_else(|| policy.to_string()),
+        }
+    }
+
+    pub(super) fn parse(&self) -> ApiResult<Policy> {
+        Ok(Policy::parse(Some(PolicyId::new(&self.id)), &self.content)
+            .map_err(crate::Error::from)?)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub(super) struct PolicyUpdate {
+    pub(super) content: String,
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/v1/policies",
+        responses((status = 200, body = Vec<AgentPolicy>))
+    )
+)]
+async fn list_policies(State(engine): State<Arc<Engine>>, headers: HeaderMap) -> Response {
+    let state = engine.state();
+    let mut policies = state
+        .policies()
+        .policies()
+        .map(AgentPolicy::from_policy)
+        .collect::<Vec<_>>();
+    policies.sort_by(|a, b| a.id.cmp(&b.id));
+    conditional(&headers, &state.fingerprint().policies, policies)
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/v1/policies/{id}",
+        params(("id" = String, Path)),
+        responses((status = 200, body = AgentPolicy), ErrorBody)
+    )
+)]
+async fn get_policy(
+    State(engine): State<Arc<Engine>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> ApiResult<Response> {
+    let state = engine.state();
+    let policy = state
+        .policies()
+        .policy(&PolicyId::new(&id))
+        .ok_or_else(|| ApiError::not_found(format!("Policy `{id}` not found")))?;
+    let body = AgentPolicy::from_policy(policy);
+    Ok(conditional(&headers, &policy_fingerprint(policy), body))
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        post,
+        path = "/v1/policies",
+        request_body = AgentPolicy,
+        responses((status = 200, body = AgentPolicy), ErrorBody)
+    )
+)]
+async fn create_policy(
+    State(engine): State<Arc<Engine>>,
+    Json(policy): Json<AgentPolicy>,
+) -> ApiResult<Json<AgentPolicy>> {
+    let parsed = policy.parse()?;
+    engine.update_policies(|policies| {
+        let mut policies = policies.clone();
+        policies.add(parsed)?;
+        Ok(policies)
+    })?;
+    Ok(Json(policy))
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        put,
+        path = "/v1/policies",
+        request_body = Vec<AgentPolicy>,
+        responses((status = 200, body = Vec<AgentPolicy>), ErrorBody)
+    )
+)]
+async fn replace_policies(
+    State(engine): State<Arc<Engine>>,
+    Json(policies): Json<Vec<AgentPolicy>>,
+) -> ApiResult<Json<Vec<AgentPolicy>>> {
+    let parsed = policies
+        .iter()
+        .map(AgentPolicy::parse)
+        .collect::<ApiResult<Vec<_>>>()?;
+    let set = PolicySet::from_policies(parsed).map_err(crate::Error::from)?;
+    engine.update_policies(|_| Ok(set))?;
+    Ok(Json(policies))
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        put,
+        path = "/v1/policies/{id}",
+        params(("id" = String, Path)),
+        request_body = PolicyUpdate,
+        responses((status = 200, body = AgentPolicy), ErrorBody)
+    )
+)]
+async fn update_policy(
+    State(engine): State<Arc<Engine>>,
+    Path(id): Path<String>,
+    Json(update): Json<PolicyUpdate>,
+) -> ApiResult<Json<AgentPolicy>> {
+    let policy = AgentPolicy {
+        id,
+        content: update.content,
+    };
+    let parsed = policy.parse()?;
+    engine.update_policies(|policies| {
+        let mut policies = policies.clone();
+        policies.remove_static(parsed.id().clone())?;
+        policies.add(parsed)?;
+        Ok(policies)
+    })?;
+    Ok(Json(policy))
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        delete,
+        path = "/v1/policies/{id}",
+        params(("id" = String, Path)),
+        responses((status = 200, body = AgentPolicy), ErrorBody)
+    )
+)]
+async fn delete_policy(
+    State(engine): State<Arc<Engine>>,
+    Path(id): Path<String>,
+) -> ApiResult<Json<AgentPolicy>> {
+    let mut removed = None;
+    engine.update_policies(|policies| {
+        let mut policies = policies.clone();
+        removed = Some(policies.remove_static(PolicyId::new(&id))?);
+        Ok(policies)
+    })?;
+    let removed = removed.expect("Removed policy is set on success");
+    Ok(Json(AgentPolicy::from_policy(&removed)))
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/v1/data",
+        responses((status = 200, body = serde_json::Value), ErrorBody)
+    )
+)]
+async fn get_data(State(engine): State<Arc<Engine>>, headers: HeaderMap) -> ApiResult<Response> {
+    let state = engine.state();
+    let body = entities_json(state.schema(), state.entities())?;
+    Ok(conditional(&headers, &state.fingerprint().entities, body))
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        put,
+        path = "/v1/data",
+        request_body = serde_json::Value,
+        responses((status = 200, body = serde_json::Value), ErrorBody)
+    )
+)]
+async fn put_data(
+    State(engine): State<Arc<Engine>>,
+    Json(data): Json<serde_json::Value>,
+) -> ApiResult<Json<serde_json::Value>> {
+    let entities = Entities::from_json_value(data, Some(engine.state().schema()))
+        .map_err(crate::Error::from)?;
+    let body = entities_json(engine.state().schema(), &entities)?;
+    engine.replace_entities(entities)?;
+    Ok(Json(body))
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        delete,
+        path = "/v1/data",
+        responses((status = 204, description = "The entities were deleted"), ErrorBody)
+    )
+)]
+async fn delete_data(State(engine): State<Arc<Engine>>) -> ApiResult<StatusCode> {
+    engine.replace_entities(Entities::empty())?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/v1/schema",
+        responses((status = 200, body = serde_json::Value), ErrorBody)
+    )
+)]
+async fn get_schema(State(engine): State<Arc<Engine>>, headers: HeaderMap) -> ApiResult<Response> {
+    let state = engine.state();
+    let schema = state
+        .schema_fragment()
+        .clone()
+        .to_json_value()
+        .map_err(crate::Error::from)?;
+    Ok(conditional(&headers, &state.fingerprint().schema, schema))
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        put,
+        path = "/v1/schema",
+        request_body = serde_json::Value,
+        responses((status = 200, body = serde_json::Value), ErrorBody)
+    )
+)]
+async fn put_schema(
+    State(engine): State<Arc<Engine>>,
+    Json(schema): Json<serde_json::Value>,
+) -> ApiResult<Json<serde_json::Value>> {
+    let fragment = SchemaFragment::from_json_value(schema.clone()).map_err(crate::Error::from)?;
+    engine.replace_schema(fragment)?;
+    Ok(Json(schema))
+}
+
+// The engine always validates against a schema, so deleting it installs an empty schema. This
+// only succeeds when no policies or entities depend on the previous one.
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        delete,
+        path = "/v1/schema",
+        responses((status = 204, description = "The schema was deleted"), ErrorBody)
+    )
+)]
+async fn delete_schema(State(engine): State<Arc<Engine>>) -> ApiResult<StatusCode> {
+    let empty =
+        SchemaFragment::from_json_value(serde_json::json!({})).map_err(crate::Error::from)?;
+    engine.replace_schema(empty)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+struct AuthorizationCall {
+    principal: Option<String>,
+    action: Option<String>,
+    resource: Option<String>,
+    context: Option<serde_json::Value>,
+    /// Replaces the stored entities for this call.
+    entities: Option<serde_json::Value>,
+    /// Added to the stored (or provided) entities for this call.
+    additional_entities: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+struct AuthorizationAnswer {
+    decision: ApiDecision,
+    diagnostics: AuthorizationDiagnostics,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+struct AuthorizationDiagnostics {
+    reason: Vec<String>,
+    errors: Vec<String>,
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        post,
+        path = "/v1/is_authorized",
+        request_body = AuthorizationCall,
+        responses((status = 200, body = AuthorizationAnswer), ErrorBody)
+    )
+)]
+async fn is_authorized(
+    State(engine): State<Arc<Engine>>,
+    Json(call): Json<AuthorizationCall>,
+) -> ApiResult<Json<AuthorizationAnswer>> {
+    authorize(&engine, call).map(Json)
+}
+
+/// Answers each call in order. Fails if any call is invalid.
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        post,
+        path = "/v1/is_authorized/batch",
+        request_body = Vec<AuthorizationCall>,
+        responses((status = 200, body = Vec<AuthorizationAnswer>), ErrorBody)
+    )
+)]
+async fn is_authorized_batch(
+    State(engine): State<Arc<Engine>>,
+    Json(calls): Json<Vec<AuthorizationCall>>,
+) -> ApiResult<Json<Vec<AuthorizationAnswer>>> {
+    calls
+        .into_iter()
+        .map(|call| authorize(&engine, call))
+        .collect::<ApiResult<_>>()
+        .map(Json)
+}
+
+fn authorize(engine: &Engine, call: AuthorizationCall) -> ApiResult<AuthorizationAnswer> {
+    let state = engine.state();
+    let schema = state.schema();
+    let required = |field: Option<String>, name: &str| {
+        field.ok_or_else(|| ApiError::bad_request(format!("Missing `{name}`")))
+    };
+    let principal = parse_uid(&required(call.principal, "principal")?)?;
+    let action = parse_uid(&required(call.action, "action")?)?;
+    let resource = parse_uid(&required(call.resource, "resource")?)?;
+    let context = parse_context(call.context, schema, &action)?;
+    let request = Request::new(principal, action, resource, context, Some(schema))
+        .map_err(crate::Error::from)?;
+
+    let mut entities = match call.entities {
+        Some(entities) => Cow::Owned(
+            Entities::from_json_value(entities, Some(schema)).map_err(crate::Error::from)?,
+        ),
+        None => Cow::Borrowed(state.entities()),
+    };
+    if let Some(additional) = call.additional_entities {
+        entities = Cow::Owned(
+            entities
+                .into_owned()
+                .add_entities_from_json_value(additional, Some(schema))
+                .map_err(crate::Error::from)?,
+        );
+    }
+
+    let response = engine.is_authorized_with_entities(&request, &entities);
+    let mut reason = response
+        .diagnostics()
+        .reason()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    reason.sort();
+    Ok(AuthorizationAnswer {
+        decision: response.decision().into(),
+        diagnostics: AuthorizationDiagnostics {
+            reason,
+            errors: response
+                .diagnostics()
+                .errors()
+                .map(ToString::to_string)
+                .collect(),
+        },
+    })
+}
+
+fn entities_json(schema: &Schema, entities: &Entities) -> ApiResult<serde_json::Value> {
+    let entities = entities
+        .iter()
+        .filter(|e| !is_action(schema, e))
+        .map(|e| e.to_json_value().map_err(crate::Error::from))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(serde_json::Value::Array(entities))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Method;
+    use serde_json::json;
+
+    use super::super::test_util::{app, call};
+    use super::*;
+
+    const ENTITIES: &str = r#"[
+        { "uid": { "type": "MyApp::Server", "id": "0" }, "attrs": {}, "parents": [] },
+        {
+            "uid": { "type": "MyApp::Project", "id": "0" },
+            "attrs": {},
+            "parents": [{ "type": "MyApp::Server", "id": "0" }]
+        }
+    ]"#;
+
+    fn authorization_call(principal: &str) -> serde_json::Value {
+        json!({
+            "principal": principal,
+            "action": "MyApp::Action::\"GetProjectMetadata\"",
+            "resource": "MyApp::Project::\"0\"",
+        })
+    }
+
+    #[tokio::test]
+    async fn test_policy_crud() {
+        let app = app("", ENTITIES);
+        let policy = json!({
+            "id": "user-0",
+            "content": "permit (principal == MyApp::User::\"0\", action, resource in MyApp::Server::\"0\");",
+        });
+
+        let (status, _) = call(&app, Method::POST, "/v1/policies", Some(policy.clone())).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = call(&app, Method::POST, "/v1/policies", Some(policy.clone())).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        let (status, body) = call(&app, Method::GET, "/v1/policies/user-0", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, policy);
+
+        let invalid =
+            json!({ "content": "permit (principal == MyApp::Group::\"0\", action, resource);" });
+        let (status, body) = call(&app, Method::PUT, "/v1/policies/user-0", Some(invalid)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["reason"].as_str().unwrap().contains("validation"));
+
+        let (status, _) = call(&app, Method::DELETE, "/v1/policies/user-0", None).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = call(&app, Method::GET, "/v1/policies/user-0", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_is_authorized() {
+        let app = app(
+            r#"permit (principal == MyApp::User::"0", action, resource in MyApp::Server::"0");"#,
+            ENTITIES,
+        );
+
+        let (status, body) = call(
+            &app,
+            Method::POST,
+            "/v1/is_authorized",
+            Some(authorization_call("MyApp::User::\"0\"")),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body,
+            json!({ "decision": "Allow", "diagnostics": { "reason": ["policy0"], "errors": [] } })
+        );
+
+        let (_, body) = call(&app, Method::DELETE, "/v1/data", None).await;
+        assert_eq!(body, serde_json::Value::Null);
+        let (_, body) = call(
+            &app,
+            Method::POST,
+            "/v1/is_authorized",
+            Some(authorization_call("MyApp::User::\"0\"")),
+        )
+        .await;
+        assert_eq!(body["decision"], "Deny");
+
+        let (status, body) = call(
+            &app,
+            Method::POST,
+            "/v1/is_authorized/batch",
+            Some(json!([
+                authorization_call("MyApp::User::\"0\""),
+                authorization_call("MyApp::User::\"1\""),
+            ])),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body.as_array().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_schema_roundtrip() {
+        let app = app("", "[]");
+        let (status, schema) = call(&app, Method::GET, "/v1/schema", None).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = call(&app, Method::PUT, "/v1/schema", Some(schema)).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = call(&app, Method::DELETE, "/v1/schema", None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+    }
+}
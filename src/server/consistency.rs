@@ -0,0 +1,93 @@
+//! Read-your-writes across requests: every response carries the engine's revision in
+//! `cedar-revision`, and a request with `cedar-min-revision` is only handled once the engine
+//! installed that revision. Writes to a store are picked up by the engine asynchronously, so a
+//! client that sends the revision of its write gets decisions that see it. Revisions come from
+//! the store's [sequence](crate::store::PolicyStore::sequence) where it has one, so they hold
+//! across replicas and restarts; entities replaced through one replica only advance its own.
+
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use super::ApiError;
+use crate::Engine;
+
+pub const REVISION: HeaderName = HeaderName::from_static("cedar-revision");
+pub const MIN_REVISION: HeaderName = HeaderName::from_static("cedar-min-revision");
+
+/// How long a request waits for the revision it requires.
+const WAIT: Duration = Duration::from_secs(5);
+
+/// How far a required revision may be ahead of the engine's. A revision further ahead was not
+/// handed out by any engine loading from the same store, so it is rejected without waiting.
+const MAX_AHEAD: u64 = 1024;
+
+pub(super) async fn read_your_writes(
+    State(engine): State<Arc<Engine>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if let Some(value) = request.headers().get(MIN_REVISION) {
+        let Some(revision) = value.to_str().ok().and_then(|v| v.parse().ok()) else {
+            return ApiError::bad_request(format!("Invalid `{MIN_REVISION}` header"))
+                .into_response();
+        };
+        let current = engine.revision();
+        if revision > current.saturating_add(MAX_AHEAD) {
+            return ApiError::bad_request(format!(
+                "`{MIN_REVISION}` {revision} is far ahead of the current revision {current}"
+            ))
+            .into_response();
+        }
+        if let Err(e) = engine.wait_for_revision(revision, WAIT).await {
+            return ApiError::from(e).into_response();
+        }
+    }
+    let mut response = next.run(request).await;
+    response
+        .headers_mut()
+        .insert(REVISION, HeaderValue::from(engine.revision()));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{Method, StatusCode},
+    };
+    use tower::ServiceExt;
+
+    use super::super::test_util::app;
+    use super::*;
+
+    #[tokio::test]
+    async fn test_revision_headers() {
+        let app = app("permit (principal, action, resource);", "[]");
+        let send = |method: Method, min_revision: Option<&str>| {
+            let mut request = Request::builder()
+                .method(method)
+                .uri("/v1/data")
+                .header("content-type", "application/json");
+            if let Some(revision) = min_revision {
+                request = request.header(MIN_REVISION, revision);
+            }
+            let body = Body::from("[]");
+            app.clone().oneshot(request.body(body).unwrap())
+        };
+        let response = send(Method::PUT, None).await.unwrap();
+        assert_eq!(response.headers()[REVISION], "1");
+        let response = send(Method::GET, Some("1")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = send(Method::GET, Some("latest")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        // Not worth waiting for.
+        let response = send(Method::GET, Some("1000000")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,84 @@
+//! Strong ETags for the artifact routes, so clients polling for updates get `304 Not Modified`
+//! instead of the unchanged schema, policies or entities.
+
+use axum::{
+    Json,
+    http::{
+        HeaderMap, HeaderValue, StatusCode,
+        header::{ETAG, IF_NONE_MATCH},
+    },
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+
+/// `body` tagged with the quoted `fingerprint`, or `304 Not Modified` if the request's
+/// `If-None-Match` lists it.
+pub(super) fn conditional(
+    headers: &HeaderMap,
+    fingerprint: &str,
+    body: impl Serialize,
+) -> Response {
+    let etag = format!("\"{fingerprint}\"");
+    let tag = HeaderValue::from_str(&etag).expect("fingerprints are hex");
+    if matches(headers, &etag) {
+        return (StatusCode::NOT_MODIFIED, [(ETAG, tag)]).into_response();
+    }
+    ([(ETAG, tag)], Json(body)).into_response()
+}
+
+// `If-None-Match` uses the weak comparison, so a `W/` prefix is ignored.
+fn matches(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{Method, Request},
+    };
+    use tower::ServiceExt;
+
+    use super::super::test_util::{app, call};
+    use super::*;
+
+    async fn get(app: &axum::Router, uri: &str, if_none_match: Option<&str>) -> Response {
+        let mut request = Request::get(uri);
+        if let Some(tag) = if_none_match {
+            request = request.header(IF_NONE_MATCH, tag);
+        }
+        let request = request.body(Body::empty()).unwrap();
+        app.clone().oneshot(request).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_conditional_requests() {
+        let app = app("permit (principal, action, resource);", "[]");
+        for uri in ["/v1/policies", "/v1/policies/policy0", "/v1/schema"] {
+            let response = get(&app, uri, None).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let etag = response.headers()[ETAG].to_str().unwrap().to_string();
+            assert!(etag.starts_with('"'));
+
+            let response = get(&app, uri, Some(&format!("\"other\", W/{etag}"))).await;
+            assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+            assert_eq!(response.headers()[ETAG], etag.as_str());
+            let response = get(&app, uri, Some("\"other\"")).await;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        let response = get(&app, "/v1/policies", None).await;
+        let etag = response.headers()[ETAG].to_str().unwrap().to_string();
+        let update = serde_json::json!({ "content": "forbid (principal, action, resource);" });
+        let (status, _) = call(&app, Method::PUT, "/v1/policies/policy0", Some(update)).await;
+        assert_eq!(status, StatusCode::OK);
+        let response = get(&app, "/v1/policies", Some(&etag)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
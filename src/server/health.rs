@@ -0,0 +1,147 @@
+//! Probes for orchestrators: `/healthz` answers as long as the process serves requests,
+//! `/readyz` as long as the checks of an installed [`ReadinessChecks`] extension pass. An
+//! installed engine state always has a parsed schema and validated policies, so without the
+//! extension the engine is always ready.
+//!
+//! Install the extension on the router, e.g.
+//! `router(engine).layer(Extension(ReadinessChecks::default().with_warm_up()))`.
+
+use std::{collections::BTreeMap, fmt, future::Future, pin::Pin, sync::Arc};
+
+use axum::{Extension, Json, Router, extract::State, http::StatusCode, routing::get};
+use serde::Serialize;
+
+use crate::{Engine, Result};
+
+type Ping = Arc<dyn Fn() -> Pin<Box<dyn Future<Output = Result<()>> + Send>> + Send + Sync>;
+
+/// What `/readyz` requires besides an installed engine state.
+#[derive(Clone, Default)]
+pub struct ReadinessChecks {
+    warm_up: bool,
+    entity_store: Option<Ping>,
+}
+
+impl fmt::Debug for ReadinessChecks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReadinessChecks")
+            .field("warm_up", &self.warm_up)
+            .field("entity_store", &self.entity_store.is_some())
+            .finish()
+    }
+}
+
+impl ReadinessChecks {
+    /// Require [`Engine::warm_up`] to have been called, for engines warmed up after the listener
+    /// is bound.
+    #[must_use]
+    pub fn with_warm_up(mut self) -> Self {
+        self.warm_up = true;
+        self
+    }
+
+    /// Require `ping` to succeed, for entities that are loaded from a store at request time.
+    #[must_use]
+    pub fn with_entity_store<F>(mut self, ping: impl Fn() -> F + Send + Sync + 'static) -> Self
+    where
+        F: Future<Output = Result<()>> + Send + 'static,
+    {
+        self.entity_store = Some(Arc::new(move || Box::pin(ping())));
+        self
+    }
+}
+
+/// The body of `/readyz`: `{"ready": false, "checks": {"warm_up": "pending"}}`.
+#[derive(Debug, Serialize)]
+struct Readiness {
+    ready: bool,
+    checks: BTreeMap<&'static str, String>,
+}
+
+pub(super) fn routes() -> Router<Arc<Engine>> {
+    Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+}
+
+async fn healthz() -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "status": "ok" }))
+}
+
+async fn readyz(
+    State(engine): State<Arc<Engine>>,
+    checks: Option<Extension<ReadinessChecks>>,
+) -> (StatusCode, Json<Readiness>) {
+    let Extension(checks) = checks.unwrap_or_default();
+    let mut ready = true;
+    let mut results = BTreeMap::new();
+    if checks.warm_up {
+        let warmed_up = engine.is_warmed_up();
+        ready &= warmed_up;
+        let warm_up = if warmed_up { "ok" } else { "pending" };
+        results.insert("warm_up", warm_up.to_string());
+    }
+    if let Some(ping) = &checks.entity_store {
+        let result = ping().await;
+        ready &= result.is_ok();
+        results.insert(
+            "entity_store",
+            result.map_or_else(|e| e.to_string(), |()| "ok".to_string()),
+        );
+    }
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let body = Readiness {
+        ready,
+        checks: results,
+    };
+    (status, Json(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Method;
+    use serde_json::json;
+
+    use super::super::test_util::{app, call};
+    use super::*;
+    use crate::Error;
+
+    #[tokio::test]
+    async fn test_probes() {
+        let app = app("permit (principal, action, resource);", "[]");
+        let (status, body) = call(&app, Method::GET, "/healthz", None).await;
+        assert_eq!((status, body), (StatusCode::OK, json!({ "status": "ok" })));
+        let (status, body) = call(&app, Method::GET, "/readyz", None).await;
+        assert_eq!(
+            (status, body),
+            (StatusCode::OK, json!({ "ready": true, "checks": {} }))
+        );
+
+        let checks = ReadinessChecks::default()
+            .with_warm_up()
+            .with_entity_store(|| async { Err(Error::Store("connection refused".into())) });
+        let gated = app.clone().layer(Extension(checks));
+        let (status, body) = call(&gated, Method::GET, "/readyz", None).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["checks"]["warm_up"], "pending");
+        assert!(
+            body["checks"]["entity_store"]
+                .as_str()
+                .unwrap()
+                .contains("connection refused")
+        );
+
+        let checks = ReadinessChecks::default().with_entity_store(|| async { Ok(()) });
+        let (status, body) =
+            call(&app.layer(Extension(checks)), Method::GET, "/readyz", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body,
+            json!({ "ready": true, "checks": { "entity_store": "ok" } })
+        );
+    }
+}
@@ -0,0 +1,275 @@
+//! HTTP API over an [`Engine`].
+//!
+//! The policy, data, schema and `is_authorized` routes under `/v1` are compatible with
+//! [cedar-agent](https://github.com/permitio/cedar-agent), so existing cedar-agent clients can
+//! be pointed at this service. The TPE and query routes are additions on top, as are the
+//! `/healthz` and `/readyz` probes. Every response carries the engine revision in
+//! [`REVISION`], which clients can send back in [`MIN_REVISION`] to read their own writes.
+
+use std::{str::FromStr, sync::Arc};
+
+use axum::{
+    Json, Router,
+    http::StatusCode,
+    middleware::from_fn_with_state,
+    response::{IntoResponse, Response},
+};
+use cedar_policy::{Context, Decision, EntityTypeName, EntityUid, PolicySetError, Schema};
+use serde::Serialize;
+
+use crate::{Engine, Error, store::PolicyStore};
+
+mod agent;
+mod consistency;
+mod etag;
+mod health;
+mod opa;
+mod store;
+mod tpe;
+
+pub use consistency::{MIN_REVISION, REVISION};
+pub use health::ReadinessChecks;
+pub use opa::opa_routes;
+
+pub fn router(engine: Arc<Engine>) -> Router {
+    let router = Router::new()
+        .merge(agent::policy_routes())
+        .merge(agent::schema_routes())
+        .merge(agent::routes())
+        .merge(tpe::routes())
+        .merge(health::routes())
+        .with_state(engine.clone());
+    with_openapi(router).layer(from_fn_with_state(engine, consistency::read_your_writes))
+}
+
+/// Like [`router`], but the policy routes read and write `store`, and the engine's policies are
+/// reloaded from the store after every write. The store validates policies against its own
+/// schema, so the schema can only be read: replacing it in the engine alone would let the store
+/// accept policies the engine rejects.
+pub fn store_router<S: PolicyStore>(engine: Arc<Engine>, store: Arc<S>) -> Router {
+    let router = Router::new()
+        .merge(agent::read_only_schema_routes())
+        .merge(agent::routes())
+        .merge(tpe::routes())
+        .merge(health::routes())
+        .with_state(engine.clone())
+        .merge(store::routes(engine.clone(), store));
+    with_openapi(router).layer(from_fn_with_state(engine, consistency::read_your_writes))
+}
+
+/// The OpenAPI document of the routes of [`router`].
+#[cfg(feature = "openapi")]
+pub fn openapi() -> utoipa::openapi::OpenApi {
+    use utoipa::OpenApi;
+
+    #[derive(OpenApi)]
+    #[openapi(info(
+        title = "cedar-tpe",
+        description = "Cedar authorization, type-aware partial evaluation and permission queries."
+    ))]
+    struct Info;
+
+    Info::openapi()
+        .merge_from(agent::Api::openapi())
+        .merge_from(tpe::Api::openapi())
+}
+
+/// Serve the OpenAPI document at `/openapi.json` if the `openapi` feature is enabled.
+fn with_openapi(router: Router) -> Router {
+    #[cfg(feature = "openapi")]
+    let router = {
+        let document = openapi();
+        router.route(
+            "/openapi.json",
+            axum::routing::get(move || async move { Json(document) }),
+        )
+    };
+    router
+}
+
+/// Error body in the shape returned by cedar-agent: `{"reason": "..."}`, with the stable
+/// `code` of engine errors added.
+#[derive(Debug)]
+pub(crate) struct ApiError {
+    status: StatusCode,
+    reason: String,
+    code: Option<&'static str>,
+}
+
+impl ApiError {
+    pub(crate) fn new(status: StatusCode, reason: impl Into<String>) -> Self {
+        Self {
+            status,
+            reason: reason.into(),
+            code: None,
+        }
+    }
+
+    pub(crate) fn bad_request(reason: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, reason)
+    }
+
+    pub(crate) fn not_found(reason: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, reason)
+    }
+}
+
+/// The JSON body of an [`ApiError`].
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema, utoipa::IntoResponses))]
+#[cfg_attr(
+    feature = "openapi",
+    response(status = "default", description = "The request failed")
+)]
+pub(crate) struct ErrorBody {
+    reason: String,
+    /// The [`Error::code`] of the error, if it was raised by the engine.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<&'static str>,
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = ErrorBody {
+            reason: self.reason,
+            code: self.code,
+        };
+        (self.status, Json(body)).into_response()
+    }
+}
+
+impl From<Error> for ApiError {
+    fn from(e: Error) -> Self {
+        let status = match &e {
+            Error::PolicySet(e) => match e.as_ref() {
+                PolicySetError::AlreadyDefined(_) => StatusCode::CONFLICT,
+                PolicySetError::PolicyNonexistent(_) | PolicySetError::TemplateNonexistent(_) => {
+                    StatusCode::NOT_FOUND
+                }
+                _ => StatusCode::BAD_REQUEST,
+            },
+            Error::Token(_) => StatusCode::UNAUTHORIZED,
+            Error::NotFound(_) => StatusCode::NOT_FOUND,
+            Error::Conflict(_) => StatusCode::CONFLICT,
+            Error::ReadOnly => StatusCode::METHOD_NOT_ALLOWED,
+            Error::Store(_) | Error::LimitExceeded(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Error::Avp(_) | Error::Remote(_) => StatusCode::BAD_GATEWAY,
+            Error::PolicyEvaluation(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
+        };
+        Self {
+            code: Some(e.code()),
+            ..Self::new(status, e.to_string())
+        }
+    }
+}
+
+/// Decisions are spelled `Allow` / `Deny`, as in cedar-agent responses.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub(crate) enum ApiDecision {
+    Allow,
+    Deny,
+}
+
+impl From<Decision> for ApiDecision {
+    fn from(decision: Decision) -> Self {
+        match decision {
+            Decision::Allow => Self::Allow,
+            Decision::Deny => Self::Deny,
+        }
+    }
+}
+
+pub(crate) fn parse_context(
+    context: Option<serde_json::Value>,
+    schema: &Schema,
+    action: &EntityUid,
+) -> Result<Context, ApiError> {
+    match context {
+        Some(context) => {
+            Ok(Context::from_json_value(context, Some((schema, action))).map_err(Error::from)?)
+        }
+        None => Ok(Context::empty()),
+    }
+}
+
+pub(crate) fn parse_uid(uid: &str) -> Result<EntityUid, ApiError> {
+    EntityUid::from_str(uid)
+        .map_err(|e| ApiError::bad_request(format!("Invalid entity UID `{uid}`: {e}")))
+}
+
+pub(crate) fn parse_type_name(name: &str) -> Result<EntityTypeName, ApiError> {
+    EntityTypeName::from_str(name)
+        .map_err(|e| ApiError::bad_request(format!("Invalid entity type `{name}`: {e}")))
+}
+
+#[cfg(test)]
+pub(crate) mod test_util {
+    use std::str::FromStr;
+
+    use axum::{
+        body::Body,
+        http::{Method, Request},
+    };
+    use cedar_policy::{Entities, PolicySet, SchemaFragment};
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::CEDAR_SCHEMA_SRC;
+
+    pub(crate) fn app(policies: &str, entities: &str) -> Router {
+        let schema = SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap();
+        let entities = Entities::from_json_str(entities, Some(&crate::CEDAR_SCHEMA)).unwrap();
+        let engine = Engine::new(schema, PolicySet::from_str(policies).unwrap(), entities).unwrap();
+        router(Arc::new(engine))
+    }
+
+    pub(crate) async fn call(
+        app: &Router,
+        method: Method,
+        uri: &str,
+        body: Option<serde_json::Value>,
+    ) -> (StatusCode, serde_json::Value) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json");
+        let body = body.map_or_else(Body::empty, |b| Body::from(b.to_string()));
+        let response = app
+            .clone()
+            .oneshot(request.body(body).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let json = if bytes.is_empty() {
+            serde_json::Value::Null
+        } else {
+            serde_json::from_slice(&bytes).unwrap()
+        };
+        (status, json)
+    }
+}
+
+#[cfg(all(test, feature = "openapi"))]
+mod tests {
+    use axum::http::Method;
+
+    use super::test_util::{app, call};
+    use super::*;
+
+    #[tokio::test]
+    async fn test_openapi() {
+        let (status, document) = call(&app("", "[]"), Method::GET, "/openapi.json", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(document["paths"]["/v1/policies/{id}"]["delete"].is_object());
+        assert!(document["paths"]["/v1/query/who-can"]["post"].is_object());
+        assert!(document["paths"]["/v1/tpe"]["post"]["responses"]["default"].is_object());
+        assert_eq!(
+            document["components"]["schemas"]["PartialUid"]["required"],
+            serde_json::json!(["type"])
+        );
+    }
+}
@@ -0,0 +1,78 @@
+//! OPA-compatible decision route, so clients of an OPA sidecar can query this service.
+
+use std::sync::Arc;
+
+use axum::{Json, Router, extract::State, routing::post};
+use cedar_policy::Decision;
+use serde::Serialize;
+
+use super::ApiError;
+use crate::{Engine, opa::OpaMapping};
+
+/// `POST /v1/data/{path}` with an OPA `{"input": ...}` body, answered as `{"result": bool}`.
+/// The policy path is ignored; every query is evaluated against the engine's policy set.
+///
+/// Merge the returned router with [`super::router`] to serve both APIs.
+pub fn opa_routes(engine: Arc<Engine>, mapping: OpaMapping) -> Router {
+    Router::new()
+        .route("/v1/data/{*path}", post(decide))
+        .with_state((engine, Arc::new(mapping)))
+}
+
+#[derive(Debug, Serialize)]
+struct OpaResult {
+    result: bool,
+}
+
+async fn decide(
+    State((engine, mapping)): State<(Arc<Engine>, Arc<OpaMapping>)>,
+    Json(input): Json<serde_json::Value>,
+) -> Result<Json<OpaResult>, ApiError> {
+    let request = mapping.to_request(&input, engine.state().schema())?;
+    let decision = engine.is_authorized(&request).decision();
+    Ok(Json(OpaResult {
+        result: decision == Decision::Allow,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use axum::http::{Method, StatusCode};
+    use cedar_policy::{Entities, PolicySet, SchemaFragment};
+    use serde_json::json;
+
+    use super::super::test_util::call;
+    use super::*;
+    use crate::CEDAR_SCHEMA_SRC;
+
+    #[tokio::test]
+    async fn test_opa_decision() {
+        let engine = Engine::new(
+            SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(),
+            PolicySet::from_str(r#"permit (principal == MyApp::User::"0", action, resource);"#)
+                .unwrap(),
+            Entities::empty(),
+        )
+        .unwrap();
+        let mapping = serde_json::from_value(json!({
+            "principal": { "type": "MyApp::User", "id": "/user" },
+            "action": { "type": "MyApp::Action", "id": "/action" },
+            "resource": { "type": "MyApp::Project", "id": "/project" }
+        }))
+        .unwrap();
+        let app = opa_routes(Arc::new(engine), mapping);
+
+        let input = |user: &str| json!({ "input": { "user": user, "action": "GetProjectMetadata", "project": "0" } });
+        let (status, body) =
+            call(&app, Method::POST, "/v1/data/authz/allow", Some(input("0"))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, json!({ "result": true }));
+        let (_, body) = call(&app, Method::POST, "/v1/data/authz/allow", Some(input("1"))).await;
+        assert_eq!(body, json!({ "result": false }));
+
+        let (status, _) = call(&app, Method::POST, "/v1/data/authz/allow", Some(json!({}))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,266 @@
+//! The cedar-agent policy routes over a [`PolicyStore`], so policy changes are persisted.
+
+use std::sync::Arc;
+
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    http::HeaderMap,
+    response::Response,
+    routing::get,
+};
+use cedar_policy::{Policy, PolicyId, PolicySet};
+
+use super::{
+    ApiError,
+    agent::{AgentPolicy, PolicyUpdate},
+    etag::conditional,
+};
+use crate::{
+    Engine, Error,
+    engine::validate_policies,
+    fingerprint::{policies_fingerprint, policy_fingerprint},
+    store::{PolicyStore, load_policies},
+};
+
+type ApiResult<T> = Result<T, ApiError>;
+
+struct StoreState<S> {
+    engine: Arc<Engine>,
+    store: Arc<S>,
+}
+
+impl<S> Clone for StoreState<S> {
+    fn clone(&self) -> Self {
+        Self {
+            engine: self.engine.clone(),
+            store: self.store.clone(),
+        }
+    }
+}
+
+impl<S: PolicyStore> StoreState<S> {
+    async fn reload(&self) -> ApiResult<()> {
+        Ok(load_policies(&self.store, &self.engine).await?)
+    }
+
+    /// Validate `policy` against the engine's schema before it is written, so a committed
+    /// write is never rejected by the reload.
+    fn validate(&self, policy: &Policy) -> ApiResult<()> {
+        let set = PolicySet::from_policies([policy.clone()]).map_err(Error::from)?;
+        Ok(validate_policies(self.engine.state().schema(), &set)?)
+    }
+
+    async fn existing(&self, id: &str) -> ApiResult<AgentPolicy> {
+        let policy = self.store.get(&PolicyId::new(id)).await?;
+        policy
+            .as_ref()
+            .map(AgentPolicy::from_policy)
+            .ok_or_else(|| ApiError::not_found(format!("Policy `{id}` not found")))
+    }
+}
+
+pub(super) fn routes<S: PolicyStore>(engine: Arc<Engine>, store: Arc<S>) -> Router {
+    Router::new()
+        .route(
+            "/v1/policies",
+            get(list_policies::<S>)
+                .post(create_policy::<S>)
+                .put(replace_policies::<S>),
+        )
+        .route(
+            "/v1/policies/{id}",
+            get(get_policy::<S>)
+                .put(update_policy::<S>)
+                .delete(delete_policy::<S>),
+        )
+        .with_state(StoreState { engine, store })
+}
+
+async fn list_policies<S: PolicyStore>(
+    State(state): State<StoreState<S>>,
+    headers: HeaderMap,
+) -> ApiResult<Response> {
+    let policies = state.store.list().await?;
+    let body = policies
+        .iter()
+        .map(AgentPolicy::from_policy)
+        .collect::<Vec<_>>();
+    let policies = PolicySet::from_policies(policies).map_err(Error::from)?;
+    Ok(conditional(
+        &headers,
+        &policies_fingerprint(&policies),
+        body,
+    ))
+}
+
+async fn get_policy<S: PolicyStore>(
+    State(state): State<StoreState<S>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> ApiResult<Response> {
+    let policy = state.store.get(&PolicyId::new(&id)).await?;
+    let policy = policy.ok_or_else(|| ApiError::not_found(format!("Policy `{id}` not found")))?;
+    let body = AgentPolicy::from_policy(&policy);
+    Ok(conditional(&headers, &policy_fingerprint(&policy), body))
+}
+
+async fn create_policy<S: PolicyStore>(
+    State(state): State<StoreState<S>>,
+    Json(policy): Json<AgentPolicy>,
+) -> ApiResult<Json<AgentPolicy>> {
+    let parsed = policy.parse()?;
+    state.validate(&parsed)?;
+    state.store.create(parsed).await?;
+    state.reload().await?;
+    Ok(Json(policy))
+}
+
+/// The policies are validated together before any is written. Atomic if the store overrides
+/// [`PolicyStore::replace_all`].
+async fn replace_policies<S: PolicyStore>(
+    State(state): State<StoreState<S>>,
+    Json(policies): Json<Vec<AgentPolicy>>,
+) -> ApiResult<Json<Vec<AgentPolicy>>> {
+    let parsed = policies
+        .iter()
+        .map(AgentPolicy::parse)
+        .collect::<ApiResult<Vec<_>>>()?;
+    let set = PolicySet::from_policies(parsed.clone()).map_err(Error::from)?;
+    validate_policies(state.engine.state().schema(), &set)?;
+    state.store.replace_all(parsed).await?;
+    state.reload().await?;
+    Ok(Json(policies))
+}
+
+async fn update_policy<S: PolicyStore>(
+    State(state): State<StoreState<S>>,
+    Path(id): Path<String>,
+    Json(update): Json<PolicyUpdate>,
+) -> ApiResult<Json<AgentPolicy>> {
+    state.existing(&id).await?;
+    let policy = AgentPolicy {
+        id,
+        content: update.content,
+    };
+    let parsed = policy.parse()?;
+    state.validate(&parsed)?;
+    state.store.put(parsed).await?;
+    state.reload().await?;
+    Ok(Json(policy))
+}
+
+async fn delete_policy<S: PolicyStore>(
+    State(state): State<StoreState<S>>,
+    Path(id): Path<String>,
+) -> ApiResult<Json<AgentPolicy>> {
+    let removed = state.existing(&id).await?;
+    state.store.delete(&PolicyId::new(&id)).await?;
+    state.reload().await?;
+    Ok(Json(removed))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use axum::http::{Method, StatusCode};
+    use cedar_policy::{Entities, PolicySet, SchemaFragment};
+    use serde_json::json;
+
+    use super::super::{store_router, test_util::call};
+    use super::*;
+    use crate::{CEDAR_SCHEMA, CEDAR_SCHEMA_SRC, store::MemoryPolicyStore};
+
+    #[tokio::test]
+    async fn test_policy_crud() {
+        let engine = Arc::new(
+            Engine::new(
+                SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(),
+                PolicySet::new(),
+                Entities::empty(),
+            )
+            .unwrap(),
+        );
+        let store = Arc::new(MemoryPolicyStore::new(CEDAR_SCHEMA.clone()));
+        let app = store_router(engine.clone(), store.clone());
+        let policy = json!({
+            "id": "user-0",
+            "content": "permit (principal == MyApp::User::\"0\", action, resource);",
+        });
+
+        let (status, _) = call(&app, Method::POST, "/v1/policies", Some(policy.clone())).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = call(&app, Method::POST, "/v1/policies", Some(policy)).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(store.list().await.unwrap().len(), 1);
+        assert!(
+            engine
+                .state()
+                .policies()
+                .policy(&PolicyId::new("user-0"))
+                .is_some()
+        );
+
+        let invalid =
+            json!({ "content": "permit (principal == MyApp::Group::\"0\", action, resource);" });
+        let (status, _) = call(&app, Method::PUT, "/v1/policies/user-0", Some(invalid)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        // A replacement with an invalid policy changes nothing.
+        let replacement = json!([
+            { "id": "user-1", "content": "permit (principal == MyApp::User::\"1\", action, resource);" },
+            { "id": "group-0", "content": "permit (principal == MyApp::Group::\"0\", action, resource);" },
+        ]);
+        let (status, _) = call(&app, Method::PUT, "/v1/policies", Some(replacement)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let ids = store.list().await.unwrap();
+        assert_eq!(
+            ids.iter().map(|p| p.id().to_string()).collect::<Vec<_>>(),
+            ["user-0"]
+        );
+
+        let (status, _) = call(&app, Method::DELETE, "/v1/policies/user-0", None).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = call(&app, Method::GET, "/v1/policies/user-0", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(engine.state().policies().num_of_policies(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_engine_schema() {
+        let engine = Arc::new(
+            Engine::new(
+                SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(),
+                PolicySet::new(),
+                Entities::empty(),
+            )
+            .unwrap(),
+        );
+        // A store whose schema has an attribute the engine's does not.
+        let schema = CEDAR_SCHEMA_SRC.replace(
+            "entity User in [Role];",
+            r#"entity User in [Role] = { "name": String };"#,
+        );
+        let store = Arc::new(MemoryPolicyStore::new(
+            cedar_policy::Schema::from_str(&schema).unwrap(),
+        ));
+        let app = store_router(engine.clone(), store.clone());
+
+        let (status, _) = call(&app, Method::GET, "/v1/schema", None).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = call(&app, Method::PUT, "/v1/schema", Some(json!({}))).await;
+        assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+        let (status, _) = call(&app, Method::DELETE, "/v1/schema", None).await;
+        assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+
+        // The store would accept the policy, but the engine rejects it, so it is not written.
+        let policy = json!({
+            "id": "named",
+            "content": "permit (principal, action, resource) when { principal.name == \"a\" };",
+        });
+        let (status, _) = call(&app, Method::POST, "/v1/policies", Some(policy)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(store.list().await.unwrap().is_empty());
+    }
+}
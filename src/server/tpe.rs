@@ -0,0 +1,367 @@
+//! Routes beyond cedar-agent: type-aware partial evaluation, its residuals as filter input and
+//! list-filtering queries.
+
+use std::sync::Arc;
+
+use axum::{Json, Router, extract::State, routing::post};
+use cedar_policy::{Effect, EntityId};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "openapi")]
+use super::ErrorBody;
+use super::{ApiDecision, ApiError, parse_context, parse_type_name, parse_uid};
+use crate::{Engine, Residuals, analysis::PrincipalDescription};
+
+type ApiResult<T> = Result<T, ApiError>;
+
+#[cfg(feature = "openapi")]
+#[derive(utoipa::OpenApi)]
+#[openapi(paths(tpe, filter, residuals, query_resources, query_principals, who_can))]
+pub(super) struct Api;
+
+pub(super) fn routes() -> Router<Arc<Engine>> {
+    Router::new()
+        .route("/v1/tpe", post(tpe))
+        .route("/v1/filter", post(filter))
+        .route("/v1/residuals", post(residuals))
+        .route("/v1/query/resources", post(query_resources))
+        .route("/v1/query/principals", post(query_principals))
+        .route("/v1/query/who-can", post(who_can))
+}
+
+/// An entity whose ID may be unknown: `{"type": "MyApp::User", "id": null}`.
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+struct PartialUid {
+    #[serde(rename = "type")]
+    type_name: String,
+    id: Option<String>,
+}
+
+impl PartialUid {
+    fn parse(&self) -> ApiResult<crate::pdp::PartialUid> {
+        Ok(crate::pdp::PartialUid {
+            entity_type: parse_type_name(&self.type_name)?,
+            id: self.id.as_deref().map(EntityId::new),
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+struct TpeCall {
+    principal: PartialUid,
+    action: String,
+    resource: PartialUid,
+    context: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+struct TpeAnswer {
+    decision: Option<ApiDecision>,
+    residuals: Vec<ResidualPolicy>,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+struct ResidualPolicy {
+    id: String,
+    #[cfg_attr(feature = "openapi", schema(value_type = String))]
+    effect: Effect,
+    policy: String,
+}
+
+impl TpeCall {
+    fn residuals(self, engine: &Engine) -> ApiResult<Residuals> {
+        let action = parse_uid(&self.action)?;
+        // TPE only accepts a fully known context, so an absent context means an empty one.
+        let context = parse_context(self.context, engine.state().schema(), &action)?;
+        Ok(engine.tpe(
+            self.principal.parse()?,
+            action,
+            self.resource.parse()?,
+            Some(context),
+        )?)
+    }
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        post,
+        path = "/v1/tpe",
+        request_body = TpeCall,
+        responses((status = 200, body = TpeAnswer), ErrorBody)
+    )
+)]
+async fn tpe(
+    State(engine): State<Arc<Engine>>,
+    Json(call): Json<TpeCall>,
+) -> ApiResult<Json<TpeAnswer>> {
+    let residuals = call.residuals(&engine)?;
+    Ok(Json(TpeAnswer {
+        decision: residuals.decision().map(Into::into),
+        residuals: residuals
+            .nontrivial_policies()
+            .map(|p| ResidualPolicy {
+                id: p.id().to_string(),
+                effect: p.effect(),
+                policy: p.to_string(),
+            })
+            .collect(),
+    }))
+}
+
+/// The residuals in Cedar's JSON policy format, as input for compiling a filter.
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        post,
+        path = "/v1/filter",
+        request_body = TpeCall,
+        responses((status = 200, body = serde_json::Value), ErrorBody)
+    )
+)]
+async fn filter(
+    State(engine): State<Arc<Engine>>,
+    Json(call): Json<TpeCall>,
+) -> ApiResult<Json<serde_json::Value>> {
+    Ok(Json(call.residuals(&engine)?.est()))
+}
+
+/// All residuals in Cedar's JSON policy format, the `false` policies included, for clients
+/// that read them back as [`Residuals`].
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        post,
+        path = "/v1/residuals",
+        request_body = TpeCall,
+        responses((status = 200, body = serde_json::Value), ErrorBody)
+    )
+)]
+async fn residuals(
+    State(engine): State<Arc<Engine>>,
+    Json(call): Json<TpeCall>,
+) -> ApiResult<Json<Residuals>> {
+    Ok(Json(call.residuals(&engine)?))
+}
+
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+struct ResourceQuery {
+    principal: String,
+    action: String,
+    resource_type: String,
+    context: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+struct ResourceAnswer {
+    resources: Vec<String>,
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        post,
+        path = "/v1/query/resources",
+        request_body = ResourceQuery,
+        responses((status = 200, body = ResourceAnswer), ErrorBody)
+    )
+)]
+async fn query_resources(
+    State(engine): State<Arc<Engine>>,
+    Json(query): Json<ResourceQuery>,
+) -> ApiResult<Json<ResourceAnswer>> {
+    let action = parse_uid(&query.action)?;
+    let context = parse_context(query.context, engine.state().schema(), &action)?;
+    let resources = engine.query_resources(
+        parse_uid(&query.principal)?,
+        action,
+        parse_type_name(&query.resource_type)?,
+        context,
+    )?;
+    Ok(Json(ResourceAnswer {
+        resources: resources.iter().map(ToString::to_string).collect(),
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+struct PrincipalQuery {
+    principal_type: String,
+    action: String,
+    resource: String,
+    context: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+struct PrincipalAnswer {
+    principals: Vec<String>,
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        post,
+        path = "/v1/query/principals",
+        request_body = PrincipalQuery,
+        responses((status = 200, body = PrincipalAnswer), ErrorBody)
+    )
+)]
+async fn query_principals(
+    State(engine): State<Arc<Engine>>,
+    Json(query): Json<PrincipalQuery>,
+) -> ApiResult<Json<PrincipalAnswer>> {
+    let action = parse_uid(&query.action)?;
+    let context = parse_context(query.context, engine.state().schema(), &action)?;
+    let principals = engine.query_principals(
+        parse_type_name(&query.principal_type)?,
+        action,
+        parse_uid(&query.resource)?,
+        context,
+    )?;
+    Ok(Json(PrincipalAnswer {
+        principals: principals.iter().map(ToString::to_string).collect(),
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+struct WhoCanQuery {
+    action: String,
+    resource: String,
+    context: Option<serde_json::Value>,
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        post,
+        path = "/v1/query/who-can",
+        request_body = WhoCanQuery,
+        responses((status = 200, body = PrincipalDescription), ErrorBody)
+    )
+)]
+async fn who_can(
+    State(engine): State<Arc<Engine>>,
+    Json(query): Json<WhoCanQuery>,
+) -> ApiResult<Json<PrincipalDescription>> {
+    let action = parse_uid(&query.action)?;
+    let context = parse_context(query.context, engine.state().schema(), &action)?;
+    Ok(Json(engine.who_can(
+        action,
+        parse_uid(&query.resource)?,
+        Some(context),
+    )?))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+    use serde_json::json;
+
+    use super::super::test_util::{app, call};
+
+    const POLICIES: &str = r#"
+        permit (principal == MyApp::User::"0", action, resource in MyApp::Server::"0");
+        permit (principal == MyApp::User::"1", action, resource == MyApp::Project::"1");
+    "#;
+
+    const ENTITIES: &str = r#"[
+        { "uid": { "type": "MyApp::Server", "id": "0" }, "attrs": {}, "parents": [] },
+        {
+            "uid": { "type": "MyApp::Project", "id": "0" },
+            "attrs": {},
+            "parents": [{ "type": "MyApp::Server", "id": "0" }]
+        },
+        { "uid": { "type": "MyApp::Project", "id": "1" }, "attrs": {}, "parents": [] },
+        { "uid": { "type": "MyApp::User", "id": "0" }, "attrs": {}, "parents": [] },
+        { "uid": { "type": "MyApp::User", "id": "1" }, "attrs": {}, "parents": [] }
+    ]"#;
+
+    #[tokio::test]
+    async fn test_tpe() {
+        let app = app(POLICIES, ENTITIES);
+        let (status, body) = call(
+            &app,
+            Method::POST,
+            "/v1/tpe",
+            Some(json!({
+                "principal": { "type": "MyApp::User", "id": "0" },
+                "action": "MyApp::Action::\"GetProjectMetadata\"",
+                "resource": { "type": "MyApp::Project" },
+            })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["decision"], serde_json::Value::Null);
+        let residuals = body["residuals"].as_array().unwrap();
+        assert_eq!(residuals.len(), 1);
+        assert_eq!(residuals[0]["id"], "policy0");
+        assert_eq!(residuals[0]["effect"], "permit");
+
+        let (status, body) = call(
+            &app,
+            Method::POST,
+            "/v1/filter",
+            Some(json!({
+                "principal": { "type": "MyApp::User", "id": "0" },
+                "action": "MyApp::Action::\"GetProjectMetadata\"",
+                "resource": { "type": "MyApp::Project" },
+            })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["policies"]["policy0"]["effect"], "permit");
+    }
+
+    #[tokio::test]
+    async fn test_query_resources_and_principals() {
+        let app = app(POLICIES, ENTITIES);
+        let (status, body) = call(
+            &app,
+            Method::POST,
+            "/v1/query/resources",
+            Some(json!({
+                "principal": "MyApp::User::\"1\"",
+                "action": "MyApp::Action::\"GetProjectMetadata\"",
+                "resource_type": "MyApp::Project",
+            })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, json!({ "resources": ["MyApp::Project::\"1\""] }));
+
+        let (_, body) = call(
+            &app,
+            Method::POST,
+            "/v1/query/principals",
+            Some(json!({
+                "principal_type": "MyApp::User",
+                "action": "MyApp::Action::\"GetProjectMetadata\"",
+                "resource": "MyApp::Project::\"0\"",
+            })),
+        )
+        .await;
+        assert_eq!(body, json!({ "principals": ["MyApp::User::\"0\""] }));
+
+        let (status, body) = call(
+            &app,
+            Method::POST,
+            "/v1/query/who-can",
+            Some(json!({
+                "action": "MyApp::Action::\"GetProjectMetadata\"",
+                "resource": "MyApp::Project::\"1\"",
+            })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["permits"][0]["policy"], "policy1");
+        assert_eq!(body["permits"][0]["principal"], "MyApp::User::\"1\"");
+    }
+}
@@ -0,0 +1,97 @@
+//! Shadow evaluation of a candidate policy set alongside the installed one.
+//!
+//! While a shadow is installed with [`crate::Engine::set_shadow`], every request evaluated by
+//! the engine is evaluated against the shadow policies as well. Only the installed policies
+//! determine the returned response; requests on which the shadow decides differently are
+//! counted and the most recent ones are kept for review.
+
+use std::{
+    collections::VecDeque,
+    sync::{
+        Mutex, PoisonError,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use cedar_policy::{Authorizer, Decision, Entities, PolicySet, Request};
+
+use crate::namespace::id_str;
+
+/// Divergences kept per shadow; older ones are only counted.
+const RECENT_DIVERGENCES: usize = 100;
+
+/// A request on which the shadow policies decided differently.
+#[derive(Debug, Clone)]
+pub struct Divergence {
+    pub request: Request,
+    pub decision: Decision,
+    pub shadow_decision: Decision,
+    /// The shadow policies that determined the shadow decision.
+    pub shadow_reasons: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ShadowReport {
+    pub evaluated: u64,
+    pub diverged: u64,
+    /// The most recent divergences, oldest first.
+    pub recent: Vec<Divergence>,
+}
+
+#[derive(Debug)]
+pub(crate) struct Shadow {
+    policies: PolicySet,
+    authorizer: Authorizer,
+    evaluated: AtomicU64,
+    diverged: AtomicU64,
+    recent: Mutex<VecDeque<Divergence>>,
+}
+
+impl Shadow {
+    pub(crate) fn new(policies: PolicySet) -> Self {
+        Self {
+            policies,
+            authorizer: Authorizer::new(),
+            evaluated: AtomicU64::new(0),
+            diverged: AtomicU64::new(0),
+            recent: Mutex::new(VecDeque::with_capacity(RECENT_DIVERGENCES)),
+        }
+    }
+
+    /// Evaluate `request` against the shadow policies and record whether the decision differs.
+    pub(crate) fn record(&self, request: &Request, entities: &Entities, decision: Decision) {
+        self.evaluated.fetch_add(1, Ordering::Relaxed);
+        let response = self
+            .authorizer
+            .is_authorized(request, &self.policies, entities);
+        if response.decision() == decision {
+            return;
+        }
+        self.diverged.fetch_add(1, Ordering::Relaxed);
+        let mut shadow_reasons = response
+            .diagnostics()
+            .reason()
+            .map(|id| id_str(id).to_string())
+            .collect::<Vec<_>>();
+        shadow_reasons.sort();
+        let mut recent = self.recent.lock().unwrap_or_else(PoisonError::into_inner);
+        if recent.len() == RECENT_DIVERGENCES {
+            recent.pop_front();
+        }
+        recent.push_back(Divergence {
+            request: request.clone(),
+            decision,
+            shadow_decision: response.decision(),
+            shadow_reasons,
+        });
+    }
+
+    pub(crate) fn report(&self) -> ShadowReport {
+        let recent = self.recent.lock().unwrap_or_else(PoisonError::into_inner);
+        ShadowReport {
+            evaluated: self.evaluated.load(Ordering::Relaxed),
+            diverged: self.diverged.load(Ordering::Relaxed),
+            recent: recent.iter().cloned().collect(),
+        }
+    }
+}
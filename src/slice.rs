@@ -0,0 +1,286 @@
+//! The policies by the action they may apply to, and by the principal and resource types
+//! their scopes admit, so requests are evaluated against a slice of the policies instead of all
+//! of them.
+//!
+//! A policy may apply to an action if its action constraint is unconstrained, names the action
+//! or names a group the action is in. Its principal or resource constraint admits a type if it
+//! is unconstrained, or every entity it names or tests with `is` has the type, and every group
+//! it tests with `in` has the type or is of a type the schema allows as its ancestor. All other
+//! policies evaluate to `false` for the request, so leaving them out changes neither decisions
+//! nor reasons.
+
+use std::collections::{HashMap, HashSet};
+
+use cedar_policy::{
+    ActionConstraint, Entities, EntityTypeName, EntityUid, Policy, PolicyId, PolicySet,
+    PrincipalConstraint, ResourceConstraint, Schema,
+};
+
+#[derive(Debug, Default)]
+pub(crate) struct ActionIndex {
+    slices: HashMap<EntityUid, PolicySet>,
+    buckets: HashMap<(EntityTypeName, EntityUid, EntityTypeName), PolicySet>,
+}
+
+impl ActionIndex {
+    /// The index of the static `policies` over the actions of `schema`, and the principal and
+    /// resource types each action applies to.
+    pub(crate) fn new(schema: &Schema, policies: &PolicySet) -> Self {
+        // The action entities of a valid schema always build.
+        let groups = schema
+            .action_entities()
+            .unwrap_or_else(|_| Entities::empty());
+        let mut index = Self::default();
+        for action in schema.actions() {
+            let applicable = policies
+                .policies()
+                .filter(|policy| may_apply(policy, action, &groups))
+                .collect::<Vec<_>>();
+            let principals = schema.principals_for_action(action).into_iter().flatten();
+            for principal in principals {
+                let resources = schema.resources_for_action(action).into_iter().flatten();
+                for resource in resources {
+                    let admitted = applicable.iter().filter(|policy| {
+                        let (is, within) = principal_scope(policy);
+                        let principal_admitted = admits(schema, principal, is, within);
+                        let (is, within) = resource_scope(policy);
+                        principal_admitted && admits(schema, resource, is, within)
+                    });
+                    let key = (principal.clone(), action.clone(), resource.clone());
+                    index.buckets.insert(key, subset(admitted.copied()));
+                }
+            }
+            index
+                .slices
+                .insert(action.clone(), subset(applicable.into_iter()));
+        }
+        index
+    }
+
+    /// The index of the same policies without the `excluded` ones.
+    pub(crate) fn without(&self, excluded: &HashSet<&PolicyId>) -> Self {
+        let retain = |policies: &PolicySet| {
+            subset(policies.policies().filter(|p| !excluded.contains(p.id())))
+        };
+        Self {
+            slices: self
+                .slices
+                .iter()
+                .map(|(action, policies)| (action.clone(), retain(policies)))
+                .collect(),
+            buckets: self
+                .buckets
+                .iter()
+                .map(|(key, policies)| (key.clone(), retain(policies)))
+                .collect(),
+        }
+    }
+
+    /// The policies that may apply to `action`, or `None` if it is not in the schema.
+    pub(crate) fn get(&self, action: &EntityUid) -> Option<&PolicySet> {
+        self.slices.get(action)
+    }
+
+    /// The policies that may apply to `action` on a principal and resource of the given types.
+    /// Like [`ActionIndex::get`] for types the action does not apply to.
+    pub(crate) fn bucket(
+        &self,
+        principal: &EntityTypeName,
+        action: &EntityUid,
+        resource: &EntityTypeName,
+    ) -> Option<&PolicySet> {
+        let key = (principal.clone(), action.clone(), resource.clone());
+        self.buckets.get(&key).or_else(|| self.get(action))
+    }
+}
+
+fn subset<'a>(policies: impl Iterator<Item = &'a Policy>) -> PolicySet {
+    PolicySet::from_policies(policies.cloned()).expect("a subset of a policy set has unique IDs")
+}
+
+fn may_apply(policy: &Policy, action: &EntityUid, groups: &Entities) -> bool {
+    match policy.action_constraint() {
+        ActionConstraint::Any => true,
+        ActionConstraint::Eq(uid) => uid == *action,
+        ActionConstraint::In(uids) => uids
+            .iter()
+            .any(|uid| uid == action || groups.is_ancestor_of(uid, action)),
+    }
+}
+
+/// The type a scope constraint requires and the group it requires membership in. `==` is
+/// treated as both.
+type Scope = (Option<EntityTypeName>, Option<EntityUid>);
+
+fn principal_scope(policy: &Policy) -> Scope {
+    match policy.principal_constraint() {
+        PrincipalConstraint::Any => (None, None),
+        PrincipalConstraint::Eq(uid) => (Some(uid.type_name().clone()), Some(uid)),
+        PrincipalConstraint::In(uid) => (None, Some(uid)),
+        PrincipalConstraint::Is(ty) => (Some(ty), None),
+        PrincipalConstraint::IsIn(ty, uid) => (Some(ty), Some(uid)),
+    }
+}
+
+fn resource_scope(policy: &Policy) -> Scope {
+    match policy.resource_constraint() {
+        ResourceConstraint::Any => (None, None),
+        ResourceConstraint::Eq(uid) => (Some(uid.type_name().clone()), Some(uid)),
+        ResourceConstraint::In(uid) => (None, Some(uid)),
+        ResourceConstraint::Is(ty) => (Some(ty), None),
+        ResourceConstraint::IsIn(ty, uid) => (Some(ty), Some(uid)),
+    }
+}
+
+fn admits(
+    schema: &Schema,
+    ty: &EntityTypeName,
+    is: Option<EntityTypeName>,
+    within: Option<EntityUid>,
+) -> bool {
+    let in_group = |group: &EntityUid| {
+        group.type_name() == ty
+            || schema
+                .ancestors(ty)
+                .is_some_and(|mut ancestors| ancestors.any(|a| a == group.type_name()))
+    };
+    is.is_none_or(|is| is == *ty) && within.is_none_or(|group| in_group(&group))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use cedar_policy::{EntityTypeName, SchemaFragment};
+
+    use super::*;
+    use crate::{CEDAR_SCHEMA, CEDAR_SCHEMA_SRC, Engine, pdp::PartialUid};
+
+    #[test]
+    fn test_action_index() {
+        let policies = PolicySet::from_str(
+            r#"
+            permit (principal, action == MyApp::Action::"GetServerMetadata", resource);
+            permit (principal, action in MyApp::Action::"ProjectActions", resource);
+            permit (principal, action in [MyApp::Action::"GetProjectMetadata"], resource);
+            permit (principal, action, resource);
+            "#,
+        )
+        .unwrap();
+        let index = ActionIndex::new(&CEDAR_SCHEMA, &policies);
+        let ids = |action: &str| {
+            let action = EntityUid::from_str(&format!(r#"MyApp::Action::"{action}""#)).unwrap();
+            let mut ids = index
+                .get(&action)
+                .unwrap()
+                .policies()
+                .map(|p| p.id().clone())
+                .collect::<Vec<_>>();
+            ids.sort();
+            ids
+        };
+        let expected = |ids: &[&str]| ids.iter().map(|id| PolicyId::new(*id)).collect::<Vec<_>>();
+        assert_eq!(
+            ids("GetProjectMetadata"),
+            expected(&["policy1", "policy2", "policy3"])
+        );
+        assert_eq!(ids("DeleteProject"), expected(&["policy1", "policy3"]));
+        assert_eq!(ids("GetServerMetadata"), expected(&["policy0", "policy3"]));
+
+        // TPE leaves out the policies for other actions.
+        let engine = Engine::new(
+            SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(),
+            policies.clone(),
+            Entities::empty(),
+        )
+        .unwrap();
+        let residuals = engine
+            .tpe(
+                PartialUid::unknown(EntityTypeName::from_str("MyApp::User").unwrap()),
+                EntityUid::from_str(r#"MyApp::Action::"GetServerMetadata""#).unwrap(),
+                PartialUid::from(EntityUid::from_str(r#"MyApp::Server::"0""#).unwrap()),
+                None,
+            )
+            .unwrap();
+        let residual_ids = residuals
+            .policies()
+            .map(|p| p.id().clone())
+            .collect::<Vec<_>>();
+        assert_eq!(residual_ids, expected(&["policy0", "policy3"]));
+
+        let (schema, _) = SchemaFragment::from_cedarschema_str("entity User;").unwrap();
+        let unknown = EntityUid::from_str(r#"MyApp::Action::"GetProjectMetadata""#).unwrap();
+        let index = ActionIndex::new(&schema.try_into().unwrap(), &policies);
+        assert!(index.get(&unknown).is_none());
+    }
+
+    #[test]
+    fn test_type_buckets() {
+        let policies = PolicySet::from_str(
+            r#"
+            permit (principal is MyApp::Role, action, resource);
+            permit (principal in MyApp::Role::"admin", action, resource in MyApp::Server::"0");
+            permit (principal == MyApp::User::"0", action, resource is MyApp::Server);
+            "#,
+        )
+        .unwrap();
+        let index = ActionIndex::new(&CEDAR_SCHEMA, &policies);
+        let ids = |principal: &str, action: &str, resource: &str| {
+            let mut ids = index
+                .bucket(
+                    &EntityTypeName::from_str(principal).unwrap(),
+                    &EntityUid::from_str(&format!(r#"MyApp::Action::"{action}""#)).unwrap(),
+                    &EntityTypeName::from_str(resource).unwrap(),
+                )
+                .unwrap()
+                .policies()
+                .map(|p| p.id().to_string())
+                .collect::<Vec<_>>();
+            ids.sort();
+            ids
+        };
+        // Users can be in roles, and projects in servers.
+        assert_eq!(
+            ids("MyApp::User", "GetProjectMetadata", "MyApp::Project"),
+            ["policy1"]
+        );
+        assert_eq!(
+            ids("MyApp::Role", "GetProjectMetadata", "MyApp::Project"),
+            ["policy0", "policy1"]
+        );
+        assert_eq!(
+            ids("MyApp::User", "GetServerMetadata", "MyApp::Server"),
+            ["policy1", "policy2"]
+        );
+        // Types the action does not apply to fall back to its slice.
+        assert_eq!(
+            ids("MyApp::Project", "GetServerMetadata", "MyApp::Server").len(),
+            3
+        );
+    }
+
+    #[test]
+    fn test_index_without() {
+        let policies = PolicySet::from_str(
+            r#"
+            permit (principal, action == MyApp::Action::"GetServerMetadata", resource);
+            permit (principal, action, resource is MyApp::Server);
+            "#,
+        )
+        .unwrap();
+        let index = ActionIndex::new(&CEDAR_SCHEMA, &policies);
+        let excluded = PolicyId::new("policy0");
+        let without = index.without(&HashSet::from([&excluded]));
+        let rest =
+            PolicySet::from_policies(policies.policy(&PolicyId::new("policy1")).cloned()).unwrap();
+        let rebuilt = ActionIndex::new(&CEDAR_SCHEMA, &rest);
+        let action = EntityUid::from_str(r#"MyApp::Action::"GetServerMetadata""#).unwrap();
+        let [user, server] =
+            ["MyApp::User", "MyApp::Server"].map(|ty| EntityTypeName::from_str(ty).unwrap());
+        assert_eq!(without.get(&action), rebuilt.get(&action));
+        assert_eq!(
+            without.bucket(&user, &action, &server),
+            rebuilt.bucket(&user, &action, &server)
+        );
+    }
+}
@@ -0,0 +1,576 @@
+//! An append-only feed of policy and entity mutations, so downstream systems can rebuild
+//! caches and auditors can reconstruct the state at any point.
+//!
+//! A [`RecordingStore`] emits an event for every successful write through it, and
+//! [`ChangeFeed::capture_entities`] emits the entity changes of every state installed into an
+//! engine. Events carry full content, so [`reconstruct`] rebuilds the state from a prefix of
+//! the feed. Events go to every [`ChangeSink`] and to subscribers of [`ChangeFeed::subscribe`].
+//! Only a JSON-lines sink is included; forwarding to a message broker such as Kafka is left to
+//! an implementation of [`ChangeSink`].
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+    sync::{
+        Arc, Mutex, PoisonError, Weak,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use cedar_policy::{Entities, EntityUid, Policy, PolicyId, PolicySet, Schema, SlotId, Template};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::{
+    runtime::Handle,
+    sync::{self, broadcast, watch},
+    task::JoinHandle,
+};
+
+use super::{PolicyStore, PolicyVersion, TemplateLink, TemplateStore};
+use crate::{
+    audit::JsonLinesSink,
+    engine::{Engine, is_action},
+    error::{Error, Result},
+    namespace::id_str,
+};
+
+/// Events buffered per subscriber before it lags behind.
+const FEED_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Change {
+    /// A static policy was inserted or replaced. `policy` is its Cedar text.
+    PolicyPut {
+        id: String,
+        policy: String,
+    },
+    PolicyDeleted {
+        id: String,
+    },
+    TemplatePut {
+        id: String,
+        template: String,
+    },
+    TemplateDeleted {
+        id: String,
+    },
+    Linked {
+        id: String,
+        template_id: String,
+        principal: Option<String>,
+        resource: Option<String>,
+    },
+    Unlinked {
+        id: String,
+    },
+    /// An entity was inserted or replaced. `entity` is in Cedar's entity JSON format.
+    EntityPut {
+        uid: String,
+        entity: Value,
+    },
+    EntityDeleted {
+        uid: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChangeEvent {
+    /// Starts at 1 and increases by one with every event of a feed.
+    pub sequence: u64,
+    pub timestamp: DateTime<Utc>,
+    #[serde(flatten)]
+    pub change: Change,
+}
+
+/// Receives every event in sequence order.
+pub trait ChangeSink: Send + Sync + 'static {
+    fn record(&self, event: &ChangeEvent) -> io::Result<()>;
+}
+
+impl<W: io::Write + Send + 'static> ChangeSink for JsonLinesSink<W> {
+    fn record(&self, event: &ChangeEvent) -> io::Result<()> {
+        self.write_json(event)
+    }
+}
+
+pub struct ChangeFeed {
+    sequence: Mutex<u64>,
+    sinks: Vec<Box<dyn ChangeSink>>,
+    subscribers: broadcast::Sender<ChangeEvent>,
+    failures: AtomicU64,
+}
+
+impl std::fmt::Debug for ChangeFeed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ChangeFeed")
+            .field("sequence", &self.sequence)
+            .field("failures", &self.failures)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for ChangeFeed {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ChangeFeed {
+    pub fn new() -> Self {
+        Self {
+            sequence: Mutex::new(0),
+            sinks: Vec::new(),
+            subscribers: broadcast::Sender::new(FEED_CAPACITY),
+            failures: AtomicU64::new(0),
+        }
+    }
+
+    pub fn with_sink(mut self, sink: impl ChangeSink) -> Self {
+        self.sinks.push(Box::new(sink));
+        self
+    }
+
+    /// Receive every event from now on. A receiver that lags behind gets
+    /// [`broadcast::error::RecvError::Lagged`] and should rebuild from a sink instead.
+    pub fn subscribe(&self) -> broadcast::Receiver<ChangeEvent> {
+        self.subscribers.subscribe()
+    }
+
+    /// The number of events a sink failed to write.
+    pub fn failures(&self) -> u64 {
+        self.failures.load(Ordering::Relaxed)
+    }
+
+    /// Emit `change` to all sinks and subscribers. A failing sink does not fail the mutation;
+    /// failures are counted instead.
+    pub fn emit(&self, change: Change) {
+        // Holding the lock while writing keeps sinks in sequence order.
+        let mut sequence = self.sequence.lock().unwrap_or_else(PoisonError::into_inner);
+        *sequence += 1;
+        let event = ChangeEvent {
+            sequence: *sequence,
+            timestamp: Utc::now(),
+            change,
+        };
+        for sink in &self.sinks {
+            if sink.record(&event).is_err() {
+                self.failures.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        // Sending only fails without subscribers.
+        let _ = self.subscribers.send(event);
+    }
+
+    /// Emit the entity changes from `before` to `after`. Action entities are derived from
+    /// `schema` and left out.
+    pub fn record_entities(&self, schema: &Schema, before: &Entities, after: &Entities) {
+        let json = |entities: &Entities| {
+            entities
+                .iter()
+                .filter(|e| !is_action(schema, e))
+                .filter_map(|e| Some((e.uid().to_string(), e.to_json_value().ok()?)))
+                .collect::<BTreeMap<_, _>>()
+        };
+        let (before, after) = (json(before), json(after));
+        for (uid, entity) in &after {
+            if before.get(uid) != Some(entity) {
+                self.emit(Change::EntityPut {
+                    uid: uid.clone(),
+                    entity: entity.clone(),
+                });
+            }
+        }
+        for uid in before.keys().filter(|uid| !after.contains_key(*uid)) {
+            self.emit(Change::EntityDeleted { uid: uid.clone() });
+        }
+    }
+
+    /// Emit the entity changes of every state installed into `engine` from now on, however it
+    /// was installed, from a task on `runtime`. The task ends when the engine is dropped.
+    pub fn capture_entities(
+        self: &Arc<Self>,
+        engine: &Arc<Engine>,
+        runtime: &Handle,
+    ) -> JoinHandle<()> {
+        let mut changes = engine.subscribe();
+        let mut previous = engine.state();
+        let (feed, engine) = (self.clone(), Arc::downgrade(engine));
+        runtime.spawn(async move {
+            loop {
+                match changes.recv().await {
+                    Ok(change) if !change.entities_changed => continue,
+                    // Lagging only coalesces changes, as the diff is against the last state.
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => return,
+                }
+                let Some(engine) = Weak::upgrade(&engine) else {
+                    return;
+                };
+                let current = engine.state();
+                feed.record_entities(current.schema(), previous.entities(), current.entities());
+                previous = current;
+            }
+        })
+    }
+}
+
+/// Rebuild the policies and entities from the events of a feed, oldest first. Pass a prefix
+/// of the feed to reconstruct an earlier state.
+pub fn reconstruct(
+    events: impl IntoIterator<Item = ChangeEvent>,
+    schema: Option<&Schema>,
+) -> Result<(PolicySet, Entities)> {
+    let mut policies = BTreeMap::new();
+    let mut templates = BTreeMap::new();
+    let mut links = BTreeMap::new();
+    let mut entities = HashMap::new();
+    for event in events {
+        match event.change {
+            Change::PolicyPut { id, policy } => {
+                policies.insert(id, policy);
+            }
+            Change::PolicyDeleted { id } => {
+                policies.remove(&id);
+            }
+            Change::TemplatePut { id, template } => {
+                templates.insert(id, template);
+            }
+            Change::TemplateDeleted { id } => {
+                templates.remove(&id);
+            }
+            Change::Linked {
+                id,
+                template_id,
+                principal,
+                resource,
+            } => {
+                links.insert(id, (template_id, principal, resource));
+            }
+            Change::Unlinked { id } => {
+                links.remove(&id);
+            }
+            Change::EntityPut { uid, entity } => {
+                entities.insert(uid, entity);
+            }
+            Change::EntityDeleted { uid } => {
+                entities.remove(&uid);
+            }
+        }
+    }
+
+    let mut set = PolicySet::new();
+    for (id, text) in policies {
+        set.add(Policy::parse(Some(PolicyId::new(id)), text)?)?;
+    }
+    for (id, text) in templates {
+        set.add_template(Template::parse(Some(PolicyId::new(id)), text)?)?;
+    }
+    for (id, (template_id, principal, resource)) in links {
+        let mut slots = HashMap::new();
+        for (slot, uid) in [
+            (SlotId::principal(), principal),
+            (SlotId::resource(), resource),
+        ] {
+            if let Some(uid) = uid {
+                let uid = uid
+                    .parse::<EntityUid>()
+                    .map_err(|e| Error::Mapping(format!("Invalid entity UID `{uid}`: {e}")))?;
+                slots.insert(slot, uid);
+            }
+        }
+        set.link(PolicyId::new(template_id), PolicyId::new(id), slots)?;
+    }
+    let entities =
+        Entities::from_json_value(Value::Array(entities.into_values().collect()), schema)?;
+    Ok((set, entities))
+}
+
+/// A store that emits a [`Change`] to `feed` for every successful write through it. Writes
+/// made to the inner store directly are not recorded.
+///
+/// Writes through the store are serialized, so events are in the order the writes were
+/// applied.
+#[derive(Debug)]
+pub struct RecordingStore<S> {
+    inner: S,
+    feed: Arc<ChangeFeed>,
+    /// Held from the write to the inner store until its event is emitted.
+    writes: sync::Mutex<()>,
+}
+
+impl<S: PolicyStore> RecordingStore<S> {
+    pub fn new(inner: S, feed: Arc<ChangeFeed>) -> Self {
+        Self {
+            inner,
+            feed,
+            writes: sync::Mutex::new(()),
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn feed(&self) -> &Arc<ChangeFeed> {
+        &self.feed
+    }
+}
+
+// `rollback` is left to the default, which writes through `put` and `delete` and is recorded.
+impl<S: PolicyStore> PolicyStore for RecordingStore<S> {
+    async fn list(&self) -> Result<Vec<Policy>> {
+        self.inner.list().await
+    }
+
+    async fn get(&self, id: &PolicyId) -> Result<Option<Policy>> {
+        self.inner.get(id).await
+    }
+
+    async fn put(&self, policy: Policy) -> Result<()> {
+        let _write = self.writes.lock().await;
+        let change = Change::PolicyPut {
+            id: id_str(policy.id()).to_string(),
+            policy: policy.to_string(),
+        };
+        self.inner.put(policy).await?;
+        self.feed.emit(change);
+        Ok(())
+    }
+
+    async fn delete(&self, id: &PolicyId) -> Result<bool> {
+        let _write = self.writes.lock().await;
+        let existed = self.inner.delete(id).await?;
+        if existed {
+            self.feed.emit(Change::PolicyDeleted {
+                id: id_str(id).to_string(),
+            });
+        }
+        Ok(existed)
+    }
+
+    async fn create(&self, policy: Policy) -> Result<()> {
+        let _write = self.writes.lock().await;
+        let change = Change::PolicyPut {
+            id: id_str(policy.id()).to_string(),
+            policy: policy.to_string(),
+        };
+        self.inner.create(policy).await?;
+        self.feed.emit(change);
+        Ok(())
+    }
+
+    async fn replace_all(&self, policies: Vec<Policy>) -> Result<()> {
+        let _write = self.writes.lock().await;
+        let removed = self
+            .inner
+            .list()
+            .await?
+            .into_iter()
+            .filter(|existing| !policies.iter().any(|policy| policy.id() == existing.id()))
+            .map(|existing| Change::PolicyDeleted {
+                id: id_str(existing.id()).to_string(),
+            })
+            .collect::<Vec<_>>();
+        let put = policies
+            .iter()
+            .map(|policy| Change::PolicyPut {
+                id: id_str(policy.id()).to_string(),
+                policy: policy.to_string(),
+            })
+            .collect::<Vec<_>>();
+        self.inner.replace_all(policies).await?;
+        for change in removed.into_iter().chain(put) {
+            self.feed.emit(change);
+        }
+        Ok(())
+    }
+
+    fn watch(&self) -> watch::Receiver<u64> {
+        self.inner.watch()
+    }
+
+    async fn sequence(&self) -> Result<Option<u64>> {
+        self.inner.sequence().await
+    }
+
+    async fn ping(&self) -> Result<()> {
+        self.inner.ping().await
+    }
+
+    async fn history(&self, id: &PolicyId) -> Result<Vec<PolicyVersion>> {
+        self.inner.history(id).await
+    }
+
+    async fn get_at(&self, id: &PolicyId, version: u64) -> Result<Option<PolicyVersion>> {
+        self.inner.get_at(id, version).await
+    }
+}
+
+impl<S: TemplateStore> TemplateStore for RecordingStore<S> {
+    async fn templates(&self) -> Result<Vec<Template>> {
+        self.inner.templates().await
+    }
+
+    async fn put_template(&self, template: Template) -> Result<()> {
+        let _write = self.writes.lock().await;
+        let change = Change::TemplatePut {
+            id: id_str(template.id()).to_string(),
+            template: template.to_string(),
+        };
+        self.inner.put_template(template).await?;
+        self.feed.emit(change);
+        Ok(())
+    }
+
+    async fn delete_template(&self, id: &PolicyId) -> Result<bool> {
+        let _write = self.writes.lock().await;
+        let existed = self.inner.delete_template(id).await?;
+        if existed {
+            self.feed.emit(Change::TemplateDeleted {
+                id: id_str(id).to_string(),
+            });
+        }
+        Ok(existed)
+    }
+
+    async fn links(&self) -> Result<Vec<TemplateLink>> {
+        self.inner.links().await
+    }
+
+    async fn link(&self, link: TemplateLink) -> Result<()> {
+        let _write = self.writes.lock().await;
+        let slot = |slot: SlotId| link.slots.get(&slot).map(ToString::to_string);
+        let change = Change::Linked {
+            id: id_str(&link.id).to_string(),
+            template_id: id_str(&link.template_id).to_string(),
+            principal: slot(SlotId::principal()),
+            resource: slot(SlotId::resource()),
+        };
+        self.inner.link(link).await?;
+        self.feed.emit(change);
+        Ok(())
+    }
+
+    async fn unlink(&self, id: &PolicyId) -> Result<bool> {
+        let _write = self.writes.lock().await;
+        let existed = self.inner.unlink(id).await?;
+        if existed {
+            self.feed.emit(Change::Unlinked {
+                id: id_str(id).to_string(),
+            });
+        }
+        Ok(existed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::{CEDAR_SCHEMA, CEDAR_SCHEMA_SRC, store::MemoryPolicyStore};
+
+    #[tokio::test]
+    async fn test_change_feed() {
+        let feed = Arc::new(ChangeFeed::new().with_sink(JsonLinesSink::new(Vec::new())));
+        let mut events = feed.subscribe();
+        let store = RecordingStore::new(MemoryPolicyStore::new(CEDAR_SCHEMA.clone()), feed.clone());
+        let policy = |id: &str, text: &str| Policy::parse(Some(PolicyId::new(id)), text).unwrap();
+        store
+            .put(policy("a", "permit (principal, action, resource);"))
+            .await
+            .unwrap();
+        store
+            .put(policy("b", "forbid (principal, action, resource);"))
+            .await
+            .unwrap();
+        assert!(store.delete(&PolicyId::new("b")).await.unwrap());
+        assert!(!store.delete(&PolicyId::new("b")).await.unwrap());
+
+        let engine = Arc::new(
+            Engine::new(
+                CEDAR_SCHEMA_SRC.parse().unwrap(),
+                PolicySet::new(),
+                Entities::empty(),
+            )
+            .unwrap(),
+        );
+        let capture = feed.capture_entities(&engine, &Handle::current());
+        let entities = Entities::from_json_str(
+            r#"[{ "uid": { "type": "MyApp::User", "id": "0" }, "attrs": {}, "parents": [] }]"#,
+            Some(&CEDAR_SCHEMA),
+        )
+        .unwrap();
+        engine.replace_entities(entities).unwrap();
+
+        let mut received = Vec::new();
+        while received.len() < 4 {
+            received.push(events.recv().await.unwrap());
+        }
+        let sequences = received.iter().map(|e| e.sequence).collect::<Vec<_>>();
+        assert_eq!(sequences, [1, 2, 3, 4]);
+        assert_eq!(
+            received[3].change,
+            Change::EntityPut {
+                uid: r#"MyApp::User::"0""#.to_string(),
+                entity: engine
+                    .state()
+                    .entities()
+                    .get(&EntityUid::from_str(r#"MyApp::User::"0""#).unwrap())
+                    .unwrap()
+                    .to_json_value()
+                    .unwrap(),
+            }
+        );
+
+        // The state after the second event had both policies.
+        let (policies, _) = reconstruct(received[..2].to_vec(), None).unwrap();
+        assert_eq!(policies.policies().count(), 2);
+        let (policies, entities) = reconstruct(received.clone(), Some(&CEDAR_SCHEMA)).unwrap();
+        assert!(policies.policy(&PolicyId::new("a")).is_some());
+        assert!(policies.policy(&PolicyId::new("b")).is_none());
+        assert!(
+            entities
+                .get(&EntityUid::from_str(r#"MyApp::User::"0""#).unwrap())
+                .is_some()
+        );
+        assert_eq!(feed.failures(), 0);
+        capture.abort();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_recording_store_orders_writes() {
+        let feed = Arc::new(ChangeFeed::new());
+        let mut events = feed.subscribe();
+        let store = Arc::new(RecordingStore::new(
+            MemoryPolicyStore::new(CEDAR_SCHEMA.clone()),
+            feed,
+        ));
+        let writes = (0..32).map(|i| {
+            let store = store.clone();
+            tokio::spawn(async move {
+                let text = format!("permit (principal, action, resource) when {{ {i} == {i} }};");
+                let policy = Policy::parse(Some(PolicyId::new("a")), text).unwrap();
+                store.put(policy).await.unwrap();
+            })
+        });
+        for write in writes.collect::<Vec<_>>() {
+            write.await.unwrap();
+        }
+        let mut last = None;
+        while let Ok(event) = events.try_recv() {
+            last = Some(event.change);
+        }
+        // The last event is the write that the store kept.
+        let Some(Change::PolicyPut { policy, .. }) = last else {
+            panic!("Expected a policy put, got {last:?}");
+        };
+        let stored = store.get(&PolicyId::new("a")).await.unwrap().unwrap();
+        assert_eq!(
+            Policy::from_str(&policy).unwrap().to_json().unwrap(),
+            stored.to_json().unwrap()
+        );
+    }
+}
@@ -0,0 +1,335 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{Arc, PoisonError, RwLock},
+};
+
+use cedar_policy::{Policy, PolicyId, PolicySet, Schema};
+use tokio::sync::watch;
+
+use super::PolicyStore;
+use crate::{
+    engine::validate_policies,
+    error::{Error, Result},
+};
+
+/// Where a policy of a [`DirectoryPolicyStore`] was loaded from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolicyLocation {
+    /// Path relative to the store's root directory.
+    pub path: PathBuf,
+    /// The top-level subdirectory the file is in, used as tenant or tag. `None` for files in
+    /// the root directory.
+    pub tag: Option<String>,
+}
+
+/// A read-only [`PolicyStore`] over a directory tree of `*.cedar` files, e.g. a git checkout.
+///
+/// A policy's ID is its `@id("...")` annotation if present. Otherwise it is the file's path
+/// relative to the root without the extension, e.g. `tenant-a/projects`, followed by `#<n>` if
+/// the file contains more than one policy. IDs must be unique across the tree. Entries whose
+/// names start with `..` are skipped, so a mounted ConfigMap or Secret volume reads like a plain
+/// directory.
+#[derive(Debug)]
+pub struct DirectoryPolicyStore {
+    root: PathBuf,
+    schema: Schema,
+    snapshot: RwLock<Arc<Snapshot>>,
+    revision: watch::Sender<u64>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Snapshot {
+    policies: BTreeMap<PolicyId, Policy>,
+    locations: BTreeMap<PolicyId, PolicyLocation>,
+}
+
+impl DirectoryPolicyStore {
+    /// Load and validate all policies below `root`.
+    pub fn open(root: impl Into<PathBuf>, schema: Schema) -> Result<Self> {
+        let root = root.into();
+        let snapshot = load(&root, &schema)?;
+        Ok(Self {
+            root,
+            schema,
+            snapshot: RwLock::new(Arc::new(snapshot)),
+            revision: watch::Sender::new(0),
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Read the directory again. Returns whether the policies changed. On error the previously
+    /// loaded policies are kept.
+    pub fn reload(&self) -> Result<bool> {
+        let snapshot = load(&self.root, &self.schema)?;
+        let mut current = self
+            .snapshot
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        if **current == snapshot {
+            return Ok(false);
+        }
+        *current = Arc::new(snapshot);
+        self.revision.send_modify(|revision| *revision += 1);
+        Ok(true)
+    }
+
+    pub fn location(&self, id: &PolicyId) -> Option<PolicyLocation> {
+        self.snapshot().locations.get(id).cloned()
+    }
+
+    /// Policies loaded from below the top-level subdirectory `tag`.
+    pub fn tagged(&self, tag: &str) -> Vec<Policy> {
+        let snapshot = self.snapshot();
+        snapshot
+            .locations
+            .iter()
+            .filter(|(_, location)| location.tag.as_deref() == Some(tag))
+            .map(|(id, _)| snapshot.policies[id].clone())
+            .collect()
+    }
+
+    fn snapshot(&self) -> Arc<Snapshot> {
+        self.snapshot
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
+
+impl PolicyStore for DirectoryPolicyStore {
+    async fn list(&self) -> Result<Vec<Policy>> {
+        Ok(self.snapshot().policies.values().cloned().collect())
+    }
+
+    async fn get(&self, id: &PolicyId) -> Result<Option<Policy>> {
+        Ok(self.snapshot().policies.get(id).cloned())
+    }
+
+    /// Policies are changed by editing the files, followed by [`DirectoryPolicyStore::reload`].
+    async fn put(&self, _policy: Policy) -> Result<()> {
+        Err(Error::ReadOnly)
+    }
+
+    async fn delete(&self, _id: &PolicyId) -> Result<bool> {
+        Err(Error::ReadOnly)
+    }
+
+    fn watch(&self) -> watch::Receiver<u64> {
+        self.revision.subscribe()
+    }
+}
+
+fn load(root: &Path, schema: &Schema) -> Result<Snapshot> {
+    let mut files = Vec::new();
+    find_policy_files(root, &mut files)?;
+    files.sort();
+
+    let mut set = PolicySet::new();
+    let mut snapshot = Snapshot::default();
+    for path in files {
+        let relative = path.strip_prefix(root).unwrap_or(&path).to_path_buf();
+        let in_file = |e: Error| Error::File {
+            path: relative.clone(),
+            source: Box::new(e),
+        };
+        let src = std::fs::read_to_string(&path).map_err(|source| Error::Io {
+            path: path.clone(),
+            source,
+        })?;
+        let parsed = parse_file(&relative, &src).map_err(in_file)?;
+        let location = PolicyLocation {
+            tag: relative
+                .parent()
+                .and_then(|p| p.components().next())
+                .map(|c| c.as_os_str().to_string_lossy().into_owned()),
+            path: relative.clone(),
+        };
+        for policy in parsed {
+            set.add(policy.clone()).map_err(|e| in_file(e.into()))?;
+            snapshot
+                .locations
+                .insert(policy.id().clone(), location.clone());
+            snapshot.policies.insert(policy.id().clone(), policy);
+        }
+    }
+    validate_policies(schema, &set)?;
+    Ok(snapshot)
+}
+
+fn find_policy_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let io_error = |source| Error::Io {
+        path: dir.to_path_buf(),
+        source,
+    };
+    for entry in std::fs::read_dir(dir).map_err(io_error)? {
+        let entry = entry.map_err(io_error)?;
+        // Kubelet keeps the versions of a ConfigMap or Secret volume in `..`-prefixed entries
+        // and links the visible files into the current one.
+        if entry.file_name().to_string_lossy().starts_with("..") {
+            continue;
+        }
+        let path = entry.path();
+        // Symbolic links to directories are not followed, so a link cycle cannot recurse
+        // forever. Links to files are, as kubelet links its files.
+        let file_type = entry.file_type().map_err(io_error)?;
+        if file_type.is_dir() {
+            find_policy_files(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "cedar")
+            && !(file_type.is_symlink() && path.is_dir())
+        {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn parse_file(relative: &Path, src: &str) -> Result<Vec<Policy>> {
+    let parsed = src.parse::<PolicySet>()?;
+    if parsed.templates().next().is_some() {
+        return Err(Error::Unsupported("templates in policy files"));
+    }
+    let count = parsed.policies().count();
+    let base = relative.with_extension("");
+    let base = base.to_string_lossy().replace('\\', "/");
+    // The parser names policies `policy0`, `policy1`, ... in the order they appear.
+    (0..count)
+        .map(|n| {
+            let policy = parsed
+                .policy(&PolicyId::new(format!("policy{n}")))
+                .expect("Parsed policies are numbered consecutively");
+            let id = match policy.annotation("id") {
+                Some(id) => id.to_string(),
+                None if count == 1 => base.clone(),
+                None => format!("{base}#{n}"),
+            };
+            Ok(policy.new_id(PolicyId::new(id)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CEDAR_SCHEMA;
+
+    fn write(root: &Path, path: &str, src: &str) {
+        let path = root.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, src).unwrap();
+    }
+
+    fn tempdir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cedar-store-{}", uuid::Uuid::now_v7()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn test_load_and_reload() {
+        let root = tempdir();
+        write(
+            &root,
+            "global.cedar",
+            "permit (principal, action, resource);",
+        );
+        write(
+            &root,
+            "tenant-a/projects.cedar",
+            r#"
+            permit (principal == MyApp::User::"0", action, resource);
+            @id("deny-1")
+            forbid (principal == MyApp::User::"1", action, resource);
+            "#,
+        );
+        write(&root, "README.md", "not a policy");
+        let store = DirectoryPolicyStore::open(&root, CEDAR_SCHEMA.clone()).unwrap();
+
+        let ids = store
+            .list()
+            .await
+            .unwrap()
+            .iter()
+            .map(|p| p.id().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["deny-1", "global", "tenant-a/projects#0"]);
+        assert_eq!(
+            store.location(&PolicyId::new("deny-1")),
+            Some(PolicyLocation {
+                path: PathBuf::from("tenant-a/projects.cedar"),
+                tag: Some("tenant-a".to_string()),
+            })
+        );
+        assert_eq!(store.tagged("tenant-a").len(), 2);
+        assert!(matches!(
+            store.put(store.tagged("tenant-a")[0].clone()).await,
+            Err(Error::ReadOnly)
+        ));
+
+        let revision = store.watch();
+        assert!(!store.reload().unwrap());
+        std::fs::remove_file(root.join("global.cedar")).unwrap();
+        assert!(store.reload().unwrap());
+        assert!(revision.has_changed().unwrap());
+        assert_eq!(store.list().await.unwrap().len(), 2);
+
+        // Invalid files are rejected and the loaded policies are kept.
+        write(
+            &root,
+            "tenant-b/invalid.cedar",
+            r#"permit (principal == MyApp::Missing::"0", action, resource);"#,
+        );
+        assert!(matches!(store.reload(), Err(Error::Validation(_))));
+        assert_eq!(store.list().await.unwrap().len(), 2);
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_duplicate_ids() {
+        let root = tempdir();
+        write(
+            &root,
+            "a.cedar",
+            r#"@id("p") permit (principal, action, resource);"#,
+        );
+        write(
+            &root,
+            "b.cedar",
+            r#"@id("p") permit (principal, action, resource);"#,
+        );
+        let err = DirectoryPolicyStore::open(&root, CEDAR_SCHEMA.clone()).unwrap_err();
+        assert!(matches!(err, Error::File { path, .. } if path == Path::new("b.cedar")));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_directory_links_are_not_followed() {
+        let root = tempdir();
+        write(
+            &root,
+            "tenant-a/projects.cedar",
+            "permit (principal, action, resource);",
+        );
+        std::os::unix::fs::symlink(&root, root.join("tenant-a/loop")).unwrap();
+        std::os::unix::fs::symlink(
+            root.join("tenant-a/projects.cedar"),
+            root.join("linked.cedar"),
+        )
+        .unwrap();
+        let store = DirectoryPolicyStore::open(&root, CEDAR_SCHEMA.clone()).unwrap();
+        let ids = store
+            .list()
+            .await
+            .unwrap()
+            .iter()
+            .map(|p| p.id().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["linked", "tenant-a/projects"]);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
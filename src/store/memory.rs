@@ -0,0 +1,443 @@
+use std::{
+    collections::BTreeMap,
+    sync::{PoisonError, RwLock},
+};
+
+use cedar_policy::{Policy, PolicyId, PolicySet, Schema, Template};
+use chrono::Utc;
+use tokio::sync::watch;
+
+use super::{PolicyStore, PolicyVersion, TemplateLink, TemplateStore};
+use crate::{
+    engine::validate_policies,
+    error::{Error, Result},
+    format::canonicalize,
+};
+
+/// A [`PolicyStore`] that keeps policies, their history and templates in memory, e.g. for
+/// tests or as a cache in front of another store. Policies are stored in
+/// [canonical form](crate::format).
+#[derive(Debug)]
+pub struct MemoryPolicyStore {
+    schema: Schema,
+    inner: RwLock<Inner>,
+    revision: watch::Sender<u64>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    history: BTreeMap<PolicyId, Vec<PolicyVersion>>,
+    templates: BTreeMap<PolicyId, Template>,
+    links: BTreeMap<PolicyId, TemplateLink>,
+}
+
+impl Inner {
+    fn current(&self, id: &PolicyId) -> Option<&Policy> {
+        self.history
+            .get(id)
+            .and_then(|versions| versions.last())
+            .and_then(|v| v.policy.as_ref())
+    }
+
+    /// Fail if `id` is taken by anything but a `kind`, which is about to be replaced.
+    fn check_id(&self, id: &PolicyId, kind: &str) -> Result<()> {
+        let taken_by = if self.current(id).is_some() {
+            "policy"
+        } else if self.templates.contains_key(id) {
+            "template"
+        } else if self.links.contains_key(id) {
+            "template-linked policy"
+        } else {
+            return Ok(());
+        };
+        if taken_by == kind {
+            Ok(())
+        } else {
+            Err(Error::Conflict(format!("`{id}` is already a {taken_by}")))
+        }
+    }
+
+    /// A policy set with `template` and all links to it, for validation.
+    fn linked(&self, template: &Template, links: &[&TemplateLink]) -> Result<PolicySet> {
+        let mut policies = PolicySet::new();
+        policies.add_template(template.clone())?;
+        for link in links {
+            link.link_into(&mut policies)?;
+        }
+        Ok(policies)
+    }
+}
+
+impl MemoryPolicyStore {
+    pub fn new(schema: Schema) -> Self {
+        Self {
+            schema,
+            inner: RwLock::default(),
+            revision: watch::Sender::new(0),
+        }
+    }
+
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, Inner> {
+        self.inner.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Apply `f` under the write lock, and signal a change if it returns `true`.
+    fn write(&self, f: impl FnOnce(&mut Inner) -> Result<bool>) -> Result<bool> {
+        let mut inner = self.inner.write().unwrap_or_else(PoisonError::into_inner);
+        let changed = f(&mut inner)?;
+        if changed {
+            self.revision.send_modify(|revision| *revision += 1);
+        }
+        Ok(changed)
+    }
+
+    fn push(inner: &mut Inner, id: PolicyId, policy: Option<Policy>) {
+        let versions = inner.history.entry(id).or_default();
+        versions.push(PolicyVersion {
+            version: versions.len() as u64 + 1,
+            policy,
+            created_at: Utc::now(),
+        });
+    }
+}
+
+impl PolicyStore for MemoryPolicyStore {
+    async fn list(&self) -> Result<Vec<Policy>> {
+        let inner = self.read();
+        Ok(inner
+            .history
+            .keys()
+            .filter_map(|id| inner.current(id).cloned())
+            .collect())
+    }
+
+    async fn get(&self, id: &PolicyId) -> Result<Option<Policy>> {
+        Ok(self.read().current(id).cloned())
+    }
+
+    async fn put(&self, policy: Policy) -> Result<()> {
+        let policy = canonicalize(&policy)?;
+        validate_policies(&self.schema, &PolicySet::from_policies([policy.clone()])?)?;
+        self.write(|inner| {
+            inner.check_id(policy.id(), "policy")?;
+            Self::push(inner, policy.id().clone(), Some(policy));
+            Ok(true)
+        })?;
+        Ok(())
+    }
+
+    async fn delete(&self, id: &PolicyId) -> Result<bool> {
+        self.write(|inner| {
+            let exists = inner.current(id).is_some();
+            if exists {
+                Self::push(inner, id.clone(), None);
+            }
+            Ok(exists)
+        })
+    }
+
+    async fn create(&self, policy: Policy) -> Result<()> {
+        let policy = canonicalize(&policy)?;
+        validate_policies(&self.schema, &PolicySet::from_policies([policy.clone()])?)?;
+        self.write(|inner| {
+            if inner.current(policy.id()).is_some() {
+                return Err(Error::Conflict(format!(
+                    "Policy `{}` already exists",
+                    policy.id()
+                )));
+            }
+            inner.check_id(policy.id(), "policy")?;
+            Self::push(inner, policy.id().clone(), Some(policy));
+            Ok(true)
+        })?;
+        Ok(())
+    }
+
+    async fn replace_all(&self, policies: Vec<Policy>) -> Result<()> {
+        let policies = policies
+            .iter()
+            .map(canonicalize)
+            .collect::<Result<Vec<_>>>()?;
+        validate_policies(&self.schema, &PolicySet::from_policies(policies.clone())?)?;
+        self.write(|inner| {
+            for policy in &policies {
+                inner.check_id(policy.id(), "policy")?;
+            }
+            let removed = inner
+                .history
+                .keys()
+                .filter(|id| inner.current(id).is_some())
+                .filter(|id| !policies.iter().any(|policy| policy.id() == *id))
+                .cloned()
+                .collect::<Vec<_>>();
+            for id in removed {
+                Self::push(inner, id, None);
+            }
+            for policy in policies {
+                Self::push(inner, policy.id().clone(), Some(policy));
+            }
+            Ok(true)
+        })?;
+        Ok(())
+    }
+
+    fn watch(&self) -> watch::Receiver<u64> {
+        self.revision.subscribe()
+    }
+
+    async fn sequence(&self) -> Result<Option<u64>> {
+        Ok(Some(*self.revision.borrow()))
+    }
+
+    async fn history(&self, id: &PolicyId) -> Result<Vec<PolicyVersion>> {
+        Ok(self.read().history.get(id).cloned().unwrap_or_default())
+    }
+
+    async fn get_at(&self, id: &PolicyId, version: u64) -> Result<Option<PolicyVersion>> {
+        Ok(self
+            .read()
+            .history
+            .get(id)
+            .and_then(|versions| versions.get(usize::try_from(version).ok()?.checked_sub(1)?))
+            .cloned())
+    }
+}
+
+impl TemplateStore for MemoryPolicyStore {
+    async fn templates(&self) -> Result<Vec<Template>> {
+        Ok(self.read().templates.values().cloned().collect())
+    }
+
+    async fn put_template(&self, template: Template) -> Result<()> {
+        self.write(|inner| {
+            inner.check_id(template.id(), "template")?;
+            let links = inner
+                .links
+                .values()
+                .filter(|link| &link.template_id == template.id())
+                .collect::<Vec<_>>();
+            validate_policies(&self.schema, &inner.linked(&template, &links)?)?;
+            inner.templates.insert(template.id().clone(), template);
+            Ok(true)
+        })?;
+        Ok(())
+    }
+
+    async fn delete_template(&self, id: &PolicyId) -> Result<bool> {
+        self.write(|inner| {
+            if let Some(link) = inner.links.values().find(|link| &link.template_id == id) {
+                return Err(Error::Conflict(format!(
+                    "Template `{id}` is still linked by `{}`",
+                    link.id
+                )));
+            }
+            Ok(inner.templates.remove(id).is_some())
+        })
+    }
+
+    async fn links(&self) -> Result<Vec<TemplateLink>> {
+        Ok(self.read().links.values().cloned().collect())
+    }
+
+    async fn link(&self, link: TemplateLink) -> Result<()> {
+        self.write(|inner| {
+            inner.check_id(&link.id, "template-linked policy")?;
+            let template = inner
+                .templates
+                .get(&link.template_id)
+                .ok_or_else(|| Error::NotFound(format!("Template `{}`", link.template_id)))?;
+            validate_policies(&self.schema, &inner.linked(template, &[&link])?)?;
+            inner.links.insert(link.id.clone(), link);
+            Ok(true)
+        })?;
+        Ok(())
+    }
+
+    async fn unlink(&self, id: &PolicyId) -> Result<bool> {
+        self.write(|inner| Ok(inner.links.remove(id).is_some()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use cedar_policy::{Entities, EntityUid, SchemaFragment, SlotId};
+
+    use super::*;
+    use crate::{
+        CEDAR_SCHEMA, CEDAR_SCHEMA_SRC, Engine,
+        store::{load_policies, load_policies_and_templates},
+    };
+
+    fn policy(id: &str, src: &str) -> Policy {
+        Policy::parse(Some(PolicyId::new(id)), src).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_crud() {
+        let store = MemoryPolicyStore::new(CEDAR_SCHEMA.clone());
+        let mut revision = store.watch();
+        let admin = policy(
+            "admin",
+            r#"permit (principal == MyApp::User::"0", action, resource);"#,
+        );
+
+        store.put(admin.clone()).await.unwrap();
+        assert!(revision.has_changed().unwrap());
+        revision.mark_unchanged();
+        let stored = store.get(&PolicyId::new("admin")).await.unwrap().unwrap();
+        assert_eq!(stored, admin);
+        assert_eq!(
+            stored.to_string(),
+            "permit (\n  principal == MyApp::User::\"0\",\n  action,\n  resource\n);"
+        );
+        assert_eq!(store.list().await.unwrap().len(), 1);
+
+        let invalid = policy(
+            "invalid",
+            r#"permit (principal == MyApp::Missing::"0", action, resource);"#,
+        );
+        assert!(matches!(
+            store.put(invalid).await.unwrap_err(),
+            Error::Validation(_)
+        ));
+        assert!(!revision.has_changed().unwrap());
+
+        assert!(store.delete(&PolicyId::new("admin")).await.unwrap());
+        assert!(!store.delete(&PolicyId::new("admin")).await.unwrap());
+        assert!(store.list().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_history_and_rollback() {
+        let store = MemoryPolicyStore::new(CEDAR_SCHEMA.clone());
+        let id = PolicyId::new("p");
+        let permit = policy("p", "permit (principal, action, resource);");
+        let forbid = policy("p", "forbid (principal, action, resource);");
+        store.put(permit.clone()).await.unwrap();
+        store.put(forbid).await.unwrap();
+        store.delete(&id).await.unwrap();
+
+        let versions = store.history(&id).await.unwrap();
+        assert_eq!(
+            versions.iter().map(|v| v.version).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        assert!(versions[2].policy.is_none());
+        assert_eq!(
+            store.get_at(&id, 1).await.unwrap().unwrap().policy,
+            Some(permit.clone())
+        );
+        assert!(store.get_at(&id, 4).await.unwrap().is_none());
+
+        store.rollback(&id, 1).await.unwrap();
+        assert_eq!(store.get(&id).await.unwrap(), Some(permit));
+        assert_eq!(store.history(&id).await.unwrap().len(), 4);
+        assert!(matches!(
+            store.rollback(&id, 7).await,
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_templates() {
+        let store = MemoryPolicyStore::new(CEDAR_SCHEMA.clone());
+        let template = Template::parse(
+            Some(PolicyId::new("owner")),
+            "permit (principal == ?principal, action, resource in ?resource);",
+        )
+        .unwrap();
+        store.put_template(template).await.unwrap();
+        let link = |id: &str, resource: &str| TemplateLink {
+            id: PolicyId::new(id),
+            template_id: PolicyId::new("owner"),
+            slots: BTreeMap::from([
+                (
+                    SlotId::principal(),
+                    EntityUid::from_str(r#"MyApp::User::"0""#).unwrap(),
+                ),
+                (SlotId::resource(), EntityUid::from_str(resource).unwrap()),
+            ]),
+        };
+        store
+            .link(link("owner-0", r#"MyApp::Server::"0""#))
+            .await
+            .unwrap();
+        assert!(matches!(
+            store.link(link("owner-1", r#"MyApp::Missing::"0""#)).await,
+            Err(Error::Validation(_))
+        ));
+        assert!(matches!(
+            store
+                .put(policy("owner-0", "permit (principal, action, resource);"))
+                .await,
+            Err(Error::Conflict(_))
+        ));
+        assert!(matches!(
+            store.delete_template(&PolicyId::new("owner")).await,
+            Err(Error::Conflict(_))
+        ));
+
+        let engine = Engine::new(
+            SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(),
+            PolicySet::new(),
+            Entities::empty(),
+        )
+        .unwrap();
+        load_policies_and_templates(&store, &engine).await.unwrap();
+        let state = engine.state();
+        let linked = state.policies().policy(&PolicyId::new("owner-0")).unwrap();
+        assert_eq!(linked.template_id(), Some(&PolicyId::new("owner")));
+
+        // Reloading only the static policies keeps the engine's templates and links.
+        store
+            .put(policy("p", "permit (principal, action, resource);"))
+            .await
+            .unwrap();
+        load_policies(&store, &engine).await.unwrap();
+        let state = engine.state();
+        assert!(state.policies().policy(&PolicyId::new("p")).is_some());
+        assert!(state.policies().policy(&PolicyId::new("owner-0")).is_some());
+        assert!(state.policies().template(&PolicyId::new("owner")).is_some());
+        store.delete(&PolicyId::new("p")).await.unwrap();
+
+        assert!(store.unlink(&PolicyId::new("owner-0")).await.unwrap());
+        assert!(
+            store
+                .delete_template(&PolicyId::new("owner"))
+                .await
+                .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_load_policies() {
+        let store = MemoryPolicyStore::new(CEDAR_SCHEMA.clone());
+        store
+            .put(policy("p", "permit (principal, action, resource);"))
+            .await
+            .unwrap();
+        let engine = Engine::new(
+            SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(),
+            PolicySet::new(),
+            Entities::empty(),
+        )
+        .unwrap();
+
+        load_policies(&store, &engine).await.unwrap();
+        // The engine's revision is the store's.
+        assert_eq!(engine.revision(), store.sequence().await.unwrap().unwrap());
+        assert!(
+            engine
+                .state()
+                .policies()
+                .policy(&PolicyId::new("p"))
+                .is_some()
+        );
+    }
+}
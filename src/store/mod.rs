@@ -0,0 +1,272 @@
+//! Policy stores: sources of policies that an [`Engine`] can be loaded from.
+//!
+//! Every store validates policies against its schema before accepting them, so a store never
+//! holds policies the engine would reject.
+
+use std::{collections::BTreeMap, future::Future, sync::Arc};
+
+use cedar_policy::{EntityUid, Policy, PolicyId, PolicySet, SlotId, Template};
+use chrono::{DateTime, Utc};
+use tokio::sync::watch;
+
+use crate::{
+    engine::Engine,
+    error::{Error, Result},
+};
+
+mod changes;
+mod directory;
+mod memory;
+#[cfg(feature = "postgres")]
+mod postgres;
+mod prefixed;
+mod refresh;
+mod retry;
+
+pub use changes::{Change, ChangeEvent, ChangeFeed, ChangeSink, RecordingStore, reconstruct};
+pub use directory::{DirectoryPolicyStore, PolicyLocation};
+pub use memory::MemoryPolicyStore;
+#[cfg(feature = "postgres")]
+pub use postgres::{PolicyRecord, PostgresPolicyStore};
+pub use prefixed::PrefixedStore;
+pub use refresh::{RefreshConfig, RefreshHandle, RefreshStatus, Refresher};
+pub use retry::{CircuitBreakerConfig, PoolConfig, ResilientStore, RetryConfig, StoreConfig};
+
+pub trait PolicyStore: Send + Sync + 'static {
+    /// All policies, ordered by ID.
+    fn list(&self) -> impl Future<Output = Result<Vec<Policy>>> + Send;
+
+    fn get(&self, id: &PolicyId) -> impl Future<Output = Result<Option<Policy>>> + Send;
+
+    /// Insert or replace the policy with the ID of `policy`, after validating it.
+    fn put(&self, policy: Policy) -> impl Future<Output = Result<()>> + Send;
+
+    /// Returns whether the policy existed.
+    fn delete(&self, id: &PolicyId) -> impl Future<Output = Result<bool>> + Send;
+
+    /// Insert `policy`, failing with [`Error::Conflict`] if a policy with its ID exists. The
+    /// default checks before writing, so concurrent creations can both succeed; stores with
+    /// conditional writes override it.
+    fn create(&self, policy: Policy) -> impl Future<Output = Result<()>> + Send {
+        async move {
+            if self.get(policy.id()).await?.is_some() {
+                return Err(Error::Conflict(format!(
+                    "Policy `{}` already exists",
+                    policy.id()
+                )));
+            }
+            self.put(policy).await
+        }
+    }
+
+    /// Replace all policies by `policies`. The default deletes and writes one policy at a
+    /// time, so a failure leaves a mix; stores with transactions override it.
+    fn replace_all(&self, policies: Vec<Policy>) -> impl Future<Output = Result<()>> + Send {
+        async move {
+            for existing in self.list().await? {
+                if !policies.iter().any(|policy| policy.id() == existing.id()) {
+                    self.delete(existing.id()).await?;
+                }
+            }
+            for policy in policies {
+                self.put(policy).await?;
+            }
+            Ok(())
+        }
+    }
+
+    /// A revision counter that changes whenever the stored policies change.
+    fn watch(&self) -> watch::Receiver<u64>;
+
+    /// A number that increases with every write to the stored policies and is the same for
+    /// every process using the store, or `None` if the store has none. Loading policies into an
+    /// engine advances its [revision](Engine::revision) to it.
+    fn sequence(&self) -> impl Future<Output = Result<Option<u64>>> + Send {
+        async { Ok(None) }
+    }
+
+    /// Check that the store is reachable, e.g. for a readiness probe.
+    fn ping(&self) -> impl Future<Output = Result<()>> + Send {
+        async { self.list().await.map(|_| ()) }
+    }
+
+    /// All versions of the policy `id`, oldest first, including deletions.
+    fn history(&self, id: &PolicyId) -> impl Future<Output = Result<Vec<PolicyVersion>>> + Send {
+        let _ = id;
+        async { Err(Error::Unsupported("policy history")) }
+    }
+
+    fn get_at(
+        &self,
+        id: &PolicyId,
+        version: u64,
+    ) -> impl Future<Output = Result<Option<PolicyVersion>>> + Send {
+        let _ = (id, version);
+        async { Err(Error::Unsupported("policy history")) }
+    }
+
+    /// Restore `version` of the policy `id` as its newest version. Rolling back to a deletion
+    /// deletes the policy. The restored policy is validated like any other write.
+    fn rollback(&self, id: &PolicyId, version: u64) -> impl Future<Output = Result<()>> + Send {
+        async move {
+            let restored = self.get_at(id, version).await?.ok_or_else(|| {
+                Error::NotFound(format!("Policy `{id}` has no version {version}"))
+            })?;
+            match restored.policy {
+                Some(policy) => self.put(policy).await,
+                None => self.delete(id).await.map(|_| ()),
+            }
+        }
+    }
+}
+
+// Stores are usually shared, e.g. between an engine reloader and an API.
+impl<S: PolicyStore> PolicyStore for Arc<S> {
+    fn list(&self) -> impl Future<Output = Result<Vec<Policy>>> + Send {
+        S::list(self)
+    }
+
+    fn get(&self, id: &PolicyId) -> impl Future<Output = Result<Option<Policy>>> + Send {
+        S::get(self, id)
+    }
+
+    fn put(&self, policy: Policy) -> impl Future<Output = Result<()>> + Send {
+        S::put(self, policy)
+    }
+
+    fn delete(&self, id: &PolicyId) -> impl Future<Output = Result<bool>> + Send {
+        S::delete(self, id)
+    }
+
+    fn create(&self, policy: Policy) -> impl Future<Output = Result<()>> + Send {
+        S::create(self, policy)
+    }
+
+    fn replace_all(&self, policies: Vec<Policy>) -> impl Future<Output = Result<()>> + Send {
+        S::replace_all(self, policies)
+    }
+
+    fn watch(&self) -> watch::Receiver<u64> {
+        S::watch(self)
+    }
+
+    fn sequence(&self) -> impl Future<Output = Result<Option<u64>>> + Send {
+        S::sequence(self)
+    }
+
+    fn ping(&self) -> impl Future<Output = Result<()>> + Send {
+        S::ping(self)
+    }
+
+    fn history(&self, id: &PolicyId) -> impl Future<Output = Result<Vec<PolicyVersion>>> + Send {
+        S::history(self, id)
+    }
+
+    fn get_at(
+        &self,
+        id: &PolicyId,
+        version: u64,
+    ) -> impl Future<Output = Result<Option<PolicyVersion>>> + Send {
+        S::get_at(self, id, version)
+    }
+
+    fn rollback(&self, id: &PolicyId, version: u64) -> impl Future<Output = Result<()>> + Send {
+        S::rollback(self, id, version)
+    }
+}
+
+/// A store that also holds policy templates and template-linked policies. Templates, links and
+/// static policies share one ID namespace.
+pub trait TemplateStore: PolicyStore {
+    /// All templates, ordered by ID.
+    fn templates(&self) -> impl Future<Output = Result<Vec<Template>>> + Send;
+
+    /// Insert or replace a template, after validating it. Existing links stay linked to the
+    /// replaced template and are validated against it.
+    fn put_template(&self, template: Template) -> impl Future<Output = Result<()>> + Send;
+
+    /// Returns whether the template existed. Fails while links to the template exist.
+    fn delete_template(&self, id: &PolicyId) -> impl Future<Output = Result<bool>> + Send;
+
+    /// All template-linked policies, ordered by ID.
+    fn links(&self) -> impl Future<Output = Result<Vec<TemplateLink>>> + Send;
+
+    /// Insert or replace a template-linked policy, after validating it.
+    fn link(&self, link: TemplateLink) -> impl Future<Output = Result<()>> + Send;
+
+    /// Returns whether the link existed.
+    fn unlink(&self, id: &PolicyId) -> impl Future<Output = Result<bool>> + Send;
+}
+
+/// A template-linked policy: the template `template_id` with its slots filled in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateLink {
+    pub id: PolicyId,
+    pub template_id: PolicyId,
+    pub slots: BTreeMap<SlotId, EntityUid>,
+}
+
+impl TemplateLink {
+    /// Link into `policies`, which must contain the template.
+    pub fn link_into(&self, policies: &mut PolicySet) -> Result<()> {
+        policies.link(
+            self.template_id.clone(),
+            self.id.clone(),
+            self.slots.clone().into_iter().collect(),
+        )?;
+        Ok(())
+    }
+}
+
+/// An immutable entry in the history of a policy. `policy` is `None` if the version deleted it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicyVersion {
+    /// Starts at 1 and increases by one with every change.
+    pub version: u64,
+    pub policy: Option<Policy>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Install the policies of `store` into `engine`, replacing its current static policies. A
+/// [`PolicyStore`] only holds static policies, so the engine's templates and template-linked
+/// policies are kept; [`load_policies_and_templates`] replaces them with those of a
+/// [`TemplateStore`].
+pub async fn load_policies(store: &impl PolicyStore, engine: &Engine) -> Result<()> {
+    // Read before the policies, so the revision never claims writes that were not loaded.
+    let sequence = store.sequence().await?;
+    let policies = PolicySet::from_policies(store.list().await?)?;
+    engine.update_policies(|current| with_templates(policies, current))?;
+    engine.advance_revision(sequence);
+    Ok(())
+}
+
+/// `policies` with the templates and template-linked policies of `current` added.
+fn with_templates(mut policies: PolicySet, current: &PolicySet) -> Result<PolicySet> {
+    for template in current.templates() {
+        policies.add_template(template.clone())?;
+    }
+    for policy in current.policies() {
+        if let (Some(template_id), Some(slots)) = (policy.template_id(), policy.template_links()) {
+            policies.link(template_id.clone(), policy.id().clone(), slots)?;
+        }
+    }
+    Ok(policies)
+}
+
+/// Install the policies, templates and template-linked policies of `store` into `engine`.
+pub async fn load_policies_and_templates(
+    store: &impl TemplateStore,
+    engine: &Engine,
+) -> Result<()> {
+    let sequence = store.sequence().await?;
+    let mut policies = PolicySet::from_policies(store.list().await?)?;
+    for template in store.templates().await? {
+        policies.add_template(template)?;
+    }
+    for link in store.links().await? {
+        link.link_into(&mut policies)?;
+    }
+    engine.update_policies(|_| Ok(policies))?;
+    engine.advance_revision(sequence);
+    Ok(())
+}
@@ -0,0 +1,418 @@
+use std::time::Duration;
+
+use cedar_policy::{Policy, PolicyId, PolicySet, Schema};
+use chrono::{DateTime, Utc};
+use sqlx::{
+    PgPool,
+    postgres::{PgListener, PgPoolOptions},
+};
+use tokio::{sync::watch, task::JoinHandle};
+
+use super::{PolicyStore, PolicyVersion, PoolConfig};
+use crate::{
+    engine::validate_policies,
+    error::{Error, Result},
+    format::canonicalize,
+    namespace::id_str,
+};
+
+const CHANNEL: &str = "cedar_policies";
+
+// Versions are assigned and recorded in `cedar_policy_versions` by triggers, so writes by
+// other writers are versioned and signaled as well. A deletion is recorded as a version without
+// content. Concurrent migrations are serialized by the advisory lock.
+const MIGRATION: &str = r#"
+SELECT pg_advisory_xact_lock(hashtext('cedar_policies'));
+CREATE TABLE IF NOT EXISTS cedar_policies (
+    id text PRIMARY KEY,
+    content text NOT NULL,
+    version bigint NOT NULL DEFAULT 1,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    updated_at timestamptz NOT NULL DEFAULT now()
+);
+CREATE TABLE IF NOT EXISTS cedar_policy_versions (
+    id text NOT NULL,
+    version bigint NOT NULL,
+    content text,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (id, version)
+);
+CREATE OR REPLACE FUNCTION cedar_policies_next_version() RETURNS trigger AS $$
+BEGIN
+    NEW.version := COALESCE(
+        (SELECT max(version) FROM cedar_policy_versions WHERE id = NEW.id), 0) + 1;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+CREATE OR REPLACE TRIGGER cedar_policies_next_version
+    BEFORE INSERT ON cedar_policies
+    FOR EACH ROW EXECUTE FUNCTION cedar_policies_next_version();
+CREATE OR REPLACE FUNCTION cedar_policies_notify() RETURNS trigger AS $$
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        INSERT INTO cedar_policy_versions (id, version, content)
+            VALUES (OLD.id, OLD.version + 1, NULL);
+    ELSE
+        INSERT INTO cedar_policy_versions (id, version, content)
+            VALUES (NEW.id, NEW.version, NEW.content);
+    END IF;
+    PERFORM pg_notify('cedar_policies', COALESCE(NEW.id, OLD.id));
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+CREATE OR REPLACE TRIGGER cedar_policies_notify
+    AFTER INSERT OR UPDATE OR DELETE ON cedar_policies
+    FOR EACH ROW EXECUTE FUNCTION cedar_policies_notify();
+"#;
+
+/// A stored policy with its metadata. `version` matches the policy's [`PolicyVersion`].
+#[derive(Debug, Clone)]
+pub struct PolicyRecord {
+    pub policy: Policy,
+    pub version: u64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+type Row = (String, String, i64, DateTime<Utc>, DateTime<Utc>);
+
+impl TryFrom<Row> for PolicyRecord {
+    type Error = Error;
+
+    fn try_from((id, content, version, created_at, updated_at): Row) -> Result<Self> {
+        Ok(Self {
+            policy: Policy::parse(Some(PolicyId::new(id)), content)?,
+            version: version as u64,
+            created_at,
+            updated_at,
+        })
+    }
+}
+
+/// A [`PolicyStore`] backed by the `cedar_policies` table. Policies are stored in
+/// [canonical form](crate::format).
+///
+/// Changes by any writer are signaled through `LISTEN`/`NOTIFY`, so engines loading from the
+/// same database see each other's updates via [`PolicyStore::watch`].
+#[derive(Debug)]
+pub struct PostgresPolicyStore {
+    pool: PgPool,
+    schema: Schema,
+    revision: watch::Sender<u64>,
+    listener: JoinHandle<()>,
+}
+
+impl PostgresPolicyStore {
+    /// Open a pool to the database at `url` and [connect](Self::connect) through it.
+    pub async fn connect_url(url: &str, schema: Schema, config: &PoolConfig) -> Result<Self> {
+        let pool = PgPoolOptions::new()
+            .max_connections(config.max_connections)
+            .min_connections(config.min_connections)
+            .acquire_timeout(config.acquire_timeout)
+            .idle_timeout(config.idle_timeout)
+            .max_lifetime(config.max_lifetime)
+            .connect(url)
+            .await?;
+        Self::connect(pool, schema).await
+    }
+
+    /// Create the table if needed and start listening for changes.
+    pub async fn connect(pool: PgPool, schema: Schema) -> Result<Self> {
+        sqlx::raw_sql(MIGRATION).execute(&pool).await?;
+        let mut listener = PgListener::connect_with(&pool).await?;
+        listener.listen(CHANNEL).await?;
+
+        let revision = watch::Sender::new(0);
+        let sender = revision.clone();
+        let listener = tokio::spawn(async move {
+            loop {
+                match listener.try_recv().await {
+                    Ok(Some(_)) => {}
+                    // The connection was lost and re-established; changes may have been missed.
+                    Ok(None) => {}
+                    Err(_) => {
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                        continue;
+                    }
+                }
+                sender.send_modify(|revision| *revision += 1);
+            }
+        });
+
+        Ok(Self {
+            pool,
+            schema,
+            revision,
+            listener,
+        })
+    }
+
+    pub async fn record(&self, id: &PolicyId) -> Result<Option<PolicyRecord>> {
+        sqlx::query_as::<_, Row>(
+            "SELECT id, content, version, created_at, updated_at FROM cedar_policies WHERE id = $1",
+        )
+        .bind(id_str(id))
+        .fetch_optional(&self.pool)
+        .await?
+        .map(PolicyRecord::try_from)
+        .transpose()
+    }
+
+    /// Write `policy` only if the stored policy is at `expected_version`, or does not exist if
+    /// `expected_version` is `None`. Returns the new version.
+    pub async fn put_versioned(
+        &self,
+        policy: Policy,
+        expected_version: Option<u64>,
+    ) -> Result<u64> {
+        let policy = self.prepare(&policy)?;
+        let id = id_str(policy.id());
+        let version = match expected_version {
+            None => sqlx::query_scalar::<_, i64>(
+                "INSERT INTO cedar_policies (id, content) VALUES ($1, $2)
+                 ON CONFLICT (id) DO NOTHING RETURNING version",
+            )
+            .bind(id)
+            .bind(policy.to_string())
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or_else(|| Error::Conflict(format!("Policy `{id}` already exists")))?,
+            Some(expected) => sqlx::query_scalar::<_, i64>(
+                "UPDATE cedar_policies SET content = $2, version = version + 1, updated_at = now()
+                 WHERE id = $1 AND version = $3 RETURNING version",
+            )
+            .bind(id)
+            .bind(policy.to_string())
+            .bind(expected as i64)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or_else(|| {
+                Error::Conflict(format!("Policy `{id}` is not at version {expected}"))
+            })?,
+        };
+        Ok(version as u64)
+    }
+
+    /// The [canonical form](crate::format) of `policy`, after validating it.
+    fn prepare(&self, policy: &Policy) -> Result<Policy> {
+        let policy = canonicalize(policy)?;
+        validate_policies(&self.schema, &PolicySet::from_policies([policy.clone()])?)?;
+        Ok(policy)
+    }
+}
+
+impl Drop for PostgresPolicyStore {
+    fn drop(&mut self) {
+        self.listener.abort();
+    }
+}
+
+impl PolicyStore for PostgresPolicyStore {
+    async fn list(&self) -> Result<Vec<Policy>> {
+        sqlx::query_as::<_, Row>(
+            "SELECT id, content, version, created_at, updated_at FROM cedar_policies ORDER BY id",
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|row| Ok(PolicyRecord::try_from(row)?.policy))
+        .collect()
+    }
+
+    async fn get(&self, id: &PolicyId) -> Result<Option<Policy>> {
+        Ok(self.record(id).await?.map(|record| record.policy))
+    }
+
+    async fn put(&self, policy: Policy) -> Result<()> {
+        let policy = self.prepare(&policy)?;
+        sqlx::query(
+            "INSERT INTO cedar_policies (id, content) VALUES ($1, $2)
+             ON CONFLICT (id) DO UPDATE SET content = EXCLUDED.content,
+                 version = cedar_policies.version + 1, updated_at = now()",
+        )
+        .bind(id_str(policy.id()))
+        .bind(policy.to_string())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn delete(&self, id: &PolicyId) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM cedar_policies WHERE id = $1")
+            .bind(id_str(id))
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn create(&self, policy: Policy) -> Result<()> {
+        self.put_versioned(policy, None).await.map(|_| ())
+    }
+
+    async fn replace_all(&self, policies: Vec<Policy>) -> Result<()> {
+        let policies = policies
+            .iter()
+            .map(canonicalize)
+            .collect::<Result<Vec<_>>>()?;
+        validate_policies(&self.schema, &PolicySet::from_policies(policies.clone())?)?;
+        let ids = policies
+            .iter()
+            .map(|policy| id_str(policy.id()).to_string())
+            .collect::<Vec<_>>();
+        let mut transaction = self.pool.begin().await?;
+        sqlx::query("DELETE FROM cedar_policies WHERE NOT (id = ANY($1))")
+            .bind(&ids)
+            .execute(&mut *transaction)
+            .await?;
+        for policy in &policies {
+            sqlx::query(
+                "INSERT INTO cedar_policies (id, content) VALUES ($1, $2)
+                 ON CONFLICT (id) DO UPDATE SET content = EXCLUDED.content,
+                     version = cedar_policies.version + 1, updated_at = now()",
+            )
+            .bind(id_str(policy.id()))
+            .bind(policy.to_string())
+            .execute(&mut *transaction)
+            .await?;
+        }
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    fn watch(&self) -> watch::Receiver<u64> {
+        self.revision.subscribe()
+    }
+
+    // Every write appends one version.
+    async fn sequence(&self) -> Result<Option<u64>> {
+        let versions = sqlx::query_scalar::<_, i64>("SELECT count(*) FROM cedar_policy_versions")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(Some(versions as u64))
+    }
+
+    async fn ping(&self) -> Result<()> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn history(&self, id: &PolicyId) -> Result<Vec<PolicyVersion>> {
+        sqlx::query_as::<_, VersionRow>(
+            "SELECT version, content, created_at FROM cedar_policy_versions
+             WHERE id = $1 ORDER BY version",
+        )
+        .bind(id_str(id))
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|row| policy_version(id, row))
+        .collect()
+    }
+
+    async fn get_at(&self, id: &PolicyId, version: u64) -> Result<Option<PolicyVersion>> {
+        sqlx::query_as::<_, VersionRow>(
+            "SELECT version, content, created_at FROM cedar_policy_versions
+             WHERE id = $1 AND version = $2",
+        )
+        .bind(id_str(id))
+        .bind(version as i64)
+        .fetch_optional(&self.pool)
+        .await?
+        .map(|row| policy_version(id, row))
+        .transpose()
+    }
+}
+
+type VersionRow = (i64, Option<String>, DateTime<Utc>);
+
+fn policy_version(
+    id: &PolicyId,
+    (version, content, created_at): VersionRow,
+) -> Result<PolicyVersion> {
+    Ok(PolicyVersion {
+        version: version as u64,
+        policy: content
+            .map(|content| Policy::parse(Some(id.clone()), content).map_err(Error::from))
+            .transpose()?,
+        created_at,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CEDAR_SCHEMA;
+
+    // Runs against the database in `DATABASE_URL`, and is skipped if it is not set.
+    async fn store() -> Option<PostgresPolicyStore> {
+        let url = std::env::var("DATABASE_URL").ok()?;
+        let pool = PgPool::connect(&url).await.unwrap();
+        Some(
+            PostgresPolicyStore::connect(pool, CEDAR_SCHEMA.clone())
+                .await
+                .unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_postgres_store() {
+        let Some(store) = store().await else {
+            return;
+        };
+        let id = format!("test-{}", uuid::Uuid::now_v7());
+        let policy = |src: &str| Policy::parse(Some(PolicyId::new(&id)), src).unwrap();
+        let mut revision = store.watch();
+
+        let v1 = store
+            .put_versioned(policy("permit (principal, action, resource);"), None)
+            .await
+            .unwrap();
+        assert_eq!(v1, 1);
+        assert!(matches!(
+            store
+                .put_versioned(policy("permit (principal, action, resource);"), None)
+                .await,
+            Err(Error::Conflict(_))
+        ));
+        let forbid = policy("forbid (principal, action, resource);");
+        assert_eq!(
+            store.put_versioned(forbid.clone(), Some(v1)).await.unwrap(),
+            2
+        );
+        assert!(matches!(
+            store.put_versioned(forbid.clone(), Some(v1)).await,
+            Err(Error::Conflict(_))
+        ));
+
+        let record = store.record(&PolicyId::new(&id)).await.unwrap().unwrap();
+        assert_eq!(record.version, 2);
+        assert!(record.updated_at >= record.created_at);
+        assert_eq!(
+            record.policy.to_string(),
+            canonicalize(&forbid).unwrap().to_string()
+        );
+
+        tokio::time::timeout(Duration::from_secs(5), revision.changed())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(store.delete(&PolicyId::new(&id)).await.unwrap());
+        assert!(store.get(&PolicyId::new(&id)).await.unwrap().is_none());
+
+        let id = PolicyId::new(&id);
+        let versions = store.history(&id).await.unwrap();
+        assert_eq!(
+            versions.iter().map(|v| v.version).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        assert!(versions[2].policy.is_none());
+        store.rollback(&id, 2).await.unwrap();
+        let record = store.record(&id).await.unwrap().unwrap();
+        assert_eq!(record.version, 4);
+        assert_eq!(
+            record.policy.to_string(),
+            canonicalize(&forbid).unwrap().to_string()
+        );
+        store.delete(&id).await.unwrap();
+    }
+}
@@ -0,0 +1,151 @@
+use cedar_policy::{Policy, PolicyId};
+use tokio::sync::watch;
+
+use super::{PolicyStore, PolicyVersion};
+use crate::{
+    error::Result,
+    namespace::{prefixed, strip_prefix},
+};
+
+/// A view of the policies in `inner` whose IDs start with `prefix/`, with the prefix removed.
+/// Several sources can share one store this way without their IDs colliding.
+///
+/// [`PolicyStore::watch`] signals changes to the whole inner store.
+#[derive(Debug)]
+pub struct PrefixedStore<S> {
+    inner: S,
+    prefix: String,
+}
+
+impl<S: PolicyStore> PrefixedStore<S> {
+    pub fn new(inner: S, prefix: impl Into<String>) -> Self {
+        Self {
+            inner,
+            prefix: prefix.into(),
+        }
+    }
+
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    fn strip(&self, policy: Policy) -> Option<Policy> {
+        strip_prefix(&self.prefix, policy.id()).map(|id| policy.new_id(id))
+    }
+}
+
+impl<S: PolicyStore> PolicyStore for PrefixedStore<S> {
+    async fn list(&self) -> Result<Vec<Policy>> {
+        Ok(self
+            .inner
+            .list()
+            .await?
+            .into_iter()
+            .filter_map(|policy| self.strip(policy))
+            .collect())
+    }
+
+    async fn get(&self, id: &PolicyId) -> Result<Option<Policy>> {
+        let policy = self.inner.get(&prefixed(&self.prefix, id)).await?;
+        Ok(policy.and_then(|policy| self.strip(policy)))
+    }
+
+    async fn put(&self, policy: Policy) -> Result<()> {
+        let id = prefixed(&self.prefix, policy.id());
+        self.inner.put(policy.new_id(id)).await
+    }
+
+    async fn delete(&self, id: &PolicyId) -> Result<bool> {
+        self.inner.delete(&prefixed(&self.prefix, id)).await
+    }
+
+    // `replace_all` is left to the default, as the inner store's would replace other prefixes.
+    async fn create(&self, policy: Policy) -> Result<()> {
+        let id = prefixed(&self.prefix, policy.id());
+        self.inner.create(policy.new_id(id)).await
+    }
+
+    fn watch(&self) -> watch::Receiver<u64> {
+        self.inner.watch()
+    }
+
+    async fn sequence(&self) -> Result<Option<u64>> {
+        self.inner.sequence().await
+    }
+
+    async fn ping(&self) -> Result<()> {
+        self.inner.ping().await
+    }
+
+    async fn history(&self, id: &PolicyId) -> Result<Vec<PolicyVersion>> {
+        let versions = self.inner.history(&prefixed(&self.prefix, id)).await?;
+        Ok(versions
+            .into_iter()
+            .map(|version| self.strip_version(version))
+            .collect())
+    }
+
+    async fn get_at(&self, id: &PolicyId, version: u64) -> Result<Option<PolicyVersion>> {
+        let version = self
+            .inner
+            .get_at(&prefixed(&self.prefix, id), version)
+            .await?;
+        Ok(version.map(|version| self.strip_version(version)))
+    }
+}
+
+impl<S: PolicyStore> PrefixedStore<S> {
+    fn strip_version(&self, version: PolicyVersion) -> PolicyVersion {
+        PolicyVersion {
+            policy: version.policy.and_then(|policy| self.strip(policy)),
+            ..version
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{CEDAR_SCHEMA, store::MemoryPolicyStore};
+
+    #[tokio::test]
+    async fn test_prefixed_views_share_a_store() {
+        let store = Arc::new(MemoryPolicyStore::new(CEDAR_SCHEMA.clone()));
+        let a = PrefixedStore::new(store.clone(), "tenant-a");
+        let b = PrefixedStore::new(store.clone(), "tenant-b");
+        let policy = Policy::parse(
+            Some(PolicyId::new("policy0")),
+            "permit (principal, action, resource);",
+        )
+        .unwrap();
+        a.put(policy.clone()).await.unwrap();
+        b.put(policy).await.unwrap();
+
+        assert_eq!(store.list().await.unwrap().len(), 2);
+        let ids = a
+            .list()
+            .await
+            .unwrap()
+            .iter()
+            .map(|p| p.id().clone())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![PolicyId::new("policy0")]);
+        assert!(
+            store
+                .get(&PolicyId::new("tenant-b/policy0"))
+                .await
+                .unwrap()
+                .is_some()
+        );
+
+        assert!(a.delete(&PolicyId::new("policy0")).await.unwrap());
+        assert!(b.get(&PolicyId::new("policy0")).await.unwrap().is_some());
+        assert_eq!(a.history(&PolicyId::new("policy0")).await.unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,260 @@
+//! Polling a [`PolicyStore`] for stores whose [`PolicyStore::watch`] does not see changes made
+//! by other processes, e.g. several servers sharing one database.
+//!
+//! Polls are jittered so that replicas started together do not hit the store at the same time,
+//! and back off exponentially while refreshes fail. A failed refresh leaves the engine with its
+//! current policies.
+
+use std::{
+    hash::BuildHasher,
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+
+use cedar_policy::{PolicySet, SchemaFragment};
+use chrono::{DateTime, Utc};
+use tokio::task::JoinHandle;
+
+use super::PolicyStore;
+use crate::{
+    engine::Engine,
+    error::Result,
+    fingerprint::{policies_fingerprint, schema_fingerprint},
+};
+
+type SchemaSource = Box<dyn Fn() -> Result<SchemaFragment> + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RefreshConfig {
+    pub interval: Duration,
+    /// Each delay is randomly shortened or lengthened by up to this fraction of it, in `0..=1`.
+    pub jitter: f64,
+    /// The longest delay after consecutive failures. The delay doubles with every failure.
+    pub max_backoff: Duration,
+}
+
+impl Default for RefreshConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(30),
+            jitter: 0.1,
+            max_backoff: Duration::from_secs(300),
+        }
+    }
+}
+
+impl RefreshConfig {
+    /// The delay before the next refresh after `failures` consecutive failures.
+    fn delay(&self, failures: u32) -> Duration {
+        let base = self
+            .interval
+            .saturating_mul(2u32.saturating_pow(failures))
+            .min(self.max_backoff.max(self.interval));
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        // A uniform sample in `-1..=1`, from the randomly keyed std hasher.
+        #[allow(clippy::cast_precision_loss)]
+        let sample = std::collections::hash_map::RandomState::new().hash_one(failures) as f64
+            / u64::MAX as f64
+            * 2.0
+            - 1.0;
+        base.mul_f64(1.0 + jitter * sample)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RefreshStatus {
+    pub last_success: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub consecutive_failures: u32,
+}
+
+pub struct Refresher<S> {
+    store: S,
+    engine: Arc<Engine>,
+    config: RefreshConfig,
+    schema: Option<SchemaSource>,
+}
+
+impl<S: PolicyStore> Refresher<S> {
+    pub fn new(store: S, engine: Arc<Engine>) -> Self {
+        Self {
+            store,
+            engine,
+            config: RefreshConfig::default(),
+            schema: None,
+        }
+    }
+
+    #[must_use]
+    pub fn with_config(mut self, config: RefreshConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Re-read the schema with every refresh as well, e.g. from a file. Without a schema source
+    /// the engine keeps its schema.
+    #[must_use]
+    pub fn with_schema(
+        mut self,
+        schema: impl Fn() -> Result<SchemaFragment> + Send + Sync + 'static,
+    ) -> Self {
+        self.schema = Some(Box::new(schema));
+        self
+    }
+
+    /// Fetch the policies, and the schema if there is a source for it, validate them and install
+    /// them if they differ from the installed ones. Returns whether the engine state was replaced.
+    pub async fn refresh_once(&self) -> Result<bool> {
+        let sequence = self.store.sequence().await?;
+        let policies = PolicySet::from_policies(self.store.list().await?)?;
+        let schema = self.schema.as_ref().map(|schema| schema()).transpose()?;
+        let installed = self.engine.state();
+        let fingerprint = installed.fingerprint();
+        if fingerprint.policies == policies_fingerprint(&policies)
+            && schema
+                .as_ref()
+                .is_none_or(|schema| fingerprint.schema == schema_fingerprint(schema))
+        {
+            self.engine.advance_revision(sequence);
+            return Ok(false);
+        }
+        match schema {
+            Some(schema) => self.engine.replace_policies(schema, policies)?,
+            None => self.engine.update_policies(|_| Ok(policies))?,
+        }
+        self.engine.advance_revision(sequence);
+        Ok(true)
+    }
+
+    /// Run [`Refresher::refresh_once`] on the tokio runtime until the returned handle is dropped.
+    /// The first refresh runs after one jittered interval.
+    pub fn spawn(self) -> RefreshHandle {
+        let status = Arc::new(Mutex::new(RefreshStatus::default()));
+        let task_status = status.clone();
+        let task = tokio::spawn(async move {
+            let mut failures = 0;
+            loop {
+                tokio::time::sleep(self.config.delay(failures)).await;
+                let result = self.refresh_once().await;
+                let mut status = task_status.lock().unwrap_or_else(PoisonError::into_inner);
+                match result {
+                    Ok(_) => {
+                        failures = 0;
+                        status.last_success = Some(Utc::now());
+                        status.last_error = None;
+                    }
+                    Err(e) => {
+                        failures = failures.saturating_add(1);
+                        status.last_error = Some(e.to_string());
+                    }
+                }
+                status.consecutive_failures = failures;
+            }
+        });
+        RefreshHandle { status, task }
+    }
+}
+
+pub struct RefreshHandle {
+    status: Arc<Mutex<RefreshStatus>>,
+    task: JoinHandle<()>,
+}
+
+impl RefreshHandle {
+    pub fn status(&self) -> RefreshStatus {
+        self.status
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// When the engine was last refreshed successfully, for monitoring staleness.
+    pub fn last_success(&self) -> Option<DateTime<Utc>> {
+        self.status().last_success
+    }
+}
+
+impl Drop for RefreshHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        str::FromStr,
+        sync::atomic::{AtomicBool, Ordering},
+    };
+
+    use cedar_policy::{Entities, Policy, PolicyId, Schema};
+
+    use super::*;
+    use crate::{CEDAR_SCHEMA_SRC, error::Error, store::MemoryPolicyStore};
+
+    #[test]
+    fn test_delay() {
+        let config = RefreshConfig {
+            interval: Duration::from_secs(10),
+            jitter: 0.5,
+            max_backoff: Duration::from_secs(40),
+        };
+        for (failures, base) in [(0, 10.0), (1, 20.0), (2, 40.0), (10, 40.0)] {
+            let delay = config.delay(failures).as_secs_f64();
+            assert!((base * 0.5..=base * 1.5).contains(&delay), "{delay}");
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_refresher() {
+        let fragment = SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap();
+        let schema: Schema = fragment.clone().try_into().unwrap();
+        let store = Arc::new(MemoryPolicyStore::new(schema));
+        let engine = Arc::new(Engine::new(fragment, PolicySet::new(), Entities::empty()).unwrap());
+        let fail = Arc::new(AtomicBool::new(false));
+        let task_fail = fail.clone();
+        let handle = Refresher::new(store.clone(), engine.clone())
+            .with_config(RefreshConfig {
+                interval: Duration::from_secs(10),
+                jitter: 0.0,
+                max_backoff: Duration::from_secs(60),
+            })
+            .with_schema(move || {
+                if task_fail.load(Ordering::Relaxed) {
+                    return Err(Error::Mapping("schema unavailable".to_string()));
+                }
+                Ok(SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap())
+            })
+            .spawn();
+        store
+            .put(
+                Policy::parse(
+                    Some(PolicyId::new("all")),
+                    "permit (principal, action, resource);",
+                )
+                .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert!(handle.last_success().is_none());
+
+        tokio::time::sleep(Duration::from_secs(11)).await;
+        assert!(handle.last_success().is_some());
+        assert_eq!(engine.state().policies().policies().count(), 1);
+        assert!(
+            !Refresher::new(store.clone(), engine.clone())
+                .refresh_once()
+                .await
+                .unwrap()
+        );
+
+        fail.store(true, Ordering::Relaxed);
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        let status = handle.status();
+        assert_eq!(status.consecutive_failures, 1);
+        assert!(status.last_error.is_some() && status.last_success.is_some());
+        // The next refresh is backed off to twice the interval.
+        tokio::time::sleep(Duration::from_secs(15)).await;
+        assert_eq!(handle.status().consecutive_failures, 1);
+    }
+}
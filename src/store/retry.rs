@@ -0,0 +1,438 @@
+//! Connection and retry settings shared by the store backends.
+//!
+//! [`ResilientStore`] wraps any [`PolicyStore`] with retries and a circuit breaker, so a
+//! backend only needs to build its client from [`PoolConfig`].
+
+use std::{
+    future::Future,
+    sync::{Mutex, PoisonError},
+    time::Duration,
+};
+
+use cedar_policy::{Policy, PolicyId, Template};
+use tokio::{sync::watch, time::Instant};
+
+use super::{PolicyStore, PolicyVersion, TemplateLink, TemplateStore};
+use crate::error::{Error, Result};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StoreConfig {
+    pub pool: PoolConfig,
+    pub retry: RetryConfig,
+    pub circuit_breaker: CircuitBreakerConfig,
+}
+
+impl StoreConfig {
+    /// Wrap `store` with the retry policy and circuit breaker.
+    pub fn wrap<S: PolicyStore>(&self, store: S) -> ResilientStore<S> {
+        ResilientStore::new(store, self.retry, self.circuit_breaker)
+    }
+}
+
+/// Connection pool settings of backends with a client pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolConfig {
+    pub max_connections: u32,
+    pub min_connections: u32,
+    /// How long a request waits for a connection.
+    pub acquire_timeout: Duration,
+    /// Idle connections above `min_connections` are closed after this long.
+    pub idle_timeout: Option<Duration>,
+    pub max_lifetime: Option<Duration>,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_connections: 10,
+            min_connections: 0,
+            acquire_timeout: Duration::from_secs(30),
+            idle_timeout: Some(Duration::from_secs(600)),
+            max_lifetime: Some(Duration::from_secs(1800)),
+        }
+    }
+}
+
+/// Retries of requests that failed with [`Error::Store`]. Other errors, e.g. validation
+/// failures, are returned immediately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryConfig {
+    /// Attempts per request, including the first. `1` disables retries.
+    pub max_attempts: u32,
+    /// The delay before the first retry. It doubles with every further retry.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(2),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CircuitBreakerConfig {
+    /// Consecutive failed requests, after retries, that open the circuit. `0` disables the
+    /// circuit breaker.
+    pub failure_threshold: u32,
+    /// How long requests fail fast once the circuit is open. Afterwards the circuit is
+    /// half-open: a single request is let through as a probe, without retries, while the others
+    /// keep failing fast. The probe closes the circuit if it succeeds and opens it again if not.
+    pub reset_timeout: Duration,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            reset_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Breaker {
+    failures: u32,
+    open_until: Option<Instant>,
+    /// Whether the probe of the half-open circuit is in flight.
+    probing: bool,
+}
+
+impl Breaker {
+    fn is_open(&self) -> bool {
+        self.open_until
+            .is_some_and(|until| self.probing || Instant::now() < until)
+    }
+}
+
+/// Ends the probe of a half-open circuit, also if the probing request is cancelled.
+struct Probe<'a>(&'a Mutex<Breaker>);
+
+impl Drop for Probe<'_> {
+    fn drop(&mut self) {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .probing = false;
+    }
+}
+
+/// A [`PolicyStore`] that retries transient failures of `inner` and stops calling it for a
+/// while after repeated failures. Reads and writes that leave the same result when repeated,
+/// such as [`PolicyStore::put`], are retried. Writes whose retry could report a different
+/// outcome than the first attempt had, such as [`PolicyStore::create`] or
+/// [`PolicyStore::delete`] after a lost response, are attempted once.
+#[derive(Debug)]
+pub struct ResilientStore<S> {
+    inner: S,
+    retry: RetryConfig,
+    circuit_breaker: CircuitBreakerConfig,
+    breaker: Mutex<Breaker>,
+}
+
+impl<S: PolicyStore> ResilientStore<S> {
+    pub fn new(inner: S, retry: RetryConfig, circuit_breaker: CircuitBreakerConfig) -> Self {
+        Self {
+            inner,
+            retry,
+            circuit_breaker,
+            breaker: Mutex::default(),
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Whether requests currently fail without reaching `inner`.
+    pub fn is_open(&self) -> bool {
+        self.breaker
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .is_open()
+    }
+
+    /// Fails if the circuit is open. Returns the probe if the request is the one let through a
+    /// half-open circuit.
+    fn admit(&self) -> Result<Option<Probe<'_>>> {
+        let mut breaker = self.breaker.lock().unwrap_or_else(PoisonError::into_inner);
+        if breaker.is_open() {
+            return Err(Error::Store("circuit breaker is open".into()));
+        }
+        if breaker.open_until.is_none() {
+            return Ok(None);
+        }
+        breaker.probing = true;
+        Ok(Some(Probe(&self.breaker)))
+    }
+
+    /// Call `f`, retrying transient failures.
+    async fn call<T, F: Future<Output = Result<T>>>(&self, f: impl Fn() -> F) -> Result<T> {
+        self.attempt(self.retry.max_attempts, f).await
+    }
+
+    /// Call `f` once, for writes that are not safe to repeat.
+    async fn call_once<T, F: Future<Output = Result<T>>>(&self, f: impl Fn() -> F) -> Result<T> {
+        self.attempt(1, f).await
+    }
+
+    async fn attempt<T, F: Future<Output = Result<T>>>(
+        &self,
+        max_attempts: u32,
+        f: impl Fn() -> F,
+    ) -> Result<T> {
+        let probe = self.admit()?;
+        let max_attempts = if probe.is_some() { 1 } else { max_attempts };
+        let mut backoff = self.retry.initial_backoff;
+        let mut attempt = 1;
+        let result = loop {
+            match f().await {
+                Err(Error::Store(_)) if attempt < max_attempts => {
+                    tokio::time::sleep(backoff).await;
+                    backoff = backoff.saturating_mul(2).min(self.retry.max_backoff);
+                    attempt += 1;
+                }
+                result => break result,
+            }
+        };
+        let mut breaker = self.breaker.lock().unwrap_or_else(PoisonError::into_inner);
+        if matches!(result, Err(Error::Store(_))) {
+            breaker.failures += 1;
+            let threshold = self.circuit_breaker.failure_threshold;
+            if threshold > 0 && breaker.failures >= threshold {
+                breaker.open_until = Some(Instant::now() + self.circuit_breaker.reset_timeout);
+            }
+        } else {
+            breaker.failures = 0;
+            breaker.open_until = None;
+        }
+        drop(breaker);
+        drop(probe);
+        result
+    }
+}
+
+impl<S: PolicyStore> PolicyStore for ResilientStore<S> {
+    async fn list(&self) -> Result<Vec<Policy>> {
+        self.call(|| self.inner.list()).await
+    }
+
+    async fn get(&self, id: &PolicyId) -> Result<Option<Policy>> {
+        self.call(|| self.inner.get(id)).await
+    }
+
+    async fn put(&self, policy: Policy) -> Result<()> {
+        self.call(|| self.inner.put(policy.clone())).await
+    }
+
+    async fn delete(&self, id: &PolicyId) -> Result<bool> {
+        self.call_once(|| self.inner.delete(id)).await
+    }
+
+    async fn create(&self, policy: Policy) -> Result<()> {
+        self.call_once(|| self.inner.create(policy.clone())).await
+    }
+
+    async fn replace_all(&self, policies: Vec<Policy>) -> Result<()> {
+        self.call(|| self.inner.replace_all(policies.clone())).await
+    }
+
+    fn watch(&self) -> watch::Receiver<u64> {
+        self.inner.watch()
+    }
+
+    async fn sequence(&self) -> Result<Option<u64>> {
+        self.call(|| self.inner.sequence()).await
+    }
+
+    // Readiness reflects the circuit, but pings are neither retried nor probes.
+    async fn ping(&self) -> Result<()> {
+        if self.is_open() {
+            return Err(Error::Store("circuit breaker is open".into()));
+        }
+        self.inner.ping().await
+    }
+
+    async fn history(&self, id: &PolicyId) -> Result<Vec<PolicyVersion>> {
+        self.call(|| self.inner.history(id)).await
+    }
+
+    async fn get_at(&self, id: &PolicyId, version: u64) -> Result<Option<PolicyVersion>> {
+        self.call(|| self.inner.get_at(id, version)).await
+    }
+
+    async fn rollback(&self, id: &PolicyId, version: u64) -> Result<()> {
+        self.call_once(|| self.inner.rollback(id, version)).await
+    }
+}
+
+impl<S: TemplateStore> TemplateStore for ResilientStore<S> {
+    async fn templates(&self) -> Result<Vec<Template>> {
+        self.call(|| self.inner.templates()).await
+    }
+
+    async fn put_template(&self, template: Template) -> Result<()> {
+        self.call(|| self.inner.put_template(template.clone()))
+            .await
+    }
+
+    async fn delete_template(&self, id: &PolicyId) -> Result<bool> {
+        self.call_once(|| self.inner.delete_template(id)).await
+    }
+
+    async fn links(&self) -> Result<Vec<TemplateLink>> {
+        self.call(|| self.inner.links()).await
+    }
+
+    async fn link(&self, link: TemplateLink) -> Result<()> {
+        self.call(|| self.inner.link(link.clone())).await
+    }
+
+    async fn unlink(&self, id: &PolicyId) -> Result<bool> {
+        self.call_once(|| self.inner.unlink(id)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+    use crate::{CEDAR_SCHEMA, store::MemoryPolicyStore};
+
+    /// Fails the first `failures` requests to `list` and `put`, each taking `delay`.
+    struct Flaky {
+        inner: MemoryPolicyStore,
+        failures: AtomicU32,
+        calls: AtomicU32,
+        delay: Duration,
+    }
+
+    impl Flaky {
+        fn new(failures: u32) -> Self {
+            Self {
+                inner: MemoryPolicyStore::new(CEDAR_SCHEMA.clone()),
+                failures: AtomicU32::new(failures),
+                calls: AtomicU32::new(0),
+                delay: Duration::ZERO,
+            }
+        }
+
+        async fn fail(&self) -> Result<()> {
+            self.calls.fetch_add(1, Ordering::Relaxed);
+            tokio::time::sleep(self.delay).await;
+            match self
+                .failures
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+            {
+                Ok(_) => Err(Error::Store("connection reset".into())),
+                Err(_) => Ok(()),
+            }
+        }
+    }
+
+    impl PolicyStore for Flaky {
+        async fn list(&self) -> Result<Vec<Policy>> {
+            self.fail().await?;
+            self.inner.list().await
+        }
+
+        async fn get(&self, id: &PolicyId) -> Result<Option<Policy>> {
+            self.inner.get(id).await
+        }
+
+        async fn put(&self, policy: Policy) -> Result<()> {
+            self.fail().await?;
+            self.inner.put(policy).await
+        }
+
+        async fn delete(&self, id: &PolicyId) -> Result<bool> {
+            self.inner.delete(id).await
+        }
+
+        fn watch(&self) -> watch::Receiver<u64> {
+            self.inner.watch()
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_retries_and_circuit_breaker() {
+        let config = StoreConfig {
+            circuit_breaker: CircuitBreakerConfig {
+                failure_threshold: 1,
+                reset_timeout: Duration::from_secs(10),
+            },
+            ..StoreConfig::default()
+        };
+        let store = config.wrap(Flaky::new(2));
+        assert!(store.list().await.unwrap().is_empty());
+        assert_eq!(store.inner().calls.load(Ordering::Relaxed), 3);
+
+        store.inner().failures.store(3, Ordering::Relaxed);
+        assert!(matches!(store.list().await, Err(Error::Store(_))));
+        assert!(store.is_open());
+        assert!(store.ping().await.is_err());
+        assert!(matches!(store.list().await, Err(Error::Store(_))));
+        assert_eq!(store.inner().calls.load(Ordering::Relaxed), 6);
+
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        assert!(store.list().await.is_ok());
+        assert!(!store.is_open());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_half_open_probe() {
+        let config = CircuitBreakerConfig {
+            failure_threshold: 1,
+            reset_timeout: Duration::from_secs(10),
+        };
+        let store = ResilientStore::new(
+            Flaky {
+                delay: Duration::from_secs(1),
+                ..Flaky::new(4)
+            },
+            RetryConfig::default(),
+            config,
+        );
+        assert!(store.list().await.is_err());
+        assert!(store.is_open());
+
+        // Of concurrent requests, only the probe reaches the store, and it is not retried.
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        let calls = store.inner().calls.load(Ordering::Relaxed);
+        let (probe, other) = tokio::join!(store.list(), async {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+            store.list().await
+        });
+        assert!(probe.is_err() && other.is_err());
+        assert_eq!(store.inner().calls.load(Ordering::Relaxed), calls + 1);
+        assert!(store.is_open());
+
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        assert!(store.list().await.is_ok());
+        assert!(!store.is_open());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_writes_not_safe_to_repeat() {
+        let store = StoreConfig::default().wrap(Flaky::new(1));
+        let policy = Policy::parse(
+            Some(PolicyId::new("p")),
+            "permit (principal, action, resource);",
+        )
+        .unwrap();
+        assert!(matches!(
+            store.create(policy.clone()).await,
+            Err(Error::Store(_))
+        ));
+        assert_eq!(store.inner().calls.load(Ordering::Relaxed), 1);
+
+        store.inner().failures.store(1, Ordering::Relaxed);
+        store.put(policy).await.unwrap();
+        assert_eq!(store.inner().calls.load(Ordering::Relaxed), 3);
+    }
+}
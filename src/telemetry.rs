@@ -0,0 +1,170 @@
+//! Engine metrics through the [`metrics`] facade, so any exporter, e.g.
+//! `metrics-exporter-prometheus`, can publish them. Durations are in seconds.
+
+use std::time::Duration;
+
+use cedar_policy::Decision;
+use metrics::{Unit, counter, describe_counter, describe_histogram, histogram};
+
+/// Decisions, labeled `decision="allow"` or `decision="deny"`.
+pub const DECISIONS: &str = "cedar_decisions_total";
+pub const EVALUATION_SECONDS: &str = "cedar_evaluation_seconds";
+/// Duration of type-aware partial evaluation, as for filters and queries.
+pub const TPE_SECONDS: &str = "cedar_tpe_seconds";
+/// Policies with a nontrivial residual per partial evaluation.
+pub const RESIDUALS: &str = "cedar_tpe_residuals";
+/// Decision cache lookups, labeled `result="hit"` or `result="miss"`.
+pub const CACHE_LOOKUPS: &str = "cedar_decision_cache_lookups_total";
+/// Installed states, labeled with what changed: `part="schema"`, `"policies"` or `"entities"`.
+pub const RELOADS: &str = "cedar_state_reloads_total";
+
+/// Register descriptions of all metrics with the installed recorder.
+pub fn describe() {
+    describe_counter!(DECISIONS, "Authorization decisions");
+    describe_histogram!(
+        EVALUATION_SECONDS,
+        Unit::Seconds,
+        "Duration of authorization decisions"
+    );
+    describe_histogram!(
+        TPE_SECONDS,
+        Unit::Seconds,
+        "Duration of type-aware partial evaluation"
+    );
+    describe_histogram!(
+        RESIDUALS,
+        Unit::Count,
+        "Nontrivial residual policies per partial evaluation"
+    );
+    describe_counter!(CACHE_LOOKUPS, "Decision cache lookups");
+    describe_counter!(RELOADS, "Installed engine states by changed part");
+}
+
+pub(crate) fn decision(decision: Decision, elapsed: Duration) {
+    let decision = match decision {
+        Decision::Allow => "allow",
+        Decision::Deny => "deny",
+    };
+    counter!(DECISIONS, "decision" => decision).increment(1);
+    histogram!(EVALUATION_SECONDS).record(elapsed);
+}
+
+pub(crate) fn tpe(elapsed: Duration, residuals: usize) {
+    histogram!(TPE_SECONDS).record(elapsed);
+    #[allow(clippy::cast_precision_loss)]
+    histogram!(RESIDUALS).record(residuals as f64);
+}
+
+pub(crate) fn cache_lookup(hit: bool) {
+    let result = if hit { "hit" } else { "miss" };
+    counter!(CACHE_LOOKUPS, "result" => result).increment(1);
+}
+
+pub(crate) fn reload(schema: bool, policies: bool, entities: bool) {
+    for (part, changed) in [
+        ("schema", schema),
+        ("policies", policies),
+        ("entities", entities),
+    ] {
+        if changed {
+            counter!(RELOADS, "part" => part).increment(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use cedar_policy::{Context, Entities, EntityUid, PolicySet, Request, SchemaFragment};
+    use metrics_util::{
+        CompositeKey, MetricKind,
+        debugging::{DebugValue, DebuggingRecorder},
+    };
+
+    use super::*;
+    use crate::{CEDAR_SCHEMA_SRC, Engine, pdp::PartialUid};
+
+    #[test]
+    fn test_metrics() {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        metrics::with_local_recorder(&recorder, || {
+            let engine = Engine::new(
+                SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(),
+                PolicySet::from_str("permit (principal, action, resource);").unwrap(),
+                Entities::empty(),
+            )
+            .unwrap();
+            let action = EntityUid::from_str(r#"MyApp::Action::"GetProjectMetadata""#).unwrap();
+            let request = Request::new(
+                EntityUid::from_str(r#"MyApp::User::"0""#).unwrap(),
+                action.clone(),
+                EntityUid::from_str(r#"MyApp::Project::"0""#).unwrap(),
+                Context::empty(),
+                None,
+            )
+            .unwrap();
+            engine.is_authorized(&request);
+            engine
+                .tpe(
+                    PartialUid::unknown("MyApp::User".parse().unwrap()),
+                    action,
+                    PartialUid::unknown("MyApp::Project".parse().unwrap()),
+                    None,
+                )
+                .unwrap();
+            engine.replace_entities(Entities::empty()).unwrap();
+        });
+
+        let metrics = snapshotter.snapshot().into_vec();
+        let value = |kind, name: &str| {
+            metrics
+                .iter()
+                .find(|(key, ..)| key.kind() == kind && key.key().name() == name)
+                .map(|(key, _, _, value)| (key, value))
+        };
+        let (key, decisions) = value(MetricKind::Counter, DECISIONS).unwrap();
+        assert_eq!(decisions, &DebugValue::Counter(1));
+        assert_eq!(
+            key,
+            &CompositeKey::new(
+                MetricKind::Counter,
+                metrics::Key::from_parts(DECISIONS, vec![metrics::Label::new("decision", "allow")])
+            )
+        );
+        assert!(value(MetricKind::Histogram, EVALUATION_SECONDS).is_some());
+        let (_, residuals) = value(MetricKind::Histogram, RESIDUALS).unwrap();
+        assert!(matches!(residuals, DebugValue::Histogram(values) if values.len() == 1));
+        // Reinstalling the same entities changes nothing.
+        assert!(value(MetricKind::Counter, RELOADS).is_none());
+
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        metrics::with_local_recorder(&recorder, || {
+            let engine = Engine::new(
+                SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(),
+                PolicySet::new(),
+                Entities::empty(),
+            )
+            .unwrap();
+            let server = r#"[{ "uid": { "type": "MyApp::Server", "id": "0" }, "attrs": {},
+                "parents": [] }]"#;
+            let entities = Entities::from_json_str(server, None).unwrap();
+            engine.replace_entities(entities).unwrap();
+        });
+        let reloads = snapshotter
+            .snapshot()
+            .into_vec()
+            .into_iter()
+            .filter(|(key, ..)| key.key().name() == RELOADS)
+            .map(|(key, _, _, value)| {
+                (
+                    key.key().labels().next().unwrap().value().to_string(),
+                    value,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(reloads, [("entities".to_string(), DebugValue::Counter(1))]);
+    }
+}
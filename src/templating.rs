@@ -0,0 +1,306 @@
+//! Policy source with typed placeholders, for stamping out similar policies, e.g. one set per
+//! project.
+//!
+//! A [`SourceTemplate`] is Cedar source with `{{name}}` placeholders. Each placeholder is
+//! replaced by the Cedar literal of a [`Var`], so UIDs and strings are quoted and escaped. Inside
+//! a string literal, as in `@id("{{project}}-readers")`, a string or number is inserted without
+//! quotes. Placeholders in comments are left alone. Cedar code that starts with two braces, such
+//! as a record literal at the start of a condition, needs a space between them.
+//!
+//! This is unrelated to Cedar's templates with `?principal` and `?resource` slots, which can
+//! only constrain the scope.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+    str::FromStr,
+};
+
+use cedar_policy::{EntityUid, PolicyId, PolicySet, Schema};
+
+use crate::{
+    builder::Expr,
+    engine::validate_policies,
+    error::{Error, Result},
+};
+
+/// A value for a placeholder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Var {
+    Uid(EntityUid),
+    String(String),
+    Long(i64),
+    Bool(bool),
+    Set(Vec<Var>),
+}
+
+impl Var {
+    fn expr(&self) -> Expr {
+        match self {
+            Self::Uid(uid) => Expr::uid(uid.clone()),
+            Self::String(s) => Expr::string(s),
+            Self::Long(n) => Expr::long(*n),
+            Self::Bool(b) => Expr::bool(*b),
+            Self::Set(elements) => Expr::set(elements.iter().map(Self::expr)),
+        }
+    }
+
+    /// The text inserted within a string literal.
+    fn in_string(&self, name: &str) -> Result<String> {
+        match self {
+            Self::String(s) => {
+                let quoted = Expr::string(s).to_string();
+                Ok(quoted[1..quoted.len() - 1].to_string())
+            }
+            Self::Long(n) => Ok(n.to_string()),
+            _ => Err(Error::SourceTemplate(format!(
+                "`{name}` is used in a string, but is not a string or number"
+            ))),
+        }
+    }
+}
+
+impl From<EntityUid> for Var {
+    fn from(uid: EntityUid) -> Self {
+        Self::Uid(uid)
+    }
+}
+
+impl From<&str> for Var {
+    fn from(s: &str) -> Self {
+        Self::String(s.to_string())
+    }
+}
+
+impl From<String> for Var {
+    fn from(s: String) -> Self {
+        Self::String(s)
+    }
+}
+
+impl From<i64> for Var {
+    fn from(n: i64) -> Self {
+        Self::Long(n)
+    }
+}
+
+impl From<bool> for Var {
+    fn from(b: bool) -> Self {
+        Self::Bool(b)
+    }
+}
+
+impl<T: Into<Var>> From<Vec<T>> for Var {
+    fn from(elements: Vec<T>) -> Self {
+        Self::Set(elements.into_iter().map(Into::into).collect())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Placeholder { name: String, in_string: bool },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceTemplate {
+    parts: Vec<Part>,
+}
+
+impl SourceTemplate {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let src = std::fs::read_to_string(path).map_err(|source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        src.parse().map_err(|e| Error::File {
+            path: path.to_path_buf(),
+            source: Box::new(e),
+        })
+    }
+
+    /// The names of all placeholders.
+    pub fn variables(&self) -> BTreeSet<&str> {
+        self.parts
+            .iter()
+            .filter_map(|part| match part {
+                Part::Placeholder { name, .. } => Some(name.as_str()),
+                Part::Text(_) => None,
+            })
+            .collect()
+    }
+
+    /// The source with all placeholders replaced. `vars` must contain exactly the variables of
+    /// the template.
+    pub fn render(&self, vars: &BTreeMap<String, Var>) -> Result<String> {
+        let variables = self.variables();
+        if let Some(unused) = vars.keys().find(|name| !variables.contains(name.as_str())) {
+            return Err(Error::SourceTemplate(format!(
+                "unknown variable `{unused}`"
+            )));
+        }
+        let mut src = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => src.push_str(text),
+                Part::Placeholder { name, in_string } => {
+                    let var = vars.get(name).ok_or_else(|| {
+                        Error::SourceTemplate(format!("missing variable `{name}`"))
+                    })?;
+                    if *in_string {
+                        src.push_str(&var.in_string(name)?);
+                    } else {
+                        src.push_str(&var.expr().to_string());
+                    }
+                }
+            }
+        }
+        Ok(src)
+    }
+
+    /// The rendered policies, validated against `schema`. A policy's ID is its `@id("...")`
+    /// annotation if present, and `policy<n>` otherwise.
+    pub fn instantiate(&self, vars: &BTreeMap<String, Var>, schema: &Schema) -> Result<PolicySet> {
+        let parsed = self.render(vars)?.parse::<PolicySet>()?;
+        if parsed.templates().next().is_some() {
+            return Err(Error::Unsupported("Cedar templates in source templates"));
+        }
+        let policies =
+            PolicySet::from_policies(parsed.policies().map(
+                |policy| match policy.annotation("id") {
+                    Some(id) => policy.new_id(PolicyId::new(id)),
+                    None => policy.clone(),
+                },
+            ))?;
+        validate_policies(schema, &policies)?;
+        Ok(policies)
+    }
+}
+
+impl FromStr for SourceTemplate {
+    type Err = Error;
+
+    fn from_str(src: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let (mut in_string, mut in_comment) = (false, false);
+        let mut chars = src.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            if !in_comment && src[i..].starts_with("{{") {
+                let end = src[i..].find("}}").ok_or_else(|| {
+                    Error::SourceTemplate(format!("unterminated placeholder at byte {i}"))
+                })?;
+                let name = src[i + 2..i + end].trim();
+                if !is_identifier(name) {
+                    return Err(Error::SourceTemplate(format!(
+                        "invalid placeholder name `{name}`"
+                    )));
+                }
+                parts.push(Part::Text(std::mem::take(&mut text)));
+                parts.push(Part::Placeholder {
+                    name: name.to_string(),
+                    in_string,
+                });
+                while chars.next_if(|(j, _)| *j < i + end + 2).is_some() {}
+                continue;
+            }
+            text.push(c);
+            match c {
+                '\\' if in_string => text.extend(chars.next().map(|(_, c)| c)),
+                '"' if !in_comment => in_string = !in_string,
+                '/' if !in_string && chars.peek().is_some_and(|(_, c)| *c == '/') => {
+                    in_comment = true;
+                }
+                '\n' => in_comment = false,
+                _ => {}
+            }
+        }
+        parts.push(Part::Text(text));
+        parts.retain(|part| part != &Part::Text(String::new()));
+        Ok(Self { parts })
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CEDAR_SCHEMA;
+
+    const TEMPLATE: &str = r#"// Readers of {{project}}, not replaced in comments.
+@id("{{slug}}-readers")
+permit (principal in {{readers}}, action in {{actions}}, resource == {{project}})
+when { {{ motd }} != "" };
+"#;
+
+    fn vars() -> BTreeMap<String, Var> {
+        let uid = |uid: &str| Var::Uid(uid.parse().unwrap());
+        BTreeMap::from([
+            ("slug".to_string(), Var::from("p\"1")),
+            ("project".to_string(), uid(r#"MyApp::Project::"p\"1""#)),
+            ("readers".to_string(), uid(r#"MyApp::Role::"reader""#)),
+            (
+                "actions".to_string(),
+                Var::Set(vec![uid(r#"MyApp::Action::"GetProjectMetadata""#)]),
+            ),
+            ("motd".to_string(), Var::from("hi")),
+        ])
+    }
+
+    #[test]
+    fn test_instantiate() {
+        let template = TEMPLATE.parse::<SourceTemplate>().unwrap();
+        assert_eq!(
+            template.variables(),
+            BTreeSet::from(["actions", "motd", "project", "readers", "slug"])
+        );
+        assert!(
+            template
+                .render(&vars())
+                .unwrap()
+                .starts_with("// Readers of {{project}}, not replaced")
+        );
+        let policies = template.instantiate(&vars(), &CEDAR_SCHEMA).unwrap();
+        let policy = policies.policy(&PolicyId::new("p\"1-readers")).unwrap();
+        assert_eq!(
+            policy.to_string(),
+            r#"@id("p\"1-readers")
+permit (principal in MyApp::Role::"reader", action in [MyApp::Action::"GetProjectMetadata"], resource == MyApp::Project::"p\"1")
+when { "hi" != "" };"#
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        let template = TEMPLATE.parse::<SourceTemplate>().unwrap();
+        let mut vars = vars();
+        vars.insert("slug".to_string(), Var::Bool(true));
+        assert!(matches!(
+            template.render(&vars),
+            Err(Error::SourceTemplate(_))
+        ));
+        vars.remove("slug");
+        assert!(matches!(
+            template.render(&vars),
+            Err(Error::SourceTemplate(_))
+        ));
+        vars.insert("slug".to_string(), Var::from("p1"));
+        vars.insert(
+            "readers".to_string(),
+            Var::Uid(r#"MyApp::Missing::"x""#.parse().unwrap()),
+        );
+        assert!(matches!(
+            template.instantiate(&vars, &CEDAR_SCHEMA),
+            Err(Error::Validation(_))
+        ));
+        assert!("{{ not a name }}".parse::<SourceTemplate>().is_err());
+    }
+}
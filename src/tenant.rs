@@ -0,0 +1,260 @@
+//! One engine per tenant, with a shared schema.
+//!
+//! Every tenant has its own policies and entities in its own [`Engine`]. Requests are always
+//! evaluated by exactly one tenant's engine, so policies and entities of different tenants can
+//! never be combined in one evaluation. [`TenantEngines`] holds every tenant in memory;
+//! [`EngineRegistry`] loads tenants on first use and evicts them again.
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use arc_swap::ArcSwap;
+use cedar_policy::{Entities, PolicySet, Schema, SchemaFragment};
+
+use crate::{
+    engine::{Engine, EngineState},
+    error::{Error, Result},
+};
+
+mod registry;
+
+pub use registry::{EngineRegistry, TenantLoader, TenantMetrics};
+
+#[derive(Debug)]
+pub struct TenantEngines {
+    schema_fragment: ArcSwap<SchemaFragment>,
+    schema: ArcSwap<Schema>,
+    tenants: ArcSwap<BTreeMap<String, Arc<Engine>>>,
+    update_lock: Mutex<()>,
+}
+
+impl TenantEngines {
+    pub fn new(schema_fragment: SchemaFragment) -> Result<Self> {
+        let schema = schema_fragment.clone().try_into()?;
+        Ok(Self {
+            schema_fragment: ArcSwap::from_pointee(schema_fragment),
+            schema: ArcSwap::from_pointee(schema),
+            tenants: ArcSwap::default(),
+            update_lock: Mutex::new(()),
+        })
+    }
+
+    pub fn schema(&self) -> Arc<Schema> {
+        self.schema.load_full()
+    }
+
+    /// The engine of `tenant`. Keep the returned engine only for the duration of a request, so
+    /// that a removed tenant is not used afterwards.
+    pub fn tenant(&self, tenant: &str) -> Result<Arc<Engine>> {
+        self.tenants
+            .load()
+            .get(tenant)
+            .cloned()
+            .ok_or_else(|| Error::NotFound(format!("Tenant `{tenant}`")))
+    }
+
+    /// All tenant keys, sorted.
+    pub fn tenants(&self) -> Vec<String> {
+        self.tenants.load().keys().cloned().collect()
+    }
+
+    /// Create a tenant after validating its policies and entities against the shared schema.
+    pub fn create_tenant(
+        &self,
+        tenant: impl Into<String>,
+        policies: PolicySet,
+        entities: Entities,
+    ) -> Result<Arc<Engine>> {
+        let tenant = tenant.into();
+        let _guard = self
+            .update_lock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let tenants = self.tenants.load();
+        if tenants.contains_key(&tenant) {
+            return Err(Error::Conflict(format!("Tenant `{tenant}` already exists")));
+        }
+        let state = EngineState::with_schema(
+            self.schema_fragment.load_full(),
+            self.schema.load_full(),
+            policies,
+            entities,
+        )?;
+        let engine = Arc::new(Engine::from_state(state));
+        let mut tenants = BTreeMap::clone(&tenants);
+        tenants.insert(tenant, engine.clone());
+        self.tenants.store(Arc::new(tenants));
+        Ok(engine)
+    }
+
+    /// Returns whether the tenant existed.
+    pub fn remove_tenant(&self, tenant: &str) -> bool {
+        let _guard = self
+            .update_lock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let mut tenants = BTreeMap::clone(&self.tenants.load());
+        let removed = tenants.remove(tenant).is_some();
+        self.tenants.store(Arc::new(tenants));
+        removed
+    }
+
+    /// Replace the schema of all tenants. Every tenant is validated against the new schema
+    /// first; if any tenant fails, no tenant is changed. Updates of tenants wait until all
+    /// of them are migrated.
+    pub fn replace_schema(&self, schema_fragment: SchemaFragment) -> Result<()> {
+        let _guard = self
+            .update_lock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let fragment = Arc::new(schema_fragment.clone());
+        let schema = Arc::new(schema_fragment.try_into()?);
+        let tenants = self.tenants.load();
+        let rebuild = |tenant: &str, state: &EngineState| {
+            EngineState::with_schema(
+                fragment.clone(),
+                Arc::clone(&schema),
+                state.policies().clone(),
+                state.entities().clone(),
+            )
+            .map_err(|e| Error::Tenant {
+                tenant: tenant.to_string(),
+                source: Box::new(e),
+            })
+        };
+        // Every tenant's updates are held off from rebuilding to installing, so no tenant
+        // changes after it was validated.
+        let guards = tenants
+            .values()
+            .map(|engine| engine.lock_updates())
+            .collect::<Vec<_>>();
+        let states = tenants
+            .iter()
+            .map(|(tenant, engine)| rebuild(tenant, &engine.state()))
+            .collect::<Result<Vec<_>>>()?;
+        for ((engine, guard), state) in tenants.values().zip(&guards).zip(states) {
+            engine.install(guard, state);
+        }
+        self.schema_fragment.store(fragment);
+        self.schema.store(schema);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use cedar_policy::{Decision, EntityUid, Request};
+
+    use super::*;
+    use crate::{CEDAR_SCHEMA, CEDAR_SCHEMA_SRC};
+
+    fn request(principal: &str) -> Request {
+        Request::new(
+            EntityUid::from_str(principal).unwrap(),
+            EntityUid::from_str(r#"MyApp::Action::"GetProjectMetadata""#).unwrap(),
+            EntityUid::from_str(r#"MyApp::Project::"0""#).unwrap(),
+            cedar_policy::Context::empty(),
+            Some(&CEDAR_SCHEMA),
+        )
+        .unwrap()
+    }
+
+    fn policies(user: &str) -> PolicySet {
+        PolicySet::from_str(&format!(
+            r#"permit (principal == MyApp::User::"{user}", action, resource);"#
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_tenants_are_isolated() {
+        let engines =
+            TenantEngines::new(SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap()).unwrap();
+        engines
+            .create_tenant("a", policies("0"), Entities::empty())
+            .unwrap();
+        engines
+            .create_tenant("b", policies("1"), Entities::empty())
+            .unwrap();
+        assert!(matches!(
+            engines.create_tenant("a", PolicySet::new(), Entities::empty()),
+            Err(Error::Conflict(_))
+        ));
+        assert_eq!(engines.tenants(), vec!["a", "b"]);
+
+        let a = engines.tenant("a").unwrap();
+        assert_eq!(
+            a.is_authorized(&request(r#"MyApp::User::"0""#)).decision(),
+            Decision::Allow
+        );
+        assert_eq!(
+            a.is_authorized(&request(r#"MyApp::User::"1""#)).decision(),
+            Decision::Deny
+        );
+
+        assert!(engines.remove_tenant("b"));
+        assert!(matches!(engines.tenant("b"), Err(Error::NotFound(_))));
+    }
+
+    #[test]
+    fn test_replace_schema_is_all_or_nothing() {
+        let engines =
+            TenantEngines::new(SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap()).unwrap();
+        engines
+            .create_tenant("a", PolicySet::new(), Entities::empty())
+            .unwrap();
+        engines
+            .create_tenant("b", policies("0"), Entities::empty())
+            .unwrap();
+
+        // Tenant `b` refers to `MyApp::User`, which the new schema lacks.
+        let err = engines
+            .replace_schema(SchemaFragment::from_str("namespace MyApp { entity Other; }").unwrap())
+            .unwrap_err();
+        assert!(matches!(err, Error::Tenant { tenant, .. } if tenant == "b"));
+        assert!(
+            engines
+                .schema()
+                .entity_types()
+                .any(|t| t.to_string() == "MyApp::User")
+        );
+    }
+
+    #[test]
+    fn test_replace_schema_with_concurrent_updates() {
+        let create = PolicySet::from_str(
+            r#"@id("create") permit (principal, action == MyApp::Action::"CreateProject", resource);"#,
+        )
+        .unwrap();
+        let create = create.policies().next().unwrap().clone();
+        // Without `CreateProject`, the policy above is invalid.
+        let without_create = CEDAR_SCHEMA_SRC.replace("CreateProject, ", "");
+        for _ in 0..20 {
+            let engines =
+                TenantEngines::new(SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap()).unwrap();
+            for tenant in ["a", "b", "c"] {
+                engines
+                    .create_tenant(tenant, policies("0"), Entities::empty())
+                    .unwrap();
+            }
+            let b = engines.tenant("b").unwrap();
+            std::thread::scope(|scope| {
+                scope.spawn(|| b.add_policy(create.clone()));
+                scope.spawn(|| {
+                    engines.replace_schema(SchemaFragment::from_str(&without_create).unwrap())
+                });
+            });
+            // Either the policy or the new schema was rejected, and all tenants have the same
+            // schema.
+            let schema = engines.schema();
+            for tenant in engines.tenants() {
+                let state = engines.tenant(&tenant).unwrap().state();
+                assert!(std::ptr::eq(state.schema(), &*schema));
+            }
+        }
+    }
+}
@@ -0,0 +1,353 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+use arc_swap::ArcSwap;
+use cedar_policy::{Entities, PolicySet, Schema, SchemaFragment};
+use tokio::sync::OnceCell;
+
+use crate::{
+    engine::{Engine, EngineState},
+    error::{Error, Result},
+};
+
+/// Loads the policies and entities of a tenant when its engine is first needed.
+pub trait TenantLoader: Send + Sync + 'static {
+    fn load(&self, tenant: &str) -> impl Future<Output = Result<(PolicySet, Entities)>> + Send;
+}
+
+impl<F, Fut> TenantLoader for F
+where
+    F: Fn(String) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<(PolicySet, Entities)>> + Send,
+{
+    fn load(&self, tenant: &str) -> impl Future<Output = Result<(PolicySet, Entities)>> + Send {
+        self(tenant.to_string())
+    }
+}
+
+/// Counters of one tenant since it was first loaded.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TenantMetrics {
+    pub lookups: u64,
+    pub loads: u64,
+    pub load_errors: u64,
+    pub evictions: u64,
+    /// Whether the engine is currently loaded.
+    pub loaded: bool,
+}
+
+#[derive(Debug)]
+struct Entry {
+    engine: Arc<OnceCell<Arc<Engine>>>,
+    last_used: Instant,
+    metrics: TenantMetrics,
+}
+
+impl Entry {
+    fn metrics(&self) -> TenantMetrics {
+        TenantMetrics {
+            loaded: self.engine.initialized(),
+            ..self.metrics.clone()
+        }
+    }
+
+    fn evict(&mut self) {
+        self.engine = Arc::default();
+        self.metrics.evictions += 1;
+    }
+
+    /// Drop the engine, and detach a load in progress, so its result is not kept.
+    fn reset(&mut self) -> bool {
+        let loaded = self.engine.initialized();
+        if loaded {
+            self.evict();
+        } else {
+            self.engine = Arc::default();
+        }
+        loaded
+    }
+}
+
+/// Like [`TenantEngines`](super::TenantEngines), but engines are built on first use from what
+/// a [`TenantLoader`] returns and evicted again when the registry is over capacity or a tenant
+/// was idle. A tenant is loaded at most once at a time, even under concurrent lookups.
+#[derive(Debug)]
+pub struct EngineRegistry<L> {
+    schema_fragment: ArcSwap<SchemaFragment>,
+    schema: ArcSwap<Schema>,
+    loader: L,
+    capacity: Option<usize>,
+    tenants: Mutex<HashMap<String, Entry>>,
+}
+
+impl<L: TenantLoader> EngineRegistry<L> {
+    pub fn new(schema_fragment: SchemaFragment, loader: L) -> Result<Self> {
+        let schema = schema_fragment.clone().try_into()?;
+        Ok(Self {
+            schema_fragment: ArcSwap::from_pointee(schema_fragment),
+            schema: ArcSwap::from_pointee(schema),
+            loader,
+            capacity: None,
+            tenants: Mutex::default(),
+        })
+    }
+
+    /// Keep at most `capacity` engines loaded, evicting the least recently used ones.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = Some(capacity);
+        self
+    }
+
+    pub fn schema(&self) -> Arc<Schema> {
+        self.schema.load_full()
+    }
+
+    /// The engine of `tenant`, loading it if needed. Keep the returned engine only for the
+    /// duration of a request, so that an evicted engine is not used afterwards.
+    pub async fn tenant(&self, tenant: &str) -> Result<Arc<Engine>> {
+        let cell = {
+            let mut tenants = self.lock();
+            let entry = tenants.entry(tenant.to_string()).or_insert_with(|| Entry {
+                engine: Arc::default(),
+                last_used: Instant::now(),
+                metrics: TenantMetrics::default(),
+            });
+            entry.last_used = Instant::now();
+            entry.metrics.lookups += 1;
+            entry.engine.clone()
+        };
+        let mut loaded = false;
+        let engine = cell
+            .get_or_try_init(|| async {
+                loaded = true;
+                self.load(tenant).await
+            })
+            .await
+            .cloned();
+
+        let mut tenants = self.lock();
+        // A load whose cell was replaced by an invalidation is only used by this lookup.
+        let current = tenants
+            .get(tenant)
+            .is_some_and(|entry| Arc::ptr_eq(&entry.engine, &cell));
+        let loaded = loaded && current;
+        if let Some(entry) = tenants.get_mut(tenant).filter(|_| current) {
+            match &engine {
+                Ok(_) if loaded => entry.metrics.loads += 1,
+                Ok(_) => {}
+                // Lookups of unknown tenants must not grow the registry.
+                Err(_) if entry.metrics.loads == 0 => {
+                    tenants.remove(tenant);
+                }
+                Err(_) => entry.metrics.load_errors += 1,
+            }
+        }
+        if loaded && engine.is_ok() {
+            self.evict_over_capacity(&mut tenants, tenant);
+        }
+        engine
+    }
+
+    async fn load(&self, tenant: &str) -> Result<Arc<Engine>> {
+        let (policies, entities) = self.loader.load(tenant).await?;
+        let state = EngineState::with_schema(
+            self.schema_fragment.load_full(),
+            self.schema.load_full(),
+            policies,
+            entities,
+        )
+        .map_err(|e| Error::Tenant {
+            tenant: tenant.to_string(),
+            source: Box::new(e),
+        })?;
+        Ok(Arc::new(Engine::from_state(state)))
+    }
+
+    fn evict_over_capacity(&self, tenants: &mut HashMap<String, Entry>, keep: &str) {
+        let Some(capacity) = self.capacity else {
+            return;
+        };
+        let mut loaded = tenants
+            .iter_mut()
+            .filter(|(tenant, entry)| *tenant != keep && entry.engine.initialized())
+            .map(|(_, entry)| entry)
+            .collect::<Vec<_>>();
+        // The kept tenant is loaded as well.
+        let excess = (loaded.len() + 1).saturating_sub(capacity);
+        loaded.sort_by_key(|entry| entry.last_used);
+        for entry in loaded.into_iter().take(excess) {
+            entry.evict();
+        }
+    }
+
+    /// Evict the engines of tenants not looked up for `max_idle`, e.g. from a periodic task.
+    /// Returns the evicted tenants, sorted.
+    pub fn evict_idle(&self, max_idle: Duration) -> Vec<String> {
+        let mut evicted = self
+            .lock()
+            .iter_mut()
+            .filter(|(_, entry)| {
+                entry.engine.initialized() && entry.last_used.elapsed() >= max_idle
+            })
+            .map(|(tenant, entry)| {
+                entry.evict();
+                tenant.clone()
+            })
+            .collect::<Vec<_>>();
+        evicted.sort();
+        evicted
+    }
+
+    /// Drop the engine of `tenant`, so it is loaded again on the next lookup, e.g. after its
+    /// policies changed. A load in progress is not kept either, as it may have read the old
+    /// policies. Returns whether it was loaded.
+    pub fn invalidate(&self, tenant: &str) -> bool {
+        self.lock().get_mut(tenant).is_some_and(Entry::reset)
+    }
+
+    /// Replace the shared schema. Loaded engines, and those being loaded, are dropped and loaded
+    /// again against the new schema on their next lookup.
+    pub fn replace_schema(&self, schema_fragment: SchemaFragment) -> Result<()> {
+        let schema = schema_fragment.clone().try_into()?;
+        let mut tenants = self.lock();
+        self.schema_fragment.store(Arc::new(schema_fragment));
+        self.schema.store(Arc::new(schema));
+        for entry in tenants.values_mut() {
+            entry.reset();
+        }
+        Ok(())
+    }
+
+    pub fn metrics(&self, tenant: &str) -> Option<TenantMetrics> {
+        self.lock().get(tenant).map(Entry::metrics)
+    }
+
+    /// The metrics of every tenant loaded so far.
+    pub fn all_metrics(&self) -> BTreeMap<String, TenantMetrics> {
+        self.lock()
+            .iter()
+            .map(|(tenant, entry)| (tenant.clone(), entry.metrics()))
+            .collect()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, Entry>> {
+        self.tenants.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        str::FromStr,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use tokio::sync::Notify;
+
+    use super::*;
+    use crate::CEDAR_SCHEMA_SRC;
+
+    fn registry(loads: Arc<AtomicUsize>) -> EngineRegistry<impl TenantLoader> {
+        let loader = move |tenant: String| {
+            loads.fetch_add(1, Ordering::SeqCst);
+            async move {
+                if tenant == "missing" {
+                    return Err(Error::NotFound(format!("Tenant `{tenant}`")));
+                }
+                let policies = PolicySet::from_str(&format!(
+                    r#"permit (principal == MyApp::User::"{tenant}", action, resource);"#
+                ))?;
+                Ok((policies, Entities::empty()))
+            }
+        };
+        EngineRegistry::new(SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(), loader).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_lazy_loading_and_eviction() {
+        let loads = Arc::new(AtomicUsize::new(0));
+        let registry = registry(loads.clone()).with_capacity(2);
+        let (a, again) = tokio::join!(registry.tenant("a"), registry.tenant("a"));
+        assert!(Arc::ptr_eq(&a.unwrap(), &again.unwrap()));
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+
+        registry.tenant("b").await.unwrap();
+        registry.tenant("a").await.unwrap();
+        // `b` is the least recently used tenant.
+        registry.tenant("c").await.unwrap();
+        let metrics = registry.all_metrics();
+        assert_eq!(
+            metrics["a"],
+            TenantMetrics {
+                lookups: 3,
+                loads: 1,
+                load_errors: 0,
+                evictions: 0,
+                loaded: true,
+            }
+        );
+        assert!(!metrics["b"].loaded);
+        assert_eq!(metrics["b"].evictions, 1);
+
+        assert!(registry.tenant("missing").await.is_err());
+        assert_eq!(registry.metrics("missing"), None);
+        assert!(registry.invalidate("a"));
+        assert_eq!(registry.evict_idle(Duration::ZERO), ["c"]);
+        registry.tenant("a").await.unwrap();
+        assert_eq!(registry.metrics("a").unwrap().loads, 2);
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_during_load() {
+        let version = Arc::new(Mutex::new("0"));
+        let (started, release) = (Arc::new(Notify::new()), Arc::new(Notify::new()));
+        let loader = {
+            let (version, started, release) = (version.clone(), started.clone(), release.clone());
+            move |_: String| {
+                let (version, started, release) =
+                    (version.clone(), started.clone(), release.clone());
+                async move {
+                    let user = *version.lock().unwrap();
+                    started.notify_one();
+                    release.notified().await;
+                    let policies = PolicySet::from_str(&format!(
+                        r#"permit (principal == MyApp::User::"{user}", action, resource);"#
+                    ))?;
+                    Ok((policies, Entities::empty()))
+                }
+            }
+        };
+        let registry =
+            EngineRegistry::new(SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(), loader)
+                .unwrap();
+        let policy = |engine: &Engine| {
+            engine
+                .state()
+                .policies()
+                .policies()
+                .next()
+                .unwrap()
+                .to_string()
+        };
+
+        let (stale, ()) = tokio::join!(registry.tenant("a"), async {
+            started.notified().await;
+            assert!(!registry.invalidate("a"));
+            *version.lock().unwrap() = "1";
+            release.notify_one();
+        });
+        assert!(policy(&stale.unwrap()).contains(r#"User::"0""#));
+        assert!(!registry.metrics("a").unwrap().loaded);
+
+        let (fresh, ()) = tokio::join!(registry.tenant("a"), async {
+            started.notified().await;
+            release.notify_one();
+        });
+        assert!(policy(&fresh.unwrap()).contains(r#"User::"1""#));
+        assert_eq!(registry.metrics("a").unwrap().loads, 1);
+    }
+}
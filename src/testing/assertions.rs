@@ -0,0 +1,244 @@
+//! [`assert_allow!`](crate::assert_allow) and [`assert_deny!`](crate::assert_deny) for tests
+//! of an [`Engine`].
+//!
+//! Entity UIDs may leave out the namespace when a single namespace of the schema declares their
+//! type, and actions may be given by ID alone, e.g. `"User::\"0\""` and `"GetProjectMetadata"`.
+
+use std::{fmt::Write as _, str::FromStr};
+
+use cedar_policy::{Context, Decision, EntityUid, Request, Schema};
+use itertools::Itertools;
+
+use crate::{
+    engine::Engine,
+    error::{Error, Result},
+    namespace::id_str,
+};
+
+/// Assert that `engine` allows a request given as short strings, with an optional JSON context.
+#[macro_export]
+macro_rules! assert_allow {
+    ($engine:expr, $principal:expr, $action:expr, $resource:expr $(, $context:expr)? $(,)?) => {
+        $crate::assert_decision!(
+            ::cedar_policy::Decision::Allow,
+            $engine, $principal, $action, $resource $(, $context)?
+        )
+    };
+}
+
+/// Assert that `engine` denies a request given as short strings, with an optional JSON context.
+#[macro_export]
+macro_rules! assert_deny {
+    ($engine:expr, $principal:expr, $action:expr, $resource:expr $(, $context:expr)? $(,)?) => {
+        $crate::assert_decision!(
+            ::cedar_policy::Decision::Deny,
+            $engine, $principal, $action, $resource $(, $context)?
+        )
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! assert_decision {
+    ($decision:expr, $engine:expr, $principal:expr, $action:expr, $resource:expr) => {
+        $crate::assert_decision!($decision, $engine, $principal, $action, $resource, None)
+    };
+    ($decision:expr, $engine:expr, $principal:expr, $action:expr, $resource:expr, $context:expr) => {
+        if let Err(message) = $crate::testing::check_decision(
+            &$engine,
+            $decision,
+            $principal,
+            $action,
+            $resource,
+            $context.into(),
+        ) {
+            panic!("{message}");
+        }
+    };
+}
+
+/// Evaluate a request given as short strings and describe how the decision differs from
+/// `expected`, including the determining and erroring policies.
+pub fn check_decision(
+    engine: &Engine,
+    expected: Decision,
+    principal: &str,
+    action: &str,
+    resource: &str,
+    context: Option<serde_json::Value>,
+) -> std::result::Result<(), String> {
+    let state = engine.state();
+    let request = request(state.schema(), principal, action, resource, context)
+        .map_err(|e| format!("invalid request: {e}"))?;
+    let response = engine.is_authorized(&request);
+    if response.decision() == expected {
+        return Ok(());
+    }
+    let name = |decision| match decision {
+        Decision::Allow => "allow",
+        Decision::Deny => "deny",
+    };
+    let mut message = format!(
+        "expected {}, got {}\n  principal: {}\n  action: {}\n  resource: {}\n",
+        name(expected),
+        name(response.decision()),
+        request.principal().expect("the principal is concrete"),
+        request.action().expect("the action is concrete"),
+        request.resource().expect("the resource is concrete"),
+    );
+    let reasons = response
+        .diagnostics()
+        .reason()
+        .map(id_str)
+        .sorted()
+        .collect_vec();
+    let reasons = if reasons.is_empty() {
+        "none".to_string()
+    } else {
+        reasons.join(", ")
+    };
+    let _ = writeln!(message, "  determining policies: {reasons}");
+    for error in response.diagnostics().errors() {
+        let _ = writeln!(message, "  {error}");
+    }
+    Err(message)
+}
+
+fn request(
+    schema: &Schema,
+    principal: &str,
+    action: &str,
+    resource: &str,
+    context: Option<serde_json::Value>,
+) -> Result<Request> {
+    let action = resolve_action(schema, action)?;
+    let context = match context {
+        Some(context) => Context::from_json_value(context, Some((schema, &action)))?,
+        None => Context::empty(),
+    };
+    Ok(Request::new(
+        resolve(schema, principal)?,
+        action,
+        resolve(schema, resource)?,
+        context,
+        Some(schema),
+    )?)
+}
+
+fn resolve_action(schema: &Schema, action: &str) -> Result<EntityUid> {
+    if action.contains("::") {
+        return resolve(schema, action);
+    }
+    let matches = schema
+        .actions()
+        .filter(|uid| AsRef::<str>::as_ref(uid.id()) == action)
+        .collect_vec();
+    match matches[..] {
+        [uid] => Ok(uid.clone()),
+        [] => Err(Error::NotFound(format!("action `{action}`"))),
+        _ => Err(Error::Mapping(format!(
+            "Action `{action}` is declared in several namespaces"
+        ))),
+    }
+}
+
+/// `uid`, or `uid` in the one namespace that declares its type.
+fn resolve(schema: &Schema, uid: &str) -> Result<EntityUid> {
+    let parse = |uid: &str| {
+        EntityUid::from_str(uid)
+            .map_err(|e| Error::Mapping(format!("Invalid entity UID `{uid}`: {e}")))
+    };
+    let declared = |uid: &EntityUid| {
+        schema.entity_types().contains(uid.type_name())
+            || schema
+                .actions()
+                .any(|action| action.type_name() == uid.type_name())
+    };
+    let parsed = parse(uid)?;
+    if declared(&parsed) {
+        return Ok(parsed);
+    }
+    let namespaces = schema
+        .entity_types()
+        .filter_map(|name| name.namespace_components().next().map(|_| name.namespace()))
+        .unique()
+        .collect_vec();
+    match namespaces
+        .iter()
+        .filter_map(|namespace| parse(&format!("{namespace}::{uid}")).ok())
+        .filter(declared)
+        .exactly_one()
+    {
+        Ok(uid) => Ok(uid),
+        // Fails later with the schema's own error.
+        Err(_) => Ok(parsed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cedar_policy::{Entities, PolicySet, SchemaFragment};
+
+    use crate::CEDAR_SCHEMA_SRC;
+
+    use super::*;
+
+    #[test]
+    fn test_assertions() {
+        let engine = Engine::new(
+            SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(),
+            PolicySet::from_str(
+                r#"
+permit (principal == MyApp::User::"0", action, resource);
+forbid (principal, action == MyApp::Action::"DeleteProject", resource);
+"#,
+            )
+            .unwrap(),
+            Entities::empty(),
+        )
+        .unwrap();
+        crate::assert_allow!(
+            engine,
+            "User::\"0\"",
+            "GetProjectMetadata",
+            "Project::\"0\""
+        );
+        crate::assert_deny!(
+            &engine,
+            "User::\"0\"",
+            "DeleteProject",
+            "MyApp::Project::\"0\""
+        );
+        crate::assert_deny!(
+            engine,
+            "User::\"1\"",
+            "GetProjectMetadata",
+            "Project::\"0\"",
+            None
+        );
+
+        let message = check_decision(
+            &engine,
+            Decision::Allow,
+            "User::\"0\"",
+            "DeleteProject",
+            "Project::\"0\"",
+            None,
+        )
+        .unwrap_err();
+        assert_eq!(
+            message,
+            "expected allow, got deny\n  principal: MyApp::User::\"0\"\n  action: MyApp::Action::\"DeleteProject\"\n  resource: MyApp::Project::\"0\"\n  determining policies: policy1\n"
+        );
+        let message = check_decision(
+            &engine,
+            Decision::Allow,
+            "User::\"0\"",
+            "Missing",
+            "Project::\"0\"",
+            None,
+        )
+        .unwrap_err();
+        assert_eq!(message, "invalid request: Not found: action `Missing`");
+    }
+}
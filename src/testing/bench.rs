@@ -0,0 +1,311 @@
+//! A load generator that measures the latency of the engine's APIs on a synthetic workload.
+//!
+//! [`benchmark`] derives entities, policies and a request mix from the schema with a
+//! [`Generator`] driven by a seeded pseudo-random source, so runs with the same
+//! [`BenchConfig`] evaluate the same workload. Concrete requests are authorized; partial
+//! requests, with the principal or the resource ID unknown, are partially evaluated and their
+//! residuals translated to Cedar's JSON format. Latencies are reported as percentiles per API.
+
+use std::{
+    fmt,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use arbitrary::Unstructured;
+use cedar_policy::{EntityUid, PolicySet, Request, SchemaFragment};
+use serde::{Deserialize, Serialize};
+
+use super::generator::{Generator, GeneratorConfig};
+use crate::{
+    engine::Engine,
+    error::{Error, Result},
+    pdp::PartialUid,
+};
+
+/// The workload. Deserializes from JSON, with defaults for missing fields.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BenchConfig {
+    /// Entities per entity type that is not enumerated.
+    pub entities_per_type: usize,
+    pub policies: usize,
+    pub requests: usize,
+    /// The share of requests with the principal or the resource ID unknown, from 0 to 1.
+    pub unknown_ratio: f64,
+    pub seed: u64,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self {
+            entities_per_type: 10,
+            policies: 100,
+            requests: 1000,
+            unknown_ratio: 0.5,
+            seed: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Api {
+    IsAuthorized,
+    Tpe,
+    /// Translating residuals to Cedar's JSON format.
+    Translate,
+}
+
+impl fmt::Display for Api {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Api::IsAuthorized => "is_authorized",
+            Api::Tpe => "tpe",
+            Api::Translate => "translate",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Latency {
+    pub api: Api,
+    pub samples: usize,
+    pub errors: usize,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BenchReport {
+    pub entities: usize,
+    pub policies: usize,
+    /// One entry per API with samples.
+    pub latencies: Vec<Latency>,
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} entities, {} policies", self.entities, self.policies)?;
+        writeln!(
+            f,
+            "{:<14} {:>8} {:>7} {:>10} {:>10} {:>10} {:>10}",
+            "api", "samples", "errors", "p50 µs", "p90 µs", "p99 µs", "max µs"
+        )?;
+        for l in &self.latencies {
+            writeln!(
+                f,
+                "{:<14} {:>8} {:>7} {:>10} {:>10} {:>10} {:>10}",
+                l.api.to_string(),
+                l.samples,
+                l.errors,
+                l.p50.as_micros(),
+                l.p90.as_micros(),
+                l.p99.as_micros(),
+                l.max.as_micros()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Generate the workload of `config` for `schema` and measure it.
+pub fn benchmark(schema: &SchemaFragment, config: &BenchConfig) -> Result<BenchReport> {
+    let generator = Generator::new(
+        schema,
+        GeneratorConfig {
+            entities_per_type: config.entities_per_type,
+            ..GeneratorConfig::default()
+        },
+    )?;
+    let mut random = SplitMix(config.seed);
+    let entities = attempts(&mut random, 1 << 20, |u| generator.arbitrary_entities(u))
+        .ok_or_else(|| Error::Mapping("Failed to generate entities for the schema".to_string()))?;
+    let policies = policies(&generator, &mut random, config.policies)?;
+    let (entity_count, policy_count) = (entities.iter().count(), policies.policies().count());
+    let engine = Engine::new(schema.clone(), policies, entities)?;
+
+    let mut samples = [Api::IsAuthorized, Api::Tpe, Api::Translate].map(|api| (api, vec![], 0));
+    for _ in 0..config.requests {
+        let Some(request) = attempts(&mut random, 1 << 12, |u| generator.arbitrary_request(u))
+        else {
+            return Err(Error::Mapping(
+                "Failed to generate requests for the schema".to_string(),
+            ));
+        };
+        if random.unit() >= config.unknown_ratio {
+            let start = Instant::now();
+            let response = engine.is_authorized(&request);
+            samples[0].1.push(start.elapsed());
+            samples[0].2 += usize::from(response.diagnostics().errors().next().is_some());
+            continue;
+        }
+        let (principal, resource) = partial(&request, random.next().is_multiple_of(2));
+        let action = request
+            .action()
+            .cloned()
+            .expect("generated requests are concrete");
+        let context = request.context().cloned();
+        let start = Instant::now();
+        let residuals = engine.tpe(principal, action, resource, context);
+        samples[1].1.push(start.elapsed());
+        let Ok(residuals) = residuals else {
+            samples[1].2 += 1;
+            continue;
+        };
+        let start = Instant::now();
+        std::hint::black_box(residuals.est());
+        samples[2].1.push(start.elapsed());
+    }
+    Ok(BenchReport {
+        entities: entity_count,
+        policies: policy_count,
+        latencies: samples
+            .into_iter()
+            .filter(|(_, durations, _)| !durations.is_empty())
+            .map(|(api, durations, errors)| latency(api, durations, errors))
+            .collect(),
+    })
+}
+
+/// The principal and resource of `request`, with the principal or else the resource ID unknown.
+fn partial(request: &Request, unknown_principal: bool) -> (PartialUid, PartialUid) {
+    let [principal, resource] = [request.principal(), request.resource()]
+        .map(|uid| uid.cloned().expect("generated requests are concrete"));
+    let unknown = |uid: EntityUid| PartialUid::unknown(uid.type_name().clone());
+    if unknown_principal {
+        (unknown(principal), resource.into())
+    } else {
+        (principal.into(), unknown(resource))
+    }
+}
+
+/// `count` policies for the actions of the schema, each naming a generated principal, resource
+/// or both. With `==` scopes only, they validate for any schema.
+fn policies(generator: &Generator, random: &mut SplitMix, count: usize) -> Result<PolicySet> {
+    let model = &generator.model;
+    let actions = model
+        .actions
+        .iter()
+        .filter(|(_, action)| !action.principals.is_empty() && !action.resources.is_empty())
+        .collect::<Vec<_>>();
+    if actions.is_empty() && count > 0 {
+        return Err(Error::Mapping(
+            "No action of the schema applies to any entity".to_string(),
+        ));
+    }
+    let mut source = String::new();
+    for i in 0..count {
+        let (action, applies) = actions[random.below(actions.len())];
+        let pick = |random: &mut SplitMix, types: &[String]| {
+            let uids = model.uids(&types[random.below(types.len())]);
+            (!uids.is_empty()).then(|| uids[random.below(uids.len())].to_string())
+        };
+        let principal = pick(random, &applies.principals);
+        let resource = pick(random, &applies.resources);
+        let (effect, principal, resource) = match (i % 4, principal, resource) {
+            (0, Some(principal), _) => ("permit", Some(principal), None),
+            (1, _, Some(resource)) => ("permit", None, Some(resource)),
+            (3, Some(principal), Some(resource)) => ("forbid", Some(principal), Some(resource)),
+            (_, principal, resource) => ("permit", principal, resource),
+        };
+        let scope = |var: &str, uid: Option<String>| match uid {
+            Some(uid) => format!("{var} == {uid}"),
+            None => var.to_string(),
+        };
+        source += &format!(
+            "{effect} ({}, action == {action}, {});\n",
+            scope("principal", principal),
+            scope("resource", resource)
+        );
+    }
+    Ok(PolicySet::from_str(&source)?)
+}
+
+fn latency(api: Api, mut durations: Vec<Duration>, errors: usize) -> Latency {
+    durations.sort();
+    // Nearest-rank percentiles.
+    let percentile = |p: usize| durations[(durations.len() * p).div_ceil(100).max(1) - 1];
+    Latency {
+        api,
+        samples: durations.len(),
+        errors,
+        p50: percentile(50),
+        p90: percentile(90),
+        p99: percentile(99),
+        max: durations[durations.len() - 1],
+    }
+}
+
+/// Run `f` on fresh random input of `len` bytes until it yields a value, at most 100 times.
+fn attempts<T>(
+    random: &mut SplitMix,
+    len: usize,
+    f: impl Fn(&mut Unstructured<'_>) -> arbitrary::Result<T>,
+) -> Option<T> {
+    (0..100).find_map(|_| {
+        let data = (0..len.div_ceil(8))
+            .flat_map(|_| random.next().to_le_bytes())
+            .collect::<Vec<_>>();
+        f(&mut Unstructured::new(&data)).ok()
+    })
+}
+
+/// The SplitMix64 generator: fast, seedable and good enough for workloads.
+struct SplitMix(u64);
+
+impl SplitMix {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    /// Uniform in `[0, 1)`.
+    fn unit(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CEDAR_SCHEMA_SRC;
+
+    #[test]
+    fn test_benchmark() {
+        let schema = SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap();
+        let config = serde_json::from_str::<BenchConfig>(
+            r#"{ "entities_per_type": 3, "policies": 20, "requests": 50, "seed": 7 }"#,
+        )
+        .unwrap();
+        let report = benchmark(&schema, &config).unwrap();
+        assert_eq!(report.policies, 20);
+        let apis = report.latencies.iter().map(|l| l.api).collect::<Vec<_>>();
+        assert_eq!(apis, [Api::IsAuthorized, Api::Tpe, Api::Translate]);
+        let samples = report.latencies[..2]
+            .iter()
+            .map(|l| l.samples)
+            .sum::<usize>();
+        assert_eq!(samples, 50);
+        for l in &report.latencies {
+            assert!(l.p50 <= l.p90 && l.p90 <= l.p99 && l.p99 <= l.max);
+        }
+        assert!(report.to_string().contains("is_authorized"));
+
+        // The workload only depends on the config.
+        let again = benchmark(&schema, &config).unwrap();
+        assert_eq!(again.entities, report.entities);
+        let samples = |r: &BenchReport| r.latencies.iter().map(|l| l.samples).collect::<Vec<_>>();
+        assert_eq!(samples(&again), samples(&report));
+        assert!(serde_json::from_str::<BenchConfig>(r#"{ "users": 1 }"#).is_err());
+    }
+}
@@ -0,0 +1,23 @@
+//! Requests shared by the tests of the bindings.
+
+use serde_json::{Value, json};
+
+/// The principal, action and resource of a request the fixture policies allow.
+pub(crate) const PRINCIPAL: &str = r#"MyApp::User::"0""#;
+pub(crate) const ACTION: &str = r#"MyApp::Action::"GetProjectMetadata""#;
+pub(crate) const RESOURCE: &str = r#"MyApp::Project::"0""#;
+
+/// The allowed request in the shape of the HTTP API's `/v1/is_authorized`.
+#[cfg(any(feature = "wasm", feature = "node", feature = "ffi"))]
+pub(crate) fn authorization_call() -> Value {
+    json!({ "principal": PRINCIPAL, "action": ACTION, "resource": RESOURCE })
+}
+
+/// The allowed request with the principal ID unknown, in the shape of the HTTP API's `/v1/tpe`.
+pub(crate) fn tpe_call() -> Value {
+    json!({
+        "principal": { "type": "MyApp::User", "id": null },
+        "action": ACTION,
+        "resource": { "type": "MyApp::Project", "id": "0" },
+    })
+}
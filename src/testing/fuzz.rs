@@ -0,0 +1,254 @@
+//! [`arbitrary`] generation of entities, contexts and requests that are valid for a schema, and
+//! a harness for fuzzing type-aware partial evaluation.
+//!
+//! Valid inputs depend on the schema, so they are built by [`Generator`] methods rather than
+//! `Arbitrary` implementations. Input that does not yield a valid value fails with
+//! [`arbitrary::Error::IncorrectFormat`], which fuzzers skip. A `cargo fuzz` target can be as
+//! small as:
+//!
+//! ```ignore
+//! static HARNESS: LazyLock<TpeHarness> = LazyLock::new(|| TpeHarness::new(..).unwrap());
+//! fuzz_target!(|data: &[u8]| HARNESS.run(data));
+//! ```
+
+use std::sync::Arc;
+
+use arbitrary::{Error as ArbitraryError, Unstructured};
+use cedar_policy::{Context, Entities, EntityUid, PolicySet, Request};
+use serde_json::{Map, Value, json};
+
+use super::{
+    generator::{Generator, GeneratorConfig, Model, Type, extension_values, uid_json},
+    tpe_discrepancies,
+};
+use crate::{
+    engine::{EngineState, validate_policies},
+    error::Result,
+};
+
+/// Entities and a request, generated together.
+#[derive(Debug, Clone)]
+pub struct FuzzCase {
+    pub entities: Entities,
+    pub request: Request,
+}
+
+impl Generator {
+    /// One entity for every ID of every entity type, with attributes and parents that conform
+    /// to the schema.
+    pub fn arbitrary_entities(&self, u: &mut Unstructured<'_>) -> arbitrary::Result<Entities> {
+        let mut entities = Vec::new();
+        for (name, entity_type) in &self.model.entity_types {
+            for (index, id) in entity_type.ids.iter().enumerate() {
+                entities.push(self.model.arbitrary_entity(u, name, index, id)?);
+            }
+        }
+        Entities::from_json_value(Value::Array(entities), Some(&self.schema))
+            .map_err(|_| ArbitraryError::IncorrectFormat)
+    }
+
+    pub fn arbitrary_context(
+        &self,
+        u: &mut Unstructured<'_>,
+        action: &EntityUid,
+    ) -> arbitrary::Result<Context> {
+        let action_model = self
+            .model
+            .actions
+            .get(action)
+            .ok_or(ArbitraryError::IncorrectFormat)?;
+        let context = self.model.arbitrary_value(u, &action_model.context)?;
+        Context::from_json_value(context, Some((&self.schema, action)))
+            .map_err(|_| ArbitraryError::IncorrectFormat)
+    }
+
+    /// A request for any action of the schema, with a principal and resource from the entity
+    /// pool and a valid context.
+    pub fn arbitrary_request(&self, u: &mut Unstructured<'_>) -> arbitrary::Result<Request> {
+        let actions = self
+            .model
+            .actions
+            .iter()
+            .filter(|(_, action)| !action.principals.is_empty() && !action.resources.is_empty())
+            .map(|(uid, _)| uid)
+            .collect::<Vec<_>>();
+        let action = *u.choose(&actions)?;
+        let applies = &self.model.actions[action];
+        let uids = |types: &[String]| {
+            types
+                .iter()
+                .flat_map(|name| self.model.uids(name))
+                .collect::<Vec<_>>()
+        };
+        let principal = u.choose(&uids(&applies.principals))?.clone();
+        let resource = u.choose(&uids(&applies.resources))?.clone();
+        let context = self.arbitrary_context(u, action)?;
+        Request::new(principal, action.clone(), resource, context, None)
+            .map_err(|_| ArbitraryError::IncorrectFormat)
+    }
+
+    pub fn fuzz_case(&self, u: &mut Unstructured<'_>) -> arbitrary::Result<FuzzCase> {
+        Ok(FuzzCase {
+            entities: self.arbitrary_entities(u)?,
+            request: self.arbitrary_request(u)?,
+        })
+    }
+}
+
+impl Model {
+    fn arbitrary_entity(
+        &self,
+        u: &mut Unstructured<'_>,
+        name: &str,
+        index: usize,
+        id: &str,
+    ) -> arbitrary::Result<Value> {
+        let entity_type = &self.entity_types[name];
+        let mut parents = Vec::new();
+        for parent in &entity_type.parents {
+            let Some(ty) = self.entity_types.get(parent) else {
+                continue;
+            };
+            // Parents of the same type have lower IDs, so the hierarchy stays acyclic.
+            let candidates = if parent == name { index } else { ty.ids.len() };
+            for id in &ty.ids[..candidates] {
+                if u.arbitrary()? {
+                    parents.push(uid_json(parent, id)["__entity"].clone());
+                }
+            }
+        }
+        Ok(json!({
+            "uid": uid_json(name, id)["__entity"],
+            "attrs": self.arbitrary_value(u, &entity_type.shape)?,
+            "parents": parents,
+        }))
+    }
+
+    fn arbitrary_value(&self, u: &mut Unstructured<'_>, ty: &Type) -> arbitrary::Result<Value> {
+        Ok(match ty {
+            Type::Bool => Value::from(u.arbitrary::<bool>()?),
+            Type::Long => Value::from(u.arbitrary::<i64>()?),
+            Type::String => Value::from(u.arbitrary::<String>()?),
+            Type::Set(element) => {
+                let len = u.int_in_range(0..=self.config.max_set_len)?;
+                Value::Array(
+                    (0..len)
+                        .map(|_| self.arbitrary_value(u, element))
+                        .collect::<arbitrary::Result<_>>()?,
+                )
+            }
+            Type::Record(attributes) => {
+                let mut record = Map::new();
+                for (name, ty, required) in attributes {
+                    if *required || u.arbitrary()? {
+                        record.insert(name.clone(), self.arbitrary_value(u, ty)?);
+                    }
+                }
+                Value::Object(record)
+            }
+            Type::Entity(name) => {
+                let ids = self
+                    .entity_types
+                    .get(name)
+                    .map(|ty| ty.ids.as_slice())
+                    .unwrap_or_default();
+                match ids {
+                    [] => uid_json(name, "0"),
+                    ids => uid_json(name, u.choose(ids)?),
+                }
+            }
+            Type::Extension(name) => {
+                let (function, args) = extension_values(name);
+                json!({ "__extn": { "fn": function, "arg": u.choose(args)? } })
+            }
+        })
+    }
+}
+
+/// Fuzzing entrypoint that checks type-aware partial evaluation of fixed policies on generated
+/// entities and requests.
+#[derive(Debug, Clone)]
+pub struct TpeHarness {
+    generator: Generator,
+    policies: Arc<PolicySet>,
+}
+
+impl TpeHarness {
+    /// Fails if `policies` are not valid for the schema of `generator`.
+    pub fn new(generator: Generator, policies: PolicySet) -> Result<Self> {
+        validate_policies(&generator.schema, &policies)?;
+        Ok(Self {
+            generator,
+            policies: Arc::new(policies),
+        })
+    }
+
+    /// Like [`TpeHarness::new`], with a generator for `fragment`.
+    pub fn for_schema(
+        fragment: &cedar_policy::SchemaFragment,
+        config: GeneratorConfig,
+        policies: PolicySet,
+    ) -> Result<Self> {
+        Self::new(Generator::new(fragment, config)?, policies)
+    }
+
+    /// Run TPE on a case generated from `data`, with the principal and with the resource
+    /// unknown. Panics if TPE fails or its residuals decide differently than the authorizer.
+    pub fn run(&self, data: &[u8]) {
+        let mut u = Unstructured::new(data);
+        let Ok(case) = self.generator.fuzz_case(&mut u) else {
+            return;
+        };
+        let state = EngineState::prevalidated(
+            self.generator.fragment.clone(),
+            self.generator.schema.clone(),
+            (*self.policies).clone(),
+            case.entities,
+        )
+        .expect("Generated entities are valid for the schema");
+        let discrepancies = tpe_discrepancies(&state, [&case.request]).expect("TPE failed");
+        assert!(
+            discrepancies.is_empty(),
+            "TPE disagrees with the authorizer: {discrepancies:#?}"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use cedar_policy::SchemaFragment;
+
+    use super::*;
+    use crate::CEDAR_SCHEMA_SRC;
+
+    #[test]
+    fn test_tpe_harness() {
+        let harness = TpeHarness::for_schema(
+            &SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(),
+            GeneratorConfig::default(),
+            PolicySet::from_str(
+                r#"
+                permit (principal in MyApp::Role::"0", action, resource in MyApp::Server::"1");
+                permit (principal is MyApp::Role, action, resource) when { principal.project == resource };
+                forbid (principal == MyApp::User::"2", action, resource);
+                "#,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let mut generated = 0;
+        for seed in 0..64u64 {
+            let data = (0..256u64)
+                .map(|i| (seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> (i % 57)) as u8 ^ i as u8)
+                .collect::<Vec<_>>();
+            let generator = &harness.generator;
+            if generator.fuzz_case(&mut Unstructured::new(&data)).is_ok() {
+                generated += 1;
+            }
+            harness.run(&data);
+        }
+        assert!(generated > 0);
+    }
+}
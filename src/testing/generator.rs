@@ -0,0 +1,281 @@
+//! A model of a schema's entity types and actions, from which valid test inputs are derived.
+//!
+//! Entities of every type are drawn from a fixed pool of IDs (`"0"`, `"1"`, ... or the
+//! values of an enumerated type), so entity references in attributes, parents, contexts and
+//! requests all point to entities that are generated. Parents of the same type always have a
+//! lower ID, which keeps such hierarchies acyclic.
+
+use std::{collections::BTreeMap, str::FromStr, sync::Arc};
+
+use cedar_policy::{EntityUid, Schema, SchemaFragment};
+use serde_json::{Value, json};
+
+use crate::error::{Error, Result};
+
+#[derive(Debug, Clone, Copy)]
+pub struct GeneratorConfig {
+    /// IDs per entity type that is not enumerated.
+    pub entities_per_type: usize,
+    pub max_set_len: usize,
+}
+
+impl Default for GeneratorConfig {
+    fn default() -> Self {
+        Self {
+            entities_per_type: 3,
+            max_set_len: 3,
+        }
+    }
+}
+
+/// Derives entities, contexts and requests that are valid for a schema.
+#[derive(Debug, Clone)]
+pub struct Generator {
+    pub(super) model: Arc<Model>,
+    pub(super) fragment: Arc<SchemaFragment>,
+    pub(super) schema: Arc<Schema>,
+}
+
+impl Generator {
+    pub fn new(fragment: &SchemaFragment, config: GeneratorConfig) -> Result<Self> {
+        let schema: Schema = fragment.clone().try_into()?;
+        let model = Model::new(&fragment.clone().to_json_value()?, config)?;
+        Ok(Self {
+            model: Arc::new(model),
+            fragment: Arc::new(fragment.clone()),
+            schema: Arc::new(schema),
+        })
+    }
+
+    pub fn schema_fragment(&self) -> &SchemaFragment {
+        &self.fragment
+    }
+
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(super) enum Type {
+    Bool,
+    Long,
+    String,
+    Set(Box<Type>),
+    Record(Vec<(String, Type, bool)>),
+    Entity(String),
+    Extension(String),
+}
+
+#[derive(Debug)]
+pub(super) struct EntityType {
+    pub(super) ids: Vec<String>,
+    pub(super) parents: Vec<String>,
+    pub(super) shape: Type,
+}
+
+#[derive(Debug)]
+pub(super) struct Action {
+    pub(super) principals: Vec<String>,
+    pub(super) resources: Vec<String>,
+    pub(super) context: Type,
+}
+
+#[derive(Debug)]
+pub(super) struct Model {
+    pub(super) entity_types: BTreeMap<String, EntityType>,
+    pub(super) actions: BTreeMap<EntityUid, Action>,
+    pub(super) config: GeneratorConfig,
+}
+
+/// Qualified type names in the JSON schema format.
+struct Names<'a> {
+    common_types: BTreeMap<String, &'a Value>,
+    entity_types: Vec<String>,
+}
+
+impl Model {
+    fn new(schema: &Value, config: GeneratorConfig) -> Result<Self> {
+        let namespaces = schema
+            .as_object()
+            .ok_or(Error::Unsupported("schema JSON that is not an object"))?;
+        let qualified = |namespace: &str, name: &str| {
+            if namespace.is_empty() {
+                name.to_string()
+            } else {
+                format!("{namespace}::{name}")
+            }
+        };
+        let mut names = Names {
+            common_types: BTreeMap::new(),
+            entity_types: Vec::new(),
+        };
+        for (namespace, definitions) in namespaces {
+            for (name, ty) in object(&definitions["commonTypes"]) {
+                names.common_types.insert(qualified(namespace, name), ty);
+            }
+            for (name, _) in object(&definitions["entityTypes"]) {
+                names.entity_types.push(qualified(namespace, name));
+            }
+        }
+
+        let mut model = Self {
+            entity_types: BTreeMap::new(),
+            actions: BTreeMap::new(),
+            config,
+        };
+        for (namespace, definitions) in namespaces {
+            for (name, definition) in object(&definitions["entityTypes"]) {
+                let ids = match definition["enum"].as_array() {
+                    Some(ids) => ids
+                        .iter()
+                        .filter_map(|id| Some(id.as_str()?.to_string()))
+                        .collect(),
+                    None => (0..config.entities_per_type)
+                        .map(|i| i.to_string())
+                        .collect(),
+                };
+                let parents = strings(&definition["memberOfTypes"])
+                    .map(|parent| names.entity(namespace, parent))
+                    .collect();
+                let shape = match definition.get("shape") {
+                    Some(shape) => names.parse(namespace, shape)?,
+                    None => Type::Record(Vec::new()),
+                };
+                model.entity_types.insert(
+                    qualified(namespace, name),
+                    EntityType {
+                        ids,
+                        parents,
+                        shape,
+                    },
+                );
+            }
+            for (name, definition) in object(&definitions["actions"]) {
+                let applies_to = &definition["appliesTo"];
+                let types = |key| {
+                    strings(&applies_to[key])
+                        .map(|name| names.entity(namespace, name))
+                        .collect()
+                };
+                let context = match applies_to.get("context") {
+                    Some(context) => names.parse(namespace, context)?,
+                    None => Type::Record(Vec::new()),
+                };
+                let uid = EntityUid::from_str(&format!(
+                    "{}::{}",
+                    qualified(namespace, "Action"),
+                    json!(name)
+                ))
+                .map_err(|_| Error::Unsupported("action names that are not valid UIDs"))?;
+                model.actions.insert(
+                    uid,
+                    Action {
+                        principals: types("principalTypes"),
+                        resources: types("resourceTypes"),
+                        context,
+                    },
+                );
+            }
+        }
+        Ok(model)
+    }
+
+    pub(super) fn uids(&self, entity_type: &str) -> Vec<EntityUid> {
+        self.entity_types
+            .get(entity_type)
+            .into_iter()
+            .flat_map(|ty| &ty.ids)
+            .filter_map(|id| EntityUid::from_json(uid_json(entity_type, id)).ok())
+            .collect()
+    }
+}
+
+impl Names<'_> {
+    fn entity(&self, namespace: &str, name: &str) -> String {
+        self.resolve(namespace, name, false)
+            .unwrap_or_else(|| name.to_string())
+    }
+
+    // Unqualified names refer to the namespace of the reference, then to the empty namespace.
+    fn resolve(&self, namespace: &str, name: &str, common: bool) -> Option<String> {
+        let candidates = if name.contains("::") || namespace.is_empty() {
+            vec![name.to_string()]
+        } else {
+            vec![format!("{namespace}::{name}"), name.to_string()]
+        };
+        candidates.into_iter().find(|candidate| {
+            if common {
+                self.common_types.contains_key(candidate)
+            } else {
+                self.entity_types.contains(candidate)
+            }
+        })
+    }
+
+    fn parse(&self, namespace: &str, ty: &Value) -> Result<Type> {
+        let name = || ty["name"].as_str().unwrap_or_default();
+        Ok(match ty["type"].as_str().unwrap_or_default() {
+            "Boolean" => Type::Bool,
+            "Long" => Type::Long,
+            "String" => Type::String,
+            "Set" => Type::Set(Box::new(self.parse(namespace, &ty["element"])?)),
+            "Record" => Type::Record(
+                object(&ty["attributes"])
+                    .map(|(name, attribute)| {
+                        let required = attribute["required"].as_bool().unwrap_or(true);
+                        Ok((name.clone(), self.parse(namespace, attribute)?, required))
+                    })
+                    .collect::<Result<_>>()?,
+            ),
+            "Entity" => Type::Entity(self.entity(namespace, name())),
+            "Extension" => Type::Extension(name().to_string()),
+            "EntityOrCommon" => self.named(namespace, name())?,
+            common => self.named(namespace, common)?,
+        })
+    }
+
+    fn named(&self, namespace: &str, name: &str) -> Result<Type> {
+        if let Some(common) = self.resolve(namespace, name, true) {
+            return self.parse(namespace, self.common_types[&common]);
+        }
+        if let Some(entity) = self.resolve(namespace, name, false) {
+            return Ok(Type::Entity(entity));
+        }
+        Ok(match name.strip_prefix("__cedar::").unwrap_or(name) {
+            "Bool" | "Boolean" => Type::Bool,
+            "Long" => Type::Long,
+            "String" => Type::String,
+            extension @ ("ipaddr" | "decimal" | "datetime" | "duration") => {
+                Type::Extension(extension.to_string())
+            }
+            _ => return Err(Error::Unsupported("schema types that cannot be resolved")),
+        })
+    }
+}
+
+fn object(value: &Value) -> impl Iterator<Item = (&String, &Value)> {
+    value.as_object().into_iter().flatten()
+}
+
+fn strings(value: &Value) -> impl Iterator<Item = &str> {
+    value
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+}
+
+pub(super) fn uid_json(entity_type: &str, id: &str) -> Value {
+    json!({ "__entity": { "type": entity_type, "id": id } })
+}
+
+/// The extension function and some valid arguments for values of extension type `name`.
+pub(super) fn extension_values(name: &str) -> (&'static str, &'static [&'static str]) {
+    match name {
+        "ipaddr" => ("ip", &["10.0.0.1", "192.168.0.0/16", "::1"]),
+        "decimal" => ("decimal", &["0.0", "1.5", "-2.25"]),
+        "datetime" => ("datetime", &["2024-01-01", "2024-06-30T12:00:00Z"]),
+        _ => ("duration", &["1h", "30m", "-1d"]),
+    }
+}
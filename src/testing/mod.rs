@@ -0,0 +1,229 @@
+//! Differential testing of type-aware partial evaluation against the concrete authorizer.
+//!
+//! For a concrete request, TPE is run with the principal or the resource ID unknown, and the
+//! residual policies are re-authorized with the full request. The result must match
+//! [`Authorizer::is_authorized`] on the original policies; a [`Discrepancy`] shows where it does
+//! not, reduced to the smallest set of policies that still disagrees.
+//!
+//! [`assert_allow!`](crate::assert_allow) and [`assert_deny!`](crate::assert_deny) check single
+//! decisions of an engine. With the `proptest` and `fuzz` features, [`Generator`] derives
+//! strategies and `arbitrary` values for valid entities, contexts and requests from a schema,
+//! and [`TpeHarness`] fuzzes TPE with them. With the `scenarios` feature,
+//! [`ScenarioFile`] runs declarative policy tests written in YAML, and [`mutation_test`] shows
+//! which parts of the policies they leave untested. With the `bench` feature, [`benchmark`]
+//! measures the latency of the engine's APIs on a workload generated from a schema.
+
+use cedar_policy::{Authorizer, Decision, EntityUid, PartialEntityUid, PolicySet, Request};
+
+use crate::{
+    engine::{EngineState, tpe},
+    error::Result,
+    namespace::id_str,
+};
+
+mod assertions;
+pub use assertions::check_decision;
+#[cfg(feature = "bench")]
+mod bench;
+#[cfg(feature = "bench")]
+pub use bench::{Api, BenchConfig, BenchReport, Latency, benchmark};
+#[cfg(all(
+    test,
+    any(
+        feature = "wasm",
+        feature = "node",
+        feature = "ffi",
+        feature = "uniffi"
+    )
+))]
+pub(crate) mod fixtures;
+#[cfg(feature = "fuzz")]
+mod fuzz;
+#[cfg(any(feature = "proptest", feature = "fuzz"))]
+mod generator;
+#[cfg(feature = "proptest")]
+mod strategies;
+#[cfg(feature = "fuzz")]
+pub use fuzz::{FuzzCase, TpeHarness};
+#[cfg(any(feature = "proptest", feature = "fuzz"))]
+pub use generator::{Generator, GeneratorConfig};
+#[cfg(feature = "scenarios")]
+mod mutation;
+#[cfg(feature = "scenarios")]
+mod scenarios;
+#[cfg(feature = "scenarios")]
+pub use mutation::{Mutant, Mutation, MutationReport, mutants, mutation_test};
+#[cfg(feature = "scenarios")]
+pub use scenarios::{Scenario, ScenarioFailure, ScenarioFile, ScenarioReport};
+
+/// Which part of the request TPE treats as unknown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unknown {
+    Principal,
+    Resource,
+}
+
+#[derive(Debug, Clone)]
+pub struct Discrepancy {
+    pub request: Request,
+    pub unknown: Unknown,
+    /// The decision of the concrete authorizer on `policies`.
+    pub expected: Decision,
+    /// The decision on the residuals of `policies`.
+    pub actual: Decision,
+    /// A minimal subset of the state's policies that still disagrees: removing any one of them
+    /// makes the decisions agree.
+    pub policies: Vec<String>,
+}
+
+/// Check every request in `requests` with the principal and with the resource unknown, against
+/// the static and template-linked policies of `state`. Requests without a concrete principal,
+/// action, resource or context are skipped.
+pub fn tpe_discrepancies<'a>(
+    state: &EngineState,
+    requests: impl IntoIterator<Item = &'a Request>,
+) -> Result<Vec<Discrepancy>> {
+    let policies = state.tpe_policies();
+    let mut discrepancies = Vec::new();
+    for request in requests {
+        for unknown in [Unknown::Principal, Unknown::Resource] {
+            let Some((expected, actual)) = decisions(state, policies, request, unknown)? else {
+                continue;
+            };
+            if expected != actual {
+                discrepancies.push(Discrepancy {
+                    request: request.clone(),
+                    unknown,
+                    expected,
+                    actual,
+                    policies: minimize(state, policies, request, unknown)?,
+                });
+            }
+        }
+    }
+    Ok(discrepancies)
+}
+
+/// The concrete decision and the decision on the residuals, or `None` if the request is not
+/// concrete.
+fn decisions(
+    state: &EngineState,
+    policies: &PolicySet,
+    request: &Request,
+    unknown: Unknown,
+) -> Result<Option<(Decision, Decision)>> {
+    let (Some(principal), Some(action), Some(resource), Some(context)) = (
+        request.principal(),
+        request.action(),
+        request.resource(),
+        request.context(),
+    ) else {
+        return Ok(None);
+    };
+    let partial = |uid: &EntityUid, is_unknown| {
+        if is_unknown {
+            PartialEntityUid::new(uid.type_name().clone(), None)
+        } else {
+            PartialEntityUid::from_concrete(uid.clone())
+        }
+    };
+    let residuals = tpe(
+        state,
+        policies,
+        partial(principal, unknown == Unknown::Principal),
+        action.clone(),
+        partial(resource, unknown == Unknown::Resource),
+        Some(context.clone()),
+    )?;
+    let authorizer = Authorizer::new();
+    let expected = authorizer
+        .is_authorized(request, policies, state.entities())
+        .decision();
+    let actual = authorizer
+        .is_authorized(request, &residuals.policy_set(), state.entities())
+        .decision();
+    Ok(Some((expected, actual)))
+}
+
+// Drops one policy at a time as long as the decisions still disagree.
+fn minimize(
+    state: &EngineState,
+    policies: &PolicySet,
+    request: &Request,
+    unknown: Unknown,
+) -> Result<Vec<String>> {
+    let mut kept = policies.policies().cloned().collect::<Vec<_>>();
+    let mut i = 0;
+    while i < kept.len() {
+        let mut candidate = kept.clone();
+        candidate.remove(i);
+        let subset = PolicySet::from_policies(candidate.iter().cloned())?;
+        match decisions(state, &subset, request, unknown)? {
+            Some((expected, actual)) if expected != actual => kept = candidate,
+            _ => i += 1,
+        }
+    }
+    let mut ids = kept
+        .iter()
+        .map(|policy| id_str(policy.id()).to_string())
+        .collect::<Vec<_>>();
+    ids.sort();
+    Ok(ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use cedar_policy::{Entities, SchemaFragment};
+
+    use super::*;
+    use crate::{CEDAR_SCHEMA, CEDAR_SCHEMA_SRC, analysis::synthetic_requests};
+
+    #[test]
+    fn test_tpe_agrees_with_authorizer() {
+        let entities = Entities::from_json_str(
+            r#"[
+                { "uid": { "type": "MyApp::Server", "id": "0" }, "attrs": {}, "parents": [] },
+                {
+                    "uid": { "type": "MyApp::Project", "id": "0" },
+                    "attrs": {},
+                    "parents": [{ "type": "MyApp::Server", "id": "0" }]
+                },
+                { "uid": { "type": "MyApp::Project", "id": "1" }, "attrs": {}, "parents": [] },
+                {
+                    "uid": { "type": "MyApp::User", "id": "0" },
+                    "attrs": {},
+                    "parents": [{ "type": "MyApp::Role", "id": "admin" }]
+                },
+                { "uid": { "type": "MyApp::User", "id": "1" }, "attrs": {}, "parents": [] },
+                {
+                    "uid": { "type": "MyApp::Role", "id": "admin" },
+                    "attrs": { "project": { "__entity": { "type": "MyApp::Project", "id": "0" } } },
+                    "parents": []
+                }
+            ]"#,
+            Some(&CEDAR_SCHEMA),
+        )
+        .unwrap();
+        let state = EngineState::new(
+            SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(),
+            PolicySet::from_str(
+                r#"
+                permit (principal in MyApp::Role::"admin", action, resource in MyApp::Server::"0");
+                permit (principal is MyApp::Role, action, resource) when { principal.project == resource };
+                permit (principal == MyApp::User::"1", action, resource == MyApp::Project::"1");
+                forbid (principal, action == MyApp::Action::"DeleteProject", resource)
+                    unless { principal in MyApp::Role::"admin" };
+                "#,
+            )
+            .unwrap(),
+            entities,
+        )
+        .unwrap();
+        let requests = synthetic_requests(&state);
+        assert!(!requests.is_empty());
+        let discrepancies = tpe_discrepancies(&state, &requests).unwrap();
+        assert!(discrepancies.is_empty(), "{discrepancies:#?}");
+    }
+}
@@ -0,0 +1,217 @@
+//! Mutation testing of a policy set against its scenarios.
+//!
+//! Each [`Mutant`] changes one static policy in one way: its effect is flipped, one of its scope
+//! constraints is dropped, or one of its conditions is dropped. Scenarios that still pass on a
+//! mutant do not pin down the mutated part of the policy, so surviving mutants point at gaps in
+//! the tests. Mutants that fail validation against the schema could never be installed and are
+//! only counted.
+
+use std::fmt;
+
+use cedar_policy::{Effect, Policy, PolicySet, PolicySetError};
+use serde_json::json;
+
+use super::scenarios::ScenarioFile;
+use crate::{
+    engine::{EngineState, validate_policies},
+    error::{Error, Result},
+    namespace::id_str,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mutation {
+    FlipEffect,
+    WidenPrincipal,
+    WidenAction,
+    WidenResource,
+    /// Drop the condition at this index, counting `when` and `unless` clauses in order.
+    DropCondition(usize),
+}
+
+impl fmt::Display for Mutation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::FlipEffect => f.write_str("flip effect"),
+            Self::WidenPrincipal => f.write_str("widen principal scope"),
+            Self::WidenAction => f.write_str("widen action scope"),
+            Self::WidenResource => f.write_str("widen resource scope"),
+            Self::DropCondition(index) => write!(f, "drop condition {index}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Mutant {
+    pub policy: String,
+    pub mutation: Mutation,
+    /// The whole policy set with the mutated policy in place of the original.
+    pub policies: PolicySet,
+}
+
+#[derive(Debug, Default)]
+pub struct MutationReport {
+    pub killed: usize,
+    /// Mutants rejected by schema validation.
+    pub invalid: usize,
+    pub survivors: Vec<Mutant>,
+}
+
+impl fmt::Display for MutationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for survivor in &self.survivors {
+            writeln!(
+                f,
+                "survived: {} on `{}`",
+                survivor.mutation, survivor.policy
+            )?;
+        }
+        write!(
+            f,
+            "{} killed, {} survived, {} invalid",
+            self.killed,
+            self.survivors.len(),
+            self.invalid
+        )
+    }
+}
+
+/// All mutants of the static policies in `policies`. Templates and template links are not
+/// mutated.
+pub fn mutants(policies: &PolicySet) -> Result<Vec<Mutant>> {
+    let mut mutants = Vec::new();
+    for policy in policies.policies().filter(|p| p.template_id().is_none()) {
+        let json = policy.to_json().map_err(PolicySetError::from)?;
+        let mut mutations = vec![Mutation::FlipEffect];
+        for (key, mutation) in [
+            ("principal", Mutation::WidenPrincipal),
+            ("action", Mutation::WidenAction),
+            ("resource", Mutation::WidenResource),
+        ] {
+            if json[key]["op"] != "All" {
+                mutations.push(mutation);
+            }
+        }
+        let conditions = json["conditions"].as_array().map_or(0, Vec::len);
+        mutations.extend((0..conditions).map(Mutation::DropCondition));
+
+        for mutation in mutations {
+            let mut mutated = json.clone();
+            match mutation {
+                Mutation::FlipEffect => {
+                    mutated["effect"] = match policy.effect() {
+                        Effect::Permit => "forbid",
+                        Effect::Forbid => "permit",
+                    }
+                    .into();
+                }
+                Mutation::WidenPrincipal => mutated["principal"] = json!({"op": "All"}),
+                Mutation::WidenAction => mutated["action"] = json!({"op": "All"}),
+                Mutation::WidenResource => mutated["resource"] = json!({"op": "All"}),
+                Mutation::DropCondition(index) => {
+                    if let Some(conditions) = mutated["conditions"].as_array_mut() {
+                        conditions.remove(index);
+                    }
+                }
+            }
+            let mutated = Policy::from_json(Some(policy.id().clone()), mutated)
+                .map_err(PolicySetError::from)?;
+            let mut set = policies.clone();
+            set.remove_static(policy.id().clone())?;
+            set.add(mutated)?;
+            mutants.push(Mutant {
+                policy: id_str(policy.id()).to_string(),
+                mutation,
+                policies: set,
+            });
+        }
+    }
+    Ok(mutants)
+}
+
+/// Run `scenarios` against every mutant of the policies of `state`. Fails if the scenarios do
+/// not pass on the unmutated policies, as every mutant would count as killed.
+pub fn mutation_test(state: &EngineState, scenarios: &ScenarioFile) -> Result<MutationReport> {
+    let baseline = scenarios.run(state);
+    if !baseline.is_success() {
+        return Err(Error::Conflict(format!(
+            "scenarios fail on the unmutated policies:\n{baseline}"
+        )));
+    }
+    let mut report = MutationReport::default();
+    for mutant in mutants(state.policies())? {
+        if validate_policies(state.schema(), &mutant.policies).is_err() {
+            report.invalid += 1;
+        } else if scenarios
+            .run_policies(state.schema(), &mutant.policies)
+            .is_success()
+        {
+            report.survivors.push(mutant);
+        } else {
+            report.killed += 1;
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use cedar_policy::{Entities, SchemaFragment};
+
+    use super::*;
+    use crate::CEDAR_SCHEMA_SRC;
+
+    #[test]
+    fn test_mutation_test() {
+        let state = EngineState::new(
+            SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(),
+            PolicySet::from_str(
+                r#"
+permit (principal in MyApp::Role::"admin", action == MyApp::Action::"DeleteProject", resource)
+when { resource in MyApp::Server::"s" };
+"#,
+            )
+            .unwrap(),
+            Entities::empty(),
+        )
+        .unwrap();
+        let scenarios = ScenarioFile::from_yaml(
+            r#"
+entities:
+  - uid: { type: MyApp::User, id: alice }
+    parents: [{ type: MyApp::Role, id: admin }]
+  - uid: { type: MyApp::Role, id: admin }
+    attrs: { project: { type: MyApp::Project, id: "0" } }
+  - uid: { type: MyApp::Project, id: "0" }
+    parents: [{ type: MyApp::Server, id: s }]
+scenarios:
+  - name: admins can delete projects
+    principal: 'MyApp::User::"alice"'
+    action: 'MyApp::Action::"DeleteProject"'
+    resource: 'MyApp::Project::"0"'
+    decision: allow
+  - name: others cannot
+    principal: 'MyApp::User::"bob"'
+    action: 'MyApp::Action::"DeleteProject"'
+    resource: 'MyApp::Project::"0"'
+    decision: deny
+"#,
+        )
+        .unwrap();
+        let report = mutation_test(&state, &scenarios).unwrap();
+        let survivors = report
+            .survivors
+            .iter()
+            .map(|m| (m.policy.as_str(), m.mutation))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            survivors,
+            vec![
+                ("policy0", Mutation::WidenAction),
+                ("policy0", Mutation::DropCondition(0))
+            ]
+        );
+        assert_eq!(report.killed, 2);
+    }
+}
@@ -0,0 +1,356 @@
+//! Declarative policy tests in YAML, for policy authors who do not write Rust.
+//!
+//! A scenario file lists entities shared by all scenarios and the scenarios themselves. Each
+//! scenario is a request with the expected decision and, optionally, the exact set of policies
+//! expected to determine it. Scenarios are evaluated against the policies and schema of an
+//! [`EngineState`] with only the entities of the file, never those of the state:
+//!
+//! ```yaml
+//! entities:
+//!   - uid: { type: MyApp::User, id: alice }
+//!     parents: [{ type: MyApp::Role, id: admin }]
+//! scenarios:
+//!   - name: admins can delete projects
+//!     principal: 'MyApp::User::"alice"'
+//!     action: { type: MyApp::Action, id: DeleteProject }
+//!     resource: { type: MyApp::Project, id: "0" }
+//!     decision: allow
+//!     policies: [admins]
+//! ```
+//!
+//! Entity UIDs are written either in Cedar syntax or as `{ type, id }`. Entities of a scenario
+//! replace file entities with the same UID, and `attrs` and `parents` may be omitted.
+
+use std::{collections::BTreeSet, fmt, path::Path, str::FromStr};
+
+use cedar_policy::{
+    Authorizer, Context, Decision, Entities, EntityUid, PolicySet, Request, Schema,
+};
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::{
+    engine::EngineState,
+    error::{Error, Result},
+    namespace::id_str,
+};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScenarioFile {
+    #[serde(default)]
+    pub entities: Vec<Value>,
+    pub scenarios: Vec<Scenario>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    pub name: String,
+    pub principal: Value,
+    pub action: Value,
+    pub resource: Value,
+    #[serde(default)]
+    pub context: Option<Value>,
+    #[serde(default)]
+    pub entities: Vec<Value>,
+    #[serde(with = "decision")]
+    pub decision: Decision,
+    /// IDs of the policies expected to determine the decision. Not checked if absent.
+    #[serde(default)]
+    pub policies: Option<BTreeSet<String>>,
+}
+
+#[derive(Debug)]
+pub enum ScenarioFailure {
+    /// The scenario does not form a valid request for the schema.
+    Invalid { scenario: String, error: Error },
+    Mismatch {
+        scenario: String,
+        expected: Decision,
+        actual: Decision,
+        /// Expected policies that did not determine the decision.
+        missing: Vec<String>,
+        /// Policies that determined the decision without being expected to.
+        unexpected: Vec<String>,
+        /// Evaluation errors, which often explain an unexpected decision.
+        errors: Vec<String>,
+    },
+}
+
+impl fmt::Display for ScenarioFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Invalid { scenario, error } => write!(f, "scenario `{scenario}`: {error}"),
+            Self::Mismatch {
+                scenario,
+                expected,
+                actual,
+                missing,
+                unexpected,
+                errors,
+            } => {
+                writeln!(f, "scenario `{scenario}`:")?;
+                if expected == actual {
+                    writeln!(f, "  decision: {}", decision_str(*actual))?;
+                } else {
+                    writeln!(
+                        f,
+                        "  decision: expected {}, got {}",
+                        decision_str(*expected),
+                        decision_str(*actual)
+                    )?;
+                }
+                for id in missing {
+                    writeln!(f, "  - {id} (expected to determine the decision)")?;
+                }
+                for id in unexpected {
+                    writeln!(f, "  + {id} (determined the decision)")?;
+                }
+                for error in errors {
+                    writeln!(f, "  error: {error}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ScenarioReport {
+    pub passed: usize,
+    pub failures: Vec<ScenarioFailure>,
+}
+
+impl ScenarioReport {
+    pub fn is_success(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+impl fmt::Display for ScenarioReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for failure in &self.failures {
+            writeln!(f, "{failure}")?;
+        }
+        write!(f, "{} passed, {} failed", self.passed, self.failures.len())
+    }
+}
+
+impl ScenarioFile {
+    pub fn from_yaml(src: &str) -> Result<Self> {
+        serde_yaml::from_str(src).map_err(|e| Error::Scenario(e.to_string()))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let src = std::fs::read_to_string(path).map_err(|source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Self::from_yaml(&src).map_err(|e| Error::File {
+            path: path.to_path_buf(),
+            source: Box::new(e),
+        })
+    }
+
+    /// Run every scenario against the policies of `state`.
+    pub fn run(&self, state: &EngineState) -> ScenarioReport {
+        self.run_policies(state.schema(), state.policies())
+    }
+
+    pub(super) fn run_policies(&self, schema: &Schema, policies: &PolicySet) -> ScenarioReport {
+        let authorizer = Authorizer::new();
+        let mut report = ScenarioReport::default();
+        for scenario in &self.scenarios {
+            let (request, entities) = match self.request(schema, scenario) {
+                Ok(evaluation) => evaluation,
+                Err(error) => {
+                    report.failures.push(ScenarioFailure::Invalid {
+                        scenario: scenario.name.clone(),
+                        error,
+                    });
+                    continue;
+                }
+            };
+            let response = authorizer.is_authorized(&request, policies, &entities);
+            let determining = response
+                .diagnostics()
+                .reason()
+                .map(|id| id_str(id).to_string())
+                .collect::<BTreeSet<_>>();
+            let (missing, unexpected) = match &scenario.policies {
+                Some(expected) => (
+                    expected.difference(&determining).cloned().collect(),
+                    determining.difference(expected).cloned().collect(),
+                ),
+                None => (Vec::new(), Vec::new()),
+            };
+            if response.decision() == scenario.decision
+                && missing.is_empty()
+                && unexpected.is_empty()
+            {
+                report.passed += 1;
+                continue;
+            }
+            report.failures.push(ScenarioFailure::Mismatch {
+                scenario: scenario.name.clone(),
+                expected: scenario.decision,
+                actual: response.decision(),
+                missing,
+                unexpected,
+                errors: response
+                    .diagnostics()
+                    .errors()
+                    .map(|e| e.to_string())
+                    .collect(),
+            });
+        }
+        report
+    }
+
+    fn request(&self, schema: &Schema, scenario: &Scenario) -> Result<(Request, Entities)> {
+        let action = uid(&scenario.action)?;
+        let context = match &scenario.context {
+            Some(context) => Context::from_json_value(context.clone(), Some((schema, &action)))?,
+            None => Context::empty(),
+        };
+        let request = Request::new(
+            uid(&scenario.principal)?,
+            action,
+            uid(&scenario.resource)?,
+            context,
+            Some(schema),
+        )?;
+        let replaced = scenario
+            .entities
+            .iter()
+            .map(|entity| &entity["uid"])
+            .collect::<Vec<_>>();
+        let entities = self
+            .entities
+            .iter()
+            .filter(|entity| !replaced.contains(&&entity["uid"]))
+            .chain(&scenario.entities)
+            .map(with_defaults)
+            .collect();
+        let entities = Entities::from_json_value(Value::Array(entities), Some(schema))?;
+        Ok((request, entities))
+    }
+}
+
+fn uid(value: &Value) -> Result<EntityUid> {
+    let uid = match value {
+        Value::String(uid) => EntityUid::from_str(uid).map_err(|e| e.to_string()),
+        _ => EntityUid::from_json(value.clone()).map_err(|e| e.to_string()),
+    };
+    uid.map_err(|e| Error::Mapping(format!("Invalid entity UID `{value}`: {e}")))
+}
+
+fn with_defaults(entity: &Value) -> Value {
+    let mut entity = entity.clone();
+    if let Some(fields) = entity.as_object_mut() {
+        fields.entry("attrs").or_insert_with(|| json!({}));
+        fields.entry("parents").or_insert_with(|| json!([]));
+    }
+    entity
+}
+
+fn decision_str(decision: Decision) -> &'static str {
+    match decision {
+        Decision::Allow => "allow",
+        Decision::Deny => "deny",
+    }
+}
+
+mod decision {
+    use cedar_policy::Decision;
+    use serde::{Deserialize, Deserializer, de::Error};
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Decision, D::Error> {
+        match String::deserialize(deserializer)?.to_lowercase().as_str() {
+            "allow" => Ok(Decision::Allow),
+            "deny" => Ok(Decision::Deny),
+            other => Err(D::Error::custom(format!(
+                "expected `allow` or `deny`, got `{other}`"
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cedar_policy::{Policy, PolicyId, PolicySet, SchemaFragment};
+
+    use super::*;
+    use crate::CEDAR_SCHEMA_SRC;
+
+    const SCENARIOS: &str = r#"
+entities:
+  - uid: { type: MyApp::User, id: alice }
+    parents: [{ type: MyApp::Role, id: admin }]
+  - uid: { type: MyApp::Role, id: admin }
+    attrs: { project: { type: MyApp::Project, id: "0" } }
+scenarios:
+  - name: admins can delete projects
+    principal: 'MyApp::User::"alice"'
+    action: { type: MyApp::Action, id: DeleteProject }
+    resource: { type: MyApp::Project, id: "0" }
+    decision: allow
+    policies: [admins]
+  - name: demoted users still can
+    principal: 'MyApp::User::"alice"'
+    action: 'MyApp::Action::"DeleteProject"'
+    resource: 'MyApp::Project::"0"'
+    entities:
+      - uid: { type: MyApp::User, id: alice }
+    decision: allow
+  - name: wrong policy
+    principal: 'MyApp::User::"alice"'
+    action: 'MyApp::Action::"GetProjectMetadata"'
+    resource: 'MyApp::Project::"0"'
+    decision: allow
+    policies: [readers]
+  - name: unknown action
+    principal: 'MyApp::User::"alice"'
+    action: 'MyApp::Action::"Missing"'
+    resource: 'MyApp::Project::"0"'
+    decision: deny
+"#;
+
+    #[test]
+    fn test_scenarios() {
+        let state = EngineState::new(
+            SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap(),
+            PolicySet::from_policies([Policy::parse(
+                Some(PolicyId::new("admins")),
+                r#"permit (principal in MyApp::Role::"admin", action, resource);"#,
+            )
+            .unwrap()])
+            .unwrap(),
+            Entities::empty(),
+        )
+        .unwrap();
+        let report = ScenarioFile::from_yaml(SCENARIOS).unwrap().run(&state);
+        assert_eq!(report.passed, 1);
+        let failures = report
+            .failures
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            failures[..2],
+            [
+                "scenario `demoted users still can`:\n  decision: expected allow, got deny\n",
+                "scenario `wrong policy`:\n  decision: allow\n  - readers (expected to determine the decision)\n  + admins (determined the decision)\n",
+            ]
+        );
+        assert!(matches!(
+            &report.failures[2],
+            ScenarioFailure::Invalid { scenario, .. } if scenario == "unknown action"
+        ));
+        assert!(report.to_string().ends_with("1 passed, 3 failed"));
+    }
+}
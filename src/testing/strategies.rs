@@ -0,0 +1,225 @@
+//! [`proptest`] strategies for entities, contexts and requests that are valid for a schema.
+
+use cedar_policy::{Context, Entities, EntityUid, Request};
+use proptest::{prelude::*, sample::select};
+use serde_json::{Map, Value, json};
+
+use super::generator::{Generator, Model, Type, extension_values, uid_json};
+
+impl Generator {
+    /// One entity for every ID of every entity type, with attributes and parents that conform
+    /// to the schema.
+    pub fn entities(&self) -> BoxedStrategy<Entities> {
+        let model = self.model.clone();
+        let entities = model
+            .entity_types
+            .iter()
+            .flat_map(|(name, entity_type)| {
+                let model = model.clone();
+                entity_type
+                    .ids
+                    .iter()
+                    .enumerate()
+                    .map(move |(i, id)| model.entity(name, i, id))
+            })
+            .collect::<Vec<_>>();
+        let schema = self.schema.clone();
+        entities
+            .prop_filter_map("entities must conform to the schema", move |entities| {
+                Entities::from_json_value(Value::Array(entities), Some(&schema)).ok()
+            })
+            .boxed()
+    }
+
+    /// A context for `action`, or `None` if the action is not in the schema.
+    pub fn context(&self, action: &EntityUid) -> Option<BoxedStrategy<Context>> {
+        let context = self.model.actions.get(action)?.context.clone();
+        let (schema, action) = (self.schema.clone(), action.clone());
+        Some(
+            self.model
+                .value(&context)
+                .prop_filter_map("context must conform to the schema", move |context| {
+                    Context::from_json_value(context, Some((&schema, &action))).ok()
+                })
+                .boxed(),
+        )
+    }
+
+    /// A request for any action of the schema, with a principal and resource from the entity
+    /// pool and a valid context.
+    pub fn requests(&self) -> BoxedStrategy<Request> {
+        let actions = self
+            .model
+            .actions
+            .iter()
+            .filter(|(_, action)| !action.principals.is_empty() && !action.resources.is_empty())
+            .map(|(uid, _)| uid.clone())
+            .collect::<Vec<_>>();
+        if actions.is_empty() {
+            return Just(())
+                .prop_filter_map("no action applies to any entity", |_| None)
+                .boxed();
+        }
+        let generator = self.clone();
+        select(actions)
+            .prop_flat_map(move |action| {
+                let model = &generator.model;
+                let applies = &model.actions[&action];
+                let uids = |types: &[String]| {
+                    types
+                        .iter()
+                        .flat_map(|name| model.uids(name))
+                        .collect::<Vec<_>>()
+                };
+                let context = generator
+                    .context(&action)
+                    .expect("the action is in the schema");
+                (
+                    Just(action),
+                    select(uids(&applies.principals)),
+                    select(uids(&applies.resources)),
+                    context,
+                )
+            })
+            .prop_filter_map(
+                "request must conform to the schema",
+                move |(action, principal, resource, context)| {
+                    Request::new(principal, action, resource, context, None).ok()
+                },
+            )
+            .boxed()
+    }
+}
+
+impl Model {
+    fn entity(&self, name: &str, index: usize, id: &str) -> BoxedStrategy<Value> {
+        let entity_type = &self.entity_types[name];
+        let parents = entity_type
+            .parents
+            .iter()
+            .filter_map(|parent| self.entity_types.get(parent).map(|ty| (parent, ty)))
+            .map(|(parent, ty)| {
+                // Parents of the same type have lower IDs, so the hierarchy stays acyclic.
+                let candidates = ty
+                    .ids
+                    .iter()
+                    .take(if parent == name { index } else { ty.ids.len() })
+                    .map(|id| uid_json(parent, id))
+                    .collect::<Vec<_>>();
+                let len = candidates.len();
+                proptest::sample::subsequence(candidates, 0..=len)
+            })
+            .collect::<Vec<_>>();
+        let uid = uid_json(name, id);
+        (self.value(&entity_type.shape), parents)
+            .prop_map(move |(attrs, parents)| {
+                json!({
+                    "uid": uid["__entity"],
+                    "attrs": attrs,
+                    "parents": parents
+                        .into_iter()
+                        .flatten()
+                        .map(|parent| parent["__entity"].clone())
+                        .collect::<Vec<_>>(),
+                })
+            })
+            .boxed()
+    }
+
+    fn value(&self, ty: &Type) -> BoxedStrategy<Value> {
+        match ty {
+            Type::Bool => any::<bool>().prop_map(Value::from).boxed(),
+            Type::Long => (-10i64..10).prop_map(Value::from).boxed(),
+            Type::String => "[a-z]{0,4}".prop_map(Value::from).boxed(),
+            Type::Set(element) => {
+                proptest::collection::vec(self.value(element), 0..=self.config.max_set_len)
+                    .prop_map(Value::Array)
+                    .boxed()
+            }
+            Type::Record(attributes) => attributes
+                .iter()
+                .map(|(name, ty, required)| {
+                    let name = name.clone();
+                    let value = self.value(ty);
+                    if *required {
+                        value.prop_map(Some).boxed()
+                    } else {
+                        proptest::option::of(value).boxed()
+                    }
+                    .prop_map(move |value| (name.clone(), value))
+                })
+                .collect::<Vec<_>>()
+                .prop_map(|attributes| {
+                    Value::Object(
+                        attributes
+                            .into_iter()
+                            .filter_map(|(name, value)| Some((name, value?)))
+                            .collect::<Map<_, _>>(),
+                    )
+                })
+                .boxed(),
+            Type::Entity(name) => {
+                let uids = self
+                    .entity_types
+                    .get(name)
+                    .into_iter()
+                    .flat_map(|ty| &ty.ids)
+                    .map(|id| uid_json(name, id))
+                    .collect::<Vec<_>>();
+                if uids.is_empty() {
+                    Just(uid_json(name, "0")).boxed()
+                } else {
+                    select(uids).boxed()
+                }
+            }
+            Type::Extension(name) => {
+                let (function, args) = extension_values(name);
+                select(args)
+                    .prop_map(move |arg| json!({ "__extn": { "fn": function, "arg": arg } }))
+                    .boxed()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use cedar_policy::{PolicySet, SchemaFragment};
+    use proptest::test_runner::{Config, TestRunner};
+
+    use super::*;
+    use crate::{
+        CEDAR_SCHEMA_SRC,
+        engine::EngineState,
+        testing::{GeneratorConfig, tpe_discrepancies},
+    };
+
+    #[test]
+    fn test_generated_scenarios() {
+        let fragment = SchemaFragment::from_str(CEDAR_SCHEMA_SRC).unwrap();
+        let generator = Generator::new(&fragment, GeneratorConfig::default()).unwrap();
+        let policies = PolicySet::from_str(
+            r#"
+            permit (principal in MyApp::Role::"0", action, resource in MyApp::Server::"1");
+            permit (principal is MyApp::Role, action, resource) when { principal.project == resource };
+            forbid (principal == MyApp::User::"2", action, resource);
+            "#,
+        )
+        .unwrap();
+        let mut runner = TestRunner::new(Config::with_cases(32));
+        runner
+            .run(
+                &(generator.entities(), generator.requests()),
+                |(entities, request)| {
+                    let state =
+                        EngineState::new(fragment.clone(), policies.clone(), entities).unwrap();
+                    let discrepancies = tpe_discrepancies(&state, [&request]).unwrap();
+                    prop_assert!(discrepancies.is_empty(), "{discrepancies:#?}");
+                    Ok(())
+                },
+            )
+            .unwrap();
+    }
+}
@@ -0,0 +1,82 @@
+//! JavaScript bindings for evaluation in the browser or at the edge, such as in Cloudflare
+//! Workers. Build with `--target wasm32-unknown-unknown --features wasm` and wasm-bindgen.
+//!
+//! Inputs and answers are JSON strings. Requests and answers have the shapes of the HTTP API's
+//! `/v1/is_authorized` and `/v1/filter`, so residuals can be evaluated where they are used.
+
+use serde_json::Value;
+use wasm_bindgen::prelude::*;
+
+use crate::{Engine, Error, Result, bindings};
+
+/// An [`Engine`] with fixed schema, policies and entities, exported as `Engine`.
+#[wasm_bindgen(js_name = Engine)]
+pub struct WasmEngine {
+    engine: Engine,
+}
+
+#[wasm_bindgen(js_class = Engine)]
+impl WasmEngine {
+    /// The schema and policies are in Cedar's JSON formats, the entities in Cedar's entities
+    /// JSON format.
+    #[wasm_bindgen(constructor)]
+    pub fn new(schema: &str, policies: &str, entities: &str) -> Result<WasmEngine, JsError> {
+        let engine = bindings::load(schema, policies, entities).map_err(js_error)?;
+        Ok(Self { engine })
+    }
+
+    /// The decision on a request, as `{"decision": "Allow", "diagnostics": {...}}`.
+    #[wasm_bindgen(js_name = isAuthorized)]
+    pub fn is_authorized(&self, request: &str) -> Result<String, JsError> {
+        self.authorize(request)
+            .map(|answer| answer.to_string())
+            .map_err(js_error)
+    }
+
+    /// The residuals of a request with unknown principal or resource IDs, in Cedar's JSON
+    /// policy format as returned by [`crate::Residuals::est`].
+    pub fn tpe(&self, request: &str) -> Result<String, JsError> {
+        self.residuals(request)
+            .map(|est| est.to_string())
+            .map_err(js_error)
+    }
+}
+
+impl WasmEngine {
+    fn authorize(&self, request: &str) -> Result<Value> {
+        bindings::authorize(&self.engine, parse(request)?)
+    }
+
+    fn residuals(&self, request: &str) -> Result<Value> {
+        Ok(bindings::residuals(&self.engine, parse(request)?)?.est())
+    }
+}
+
+fn parse(request: &str) -> Result<Value> {
+    serde_json::from_str(request).map_err(|e| Error::Mapping(e.to_string()))
+}
+
+fn js_error(e: Error) -> JsError {
+    JsError::new(&e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bindings::tests::engine,
+        testing::fixtures::{authorization_call, tpe_call},
+    };
+
+    #[test]
+    fn test_engine() {
+        let engine = WasmEngine { engine: engine() };
+        let answer = engine.authorize(&authorization_call().to_string()).unwrap();
+        assert_eq!(answer["decision"], "Allow");
+
+        let est = engine.residuals(&tpe_call().to_string()).unwrap();
+        assert!(est["decision"].is_null());
+        assert!(est["policies"]["policy0"].is_object());
+        assert!(engine.authorize("not json").is_err());
+    }
+}